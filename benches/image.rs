@@ -0,0 +1,44 @@
+use criterion::{
+    black_box,
+    criterion_group,
+    criterion_main,
+    Criterion,
+};
+use docker_registry_client::Image;
+
+const COUNT: usize = 10_000;
+
+fn references() -> Vec<String> {
+    (0..COUNT)
+        .map(|i| match i % 4 {
+            0 => format!("ghcr.io/sigstore/cosign/cosign:v2.{i}.0"),
+            1 => format!("quay.io/openshift-community-operators/external-secrets-operator:v0.{i}"),
+            2 => format!("registry.access.redhat.com/ubi8:8.{i}"),
+            _ => format!("registry.k8s.io/autoscaling/vpa-recommender@sha256:{i:064x}"),
+        })
+        .collect()
+}
+
+fn parse(references: &[String]) -> Vec<Image> {
+    references
+        .iter()
+        .map(|reference| reference.parse().expect("valid reference"))
+        .collect()
+}
+
+fn parse_and_clone(c: &mut Criterion) {
+    let references = references();
+
+    c.bench_function("parse 10k references", |b| {
+        b.iter(|| parse(black_box(&references)));
+    });
+
+    let images = parse(&references);
+
+    c.bench_function("clone 10k images", |b| {
+        b.iter(|| black_box(&images).to_vec());
+    });
+}
+
+criterion_group!(benches, parse_and_clone);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+use criterion::{
+    black_box,
+    criterion_group,
+    criterion_main,
+    Criterion,
+};
+use docker_registry_client::{
+    docker::transport::{
+        MockResponse,
+        MockTransport,
+    },
+    http::{
+        Method,
+        StatusCode,
+    },
+    Client,
+    Image,
+};
+use futures::{
+    StreamExt,
+    TryStreamExt,
+};
+
+const URL: &str = "https://registry.k8s.io/v2/pause/tags/list";
+
+/// A tag list body of about 5 MB.
+fn body() -> String {
+    let tags = (0..300_000)
+        .map(|i| format!("\"v{i}.0.0-build\""))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!(r#"{{"name":"pause","tags":[{tags}]}}"#)
+}
+
+fn list_tags(c: &mut Criterion) {
+    let body = body();
+    assert!(body.len() > 5_000_000);
+
+    let transport = MockTransport::new().with_response(
+        Method::GET,
+        URL,
+        MockResponse::new(StatusCode::OK).body(body),
+    );
+
+    let client = Client::builder().transport(transport).build();
+    let image: Image = "registry.k8s.io/pause:3.9"
+        .parse()
+        .expect("valid reference");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("runtime");
+
+    c.bench_function("list 5 MB of tags", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(client.list_tags(black_box(&image)))
+                .expect("tags")
+        });
+    });
+
+    c.bench_function("stream first 100 of 5 MB of tags", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(
+                    client
+                        .list_tags_stream(black_box(&image))
+                        .take(100)
+                        .try_collect::<Vec<_>>(),
+                )
+                .expect("tags")
+        });
+    });
+}
+
+criterion_group!(benches, list_tags);
+criterion_main!(benches);
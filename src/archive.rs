@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
+};
+
+use bytes::Bytes;
+use serde::Deserialize;
+
+use crate::{
+    Digest,
+    Image,
+    ImageConfig,
+};
+
+/// A tarball written by `docker save`. Both the legacy layout with one
+/// directory per layer and the OCI layout Docker writes since version 25 are
+/// supported, as both describe their images in `manifest.json`.
+///
+/// The whole archive is read into memory when it is opened.
+#[derive(Debug, Clone)]
+pub struct DockerArchive {
+    images: Vec<ArchiveImage>,
+}
+
+/// An image of a [`DockerArchive`].
+#[derive(Debug, Clone)]
+pub struct ArchiveImage {
+    /// The tags the image was saved with, for example `busybox:latest`.
+    pub repo_tags: Vec<String>,
+
+    pub config: ImageConfig,
+
+    /// The layers in the order of the `diff_ids` of the config.
+    pub layers: Vec<ArchiveLayer>,
+
+    config_blob: Bytes,
+}
+
+/// An uncompressed layer tarball of an [`ArchiveImage`].
+#[derive(Debug, Clone)]
+pub struct ArchiveLayer {
+    /// The digest of the uncompressed tarball.
+    pub diff_id: Digest,
+
+    tar: Bytes,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Read(std::io::Error),
+    MissingEntry(String),
+    DeserializeManifest(serde_json::Error),
+    DeserializeConfig(String, serde_json::Error),
+    NoImages,
+    LayerCountMismatch { expected: usize, actual: usize },
+    DiffIdMismatch { expected: Digest, actual: Digest },
+}
+
+/// An entry of `manifest.json`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ManifestEntry {
+    config: String,
+
+    /// `null` for images that were saved by ID.
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+
+    layers: Vec<String>,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(e) => write!(f, "failed to read archive: {e}"),
+            Self::MissingEntry(path) => write!(f, "archive has no entry {path}"),
+            Self::DeserializeManifest(e) => {
+                write!(f, "failed to deserialize manifest.json: {e}")
+            }
+            Self::DeserializeConfig(path, e) => {
+                write!(f, "failed to deserialize config {path}: {e}")
+            }
+            Self::NoImages => write!(f, "archive contains no images"),
+            Self::LayerCountMismatch { expected, actual } => write!(
+                f,
+                "config lists {expected} layers but the archive has {actual}"
+            ),
+            Self::DiffIdMismatch { expected, actual } => write!(
+                f,
+                "layer has digest {actual} but the config expects {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl DockerArchive {
+    /// Reads a `docker save` tarball and checks every layer against the
+    /// `diff_ids` of its config.
+    ///
+    /// # Errors
+    /// Returns an error if the tarball can not be read, `manifest.json` or a
+    /// config is missing or malformed or a layer does not match its config.
+    pub fn open(reader: impl Read) -> Result<Self, Error> {
+        let entries = Entries::read(reader)?;
+
+        let manifest: Vec<ManifestEntry> = serde_json::from_slice(entries.get("manifest.json")?)
+            .map_err(Error::DeserializeManifest)?;
+
+        if manifest.is_empty() {
+            return Err(Error::NoImages);
+        }
+
+        let images = manifest
+            .into_iter()
+            .map(|entry| ArchiveImage::read(&entries, entry))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { images })
+    }
+
+    #[must_use]
+    pub fn images(&self) -> &[ArchiveImage] {
+        &self.images
+    }
+
+    /// Returns the image that was saved with the name and tag of `image`. An
+    /// archive with a single image returns it for any name.
+    #[must_use]
+    pub fn find(&self, image: &Image) -> Option<&ArchiveImage> {
+        let tagged = self.images.iter().find(|candidate| {
+            candidate.repo_tags.iter().any(|tag| {
+                tag.parse::<Image>()
+                    .is_ok_and(|tag| tag.image_name == image.image_name)
+            })
+        });
+
+        match (tagged, self.images.as_slice()) {
+            (Some(found), _) | (None, [found]) => Some(found),
+            (None, _) => None,
+        }
+    }
+}
+
+impl ArchiveImage {
+    fn read(entries: &Entries, entry: ManifestEntry) -> Result<Self, Error> {
+        let config_blob = entries.get(&entry.config)?.clone();
+
+        let config: ImageConfig = serde_json::from_slice(&config_blob)
+            .map_err(|e| Error::DeserializeConfig(entry.config.clone(), e))?;
+
+        if config.rootfs.diff_ids.len() != entry.layers.len() {
+            return Err(Error::LayerCountMismatch {
+                expected: config.rootfs.diff_ids.len(),
+                actual: entry.layers.len(),
+            });
+        }
+
+        let layers = entry
+            .layers
+            .iter()
+            .zip(&config.rootfs.diff_ids)
+            .map(|(path, expected)| {
+                let tar = entries.get(path)?.clone();
+                let actual = Digest::sha256(&tar);
+
+                if !actual.is_equivalent(expected) {
+                    return Err(Error::DiffIdMismatch {
+                        expected: expected.clone(),
+                        actual,
+                    });
+                }
+
+                Ok(ArchiveLayer {
+                    diff_id: actual,
+                    tar,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            repo_tags: entry.repo_tags.unwrap_or_default(),
+            config,
+            layers,
+            config_blob,
+        })
+    }
+
+    /// The config as it is stored in the archive. Its digest is the image ID.
+    #[must_use]
+    pub fn config_blob(&self) -> &Bytes {
+        &self.config_blob
+    }
+
+    #[must_use]
+    pub fn config_digest(&self) -> Digest {
+        Digest::sha256(&self.config_blob)
+    }
+}
+
+impl ArchiveLayer {
+    #[must_use]
+    pub fn tar(&self) -> &Bytes {
+        &self.tar
+    }
+}
+
+/// The files and symlinks of a tarball keyed by their normalized path.
+struct Entries {
+    files: HashMap<PathBuf, Bytes>,
+    links: HashMap<PathBuf, PathBuf>,
+}
+
+impl Entries {
+    fn read(reader: impl Read) -> Result<Self, Error> {
+        let mut files = HashMap::new();
+        let mut links = HashMap::new();
+
+        let mut archive = tar::Archive::new(reader);
+
+        for entry in archive.entries().map_err(Error::Read)? {
+            let mut entry = entry.map_err(Error::Read)?;
+            let path = normalize(&entry.path().map_err(Error::Read)?);
+
+            match entry.header().entry_type() {
+                tar::EntryType::Regular => {
+                    let mut content = Vec::new();
+                    entry.read_to_end(&mut content).map_err(Error::Read)?;
+
+                    files.insert(path, content.into());
+                }
+
+                tar::EntryType::Symlink => {
+                    if let Some(target) = entry.link_name().map_err(Error::Read)? {
+                        let target = path.parent().unwrap_or(Path::new("")).join(target);
+                        links.insert(path, normalize(&target));
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        Ok(Self { files, links })
+    }
+
+    /// Returns the content of the file at `path`, following symlinks like
+    /// the ones newer Docker versions write for the legacy layer paths.
+    fn get(&self, path: &str) -> Result<&Bytes, Error> {
+        let mut path = normalize(Path::new(path));
+
+        // Bounded so a symlink loop can not hang.
+        for _ in 0..8 {
+            if let Some(content) = self.files.get(&path) {
+                return Ok(content);
+            }
+
+            match self.links.get(&path) {
+                Some(target) => path = target.clone(),
+                None => break,
+            }
+        }
+
+        Err(Error::MissingEntry(path.display().to_string()))
+    }
+}
+
+/// Resolves `.` and `..` so paths of entries and links can be compared.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const LEGACY: &[u8] = include_bytes!("../resources/archive/busybox-legacy.tar");
+    const OCI: &[u8] = include_bytes!("../resources/archive/busybox-oci.tar");
+
+    #[test]
+    fn both_layouts_are_equal() {
+        let legacy = DockerArchive::open(LEGACY).unwrap();
+        let oci = DockerArchive::open(OCI).unwrap();
+
+        for archive in [&legacy, &oci] {
+            let [image] = archive.images() else {
+                panic!("expected a single image");
+            };
+
+            assert_eq!(image.repo_tags, vec!["busybox:latest".to_string()]);
+            assert_eq!(image.layers.len(), 1);
+            assert_eq!(
+                image.config.rootfs.diff_ids,
+                vec![image.layers[0].diff_id.clone()]
+            );
+        }
+
+        assert_eq!(
+            legacy.images()[0].config_digest(),
+            oci.images()[0].config_digest()
+        );
+    }
+
+    #[test]
+    fn find() {
+        let archive = DockerArchive::open(LEGACY).unwrap();
+
+        assert!(archive.find(&"busybox:latest".parse().unwrap()).is_some());
+        // A single image is pushed under any name.
+        assert!(archive
+            .find(&"registry.k8s.io/tools/box:1.0".parse().unwrap())
+            .is_some());
+    }
+
+    #[test]
+    fn missing_manifest() {
+        let got =
+            DockerArchive::open(&include_bytes!("../resources/archive/busybox-oci.tar")[..512]);
+
+        assert!(matches!(got, Err(Error::MissingEntry(path)) if path == "manifest.json"));
+    }
+
+    #[test]
+    fn symlinks_are_followed() {
+        let entries = Entries::read(OCI).unwrap();
+        let layer = entries
+            .get("b251968b0d144f82a252ba6b81b6cb63cfe49000a5f0b2eebe2974179d6a8479/layer.tar")
+            .unwrap();
+
+        assert_eq!(
+            Digest::sha256(layer).to_string(),
+            "sha256:b251968b0d144f82a252ba6b81b6cb63cfe49000a5f0b2eebe2974179d6a8479"
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::BTreeMap;
+
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+
+use crate::Digest;
+
+/// Media type of the layers of an attestation manifest that contain an
+/// in-toto statement.
+pub const IN_TOTO_MEDIA_TYPE: &str = "application/vnd.in-toto+json";
+
+const SLSA_PROVENANCE_V02: &str = "https://slsa.dev/provenance/v0.2";
+const SLSA_PROVENANCE_V1: &str = "https://slsa.dev/provenance/v1";
+const SPDX_DOCUMENT: &str = "https://spdx.dev/Document";
+
+/// An attestation attached to an image, for example by `docker buildx build
+/// --provenance=true --sbom=true`.
+#[derive(Debug, Serialize, Clone)]
+pub enum Attestation {
+    SlsaProvenanceV02(InTotoStatement<SlsaProvenanceV02>),
+    SlsaProvenanceV1(InTotoStatement<SlsaProvenanceV1>),
+    Spdx(InTotoStatement<SpdxDocument>),
+
+    /// A statement with a predicate type that is not known, the predicate is
+    /// kept as it is.
+    Other(InTotoStatement<Value>),
+}
+
+/// An in-toto statement about the artifacts in `subject`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct InTotoStatement<P> {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+
+    pub subject: Vec<Subject>,
+
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+
+    pub predicate: P,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Subject {
+    pub name: String,
+
+    /// Digests of the subject keyed by algorithm.
+    pub digest: BTreeMap<String, String>,
+}
+
+/// The SLSA provenance v0.2 predicate. This is what buildkit generates by
+/// default.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SlsaProvenanceV02 {
+    pub builder: Builder,
+    pub build_type: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation: Option<Invocation>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<ResourceDescriptor>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataV02>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Invocation {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_source: Option<ConfigSource>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub parameters: Value,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub environment: Value,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSource {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub digest: BTreeMap<String, String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataV02 {
+    #[serde(rename = "buildInvocationID")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_invocation_id: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_started_on: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_finished_on: Option<DateTime<Utc>>,
+}
+
+/// The SLSA provenance v1 predicate, generated by buildkit with
+/// `--provenance=version=v1`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SlsaProvenanceV1 {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub external_parameters: Value,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub internal_parameters: Value,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RunDetails {
+    pub builder: Builder,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataV1>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataV1 {
+    #[serde(rename = "invocationID")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_on: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Builder {
+    pub id: String,
+}
+
+/// An artifact used during the build, called a material in SLSA v0.2 and a
+/// resolved dependency in SLSA v1.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResourceDescriptor {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub digest: BTreeMap<String, String>,
+}
+
+/// The parts of an SPDX document we care about. Other fields of the document
+/// are ignored.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpdxDocument {
+    pub spdx_version: String,
+    pub name: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_namespace: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<SpdxPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpdxPackage {
+    pub name: String,
+
+    #[serde(rename = "SPDXID")]
+    pub spdx_id: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_info: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    UnsupportedMediaType(String),
+    Deserialize(serde_json::Error),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedMediaType(media_type) => {
+                write!(f, "unsupported attestation media type: {media_type}")
+            }
+            Self::Deserialize(e) => write!(f, "failed to deserialize attestation: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Attestation {
+    /// Parses the content of an attestation layer with the given media type
+    /// and picks the predicate from its predicate type.
+    ///
+    /// # Errors
+    /// Returns [`ParseError::UnsupportedMediaType`] if the layer does not
+    /// contain an in-toto statement.
+    /// Returns [`ParseError::Deserialize`] if the statement or a known
+    /// predicate is malformed.
+    pub fn parse(media_type: &str, bytes: &[u8]) -> Result<Self, ParseError> {
+        if media_type != IN_TOTO_MEDIA_TYPE {
+            return Err(ParseError::UnsupportedMediaType(media_type.to_string()));
+        }
+
+        let statement: InTotoStatement<Value> =
+            serde_json::from_slice(bytes).map_err(ParseError::Deserialize)?;
+
+        let attestation = match statement.predicate_type.as_str() {
+            SLSA_PROVENANCE_V02 => Self::SlsaProvenanceV02(statement.typed()?),
+            SLSA_PROVENANCE_V1 => Self::SlsaProvenanceV1(statement.typed()?),
+            SPDX_DOCUMENT => Self::Spdx(statement.typed()?),
+            _ => Self::Other(statement),
+        };
+
+        Ok(attestation)
+    }
+
+    #[must_use]
+    pub fn predicate_type(&self) -> &str {
+        match self {
+            Self::SlsaProvenanceV02(statement) => &statement.predicate_type,
+            Self::SlsaProvenanceV1(statement) => &statement.predicate_type,
+            Self::Spdx(statement) => &statement.predicate_type,
+            Self::Other(statement) => &statement.predicate_type,
+        }
+    }
+
+    #[must_use]
+    pub fn subject(&self) -> &[Subject] {
+        match self {
+            Self::SlsaProvenanceV02(statement) => &statement.subject,
+            Self::SlsaProvenanceV1(statement) => &statement.subject,
+            Self::Spdx(statement) => &statement.subject,
+            Self::Other(statement) => &statement.subject,
+        }
+    }
+}
+
+impl InTotoStatement<Value> {
+    fn typed<P: DeserializeOwned>(self) -> Result<InTotoStatement<P>, ParseError> {
+        Ok(InTotoStatement {
+            statement_type: self.statement_type,
+            subject: self.subject,
+            predicate_type: self.predicate_type,
+            predicate: serde_json::from_value(self.predicate).map_err(ParseError::Deserialize)?,
+        })
+    }
+}
+
+impl Subject {
+    /// Returns the sha256 digest of the subject if it has one.
+    #[must_use]
+    pub fn sha256(&self) -> Option<Digest> {
+        self.digest.get("sha256").map(|hex| {
+            format!("sha256:{hex}")
+                .parse()
+                .unwrap_or_else(|e| match e {})
+        })
+    }
+}
+
+impl SlsaProvenanceV1 {
+    #[must_use]
+    pub fn builder_id(&self) -> &str {
+        &self.run_details.builder.id
+    }
+
+    #[must_use]
+    pub fn invocation_id(&self) -> Option<&str> {
+        self.run_details
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.invocation_id.as_deref())
+    }
+
+    /// The artifacts used during the build, the equivalent of the materials
+    /// of SLSA v0.2.
+    #[must_use]
+    pub fn materials(&self) -> &[ResourceDescriptor] {
+        &self.build_definition.resolved_dependencies
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    mod parse {
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            attestation::{
+                Attestation,
+                ParseError,
+                IN_TOTO_MEDIA_TYPE,
+            },
+            Digest,
+        };
+
+        const PROVENANCE_V02: &str = include_str!("../resources/attestation/provenance-v0.2.json");
+        const PROVENANCE_V1: &str = include_str!("../resources/attestation/provenance-v1.json");
+        const SBOM: &str = include_str!("../resources/attestation/sbom-spdx.json");
+        const UNKNOWN: &str = include_str!("../resources/attestation/unknown-predicate.json");
+
+        #[test]
+        fn provenance_v02() {
+            let got = Attestation::parse(IN_TOTO_MEDIA_TYPE, PROVENANCE_V02.as_bytes()).unwrap();
+
+            assert!(matches!(got, Attestation::SlsaProvenanceV02(_)));
+            insta::assert_json_snapshot!(got);
+        }
+
+        #[test]
+        fn provenance_v1() {
+            let got = Attestation::parse(IN_TOTO_MEDIA_TYPE, PROVENANCE_V1.as_bytes()).unwrap();
+
+            let Attestation::SlsaProvenanceV1(statement) = &got else {
+                panic!("expected a SLSA v1 provenance, got {got:?}");
+            };
+
+            assert_eq!(
+                statement.predicate.builder_id(),
+                "https://github.com/example/app/actions/runs/9876543210"
+            );
+            assert_eq!(
+                statement.predicate.invocation_id(),
+                Some("w1bqlh6cs2q7m3cpzd0p2k5wh")
+            );
+            assert_eq!(statement.predicate.materials().len(), 1);
+            insta::assert_json_snapshot!(got);
+        }
+
+        #[test]
+        fn spdx() {
+            let got = Attestation::parse(IN_TOTO_MEDIA_TYPE, SBOM.as_bytes()).unwrap();
+
+            assert!(matches!(got, Attestation::Spdx(_)));
+            insta::assert_json_snapshot!(got);
+        }
+
+        #[test]
+        fn unknown_predicate() {
+            let got = Attestation::parse(IN_TOTO_MEDIA_TYPE, UNKNOWN.as_bytes()).unwrap();
+
+            let Attestation::Other(statement) = &got else {
+                panic!("expected an unknown predicate, got {got:?}");
+            };
+
+            assert_eq!(
+                statement.predicate["scanner"]["uri"],
+                "pkg:github/aquasecurity/trivy@0.52.0"
+            );
+            assert_eq!(
+                got.subject()[0].sha256(),
+                Some(
+                    "sha256:4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945"
+                        .parse::<Digest>()
+                        .unwrap()
+                )
+            );
+        }
+
+        #[test]
+        fn unsupported_media_type() {
+            let got = Attestation::parse("application/json", SBOM.as_bytes()).unwrap_err();
+
+            assert!(matches!(got, ParseError::UnsupportedMediaType(_)));
+        }
+    }
+}
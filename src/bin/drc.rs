@@ -0,0 +1,47 @@
+//! `drc`, a command line client for container registries.
+//!
+//! ```text
+//! drc health <registry>
+//! ```
+//!
+//! `health` prints the [`docker_registry_client::docker::health::HealthReport`]
+//! of the registry as JSON and exits with `1` if it is not healthy.
+
+use std::process::ExitCode;
+
+use docker_registry_client::{
+    docker::Client,
+    Registry,
+};
+
+const USAGE: &str = "usage: drc health <registry>";
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["health", registry] => health(&Registry::try_from_host(registry)).await,
+
+        _ => {
+            eprintln!("{USAGE}");
+            Ok(ExitCode::from(2))
+        }
+    }
+}
+
+async fn health(registry: &Registry) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let report = Client::new().health_check(registry).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    if report.is_healthy() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
+    time::Duration,
+};
+
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    manifest::{
+        self,
+        Architecture,
+        OperatingSystem,
+    },
+    Digest,
+};
+
+/// The configuration blob of an image that is referenced by the `config`
+/// descriptor of an image manifest.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ImageConfig {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    pub architecture: Architecture,
+    pub os: OperatingSystem,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ExecutionConfig>,
+
+    pub rootfs: RootFs,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<History>,
+}
+
+/// The parameters used when running a container from the image.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ExecutionConfig {
+    #[serde(rename = "User")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    #[serde(rename = "Env")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+
+    #[serde(rename = "Entrypoint")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+
+    #[serde(rename = "Cmd")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+
+    #[serde(rename = "WorkingDir")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+
+    #[serde(rename = "Labels")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Ports like `80/tcp` the container listens on.
+    #[serde(rename = "ExposedPorts")]
+    #[serde(default, with = "object_set")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposed_ports: Option<BTreeSet<String>>,
+
+    /// Paths in the container that are mounted as volumes.
+    #[serde(rename = "Volumes")]
+    #[serde(default, with = "object_set")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<BTreeSet<String>>,
+
+    #[serde(rename = "Healthcheck")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub healthcheck: Option<Healthcheck>,
+
+    #[serde(rename = "StopSignal")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<String>,
+}
+
+/// How the health of a container started from the image is checked. The
+/// durations are stored as nanoseconds, a missing or zero duration means
+/// the default of the container runtime is used.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct Healthcheck {
+    /// The command to run, for example `["CMD-SHELL", "curl -f http://localhost/"]`.
+    /// `["NONE"]` disables a check inherited from the base image.
+    #[serde(rename = "Test")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub test: Vec<String>,
+
+    #[serde(rename = "Interval")]
+    #[serde(default, with = "nanoseconds")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<Duration>,
+
+    #[serde(rename = "Timeout")]
+    #[serde(default, with = "nanoseconds")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+
+    #[serde(rename = "StartPeriod")]
+    #[serde(default, with = "nanoseconds")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_period: Option<Duration>,
+
+    #[serde(rename = "Retries")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RootFs {
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    pub diff_ids: Vec<Digest>,
+}
+
+/// A step of the build of the image as recorded in the configuration.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct History {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub empty_layer: bool,
+}
+
+/// A step of the build paired with the layer it produced, see
+/// [`ImageConfig::history_entries`].
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub created: Option<DateTime<Utc>>,
+    pub created_by: Option<String>,
+    pub layer: Option<(Digest, u64)>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HistoryError {
+    /// The number of history entries that produced a layer does not match
+    /// the number of layers of the manifest.
+    LayerCountMismatch { history: usize, layers: usize },
+}
+
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LayerCountMismatch { history, layers } => write!(
+                f,
+                "history has {history} entries with a layer but the manifest has {layers} layers"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl ExecutionConfig {
+    /// Returns the numbers of the exposed ports in ascending order, see
+    /// [`exposed_port_numbers`].
+    #[must_use]
+    pub fn exposed_port_numbers(&self) -> Vec<u16> {
+        exposed_port_numbers(self.exposed_ports.as_ref())
+    }
+}
+
+/// Returns the numbers of ports like `80/tcp` or `53` in ascending order.
+/// Ports exposed for several protocols are returned once, entries that are
+/// not a port number are skipped.
+pub(crate) fn exposed_port_numbers(ports: Option<&BTreeSet<String>>) -> Vec<u16> {
+    let mut numbers: Vec<u16> = ports
+        .into_iter()
+        .flatten()
+        .filter_map(|port| port.split('/').next()?.parse().ok())
+        .collect();
+
+    numbers.sort_unstable();
+    numbers.dedup();
+
+    numbers
+}
+
+/// Serializes an optional set like `ExposedPorts` or `Volumes` the way
+/// Docker writes them, as a JSON object with empty objects as values.
+/// Values that are not empty are ignored when deserializing.
+pub(crate) mod object_set {
+    use std::collections::{
+        BTreeMap,
+        BTreeSet,
+    };
+
+    use serde::{
+        ser::SerializeMap,
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    #[derive(serde::Serialize)]
+    struct Empty {}
+
+    #[expect(clippy::ref_option, reason = "serde passes the field by reference")]
+    pub(crate) fn serialize<S>(
+        set: &Option<BTreeSet<String>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Some(set) = set else {
+            return serializer.serialize_none();
+        };
+
+        let mut map = serializer.serialize_map(Some(set.len()))?;
+        for key in set {
+            map.serialize_entry(key, &Empty {})?;
+        }
+        map.end()
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<BTreeSet<String>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let map = Option::<BTreeMap<String, serde_json::Value>>::deserialize(deserializer)?;
+
+        Ok(map.map(BTreeMap::into_keys).map(Iterator::collect))
+    }
+}
+
+/// Serializes an optional [`Duration`] as whole nanoseconds like Docker
+/// does. Negative values are treated as missing.
+mod nanoseconds {
+    use std::time::Duration;
+
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    #[expect(clippy::ref_option, reason = "serde passes the field by reference")]
+    pub(super) fn serialize<S>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => {
+                serializer.serialize_u64(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let nanoseconds = Option::<i64>::deserialize(deserializer)?;
+
+        Ok(nanoseconds
+            .and_then(|nanoseconds| u64::try_from(nanoseconds).ok())
+            .map(Duration::from_nanos))
+    }
+}
+
+impl ImageConfig {
+    /// Pairs the history of the configuration with the layers of `manifest`
+    /// like `docker history` does. Entries marked as `empty_layer` did not
+    /// produce a layer, every other entry consumes the next layer in order.
+    ///
+    /// # Errors
+    /// Returns [`HistoryError::LayerCountMismatch`] if the number of entries
+    /// that produced a layer does not match the number of layers. Some image
+    /// builders are known to get this wrong.
+    pub fn history_entries(
+        &self,
+        manifest: &manifest::Image,
+    ) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let history = self
+            .history
+            .iter()
+            .filter(|history| !history.empty_layer)
+            .count();
+
+        if history != manifest.layers.len() {
+            return Err(HistoryError::LayerCountMismatch {
+                history,
+                layers: manifest.layers.len(),
+            });
+        }
+
+        let mut layers = manifest.layers.iter();
+
+        let entries = self
+            .history
+            .iter()
+            .map(|history| {
+                let layer = if history.empty_layer {
+                    None
+                } else {
+                    layers.next().map(|layer| {
+                        let digest = layer.digest.clone();
+                        (digest, layer.size)
+                    })
+                };
+
+                HistoryEntry {
+                    created: history.created,
+                    created_by: history.created_by.clone(),
+                    layer,
+                    comment: history.comment.clone(),
+                }
+            })
+            .collect();
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "unwrap use in tests is fine")]
+mod tests {
+    mod deserialize {
+        use std::time::Duration;
+
+        use pretty_assertions::assert_eq;
+
+        use crate::config::ImageConfig;
+
+        #[test]
+        fn nginx() {
+            const INPUT: &str = include_str!("../resources/config/nginx.json");
+
+            let out: ImageConfig = serde_json::from_str(INPUT).unwrap();
+            let config = out.config.as_ref().unwrap();
+
+            assert_eq!(config.exposed_port_numbers(), [80, 443]);
+            assert_eq!(
+                config
+                    .volumes
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+                ["/var/cache/nginx", "/var/log/nginx"]
+            );
+
+            let healthcheck = config.healthcheck.as_ref().unwrap();
+            assert_eq!(healthcheck.interval, Some(Duration::from_secs(30)));
+            assert_eq!(healthcheck.timeout, Some(Duration::from_secs(5)));
+            assert_eq!(healthcheck.start_period, Some(Duration::from_secs(10)));
+            assert_eq!(healthcheck.retries, Some(3));
+
+            insta::assert_json_snapshot!(out);
+        }
+    }
+
+    mod history_entries {
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            config::{
+                HistoryError,
+                ImageConfig,
+            },
+            manifest,
+        };
+
+        const CONFIG: &str = include_str!("../resources/config/multi-stage.json");
+        const MANIFEST: &str = include_str!("../resources/manifest/image/multi-stage.json");
+
+        #[test]
+        fn multi_stage() {
+            let config: ImageConfig = serde_json::from_str(CONFIG).unwrap();
+            let manifest: manifest::Image = serde_json::from_str(MANIFEST).unwrap();
+
+            let out = config.history_entries(&manifest).unwrap();
+
+            insta::assert_json_snapshot!(out);
+        }
+
+        #[test]
+        fn layer_count_mismatch() {
+            let config: ImageConfig = serde_json::from_str(CONFIG).unwrap();
+            let mut manifest: manifest::Image = serde_json::from_str(MANIFEST).unwrap();
+            manifest.layers.pop();
+
+            assert_eq!(
+                config.history_entries(&manifest),
+                Err(HistoryError::LayerCountMismatch {
+                    history: 3,
+                    layers: 2,
+                })
+            );
+        }
+
+        #[test]
+        fn scratch() {
+            let config: ImageConfig = serde_json::from_str(
+                r#"{"architecture":"amd64","os":"linux","rootfs":{"type":"layers","diff_ids":[]}}"#,
+            )
+            .unwrap();
+            let manifest: manifest::Image =
+                serde_json::from_str(include_str!("../resources/manifest/image/scratch.json"))
+                    .unwrap();
+
+            assert_eq!(config.history_entries(&manifest), Ok(Vec::new()));
+        }
+    }
+}
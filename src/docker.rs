@@ -1,49 +1,349 @@
-use reqwest::{
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::Instant,
+};
+
+use base64::Engine as _;
+use bytes::Bytes;
+use either::Either;
+use futures::{
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use http::{
     header::HeaderMap,
-    Client as HTTPClient,
+    Method,
 };
 use serde::{
+    de::DeserializeOwned,
     Deserialize,
     Serialize,
 };
+use tokio::io::AsyncRead;
 use tracing::{
+    debug,
     info_span,
+    warn,
     Instrument,
 };
 use url::Url;
 
 use crate::{
+    manifest,
+    Digest,
     Image,
     Manifest,
     Registry,
 };
 
+pub mod api;
+pub mod attestations;
+pub mod auth;
+pub mod blob;
+mod builder;
+pub mod bundle;
+pub mod cancellation;
+mod challenge;
+pub mod containerd;
+pub mod content_store;
+#[cfg(feature = "cosign-verify")]
+pub mod cosign;
+#[cfg(feature = "dockerhub-api")]
+pub mod dockerhub;
+pub mod download;
+mod encoding;
 mod error;
+pub mod expand;
+#[cfg(test)]
+mod fixtures;
+mod foreign;
+pub mod health;
+mod immutable;
+mod in_flight;
+pub mod interceptor;
+pub mod layer;
+pub mod manifest_cache;
+pub mod media_types;
+pub mod metrics;
+pub mod mirror;
+pub mod missing_child;
+pub mod negotiation;
+pub mod options;
+#[cfg(feature = "otel")]
+mod otel;
+pub mod ping;
+pub mod platform;
+pub mod policy;
+pub mod progress;
+pub mod push;
+mod quay;
+mod rate_limit;
+pub mod rate_limit_status;
+mod redirect;
+mod registry_headers;
+mod shutdown;
+pub mod snapshot;
+pub mod stale;
+pub mod stats;
+pub mod tag_groups;
+pub mod tags;
 pub mod token;
 pub mod token_cache;
+pub mod transport;
+pub mod walk;
+pub mod warning;
 
-pub use error::Error;
+pub use builder::ClientBuilder;
+pub use error::{
+    Error,
+    ErrorCode,
+    FailedResponse,
+};
+use in_flight::{
+    InFlight,
+    RequestKey,
+};
+use manifest_cache::Cache as ManifestCache;
+use mirror::{
+    Authentication,
+    Endpoint,
+    Mirrors,
+};
+use negotiation::{
+    ManifestPreference,
+    Negotiation,
+};
+use progress::{
+    NoProgress,
+    Progress,
+};
+use rate_limit_status::RateLimitStatus;
+use stats::Event;
 use token::Token;
 use token_cache::Cache as TokenCache;
+use transport::{
+    Request,
+    Transport,
+};
+use warning::RegistryWarning;
+
+/// Default limit for manifest and token response bodies. Matches the
+/// manifest size limit of the distribution registry.
+pub const DEFAULT_MAX_MANIFEST_SIZE: u64 = 4 * 1024 * 1024;
+
+/// How many redirects are followed for a single request by default, the same
+/// limit reqwest uses.
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Default limit for a single page of a tag list. Tag lists of popular
+/// repositories are a lot larger than manifests.
+pub const DEFAULT_MAX_TAG_LIST_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Tokens that expire sooner than this are fetched again by
+/// [`Client::warm_tokens`].
+pub const TOKEN_EXPIRY_MARGIN: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How far the clock of a token server may be off by default, see
+/// [`ClientBuilder::token_clock_skew`].
+pub const DEFAULT_TOKEN_CLOCK_SKEW: std::time::Duration = std::time::Duration::from_mins(5);
+
+/// How many manifest lookups and layer downloads all clones of a client run
+/// at the same time by default, see [`ClientBuilder::concurrency_limit`].
+pub const DEFAULT_CONCURRENCY_LIMIT: usize = 8;
 
+/// How many tokens [`Client::warm_tokens`] requests at the same time.
+const WARM_TOKENS_CONCURRENCY: usize = 4;
+
+/// Default size of the chunks blobs are uploaded in. Smaller blobs are
+/// uploaded with a single request.
+pub const DEFAULT_UPLOAD_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// `Client` talks to registries. Build one with [`Client::builder`].
+///
+/// Cloning a client is cheap, clones share all state: the HTTP transport,
+/// the token and manifest caches, requests in flight, rate limits, the
+/// concurrency limit and the observed pull quotas.
 #[derive(Debug, Clone)]
 pub struct Client {
-    client: HTTPClient,
+    inner: Arc<ClientInner>,
+}
+
+/// The state shared by all clones of a [`Client`].
+#[derive(Debug, Clone)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "the options of the builder, see ClientBuilder"
+)]
+struct ClientInner {
+    transport: Arc<dyn Transport>,
     token_cache: Box<dyn TokenCache + Send>,
+    token_file: Option<Arc<token_cache::TokenFile>>,
+    manifest_cache: Box<dyn ManifestCache + Send>,
+    in_flight: InFlight<RawResponse>,
+    tokens_in_flight: InFlight<Token>,
+    mirrors: Mirrors,
+    offline: bool,
+    max_manifest_size: u64,
+    max_redirects: usize,
+    max_tag_list_size: u64,
+    max_blob_size: Option<u64>,
+    blob_idle_timeout: Option<std::time::Duration>,
+    blob_idle_timeout_without_length: bool,
+    token_clock_skew: chrono::Duration,
+    default_platform: manifest::Platform,
+    verify_descriptors: bool,
+    allow_digest_mismatch: bool,
+    try_anonymous_on_token_failure: bool,
+    upload_chunk_size: u64,
+    rate_limits: rate_limit::RateLimits,
+    concurrency_limit: usize,
+    concurrency: Arc<tokio::sync::Semaphore>,
+    registry_headers: Arc<registry_headers::RegistryHeaders>,
+    rate_limit_status: rate_limit_status::Observed,
+    api_version_exempt: Arc<std::collections::HashSet<Registry>>,
+    registry_policy: Arc<policy::Policy>,
+    shutdown_hooks: shutdown::Hooks,
+    metrics: Option<Arc<dyn metrics::Metrics>>,
+    token_params: std::collections::HashMap<Registry, auth::TokenParams>,
+    #[cfg(feature = "dockerhub-api")]
+    dockerhub: dockerhub::Hub,
 }
 
+/// A manifest fetched with [`Client::get_manifest`]. Besides the manifest the
+/// serialization has fields that describe the request that fetched it, like
+/// the pull quota and the host that served it. See
+/// [`Response::redacted_for_snapshot`] for the stable part.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Response {
     pub digest: Option<String>,
     pub manifest: Manifest,
+
+    /// Warnings the registry sent with the manifest. Always empty for
+    /// manifests served from the cache.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<RegistryWarning>,
+
+    /// The pull quota the registry announced with the manifest. Always `None`
+    /// for manifests served from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitStatus>,
+
+    /// What the client asked the registry for and which manifest type it
+    /// answered with.
+    #[serde(default)]
+    pub negotiation: Negotiation,
+
+    /// `true` if the manifest was requested by digest and the registry
+    /// answered with that digest. `false` for manifests requested by tag and
+    /// for mismatches let through with
+    /// [`ClientBuilder::allow_digest_mismatch`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub digest_verified: bool,
+
+    /// The host that answered with the manifest after redirects were
+    /// followed, a mirror if one served it. Always `None` for manifests
+    /// served from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<String>,
+}
+
+/// A manifest as the registry returned it, see [`Client::get_manifest_raw`].
+#[derive(Debug, Clone)]
+pub struct RawResponse {
+    pub digest: Option<String>,
+    pub content_type: Option<String>,
+    pub warnings: Vec<RegistryWarning>,
+    pub rate_limit: Option<RateLimitStatus>,
+    pub negotiation: Negotiation,
+    pub digest_verified: bool,
+    pub served_by: Option<String>,
+    pub body: Bytes,
+    pub json: serde_json::Value,
+}
+
+/// A manifest parsed with [`Manifest::parse_lenient`], see
+/// [`Client::get_manifest_lenient`].
+#[derive(Debug, Clone)]
+pub struct LenientResponse {
+    pub digest: Option<String>,
+
+    /// `None` if nothing could be salvaged from the body.
+    pub manifest: Option<Manifest>,
+
+    /// What was skipped or coerced while parsing the manifest.
+    pub parse_warnings: Vec<manifest::ParseWarning>,
+
+    /// Warnings the registry sent with the manifest.
+    pub warnings: Vec<RegistryWarning>,
+
+    /// The pull quota the registry announced with the manifest.
+    pub rate_limit: Option<RateLimitStatus>,
+}
+
+/// Result of comparing a known digest against the digest a tag currently
+/// points to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateStatus {
+    /// The tag still points to the known digest.
+    UpToDate,
+
+    /// The tag was moved and now points to `new_digest`.
+    UpdateAvailable { new_digest: Digest },
+
+    /// The tag does not exist anymore in the registry.
+    TagGone,
+}
+
+/// A manifest response whose status was checked and whose body was read.
+struct ReceivedManifest {
+    entry: manifest_cache::Entry,
+    warnings: Vec<RegistryWarning>,
+    rate_limit: Option<RateLimitStatus>,
+    served_by: Option<String>,
+}
+
+impl RawResponse {
+    fn from_received(received: ReceivedManifest) -> Result<Self, Error> {
+        debug!(
+            served_by = received.served_by.as_deref(),
+            negotiation = %received.entry.negotiation,
+            "negotiated the manifest type"
+        );
+
+        let mut raw = Self::from_entry(received.entry, received.warnings, received.rate_limit)?;
+        raw.served_by = received.served_by;
+
+        Ok(raw)
+    }
+
+    fn from_entry(
+        entry: manifest_cache::Entry,
+        warnings: Vec<RegistryWarning>,
+        rate_limit: Option<RateLimitStatus>,
+    ) -> Result<Self, Error> {
+        let json = serde_json::from_slice(manifest_json(entry.body.as_bytes()))
+            .map_err(|e| Error::DeserializeManifestBody(e, entry.body.clone()))?;
+
+        Ok(Self {
+            digest: entry.digest,
+            content_type: entry.content_type,
+            warnings,
+            rate_limit,
+            negotiation: entry.negotiation,
+            digest_verified: entry.digest_verified,
+            served_by: None,
+            body: entry.body.into(),
+            json,
+        })
+    }
 }
 
 impl Default for Client {
     fn default() -> Self {
-        Self {
-            client: HTTPClient::new(),
-            token_cache: Box::new(token_cache::MemoryTokenCache::default()),
-        }
+        ClientBuilder::default().build()
     }
 }
 
@@ -53,55 +353,319 @@ impl Client {
         Self::default()
     }
 
+    #[must_use]
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Returns a [`api::RegistryApi`] that sends its requests through the
+    /// transport of this client, including its interceptors, but without
+    /// its authentication, mirrors, caches and rate limits.
+    #[must_use]
+    pub fn registry_api(&self) -> api::RegistryApi {
+        api::RegistryApi::from_transport(Arc::clone(&self.inner.transport))
+            .max_manifest_size(self.inner.max_manifest_size)
+            .max_redirects(self.inner.max_redirects)
+            .max_tag_list_size(self.inner.max_tag_list_size)
+    }
+
+    /// Returns the hit and miss counters of the manifest cache if the
+    /// configured cache keeps track of them.
+    #[must_use]
+    pub fn manifest_cache_stats(&self) -> Option<manifest_cache::Stats> {
+        self.inner.manifest_cache.stats()
+    }
+
+    /// Returns the pull quota every registry announced with its last
+    /// response. Registries that never sent rate limit headers are missing.
+    #[must_use]
+    pub fn rate_limit_status(&self) -> HashMap<Registry, RateLimitStatus> {
+        self.inner.rate_limit_status.snapshot()
+    }
+
+    /// Fails with [`Error::RegistryDenied`] if the registry policy does not
+    /// allow `registry`.
+    fn check_registry_policy(&self, registry: &Registry) -> Result<(), Error> {
+        if self.inner.registry_policy.is_allowed(registry) {
+            return Ok(());
+        }
+
+        warn!(%registry, "registry denied by the registry policy");
+
+        Err(Error::RegistryDenied {
+            registry: registry.clone(),
+        })
+    }
+
+    /// The state of this client to change. A client that has clones gets
+    /// its own copy first, so setters only change the client they are
+    /// called on. The copy still shares the caches and rate limits with the
+    /// clones unless a setter replaces them.
+    fn inner_mut(&mut self) -> &mut ClientInner {
+        Arc::make_mut(&mut self.inner)
+    }
+
+    /// Enables or disables offline mode. In offline mode requests are only
+    /// served from the manifest cache and no network requests are made, not
+    /// even to fetch tokens.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.inner_mut().offline = offline;
+    }
+
     pub fn set_cache_memory(&mut self) {
-        self.token_cache = Box::new(token_cache::MemoryTokenCache::default());
+        let inner = self.inner_mut();
+        inner.token_cache = Box::new(token_cache::MemoryTokenCache::default());
+        inner.token_file = None;
     }
 
     pub fn disable_caching(&mut self) {
-        self.token_cache = Box::new(token_cache::NoCache);
+        let inner = self.inner_mut();
+        inner.token_cache = Box::new(token_cache::NoCache);
+        inner.token_file = None;
     }
 
     #[cfg(feature = "redis_cache")]
     pub fn set_cache_redis(&mut self, redis_client: redis::Client) {
-        self.token_cache = Box::new(token_cache::RedisCache::new(redis_client));
+        let inner = self.inner_mut();
+        inner.token_cache = Box::new(token_cache::RedisCache::new(redis_client));
+        inner.token_file = None;
+    }
+
+    /// Saves the token cache to the file configured with
+    /// [`ClientBuilder::token_cache_file`]. Does nothing if no file was
+    /// configured.
+    ///
+    /// # Errors
+    /// Returns an error if the file can not be written.
+    pub async fn persist_tokens(&self) -> Result<(), Error> {
+        let Some(token_file) = &self.inner.token_file else {
+            return Ok(());
+        };
+
+        token_file.save().await.map_err(Error::PersistTokens)
     }
 
-    #[tracing::instrument]
+    /// Caches manifests that are requested by digest in Redis. Manifests
+    /// requested by tag are always fetched from the registry.
+    #[cfg(feature = "redis_cache")]
+    pub fn set_manifest_cache_redis(&mut self, redis_client: redis::Client) {
+        self.inner_mut().manifest_cache =
+            Box::new(manifest_cache::RedisDigestCache::new(redis_client));
+    }
+
+    pub fn disable_manifest_caching(&mut self) {
+        self.inner_mut().manifest_cache = Box::new(manifest_cache::NoCache);
+    }
+
+    /// # Errors
+    /// Returns an error if the request fails.
+    /// Returns an error if the response body is not a valid manifest.
+    /// Returns an error if the response status is not successful.
     pub async fn get_manifest_url(&self, url: &Url, image: &Image) -> Result<Response, Error> {
-        let mut headers = self.get_headers(image).await?;
-
-        let accept_header = [
-            "application/vnd.docker.container.image.v1+json",
-            "application/vnd.docker.distribution.manifest.list.v2+json",
-            "application/vnd.docker.distribution.manifest.v2+json",
-            "application/vnd.docker.image.rootfs.diff.tar.gzip",
-            "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip",
-            "application/vnd.docker.plugin.v1+json",
-            "application/vnd.oci.image.index.v1+json",
-            "application/vnd.oci.image.manifest.v1+json",
-        ]
-        .join(", ");
-
-        headers.insert(
-            "Accept",
-            accept_header
-                .parse()
-                .map_err(Error::ParseManifestAcceptHeader)?,
+        self.get_manifest_url_with_preference(url, image, ManifestPreference::Any)
+            .await
+    }
+
+    /// Same as [`Client::get_manifest_url`] but asks the registry for the
+    /// kind of manifest given by `preference`, see
+    /// [`Client::get_manifest_with_preference`].
+    ///
+    /// # Errors
+    /// Returns an error if the request fails.
+    /// Returns an error if the response body is not a valid manifest.
+    /// Returns an error if the response status is not successful.
+    pub async fn get_manifest_url_with_preference(
+        &self,
+        url: &Url,
+        image: &Image,
+        preference: ManifestPreference,
+    ) -> Result<Response, Error> {
+        let raw = self
+            .get_manifest_raw_with_preference(
+                image,
+                Vec::new(),
+                Endpoint::upstream(url.clone()),
+                preference,
+            )
+            .await?;
+
+        Self::response_from_raw(raw)
+    }
+
+    async fn get_manifest_raw_with_preference(
+        &self,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+        preference: ManifestPreference,
+    ) -> Result<RawResponse, Error> {
+        if preference == ManifestPreference::Any {
+            return self.get_manifest_raw_from(image, mirrors, last).await;
+        }
+
+        // The registry may answer the same reference with a different
+        // manifest depending on the preference, so these requests bypass
+        // the cache and are not shared with other requests.
+        self.check_registry_policy(&image.registry)?;
+
+        let accept = preference.accept();
+        let (endpoint, response) = self
+            .send_manifest_request(Method::GET, image, mirrors, last, accept.clone())
+            .await?;
+
+        let received = self
+            .receive_manifest(image, endpoint.clone(), &accept, response)
+            .await?;
+        let received = self.negotiate(image, endpoint, preference, received).await;
+
+        RawResponse::from_received(received)
+    }
+
+    #[tracing::instrument(
+        name = "get_manifest",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            reference = %image.image_name.identifier,
+        )
+    )]
+    async fn get_manifest_raw_from(
+        &self,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+    ) -> Result<RawResponse, Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        // Concurrent requests for the same manifest share a single request.
+        let key = RequestKey::new(
+            Method::GET,
+            mirrors
+                .iter()
+                .chain(std::iter::once(&last))
+                .map(|endpoint| endpoint.url.clone())
+                .collect(),
+            Some(image.into()),
         );
 
-        let response = self
-            .client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
-            .instrument(info_span!("get manifest request"))
+        self.inner
+            .in_flight
+            .run(key, || self.fetch_manifest_raw(image, mirrors, last))
+            .await
+    }
+
+    async fn fetch_manifest_raw(
+        &self,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+    ) -> Result<RawResponse, Error> {
+        let cache_key: manifest_cache::CacheKey = image.into();
+
+        let cached = self
+            .inner
+            .manifest_cache
+            .fetch(&cache_key)
+            .await
+            .map_err(Error::FetchManifest)?;
+
+        if let Some(entry) = cached {
+            stats::record(Event::CacheHit);
+            return RawResponse::from_entry(entry, Vec::new(), None);
+        }
+
+        let preference = ManifestPreference::Any;
+
+        let accept = preference.accept();
+        let (endpoint, response) = self
+            .send_manifest_request(Method::GET, image, mirrors, last, accept.clone())
+            .await?;
+
+        let received = self
+            .receive_manifest(image, endpoint.clone(), &accept, response)
+            .await?;
+        let received = self.negotiate(image, endpoint, preference, received).await;
+        let entry = received.entry.clone();
+
+        let raw = RawResponse::from_received(received)?;
+
+        self.inner
+            .manifest_cache
+            .store(cache_key, entry)
             .await
-            .map_err(Error::GetManifest)?;
+            .map_err(Error::StoreManifest)?;
+
+        Ok(raw)
+    }
+
+    /// Sends the request for the manifest again if the registry ignored the
+    /// `Accept` header and answered with a schema1 manifest. The retry only
+    /// goes to the endpoint that answered. If it fails the schema1 manifest
+    /// is returned.
+    async fn negotiate(
+        &self,
+        image: &Image,
+        endpoint: Endpoint,
+        preference: ManifestPreference,
+        received: ReceivedManifest,
+    ) -> ReceivedManifest {
+        if !negotiation::is_schema1(received.entry.content_type.as_deref()) {
+            return received;
+        }
+
+        debug!(
+            url = %endpoint.url,
+            content_type = received.entry.content_type.as_deref(),
+            "registry answered with a schema1 manifest, retrying with v2 manifest types"
+        );
 
-        let status = response.status();
+        let retry = async {
+            let accept = preference.retry_accept();
+            let (endpoint, response) = self
+                .send_manifest_request(Method::GET, image, Vec::new(), endpoint, accept.clone())
+                .await?;
+
+            self.receive_manifest(image, endpoint, &accept, response)
+                .await
+        };
+
+        match retry.await {
+            Ok(mut retried) => {
+                retried.entry.negotiation.retried = true;
+                retried
+            }
+
+            Err(error) => {
+                debug!(%error, "retrying the manifest request failed, using the schema1 manifest");
+                received
+            }
+        }
+    }
+
+    /// Checks the status of a manifest response and reads its body.
+    /// `accept` is the `Accept` header the request was sent with.
+    async fn receive_manifest(
+        &self,
+        image: &Image,
+        endpoint: Endpoint,
+        accept: &str,
+        response: transport::Response,
+    ) -> Result<ReceivedManifest, Error> {
+        let rate_limit = self
+            .inner
+            .rate_limit_status
+            .observe(&image.registry, &response.headers);
+        let status = response.status;
+
+        let content_type = response
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|header| header.to_str().ok())
+            .map(String::from);
 
         let digest = response
-            .headers()
+            .headers
             .get("Docker-Content-Digest")
             .map(|header| {
                 header
@@ -111,209 +675,5136 @@ impl Client {
             })
             .transpose()?;
 
-        let body = response
-            .text()
-            .instrument(info_span!("extract manifest request body"))
-            .await
-            .map_err(Error::ExtractManifestBody)?;
+        let warnings = warning::collect(&response.headers);
+        let served_by = response.url.host_str().map(String::from);
 
-        if !status.is_success() {
-            if status == reqwest::StatusCode::NOT_FOUND {
-                return Err(Error::ManifestNotFound(url.clone()));
-            }
+        if status == http::StatusCode::NOT_MODIFIED {
+            return Err(Error::ManifestNotModified(endpoint.url));
+        }
 
-            return Err(Error::FailedManifestRequest(status, body));
+        if quay::is_missing_repository(&image.registry, status) {
+            return Err(Error::ManifestNotFound(endpoint.url));
         }
 
-        let manifest =
-            serde_json::from_str(&body).map_err(|e| Error::DeserializeManifestBody(e, body))?;
+        let api = api::RegistryApi::for_client(self);
+        let response = self
+            .check_response(
+                &api,
+                response,
+                rate_limit.as_ref(),
+                Error::ManifestNotFound,
+                Error::FailedManifestRequest,
+            )
+            .await?;
 
-        Ok(Response { digest, manifest })
-    }
+        self.check_api_version(
+            &image.registry,
+            &endpoint.url,
+            &response.headers,
+            ping::api_version(&response.headers).as_deref(),
+            false,
+        )?;
 
-    /// # Errors
-    /// Returns an error if the request fails.
-    /// Returns an error if the response body is not valid JSON.
-    /// Returns an error if the response body is not a valid manifest.
-    /// Returns an error if the response status is not successful.
-    #[tracing::instrument(skip_all)]
-    pub async fn get_manifest(&self, image: &Image) -> Result<Response, Error> {
-        let registry_domain = image.registry.registry_domain();
+        let body = text(api.read_manifest(response).await?);
 
-        let url = Url::parse(&format!(
-            "https://{domain}/v2/{namespace}{repository}{image_name}/manifests/{identifier}",
-            domain = registry_domain,
-            namespace = match image.namespace {
-                Some(ref namespace) => format!("{namespace}/"),
-                None => String::new(),
-            },
-            repository = match image.repository {
-                Some(ref repository) => format!("{repository}/"),
-                None => String::new(),
-            },
-            image_name = image.image_name.name,
-            identifier = image.image_name.identifier
-        ))
-        .map_err(Error::InvalidManifestUrl)?;
+        if body.starts_with('\u{feff}') {
+            warn!(
+                registry = %image.registry,
+                url = %endpoint.url,
+                "registry sent the manifest with a UTF-8 byte order mark, it is skipped for parsing but stays part of the digest"
+            );
+        }
+
+        let negotiation = Negotiation::new(accept, content_type.as_deref());
+        let mut entry = manifest_cache::Entry {
+            body,
+            content_type,
+            digest,
+            negotiation,
+            digest_verified: false,
+        };
+        self.verify_digest(image, &endpoint.url, &mut entry)?;
 
-        self.get_manifest_url(&url, image).await
+        Ok(ReceivedManifest {
+            entry,
+            warnings,
+            rate_limit,
+            served_by,
+        })
     }
 
-    #[tracing::instrument(skip_all)]
-    async fn get_headers(&self, image: &Image) -> Result<HeaderMap, Error> {
-        if !image.registry.needs_authentication() {
-            return Ok(HeaderMap::new());
-        }
+    /// Checks a manifest that was requested by digest against that digest.
+    /// The `Docker-Content-Digest` header is compared and so is the digest of
+    /// the body computed with the algorithm of the requested digest, unless
+    /// the manifest is a signed schema1 manifest, whose digest excludes the
+    /// signature. Manifests requested by tag are not checked.
+    fn verify_digest(
+        &self,
+        image: &Image,
+        url: &Url,
+        entry: &mut manifest_cache::Entry,
+    ) -> Result<(), Error> {
+        let Either::Right(requested) = &image.image_name.identifier else {
+            return Ok(());
+        };
 
-        let cache_key = image.into();
+        let header = entry
+            .digest
+            .as_deref()
+            .map(|digest| digest.parse::<Digest>().unwrap_or_else(|e| match e {}));
 
-        let token = self
-            .token_cache
-            .fetch(&cache_key)
-            .await
-            .map_err(Error::FetchToken)?;
+        let algorithm = requested
+            .algorithm()
+            .map_err(Error::UnsupportedDigestAlgorithm)?;
 
-        let token = if let Some(token) = token {
-            token
-        } else {
-            let namespace = match &image.namespace {
-                Some(namespace) => format!("{namespace}/"),
-                None => String::new(),
-            };
+        let computed = (!negotiation::is_schema1(entry.content_type.as_deref()))
+            .then(|| algorithm.digest(entry.body.as_bytes()));
 
-            let repository = match &image.repository {
-                Some(repository) => format!("{repository}/"),
-                None => String::new(),
-            };
+        if header.is_none() && computed.is_none() {
+            return Ok(());
+        }
 
-            let token_url = match image.registry {
-                Registry::Github => format!(
-                    "https://ghcr.io/token?scope=repository:{namespace}{repository}{image_name}:pull&service=ghcr.io",
-                    image_name = image.image_name.name
-                ),
+        let Some(received) = header
+            .into_iter()
+            .chain(computed)
+            .find(|received| !received.is_equivalent(requested))
+        else {
+            entry.digest_verified = true;
+            return Ok(());
+        };
 
-                Registry::DockerHub => format!("https://auth.docker.io/token?service=registry.docker.io&scope=repository:{namespace}{repository}{image_name}:pull&service=registry.docker.io", image_name = image.image_name.name),
+        if algorithm
+            .digest(manifest_json(entry.body.as_bytes()))
+            .is_equivalent(requested)
+        {
+            warn!(
+                registry = %image.registry,
+                url = %url,
+                "the manifest only matches the requested digest without its byte order mark and surrounding whitespace"
+            );
+        }
 
-                Registry::Quay => format!("https://quay.io/v2/auth?scope=repository:{namespace}{repository}{image_name}:pull&service=quay.io", image_name = image.image_name.name),
+        if !self.inner.allow_digest_mismatch {
+            return Err(Error::DigestMismatch {
+                requested: requested.clone(),
+                received,
+                url: url.clone(),
+            });
+        }
 
-                Registry::RedHat | Registry::K8s | Registry::Google | Registry::Microsoft => return Ok(HeaderMap::new()),
-            };
+        tracing::error!(
+            url = %url,
+            requested = %requested,
+            received = %received,
+            "registry answered with a manifest for a different digest"
+        );
 
-            let token_url = Url::parse(&token_url).map_err(Error::InvalidTokenUrl)?;
+        Ok(())
+    }
 
-            let response = self
-                .client
-                .get(token_url)
-                .send()
-                .instrument(info_span!("get token request"))
-                .await
-                .map_err(Error::GetToken)?;
+    fn response_from_raw(raw: RawResponse) -> Result<Response, Error> {
+        let has_media_type = raw.json.get("mediaType").is_some();
 
-            let body = response
-                .text()
-                .instrument(info_span!("extract token request body"))
-                .await
-                .map_err(Error::ExtractTokenBody)?;
+        let mut manifest: Manifest = serde_json::from_value(raw.json).map_err(|e| {
+            Error::DeserializeManifestBody(e, String::from_utf8_lossy(&raw.body).into_owned())
+        })?;
 
-            let token: Token =
-                serde_json::from_str(&body).map_err(|e| Error::DeserializeToken(e, body))?;
+        if let (false, Some(content_type)) = (has_media_type, raw.content_type.as_deref()) {
+            manifest.default_media_type(content_type);
+        }
 
-            self.token_cache
-                .store(cache_key, token.clone())
-                .await
-                .map_err(Error::StoreToken)?;
+        Ok(Response {
+            digest: raw.digest,
+            manifest,
+            warnings: raw.warnings,
+            rate_limit: raw.rate_limit,
+            negotiation: raw.negotiation,
+            digest_verified: raw.digest_verified,
+            served_by: raw.served_by,
+        })
+    }
 
-            token
-        };
+    /// # Errors
+    /// Returns an error if the client is offline and the manifest is not
+    /// cached.
+    /// Returns an error if the request fails.
+    /// Returns an error if the response body is not valid JSON.
+    /// Returns an error if the response body is not a valid manifest.
+    /// Returns an error if the response status is not successful.
+    pub async fn get_manifest(&self, image: &Image) -> Result<Response, Error> {
+        self.get_manifest_with(image, &options::GetManifestOptions::default())
+            .await
+    }
 
-        let headers = token.try_into().map_err(Error::ParseAuthorizationHeader)?;
+    /// Same as [`Client::get_manifest`] but asks the registry for the kind
+    /// of manifest given by `preference`. With a preference other than
+    /// [`ManifestPreference::Any`] the manifest cache is bypassed.
+    ///
+    /// Registries that ignore the `Accept` header and answer with a schema1
+    /// manifest are asked once more with only v2 and OCI manifest types,
+    /// [`Response::negotiation`] tells if that happened.
+    ///
+    /// # Errors
+    /// Returns an error if the request fails.
+    /// Returns an error if the response body is not a valid manifest.
+    /// Returns an error if the response status is not successful.
+    pub async fn get_manifest_with_preference(
+        &self,
+        image: &Image,
+        preference: ManifestPreference,
+    ) -> Result<Response, Error> {
+        let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
 
-        Ok(headers)
+        let (mirrors, last) = self
+            .inner
+            .mirrors
+            .endpoints(image, &segments)
+            .map_err(Error::InvalidManifestUrl)?;
+
+        let raw = self
+            .get_manifest_raw_with_preference(image, mirrors, last, preference)
+            .await?;
+
+        Self::response_from_raw(raw)
     }
-}
 
-#[cfg(test)]
-#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
-mod tests {
-    mod dockerhub {
-        use crate::{
-            Client,
-            Image,
-            ImageName,
-            Registry,
-            Tag,
-        };
-        use either::Either;
+    /// Same as [`Client::get_manifest`] but returns the manifest as JSON
+    /// without interpreting it, so manifests the typed model does not know
+    /// about are returned as well.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline and the manifest is not
+    /// cached.
+    /// Returns an error if the request fails.
+    /// Returns an error if the response body is not valid JSON.
+    /// Returns an error if the response status is not successful.
+    pub async fn get_manifest_raw(&self, image: &Image) -> Result<RawResponse, Error> {
+        let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
 
-        #[tokio::test]
-        async fn alpine() {
-            let client = Client::new();
+        let (mirrors, last) = self
+            .inner
+            .mirrors
+            .endpoints(image, &segments)
+            .map_err(Error::InvalidManifestUrl)?;
 
-            let image_name = Image {
-                registry: Registry::DockerHub,
-                namespace: None,
-                repository: Some("library".to_string()),
-                image_name: ImageName {
-                    name: "alpine".to_string(),
-                    identifier: Either::Left(Tag::Specific("3.20".to_string())),
-                },
-            };
+        self.get_manifest_raw_from(image, mirrors, last).await
+    }
 
-            let response = client.get_manifest(&image_name).await.unwrap();
+    /// Same as [`Client::get_manifest`] but parses the manifest with
+    /// [`Manifest::parse_lenient`], so a manifest with a field that does not
+    /// fit the typed model is returned partially together with what was
+    /// skipped instead of failing.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline and the manifest is not
+    /// cached.
+    /// Returns an error if the request fails.
+    /// Returns an error if the response body is not valid JSON.
+    /// Returns an error if the response status is not successful.
+    pub async fn get_manifest_lenient(&self, image: &Image) -> Result<LenientResponse, Error> {
+        let raw = self.get_manifest_raw(image).await?;
 
-            insta::assert_json_snapshot!(response);
-        }
+        let (manifest, parse_warnings) =
+            Manifest::parse_lenient(manifest_json(&raw.body), raw.content_type.as_deref());
+
+        Ok(LenientResponse {
+            digest: raw.digest,
+            manifest,
+            parse_warnings,
+            warnings: raw.warnings,
+            rate_limit: raw.rate_limit,
+        })
     }
 
-    mod redhat {
-        use crate::{
-            Client,
-            Image,
-            ImageName,
-            Registry,
-            Tag,
-        };
-        use either::Either;
+    /// Checks if the tag of the given image still points to `known_digest`.
+    /// Uses a HEAD request so the manifest itself is not transferred.
+    ///
+    /// # Errors
+    /// Returns an error if the image is referenced by digest instead of a
+    /// tag. Returns an error if the client is offline as the check always
+    /// needs the registry. Returns an error if the request fails or the
+    /// registry does not return a `Docker-Content-Digest` header.
+    pub async fn check_for_update(
+        &self,
+        image: &Image,
+        known_digest: &Digest,
+    ) -> Result<UpdateStatus, Error> {
+        let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
 
-        #[tokio::test]
-        async fn ubi8() {
-            let client = Client::new();
+        let (mirrors, last) = self
+            .inner
+            .mirrors
+            .endpoints(image, &segments)
+            .map_err(Error::InvalidManifestUrl)?;
 
-            let image = Image {
-                registry: Registry::RedHat,
-                namespace: None,
-                repository: None,
-                image_name: ImageName {
-                    name: "ubi8".to_string(),
-                    identifier: Either::Left(Tag::Specific("8.9".to_string())),
-                },
-            };
+        self.check_for_update_from(image, known_digest, mirrors, last)
+            .await
+    }
 
-            let response = client.get_manifest(&image).await.unwrap();
+    /// Same as [`Client::check_for_update`] but uses the given manifest url
+    /// instead of constructing it from the image.
+    ///
+    /// # Errors
+    /// Returns an error if the image is referenced by digest instead of a
+    /// tag. Returns an error if the request fails or the registry does not
+    /// return a `Docker-Content-Digest` header.
+    pub async fn check_for_update_url(
+        &self,
+        url: &Url,
+        image: &Image,
+        known_digest: &Digest,
+    ) -> Result<UpdateStatus, Error> {
+        self.check_for_update_from(
+            image,
+            known_digest,
+            Vec::new(),
+            Endpoint::upstream(url.clone()),
+        )
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "check_for_update",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            reference = %image.image_name.identifier,
+        )
+    )]
+    async fn check_for_update_from(
+        &self,
+        image: &Image,
+        known_digest: &Digest,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+    ) -> Result<UpdateStatus, Error> {
+        self.check_registry_policy(&image.registry)?;
 
-            insta::assert_json_snapshot!(response);
+        if image.image_name.identifier.is_right() {
+            return Err(Error::UpdateCheckRequiresTag(image.clone()));
+        }
+
+        let Some(current_digest) = self.head_digest(image, mirrors, last).await? else {
+            return Ok(UpdateStatus::TagGone);
+        };
+
+        if current_digest.is_equivalent(known_digest) {
+            Ok(UpdateStatus::UpToDate)
+        } else {
+            Ok(UpdateStatus::UpdateAvailable {
+                new_digest: current_digest,
+            })
+        }
+    }
+
+    /// Returns the digest the reference of `image` currently points to using a
+    /// HEAD request, or `None` if the reference does not exist.
+    async fn head_digest(
+        &self,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+    ) -> Result<Option<Digest>, Error> {
+        let (_, response) = self
+            .send_manifest_request(
+                Method::HEAD,
+                image,
+                mirrors,
+                last,
+                ManifestPreference::Any.accept(),
+            )
+            .await?;
+
+        warning::collect(&response.headers);
+        let rate_limit = self
+            .inner
+            .rate_limit_status
+            .observe(&image.registry, &response.headers);
+
+        if response.status == http::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = self
+            .check_response(
+                &api::RegistryApi::for_client(self),
+                response,
+                rate_limit.as_ref(),
+                Error::ManifestNotFound,
+                Error::FailedManifestRequest,
+            )
+            .await?;
+
+        api::content_digest(&response.headers).map(Some)
+    }
+
+    /// Streams the blob with the given digest. The content is hashed while
+    /// streaming and the stream yields [`blob::Error::DigestMismatch`] as its
+    /// last item if it does not match `digest`.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline.
+    /// Returns an error if the request fails or the blob does not exist.
+    pub async fn get_blob(
+        &self,
+        image: &Image,
+        digest: &Digest,
+    ) -> Result<impl Stream<Item = Result<Bytes, blob::Error>> + Send + 'static, Error> {
+        self.get_blob_with_progress(image, digest, Arc::new(NoProgress))
+            .await
+    }
+
+    /// Same as [`Client::get_blob`] but reports the transfer to `progress`.
+    /// The total passed to [`Progress::on_start`] is taken from the
+    /// `Content-Length` header of the registry response.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline.
+    /// Returns an error if the request fails or the blob does not exist.
+    #[tracing::instrument(
+        name = "get_blob",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            digest = %digest,
+        )
+    )]
+    pub async fn get_blob_with_progress(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        progress: Arc<dyn Progress>,
+    ) -> Result<impl Stream<Item = Result<Bytes, blob::Error>> + Send + 'static, Error> {
+        self.fetch_blob(image, digest, self.inner.max_blob_size, None, progress)
+            .await
+    }
+
+    /// Streams a blob like [`Client::get_blob_with_progress`] but fails once
+    /// it is larger than `limit` instead of the configured limit. `size` is
+    /// the size of the descriptor of the blob if it is known.
+    async fn fetch_blob(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        limit: Option<u64>,
+        size: Option<u64>,
+        progress: Arc<dyn Progress>,
+    ) -> Result<impl Stream<Item = Result<Bytes, blob::Error>> + Send + 'static, Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        digest
+            .algorithm()
+            .map_err(Error::UnsupportedDigestAlgorithm)?;
+
+        let segments = image.blob_segments(digest).map_err(Error::InvalidPath)?;
+
+        let (mirrors, last) = self
+            .inner
+            .mirrors
+            .endpoints(image, &segments)
+            .map_err(Error::InvalidBlobUrl)?;
+
+        let api = api::RegistryApi::for_client(self);
+
+        let (endpoint, response) = self
+            .send_request(
+                image,
+                mirrors,
+                last,
+                |url, auth| api.blob_request(Method::GET, url, auth),
+                Error::FailedBlobRequest,
+            )
+            .await?;
+
+        warning::collect(&response.headers);
+        let rate_limit = self
+            .inner
+            .rate_limit_status
+            .observe(&image.registry, &response.headers);
+
+        let response = self
+            .check_response(
+                &api,
+                response,
+                rate_limit.as_ref(),
+                Error::BlobNotFound,
+                Error::FailedBlobRequest,
+            )
+            .await?;
+
+        let url = endpoint.url;
+        let total = content_length(&response.headers);
+
+        if let (Some(total), Some(limit)) = (total, limit) {
+            if total > limit {
+                return Err(Error::BodyTooLarge { limit, url });
+            }
+        }
+
+        progress.on_start(total);
+
+        Ok(progress::track(
+            self.blob_body(response, digest, limit, size),
+            progress,
+        ))
+    }
+
+    /// The body of a successful blob `response` with the size `limit`, the
+    /// idle timeout and the digest check applied. A body without
+    /// `Content-Length` is read until the connection is closed and only the
+    /// digest tells if it is complete, see [`blob::closed_early`].
+    fn blob_body(
+        &self,
+        response: transport::Response,
+        digest: &Digest,
+        limit: Option<u64>,
+        size: Option<u64>,
+    ) -> impl Stream<Item = Result<Bytes, blob::Error>> + Send + 'static {
+        let length_known = content_length(&response.headers).is_some();
+
+        if !length_known {
+            debug!(url = %response.url, "blob response without Content-Length");
+        }
+
+        let idle_timeout = self
+            .inner
+            .blob_idle_timeout
+            .filter(|_| length_known || self.inner.blob_idle_timeout_without_length);
+
+        let url = response.url.clone();
+        let stream = blob::idle(
+            blob::limit(response.into_stream(), limit, url),
+            idle_timeout,
+        );
+        let stream = blob::verify(stream, digest.clone());
+
+        if length_known {
+            futures::future::Either::Left(stream)
+        } else {
+            futures::future::Either::Right(blob::closed_early(stream, digest.clone(), size))
+        }
+    }
+
+    /// Fetches the blob `descriptor` points to and deserializes it from JSON,
+    /// for small artifacts like image configs, SBOMs or in-toto statements.
+    /// Content embedded in the `data` field of the descriptor is used without
+    /// a request. Either way the content has to match the size and the
+    /// digest of the descriptor, a blob that is larger is not read further.
+    ///
+    /// # Errors
+    /// Returns [`Error::DescriptorMismatch`] if the blob does not match the
+    /// size or digest of `descriptor`.
+    /// Returns [`Error::BodyTooLarge`] or [`Error::ReadBlob`] if the blob is
+    /// larger than `descriptor` says.
+    /// Returns [`Error::DeserializeBlob`] with the body if it is not valid
+    /// JSON for `T`.
+    /// Returns an error if the blob can not be fetched.
+    pub async fn get_blob_json<T: DeserializeOwned>(
+        &self,
+        image: &Image,
+        descriptor: &manifest::Descriptor,
+    ) -> Result<T, Error> {
+        let body = self.get_descriptor_bytes(image, descriptor).await?;
+
+        serde_json::from_slice(&body)
+            .map_err(|e| Error::DeserializeBlob(e, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Reads the whole blob `descriptor` points to into memory and checks it
+    /// against the descriptor, see [`Client::get_blob_json`].
+    async fn get_descriptor_bytes(
+        &self,
+        image: &Image,
+        descriptor: &manifest::Descriptor,
+    ) -> Result<Vec<u8>, Error> {
+        let algorithm = descriptor
+            .digest
+            .algorithm()
+            .map_err(Error::UnsupportedDigestAlgorithm)?;
+
+        let body = if let Some(data) = &descriptor.data {
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(Error::DecodeInlineData)?
+        } else {
+            let limit = self
+                .inner
+                .max_blob_size
+                .map_or(descriptor.size, |limit| limit.min(descriptor.size));
+
+            let chunks: Vec<Bytes> = self
+                .fetch_blob(
+                    image,
+                    &descriptor.digest,
+                    Some(limit),
+                    Some(descriptor.size),
+                    Arc::new(NoProgress),
+                )
+                .await?
+                .try_collect()
+                .await
+                .map_err(Error::ReadBlob)?;
+
+            chunks.concat()
+        };
+
+        let actual_size = body.len() as u64;
+        let actual_digest = algorithm.digest(&body);
+
+        if actual_size != descriptor.size || !actual_digest.is_equivalent(&descriptor.digest) {
+            return Err(Error::DescriptorMismatch {
+                expected_size: descriptor.size,
+                actual_size,
+                expected_digest: descriptor.digest.clone(),
+                actual_digest,
+            });
+        }
+
+        Ok(body)
+    }
+
+    /// Streams the decompressed tarball of a layer. The decompressor is picked
+    /// from the media type of the layer and the compressed content is
+    /// verified against the digest of the layer while reading. A digest
+    /// mismatch surfaces as an [`std::io::Error`] wrapping
+    /// [`blob::Error::DigestMismatch`] at the end of the tarball. Foreign
+    /// layers are fetched from their `urls` first, see
+    /// [`Client::get_layer_blob`].
+    ///
+    /// # Errors
+    /// Returns an error if the media type of the layer is unknown or its
+    /// compression is not supported.
+    /// Returns an error if the blob can not be fetched.
+    pub async fn get_layer_tar(
+        &self,
+        image: &Image,
+        layer: &manifest::Layer,
+    ) -> Result<impl AsyncRead + Send + Unpin, Error> {
+        let compression =
+            layer::Compression::from_media_type(&layer.media_type).map_err(Error::DecodeLayer)?;
+
+        let blob = self.get_layer_blob(image, layer).await?;
+
+        layer::decode(blob, compression).map_err(Error::DecodeLayer)
+    }
+
+    /// Executes a request and follows the redirects the server answers
+    /// with, see [`redirect::follow`]. Every hop is sent with
+    /// [`Client::execute_hop`].
+    async fn execute(&self, request: Request) -> Result<transport::Response, transport::Error> {
+        redirect::follow(request, self.inner.max_redirects, |request| {
+            Box::pin(self.execute_hop(request))
+        })
+        .await
+    }
+
+    /// Executes a single request in an `http.request` span that records the
+    /// status code and duration. Headers are not recorded as they carry
+    /// credentials. With the `otel` feature the trace context of the span is
+    /// sent along with the request.
+    ///
+    /// Requests to rate limited hosts first wait for a permit, the wait is
+    /// not part of the recorded duration.
+    ///
+    /// The request and the bytes of its body and of the response body are
+    /// counted for [`stats::measure`].
+    async fn execute_hop(
+        &self,
+        mut request: Request,
+    ) -> Result<transport::Response, transport::Error> {
+        self.inner.rate_limits.acquire(&request.url).await?;
+        self.inner.registry_headers.apply(&mut request);
+
+        let span = info_span!(
+            "http.request",
+            otel.kind = "client",
+            http.request.method = %request.method,
+            server.address = %request.url.host_str().unwrap_or_default(),
+            url.full = %request.url,
+            http.status_code = tracing::field::Empty,
+            http.response.status_code = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+        );
+
+        #[cfg(feature = "otel")]
+        otel::inject(&span, &mut request.headers);
+
+        let uploaded = request.body.as_ref().map_or(0, |body| body.len() as u64);
+        stats::record(Event::Request { body: uploaded });
+
+        let host = rate_limit::authority(&request.url);
+        let endpoint = metrics::Endpoint::of(&request.url);
+
+        let start = Instant::now();
+        let mut result = self
+            .inner
+            .transport
+            .execute(request)
+            .instrument(span.clone())
+            .await
+            .map(transport::Response::counted);
+
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.request(&metrics::RequestMetric {
+                host: &host,
+                endpoint,
+                status: result.as_ref().ok().map(|response| response.status),
+                duration: start.elapsed(),
+            });
+
+            if uploaded > 0 {
+                metrics.bytes_transferred(&host, metrics::Direction::Upload, uploaded);
+            }
+
+            result = result.map(|response| response.metered(Arc::clone(metrics), host));
+        }
+
+        span.record("duration_ms", start.elapsed().as_millis());
+        if let Ok(response) = &result {
+            span.record("http.status_code", response.status.as_u16());
+            span.record(
+                "http.response.status_code",
+                i64::from(response.status.as_u16()),
+            );
+        }
+
+        result
+    }
+
+    /// Counts a token served from the token cache for [`stats::measure`] and
+    /// the [`metrics::Metrics`] of the client.
+    fn token_cache_hit(&self, registry: &Registry) {
+        stats::record(Event::CacheHit);
+
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.token_cache_hit(registry);
+        }
+    }
+
+    /// Waits for a slot of the concurrency limit all clones share, see
+    /// [`ClientBuilder::concurrency_limit`]. The slot is free again once the
+    /// permit is dropped. The semaphore is never closed, so there always is
+    /// a permit.
+    async fn concurrency_permit(&self) -> Option<tokio::sync::SemaphorePermit<'_>> {
+        self.inner.concurrency.acquire().await.ok()
+    }
+
+    /// Turns a response with an unexpected status into an error. A `429` with
+    /// rate limit headers becomes [`Error::RateLimited`], everything else
+    /// the error `failed` returns.
+    async fn failed_request(
+        &self,
+        response: transport::Response,
+        rate_limit: Option<RateLimitStatus>,
+        failed: fn(Box<FailedResponse>) -> Error,
+    ) -> Error {
+        let status = response.status;
+        let response = FailedResponse::read(response, self.inner.max_manifest_size).await;
+
+        match rate_limit {
+            Some(rate_limit) if status == http::StatusCode::TOO_MANY_REQUESTS => {
+                Error::RateLimited {
+                    status: Box::new(rate_limit),
+                    response,
+                }
+            }
+
+            _ => failed(response),
+        }
+    }
+
+    /// Checks the status of `response` like [`api::RegistryApi::check`], a
+    /// `429` with rate limit headers becomes [`Error::RateLimited`] first.
+    async fn check_response(
+        &self,
+        api: &api::RegistryApi,
+        response: transport::Response,
+        rate_limit: Option<&RateLimitStatus>,
+        not_found: fn(Url) -> Error,
+        failed: fn(Box<FailedResponse>) -> Error,
+    ) -> Result<transport::Response, Error> {
+        if response.status == http::StatusCode::TOO_MANY_REQUESTS && rate_limit.is_some() {
+            return Err(self
+                .failed_request(response, rate_limit.cloned(), failed)
+                .await);
+        }
+
+        api.check(response, not_found, failed).await
+    }
+
+    /// Turns a `401` of a request sent with the cached token of `image` into
+    /// [`Error::TokenAudienceMismatch`] if the token was issued for another
+    /// audience, `failed` builds the error of the denied request. Other
+    /// responses are returned as they are.
+    async fn check_token_audience(
+        &self,
+        image: &Image,
+        authentication: &Authentication,
+        response: transport::Response,
+        failed: fn(Box<FailedResponse>) -> Error,
+    ) -> Result<transport::Response, Error> {
+        if response.status != http::StatusCode::UNAUTHORIZED
+            || !matches!(authentication, Authentication::Upstream)
+        {
+            return Ok(response);
+        }
+
+        let mismatch = self
+            .inner
+            .token_cache
+            .fetch(&image.into())
+            .await
+            .ok()
+            .flatten()
+            .and_then(|token| token.audience_mismatch());
+
+        let Some(mismatch) = mismatch else {
+            return Ok(response);
+        };
+
+        Err(Error::TokenAudienceMismatch {
+            mismatch: Box::new(mismatch),
+            error: Box::new(self.failed_request(response, None, failed).await),
+        })
+    }
+
+    /// Sends a request to the mirrors in order and then to `last`, `send`
+    /// sends it to a single endpoint with the given authentication headers.
+    /// Mirrors that fail, including getting their token, or answer with not
+    /// found or a server error are skipped, the response of `last` is
+    /// returned as is together with the endpoint that served it.
+    ///
+    /// With [`ClientBuilder::try_anonymous_on_token_failure`] the request to
+    /// `last` is sent without a token if the token endpoint is unavailable.
+    /// If that is denied as well, or fails, the token error is returned with
+    /// the error of the request, `failed` builds the one for a denied
+    /// request. A request denied with a token for another audience fails
+    /// with [`Error::TokenAudienceMismatch`].
+    async fn send_request<F, Fut>(
+        &self,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+        send: F,
+        failed: fn(Box<FailedResponse>) -> Error,
+    ) -> Result<(Endpoint, transport::Response), Error>
+    where
+        F: Fn(Url, HeaderMap) -> Fut,
+        Fut: Future<Output = Result<transport::Response, Error>>,
+    {
+        if self.inner.offline {
+            return Err(Error::OfflineCacheMiss {
+                image: image.clone(),
+            });
+        }
+
+        for endpoint in mirrors {
+            let headers = match self
+                .get_endpoint_headers(image, &endpoint.authentication)
+                .await
+            {
+                Ok(headers) => headers,
+
+                Err(e) => {
+                    warn!(
+                        url = %endpoint.url,
+                        error = %e,
+                        "failed to get headers for mirror, trying next endpoint"
+                    );
+
+                    stats::record(Event::Retry);
+                    continue;
+                }
+            };
+
+            match send(endpoint.url.clone(), headers).await {
+                Ok(response)
+                    if response.status != http::StatusCode::NOT_FOUND
+                        && !response.status.is_server_error() =>
+                {
+                    return Ok((endpoint, response));
+                }
+
+                Ok(response) => warn!(
+                    url = %endpoint.url,
+                    status = %response.status,
+                    "mirror did not serve the request, trying next endpoint"
+                ),
+
+                Err(e) => warn!(
+                    url = %endpoint.url,
+                    error = %e,
+                    "mirror request failed, trying next endpoint"
+                ),
+            }
+
+            stats::record(Event::Retry);
+        }
+
+        let (mut headers, token_error) =
+            match self.get_endpoint_headers(image, &last.authentication).await {
+                Ok(headers) => (headers, None),
+
+                Err(e) if self.inner.try_anonymous_on_token_failure && e.is_token_unavailable() => {
+                    warn!(
+                        registry = %image.registry,
+                        url = %last.url,
+                        error = %e,
+                        "token endpoint is unavailable, trying without a token"
+                    );
+                    (HeaderMap::new(), Some(e))
+                }
+
+                Err(e) => return Err(e),
+            };
+
+        let result = send(last.url.clone(), headers.clone()).await;
+
+        let Some(token) = token_error else {
+            let response = result?;
+
+            // Registries without a built-in token endpoint ask for a token
+            // with a challenge, the request is repeated once with it. A
+            // cached token that was rejected is replaced the same way.
+            let challenge = challenge::of(&response).filter(|_| {
+                !image.registry.needs_authentication()
+                    && matches!(last.authentication, Authentication::Upstream)
+            });
+
+            let Some(challenge) = challenge else {
+                let response = self
+                    .check_token_audience(image, &last.authentication, response, failed)
+                    .await?;
+
+                return Ok((last, response));
+            };
+
+            headers.extend(self.get_challenge_headers(image, &challenge).await?);
+
+            let response = send(last.url.clone(), headers).await?;
+
+            let response = self
+                .check_token_audience(image, &last.authentication, response, failed)
+                .await?;
+
+            return Ok((last, response));
+        };
+
+        let anonymous = match result {
+            Ok(response)
+                if response.status == http::StatusCode::UNAUTHORIZED
+                    || response.status == http::StatusCode::FORBIDDEN =>
+            {
+                self.failed_request(response, None, failed).await
+            }
+
+            Ok(response) => return Ok((last, response)),
+            Err(e) => e,
+        };
+
+        Err(Error::AnonymousFallbackFailed {
+            token: Box::new(token),
+            anonymous: Box::new(anonymous),
+        })
+    }
+
+    /// Sends a manifest request with `accept` as the `Accept` header through
+    /// [`api::RegistryApi::manifest_request`], see [`Client::send_request`].
+    async fn send_manifest_request(
+        &self,
+        method: Method,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+        accept: String,
+    ) -> Result<(Endpoint, transport::Response), Error> {
+        let api = api::RegistryApi::for_client(self);
+
+        Box::pin(self.send_request(
+            image,
+            mirrors,
+            last,
+            |url, auth| api.manifest_request(method.clone(), url, auth, &accept),
+            Error::FailedManifestRequest,
+        ))
+        .await
+    }
+
+    async fn get_endpoint_headers(
+        &self,
+        image: &Image,
+        authentication: &Authentication,
+    ) -> Result<HeaderMap, Error> {
+        match authentication.header_value() {
+            Some(value) => {
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    http::header::AUTHORIZATION,
+                    value.map_err(Error::ParseAuthorizationHeader)?,
+                );
+
+                Ok(headers)
+            }
+
+            None if matches!(authentication, Authentication::Upstream) => {
+                self.get_headers(image, &[auth::Action::Pull]).await
+            }
+
+            None => Ok(HeaderMap::new()),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "token",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+        )
+    )]
+    async fn get_headers(
+        &self,
+        image: &Image,
+        actions: &[auth::Action],
+    ) -> Result<HeaderMap, Error> {
+        let cache_key = token::CacheKey::new(image, actions);
+
+        let token = self
+            .inner
+            .token_cache
+            .fetch(&cache_key)
+            .await
+            .map_err(Error::FetchToken)?
+            .filter(|token| !token.is_expired(chrono::Utc::now(), self.inner.token_clock_skew));
+
+        let token = match token {
+            Some(token) => {
+                self.token_cache_hit(&image.registry);
+                token
+            }
+
+            // Registries without a built-in token endpoint only have the
+            // tokens fetched for their challenges.
+            None if !image.registry.needs_authentication() => return Ok(HeaderMap::new()),
+
+            None => match self.request_token(&image.registry, vec![cache_key]).await? {
+                Some(token) => token,
+                None => return Ok(HeaderMap::new()),
+            },
+        };
+
+        let headers = token.try_into().map_err(Error::ParseAuthorizationHeader)?;
+
+        Ok(headers)
+    }
+
+    /// Fetches tokens for all repositories of `images` that are not cached
+    /// yet. Repositories of the same registry share a single token request
+    /// with one scope per repository, the token is cached for each of them.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline and a token is missing.
+    /// Returns an error if a token can not be fetched or cached.
+    #[tracing::instrument(name = "token", skip_all)]
+    pub async fn prefetch_tokens(&self, images: &[Image]) -> Result<(), Error> {
+        let mut missing: Vec<(Registry, Vec<token::CacheKey>)> = Vec::new();
+
+        for image in images {
+            self.check_registry_policy(&image.registry)?;
+
+            if !image.registry.needs_authentication() {
+                continue;
+            }
+
+            let cache_key: token::CacheKey = image.into();
+
+            let cached = self
+                .inner
+                .token_cache
+                .fetch(&cache_key)
+                .await
+                .map_err(Error::FetchToken)?;
+
+            if cached.is_some_and(|token| {
+                !token.is_expired(chrono::Utc::now(), self.inner.token_clock_skew)
+            }) {
+                continue;
+            }
+
+            match missing
+                .iter_mut()
+                .find(|(registry, _)| *registry == image.registry)
+            {
+                Some((_, keys)) => keys.push(cache_key),
+                None => missing.push((image.registry.clone(), vec![cache_key])),
+            }
+        }
+
+        for (registry, keys) in missing {
+            self.request_token(&registry, keys).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetches and caches a token for every repository of `images` ahead of
+    /// time, so later requests do not have to wait for the token endpoint.
+    /// Unlike [`Client::prefetch_tokens`] every repository gets its own token
+    /// and a failure only affects its repository. Repositories whose cached
+    /// token is valid for longer than [`TOKEN_EXPIRY_MARGIN`] are skipped.
+    ///
+    /// Returns one result per repository together with the first image of
+    /// `images` that belongs to it, in the order of `images`.
+    #[tracing::instrument(name = "token", skip_all)]
+    pub async fn warm_tokens(&self, images: &[Image]) -> Vec<(Image, Result<(), Error>)> {
+        let mut seen = std::collections::HashSet::new();
+        let scopes: Vec<&Image> = images
+            .iter()
+            .filter(|image| image.registry.needs_authentication())
+            .filter(|image| seen.insert(token::CacheKey::from(*image)))
+            .collect();
+
+        futures::stream::iter(scopes)
+            .map(|image| async move { (image.clone(), self.warm_token(image).await) })
+            .buffered(WARM_TOKENS_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    async fn warm_token(&self, image: &Image) -> Result<(), Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        let cache_key: token::CacheKey = image.into();
+
+        let cached = self
+            .inner
+            .token_cache
+            .fetch(&cache_key)
+            .await
+            .map_err(Error::FetchToken)?;
+
+        let margin = chrono::Duration::from_std(TOKEN_EXPIRY_MARGIN).unwrap_or_default();
+        if cached.is_some_and(|token| !token.expires_within(margin, self.inner.token_clock_skew)) {
+            self.token_cache_hit(&image.registry);
+            return Ok(());
+        }
+
+        self.request_token(&image.registry, vec![cache_key]).await?;
+
+        Ok(())
+    }
+
+    /// Requests a token covering all `keys` and stores it in the cache under
+    /// every key. Returns `None` if the registry does not use tokens.
+    async fn request_token(
+        &self,
+        registry: &Registry,
+        keys: Vec<token::CacheKey>,
+    ) -> Result<Option<Token>, Error> {
+        let Some(request) = token::request(registry, &keys) else {
+            return Ok(None);
+        };
+
+        let token_url = request.url().map_err(Error::InvalidTokenUrl)?;
+
+        if self.inner.offline {
+            return Err(Error::Offline);
+        }
+
+        // The scopes are part of the URL, so concurrent requests for the same
+        // repositories share a single token.
+        let key = RequestKey::new(Method::GET, vec![token_url.clone()], None);
+
+        let token = self
+            .inner
+            .tokens_in_flight
+            .run(key, || self.fetch_token(registry, token_url, keys))
+            .await?;
+
+        Ok(Some(token))
+    }
+
+    async fn fetch_token(
+        &self,
+        registry: &Registry,
+        url: Url,
+        keys: Vec<token::CacheKey>,
+    ) -> Result<Token, Error> {
+        let response = self
+            .execute(Request::new(Method::GET, url.clone()))
+            .await
+            .map_err(Error::GetToken)?;
+
+        if quay::is_missing_repository(registry, response.status) {
+            return Err(Error::RepositoryNotFound(url));
+        }
+
+        if !response.status.is_success() {
+            return Err(
+                Box::pin(self.failed_request(response, None, Error::FailedTokenRequest)).await,
+            );
+        }
+
+        let body = read_body(
+            response,
+            self.inner.max_manifest_size,
+            Error::ExtractTokenBody,
+        )
+        .await?;
+
+        let token = serde_json::from_slice::<Token>(&body)
+            .map_err(|e| Error::DeserializeToken(e, String::from_utf8_lossy(&body).into_owned()))?
+            .received(self.inner.token_clock_skew)
+            .requested_from(&url);
+
+        for key in keys {
+            self.inner
+                .token_cache
+                .store(key, token.clone())
+                .await
+                .map_err(Error::StoreToken)?;
+        }
+
+        Ok(token)
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.parse().ok())
+}
+
+/// Reads the body but stops as soon as it grows beyond `limit` bytes so a
+/// hostile registry can not make us buffer an arbitrary amount of data. A
+/// `Content-Encoding` is removed first, see [`encoding::decoded_body`], the
+/// limit applies to the decoded body.
+async fn read_body(
+    response: transport::Response,
+    limit: u64,
+    map_err: fn(transport::Error) -> Error,
+) -> Result<Vec<u8>, Error> {
+    let url = response.url.clone();
+    let length = content_length(&response.headers);
+
+    if length.is_some_and(|length| length > limit) {
+        return Err(Error::BodyTooLarge { limit, url });
+    }
+
+    let capacity = length
+        .and_then(|length| usize::try_from(length).ok())
+        .unwrap_or_default();
+
+    let mut stream = encoding::decoded_body(response).map_err(map_err)?;
+    let mut body = Vec::with_capacity(capacity);
+
+    while let Some(chunk) = stream.try_next().await.map_err(map_err)? {
+        if body.len() as u64 + chunk.len() as u64 > limit {
+            return Err(Error::BodyTooLarge { limit, url });
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+/// Same as [`read_body`] but returns the body as text. Valid UTF-8 takes over
+/// the buffer without copying it, invalid sequences are replaced.
+async fn read_text(
+    response: transport::Response,
+    limit: u64,
+    map_err: fn(transport::Error) -> Error,
+) -> Result<String, Error> {
+    let body = read_body(response, limit, map_err).await?;
+
+    Ok(text(body))
+}
+
+/// Turns a body into text, see [`read_text`].
+fn text(body: Vec<u8>) -> String {
+    String::from_utf8(body).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// The JSON of a manifest body without a leading UTF-8 byte order mark and
+/// surrounding whitespace, which some registries and proxies add.
+/// Digests are always computed over the body as it was received, so a
+/// manifest served with a byte order mark does not match the digest the
+/// registry stores it under.
+fn manifest_json(body: &[u8]) -> &[u8] {
+    body.strip_prefix("\u{feff}".as_bytes())
+        .unwrap_or(body)
+        .trim_ascii()
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use crate::docker::transport;
+
+    /// Replays the named cassette from `src/cassettes`, or records it when the
+    /// `RECORD=1` environment variable is set.
+    fn cassette(name: &str) -> transport::cassette::CassetteTransport {
+        let path = format!("{}/src/cassettes/{name}.json", env!("CARGO_MANIFEST_DIR"));
+
+        transport::cassette::CassetteTransport::from_env(path).unwrap()
+    }
+
+    mod dockerhub {
+        use either::Either;
+
+        use crate::{
+            Client,
+            Image,
+            ImageName,
+            Registry,
+            Tag,
+        };
+
+        #[tokio::test]
+        async fn alpine() {
+            let cassette = super::cassette("dockerhub_alpine");
+            let client = Client::builder().transport(cassette.clone()).build();
+
+            let image_name = Image {
+                registry: Registry::DockerHub,
+                repository: Some("library".parse().unwrap()),
+                image_name: ImageName {
+                    name: "alpine".into(),
+                    identifier: Either::Left(Tag::Specific("3.20".into())),
+                },
+            };
+
+            let response = client.get_manifest(&image_name).await.unwrap();
+            cassette.finish().unwrap();
+
+            insta::assert_json_snapshot!(response.redacted_for_snapshot());
+        }
+    }
+
+    mod redhat {
+        use either::Either;
+
+        use crate::{
+            Client,
+            Image,
+            ImageName,
+            Registry,
+            Tag,
+        };
+
+        #[tokio::test]
+        async fn ubi8() {
+            let cassette = super::cassette("redhat_ubi8");
+            let client = Client::builder().transport(cassette.clone()).build();
+
+            let image = Image {
+                registry: Registry::RedHat,
+                repository: None,
+                image_name: ImageName {
+                    name: "ubi8".into(),
+                    identifier: Either::Left(Tag::Specific("8.9".into())),
+                },
+            };
+
+            let response = client.get_manifest(&image).await.unwrap();
+            cassette.finish().unwrap();
+
+            insta::assert_json_snapshot!(response.redacted_for_snapshot());
+        }
+
+        #[tokio::test]
+        async fn cosign() {
+            const INPUT: &str = "ghcr.io/sigstore/cosign/cosign:v2.4.0";
+
+            let cassette = super::cassette("github_cosign");
+            let client = Client::builder().transport(cassette.clone()).build();
+            let image = INPUT.parse().unwrap();
+            let response = client.get_manifest(&image).await.unwrap();
+            cassette.finish().unwrap();
+
+            insta::assert_json_snapshot!(response.redacted_for_snapshot());
+        }
+
+        #[tokio::test]
+        async fn playwright() {
+            const INPUT: &str = "mcr.microsoft.com/playwright:v1.48.2-noble";
+
+            let cassette = super::cassette("microsoft_playwright");
+            let client = Client::builder().transport(cassette.clone()).build();
+            let image = INPUT.parse().unwrap();
+            let response = client.get_manifest(&image).await.unwrap();
+            cassette.finish().unwrap();
+
+            insta::assert_json_snapshot!(response.redacted_for_snapshot());
+        }
+    }
+
+    mod mocked {
+        use either::Either;
+        use http::{
+            Method,
+            StatusCode,
+        };
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            Image,
+            ImageName,
+        };
+
+        #[tokio::test]
+        async fn lenient_manifest() {
+            const URL: &str = "https://registry.k8s.io/v2/app/manifests/1.0";
+            const INPUT: &str =
+                include_str!("../resources/manifest/lenient/weird-architecture.json");
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                URL,
+                MockResponse::new(StatusCode::OK).body(INPUT),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+            // The typed parse fails on the unknown architecture.
+            assert!(client.get_manifest(&image).await.is_err());
+
+            let got = client.get_manifest_lenient(&image).await.unwrap();
+
+            let Some(crate::Manifest::List(list)) = got.manifest else {
+                panic!("expected a list");
+            };
+            assert_eq!(3, list.manifests.len());
+            assert_eq!(2, got.parse_warnings.len());
+        }
+
+        #[tokio::test]
+        async fn authorization_header() {
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io",
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"github-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/github/cosign.json")),
+                );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            let requests = transport.requests();
+            assert_eq!(2, requests.len());
+            assert_eq!(
+                "Bearer github-token",
+                requests[1].headers.get("Authorization").unwrap()
+            );
+        }
+
+        #[tokio::test]
+        async fn invalid_path_is_rejected_before_request() {
+            let transport = MockTransport::new();
+            let client = Client::builder().transport(transport.clone()).build();
+            // Image names built without the parser are only validated when
+            // the URL is built.
+            let image = Image {
+                image_name: ImageName::new("..", Either::Left("v2.4.0".parse().unwrap())),
+                ..crate::image!("ghcr.io/sigstore/cosign/cosign:v2.4.0")
+            };
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(got, crate::ClientError::InvalidPath(_)));
+            assert!(transport.requests().is_empty());
+        }
+
+        #[tokio::test]
+        async fn unmatched_request() {
+            let client = Client::builder().transport(MockTransport::new()).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(got, crate::ClientError::GetManifest(_)));
+        }
+
+        #[tokio::test]
+        async fn warnings() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK)
+                    .header("Warning", r#"299 - "first""#)
+                    .header("Warning", "malformed")
+                    .header("Warning", r#"299 - "second""#)
+                    .body(include_str!("../resources/registry/redhat/ubi8.json")),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap();
+
+            let texts: Vec<_> = got.warnings.iter().map(|w| w.text.as_str()).collect();
+            assert_eq!(texts, ["first", "second"]);
+        }
+
+        #[tokio::test]
+        async fn failed_response_headers() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::TOO_MANY_REQUESTS)
+                    .header("Retry-After", "30")
+                    .header("X-Request-Id", "abc123")
+                    .body(r#"{"errors":[{"code":"TOOMANYREQUESTS"}]}"#),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            let crate::ClientError::FailedManifestRequest(response) = &got else {
+                panic!("unexpected error: {got}");
+            };
+            assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.header("retry-after"), Some("30"));
+            assert_eq!(response.header("x-request-id"), Some("abc123"));
+            assert_eq!(
+                got.to_string(),
+                "Failed manifest request: status 429 Too Many Requests from \
+                 https://registry.access.redhat.com/v2/ubi8/manifests/8.9, body: \
+                 {\"errors\":[{\"code\":\"TOOMANYREQUESTS\"}]}"
+            );
+        }
+
+        #[tokio::test]
+        async fn html_instead_of_manifest() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK)
+                    .header("Content-Type", "text/html")
+                    .body("<!DOCTYPE html><html><body>Sign in</body></html>"),
+            );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(
+                &got,
+                crate::ClientError::NotAV2Registry { url, content_type }
+                    if url.as_str() == "https://registry.access.redhat.com/v2/ubi8/manifests/8.9"
+                        && content_type.as_deref() == Some("text/html")
+            ));
+
+            let client = Client::builder()
+                .transport(transport)
+                .skip_api_version_check("registry.access.redhat.com")
+                .build();
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                crate::ClientError::DeserializeManifestBody(..)
+            ));
+        }
+
+        #[tokio::test]
+        async fn manifest_without_api_version() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/raw",
+                MockResponse::new(StatusCode::OK)
+                    .header("Content-Type", "application/vnd.oci.image.index.v1+json")
+                    .body(r#"{"schemaVersion":2,"manifests":[]}"#),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.access.redhat.com/ubi8:raw".parse().unwrap();
+
+            client.get_manifest_raw(&image).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn rate_limited() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::TOO_MANY_REQUESTS)
+                    .header("ratelimit-limit", "100;w=21600")
+                    .header("ratelimit-remaining", "0;w=21600")
+                    .header("docker-ratelimit-source", "192.0.2.1"),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            let crate::ClientError::RateLimited { status, response } = &got else {
+                panic!("unexpected error: {got}");
+            };
+            assert_eq!((status.limit, status.remaining), (100, 0));
+            assert_eq!(status.source_ip_scope.as_deref(), Some("192.0.2.1"));
+            assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+            assert_eq!(
+                client.rate_limit_status().get(&crate::Registry::RedHat),
+                Some(status.as_ref())
+            );
+        }
+
+        #[tokio::test]
+        async fn rate_limit_on_success() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/raw",
+                MockResponse::new(StatusCode::OK)
+                    .header("ratelimit-limit", "100;w=21600")
+                    .header("ratelimit-remaining", "76;w=21600")
+                    .body("{}"),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.access.redhat.com/ubi8:raw".parse().unwrap();
+
+            assert!(client.rate_limit_status().is_empty());
+
+            let got = client.get_manifest_raw(&image).await.unwrap();
+            let status = got.rate_limit.unwrap();
+
+            assert_eq!(status.remaining, 76);
+            assert_eq!(status.window, std::time::Duration::from_hours(6));
+            assert!(status.estimated_reset > chrono::Utc::now());
+            assert_eq!(
+                client.rate_limit_status().get(&crate::Registry::RedHat),
+                Some(&status)
+            );
+        }
+
+        #[tokio::test]
+        async fn raw_artifact_manifest() {
+            const ARTIFACT: &str = r#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.artifact.manifest.v1+json",
+                "artifactType": "application/vnd.example.sbom.v1",
+                "blobs": []
+            }"#;
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/sbom",
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.oci.artifact.manifest.v1+json",
+                    )
+                    .body(ARTIFACT),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.access.redhat.com/ubi8:sbom".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+            assert!(matches!(
+                got,
+                crate::ClientError::DeserializeManifestBody(..)
+            ));
+
+            let got = client.get_manifest_raw(&image).await.unwrap();
+            assert_eq!(
+                got.content_type.as_deref(),
+                Some("application/vnd.oci.artifact.manifest.v1+json")
+            );
+            assert_eq!(got.json["artifactType"], "application/vnd.example.sbom.v1");
+            assert_eq!(got.body, ARTIFACT.as_bytes());
+        }
+
+        #[tokio::test]
+        async fn prefetch_tokens() {
+            const TOKEN_URL: &str = "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&scope=repository:sigstore/rekor-cli:pull&service=ghcr.io";
+            const MANIFEST_URL: &str = "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0";
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    TOKEN_URL,
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"shared-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    MANIFEST_URL,
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/github/cosign.json")),
+                );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let cosign: crate::Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+            let rekor = "ghcr.io/sigstore/rekor-cli:v1.3.6".parse().unwrap();
+
+            client
+                .prefetch_tokens(&[cosign.clone(), rekor, cosign.clone()])
+                .await
+                .unwrap();
+
+            // The token is cached for each scope so the manifest request does
+            // not fetch another one.
+            client.get_manifest(&cosign).await.unwrap();
+
+            let requests = transport.requests();
+            let urls: Vec<_> = requests.iter().map(|r| r.url.as_str()).collect();
+            assert_eq!(urls, [TOKEN_URL, MANIFEST_URL]);
+            assert_eq!(
+                "Bearer shared-token",
+                requests[1].headers.get("Authorization").unwrap()
+            );
+        }
+    }
+
+    mod warm_tokens {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            Image,
+        };
+
+        const COSIGN: &str =
+            "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io";
+        const REKOR: &str =
+            "https://ghcr.io/token?scope=repository:sigstore/rekor-cli:pull&service=ghcr.io";
+
+        /// A token issued now by a server whose clock is `server_clock` off.
+        fn token(expires_in: i64, server_clock: chrono::Duration) -> String {
+            serde_json::json!({
+                "token": "token",
+                "expires_in": expires_in,
+                "issued_at": chrono::Utc::now() + server_clock,
+            })
+            .to_string()
+        }
+
+        fn images(names: &[&str]) -> Vec<Image> {
+            names.iter().map(|name| name.parse().unwrap()).collect()
+        }
+
+        #[tokio::test]
+        async fn deduplicates_scopes() {
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    COSIGN,
+                    MockResponse::new(StatusCode::OK).body(token(300, chrono::Duration::zero())),
+                )
+                .with_response(
+                    Method::GET,
+                    REKOR,
+                    MockResponse::new(StatusCode::OK).body(token(300, chrono::Duration::zero())),
+                );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let images = images(&[
+                "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+                "ghcr.io/sigstore/rekor-cli:v1.3.6",
+                "ghcr.io/sigstore/cosign/cosign:v2.4.1",
+                "ghcr.io/sigstore/cosign/cosign:latest",
+                "ghcr.io/sigstore/rekor-cli:latest",
+            ]);
+
+            let got = client.warm_tokens(&images).await;
+
+            let warmed: Vec<String> = got.iter().map(|(image, _)| image.to_string()).collect();
+            assert_eq!(
+                warmed,
+                [
+                    "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+                    "ghcr.io/sigstore/rekor-cli:v1.3.6"
+                ]
+            );
+            assert!(got.iter().all(|(_, result)| result.is_ok()));
+            assert_eq!(2, transport.requests().len());
+
+            // Both tokens are fresh, so warming again does nothing.
+            client.warm_tokens(&images).await;
+            assert_eq!(2, transport.requests().len());
+        }
+
+        #[tokio::test]
+        async fn refreshes_expiring_tokens() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                COSIGN,
+                MockResponse::new(StatusCode::OK).body(token(20, chrono::Duration::zero())),
+            );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let images = images(&["ghcr.io/sigstore/cosign/cosign:v2.4.0"]);
+
+            client.warm_tokens(&images).await;
+            client.warm_tokens(&images).await;
+
+            // The token expires within the margin, so it is fetched again.
+            assert_eq!(2, transport.requests().len());
+        }
+
+        /// The lifetime counts from when the token was received, a server
+        /// clock that is off does not expire tokens early or late.
+        #[tokio::test]
+        async fn server_clock_skew() {
+            for skew in [chrono::Duration::minutes(5), chrono::Duration::minutes(-5)] {
+                let transport = MockTransport::new().with_response(
+                    Method::GET,
+                    COSIGN,
+                    MockResponse::new(StatusCode::OK).body(token(300, skew)),
+                );
+
+                let client = Client::builder()
+                    .transport(transport.clone())
+                    .token_clock_skew(std::time::Duration::from_mins(1))
+                    .build();
+                let images = images(&["ghcr.io/sigstore/cosign/cosign:v2.4.0"]);
+
+                client.warm_tokens(&images).await;
+                client.warm_tokens(&images).await;
+                client.prefetch_tokens(&images).await.unwrap();
+
+                assert_eq!(1, transport.requests().len(), "{skew}");
+            }
+        }
+
+        #[tokio::test]
+        async fn failures_do_not_stop_other_scopes() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                REKOR,
+                MockResponse::new(StatusCode::OK).body(token(300, chrono::Duration::zero())),
+            );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let images = images(&[
+                "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+                "ghcr.io/sigstore/rekor-cli:v1.3.6",
+                "registry.k8s.io/pause:3.9",
+            ]);
+
+            let got = client.warm_tokens(&images).await;
+
+            // Registries without tokens are left out.
+            assert_eq!(2, got.len());
+            assert!(got[0].1.is_err());
+            assert!(got[1].1.is_ok());
+        }
+    }
+
+    mod mirror {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                mirror::{
+                    Authentication,
+                    Mirror,
+                },
+                transport::{
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            Client,
+            ClientError,
+        };
+
+        const FIRST_MIRROR: &str = "http://mirror-a.internal:5000/v2/ubi8/manifests/8.9";
+        const SECOND_MIRROR: &str = "http://mirror-b.internal/cache/v2/ubi8/manifests/8.9";
+        const UPSTREAM: &str = "https://registry.access.redhat.com/v2/ubi8/manifests/8.9";
+
+        const BODY: &str = include_str!("../resources/registry/redhat/ubi8.json");
+
+        fn requested_urls(transport: &MockTransport) -> Vec<String> {
+            transport
+                .requests()
+                .into_iter()
+                .map(|request| request.url.to_string())
+                .collect()
+        }
+
+        fn client(transport: &MockTransport) -> crate::ClientBuilder {
+            Client::builder()
+                .transport(transport.clone())
+                .registry_mirror(
+                    "registry.access.redhat.com",
+                    "http://mirror-a.internal:5000".parse::<url::Url>().unwrap(),
+                )
+                .registry_mirror(
+                    "registry.access.redhat.com",
+                    "http://mirror-b.internal/cache"
+                        .parse::<url::Url>()
+                        .unwrap(),
+                )
+        }
+
+        #[tokio::test]
+        async fn falls_back_in_order() {
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    FIRST_MIRROR,
+                    MockResponse::new(StatusCode::NOT_FOUND),
+                )
+                .with_response(
+                    Method::GET,
+                    SECOND_MIRROR,
+                    MockResponse::new(StatusCode::SERVICE_UNAVAILABLE),
+                )
+                .with_response(
+                    Method::GET,
+                    UPSTREAM,
+                    MockResponse::new(StatusCode::OK).body(BODY),
+                );
+
+            let client = client(&transport).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            assert_eq!(
+                vec![FIRST_MIRROR, SECOND_MIRROR, UPSTREAM],
+                requested_urls(&transport)
+            );
+        }
+
+        #[tokio::test]
+        async fn first_mirror_serves() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                FIRST_MIRROR,
+                MockResponse::new(StatusCode::OK).body(BODY),
+            );
+
+            let client = client(&transport).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            assert_eq!(vec![FIRST_MIRROR], requested_urls(&transport));
+        }
+
+        #[tokio::test]
+        async fn mirror_token_failure_tries_next() {
+            const TOKEN: &str =
+                "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io";
+            const SECOND: &str =
+                "http://mirror-b.internal/v2/sigstore/cosign/cosign/manifests/v2.4.0";
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    TOKEN,
+                    MockResponse::new(StatusCode::SERVICE_UNAVAILABLE),
+                )
+                .with_response(
+                    Method::GET,
+                    SECOND,
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/github/cosign.json")),
+                );
+
+            let client = Client::builder()
+                .transport(transport.clone())
+                .registry_mirror(
+                    "ghcr.io",
+                    Mirror::new("http://mirror-a.internal:5000".parse().unwrap())
+                        .authentication(Authentication::Upstream),
+                )
+                .registry_mirror(
+                    "ghcr.io",
+                    "http://mirror-b.internal".parse::<url::Url>().unwrap(),
+                )
+                .build();
+
+            let image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            // The first mirror is never asked as its token could not be
+            // fetched.
+            assert_eq!(vec![TOKEN, SECOND], requested_urls(&transport));
+        }
+
+        #[tokio::test]
+        async fn upstream_fallback_disabled() {
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    FIRST_MIRROR,
+                    MockResponse::new(StatusCode::NOT_FOUND),
+                )
+                .with_response(
+                    Method::GET,
+                    SECOND_MIRROR,
+                    MockResponse::new(StatusCode::NOT_FOUND),
+                );
+
+            let client = client(&transport).fallback_to_upstream(false).build();
+            let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(
+                matches!(got, ClientError::ManifestNotFound(url) if url.as_str() == SECOND_MIRROR)
+            );
+            assert_eq!(
+                vec![FIRST_MIRROR, SECOND_MIRROR],
+                requested_urls(&transport)
+            );
+        }
+
+        #[tokio::test]
+        async fn own_credentials() {
+            const MIRROR: &str =
+                "http://mirror.internal:5000/v2/sigstore/cosign/cosign/manifests/v2.4.0";
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                MIRROR,
+                MockResponse::new(StatusCode::OK)
+                    .body(include_str!("../resources/registry/github/cosign.json")),
+            );
+
+            let mirror = Mirror::new("http://mirror.internal:5000".parse().unwrap())
+                .authentication(Authentication::Basic {
+                    username: "user".to_string(),
+                    password: "password".to_string(),
+                });
+
+            let client = Client::builder()
+                .transport(transport.clone())
+                .registry_mirror("ghcr.io", mirror)
+                .build();
+
+            let image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            // No token is requested from the original registry.
+            let requests = transport.requests();
+            assert_eq!(1, requests.len());
+            assert_eq!(
+                "Basic dXNlcjpwYXNzd29yZA==",
+                requests[0].headers.get("Authorization").unwrap()
+            );
+        }
+
+        #[tokio::test]
+        async fn docker_io_alias() {
+            const MIRROR: &str = "http://mirror.internal:5000/v2/library/alpine/manifests/3.20";
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                MIRROR,
+                MockResponse::new(StatusCode::OK)
+                    .body(include_str!("../resources/registry/dockerhub/alpine.json")),
+            );
+
+            let client = Client::builder()
+                .transport(transport.clone())
+                .registry_mirror(
+                    "docker.io",
+                    "http://mirror.internal:5000".parse::<url::Url>().unwrap(),
+                )
+                .fallback_to_upstream(false)
+                .build();
+
+            let image = "alpine:3.20".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            assert_eq!(vec![MIRROR], requested_urls(&transport));
+        }
+
+        #[tokio::test]
+        async fn dockerhub_api_host() {
+            const MANIFEST: &str =
+                "https://registry-1.docker.io/v2/library/alpine/manifests/latest";
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    "https://auth.docker.io/token?service=registry.docker.io&scope=repository:library/alpine:pull&service=registry.docker.io",
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"hub-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    MANIFEST,
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/dockerhub/alpine.json")),
+                );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let image: crate::Image = "alpine:latest".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            assert_eq!(MANIFEST, requested_urls(&transport)[1]);
+            assert_eq!("index.docker.io/library/alpine:latest", image.to_string());
+        }
+
+        #[tokio::test]
+        async fn api_base_override() {
+            const MANIFEST: &str = "https://mirror.gcr.io/v2/library/alpine/manifests/latest";
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    "https://auth.docker.io/token?service=registry.docker.io&scope=repository:library/alpine:pull&service=registry.docker.io",
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"hub-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    MANIFEST,
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/dockerhub/alpine.json")),
+                );
+
+            let client = Client::builder()
+                .transport(transport.clone())
+                .registry_api_base("docker.io", "https://mirror.gcr.io".parse().unwrap())
+                .build();
+            let image: crate::Image = "alpine:latest".parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+
+            assert_eq!(MANIFEST, requested_urls(&transport)[1]);
+            assert_eq!("index.docker.io/library/alpine:latest", image.to_string());
+        }
+    }
+
+    mod missing_media_type {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            Image,
+            Manifest,
+        };
+
+        #[tokio::test]
+        async fn uses_content_type() {
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.v2+json",
+                    )
+                    .body(include_str!(
+                        "../resources/manifest/image/oci-without-media-type.json"
+                    )),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let got = client.get_manifest(&image).await.unwrap();
+
+            let Manifest::Image(manifest) = got.manifest else {
+                panic!("expected an image manifest");
+            };
+            assert_eq!(
+                manifest.media_type,
+                "application/vnd.docker.distribution.manifest.v2+json"
+            );
+        }
+    }
+
+    mod digest_mismatch {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const BODY: &str = include_str!("../resources/manifest/image/example.json");
+        const LIE: &str = "sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+        fn digest() -> Digest {
+            Digest::sha256(BODY.as_bytes())
+        }
+
+        fn image() -> Image {
+            format!("registry.access.redhat.com/ubi8@{}", digest())
+                .parse()
+                .unwrap()
+        }
+
+        /// Serves the manifest requested by digest with `header` as its
+        /// `Docker-Content-Digest` and `body` as its content.
+        fn transport(header: &str, body: &str) -> MockTransport {
+            MockTransport::new().with_response(
+                Method::GET,
+                &format!(
+                    "https://registry.access.redhat.com/v2/ubi8/manifests/{}",
+                    digest()
+                ),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", header)
+                    .body(body.to_string()),
+            )
+        }
+
+        #[tokio::test]
+        async fn verified() {
+            let client = Client::builder()
+                .transport(transport(&digest().to_string(), BODY))
+                .build();
+
+            let got = client.get_manifest(&image()).await.unwrap();
+
+            assert!(got.digest_verified);
+        }
+
+        #[tokio::test]
+        async fn lying_header() {
+            let client = Client::builder().transport(transport(LIE, BODY)).build();
+
+            let got = client.get_manifest(&image()).await.unwrap_err();
+
+            let ClientError::DigestMismatch {
+                requested,
+                received,
+                ..
+            } = got
+            else {
+                panic!("expected a digest mismatch, got {got}");
+            };
+            assert_eq!(requested, digest());
+            assert_eq!(received.to_string(), LIE);
+        }
+
+        #[tokio::test]
+        async fn lying_body() {
+            let body = BODY.replace("sha256", "sha256 ");
+            let client = Client::builder()
+                .transport(transport(&digest().to_string(), &body))
+                .build();
+
+            let got = client.get_manifest_raw(&image()).await.unwrap_err();
+
+            let ClientError::DigestMismatch { received, .. } = got else {
+                panic!("expected a digest mismatch, got {got}");
+            };
+            assert_eq!(received, Digest::sha256(body.as_bytes()));
+        }
+
+        #[tokio::test]
+        async fn allowed() {
+            let client = Client::builder()
+                .transport(transport(LIE, BODY))
+                .allow_digest_mismatch(true)
+                .build();
+
+            let got = client.get_manifest(&image()).await.unwrap();
+
+            assert!(!got.digest_verified);
+            assert_eq!(got.digest.as_deref(), Some(LIE));
+        }
+
+        #[tokio::test]
+        async fn by_tag() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", LIE)
+                    .body(BODY),
+            );
+            let client = Client::builder().transport(transport).build();
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap();
+
+            assert!(!got.digest_verified);
+        }
+    }
+
+    mod offline {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Image,
+        };
+
+        #[tokio::test]
+        async fn serves_from_cache() {
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK)
+                    .body(include_str!("../resources/registry/redhat/ubi8.json")),
+            );
+
+            let mut client = Client::builder()
+                .transport(transport.clone())
+                .manifest_cache_memory()
+                .build();
+
+            let mut expected = client.get_manifest(&image).await.unwrap();
+            assert_eq!(1, transport.requests().len());
+
+            // The cache does not know which host served the manifest.
+            expected.served_by = None;
+
+            client.set_offline(true);
+
+            let got = client.get_manifest(&image).await.unwrap();
+            assert_eq!(
+                serde_json::to_value(expected).unwrap(),
+                serde_json::to_value(got).unwrap()
+            );
+
+            let missing: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+            let got = client.get_manifest(&missing).await.unwrap_err();
+            assert!(matches!(got, ClientError::OfflineCacheMiss { image } if image == missing));
+
+            // Neither the cache hit nor the miss touched the network.
+            assert_eq!(1, transport.requests().len());
+        }
+
+        #[tokio::test]
+        async fn without_cache() {
+            let transport = MockTransport::new();
+            let client = Client::builder()
+                .transport(transport.clone())
+                .offline(true)
+                .build();
+
+            let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+            let known_digest =
+                "sha256:2247f14d217577b451727b3015f95e97d47941e96b99806f8589a34c43112ec3"
+                    .parse()
+                    .unwrap();
+
+            let got = client
+                .check_for_update(&image, &known_digest)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(got, ClientError::OfflineCacheMiss { .. }));
+            assert!(transport.requests().is_empty());
+        }
+    }
+
+    mod registry_policy {
+        use http::{
+            Method,
+            StatusCode,
+        };
+
+        use crate::{
+            docker::{
+                policy::Policy,
+                transport::{
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            Client,
+            ClientError,
+            Image,
+            Registry,
+        };
+
+        fn client(transport: &MockTransport, policy: Policy) -> Client {
+            Client::builder()
+                .transport(transport.clone())
+                .registry_policy(policy)
+                .build()
+        }
+
+        #[tokio::test]
+        async fn allowed() {
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK)
+                    .body(include_str!("../resources/registry/redhat/ubi8.json")),
+            );
+
+            let client = client(
+                &transport,
+                Policy::default().allow("registry.access.redhat.com"),
+            );
+
+            client.get_manifest(&image).await.unwrap();
+            assert_eq!(1, transport.requests().len());
+        }
+
+        #[tokio::test]
+        async fn denied_before_any_request() {
+            let transport = MockTransport::new();
+            let client = client(
+                &transport,
+                Policy::default().allow("*.internal.example.com"),
+            );
+
+            let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+            let digest = "sha256:2247f14d217577b451727b3015f95e97d47941e96b99806f8589a34c43112ec3"
+                .parse()
+                .unwrap();
+
+            let denied = |result: Result<_, ClientError>| {
+                matches!(
+                    result,
+                    Err(ClientError::RegistryDenied { registry }) if registry == Registry::Github
+                )
+            };
+
+            assert!(denied(client.get_manifest(&image).await.map(drop)));
+            assert!(denied(
+                client.check_for_update(&image, &digest).await.map(drop)
+            ));
+            assert!(denied(client.get_blob(&image, &digest).await.map(drop)));
+            assert!(denied(client.list_tags(&image).await.map(drop)));
+            assert!(denied(client.blob_exists(&image, &digest).await.map(drop)));
+            assert!(denied(
+                client.prefetch_tokens(std::slice::from_ref(&image)).await
+            ));
+            assert!(denied(client.ping(&Registry::Github).await.map(drop)));
+            assert!(denied(
+                client.health_check(&Registry::Github).await.map(drop)
+            ));
+
+            assert!(transport.requests().is_empty());
+        }
+
+        #[tokio::test]
+        async fn wildcard_allows_subdomains() {
+            let image: Image = "registry.internal.example.com/team/app:1.0"
+                .parse()
+                .unwrap();
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.internal.example.com/v2/team/app/manifests/1.0",
+                MockResponse::new(StatusCode::OK)
+                    .body(include_str!("../resources/registry/redhat/ubi8.json")),
+            );
+
+            let client = client(
+                &transport,
+                Policy::default().allow("*.internal.example.com"),
+            );
+
+            client.get_manifest(&image).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn denylist() {
+            let transport = MockTransport::new();
+            let client = client(&transport, Policy::allow_all().deny("quay.io"));
+
+            let image: Image = "quay.io/prometheus/prometheus:v2.53.2".parse().unwrap();
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::RegistryDenied { registry } if registry == Registry::Quay
+            ));
+            assert!(transport.requests().is_empty());
+        }
+    }
+
+    mod get_layer_tar {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+        use tokio::io::{
+            AsyncRead,
+            AsyncReadExt,
+        };
+
+        use crate::{
+            docker::{
+                blob,
+                layer,
+                transport::{
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            manifest::Layer,
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const GZIP: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+        fn tarball() -> Vec<u8> {
+            let content = b"hello from a layer\n";
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            let mut builder = tar::Builder::new(Vec::new());
+            builder
+                .append_data(&mut header, "etc/motd", &content[..])
+                .unwrap();
+
+            builder.into_inner().unwrap()
+        }
+
+        async fn gzip(content: &[u8]) -> Vec<u8> {
+            let mut compressed = Vec::new();
+            async_compression::tokio::bufread::GzipEncoder::new(content)
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap();
+
+            compressed
+        }
+
+        /// Serves `blob` for a layer with the given media type and digest and
+        /// returns the reader of the layer tarball.
+        async fn layer_tar(
+            media_type: &str,
+            digest: &Digest,
+            blob: Vec<u8>,
+        ) -> Result<impl AsyncRead + Unpin, ClientError> {
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &format!("https://registry.access.redhat.com/v2/ubi8/blobs/{digest}"),
+                MockResponse::new(StatusCode::OK).body(blob),
+            );
+
+            let layer = Layer::new(media_type, 0, digest.clone());
+
+            Client::builder()
+                .transport(transport)
+                .build()
+                .get_layer_tar(&image, &layer)
+                .await
+        }
+
+        async fn round_trip(media_type: &str, compressed: Vec<u8>) {
+            let digest = Digest::sha256(&compressed);
+
+            let mut reader = layer_tar(media_type, &digest, compressed).await.unwrap();
+
+            let mut got = Vec::new();
+            reader.read_to_end(&mut got).await.unwrap();
+
+            assert_eq!(tarball(), got);
+
+            let mut archive = tar::Archive::new(got.as_slice());
+            let entry = archive.entries().unwrap().next().unwrap().unwrap();
+            assert_eq!("etc/motd", entry.path().unwrap().to_str().unwrap());
+        }
+
+        #[tokio::test]
+        async fn gzip_round_trip() {
+            round_trip(GZIP, gzip(&tarball()).await).await;
+        }
+
+        #[tokio::test]
+        async fn uncompressed_round_trip() {
+            round_trip("application/vnd.oci.image.layer.v1.tar", tarball()).await;
+        }
+
+        #[cfg(feature = "zstd")]
+        #[tokio::test]
+        async fn zstd_round_trip() {
+            let mut compressed = Vec::new();
+            async_compression::tokio::bufread::ZstdEncoder::new(tarball().as_slice())
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap();
+
+            round_trip("application/vnd.oci.image.layer.v1.tar+zstd", compressed).await;
+        }
+
+        #[cfg(not(feature = "zstd"))]
+        #[tokio::test]
+        async fn zstd_disabled() {
+            let digest = Digest::sha256(b"");
+
+            let got = layer_tar(
+                "application/vnd.oci.image.layer.v1.tar+zstd",
+                &digest,
+                Vec::new(),
+            )
+            .await
+            .err()
+            .unwrap();
+
+            assert!(matches!(
+                got,
+                ClientError::DecodeLayer(layer::Error::UnsupportedCompression(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn digest_mismatch() {
+            let expected = Digest::sha256(&gzip(&tarball()).await);
+
+            // Still a valid gzip stream so only the digest check can catch it.
+            let corrupted = gzip(b"not the layer you are looking for").await;
+
+            let mut reader = layer_tar(GZIP, &expected, corrupted).await.unwrap();
+
+            let got = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+            let got = got.into_inner().unwrap().downcast::<blob::Error>().unwrap();
+
+            assert!(matches!(*got, blob::Error::DigestMismatch { .. }));
+        }
+
+        #[tokio::test]
+        async fn unknown_media_type() {
+            let digest = Digest::sha256(b"");
+
+            let got = layer_tar("application/octet-stream", &digest, Vec::new())
+                .await
+                .err()
+                .unwrap();
+
+            assert!(matches!(
+                got,
+                ClientError::DecodeLayer(layer::Error::UnknownMediaType(_))
+            ));
+        }
+    }
+
+    mod content_encoding {
+        use futures::TryStreamExt;
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+        use tokio::io::AsyncReadExt;
+
+        use crate::{
+            docker::transport::{
+                self,
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const MANIFEST: &str = include_str!("../resources/manifest/image/example.json");
+        const BASE: &str = "https://registry.access.redhat.com/v2/ubi8";
+
+        async fn gzip(content: &[u8]) -> Vec<u8> {
+            let mut compressed = Vec::new();
+            async_compression::tokio::bufread::GzipEncoder::new(content)
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap();
+
+            compressed
+        }
+
+        fn image(digest: &Digest) -> Image {
+            format!("registry.access.redhat.com/ubi8@{digest}")
+                .parse()
+                .unwrap()
+        }
+
+        async fn get_manifest(
+            encoding: &str,
+            body: Vec<u8>,
+        ) -> Result<crate::Response, ClientError> {
+            let digest = Digest::sha256(MANIFEST.as_bytes());
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{digest}"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Content-Encoding", encoding)
+                    .header("Docker-Content-Digest", &digest.to_string())
+                    .body(body),
+            );
+
+            Client::builder()
+                .transport(transport)
+                .build()
+                .get_manifest(&image(&digest))
+                .await
+        }
+
+        /// The digest is computed over the decoded manifest.
+        #[tokio::test]
+        async fn gzip_manifest() {
+            let got = get_manifest("gzip", gzip(MANIFEST.as_bytes()).await)
+                .await
+                .unwrap();
+
+            assert!(got.digest_verified);
+        }
+
+        #[cfg(feature = "zstd")]
+        #[tokio::test]
+        async fn zstd_manifest() {
+            let mut compressed = Vec::new();
+            async_compression::tokio::bufread::ZstdEncoder::new(MANIFEST.as_bytes())
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap();
+
+            let got = get_manifest("zstd", compressed).await.unwrap();
+
+            assert!(got.digest_verified);
+        }
+
+        #[tokio::test]
+        async fn unsupported() {
+            let got = get_manifest("br", MANIFEST.into()).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::ExtractManifestBody(transport::Error::UnsupportedContentEncoding(
+                    encoding
+                )) if encoding == "br"
+            ));
+        }
+
+        #[tokio::test]
+        async fn corrupt() {
+            let got = get_manifest("gzip", MANIFEST.into()).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::ExtractManifestBody(transport::Error::Decode(_))
+            ));
+        }
+
+        /// Blobs are stored compressed, a `Content-Encoding` some storage
+        /// backends add does not change what the digest is computed over.
+        #[tokio::test]
+        async fn blob_is_not_decoded() {
+            let blob = gzip(b"layer").await;
+            let digest = Digest::sha256(&blob);
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{digest}"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Content-Encoding", "gzip")
+                    .body(blob.clone()),
+            );
+
+            let chunks: Vec<bytes::Bytes> = Client::builder()
+                .transport(transport)
+                .build()
+                .get_blob(&image(&digest), &digest)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+
+            assert_eq!(chunks.concat(), blob);
+        }
+    }
+
+    /// Manifests served with a UTF-8 byte order mark and surrounding
+    /// whitespace are parsed without them. The digest is computed over the
+    /// body as it was received, byte order mark included.
+    mod byte_order_mark {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Digest,
+            Image,
+            Manifest,
+        };
+
+        const MANIFEST: &str = include_str!("../resources/manifest/image/example.json");
+        const BASE: &str = "https://registry.access.redhat.com/v2/ubi8";
+
+        fn body() -> String {
+            format!("\u{feff}\n{MANIFEST}\n")
+        }
+
+        fn client(reference: &str) -> Client {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{reference}"),
+                MockResponse::new(StatusCode::OK).body(body()),
+            );
+
+            Client::builder().transport(transport).build()
+        }
+
+        #[tokio::test]
+        async fn by_tag() {
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+            let got = client("8.9").get_manifest_raw(&image).await.unwrap();
+
+            assert_eq!(got.body, body().as_bytes());
+            assert_eq!(got.json["schemaVersion"], 2);
+
+            let got = client("8.9").get_manifest(&image).await.unwrap();
+            assert!(matches!(got.manifest, Manifest::Image(_)));
+        }
+
+        #[tokio::test]
+        async fn digest_of_received_body() {
+            let digest = Digest::sha256(body().as_bytes());
+            let image: Image = format!("registry.access.redhat.com/ubi8@{digest}")
+                .parse()
+                .unwrap();
+
+            let got = client(&digest.to_string())
+                .get_manifest(&image)
+                .await
+                .unwrap();
+
+            assert!(got.digest_verified);
+        }
+
+        /// The registry changed the manifest it stores, which is reported
+        /// like any other mismatch.
+        #[tokio::test]
+        async fn digest_of_stored_manifest() {
+            let digest = Digest::sha256(MANIFEST.as_bytes());
+            let image: Image = format!("registry.access.redhat.com/ubi8@{digest}")
+                .parse()
+                .unwrap();
+
+            let got = client(&digest.to_string())
+                .get_manifest(&image)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(got, ClientError::DigestMismatch { .. }));
+        }
+    }
+
+    mod download_layers {
+        use std::time::{
+            Duration,
+            Instant,
+        };
+
+        use pretty_assertions::assert_eq;
+        use wiremock::{
+            matchers::{
+                method,
+                path,
+            },
+            Mock,
+            MockServer,
+            ResponseTemplate,
+        };
+
+        use crate::{
+            docker::{
+                cancellation::{
+                    Cancellation,
+                    Reason,
+                },
+                progress::NoProgress,
+            },
+            manifest,
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const DELAY: Duration = Duration::from_millis(300);
+
+        fn manifest(blobs: &[&[u8]]) -> manifest::Image {
+            let layers = blobs
+                .iter()
+                .map(|blob| {
+                    serde_json::json!({
+                        "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                        "size": blob.len(),
+                        "digest": Digest::sha256(blob),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::from_value(serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "size": 0,
+                    "digest": Digest::sha256(b""),
+                },
+                "layers": layers,
+            }))
+            .unwrap()
+        }
+
+        async fn serve_blob(server: &MockServer, digest: &Digest, body: &[u8]) {
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/ubi8/blobs/{digest}")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_bytes(body)
+                        .set_delay(DELAY),
+                )
+                .expect(1)
+                .mount(server)
+                .await;
+        }
+
+        /// Uses the mock server as the only mirror of the Red Hat registry.
+        fn client(server: &MockServer) -> Client {
+            Client::builder()
+                .registry_mirror(
+                    "registry.access.redhat.com",
+                    server.uri().parse::<url::Url>().unwrap(),
+                )
+                .fallback_to_upstream(false)
+                .build()
+        }
+
+        fn dest_dir(name: &str) -> std::path::PathBuf {
+            let dir = std::env::temp_dir().join(format!(
+                "docker-registry-client-{name}-{}",
+                std::process::id()
+            ));
+
+            std::fs::create_dir_all(&dir).unwrap();
+
+            dir
+        }
+
+        #[tokio::test]
+        async fn concurrent_and_deduplicated() {
+            let server = MockServer::start().await;
+
+            let blobs: [&[u8]; 4] = [b"layer one", b"layer two", b"layer three", b"layer four"];
+            for blob in blobs {
+                serve_blob(&server, &Digest::sha256(blob), blob).await;
+            }
+
+            // The last layer repeats the first one and must not be fetched
+            // again.
+            let manifest = manifest(&[blobs[0], blobs[1], blobs[2], blobs[3], blobs[0]]);
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let dir = dest_dir("concurrent");
+
+            let start = Instant::now();
+            let got = client(&server)
+                .download_layers(&image, &manifest, &dir, 4)
+                .await
+                .unwrap();
+            let elapsed = start.elapsed();
+
+            // Sequential downloads would take at least four times the delay.
+            assert!(elapsed < DELAY * 3, "took {elapsed:?}");
+
+            assert_eq!(5, got.len());
+            assert_eq!(
+                vec![false, false, false, false, true],
+                got.iter()
+                    .map(|layer| layer.deduplicated)
+                    .collect::<Vec<_>>()
+            );
+
+            for (layer, blob) in got
+                .iter()
+                .zip([blobs[0], blobs[1], blobs[2], blobs[3], blobs[0]])
+            {
+                assert_eq!(blob, std::fs::read(&layer.path).unwrap());
+            }
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[tokio::test]
+        async fn aggregates_failures() {
+            let server = MockServer::start().await;
+
+            let good: &[u8] = b"good layer";
+            let corrupted: &[u8] = b"corrupted layer";
+            let missing: &[u8] = b"missing layer";
+
+            serve_blob(&server, &Digest::sha256(good), good).await;
+            serve_blob(&server, &Digest::sha256(corrupted), b"something else").await;
+
+            let manifest = manifest(&[good, corrupted, missing]);
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let dir = dest_dir("failures");
+
+            let got = client(&server)
+                .download_layers(&image, &manifest, &dir, 2)
+                .await
+                .unwrap_err();
+
+            let ClientError::DownloadLayers(failed) = got else {
+                panic!("unexpected error: {got}");
+            };
+
+            assert_eq!(
+                vec![Digest::sha256(corrupted), Digest::sha256(missing)],
+                failed
+                    .into_iter()
+                    .map(|layer| layer.digest)
+                    .collect::<Vec<_>>()
+            );
+
+            // Only the verified layer is kept.
+            assert_eq!(1, std::fs::read_dir(&dir).unwrap().count());
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[tokio::test]
+        async fn deadline_keeps_finished_layers() {
+            let server = MockServer::start().await;
+
+            let fast: &[u8] = b"fast layer";
+            let slow: &[u8] = b"slow layer";
+
+            serve_blob(&server, &Digest::sha256(fast), fast).await;
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/ubi8/blobs/{}", Digest::sha256(slow))))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_bytes(slow)
+                        .set_delay(DELAY * 20),
+                )
+                .mount(&server)
+                .await;
+
+            let manifest = manifest(&[fast, slow]);
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let dir = dest_dir("deadline");
+
+            let got = client(&server)
+                .download_layers_with_cancellation(
+                    &image,
+                    &manifest,
+                    &dir,
+                    2,
+                    std::sync::Arc::new(NoProgress),
+                    &Cancellation::new().timeout(DELAY * 3),
+                )
+                .await
+                .unwrap_err();
+
+            let ClientError::Cancelled(cancelled) = got else {
+                panic!("unexpected error: {got}");
+            };
+
+            assert_eq!(Reason::DeadlineExceeded, cancelled.reason);
+            assert_eq!(vec![Digest::sha256(fast)], cancelled.completed);
+
+            // The partial file of the slow layer is removed.
+            assert_eq!(1, std::fs::read_dir(&dir).unwrap().count());
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    mod progress {
+        use std::sync::{
+            atomic::{
+                AtomicU64,
+                Ordering,
+            },
+            Arc,
+            Mutex,
+        };
+
+        use futures::TryStreamExt;
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                progress::Progress,
+                transport::{
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            manifest,
+            Client,
+            Digest,
+            Image,
+        };
+
+        #[derive(Debug, Default)]
+        struct Counting {
+            total: Mutex<Option<u64>>,
+            starts: AtomicU64,
+            bytes: AtomicU64,
+            finishes: AtomicU64,
+        }
+
+        impl Progress for Counting {
+            fn on_start(&self, total: Option<u64>) {
+                self.starts.fetch_add(1, Ordering::SeqCst);
+                *self.total.lock().unwrap() = total;
+            }
+
+            fn on_chunk(&self, bytes: u64) {
+                self.bytes.fetch_add(bytes, Ordering::SeqCst);
+            }
+
+            fn on_finish(&self) {
+                self.finishes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn blob_url(digest: &Digest) -> String {
+            format!("https://registry.access.redhat.com/v2/ubi8/blobs/{digest}")
+        }
+
+        #[tokio::test]
+        async fn get_blob() {
+            let blob = vec![7; 64 * 1024];
+            let digest = Digest::sha256(&blob);
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &blob_url(&digest),
+                MockResponse::new(StatusCode::OK)
+                    .header("Content-Length", &blob.len().to_string())
+                    .body(blob.clone()),
+            );
+
+            let client = Client::builder().transport(transport).build();
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let progress = Arc::new(Counting::default());
+
+            let stream = client
+                .get_blob_with_progress(&image, &digest, progress.clone())
+                .await
+                .unwrap();
+            let chunks: Vec<_> = stream.try_collect().await.unwrap();
+
+            assert_eq!(blob.len(), chunks.concat().len());
+            assert_eq!(1, progress.starts.load(Ordering::SeqCst));
+            assert_eq!(Some(blob.len() as u64), *progress.total.lock().unwrap());
+            assert_eq!(blob.len() as u64, progress.bytes.load(Ordering::SeqCst));
+            assert_eq!(1, progress.finishes.load(Ordering::SeqCst));
+        }
+
+        #[tokio::test]
+        async fn download_layers() {
+            let blobs: [&[u8]; 2] = [b"first layer", b"second layer with more bytes"];
+
+            let mut transport = MockTransport::new();
+            for blob in blobs {
+                transport = transport.with_response(
+                    Method::GET,
+                    &blob_url(&Digest::sha256(blob)),
+                    MockResponse::new(StatusCode::OK).body(blob.to_vec()),
+                );
+            }
+
+            // The repeated layer is only transferred once.
+            let layers = [blobs[0], blobs[1], blobs[0]]
+                .iter()
+                .map(|blob| {
+                    serde_json::json!({
+                        "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                        "size": blob.len(),
+                        "digest": Digest::sha256(blob),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let manifest: manifest::Image = serde_json::from_value(serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "size": 0,
+                    "digest": Digest::sha256(b""),
+                },
+                "layers": layers,
+            }))
+            .unwrap();
+
+            let dir = std::env::temp_dir().join(format!(
+                "docker-registry-client-progress-{}",
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let client = Client::builder().transport(transport).build();
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let progress = Arc::new(Counting::default());
+
+            client
+                .download_layers_with_progress(&image, &manifest, &dir, 2, progress.clone())
+                .await
+                .unwrap();
+
+            std::fs::remove_dir_all(dir).unwrap();
+
+            let expected = (blobs[0].len() + blobs[1].len()) as u64;
+
+            assert_eq!(1, progress.starts.load(Ordering::SeqCst));
+            assert_eq!(Some(expected), *progress.total.lock().unwrap());
+            assert_eq!(expected, progress.bytes.load(Ordering::SeqCst));
+            assert_eq!(1, progress.finishes.load(Ordering::SeqCst));
+        }
+    }
+
+    mod unknown_length {
+        use std::time::Duration;
+
+        use bytes::Bytes;
+        use futures::{
+            StreamExt,
+            TryStreamExt,
+        };
+        use http::{
+            header::HeaderMap,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                blob,
+                download::LayerError,
+                transport::{
+                    self,
+                    Request,
+                    Response,
+                    Transport,
+                },
+            },
+            manifest,
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const BLOB: &[u8] = b"a blob served by a legacy registry";
+
+        /// Serves every blob like a legacy registry: in chunks without
+        /// `Content-Length`, waiting `pause` before each chunk. The connection
+        /// ends after `chunks`, with a reset if `reset` is set.
+        #[derive(Debug, Clone)]
+        struct Legacy {
+            chunks: Vec<&'static [u8]>,
+            pause: Duration,
+            reset: bool,
+        }
+
+        impl Legacy {
+            fn new(chunks: Vec<&'static [u8]>) -> Self {
+                Self {
+                    chunks,
+                    pause: Duration::ZERO,
+                    reset: false,
+                }
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl Transport for Legacy {
+            async fn execute(&self, request: Request) -> Result<Response, transport::Error> {
+                let pause = self.pause;
+                let reset = self.reset.then(|| {
+                    Err(transport::Error::Decode(
+                        std::io::ErrorKind::ConnectionReset.into(),
+                    ))
+                });
+
+                let body = futures::stream::iter(
+                    self.chunks
+                        .clone()
+                        .into_iter()
+                        .map(|chunk| Ok(Bytes::from_static(chunk)))
+                        .chain(reset),
+                )
+                .then(move |chunk| async move {
+                    tokio::time::sleep(pause).await;
+                    chunk
+                });
+
+                Ok(Response::new(
+                    StatusCode::OK,
+                    HeaderMap::new(),
+                    request.url,
+                    body,
+                ))
+            }
+        }
+
+        fn image() -> Image {
+            "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+        }
+
+        fn manifest() -> manifest::Image {
+            serde_json::from_value(serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "size": 0,
+                    "digest": Digest::sha256(b""),
+                },
+                "layers": [{
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                    "size": BLOB.len(),
+                    "digest": Digest::sha256(BLOB),
+                }],
+            }))
+            .unwrap()
+        }
+
+        fn dest_dir(name: &str) -> std::path::PathBuf {
+            let dir = std::env::temp_dir().join(format!(
+                "docker-registry-client-unknown-length-{name}-{}",
+                std::process::id()
+            ));
+
+            std::fs::create_dir_all(&dir).unwrap();
+
+            dir
+        }
+
+        /// Downloads the layer of [`manifest`] and returns the error of the
+        /// layer if it failed.
+        async fn download(client: &Client, name: &str) -> Option<blob::Error> {
+            let dir = dest_dir(name);
+            let got = client.download_layers(&image(), &manifest(), &dir, 1).await;
+
+            let result = match got {
+                Ok(layers) => {
+                    assert_eq!(std::fs::read(&layers[0].path).unwrap(), BLOB);
+                    None
+                }
+
+                Err(ClientError::DownloadLayers(mut failed)) => match failed.remove(0).error {
+                    LayerError::ReadBlob(e) => Some(e),
+                    e => panic!("unexpected error: {e}"),
+                },
+
+                Err(e) => panic!("unexpected error: {e}"),
+            };
+
+            std::fs::remove_dir_all(dir).unwrap();
+
+            result
+        }
+
+        #[tokio::test]
+        async fn verified_download() {
+            let client = Client::builder()
+                .transport(Legacy::new(vec![&BLOB[..8], &BLOB[8..20], &BLOB[20..]]))
+                .build();
+
+            assert!(download(&client, "verified").await.is_none());
+        }
+
+        #[tokio::test]
+        async fn connection_reset() {
+            let client = Client::builder()
+                .transport(Legacy {
+                    reset: true,
+                    ..Legacy::new(vec![&BLOB[..8]])
+                })
+                .build();
+
+            let got = download(&client, "reset").await.unwrap();
+
+            assert!(
+                matches!(
+                    got,
+                    blob::Error::ConnectionClosed {
+                        received: 8,
+                        source: Some(_),
+                        ..
+                    }
+                ),
+                "{got:?}"
+            );
+        }
+
+        #[tokio::test]
+        async fn closed_before_size() {
+            let client = Client::builder()
+                .transport(Legacy::new(vec![&BLOB[..8]]))
+                .build();
+
+            let got = download(&client, "closed").await.unwrap();
+
+            assert!(
+                matches!(
+                    got,
+                    blob::Error::ConnectionClosed {
+                        received: 8,
+                        source: None,
+                        ..
+                    }
+                ),
+                "{got:?}"
+            );
+
+            // Without a descriptor the size is unknown, which leaves the
+            // digest.
+            let got = client
+                .get_blob(&image(), &Digest::sha256(BLOB))
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap_err();
+
+            assert!(matches!(got, blob::Error::DigestMismatch { .. }), "{got:?}");
+        }
+
+        #[tokio::test]
+        async fn wrong_content() {
+            let client = Client::builder()
+                .transport(Legacy::new(vec![b"a blob served by a hostile registry"]))
+                .build();
+
+            let got = download(&client, "wrong").await.unwrap();
+
+            assert!(matches!(got, blob::Error::DigestMismatch { .. }), "{got:?}");
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn idle_timeout() {
+            let transport = Legacy {
+                pause: Duration::from_mins(1),
+                ..Legacy::new(vec![&BLOB[..8], &BLOB[8..]])
+            };
+
+            let client = Client::builder()
+                .transport(transport.clone())
+                .blob_idle_timeout(Duration::from_secs(30))
+                .build();
+
+            assert!(download(&client, "exempt").await.is_none());
+
+            let client = Client::builder()
+                .transport(transport)
+                .blob_idle_timeout(Duration::from_secs(30))
+                .blob_idle_timeout_without_length(true)
+                .build();
+
+            let got = download(&client, "idle").await.unwrap();
+
+            assert!(
+                matches!(got, blob::Error::IdleTimeout(timeout) if timeout == Duration::from_secs(30)),
+                "{got:?}"
+            );
+        }
+    }
+
+    mod body_limits {
+        use std::sync::{
+            atomic::{
+                AtomicU64,
+                Ordering,
+            },
+            Arc,
+        };
+
+        use bytes::Bytes;
+        use futures::{
+            StreamExt,
+            TryStreamExt,
+        };
+        use http::{
+            header::HeaderMap,
+            Method,
+            StatusCode,
+        };
+
+        use crate::{
+            docker::{
+                blob,
+                transport::{
+                    self,
+                    MockResponse,
+                    MockTransport,
+                    Request,
+                    Response,
+                    Transport,
+                },
+            },
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const CHUNK_SIZE: u64 = 64 * 1024;
+        const LIMIT: u64 = 1024 * 1024;
+
+        /// Answers every request with a body that never ends and counts how
+        /// many bytes were pulled from it.
+        #[derive(Debug, Default, Clone)]
+        struct Endless {
+            read: Arc<AtomicU64>,
+        }
+
+        #[async_trait::async_trait]
+        impl Transport for Endless {
+            async fn execute(&self, request: Request) -> Result<Response, transport::Error> {
+                let read = self.read.clone();
+                let chunk = Bytes::from(vec![b' '; usize::try_from(CHUNK_SIZE).unwrap()]);
+
+                let body = futures::stream::repeat(chunk).map(move |chunk| {
+                    read.fetch_add(chunk.len() as u64, Ordering::SeqCst);
+                    Ok(chunk)
+                });
+
+                Ok(Response::new(
+                    StatusCode::OK,
+                    HeaderMap::new(),
+                    request.url,
+                    body,
+                ))
+            }
+        }
+
+        fn image() -> Image {
+            "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+        }
+
+        #[tokio::test]
+        async fn manifest_stops_early() {
+            let transport = Endless::default();
+            let client = Client::builder()
+                .transport(transport.clone())
+                .max_manifest_size(LIMIT)
+                .build();
+
+            let got = client.get_manifest(&image()).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::BodyTooLarge { limit: LIMIT, .. }
+            ));
+            assert!(transport.read.load(Ordering::SeqCst) <= LIMIT + CHUNK_SIZE);
+        }
+
+        #[tokio::test]
+        async fn manifest_content_length() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK).header("Content-Length", "1073741824"),
+            );
+
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.get_manifest(&image()).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::BodyTooLarge {
+                    limit: crate::docker::DEFAULT_MAX_MANIFEST_SIZE,
+                    ..
+                }
+            ));
+        }
+
+        #[tokio::test]
+        async fn blob_stops_early() {
+            let transport = Endless::default();
+            let client = Client::builder()
+                .transport(transport.clone())
+                .max_blob_size(Some(LIMIT))
+                .build();
+
+            let stream = client
+                .get_blob(&image(), &Digest::sha256(b""))
+                .await
+                .unwrap();
+
+            let got = stream.try_for_each(|_| async { Ok(()) }).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                blob::Error::BodyTooLarge { limit: LIMIT, .. }
+            ));
+            assert!(transport.read.load(Ordering::SeqCst) <= LIMIT + CHUNK_SIZE);
+        }
+
+        #[tokio::test]
+        async fn blob_json_stops_at_descriptor_size() {
+            let transport = Endless::default();
+            let client = Client::builder().transport(transport.clone()).build();
+            let descriptor =
+                crate::manifest::Descriptor::new("application/json", LIMIT, Digest::sha256(b""));
+
+            let got = client
+                .get_blob_json::<serde_json::Value>(&image(), &descriptor)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::ReadBlob(blob::Error::BodyTooLarge { limit: LIMIT, .. })
+            ));
+            assert!(transport.read.load(Ordering::SeqCst) <= LIMIT + CHUNK_SIZE);
+        }
+    }
+
+    mod get_blob_json {
+        use base64::Engine as _;
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+        use serde::Deserialize;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            manifest::Descriptor,
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const SBOM: &[u8] = br#"{"spdxVersion":"SPDX-2.3","name":"app"}"#;
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        struct Sbom {
+            spdx_version: String,
+            name: String,
+        }
+
+        fn image() -> Image {
+            "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+        }
+
+        fn descriptor(content: &[u8]) -> Descriptor {
+            Descriptor::new(
+                "application/spdx+json",
+                content.len() as u64,
+                Digest::sha256(content),
+            )
+        }
+
+        fn serving(digest: &Digest, body: &'static [u8]) -> Client {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &format!("https://registry.access.redhat.com/v2/ubi8/blobs/{digest}"),
+                MockResponse::new(StatusCode::OK).body(body),
+            );
+
+            Client::builder().transport(transport).build()
+        }
+
+        #[tokio::test]
+        async fn deserializes() {
+            let descriptor = descriptor(SBOM);
+            let client = serving(&descriptor.digest, SBOM);
+
+            let got: Sbom = client.get_blob_json(&image(), &descriptor).await.unwrap();
+
+            assert_eq!(
+                got,
+                Sbom {
+                    spdx_version: "SPDX-2.3".to_string(),
+                    name: "app".to_string(),
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn digest_mismatch() {
+            let descriptor = descriptor(SBOM);
+            let tampered = br#"{"spdxVersion":"SPDX-2.3","name":"bad"}"#;
+            let client = serving(&descriptor.digest, tampered);
+
+            let got = client
+                .get_blob_json::<Sbom>(&image(), &descriptor)
+                .await
+                .unwrap_err();
+
+            assert!(
+                matches!(got, ClientError::ReadBlob(crate::docker::blob::Error::DigestMismatch { expected, .. }) if expected == descriptor.digest)
+            );
+        }
+
+        #[tokio::test]
+        async fn size_mismatch() {
+            let descriptor = Descriptor {
+                size: SBOM.len() as u64 + 1,
+                ..descriptor(SBOM)
+            };
+            let client = serving(&descriptor.digest, SBOM);
+
+            let got = client
+                .get_blob_json::<Sbom>(&image(), &descriptor)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::DescriptorMismatch { expected_size, actual_size, .. }
+                    if expected_size == SBOM.len() as u64 + 1 && actual_size == SBOM.len() as u64
+            ));
+        }
+
+        #[tokio::test]
+        async fn inline_data() {
+            let descriptor = Descriptor {
+                data: Some(base64::engine::general_purpose::STANDARD.encode(SBOM)),
+                ..descriptor(SBOM)
+            };
+            let transport = MockTransport::new();
+            let client = Client::builder().transport(transport.clone()).build();
+
+            let got: Sbom = client.get_blob_json(&image(), &descriptor).await.unwrap();
+
+            assert_eq!(got.name, "app");
+            assert!(transport.requests().is_empty());
+
+            let tampered = Descriptor {
+                data: Some(base64::engine::general_purpose::STANDARD.encode(b"{}")),
+                ..descriptor
+            };
+
+            let got = client
+                .get_blob_json::<Sbom>(&image(), &tampered)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(got, ClientError::DescriptorMismatch { .. }));
+        }
+
+        #[tokio::test]
+        async fn invalid_json_keeps_body() {
+            let body = b"not json";
+            let descriptor = descriptor(body);
+            let client = serving(&descriptor.digest, body);
+
+            let got = client
+                .get_blob_json::<Sbom>(&image(), &descriptor)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(got, ClientError::DeserializeBlob(_, body) if body == "not json"));
+        }
+    }
+
+    mod digest_algorithms {
+        use futures::TryStreamExt;
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                blob,
+                transport::{
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            manifest::Descriptor,
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const BASE: &str = "https://registry.access.redhat.com/v2/ubi8";
+        const CONTENT: &[u8] = br#"{"name":"sha512 content"}"#;
+        const BLAKE3: &str =
+            "blake3:af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+
+        fn image() -> Image {
+            "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+        }
+
+        fn serving(digest: &Digest, body: &'static [u8]) -> MockTransport {
+            MockTransport::new().with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{digest}"),
+                MockResponse::new(StatusCode::OK).body(body),
+            )
+        }
+
+        #[tokio::test]
+        async fn sha512_blob() {
+            let digest = Digest::sha512(CONTENT);
+            let client = Client::builder()
+                .transport(serving(&digest, CONTENT))
+                .build();
+
+            let chunks: Vec<bytes::Bytes> = client
+                .get_blob(&image(), &digest)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+
+            assert_eq!(chunks.concat(), CONTENT);
+        }
+
+        #[tokio::test]
+        async fn sha512_blob_mismatch() {
+            let digest = Digest::sha512(CONTENT);
+            let client = Client::builder()
+                .transport(serving(&digest, b"tampered"))
+                .build();
+
+            let got = client
+                .get_blob(&image(), &digest)
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap_err();
+
+            let blob::Error::DigestMismatch { actual, .. } = got else {
+                panic!("expected a digest mismatch, got {got}");
+            };
+            assert_eq!(actual, Digest::sha512(b"tampered"));
+        }
+
+        #[tokio::test]
+        async fn sha512_descriptor() {
+            let descriptor = Descriptor::new(
+                "application/json",
+                CONTENT.len() as u64,
+                Digest::sha512(CONTENT),
+            );
+            let client = Client::builder()
+                .transport(serving(&descriptor.digest, CONTENT))
+                .build();
+
+            let got: serde_json::Value = client.get_blob_json(&image(), &descriptor).await.unwrap();
+
+            assert_eq!(got["name"], "sha512 content");
+        }
+
+        #[tokio::test]
+        async fn sha512_manifest() {
+            const BODY: &str = include_str!("../resources/manifest/image/example.json");
+            let digest = Digest::sha512(BODY.as_bytes());
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{digest}"),
+                MockResponse::new(StatusCode::OK).body(BODY),
+            );
+            let client = Client::builder().transport(transport).build();
+            let image: Image = format!("registry.access.redhat.com/ubi8@{digest}")
+                .parse()
+                .unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap();
+
+            assert!(got.digest_verified);
+        }
+
+        #[tokio::test]
+        async fn blake3_rejected() {
+            let transport = MockTransport::new();
+            let client = Client::builder().transport(transport.clone()).build();
+            let digest: Digest = BLAKE3.parse().unwrap();
+
+            let got = client.get_blob(&image(), &digest).await.err().unwrap();
+            assert!(
+                matches!(&got, ClientError::UnsupportedDigestAlgorithm(e) if e.0 == digest),
+                "{got}"
+            );
+
+            let got = client
+                .get_blob_json::<serde_json::Value>(
+                    &image(),
+                    &Descriptor {
+                        data: Some("e30=".to_string()),
+                        ..Descriptor::new("application/json", 2, digest)
+                    },
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(got, ClientError::UnsupportedDigestAlgorithm(_)));
+
+            assert!(transport.requests().is_empty());
+        }
+    }
+
+    mod live {
+        use crate::{
+            manifest::Platform,
+            Client,
+            Image,
+            Manifest,
+        };
+
+        async fn smoke(input: &str) {
+            let client = Client::new();
+            let image = input.parse().unwrap();
+
+            client.get_manifest(&image).await.unwrap();
+        }
+
+        #[tokio::test]
+        #[ignore = "requires network access to Docker Hub"]
+        async fn dockerhub() {
+            smoke("library/alpine:3.20").await;
+        }
+
+        #[tokio::test]
+        #[ignore = "requires network access to the Red Hat registry"]
+        async fn redhat() {
+            smoke("registry.access.redhat.com/ubi8:8.9").await;
+        }
+
+        #[tokio::test]
+        #[ignore = "requires network access to the GitHub registry"]
+        async fn github() {
+            smoke("ghcr.io/sigstore/cosign/cosign:v2.4.0").await;
+        }
+
+        #[tokio::test]
+        #[ignore = "requires network access to the Microsoft registry"]
+        async fn microsoft() {
+            smoke("mcr.microsoft.com/playwright:v1.48.2-noble").await;
+        }
+
+        /// registry.k8s.io redirects to the registry of a cloud provider.
+        #[tokio::test]
+        #[ignore = "requires network access to registry.k8s.io"]
+        async fn k8s_redirects() {
+            use futures::TryStreamExt;
+
+            let client = Client::new();
+            let image: Image = "registry.k8s.io/pause:3.9".parse().unwrap();
+
+            let manifest = client
+                .get_manifest_for_platform(&image, &Platform::current())
+                .await
+                .unwrap();
+            assert_ne!(manifest.served_by.as_deref(), Some("registry.k8s.io"));
+
+            let Manifest::Image(manifest) = manifest.manifest else {
+                panic!("expected an image manifest");
+            };
+            let digest = manifest.layers[0].digest.clone();
+
+            let blob: Vec<_> = client
+                .get_blob(&image, &digest)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert!(!blob.is_empty());
+        }
+    }
+
+    mod check_for_update {
+        use either::Either;
+        use pretty_assertions::assert_eq;
+        use url::Url;
+        use wiremock::{
+            matchers::{
+                method,
+                path,
+            },
+            Mock,
+            MockServer,
+            ResponseTemplate,
+        };
+
+        use crate::{
+            Client,
+            ClientError,
+            Digest,
+            Image,
+            ImageName,
+            Registry,
+            Tag,
+            UpdateStatus,
+        };
+
+        const KNOWN_DIGEST: &str =
+            "sha256:2247f14d217577b451727b3015f95e97d47941e96b99806f8589a34c43112ec3";
+
+        const NEW_DIGEST: &str =
+            "sha256:b018257986b51ee1179f5a416c2c90e9698941d3f3104ccecfdc250e9bf07555";
+
+        fn image() -> Image {
+            Image {
+                registry: Registry::RedHat,
+                repository: None,
+                image_name: ImageName {
+                    name: "ubi8".into(),
+                    identifier: Either::Left(Tag::Specific("8.9".into())),
+                },
+            }
+        }
+
+        async fn server(response: ResponseTemplate) -> (MockServer, Url) {
+            let server = MockServer::start().await;
+
+            Mock::given(method("HEAD"))
+                .and(path("/v2/ubi8/manifests/8.9"))
+                .respond_with(response)
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let url = format!("{}/v2/ubi8/manifests/8.9", server.uri())
+                .parse()
+                .unwrap();
+
+            (server, url)
+        }
+
+        #[tokio::test]
+        async fn up_to_date() {
+            let (_server, url) = server(
+                ResponseTemplate::new(200)
+                    .insert_header("Docker-Content-Digest", KNOWN_DIGEST.to_uppercase()),
+            )
+            .await;
+
+            let known_digest = KNOWN_DIGEST.parse().unwrap();
+
+            let got = Client::new()
+                .check_for_update_url(&url, &image(), &known_digest)
+                .await
+                .unwrap();
+
+            assert_eq!(UpdateStatus::UpToDate, got);
+        }
+
+        #[tokio::test]
+        async fn update_available() {
+            let (_server, url) = server(
+                ResponseTemplate::new(200).insert_header("Docker-Content-Digest", NEW_DIGEST),
+            )
+            .await;
+
+            let known_digest = KNOWN_DIGEST.parse().unwrap();
+
+            let got = Client::new()
+                .check_for_update_url(&url, &image(), &known_digest)
+                .await
+                .unwrap();
+
+            let expected = UpdateStatus::UpdateAvailable {
+                new_digest: NEW_DIGEST.parse().unwrap(),
+            };
+
+            assert_eq!(expected, got);
+        }
+
+        #[tokio::test]
+        async fn tag_gone() {
+            let (_server, url) = server(ResponseTemplate::new(404)).await;
+
+            let known_digest = KNOWN_DIGEST.parse().unwrap();
+
+            let got = Client::new()
+                .check_for_update_url(&url, &image(), &known_digest)
+                .await
+                .unwrap();
+
+            assert_eq!(UpdateStatus::TagGone, got);
+        }
+
+        #[tokio::test]
+        async fn by_digest() {
+            let known_digest: Digest = KNOWN_DIGEST.parse().unwrap();
+
+            let mut image = image();
+            image.image_name.identifier = Either::Right(known_digest.clone());
+
+            let got = Client::new()
+                .check_for_update(&image, &known_digest)
+                .await
+                .unwrap_err();
+
+            assert!(matches!(got, ClientError::UpdateCheckRequiresTag(_)));
+        }
+    }
+
+    mod manifest_cache_memory {
+        use std::time::Duration;
+
+        use pretty_assertions::assert_eq;
+        use url::Url;
+        use wiremock::{
+            matchers::{
+                method,
+                path,
+            },
+            Mock,
+            MockServer,
+            ResponseTemplate,
+        };
+
+        use crate::{
+            docker::manifest_cache::Stats,
+            Client,
+            Image,
+        };
+
+        const BODY: &str = include_str!("../resources/manifest/image/example.json");
+
+        #[tokio::test]
+        async fn concurrent_fetches_coalesce() {
+            let server = MockServer::start().await;
+
+            Mock::given(method("GET"))
+                .and(path("/v2/ubi8/manifests/8.9"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_string(BODY)
+                        .set_delay(Duration::from_millis(200)),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let client = Client::builder().manifest_cache_memory().build();
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let url: Url = format!("{}/v2/ubi8/manifests/8.9", server.uri())
+                .parse()
+                .unwrap();
+
+            let (first, second) = tokio::join!(
+                client.get_manifest_url(&url, &image),
+                client.get_manifest_url(&url, &image)
+            );
+
+            first.unwrap();
+            second.unwrap();
+
+            // The second fetch shares the request of the first one and does
+            // not look at the cache.
+            let expected = Stats { hits: 0, misses: 1 };
+            assert_eq!(Some(expected), client.manifest_cache_stats());
+            assert_eq!(0, client.inner.in_flight.len());
+        }
+    }
+
+    mod redirects {
+        use futures::TryStreamExt;
+        use http::{
+            header::AUTHORIZATION,
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                stats,
+                transport::{
+                    self,
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const CONTENT: &str = "layer";
+        const TOKEN: &str =
+            "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io";
+        const STORAGE: &str = "https://pkg-containers.githubusercontent.com/ghcr1/blobs/layer";
+
+        #[tokio::test]
+        async fn cross_host_blob_drops_credentials() {
+            let digest = Digest::sha256(CONTENT.as_bytes());
+            let blob = format!("https://ghcr.io/v2/sigstore/cosign/cosign/blobs/{digest}");
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    TOKEN,
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    &blob,
+                    MockResponse::new(StatusCode::TEMPORARY_REDIRECT).header("Location", STORAGE),
+                )
+                .with_response(
+                    Method::GET,
+                    STORAGE,
+                    MockResponse::new(StatusCode::OK).body(CONTENT),
+                );
+
+            let client = Client::builder().transport(transport.clone()).build();
+            let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+            let (chunks, stats) = stats::measure(async {
+                let stream = client.get_blob(&image, &digest).await.unwrap();
+                stream.try_collect::<Vec<_>>().await.unwrap()
+            })
+            .await;
+
+            assert_eq!(chunks.concat(), CONTENT.as_bytes());
+            assert_eq!((stats.requests, stats.redirects), (3, 1));
+
+            let requests = transport.requests();
+            assert_eq!(requests[1].url.as_str(), blob);
+            assert_eq!(requests[1].headers[AUTHORIZATION], "Bearer registry-token");
+            assert_eq!(requests[2].url.as_str(), STORAGE);
+            assert!(!requests[2].headers.contains_key(AUTHORIZATION));
+        }
+
+        #[tokio::test]
+        async fn manifest_records_final_host() {
+            const TARGET: &str =
+                "https://us-west1-docker.pkg.dev/v2/k8s-artifacts-prod/images/pause/manifests/3.9";
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    "https://registry.k8s.io/v2/pause/manifests/3.9",
+                    MockResponse::new(StatusCode::FOUND).header("Location", TARGET),
+                )
+                .with_response(
+                    Method::GET,
+                    TARGET,
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/manifest/image/example.json")),
+                );
+
+            let client = Client::builder().transport(transport).build();
+            let image: Image = "registry.k8s.io/pause:3.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap();
+
+            assert_eq!(got.served_by.as_deref(), Some("us-west1-docker.pkg.dev"));
         }
 
         #[tokio::test]
-        async fn cosign() {
-            const INPUT: &str = "ghcr.io/sigstore/cosign/cosign:v2.4.0";
+        async fn limit() {
+            const URL: &str = "https://registry.k8s.io/v2/pause/manifests/3.9";
 
-            let client = Client::new();
-            let image = INPUT.parse().unwrap();
-            let response = client.get_manifest(&image).await.unwrap();
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                URL,
+                MockResponse::new(StatusCode::FOUND).header("Location", URL),
+            );
+
+            let client = Client::builder()
+                .transport(transport.clone())
+                .max_redirects(2)
+                .build();
+            let image: Image = "registry.k8s.io/pause:3.9".parse().unwrap();
+
+            let got = client.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::GetManifest(transport::Error::TooManyRedirects { limit: 2, .. })
+            ));
+            assert_eq!(transport.requests().len(), 3);
+        }
+    }
+
+    mod in_flight {
+        use std::time::Duration;
+
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Image,
+        };
+
+        const DELAY: Duration = Duration::from_millis(100);
+
+        fn token_url(repository: &str) -> String {
+            format!(
+                "https://auth.docker.io/token?service=registry.docker.io&scope=repository:{repository}:pull&service=registry.docker.io"
+            )
+        }
+
+        fn manifest_url(repository: &str) -> String {
+            format!("https://registry-1.docker.io/v2/{repository}/manifests/latest")
+        }
+
+        /// A Docker Hub that answers slowly so concurrent requests overlap.
+        fn transport(manifest_status: StatusCode) -> MockTransport {
+            ["library/alpine", "library/busybox"].into_iter().fold(
+                MockTransport::new(),
+                |transport, repository| {
+                    transport
+                        .with_response(
+                            Method::GET,
+                            &token_url(repository),
+                            MockResponse::new(StatusCode::OK)
+                                .body(r#"{"token":"hub-token"}"#)
+                                .delay(DELAY),
+                        )
+                        .with_response(
+                            Method::GET,
+                            &manifest_url(repository),
+                            MockResponse::new(manifest_status)
+                                .body(include_str!("../resources/registry/dockerhub/alpine.json"))
+                                .delay(DELAY),
+                        )
+                },
+            )
+        }
+
+        fn client(transport: &MockTransport) -> Client {
+            Client::builder()
+                .transport(transport.clone())
+                .disable_token_caching()
+                .disable_manifest_caching()
+                .build()
+        }
+
+        fn requested_urls(transport: &MockTransport) -> Vec<String> {
+            transport
+                .requests()
+                .iter()
+                .map(|request| request.url.to_string())
+                .collect()
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn concurrent_fetches_share_one_request() {
+            let transport = transport(StatusCode::OK);
+            let client = client(&transport);
+            let image: Image = "library/alpine:latest".parse().unwrap();
+
+            let got = futures::future::join_all((0..50).map(|_| client.get_manifest(&image))).await;
+
+            assert!(got.iter().all(Result::is_ok));
+            assert_eq!(
+                vec![
+                    url::Url::parse(&token_url("library/alpine"))
+                        .unwrap()
+                        .to_string(),
+                    manifest_url("library/alpine"),
+                ],
+                requested_urls(&transport)
+            );
+            assert_eq!(0, client.inner.in_flight.len());
+            assert_eq!(0, client.inner.tokens_in_flight.len());
+
+            // Once done the next fetch sends its own requests.
+            client.get_manifest(&image).await.unwrap();
+            assert_eq!(4, transport.requests().len());
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn errors_are_shared() {
+            let transport = transport(StatusCode::INTERNAL_SERVER_ERROR);
+            let client = client(&transport);
+            let image: Image = "library/alpine:latest".parse().unwrap();
+
+            let got = futures::future::join_all((0..3).map(|_| client.get_manifest(&image))).await;
+
+            for result in got {
+                let error = result.unwrap_err();
+                let ClientError::Shared(error) = error else {
+                    panic!("expected a shared error, got {error}");
+                };
+
+                assert!(matches!(*error, ClientError::FailedManifestRequest(_)));
+            }
+
+            assert_eq!(2, transport.requests().len());
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn different_repositories_are_not_shared() {
+            let transport = transport(StatusCode::OK);
+            let client = client(&transport);
+            let alpine: Image = "library/alpine:latest".parse().unwrap();
+            let busybox: Image = "library/busybox:latest".parse().unwrap();
+
+            let (first, second) =
+                tokio::join!(client.get_manifest(&alpine), client.get_manifest(&busybox));
+
+            first.unwrap();
+            second.unwrap();
+
+            // Each repository needs a token with its own scope.
+            assert_eq!(4, transport.requests().len());
+        }
+    }
+
+    mod official_images {
+        use either::Either;
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            Image,
+            ImageName,
+            Registry,
+            Tag,
+        };
+
+        const TOKEN: &str = "https://auth.docker.io/token?service=registry.docker.io&scope=repository:library/alpine:pull&service=registry.docker.io";
+        const MANIFEST: &str = "https://registry-1.docker.io/v2/library/alpine/manifests/latest";
+        const TAGS: &str = "https://registry-1.docker.io/v2/library/alpine/tags/list";
 
-            insta::assert_json_snapshot!(response);
+        /// Built by hand, so the parser did not add the `library` repository.
+        fn image() -> Image {
+            Image::new(
+                Registry::DockerHub,
+                ImageName::new("alpine", Either::Left(Tag::Latest)),
+            )
+        }
+
+        fn transport() -> MockTransport {
+            MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    TOKEN,
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"hub-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    MANIFEST,
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/dockerhub/alpine.json")),
+                )
+                .with_response(
+                    Method::GET,
+                    TAGS,
+                    MockResponse::new(StatusCode::OK)
+                        .body(r#"{"name":"library/alpine","tags":["3.20","latest"]}"#),
+                )
+        }
+
+        fn requested_paths(transport: &MockTransport) -> Vec<String> {
+            transport
+                .requests()
+                .iter()
+                .map(|request| request.url.path().to_string())
+                .collect()
         }
 
         #[tokio::test]
-        async fn playwright() {
-            const INPUT: &str = "mcr.microsoft.com/playwright:v1.48.2-noble";
+        async fn manifest() {
+            let transport = transport();
+            let client = Client::builder().transport(transport.clone()).build();
 
-            let client = Client::new();
-            let image = INPUT.parse().unwrap();
-            let response = client.get_manifest(&image).await.unwrap();
+            client.get_manifest(&image()).await.unwrap();
+
+            assert_eq!(
+                requested_paths(&transport),
+                ["/token", "/v2/library/alpine/manifests/latest"]
+            );
+        }
+
+        #[tokio::test]
+        async fn tags() {
+            let transport = transport();
+            let client = Client::builder().transport(transport.clone()).build();
+
+            let got = client.list_tags(&image()).await.unwrap();
+
+            assert_eq!(got, [Tag::Specific("3.20".into()), Tag::Latest]);
+            assert_eq!(
+                requested_paths(&transport),
+                ["/token", "/v2/library/alpine/tags/list"]
+            );
+        }
+    }
+
+    mod tracing_fields {
+        use std::{
+            fmt::Write,
+            sync::{
+                atomic::{
+                    AtomicU64,
+                    Ordering,
+                },
+                Arc,
+                Mutex,
+            },
+        };
+
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use tracing::{
+            field::{
+                Field,
+                Visit,
+            },
+            span,
+            Event,
+            Metadata,
+            Subscriber,
+        };
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+        };
+
+        const TOKEN: &str = "bearer-secret-8c5d1e";
+
+        /// Records the name and every field of all spans and events as text.
+        #[derive(Debug, Clone, Default)]
+        struct Capture {
+            lines: Arc<Mutex<Vec<String>>>,
+            next_id: Arc<AtomicU64>,
+        }
+
+        struct Fields<'a>(&'a mut String);
+
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                write!(self.0, " {}={value:?}", field.name()).unwrap();
+            }
+        }
+
+        impl Subscriber for Capture {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                let mut line = span.metadata().name().to_string();
+                span.record(&mut Fields(&mut line));
+                self.lines.lock().unwrap().push(line);
+
+                span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+            }
+
+            fn record(&self, _: &span::Id, values: &span::Record<'_>) {
+                let mut line = String::from("record");
+                values.record(&mut Fields(&mut line));
+                self.lines.lock().unwrap().push(line);
+            }
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut line = String::from("event");
+                event.record(&mut Fields(&mut line));
+                self.lines.lock().unwrap().push(line);
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        #[tokio::test]
+        async fn no_credentials() {
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io",
+                    MockResponse::new(StatusCode::OK)
+                        .body(format!(r#"{{"token":"{TOKEN}","expires_in":300}}"#)),
+                )
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+                    MockResponse::new(StatusCode::OK)
+                        .body(include_str!("../resources/registry/github/cosign.json")),
+                );
+
+            let client = Client::builder().transport(transport).build();
+            let image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+            let capture = Capture::default();
+            let _guard = tracing::subscriber::set_default(capture.clone());
+
+            // The second request uses the cached token.
+            client.get_manifest(&image).await.unwrap();
+            client.get_manifest(&image).await.unwrap();
+
+            let lines = capture.lines.lock().unwrap().join("\n");
+
+            assert!(!lines.contains(TOKEN), "{lines}");
+            assert!(
+                lines.contains(
+                    "get_manifest registry=ghcr.io repository=sigstore/cosign/cosign \
+                     reference=v2.4.0"
+                ),
+                "{lines}"
+            );
+            assert!(lines.contains("token registry=ghcr.io"), "{lines}");
+            assert!(
+                lines.contains("http.request.method=GET server.address=ghcr.io"),
+                "{lines}"
+            );
+            assert!(lines.contains("record http.status_code=200"), "{lines}");
+            assert!(lines.contains("duration_ms="), "{lines}");
+        }
+
+        #[tokio::test]
+        async fn debug_http_logging_redacts_secrets() {
+            const COOKIE: &str = "session-secret-41f0a2";
+
+            let transport = MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io",
+                    MockResponse::new(StatusCode::OK)
+                        .header("Set-Cookie", &format!("session={COOKIE}; Secure"))
+                        .body(format!(r#"{{"token":"{TOKEN}","expires_in":300}}"#)),
+                )
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+                    MockResponse::new(StatusCode::OK)
+                        .header("Content-Length", "2048")
+                        .body(include_str!("../resources/registry/github/cosign.json")),
+                );
+
+            let client = Client::builder()
+                .transport(transport)
+                .debug_http_logging(true)
+                .build();
+            let image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+            let capture = Capture::default();
+            let _guard = tracing::subscriber::set_default(capture.clone());
+
+            client.get_manifest(&image).await.unwrap();
+
+            let lines = capture.lines.lock().unwrap().join("\n");
+            let exchanges: Vec<&str> = lines
+                .lines()
+                .filter(|line| line.contains("registry http exchange"))
+                .collect();
+
+            assert_eq!(exchanges.len(), 2, "{lines}");
+            assert!(
+                exchanges[0].contains(
+                    "url=https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io"
+                ),
+                "{lines}"
+            );
+            assert!(exchanges[1].contains("status=200"), "{lines}");
+            assert!(exchanges[1].contains("response_size=2048"), "{lines}");
+            assert!(
+                exchanges[1].contains(r#""authorization": "[REDACTED]""#),
+                "{lines}"
+            );
+            assert!(
+                exchanges[0].contains(r#""set-cookie": "[REDACTED]""#),
+                "{lines}"
+            );
+
+            for secret in [TOKEN, COOKIE, &TOKEN[7..], &COOKIE[8..]] {
+                assert!(!lines.contains(secret), "{secret} in {lines}");
+            }
+        }
+    }
+
+    mod anonymous_fallback {
+        use http::{
+            header::AUTHORIZATION,
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Image,
+            Manifest,
+        };
+
+        const MANIFEST: &str = include_str!("../resources/manifest/image/example.json");
+        const TOKEN: &str =
+            "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io";
+        const URL: &str = "https://ghcr.io/v2/example/app/manifests/1.0";
+
+        fn image() -> Image {
+            "ghcr.io/example/app:1.0".parse().unwrap()
+        }
+
+        fn transport(token: StatusCode, manifest: StatusCode) -> MockTransport {
+            MockTransport::new()
+                .with_response(Method::GET, TOKEN, MockResponse::new(token))
+                .with_response(Method::GET, URL, MockResponse::new(manifest).body(MANIFEST))
+        }
+
+        fn client(transport: &MockTransport, try_anonymous: bool) -> Client {
+            Client::builder()
+                .transport(transport.clone())
+                .try_anonymous_on_token_failure(try_anonymous)
+                .build()
+        }
+
+        #[tokio::test]
+        async fn token_unavailable_manifest_public() {
+            let transport = transport(StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK);
+
+            let got = client(&transport, true)
+                .get_manifest(&image())
+                .await
+                .unwrap();
+
+            assert!(matches!(got.manifest, Manifest::Image(_)));
+
+            let requests = transport.requests();
+            assert_eq!(requests.len(), 2);
+            assert!(!requests[1].headers.contains_key(AUTHORIZATION));
+        }
+
+        #[tokio::test]
+        async fn token_unavailable_manifest_private() {
+            let transport = transport(StatusCode::SERVICE_UNAVAILABLE, StatusCode::UNAUTHORIZED);
+
+            let got = client(&transport, true)
+                .get_manifest(&image())
+                .await
+                .unwrap_err();
+
+            let ClientError::AnonymousFallbackFailed { token, anonymous } = got else {
+                panic!("expected the token error with the anonymous one, got {got:?}");
+            };
+
+            assert!(token.is_token_unavailable());
+            assert!(matches!(
+                *anonymous,
+                ClientError::FailedManifestRequest(response)
+                    if response.status == StatusCode::UNAUTHORIZED
+            ));
+        }
+
+        /// Without the option the manifest is not requested.
+        #[tokio::test]
+        async fn disabled() {
+            let transport = transport(StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK);
+
+            let got = client(&transport, false)
+                .get_manifest(&image())
+                .await
+                .unwrap_err();
+
+            assert!(got.is_token_unavailable(), "{got:?}");
+            assert_eq!(transport.requests().len(), 1);
+        }
+
+        /// A token endpoint that denies the request is not worked around.
+        #[tokio::test]
+        async fn token_denied() {
+            let transport = transport(StatusCode::FORBIDDEN, StatusCode::OK);
+
+            let got = client(&transport, true)
+                .get_manifest(&image())
+                .await
+                .unwrap_err();
+
+            assert!(!got.is_token_unavailable(), "{got:?}");
+            assert_eq!(transport.requests().len(), 1);
+        }
+    }
+
+    mod token_audience {
+        use base64::Engine as _;
+        use http::{
+            Method,
+            StatusCode,
+        };
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Image,
+        };
+
+        const TOKEN: &str =
+            "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io";
+        const URL: &str = "https://ghcr.io/v2/example/app/manifests/1.0";
+
+        fn transport(audience: &str) -> MockTransport {
+            let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .encode(serde_json::json!({ "aud": audience }).to_string());
+            let token = format!("eyJhbGciOiJub25lIn0.{claims}.c2lnbmF0dXJl");
+
+            MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    TOKEN,
+                    MockResponse::new(StatusCode::OK)
+                        .body(serde_json::json!({ "token": token }).to_string()),
+                )
+                .with_response(
+                    Method::GET,
+                    URL,
+                    MockResponse::new(StatusCode::UNAUTHORIZED)
+                        .body(r#"{"errors":[{"code":"UNAUTHORIZED","message":"invalid token"}]}"#),
+                )
+        }
+
+        fn image() -> Image {
+            "ghcr.io/example/app:1.0".parse().unwrap()
+        }
+
+        #[tokio::test]
+        async fn mismatch() {
+            let client = Client::builder()
+                .transport(transport("mirror.example.com"))
+                .build();
+
+            let got = client.get_manifest(&image()).await.unwrap_err();
+
+            let ClientError::TokenAudienceMismatch { mismatch, error } = &got else {
+                panic!("expected an audience mismatch, got {got:?}");
+            };
+
+            assert_eq!(mismatch.expected, "ghcr.io");
+            assert_eq!(mismatch.audience, ["mirror.example.com"]);
+            assert!(matches!(**error, ClientError::FailedManifestRequest(_)));
+            assert!(
+                got.to_string().ends_with(
+                    "token was issued for audience mirror.example.com instead of service ghcr.io"
+                ),
+                "{got}"
+            );
+        }
+
+        #[tokio::test]
+        async fn matching_audience() {
+            let client = Client::builder().transport(transport("ghcr.io")).build();
+
+            let got = client.get_manifest(&image()).await.unwrap_err();
+
+            assert!(
+                matches!(got, ClientError::FailedManifestRequest(_)),
+                "{got:?}"
+            );
+        }
+    }
+
+    mod clones {
+        use std::{
+            sync::{
+                atomic::{
+                    AtomicUsize,
+                    Ordering,
+                },
+                Arc,
+            },
+            time::Duration,
+        };
+
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                interceptor::{
+                    RequestInterceptor,
+                    RequestParts,
+                    ResponseParts,
+                },
+                transport::{
+                    self,
+                    MockResponse,
+                    MockTransport,
+                },
+            },
+            Client,
+            ClientError,
+            Digest,
+            Image,
+        };
+
+        const MANIFEST: &str = include_str!("../resources/manifest/image/example.json");
+        const TOKEN: &str =
+            "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io";
+
+        fn transport() -> MockTransport {
+            MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    TOKEN,
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"shared-token"}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/v2/example/app/manifests/1.0",
+                    MockResponse::new(StatusCode::OK).body(MANIFEST),
+                )
+                .with_response(
+                    Method::GET,
+                    "https://ghcr.io/v2/example/app/manifests/2.0",
+                    MockResponse::new(StatusCode::OK).body(MANIFEST),
+                )
+        }
+
+        #[test]
+        fn clone_is_a_pointer() {
+            assert_eq!(size_of::<Client>(), size_of::<usize>());
+        }
+
+        #[tokio::test]
+        async fn token_cache_is_shared() {
+            let transport = transport();
+            let first = Client::builder().transport(transport.clone()).build();
+            let second = first.clone();
+
+            let image: Image = "ghcr.io/example/app:1.0".parse().unwrap();
+            first.get_manifest(&image).await.unwrap();
+
+            let image: Image = "ghcr.io/example/app:2.0".parse().unwrap();
+            second.get_manifest(&image).await.unwrap();
+
+            let token_requests = transport
+                .requests()
+                .iter()
+                .filter(|request| request.url.as_str() == TOKEN)
+                .count();
+            assert_eq!(token_requests, 1);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn rate_limit_is_shared() {
+            let first = Client::builder()
+                .transport(transport())
+                .rate_limit("ghcr.io", 2, Duration::from_secs(10))
+                .rate_limit_timeout(Duration::from_secs(1))
+                .build();
+            let second = first.clone();
+
+            // The token and the manifest request of the first clone use up
+            // the limit.
+            let image: Image = "ghcr.io/example/app:1.0".parse().unwrap();
+            first.get_manifest(&image).await.unwrap();
+
+            let image: Image = "ghcr.io/example/app:2.0".parse().unwrap();
+            let got = second.get_manifest(&image).await.unwrap_err();
+
+            assert!(matches!(
+                got,
+                ClientError::GetManifest(transport::Error::RateLimitTimeout(host)) if host == "ghcr.io"
+            ));
+        }
+
+        /// Counts the HEAD requests in flight and remembers the most at once.
+        #[derive(Debug, Default)]
+        struct HeadsInFlight {
+            current: AtomicUsize,
+            max: AtomicUsize,
+        }
+
+        #[async_trait::async_trait]
+        impl RequestInterceptor for Arc<HeadsInFlight> {
+            async fn before(&self, request: &mut RequestParts) {
+                if request.method == Method::HEAD {
+                    let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+                    self.max.fetch_max(current, Ordering::SeqCst);
+                }
+            }
+
+            async fn after(&self, response: &ResponseParts) {
+                if response.method == Method::HEAD {
+                    self.current.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn concurrency_limit_is_shared() {
+            let transport = ["pause", "etcd"].into_iter().fold(
+                MockTransport::new(),
+                |transport, repository| {
+                    let tags: Vec<String> = (1..=6).map(|tag| tag.to_string()).collect();
+
+                    let transport = transport.with_response(
+                        Method::GET,
+                        &format!("https://registry.k8s.io/v2/{repository}/tags/list"),
+                        MockResponse::new(StatusCode::OK).body(
+                            serde_json::json!({ "name": repository, "tags": tags }).to_string(),
+                        ),
+                    );
+
+                    tags.iter().fold(transport, |transport, tag| {
+                        transport.with_response(
+                            Method::HEAD,
+                            &format!("https://registry.k8s.io/v2/{repository}/manifests/{tag}"),
+                            MockResponse::new(StatusCode::OK)
+                                .header("Docker-Content-Digest", &Digest::sha256(b"").to_string())
+                                .delay(Duration::from_millis(50)),
+                        )
+                    })
+                },
+            );
+
+            let heads = Arc::new(HeadsInFlight::default());
+            let first = Client::builder()
+                .transport(transport)
+                .interceptor(heads.clone())
+                .concurrency_limit(2)
+                .build();
+            let second = first.clone();
+
+            let pause: Image = "registry.k8s.io/pause:1".parse().unwrap();
+            let etcd: Image = "registry.k8s.io/etcd:1".parse().unwrap();
+            let digest = Digest::sha256(b"");
+
+            let (pause, etcd) = futures::join!(
+                first.tags_for_digest(&pause, &digest, None),
+                second.tags_for_digest(&etcd, &digest, None),
+            );
+
+            assert_eq!(pause.unwrap().len(), 6);
+            assert_eq!(etcd.unwrap().len(), 6);
+            assert_eq!(heads.max.load(Ordering::SeqCst), 2);
+        }
+
+        /// Setters change the client they are called on, not its clones.
+        #[tokio::test]
+        async fn setters_detach() {
+            let first = Client::builder().transport(transport()).build();
+            let mut second = first.clone();
+            second.set_offline(true);
+
+            let image: Image = "ghcr.io/example/app:1.0".parse().unwrap();
 
-            insta::assert_json_snapshot!(response);
+            first.get_manifest(&image).await.unwrap();
+            assert!(second.get_manifest(&image).await.is_err());
         }
     }
 }
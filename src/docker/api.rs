@@ -0,0 +1,753 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use http::{
+    header::{
+        HeaderMap,
+        HeaderValue,
+        ACCEPT,
+        CONTENT_TYPE,
+    },
+    Method,
+    StatusCode,
+};
+use url::Url;
+
+use crate::{
+    docker::{
+        blob,
+        error::FailedResponse,
+        media_types::MediaType,
+        negotiation::ManifestPreference,
+        read_body,
+        redirect,
+        tags,
+        transport::{
+            self,
+            Request,
+            Transport,
+        },
+        Client,
+        Error,
+        DEFAULT_MAX_MANIFEST_SIZE,
+        DEFAULT_MAX_REDIRECTS,
+        DEFAULT_MAX_TAG_LIST_SIZE,
+    },
+    image::append_segments,
+    manifest,
+    Digest,
+    Image,
+    Manifest,
+    Tag,
+};
+
+/// `RegistryApi` sends single requests to the endpoints of the distribution
+/// API and parses their responses. Unlike [`crate::Client`] it does not
+/// fetch tokens, follow mirrors, retry, cache or limit the request rate, the
+/// caller passes the `Authorization` header with every call and decides
+/// what to do with errors. Redirects are followed like the client does.
+///
+/// Create one with [`RegistryApi::new`] for a transport of your own or with
+/// [`crate::Client::registry_api`] to share the transport of a client.
+///
+/// The client sends its own requests to the registry and its mirrors with a
+/// `RegistryApi` as well and keeps tokens, mirrors and caches as the policy
+/// around them.
+#[derive(Debug, Clone)]
+pub struct RegistryApi {
+    transport: Arc<dyn Transport>,
+    base: Option<Url>,
+    max_manifest_size: u64,
+    max_tag_list_size: u64,
+    max_redirects: usize,
+}
+
+/// Sends every hop of a request with [`Client::execute_hop`], so the
+/// requests a client sends through [`RegistryApi`] wait for its rate limits
+/// and are counted in its stats and metrics like all of its other requests.
+#[derive(Debug)]
+struct ClientHops(Client);
+
+#[async_trait::async_trait]
+impl Transport for ClientHops {
+    async fn execute(&self, request: Request) -> Result<transport::Response, transport::Error> {
+        self.0.execute_hop(request).await
+    }
+}
+
+/// A parsed response of [`RegistryApi`] together with what the registry
+/// sent besides the body.
+#[derive(Debug, Clone)]
+pub struct ApiResponse<T> {
+    pub value: T,
+    pub url: Url,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+}
+
+/// A manifest returned by [`RegistryApi::manifest_get`].
+#[derive(Debug, Clone)]
+pub struct ManifestBody {
+    pub manifest: Manifest,
+
+    /// The manifest exactly as the registry sent it, the digest of the
+    /// manifest is computed over these bytes.
+    pub body: Bytes,
+}
+
+/// A page of a tag list returned by [`RegistryApi::tags_list`].
+#[derive(Debug, Clone)]
+pub struct TagsPage {
+    pub tags: Vec<Tag>,
+
+    /// The URL of the next page, see [`RegistryApi::tags_list_url`].
+    pub next: Option<Url>,
+}
+
+impl<T> ApiResponse<T> {
+    /// The value of the `Docker-Content-Digest` header.
+    #[must_use]
+    pub fn digest(&self) -> Option<&str> {
+        self.headers
+            .get("Docker-Content-Digest")
+            .and_then(|header| header.to_str().ok())
+    }
+
+    /// The value of the `Content-Type` header.
+    #[must_use]
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|header| header.to_str().ok())
+    }
+
+    fn new(value: T, response: &transport::Response) -> Self {
+        Self {
+            value,
+            url: response.url.clone(),
+            status: response.status,
+            headers: response.headers.clone(),
+        }
+    }
+
+    fn with<U>(self, value: U) -> ApiResponse<U> {
+        ApiResponse {
+            value,
+            url: self.url,
+            status: self.status,
+            headers: self.headers,
+        }
+    }
+}
+
+impl RegistryApi {
+    #[must_use]
+    pub fn new(transport: impl Transport + 'static) -> Self {
+        Self::from_transport(Arc::new(transport))
+    }
+
+    pub(super) fn from_transport(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            base: None,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            max_tag_list_size: DEFAULT_MAX_TAG_LIST_SIZE,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    /// The `RegistryApi` the client sends its requests with. Unlike
+    /// [`Client::registry_api`] every request goes through the rate limits,
+    /// stats and metrics of `client`.
+    pub(super) fn for_client(client: &Client) -> Self {
+        Self::from_transport(Arc::new(ClientHops(client.clone())))
+            .max_manifest_size(client.inner.max_manifest_size)
+            .max_redirects(client.inner.max_redirects)
+            .max_tag_list_size(client.inner.max_tag_list_size)
+    }
+
+    /// Sends all requests to `base`, for example `http://localhost:5000/`,
+    /// instead of the API base of the registry of the image.
+    #[must_use]
+    pub fn base(mut self, base: Url) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    /// Limits manifest bodies to `limit` bytes, see
+    /// [`crate::ClientBuilder::max_manifest_size`].
+    #[must_use]
+    pub fn max_manifest_size(mut self, limit: u64) -> Self {
+        self.max_manifest_size = limit;
+        self
+    }
+
+    /// Limits a single page of a tag list to `limit` bytes.
+    #[must_use]
+    pub fn max_tag_list_size(mut self, limit: u64) -> Self {
+        self.max_tag_list_size = limit;
+        self
+    }
+
+    /// Follows at most `limit` redirects per request, see
+    /// [`crate::ClientBuilder::max_redirects`].
+    #[must_use]
+    pub fn max_redirects(mut self, limit: usize) -> Self {
+        self.max_redirects = limit;
+        self
+    }
+
+    /// `GET /v2/<name>/manifests/<reference>`
+    ///
+    /// # Errors
+    /// Returns [`Error::ManifestNotFound`] if the registry answers with
+    /// `404`.
+    /// Returns an error if the request fails, the response status is not
+    /// successful or the body is not a valid manifest.
+    pub async fn manifest_get(
+        &self,
+        image: &Image,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<ManifestBody>, Error> {
+        let url = self.url(image, image.manifest_segments())?;
+        let response = self
+            .manifest_request(
+                Method::GET,
+                url,
+                auth.clone(),
+                &ManifestPreference::Any.accept(),
+            )
+            .await?;
+
+        let response = self
+            .check(
+                response,
+                Error::ManifestNotFound,
+                Error::FailedManifestRequest,
+            )
+            .await?;
+
+        let api = ApiResponse::new((), &response);
+        let body = self.read_manifest(response).await?;
+
+        let manifest = serde_json::from_slice(&body).map_err(|e| {
+            Error::DeserializeManifestBody(e, String::from_utf8_lossy(&body).into_owned())
+        })?;
+
+        Ok(api.with(ManifestBody {
+            manifest,
+            body: body.into(),
+        }))
+    }
+
+    /// `HEAD /v2/<name>/manifests/<reference>`, returns the digest the
+    /// reference points to.
+    ///
+    /// # Errors
+    /// Returns [`Error::ManifestNotFound`] if the registry answers with
+    /// `404`.
+    /// Returns an error if the request fails, the response status is not
+    /// successful or the `Docker-Content-Digest` header is missing.
+    pub async fn manifest_head(
+        &self,
+        image: &Image,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<Digest>, Error> {
+        let url = self.url(image, image.manifest_segments())?;
+        let response = self
+            .manifest_request(
+                Method::HEAD,
+                url,
+                auth.clone(),
+                &ManifestPreference::Any.accept(),
+            )
+            .await?;
+
+        let response = self
+            .check(
+                response,
+                Error::ManifestNotFound,
+                Error::FailedManifestRequest,
+            )
+            .await?;
+
+        let digest = content_digest(&response.headers)?;
+
+        Ok(ApiResponse::new(digest, &response))
+    }
+
+    /// `PUT /v2/<name>/manifests/<reference>`, returns the digest of `body`.
+    ///
+    /// # Errors
+    /// Returns an error if `media_type` is not a valid header value, if the
+    /// request fails or if the response status is not successful.
+    pub async fn manifest_put(
+        &self,
+        image: &Image,
+        media_type: &str,
+        body: Bytes,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<Digest>, Error> {
+        let url = self.url(image, image.manifest_segments())?;
+        let (digest, response) = self
+            .manifest_put_request(url, media_type, body, auth.clone())
+            .await?;
+
+        if !response.status.is_success() {
+            return Err(Error::FailedManifestPush(
+                FailedResponse::read(response, self.max_manifest_size).await,
+            ));
+        }
+
+        Ok(ApiResponse::new(digest, &response))
+    }
+
+    /// `GET /v2/<name>/blobs/<digest>`, reads the whole blob into memory and
+    /// checks it against `digest`. Reading stops after `limit` bytes.
+    ///
+    /// # Errors
+    /// Returns [`Error::BlobNotFound`] if the registry answers with `404`.
+    /// Returns an error if the request fails, the response status is not
+    /// successful, or the blob is larger than `limit` or does not match
+    /// `digest`.
+    pub async fn blob_get(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        limit: Option<u64>,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<Bytes>, Error> {
+        let url = self.url(image, image.blob_segments(digest))?;
+        let response = self.blob_request(Method::GET, url, auth.clone()).await?;
+
+        let response = self
+            .check(response, Error::BlobNotFound, Error::FailedBlobRequest)
+            .await?;
+
+        let api = ApiResponse::new((), &response);
+        let stream = blob::limit(response.into_stream(), limit, api.url.clone());
+
+        let body: Vec<u8> = blob::verify(stream, digest.clone())
+            .try_fold(Vec::new(), |mut body, chunk| async move {
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            })
+            .await
+            .map_err(Error::ReadBlob)?;
+
+        Ok(api.with(body.into()))
+    }
+
+    /// `GET /v2/<name>/tags/list?n=<n>&last=<last>`, returns the first page
+    /// of the tag list. Follow [`TagsPage::next`] with
+    /// [`RegistryApi::tags_list_url`].
+    ///
+    /// # Errors
+    /// Returns [`Error::TagsNotFound`] if the registry answers with `404`.
+    /// Returns an error if the request fails, the response status is not
+    /// successful or the body is not a valid tag list.
+    pub async fn tags_list(
+        &self,
+        image: &Image,
+        n: Option<usize>,
+        last: Option<&str>,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<TagsPage>, Error> {
+        let mut url = self.url(image, image.tags_segments())?;
+
+        if n.is_some() || last.is_some() {
+            let mut query = url.query_pairs_mut();
+
+            if let Some(n) = n {
+                query.append_pair("n", &n.to_string());
+            }
+
+            if let Some(last) = last {
+                query.append_pair("last", last);
+            }
+        }
+
+        self.tags_list_url(url, auth).await
+    }
+
+    /// Fetches the page of a tag list at `url`, usually the
+    /// [`TagsPage::next`] of the previous page.
+    ///
+    /// # Errors
+    /// Same as [`RegistryApi::tags_list`].
+    pub async fn tags_list_url(
+        &self,
+        url: Url,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<TagsPage>, Error> {
+        let response = self.tags_request(url, auth.clone()).await?;
+
+        let response = self
+            .check(response, Error::TagsNotFound, Error::FailedTagsRequest)
+            .await?;
+
+        let api = ApiResponse::new((), &response);
+        let body = self.read_tags(response).await?;
+
+        let (tags, next) = tags::parse_page(&api.headers, &api.url, &body)?;
+
+        Ok(api.with(TagsPage { tags, next }))
+    }
+
+    /// `GET /v2/<name>/referrers/<digest>?artifactType=<artifact_type>`,
+    /// returns the index of the manifests that refer to `subject`.
+    ///
+    /// # Errors
+    /// Returns [`Error::ManifestNotFound`] if the registry answers with
+    /// `404`, which registries without the referrers API do as well.
+    /// Returns an error if the request fails, the response status is not
+    /// successful or the body is not an image index.
+    pub async fn referrers_get(
+        &self,
+        image: &Image,
+        subject: &Digest,
+        artifact_type: Option<&str>,
+        auth: &HeaderMap,
+    ) -> Result<ApiResponse<manifest::List>, Error> {
+        let mut url = self.url(image, image.referrers_segments(subject))?;
+
+        if let Some(artifact_type) = artifact_type {
+            url.query_pairs_mut()
+                .append_pair("artifactType", artifact_type);
+        }
+
+        let response = self
+            .manifest_request(Method::GET, url, auth.clone(), MediaType::OciIndex.as_str())
+            .await?;
+
+        let response = self
+            .check(
+                response,
+                Error::ManifestNotFound,
+                Error::FailedManifestRequest,
+            )
+            .await?;
+
+        let api = ApiResponse::new((), &response);
+        let body = self.read_manifest(response).await?;
+
+        let list = serde_json::from_slice(&body).map_err(|e| {
+            Error::DeserializeManifestBody(e, String::from_utf8_lossy(&body).into_owned())
+        })?;
+
+        Ok(api.with(list))
+    }
+
+    fn url(
+        &self,
+        image: &Image,
+        segments: Result<Vec<String>, crate::image::UrlError>,
+    ) -> Result<Url, Error> {
+        let segments = segments.map_err(Error::InvalidPath)?;
+
+        let base = match &self.base {
+            Some(base) => base.clone(),
+            None => image
+                .registry
+                .api_base()
+                .map_err(Error::InvalidManifestUrl)?,
+        };
+
+        Ok(append_segments(base, &segments))
+    }
+
+    /// Sends a manifest request to `url` with `accept` as the `Accept`
+    /// header, without looking at the response status.
+    pub(super) async fn manifest_request(
+        &self,
+        method: Method,
+        url: Url,
+        auth: HeaderMap,
+        accept: &str,
+    ) -> Result<transport::Response, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            accept.parse().map_err(Error::ParseManifestAcceptHeader)?,
+        );
+
+        self.send(method, url, auth, headers, Error::GetManifest)
+            .await
+    }
+
+    /// Sends `body` as a manifest of type `media_type` to `url` and returns
+    /// the digest of `body`, without looking at the response status.
+    pub(super) async fn manifest_put_request(
+        &self,
+        url: Url,
+        media_type: &str,
+        body: Bytes,
+        auth: HeaderMap,
+    ) -> Result<(Digest, transport::Response), Error> {
+        let mut headers = auth;
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(media_type).map_err(Error::InvalidPushHeader)?,
+        );
+
+        let digest = Digest::sha256(&body);
+
+        let mut request = Request::new(Method::PUT, url).headers(headers);
+        request.body = Some(body);
+
+        let response = self.execute(request).await.map_err(Error::PushManifest)?;
+
+        Ok((digest, response))
+    }
+
+    /// Sends a blob request to `url`, without looking at the response
+    /// status.
+    pub(super) async fn blob_request(
+        &self,
+        method: Method,
+        url: Url,
+        auth: HeaderMap,
+    ) -> Result<transport::Response, Error> {
+        self.send(method, url, auth, HeaderMap::new(), Error::GetBlob)
+            .await
+    }
+
+    /// Requests the page of a tag list at `url`, without looking at the
+    /// response status.
+    pub(super) async fn tags_request(
+        &self,
+        url: Url,
+        auth: HeaderMap,
+    ) -> Result<transport::Response, Error> {
+        self.send(Method::GET, url, auth, HeaderMap::new(), Error::GetTags)
+            .await
+    }
+
+    /// Reads the body of a manifest response up to the manifest size limit.
+    pub(super) async fn read_manifest(
+        &self,
+        response: transport::Response,
+    ) -> Result<Vec<u8>, Error> {
+        read_body(response, self.max_manifest_size, Error::ExtractManifestBody).await
+    }
+
+    /// Reads the body of a tag list response up to the tag list size limit.
+    pub(super) async fn read_tags(&self, response: transport::Response) -> Result<Vec<u8>, Error> {
+        read_body(response, self.max_tag_list_size, Error::ExtractTagsBody).await
+    }
+
+    /// Turns `404` into `not_found` and every other unsuccessful status into
+    /// `failed`.
+    pub(super) async fn check(
+        &self,
+        response: transport::Response,
+        not_found: fn(Url) -> Error,
+        failed: fn(Box<FailedResponse>) -> Error,
+    ) -> Result<transport::Response, Error> {
+        if response.status == StatusCode::NOT_FOUND {
+            return Err(not_found(response.url));
+        }
+
+        if !response.status.is_success() {
+            return Err(failed(
+                FailedResponse::read(response, self.max_manifest_size).await,
+            ));
+        }
+
+        Ok(response)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        auth: HeaderMap,
+        extra_headers: HeaderMap,
+        map_err: fn(transport::Error) -> Error,
+    ) -> Result<transport::Response, Error> {
+        let mut headers = auth;
+        headers.extend(extra_headers);
+
+        self.execute(Request::new(method, url).headers(headers))
+            .await
+            .map_err(map_err)
+    }
+
+    /// Sends `request` and follows the redirects the server answers with,
+    /// see [`redirect::follow`].
+    async fn execute(&self, request: Request) -> Result<transport::Response, transport::Error> {
+        redirect::follow(request, self.max_redirects, |request| {
+            self.transport.execute(request)
+        })
+        .await
+    }
+}
+
+/// Parses the `Docker-Content-Digest` header of a manifest response.
+pub(super) fn content_digest(headers: &HeaderMap) -> Result<Digest, Error> {
+    headers
+        .get("Docker-Content-Digest")
+        .ok_or(Error::MissingDockerContentDigestHeader)?
+        .to_str()
+        .map_err(Error::ParseDockerContentDigestHeader)?
+        .parse()
+        .map_err(Error::ParseDockerContentDigest)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        header::{
+            HeaderMap,
+            AUTHORIZATION,
+        },
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::{
+            stats,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        manifest::Manifest,
+        Client,
+        ClientError,
+        Digest,
+        Image,
+        RegistryApi,
+    };
+
+    fn image() -> Image {
+        "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap()
+    }
+
+    fn auth() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer my-own-token".parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn manifest_get() {
+        let body = include_str!("../../resources/registry/github/cosign.json");
+
+        // Only the manifest is served, a token request would fail.
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+            MockResponse::new(StatusCode::OK)
+                .header("Docker-Content-Digest", "sha256:abc")
+                .body(body),
+        );
+
+        let api = RegistryApi::new(transport.clone());
+        let got = api.manifest_get(&image(), &auth()).await.unwrap();
+
+        assert!(matches!(got.value.manifest, Manifest::List(_)));
+        assert_eq!(got.value.body, body.as_bytes());
+        assert_eq!(got.digest(), Some("sha256:abc"));
+        assert_eq!(got.status, StatusCode::OK);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers[AUTHORIZATION], "Bearer my-own-token");
+    }
+
+    #[tokio::test]
+    async fn for_client_goes_through_the_client() {
+        let body = include_str!("../../resources/registry/github/cosign.json");
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+            MockResponse::new(StatusCode::OK).body(body),
+        );
+
+        let client = Client::builder().transport(transport.clone()).build();
+
+        let (result, direct) =
+            stats::measure(client.registry_api().manifest_get(&image(), &auth())).await;
+        result.unwrap();
+
+        let (result, through_client) =
+            stats::measure(RegistryApi::for_client(&client).manifest_get(&image(), &auth())).await;
+        result.unwrap();
+
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(direct.requests, 0);
+        assert_eq!(through_client.requests, 1);
+    }
+
+    #[tokio::test]
+    async fn manifest_get_not_found() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+            MockResponse::new(StatusCode::NOT_FOUND),
+        );
+
+        let got = RegistryApi::new(transport)
+            .manifest_get(&image(), &auth())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::ManifestNotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn tags_list() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://ghcr.io/v2/sigstore/cosign/cosign/tags/list?n=2",
+            MockResponse::new(StatusCode::OK)
+                .header(
+                    "Link",
+                    r#"</v2/sigstore/cosign/cosign/tags/list?last=0.2.0&n=2>; rel="next""#,
+                )
+                .body(r#"{"name":"sigstore/cosign/cosign","tags":["0.1.0","0.2.0"]}"#),
+        );
+
+        let got = RegistryApi::new(transport)
+            .tags_list(&image(), Some(2), None, &auth())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            got.value
+                .tags
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["0.1.0", "0.2.0"]
+        );
+        assert_eq!(
+            got.value.next.unwrap().as_str(),
+            "https://ghcr.io/v2/sigstore/cosign/cosign/tags/list?last=0.2.0&n=2"
+        );
+    }
+
+    #[tokio::test]
+    async fn blob_get_verifies_digest() {
+        let digest = Digest::sha256(b"expected");
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            &format!("https://ghcr.io/v2/sigstore/cosign/cosign/blobs/{digest}"),
+            MockResponse::new(StatusCode::OK).body("tampered"),
+        );
+
+        let got = RegistryApi::new(transport)
+            .blob_get(&image(), &digest, None, &auth())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::ReadBlob(_)));
+    }
+}
@@ -0,0 +1,234 @@
+use either::Either;
+
+use crate::{
+    attestation::{
+        Attestation,
+        IN_TOTO_MEDIA_TYPE,
+    },
+    docker::{
+        Client,
+        Error,
+    },
+    image::image_name::ImageName,
+    manifest::List,
+    Image,
+    Manifest,
+};
+
+/// Annotation buildkit sets on the layers of an attestation manifest with the
+/// predicate type of the statement in the layer.
+const PREDICATE_TYPE_ANNOTATION: &str = "in-toto.io/predicate-type";
+
+impl Client {
+    /// Fetches the attestations buildkit attached to the manifest list of
+    /// `image`, for example with `docker buildx build --provenance=true
+    /// --sbom=true`. Images that are not a manifest list have no attestations.
+    ///
+    /// # Errors
+    /// Returns an error if the manifest list, an attestation manifest or one
+    /// of its layers can not be fetched.
+    /// Returns [`Error::ParseAttestation`] if a layer is not a valid in-toto
+    /// statement.
+    #[tracing::instrument(
+        name = "get_attestations",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+        )
+    )]
+    pub async fn get_attestations(&self, image: &Image) -> Result<Vec<Attestation>, Error> {
+        let response = self.get_manifest(image).await?;
+
+        let Manifest::List(list) = &response.manifest else {
+            return Ok(Vec::new());
+        };
+
+        self.list_attestations(image, list, |_| true).await
+    }
+
+    /// Fetches the attestations of the entries of `list` whose predicate
+    /// type `wanted` accepts. Layers annotated with a predicate type that is
+    /// not wanted are not fetched.
+    pub(super) async fn list_attestations(
+        &self,
+        image: &Image,
+        list: &List,
+        wanted: fn(&str) -> bool,
+    ) -> Result<Vec<Attestation>, Error> {
+        let mut attestations = Vec::new();
+
+        for entry in list
+            .manifests
+            .iter()
+            .filter(|entry| entry.attestation_for().is_some())
+        {
+            let digest = entry.digest.clone();
+            let attestation_image = Image {
+                image_name: ImageName::new(image.image_name.name.clone(), Either::Right(digest)),
+                ..image.clone()
+            };
+
+            let Manifest::Image(manifest) = self.get_manifest(&attestation_image).await?.manifest
+            else {
+                continue;
+            };
+
+            for layer in manifest
+                .layers
+                .iter()
+                .filter(|layer| layer.media_type == IN_TOTO_MEDIA_TYPE)
+                .filter(|layer| {
+                    layer
+                        .annotations
+                        .get(PREDICATE_TYPE_ANNOTATION)
+                        .is_none_or(|predicate_type| wanted(predicate_type))
+                })
+            {
+                let body = self.get_descriptor_bytes(image, layer).await?;
+
+                let attestation = Attestation::parse(&layer.media_type, &body)
+                    .map_err(Error::ParseAttestation)?;
+
+                if wanted(attestation.predicate_type()) {
+                    attestations.push(attestation);
+                }
+            }
+        }
+
+        Ok(attestations)
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Attestation,
+        Client,
+        Digest,
+    };
+
+    const LIST_URL: &str = "https://registry.k8s.io/v2/app/manifests/1.0";
+    const PROVENANCE: &str = include_str!("../../resources/attestation/provenance-v0.2.json");
+    const SBOM: &str = include_str!("../../resources/attestation/sbom-spdx.json");
+
+    /// Serves a list with one image and the attestation manifest buildkit
+    /// pushes next to it.
+    fn transport() -> MockTransport {
+        let image_digest =
+            "sha256:4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945";
+        let provenance = Digest::sha256(PROVENANCE.as_bytes());
+        let sbom = Digest::sha256(SBOM.as_bytes());
+
+        let attestation_manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": 167,
+                "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111"
+            },
+            "layers": [
+                {
+                    "mediaType": "application/vnd.in-toto+json",
+                    "size": PROVENANCE.len(),
+                    "digest": provenance.to_string(),
+                    "annotations": { "in-toto.io/predicate-type": "https://slsa.dev/provenance/v0.2" }
+                },
+                {
+                    "mediaType": "application/vnd.in-toto+json",
+                    "size": SBOM.len(),
+                    "digest": sbom.to_string(),
+                    "annotations": { "in-toto.io/predicate-type": "https://spdx.dev/Document" }
+                }
+            ]
+        })
+        .to_string();
+        let attestation_digest = Digest::sha256(attestation_manifest.as_bytes());
+
+        let list = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": 1022,
+                    "digest": image_digest,
+                    "platform": { "architecture": "amd64", "os": "linux" }
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": attestation_manifest.len(),
+                    "digest": attestation_digest.to_string(),
+                    "platform": { "architecture": "unknown", "os": "unknown" },
+                    "annotations": {
+                        "vnd.docker.reference.digest": image_digest,
+                        "vnd.docker.reference.type": "attestation-manifest"
+                    }
+                }
+            ]
+        });
+
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                LIST_URL,
+                MockResponse::new(StatusCode::OK).body(list.to_string()),
+            )
+            .with_response(
+                Method::GET,
+                &format!("https://registry.k8s.io/v2/app/manifests/{attestation_digest}"),
+                MockResponse::new(StatusCode::OK).body(attestation_manifest),
+            )
+            .with_response(
+                Method::GET,
+                &format!("https://registry.k8s.io/v2/app/blobs/{provenance}"),
+                MockResponse::new(StatusCode::OK).body(PROVENANCE),
+            )
+            .with_response(
+                Method::GET,
+                &format!("https://registry.k8s.io/v2/app/blobs/{sbom}"),
+                MockResponse::new(StatusCode::OK).body(SBOM),
+            )
+    }
+
+    #[tokio::test]
+    async fn typed_attestations() {
+        let client = Client::builder().transport(transport()).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client.get_attestations(&image).await.unwrap();
+
+        assert_eq!(got.len(), 2);
+        assert!(matches!(got[0], Attestation::SlsaProvenanceV02(_)));
+        assert!(matches!(got[1], Attestation::Spdx(_)));
+    }
+
+    #[tokio::test]
+    async fn image_without_list() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            LIST_URL,
+            MockResponse::new(StatusCode::OK)
+                .body(include_str!("../../resources/manifest/image/example.json")),
+        );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client.get_attestations(&image).await.unwrap();
+
+        assert!(got.is_empty());
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -0,0 +1,479 @@
+//! The token requests the client sends to the token endpoints of registries,
+//! for callers that fetch tokens themselves, for example in a sidecar that
+//! hands them to the client later.
+//!
+//! ```
+//! use docker_registry_client::{
+//!     docker::auth::{
+//!         self,
+//!         Action,
+//!     },
+//!     Image,
+//! };
+//!
+//! let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+//! let request = auth::token_request_for(&image, &[Action::Pull], None)
+//!     .unwrap()
+//!     .unwrap();
+//!
+//! assert_eq!(
+//!     request.url().unwrap().as_str(),
+//!     "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io"
+//! );
+//! ```
+
+use http::{
+    header::{
+        HeaderValue,
+        InvalidHeaderValue,
+    },
+    Method,
+};
+use url::Url;
+
+use crate::{
+    docker::mirror::Authentication,
+    Image,
+    Registry,
+};
+
+/// What a token allows to do with a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Pull,
+    Push,
+    Delete,
+}
+
+/// A `Bearer` challenge of a `WWW-Authenticate` header, see
+/// [`parse_challenge`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub realm: String,
+    pub service: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// A request for a token. The client sends it as a `GET` to
+/// [`TokenRequest::url`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenRequest {
+    /// The token endpoint without query parameters.
+    pub endpoint: String,
+    pub method: Method,
+
+    /// The query parameters in the order they are sent. Parameters can
+    /// repeat, Docker Hub gets `service` twice and every repository gets its
+    /// own `scope`.
+    pub params: Vec<(String, String)>,
+}
+
+/// Overrides how the client builds token requests from the challenge of a
+/// registry, for token servers that reject requests other servers accept,
+/// see [`crate::ClientBuilder::token_params`].
+///
+/// By default a token request for a challenge always carries the `service`
+/// of the challenge and a `scope` only if the challenge named one.
+#[derive(Debug, Clone, Default)]
+pub struct TokenParams {
+    omit_scope: bool,
+    credentials: Option<Authentication>,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pull => f.write_str("pull"),
+            Self::Push => f.write_str("push"),
+            Self::Delete => f.write_str("delete"),
+        }
+    }
+}
+
+impl TokenRequest {
+    /// Builds the request for `scopes` from the token endpoint of `registry`
+    /// or from `challenge` if there is one. Returns `None` if there is no
+    /// challenge and the registry does not need authentication.
+    pub(super) fn new(
+        registry: &Registry,
+        scopes: &[String],
+        challenge: Option<&Challenge>,
+    ) -> Option<Self> {
+        if let Some(challenge) = challenge {
+            return Some(Self::from_challenge(
+                challenge,
+                scopes,
+                &TokenParams::default(),
+            ));
+        }
+
+        let scopes = scopes
+            .iter()
+            .map(|scope| ("scope".to_string(), scope.clone()));
+
+        let (endpoint, params): (&str, Vec<(String, String)>) = match registry {
+            Registry::Github => (
+                "https://ghcr.io/token",
+                scopes.chain([service("ghcr.io")]).collect(),
+            ),
+
+            Registry::DockerHub => (
+                "https://auth.docker.io/token",
+                std::iter::once(service("registry.docker.io"))
+                    .chain(scopes)
+                    .chain([service("registry.docker.io")])
+                    .collect(),
+            ),
+
+            Registry::Quay => (
+                "https://quay.io/v2/auth",
+                scopes.chain([service("quay.io")]).collect(),
+            ),
+
+            Registry::RedHat
+            | Registry::K8s
+            | Registry::Google
+            | Registry::Microsoft
+            | Registry::Custom(_) => return None,
+        };
+
+        Some(Self {
+            endpoint: endpoint.to_string(),
+            method: Method::GET,
+            params,
+        })
+    }
+
+    /// Builds the request for `scopes` from the realm and service of
+    /// `challenge`. `params` can drop the scopes and add the `account` of
+    /// its credentials.
+    pub(super) fn from_challenge(
+        challenge: &Challenge,
+        scopes: &[String],
+        params: &TokenParams,
+    ) -> Self {
+        let scopes = if params.omit_scope { &[] } else { scopes };
+
+        let params = scopes
+            .iter()
+            .map(|scope| ("scope".to_string(), scope.clone()))
+            .chain(challenge.service.as_deref().map(service))
+            .chain(
+                params
+                    .account()
+                    .map(|account| ("account".to_string(), account.to_string())),
+            )
+            .collect();
+
+        Self {
+            endpoint: challenge.realm.clone(),
+            method: Method::GET,
+            params,
+        }
+    }
+
+    /// The URL the request is sent to. The parameters are appended as they
+    /// are, scopes keep their `:` and `/` like registries expect them.
+    ///
+    /// # Errors
+    /// Returns an error if the endpoint, which may come from a challenge, is
+    /// not a valid URL.
+    pub fn url(&self) -> Result<Url, url::ParseError> {
+        let query = self
+            .params
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        if query.is_empty() {
+            return Url::parse(&self.endpoint);
+        }
+
+        let separator = if self.endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+
+        Url::parse(&format!("{}{separator}{query}", self.endpoint))
+    }
+}
+
+impl TokenParams {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never sends a `scope`, even if the challenge names one. For token
+    /// servers like older Harbor releases that reject anonymous requests
+    /// with a scope but hand out a token without one.
+    #[must_use]
+    pub fn omit_scope(mut self, omit_scope: bool) -> Self {
+        self.omit_scope = omit_scope;
+        self
+    }
+
+    /// Sends `username` as `account` and authenticates the token request
+    /// with HTTP basic authentication, the registry itself only ever sees
+    /// the token.
+    #[must_use]
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some(Authentication::Basic {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    fn account(&self) -> Option<&str> {
+        match &self.credentials {
+            Some(Authentication::Basic { username, .. }) => Some(username),
+            _ => None,
+        }
+    }
+
+    /// The `Authorization` header of the token request, `None` without
+    /// credentials.
+    pub(super) fn authorization(&self) -> Option<Result<HeaderValue, InvalidHeaderValue>> {
+        self.credentials
+            .as_ref()
+            .and_then(Authentication::header_value)
+    }
+}
+
+fn service(service: &str) -> (String, String) {
+    ("service".to_string(), service.to_string())
+}
+
+/// The scope for `actions` on the repository of `image`, for example
+/// `repository:library/alpine:pull,push`.
+#[must_use]
+pub fn scope(image: &Image, actions: &[Action]) -> String {
+    repository_scope(&image.repository_path(), actions)
+}
+
+/// The scope for `actions` on the repository at `path`, see [`scope`].
+pub(super) fn repository_scope(path: &str, actions: &[Action]) -> String {
+    format!("repository:{path}:{}", join_actions(actions))
+}
+
+/// The actions separated by commas like in a scope, for example `pull,push`.
+pub(super) fn join_actions(actions: &[Action]) -> String {
+    actions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns the token request the client sends for `actions` on the
+/// repository of `image`. With a `challenge`, for example from a `401` of
+/// the registry, its realm and service are used instead of the built-in
+/// endpoint of the registry. Without `actions` the token has no scope.
+///
+/// Returns `None` if there is no challenge and the client does not request
+/// tokens for the registry of `image`.
+///
+/// # Errors
+/// Returns an error if the realm of `challenge` is not a valid URL.
+#[must_use]
+pub fn token_request_for(
+    image: &Image,
+    actions: &[Action],
+    challenge: Option<&Challenge>,
+) -> Option<Result<TokenRequest, url::ParseError>> {
+    let scopes = if actions.is_empty() {
+        Vec::new()
+    } else {
+        vec![scope(image, actions)]
+    };
+
+    let request = TokenRequest::new(&image.registry, &scopes, challenge)?;
+
+    Some(request.url().map(|_| request))
+}
+
+/// Parses the value of a `WWW-Authenticate` header like
+/// `Bearer realm="https://auth.docker.io/token",service="registry.docker.io"`.
+/// Returns `None` for other schemes or if the realm is missing.
+#[must_use]
+pub fn parse_challenge(value: &str) -> Option<Challenge> {
+    let (scheme, params) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    let mut realm = None;
+    let mut service = None;
+    let mut scope = None;
+
+    for (name, value) in challenge_params(params) {
+        match name.as_str() {
+            "realm" => realm = Some(value),
+            "service" => service = Some(value),
+            "scope" => scope = Some(value),
+            _ => {}
+        }
+    }
+
+    Some(Challenge {
+        realm: realm?,
+        service,
+        scope,
+    })
+}
+
+/// Splits `name="value"` pairs at commas that are not quoted, scopes like
+/// `repository:a:pull,push` contain commas.
+fn challenge_params(params: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = params.trim();
+
+    while let Some((name, after)) = rest.split_once('=') {
+        let name = name
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let after = after.trim_start();
+
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, next)) => (value, next),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+
+        pairs.push((name, value.trim().to_string()));
+        rest = next.trim_start().trim_start_matches(',');
+    }
+
+    pairs
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        parse_challenge,
+        token_request_for,
+        Action,
+        Challenge,
+    };
+    use crate::Image;
+
+    fn url(image: &str, actions: &[Action]) -> Option<String> {
+        let image: Image = image.parse().unwrap();
+
+        token_request_for(&image, actions, None)
+            .map(|request| request.unwrap().url().unwrap().to_string())
+    }
+
+    #[test]
+    fn built_in_registries() {
+        for (image, expected) in [
+            (
+                "alpine:3.20",
+                Some(
+                    "https://auth.docker.io/token?service=registry.docker.io&scope=repository:library/alpine:pull&service=registry.docker.io",
+                ),
+            ),
+            (
+                "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+                Some(
+                    "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io",
+                ),
+            ),
+            (
+                "quay.io/prometheus/prometheus:v2.53.2",
+                Some(
+                    "https://quay.io/v2/auth?scope=repository:prometheus/prometheus:pull&service=quay.io",
+                ),
+            ),
+            ("registry.access.redhat.com/ubi8:8.9", None),
+            ("registry.k8s.io/pause:3.9", None),
+            ("gcr.io/distroless/static:latest", None),
+            ("mcr.microsoft.com/windows/nanoserver:ltsc2022", None),
+            ("registry.example.com/team/app:1.0", None),
+        ] {
+            assert_eq!(url(image, &[Action::Pull]).as_deref(), expected, "{image}");
+        }
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(
+            url(
+                "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+                &[Action::Pull, Action::Push]
+            )
+            .unwrap(),
+            "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull,push&service=ghcr.io"
+        );
+
+        assert_eq!(
+            url("ghcr.io/sigstore/cosign/cosign:v2.4.0", &[]).unwrap(),
+            "https://ghcr.io/token?service=ghcr.io"
+        );
+    }
+
+    #[test]
+    fn challenge() {
+        let image: Image = "registry.example.com/team/app:1.0".parse().unwrap();
+        let challenge = parse_challenge(
+            r#"Bearer realm="https://auth.example.com/token",service="registry.example.com""#,
+        )
+        .unwrap();
+
+        let got = token_request_for(&image, &[Action::Pull, Action::Delete], Some(&challenge))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            got.url().unwrap().as_str(),
+            "https://auth.example.com/token?scope=repository:team/app:pull,delete&service=registry.example.com"
+        );
+    }
+
+    #[test]
+    fn invalid_realm() {
+        let image: Image = "registry.example.com/team/app:1.0".parse().unwrap();
+        let challenge = parse_challenge(r#"Bearer realm="/token""#).unwrap();
+
+        assert!(token_request_for(&image, &[Action::Pull], Some(&challenge))
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_challenge(
+                r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull,push""#
+            ),
+            Some(Challenge {
+                realm: "https://auth.docker.io/token".to_string(),
+                service: Some("registry.docker.io".to_string()),
+                scope: Some("repository:library/alpine:pull,push".to_string()),
+            })
+        );
+
+        assert_eq!(
+            parse_challenge(r#"bearer realm="https://ghcr.io/token""#),
+            Some(Challenge {
+                realm: "https://ghcr.io/token".to_string(),
+                service: None,
+                scope: None,
+            })
+        );
+
+        assert_eq!(parse_challenge(r#"Basic realm="registry""#), None);
+        assert_eq!(parse_challenge(r#"Bearer service="registry""#), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{
+    future::Either,
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use url::Url;
+
+use crate::{
+    docker::transport,
+    image::image_name::digest::{
+        Hasher,
+        UnsupportedDigestAlgorithm,
+    },
+    Digest,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    ReadChunk(transport::Error),
+    DigestMismatch {
+        expected: Digest,
+        actual: Digest,
+    },
+
+    /// The connection of a response without `Content-Length` ended after
+    /// `received` bytes, before the blob matched `expected`. `source` is the
+    /// read error if the connection broke, `None` if it was closed before
+    /// the size of the descriptor was reached.
+    ConnectionClosed {
+        expected: Digest,
+        received: u64,
+        source: Option<transport::Error>,
+    },
+
+    /// No chunk arrived for the duration, see
+    /// [`crate::ClientBuilder::blob_idle_timeout`].
+    IdleTimeout(Duration),
+    BodyTooLarge {
+        limit: u64,
+        url: Url,
+    },
+    UnsupportedDigestAlgorithm(UnsupportedDigestAlgorithm),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadChunk(e) => write!(f, "failed to read blob chunk: {e}"),
+            Self::DigestMismatch { expected, actual } => {
+                write!(f, "blob digest mismatch: expected {expected}, got {actual}")
+            }
+            Self::ConnectionClosed {
+                expected,
+                received,
+                source,
+            } => {
+                write!(
+                    f,
+                    "connection closed after {received} bytes before the blob matched {expected}"
+                )?;
+
+                match source {
+                    Some(e) => write!(f, ": {e}"),
+                    None => Ok(()),
+                }
+            }
+            Self::IdleTimeout(timeout) => write!(f, "no blob data received for {timeout:?}"),
+            Self::BodyTooLarge { limit, url } => {
+                write!(f, "blob at {url} is larger than the limit of {limit} bytes")
+            }
+            Self::UnsupportedDigestAlgorithm(e) => write!(f, "can not verify blob: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        Self::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Ends `stream` with [`Error::BodyTooLarge`] as soon as more than `limit`
+/// bytes were read. Without a limit the stream is passed through.
+pub(super) fn limit(
+    stream: impl Stream<Item = Result<Bytes, transport::Error>> + Send + 'static,
+    limit: Option<u64>,
+    url: Url,
+) -> impl Stream<Item = Result<Bytes, Error>> + Send + 'static {
+    let mut read: u64 = 0;
+
+    stream
+        .map_err(Error::ReadChunk)
+        .scan(false, move |done, item| {
+            if *done {
+                return futures::future::ready(None);
+            }
+
+            let item = item.and_then(|chunk| {
+                read += chunk.len() as u64;
+
+                match limit {
+                    Some(limit) if read > limit => Err(Error::BodyTooLarge {
+                        limit,
+                        url: url.clone(),
+                    }),
+                    _ => Ok(chunk),
+                }
+            });
+
+            *done = item.is_err();
+
+            futures::future::ready(Some(item))
+        })
+}
+
+/// Ends `stream` with [`Error::IdleTimeout`] if no chunk arrives for
+/// `timeout`. Without a timeout the stream is passed through.
+pub(super) fn idle(
+    stream: impl Stream<Item = Result<Bytes, Error>> + Send + 'static,
+    timeout: Option<Duration>,
+) -> impl Stream<Item = Result<Bytes, Error>> + Send + 'static {
+    let Some(timeout) = timeout else {
+        return Either::Left(stream);
+    };
+
+    let state = Some(Box::pin(stream));
+
+    Either::Right(futures::stream::unfold(state, move |state| async move {
+        let mut stream = state?;
+
+        match tokio::time::timeout(timeout, stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            Err(_) => Some((Err(Error::IdleTimeout(timeout)), None)),
+        }
+    }))
+}
+
+/// Passes the chunks of `stream` through while hashing them with the
+/// algorithm of `expected`. Once the stream ends the hash is compared against
+/// `expected` and a [`Error::DigestMismatch`] is yielded as the last item if
+/// they differ. If the algorithm is not supported the stream only yields
+/// [`Error::UnsupportedDigestAlgorithm`].
+pub(super) fn verify(
+    stream: impl Stream<Item = Result<Bytes, Error>> + Send + 'static,
+    expected: Digest,
+) -> impl Stream<Item = Result<Bytes, Error>> + Send + 'static {
+    let hasher = match expected.algorithm() {
+        Ok(algorithm) => Hasher::new(algorithm),
+        Err(e) => {
+            return Either::Left(futures::stream::iter([Err(
+                Error::UnsupportedDigestAlgorithm(e),
+            )]));
+        }
+    };
+
+    let state = Some((Box::pin(stream), hasher, expected));
+
+    Either::Right(futures::stream::unfold(state, |state| async move {
+        let (mut stream, mut hasher, expected) = state?;
+
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                hasher.update(&chunk);
+                Some((Ok(chunk), Some((stream, hasher, expected))))
+            }
+
+            Some(Err(e)) => Some((Err(e), None)),
+
+            None => {
+                let actual = hasher.finalize();
+
+                if actual.is_equivalent(&expected) {
+                    None
+                } else {
+                    Some((Err(Error::DigestMismatch { expected, actual }), None))
+                }
+            }
+        }
+    }))
+}
+
+/// A body without `Content-Length` ends when the registry closes the
+/// connection, so a connection that breaks or is closed early looks like a
+/// complete blob with the wrong content. Turns a read error, or a digest
+/// mismatch after fewer than `size` bytes if the size is known, of the
+/// verified `stream` into [`Error::ConnectionClosed`].
+pub(super) fn closed_early(
+    stream: impl Stream<Item = Result<Bytes, Error>> + Send + 'static,
+    expected: Digest,
+    size: Option<u64>,
+) -> impl Stream<Item = Result<Bytes, Error>> + Send + 'static {
+    let mut received: u64 = 0;
+
+    stream.map(move |item| match item {
+        Ok(chunk) => {
+            received += chunk.len() as u64;
+            Ok(chunk)
+        }
+
+        Err(Error::ReadChunk(source)) => Err(Error::ConnectionClosed {
+            expected: expected.clone(),
+            received,
+            source: Some(source),
+        }),
+
+        Err(Error::DigestMismatch { .. }) if size.is_some_and(|size| received < size) => {
+            Err(Error::ConnectionClosed {
+                expected: expected.clone(),
+                received,
+                source: None,
+            })
+        }
+
+        Err(e) => Err(e),
+    })
+}
@@ -0,0 +1,699 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
+    time::Duration,
+};
+
+use http::header::{
+    HeaderName,
+    HeaderValue,
+};
+
+#[cfg(feature = "dockerhub-api")]
+use crate::docker::dockerhub;
+use crate::{
+    docker::{
+        auth::TokenParams,
+        containerd,
+        in_flight::InFlight,
+        interceptor::{
+            DebugHttpLogging,
+            Intercepted,
+            RequestInterceptor,
+        },
+        manifest_cache::{
+            self,
+            Cache as ManifestCache,
+            MemoryManifestCache,
+        },
+        metrics::Metrics,
+        mirror::{
+            Mirror,
+            Mirrors,
+        },
+        policy::Policy,
+        rate_limit::{
+            Limit,
+            RateLimits,
+        },
+        rate_limit_status,
+        registry_headers::{
+            RegistryHeader,
+            RegistryHeaders,
+        },
+        shutdown,
+        token_cache::{
+            self,
+            Cache as TokenCache,
+            TokenFile,
+        },
+        transport::{
+            ReqwestTransport,
+            Transport,
+        },
+        Client,
+        ClientInner,
+        DEFAULT_CONCURRENCY_LIMIT,
+        DEFAULT_MAX_MANIFEST_SIZE,
+        DEFAULT_MAX_REDIRECTS,
+        DEFAULT_MAX_TAG_LIST_SIZE,
+        DEFAULT_TOKEN_CLOCK_SKEW,
+        DEFAULT_UPLOAD_CHUNK_SIZE,
+    },
+    manifest::Platform,
+    Registry,
+};
+
+/// `ClientBuilder` configures a [`Client`]. By default tokens are cached in
+/// memory and manifests are not cached.
+#[derive(Debug, Clone)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "every flag is an independent builder option"
+)]
+pub struct ClientBuilder {
+    transport: Option<Arc<dyn Transport>>,
+    host_clients: Vec<(String, reqwest::Client)>,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    debug_http_logging: bool,
+    token_cache: Box<dyn TokenCache + Send>,
+    token_file: Option<(PathBuf, token_cache::MemoryTokenCache)>,
+    manifest_cache: Box<dyn ManifestCache + Send>,
+    mirrors: Mirrors,
+    offline: bool,
+    max_manifest_size: u64,
+    max_redirects: usize,
+    max_tag_list_size: u64,
+    max_blob_size: Option<u64>,
+    blob_idle_timeout: Option<Duration>,
+    blob_idle_timeout_without_length: bool,
+    token_clock_skew: Duration,
+    default_platform: Platform,
+    verify_descriptors: bool,
+    allow_digest_mismatch: bool,
+    try_anonymous_on_token_failure: bool,
+    upload_chunk_size: u64,
+    rate_limits: Vec<Limit>,
+    rate_limit_timeout: Option<Duration>,
+    concurrency_limit: usize,
+    registry_headers: Vec<RegistryHeader>,
+    api_version_exempt: HashSet<Registry>,
+    registry_policy: Policy,
+    shutdown_hooks: shutdown::Hooks,
+    metrics: Option<Arc<dyn Metrics>>,
+    token_params: HashMap<Registry, TokenParams>,
+    #[cfg(feature = "dockerhub-api")]
+    dockerhub_credentials: Option<dockerhub::Credentials>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            transport: None,
+            host_clients: Vec::new(),
+            interceptors: Vec::new(),
+            debug_http_logging: false,
+            token_cache: Box::new(token_cache::MemoryTokenCache::default()),
+            token_file: None,
+            manifest_cache: Box::new(manifest_cache::NoCache),
+            mirrors: Mirrors::default(),
+            offline: false,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_tag_list_size: DEFAULT_MAX_TAG_LIST_SIZE,
+            max_blob_size: None,
+            blob_idle_timeout: None,
+            blob_idle_timeout_without_length: false,
+            token_clock_skew: DEFAULT_TOKEN_CLOCK_SKEW,
+            default_platform: Platform::current(),
+            verify_descriptors: true,
+            allow_digest_mismatch: false,
+            try_anonymous_on_token_failure: false,
+            upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            rate_limits: Vec::new(),
+            rate_limit_timeout: None,
+            concurrency_limit: DEFAULT_CONCURRENCY_LIMIT,
+            registry_headers: Vec::new(),
+            api_version_exempt: HashSet::new(),
+            registry_policy: Policy::default(),
+            shutdown_hooks: shutdown::Hooks::default(),
+            metrics: None,
+            token_params: HashMap::new(),
+            #[cfg(feature = "dockerhub-api")]
+            dockerhub_credentials: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends all requests through the given transport instead of reqwest.
+    #[must_use]
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Adds an interceptor that is called around every request. Interceptors
+    /// run in the order they were added.
+    #[must_use]
+    pub fn interceptor(mut self, interceptor: impl RequestInterceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Emits a tracing event at debug level for every request with its
+    /// method, URL, status, duration, response size and headers. The values
+    /// of the `Authorization`, `Cookie` and `Set-Cookie` headers and of all
+    /// query parameters except `scope` and `service` are replaced with
+    /// `[REDACTED]`, bodies are not logged at all.
+    #[must_use]
+    pub fn debug_http_logging(mut self, enabled: bool) -> Self {
+        self.debug_http_logging = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn token_cache_memory(mut self) -> Self {
+        self.token_cache = Box::new(token_cache::MemoryTokenCache::default());
+        self.token_file = None;
+        self
+    }
+
+    /// Caches tokens in memory and loads the tokens saved in `path` by an
+    /// earlier process right away. The tokens are saved back to `path` when
+    /// the last clone of the client is dropped or with
+    /// [`Client::persist_tokens`]. Expired tokens are neither loaded nor
+    /// saved. A missing or corrupted file is treated as an empty cache.
+    #[must_use]
+    pub fn token_cache_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let cache = token_cache::MemoryTokenCache::load_from(&path);
+
+        self.token_cache = Box::new(cache.clone());
+        self.token_file = Some((path, cache));
+        self
+    }
+
+    /// Sets how far the clock of a token server may be off from the local
+    /// clock. Defaults to [`DEFAULT_TOKEN_CLOCK_SKEW`].
+    ///
+    /// The lifetime of a token counts from when it was received, so the
+    /// clock of the token server does not matter for tokens fetched by this
+    /// client. Tokens cached by earlier versions only carry the time the
+    /// server issued them at, those are treated as expiring `skew` early and
+    /// are not used if they were issued more than `skew` in the future. The
+    /// memory and file caches remove expired tokens with
+    /// [`DEFAULT_TOKEN_CLOCK_SKEW`].
+    #[must_use]
+    pub fn token_clock_skew(mut self, skew: Duration) -> Self {
+        self.token_clock_skew = skew;
+        self
+    }
+
+    /// Caches tokens in memory but keeps at most `max_entries` of them. The
+    /// tokens that expire first are removed when the limit is exceeded.
+    #[must_use]
+    pub fn token_cache_memory_max_entries(mut self, max_entries: usize) -> Self {
+        self.token_cache = Box::new(token_cache::MemoryTokenCache::with_max_entries(max_entries));
+        self.token_file = None;
+        self
+    }
+
+    #[must_use]
+    pub fn disable_token_caching(mut self) -> Self {
+        self.token_cache = Box::new(token_cache::NoCache);
+        self.token_file = None;
+        self
+    }
+
+    #[cfg(feature = "redis_cache")]
+    #[must_use]
+    pub fn token_cache_redis(mut self, redis_client: redis::Client) -> Self {
+        self.token_cache = Box::new(token_cache::RedisCache::new(redis_client));
+        self.token_file = None;
+        self
+    }
+
+    /// Caches recently fetched manifests in memory using
+    /// [`MemoryManifestCache::default`].
+    #[must_use]
+    pub fn manifest_cache_memory(self) -> Self {
+        self.manifest_cache_memory_with(MemoryManifestCache::default())
+    }
+
+    /// Caches recently fetched manifests in the given memory cache. Clones of
+    /// the cache share their entries and counters, so a clone can be kept to
+    /// inspect the cache later.
+    #[must_use]
+    pub fn manifest_cache_memory_with(mut self, cache: MemoryManifestCache) -> Self {
+        self.manifest_cache = Box::new(cache);
+        self
+    }
+
+    /// Caches manifests that are requested by digest in Redis. Manifests
+    /// requested by tag are always fetched from the registry.
+    #[cfg(feature = "redis_cache")]
+    #[must_use]
+    pub fn manifest_cache_redis(mut self, redis_client: redis::Client) -> Self {
+        self.manifest_cache = Box::new(manifest_cache::RedisDigestCache::new(redis_client));
+        self
+    }
+
+    #[must_use]
+    pub fn disable_manifest_caching(mut self) -> Self {
+        self.manifest_cache = Box::new(manifest_cache::NoCache);
+        self
+    }
+
+    /// Sends manifest requests for the registry at `original_host` to the
+    /// given mirror first. Mirrors of the same registry are tried in the order
+    /// they were added, a mirror that fails or answers with not found or a
+    /// server error is skipped.
+    ///
+    /// `mirror` can be a [`url::Url`] for an anonymous mirror or a [`Mirror`]
+    /// with its own credentials.
+    #[must_use]
+    pub fn registry_mirror(mut self, original_host: &str, mirror: impl Into<Mirror>) -> Self {
+        self.mirrors.add(original_host, mirror.into());
+        self
+    }
+
+    /// Sends requests for the registry at `original_host` to `api_base`
+    /// instead of the API host of the registry, for example
+    /// `https://index.docker.io` for proxies that only allow that host for
+    /// Docker Hub. Image references and their display are not affected.
+    #[must_use]
+    pub fn registry_api_base(mut self, original_host: &str, api_base: url::Url) -> Self {
+        self.mirrors.set_api_base(original_host, api_base);
+        self
+    }
+
+    /// Configures mirrors and TLS settings like containerd from the
+    /// `hosts.toml` files in the directories of `path`, usually
+    /// `/etc/containerd/certs.d`. Every directory is named after the registry
+    /// it configures:
+    ///
+    /// - hosts with the `pull` and `resolve` capabilities become anonymous
+    ///   mirrors in the order of the file,
+    /// - a `server` other than the API host of the registry becomes its API
+    ///   base, see [`ClientBuilder::registry_api_base`],
+    /// - `ca` files and `skip_verify` apply to the connections to their host.
+    ///
+    /// Other capabilities and settings, hosts without both capabilities and
+    /// the `_default` directory are ignored with a warning. The TLS settings
+    /// only apply to the default transport, not to one set with
+    /// [`ClientBuilder::transport`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a `hosts.toml` or CA file can not be read or
+    /// parsed.
+    pub fn load_containerd_hosts_dir(
+        mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, containerd::Error> {
+        let hosts = containerd::load(path.as_ref())?;
+
+        for (original_host, mirror) in hosts.mirrors {
+            self.mirrors.add(&original_host, mirror);
+        }
+
+        for (original_host, api_base) in hosts.api_bases {
+            self.mirrors.set_api_base(&original_host, api_base);
+        }
+
+        for tls in hosts.tls {
+            if tls.skip_verify {
+                tracing::warn!(host = tls.host, "certificates of the host are not verified");
+            }
+
+            tracing::debug!(host = tls.host, ca_files = ?tls.ca_files, "using custom TLS settings");
+
+            self.host_clients.push((tls.host, tls.client));
+        }
+
+        Ok(self)
+    }
+
+    /// Controls if the original registry is tried after all mirrors failed.
+    /// Defaults to `true`, disable it for air-gapped environments where the
+    /// original registry is not reachable.
+    #[must_use]
+    pub fn fallback_to_upstream(mut self, fallback_to_upstream: bool) -> Self {
+        self.mirrors.set_fallback_to_upstream(fallback_to_upstream);
+        self
+    }
+
+    /// Serves requests only from the configured caches without making any
+    /// network requests. Cache misses fail with
+    /// [`crate::ClientError::OfflineCacheMiss`].
+    #[must_use]
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Limits the size of manifest and token response bodies. Larger
+    /// responses fail with [`crate::ClientError::BodyTooLarge`] without being
+    /// buffered completely. Defaults to [`DEFAULT_MAX_MANIFEST_SIZE`].
+    #[must_use]
+    pub fn max_manifest_size(mut self, limit: u64) -> Self {
+        self.max_manifest_size = limit;
+        self
+    }
+
+    /// Follows at most `limit` redirects per request, a server that
+    /// redirects more often fails the request with
+    /// [`crate::docker::transport::Error::TooManyRedirects`]. Credentials are
+    /// only sent to the host the request was made to, redirects to another
+    /// host, like the blob storage registry.k8s.io redirects to, are sent
+    /// without them. Defaults to [`DEFAULT_MAX_REDIRECTS`].
+    #[must_use]
+    pub fn max_redirects(mut self, limit: usize) -> Self {
+        self.max_redirects = limit;
+        self
+    }
+
+    /// Limits the size of a single page of a tag list. Defaults to
+    /// [`DEFAULT_MAX_TAG_LIST_SIZE`].
+    #[must_use]
+    pub fn max_tag_list_size(mut self, limit: u64) -> Self {
+        self.max_tag_list_size = limit;
+        self
+    }
+
+    /// Limits the size of blobs. Blobs are streamed so by default they are
+    /// not limited.
+    #[must_use]
+    pub fn max_blob_size(mut self, limit: Option<u64>) -> Self {
+        self.max_blob_size = limit;
+        self
+    }
+
+    /// Fails blob downloads that receive nothing for `timeout` with
+    /// [`crate::docker::blob::Error::IdleTimeout`]. By default blob downloads
+    /// do not time out.
+    ///
+    /// Responses without `Content-Length` are exempt unless
+    /// [`ClientBuilder::blob_idle_timeout_without_length`] is set. Legacy
+    /// registries send blobs that way with `Connection: close` and can stall
+    /// between chunks, the end of such a blob is only known once the
+    /// connection is closed.
+    #[must_use]
+    pub fn blob_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.blob_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Applies [`ClientBuilder::blob_idle_timeout`] to blob responses without
+    /// `Content-Length` as well. Defaults to `false`.
+    #[must_use]
+    pub fn blob_idle_timeout_without_length(mut self, enabled: bool) -> Self {
+        self.blob_idle_timeout_without_length = enabled;
+        self
+    }
+
+    /// Sets the platform manifest lists are resolved to by
+    /// [`Client::get_manifest_resolved`]. Defaults to [`Platform::current`].
+    #[must_use]
+    pub fn default_platform(mut self, platform: Platform) -> Self {
+        self.default_platform = platform;
+        self
+    }
+
+    /// Controls if manifests that are fetched by following an entry of a
+    /// manifest list are checked against the size and digest of the entry.
+    /// Defaults to `true`, disable it for registries known to serve
+    /// inconsistent lists.
+    #[must_use]
+    pub fn verify_descriptors(mut self, verify_descriptors: bool) -> Self {
+        self.verify_descriptors = verify_descriptors;
+        self
+    }
+
+    /// Controls if a manifest requested by digest is returned even though
+    /// the registry answered with a different digest. Defaults to `false`,
+    /// which fails the request with [`crate::ClientError::DigestMismatch`].
+    /// Only meant for debugging broken mirrors, the mismatch is logged as an
+    /// error and [`crate::Response::digest_verified`] is `false`.
+    #[must_use]
+    pub fn allow_digest_mismatch(mut self, allow_digest_mismatch: bool) -> Self {
+        self.allow_digest_mismatch = allow_digest_mismatch;
+        self
+    }
+
+    /// Sends requests to the registry without a token if the token endpoint
+    /// can not be reached or answers with a server error, for registries that
+    /// ask for a token but serve public images without one. A token endpoint
+    /// that denies the request is not worked around. If the registry denies
+    /// the request without a token as well,
+    /// [`crate::ClientError::AnonymousFallbackFailed`] has both errors.
+    /// Mirrors are not retried without a token. Defaults to `false`.
+    #[must_use]
+    pub fn try_anonymous_on_token_failure(mut self, try_anonymous: bool) -> Self {
+        self.try_anonymous_on_token_failure = try_anonymous;
+        self
+    }
+
+    /// Limits the requests to the registry at `registry_host` to
+    /// `requests_per_interval` per `interval`, for example to stay under the
+    /// anonymous pull limit of Docker Hub. Bursts of up to
+    /// `requests_per_interval` requests are sent right away. The limit covers
+    /// every request to the registry including token and blob requests but
+    /// not requests to its mirrors, and is shared by clones of the client.
+    ///
+    /// Requests over the limit wait until they are allowed, in the order they
+    /// were made. See [`ClientBuilder::rate_limit_timeout`] to bound the wait.
+    #[must_use]
+    pub fn rate_limit(
+        mut self,
+        registry_host: &str,
+        requests_per_interval: u32,
+        interval: Duration,
+    ) -> Self {
+        self.rate_limits
+            .push(Limit::new(registry_host, requests_per_interval, interval));
+        self
+    }
+
+    /// Fails requests that waited longer than `timeout` for the rate limit of
+    /// their registry with
+    /// [`crate::docker::transport::Error::RateLimitTimeout`].
+    /// The wait happens before the request is handed to the transport, so it
+    /// does not count against a timeout of the transport. By default requests
+    /// wait as long as needed.
+    #[must_use]
+    pub fn rate_limit_timeout(mut self, timeout: Duration) -> Self {
+        self.rate_limit_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how many manifest lookups of [`Client::tags_for_digest`] and
+    /// [`Client::walk_repository`] and layer downloads of
+    /// [`Client::download_layers`] run at the same time. The limit is shared
+    /// by all clones of the client. Defaults to
+    /// [`DEFAULT_CONCURRENCY_LIMIT`].
+    #[must_use]
+    pub fn concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency_limit = limit.max(1);
+        self
+    }
+
+    /// Sets the size of the chunks blobs larger than it are uploaded in by
+    /// [`Client::push_oci_layout`]. Defaults to
+    /// [`DEFAULT_UPLOAD_CHUNK_SIZE`].
+    #[must_use]
+    pub fn upload_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.upload_chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Adds the header `name` with `value` to every request to the registry
+    /// at `registry_host`, for example a token a gateway in front of the
+    /// registry requires. Like [`ClientBuilder::rate_limit`] this covers
+    /// manifest, token and blob requests to the registry and redirects that
+    /// stay on its hosts, but not mirrors or redirects to other hosts. Token
+    /// requests to the realm of a challenge of the registry get them as well,
+    /// also if the realm is on another host.
+    /// Multiple headers and multiple values of the same header can be added
+    /// per registry.
+    ///
+    /// The value is treated as a secret and not shown when headers are
+    /// logged.
+    #[must_use]
+    pub fn registry_header(
+        mut self,
+        registry_host: &str,
+        name: HeaderName,
+        value: HeaderValue,
+    ) -> Self {
+        self.registry_headers
+            .push(RegistryHeader::new(registry_host, name, value));
+        self
+    }
+
+    /// Accepts responses of the registry at `registry_host` without a
+    /// `Docker-Distribution-API-Version: registry/2.0` header and with any
+    /// content type. Meant for registries that are known to omit the header,
+    /// by default [`Client::ping`] fails with
+    /// [`crate::docker::Error::NotAV2Registry`] without it and manifest
+    /// requests fail if they receive an HTML page.
+    #[must_use]
+    pub fn skip_api_version_check(mut self, registry_host: &str) -> Self {
+        self.api_version_exempt
+            .insert(Registry::try_from_host(registry_host));
+        self
+    }
+
+    /// Restricts the registries the client talks to, see [`Policy`]. By
+    /// default every registry is allowed.
+    #[must_use]
+    pub fn registry_policy(mut self, policy: Policy) -> Self {
+        self.registry_policy = policy;
+        self
+    }
+
+    /// Logs in to the Docker Hub API with the given credentials, required to
+    /// read the metadata of private repositories with
+    /// [`Client::dockerhub_repository`] and [`Client::dockerhub_tags`].
+    #[cfg(feature = "dockerhub-api")]
+    #[must_use]
+    pub fn dockerhub_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.dockerhub_credentials = Some(dockerhub::Credentials {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Changes the token requests the client sends for challenges of the
+    /// registry at `registry_host`, for token servers that need other
+    /// parameters than the challenge asks for, see [`TokenParams`]. Only
+    /// applies to registries without a built-in token endpoint, which
+    /// authenticate by answering with a `WWW-Authenticate` challenge.
+    #[must_use]
+    pub fn token_params(mut self, registry_host: &str, params: TokenParams) -> Self {
+        self.token_params
+            .insert(Registry::try_from_host(registry_host), params);
+        self
+    }
+
+    /// Reports every request, token cache hit and transferred body to
+    /// `metrics`, for example [`crate::docker::metrics::PrometheusMetrics`]
+    /// with the `prometheus` feature.
+    #[must_use]
+    pub fn metrics(mut self, metrics: impl Metrics + 'static) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Runs `hook` when [`Client::shutdown`] is called, for example to flush
+    /// metrics. Hooks run in the order they were added.
+    #[must_use]
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks.push(hook);
+        self
+    }
+
+    #[must_use]
+    pub fn build(mut self) -> Client {
+        if self.debug_http_logging {
+            self.interceptors.push(Arc::new(DebugHttpLogging));
+        }
+
+        let transport: Arc<dyn Transport> = match self.transport {
+            Some(transport) => {
+                if !self.host_clients.is_empty() {
+                    tracing::warn!(
+                        "the TLS settings of registries do not apply to a custom transport"
+                    );
+                }
+
+                transport
+            }
+            None => Arc::new(
+                self.host_clients
+                    .iter()
+                    .fold(ReqwestTransport::default(), |transport, (host, client)| {
+                        transport.host_client(host, client.clone())
+                    }),
+            ),
+        };
+
+        let transport: Arc<dyn Transport> = if self.interceptors.is_empty() {
+            transport
+        } else {
+            Arc::new(Intercepted {
+                inner: transport,
+                interceptors: self.interceptors,
+            })
+        };
+
+        let rate_limits =
+            RateLimits::new(&self.rate_limits, &self.mirrors, self.rate_limit_timeout);
+        let registry_headers =
+            Arc::new(RegistryHeaders::new(&self.registry_headers, &self.mirrors));
+
+        let inner = ClientInner {
+            transport,
+            token_cache: self.token_cache,
+            token_file: self
+                .token_file
+                .map(|(path, cache)| Arc::new(TokenFile::new(path, cache))),
+            manifest_cache: self.manifest_cache,
+            in_flight: InFlight::default(),
+            tokens_in_flight: InFlight::default(),
+            mirrors: self.mirrors,
+            offline: self.offline,
+            max_manifest_size: self.max_manifest_size,
+            max_redirects: self.max_redirects,
+            max_tag_list_size: self.max_tag_list_size,
+            max_blob_size: self.max_blob_size,
+            blob_idle_timeout: self.blob_idle_timeout,
+            blob_idle_timeout_without_length: self.blob_idle_timeout_without_length,
+            token_clock_skew: chrono::Duration::from_std(self.token_clock_skew)
+                .unwrap_or(chrono::Duration::MAX),
+            default_platform: self.default_platform,
+            verify_descriptors: self.verify_descriptors,
+            allow_digest_mismatch: self.allow_digest_mismatch,
+            try_anonymous_on_token_failure: self.try_anonymous_on_token_failure,
+            upload_chunk_size: self.upload_chunk_size,
+            rate_limits,
+            concurrency_limit: self.concurrency_limit,
+            concurrency: Arc::new(tokio::sync::Semaphore::new(self.concurrency_limit)),
+            registry_headers,
+            rate_limit_status: rate_limit_status::Observed::default(),
+            api_version_exempt: Arc::new(self.api_version_exempt),
+            registry_policy: Arc::new(self.registry_policy),
+            shutdown_hooks: self.shutdown_hooks,
+            metrics: self.metrics,
+            token_params: self.token_params,
+            #[cfg(feature = "dockerhub-api")]
+            dockerhub: dockerhub::Hub::new(self.dockerhub_credentials),
+        };
+
+        Client {
+            inner: Arc::new(inner),
+        }
+    }
+}
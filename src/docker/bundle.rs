@@ -0,0 +1,472 @@
+//! Fetching an image together with the artifacts attached to it, see
+//! [`Client::get_bundle`].
+
+use either::Either;
+
+use crate::{
+    attestation::Attestation,
+    docker::{
+        media_types::MediaType,
+        Client,
+        Error,
+        Response,
+    },
+    image::image_name::ImageName,
+    Digest,
+    Image,
+    ImageConfig,
+    Manifest,
+    Tag,
+};
+
+/// Annotation of a simple signing layer that holds the base64 encoded
+/// signature of the layer.
+pub(super) const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+
+/// Which parts of an image [`Client::get_bundle`] fetches. Nothing is
+/// requested by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "one toggle per part of the bundle"
+)]
+pub struct BundleRequest {
+    manifest: bool,
+    config: bool,
+    signatures: bool,
+    sboms: bool,
+    provenance: bool,
+}
+
+/// Everything [`Client::get_bundle`] found for an image. Parts that were not
+/// requested are `None`, parts that could not be fetched keep their error
+/// without failing the others.
+#[derive(Debug)]
+pub struct Bundle {
+    /// The digest the reference of the image resolved to. Signatures are
+    /// looked up for this digest.
+    pub digest: Digest,
+
+    /// The manifest of the image. It is fetched to resolve the digest
+    /// either way, so a failure fails the whole bundle instead.
+    pub manifest: Option<Response>,
+
+    /// The config of the image. Manifest lists and schema1 manifests have
+    /// none and result in [`Error::NotAnImageManifest`].
+    pub config: Option<Result<ImageConfig, Error>>,
+
+    /// The cosign signatures from the `sha256-<digest>.sig` tag, empty if the
+    /// image is not signed.
+    pub signatures: Option<Result<Vec<Signature>, Error>>,
+
+    /// The SPDX and `CycloneDX` attestations buildkit attached to the
+    /// manifest list.
+    pub sboms: Option<Result<Vec<Attestation>, Error>>,
+
+    /// The SLSA provenance attestations buildkit attached to the manifest
+    /// list.
+    pub provenance: Option<Result<Vec<Attestation>, Error>>,
+}
+
+/// A cosign signature of an image. Use [`Client::verify_cosign_signature`]
+/// to check it against a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// The digest of the simple signing payload that was signed.
+    pub digest: Digest,
+
+    /// The base64 encoded signature, `None` if the layer has no signature
+    /// annotation.
+    pub signature: Option<String>,
+
+    /// The simple signing payload.
+    pub payload: Vec<u8>,
+}
+
+impl BundleRequest {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests every part of the bundle.
+    #[must_use]
+    pub fn all() -> Self {
+        Self {
+            manifest: true,
+            config: true,
+            signatures: true,
+            sboms: true,
+            provenance: true,
+        }
+    }
+
+    #[must_use]
+    pub fn manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    #[must_use]
+    pub fn config(mut self, config: bool) -> Self {
+        self.config = config;
+        self
+    }
+
+    #[must_use]
+    pub fn signatures(mut self, signatures: bool) -> Self {
+        self.signatures = signatures;
+        self
+    }
+
+    #[must_use]
+    pub fn sboms(mut self, sboms: bool) -> Self {
+        self.sboms = sboms;
+        self
+    }
+
+    #[must_use]
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.provenance = provenance;
+        self
+    }
+}
+
+impl Client {
+    /// Fetches the parts of `image` selected by `wants` at once. The
+    /// manifest is fetched first to resolve the digest, the other parts are
+    /// then fetched concurrently and each keeps its own result.
+    ///
+    /// # Errors
+    /// Returns an error if the manifest can not be fetched or parsed, every
+    /// other part depends on it.
+    #[tracing::instrument(name = "get_bundle", skip_all, fields(image = %image))]
+    pub async fn get_bundle(&self, image: &Image, wants: BundleRequest) -> Result<Bundle, Error> {
+        let raw = self.get_manifest_raw(image).await?;
+
+        let digest = match &image.image_name.identifier {
+            Either::Right(digest) => digest.clone(),
+            Either::Left(_) => match &raw.digest {
+                Some(digest) => digest.parse().map_err(Error::ParseDockerContentDigest)?,
+                None => Digest::sha256(&raw.body),
+            },
+        };
+
+        let response = Self::response_from_raw(raw)?;
+        let manifest = &response.manifest;
+
+        let config = async {
+            if !wants.config {
+                return None;
+            }
+
+            Some(match manifest {
+                Manifest::Image(manifest) => self.get_blob_json(image, &manifest.config).await,
+                Manifest::List(_) | Manifest::Single(_) => {
+                    Err(Error::NotAnImageManifest(digest.clone()))
+                }
+            })
+        };
+
+        let signatures = async {
+            if !wants.signatures {
+                return None;
+            }
+
+            Some(self.get_signatures(image, &digest).await)
+        };
+
+        let sboms = async {
+            if !wants.sboms {
+                return None;
+            }
+
+            Some(self.manifest_attestations(image, manifest, is_sbom).await)
+        };
+
+        let provenance = async {
+            if !wants.provenance {
+                return None;
+            }
+
+            Some(
+                self.manifest_attestations(image, manifest, is_provenance)
+                    .await,
+            )
+        };
+
+        // Boxed to keep the future of the bundle small, each part holds the
+        // state of several requests.
+        let (config, signatures, sboms, provenance) = futures::join!(
+            Box::pin(config),
+            Box::pin(signatures),
+            Box::pin(sboms),
+            Box::pin(provenance)
+        );
+
+        Ok(Bundle {
+            digest,
+            manifest: wants.manifest.then_some(response),
+            config,
+            signatures,
+            sboms,
+            provenance,
+        })
+    }
+
+    /// Reads the cosign signatures of `digest` from the signature tag next
+    /// to `image`.
+    async fn get_signatures(
+        &self,
+        image: &Image,
+        digest: &Digest,
+    ) -> Result<Vec<Signature>, Error> {
+        let manifest = match self.get_manifest(&signature_image(image, digest)).await {
+            Ok(response) => response.manifest,
+            Err(e) if e.is_missing_manifest() => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let Manifest::Image(manifest) = manifest else {
+            return Ok(Vec::new());
+        };
+
+        let mut signatures = Vec::new();
+
+        for layer in manifest
+            .layers
+            .iter()
+            .filter(|layer| layer.media_type == MediaType::CosignSimpleSigning.as_str())
+        {
+            signatures.push(Signature {
+                digest: layer.digest.clone(),
+                signature: layer.annotations.get(SIGNATURE_ANNOTATION).cloned(),
+                payload: self.get_descriptor_bytes(image, layer).await?,
+            });
+        }
+
+        Ok(signatures)
+    }
+
+    /// Fetches the attestations of `manifest` that `wanted` accepts. Only
+    /// manifest lists have attestations.
+    async fn manifest_attestations(
+        &self,
+        image: &Image,
+        manifest: &Manifest,
+        wanted: fn(&str) -> bool,
+    ) -> Result<Vec<Attestation>, Error> {
+        match manifest {
+            Manifest::List(list) => self.list_attestations(image, list, wanted).await,
+            Manifest::Image(_) | Manifest::Single(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+/// The image of the tag cosign pushes the signatures of `digest` to,
+/// `<algorithm>-<hex>.sig` in the repository of `image`.
+pub(super) fn signature_image(image: &Image, digest: &Digest) -> Image {
+    Image {
+        image_name: ImageName::new(
+            image.image_name.name.clone(),
+            Either::Left(Tag::Specific(
+                format!(
+                    "{}.sig",
+                    digest.normalized().to_string().replacen(':', "-", 1)
+                )
+                .into(),
+            )),
+        ),
+        ..image.clone()
+    }
+}
+
+fn is_sbom(predicate_type: &str) -> bool {
+    predicate_type.starts_with("https://spdx.dev/")
+        || predicate_type.starts_with("https://cyclonedx.org/bom")
+}
+
+fn is_provenance(predicate_type: &str) -> bool {
+    predicate_type.starts_with("https://slsa.dev/provenance/")
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::BundleRequest;
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        ClientError,
+        Digest,
+    };
+
+    const BASE: &str = "https://registry.k8s.io/v2/app";
+    const CONFIG: &str =
+        r#"{"architecture":"amd64","os":"linux","rootfs":{"type":"layers","diff_ids":[]}}"#;
+    const PAYLOAD: &str = include_str!("../../resources/cosign/signed.json");
+    const SIGNATURE: &str = include_str!("../../resources/cosign/signed.sig");
+
+    fn manifest() -> String {
+        serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": CONFIG.len(),
+                "digest": Digest::sha256(CONFIG.as_bytes()).to_string()
+            },
+            "layers": []
+        })
+        .to_string()
+    }
+
+    /// Serves a signed image without attestations. If `signature` is set
+    /// the signature tag is answered with it instead.
+    fn transport(signature: Option<MockResponse>) -> MockTransport {
+        let manifest = manifest();
+        let digest = Digest::sha256(manifest.as_bytes());
+
+        let signature_manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": 233,
+                "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111"
+            },
+            "layers": [
+                {
+                    "mediaType": "application/vnd.dev.cosign.simplesigning.v1+json",
+                    "size": PAYLOAD.len(),
+                    "digest": Digest::sha256(PAYLOAD.as_bytes()).to_string(),
+                    "annotations": { "dev.cosignproject.cosign/signature": SIGNATURE }
+                }
+            ]
+        });
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", &digest.to_string())
+                    .body(manifest),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{}", Digest::sha256(CONFIG.as_bytes())),
+                MockResponse::new(StatusCode::OK).body(CONFIG),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{}", Digest::sha256(PAYLOAD.as_bytes())),
+                MockResponse::new(StatusCode::OK).body(PAYLOAD),
+            );
+
+        let signature_url = format!(
+            "{BASE}/manifests/{}.sig",
+            digest.to_string().replace(':', "-")
+        );
+
+        transport.with_response(
+            Method::GET,
+            &signature_url,
+            signature.unwrap_or_else(|| {
+                MockResponse::new(StatusCode::OK).body(signature_manifest.to_string())
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn signatures_without_sbom() {
+        let client = Client::builder().transport(transport(None)).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .get_bundle(&image, BundleRequest::all())
+            .await
+            .unwrap();
+
+        assert_eq!(got.digest, Digest::sha256(manifest().as_bytes()));
+        assert!(got.manifest.is_some());
+        assert_eq!(got.config.unwrap().unwrap().os.to_string(), "linux");
+
+        let signatures = got.signatures.unwrap().unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].signature.as_deref(), Some(SIGNATURE));
+        assert_eq!(signatures[0].payload, PAYLOAD.as_bytes());
+
+        assert!(got.sboms.unwrap().unwrap().is_empty());
+        assert!(got.provenance.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failures_are_kept_per_part() {
+        let client = Client::builder()
+            .transport(transport(Some(MockResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))))
+            .build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .get_bundle(&image, BundleRequest::all())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            got.signatures,
+            Some(Err(ClientError::FailedManifestRequest(_)))
+        ));
+        assert!(got.config.unwrap().is_ok());
+    }
+
+    /// Registries that answer a missing signature tag with the
+    /// `MANIFEST_UNKNOWN` code but without `404` still have no signatures.
+    #[tokio::test]
+    async fn unsigned_with_manifest_unknown() {
+        let client = Client::builder()
+            .transport(transport(Some(
+                MockResponse::new(StatusCode::BAD_REQUEST)
+                    .body(r#"{"errors": [{"code": "MANIFEST_UNKNOWN", "message": "unknown"}]}"#),
+            )))
+            .build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .get_bundle(&image, BundleRequest::new().signatures(true))
+            .await
+            .unwrap();
+
+        assert!(got.signatures.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_requested_parts() {
+        let transport = transport(None);
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .get_bundle(&image, BundleRequest::new().signatures(true))
+            .await
+            .unwrap();
+
+        assert!(got.manifest.is_none());
+        assert!(got.config.is_none());
+        assert!(got.sboms.is_none());
+        assert!(got.provenance.is_none());
+        assert_eq!(got.signatures.unwrap().unwrap().len(), 1);
+        assert_eq!(transport.requests().len(), 3);
+    }
+}
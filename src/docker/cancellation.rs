@@ -0,0 +1,170 @@
+use std::{
+    future::Future,
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    docker::Error,
+    Digest,
+};
+
+/// Stops a long running operation like
+/// [`crate::Client::push_oci_layout_with_cancellation`] when a token is
+/// cancelled or a deadline passed. Operations check it before every request
+/// and stop waiting for a request that is in flight.
+///
+/// The default never cancels.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    token: Option<CancellationToken>,
+    deadline: Option<Instant>,
+}
+
+/// Why an operation was stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Cancelled,
+    DeadlineExceeded,
+}
+
+/// The work an operation completed before it was stopped, returned with
+/// [`Error::Cancelled`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cancelled {
+    pub reason: Reason,
+
+    /// The digests of the blobs and manifests that were uploaded or the
+    /// layers that were downloaded completely, in the order they finished.
+    pub completed: Vec<Digest>,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::DeadlineExceeded => write!(f, "deadline exceeded"),
+        }
+    }
+}
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} after completing {} transfers",
+            self.reason,
+            self.completed.len()
+        )
+    }
+}
+
+impl Cancellation {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops the operation once `token` is cancelled.
+    #[must_use]
+    pub fn token(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Stops the operation once `deadline` passed.
+    #[must_use]
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stops the operation once `timeout` passed from now.
+    #[must_use]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Returns why the operation has to stop, if it has to.
+    pub(super) fn check(&self) -> Result<(), Reason> {
+        if self
+            .token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(Reason::Cancelled);
+        }
+
+        if self
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            return Err(Reason::DeadlineExceeded);
+        }
+
+        Ok(())
+    }
+
+    /// Runs `future` unless the operation has to stop before or while it
+    /// runs.
+    pub(super) async fn run<F: Future>(&self, future: F) -> Result<F::Output, Reason> {
+        self.check()?;
+
+        tokio::select! {
+            biased;
+
+            reason = self.stopped() => Err(reason),
+            output = future => Ok(output),
+        }
+    }
+
+    /// Resolves once the token is cancelled or the deadline passed.
+    async fn stopped(&self) -> Reason {
+        let cancelled = async {
+            match &self.token {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let expired = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            () = cancelled => Reason::Cancelled,
+            () = expired => Reason::DeadlineExceeded,
+        }
+    }
+}
+
+impl Error {
+    /// Records `completed` as the work done by an operation that was
+    /// stopped. Other errors are returned as they are.
+    pub(super) fn with_completed(self, completed: Vec<Digest>) -> Self {
+        match self {
+            Self::Cancelled(cancelled) => Self::Cancelled(Cancelled {
+                completed,
+                ..cancelled
+            }),
+
+            other => other,
+        }
+    }
+}
+
+impl Reason {
+    /// The error of an operation that was stopped before it completed any
+    /// work.
+    pub(super) fn error(self) -> Error {
+        Error::Cancelled(Cancelled {
+            reason: self,
+            completed: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,348 @@
+//! Tokens for the `WWW-Authenticate` challenges of registries the client has
+//! no built-in token endpoint for.
+
+use http::{
+    header::{
+        HeaderMap,
+        AUTHORIZATION,
+        WWW_AUTHENTICATE,
+    },
+    Method,
+    StatusCode,
+};
+
+use crate::{
+    docker::{
+        auth::{
+            self,
+            Challenge,
+            TokenParams,
+        },
+        rate_limit::authority,
+        read_body,
+        redirect,
+        token::Token,
+        transport::{
+            self,
+            Request,
+        },
+        Client,
+        Error,
+    },
+    Image,
+    Registry,
+};
+
+impl Client {
+    /// Fetches a token for `challenge`. The request always carries the
+    /// `service` of the challenge but a `scope` only if the challenge named
+    /// one, some token servers reject anonymous requests with a scope. The
+    /// [`crate::ClientBuilder::token_params`] of `registry` are applied on
+    /// top.
+    #[tracing::instrument(name = "token", skip_all, fields(registry = %registry))]
+    pub(super) async fn get_challenge_token(
+        &self,
+        registry: &Registry,
+        challenge: &Challenge,
+    ) -> Result<Token, Error> {
+        if self.inner.offline {
+            return Err(Error::Offline);
+        }
+
+        let default_params = TokenParams::default();
+        let params = self
+            .inner
+            .token_params
+            .get(registry)
+            .unwrap_or(&default_params);
+
+        let scopes: Vec<String> = challenge.scope.iter().cloned().collect();
+        let token_url = auth::TokenRequest::from_challenge(challenge, &scopes, params)
+            .url()
+            .map_err(Error::InvalidTokenUrl)?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(value) = params.authorization() {
+            headers.insert(
+                AUTHORIZATION,
+                value.map_err(Error::ParseAuthorizationHeader)?,
+            );
+        }
+
+        // The realm is only known now, so the headers configured for the
+        // registry are added to the hops that go to it here.
+        let realm = authority(&token_url);
+        let request = Request::new(Method::GET, token_url.clone()).headers(headers);
+        let response = redirect::follow(request, self.inner.max_redirects, |mut request| {
+            self.inner
+                .registry_headers
+                .apply_to_realm(registry, &realm, &mut request);
+
+            Box::pin(self.execute_hop(request))
+        })
+        .await
+        .map_err(Error::GetToken)?;
+
+        if !response.status.is_success() {
+            return Err(
+                Box::pin(self.failed_request(response, None, Error::FailedTokenRequest)).await,
+            );
+        }
+
+        let body = read_body(
+            response,
+            self.inner.max_manifest_size,
+            Error::ExtractTokenBody,
+        )
+        .await?;
+
+        serde_json::from_slice::<Token>(&body)
+            .map(|token| {
+                token
+                    .received(self.inner.token_clock_skew)
+                    .requested_from(&token_url)
+            })
+            .map_err(|e| Error::DeserializeToken(e, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Fetches a token for `challenge` and caches it for the repository of
+    /// `image`, later requests send it right away.
+    pub(super) async fn get_challenge_headers(
+        &self,
+        image: &Image,
+        challenge: &Challenge,
+    ) -> Result<HeaderMap, Error> {
+        let token = self.get_challenge_token(&image.registry, challenge).await?;
+
+        self.inner
+            .token_cache
+            .store(image.into(), token.clone())
+            .await
+            .map_err(Error::StoreToken)?;
+
+        token.try_into().map_err(Error::ParseAuthorizationHeader)
+    }
+}
+
+/// The bearer challenge of a `401` response, `None` for other responses.
+pub(super) fn of(response: &transport::Response) -> Option<Challenge> {
+    if response.status != StatusCode::UNAUTHORIZED {
+        return None;
+    }
+
+    parse(&response.headers)
+}
+
+pub(super) fn parse(headers: &HeaderMap) -> Option<Challenge> {
+    auth::parse_challenge(headers.get(WWW_AUTHENTICATE)?.to_str().ok()?)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use http::{
+        header::AUTHORIZATION,
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::{
+            auth::TokenParams,
+            transport::{
+                self,
+                MockResponse,
+                MockTransport,
+                Request,
+                Response,
+                Transport,
+            },
+        },
+        Client,
+        ClientError,
+        Image,
+    };
+
+    const MANIFEST: &str = include_str!("../../resources/manifest/image/example.json");
+    /// The URL and `Authorization` header of a token request.
+    type TokenRequest = (String, Option<String>);
+
+    /// A registry like older Harbor releases: manifests need a token and the
+    /// token server answers `400` to requests with a scope.
+    #[derive(Debug, Clone)]
+    struct Harbor {
+        challenge: &'static str,
+
+        /// The URLs and `Authorization` headers of the token requests.
+        token_requests: Arc<Mutex<Vec<TokenRequest>>>,
+    }
+
+    impl Harbor {
+        fn new(challenge: &'static str) -> Self {
+            Self {
+                challenge,
+                token_requests: Arc::default(),
+            }
+        }
+
+        fn token_requests(&self) -> Vec<TokenRequest> {
+            self.token_requests.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for Harbor {
+        async fn execute(&self, request: Request) -> Result<Response, transport::Error> {
+            let authorization = request
+                .headers
+                .get(AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_string());
+
+            let response = match request.url.path() {
+                "/service/token" => {
+                    self.token_requests
+                        .lock()
+                        .unwrap()
+                        .push((request.url.to_string(), authorization));
+
+                    if request.url.query_pairs().any(|(name, _)| name == "scope") {
+                        MockResponse::new(StatusCode::BAD_REQUEST).body(
+                            r#"{"errors":[{"code":"UNAUTHORIZED","message":"invalid scope"}]}"#,
+                        )
+                    } else {
+                        MockResponse::new(StatusCode::OK).body(r#"{"token":"harbor-token"}"#)
+                    }
+                }
+
+                "/v2/team/app/manifests/1.0"
+                    if authorization.as_deref() == Some("Bearer harbor-token") =>
+                {
+                    MockResponse::new(StatusCode::OK).body(MANIFEST)
+                }
+
+                "/v2/team/app/manifests/1.0" => MockResponse::new(StatusCode::UNAUTHORIZED)
+                    .header("WWW-Authenticate", self.challenge),
+
+                _ => return Err(transport::Error::Unmatched(request.url.to_string())),
+            };
+
+            MockTransport::new()
+                .with_response(Method::GET, request.url.as_str(), response)
+                .execute(request)
+                .await
+        }
+    }
+
+    fn image() -> Image {
+        "harbor.example.com/team/app:1.0".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn service_only() {
+        let transport = Harbor::new(
+            r#"Bearer realm="https://harbor.example.com/service/token",service="harbor-registry""#,
+        );
+        let client = Client::builder().transport(transport.clone()).build();
+
+        client.get_manifest(&image()).await.unwrap();
+        client.get_manifest(&image()).await.unwrap();
+
+        // The token is cached, the second fetch sends it right away.
+        assert_eq!(
+            transport.token_requests(),
+            vec![(
+                "https://harbor.example.com/service/token?service=harbor-registry".to_string(),
+                None
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn scope_rejected() {
+        const CHALLENGE: &str = r#"Bearer realm="https://harbor.example.com/service/token",service="harbor-registry",scope="repository:team/app:pull""#;
+
+        let transport = Harbor::new(CHALLENGE);
+        let client = Client::builder().transport(transport.clone()).build();
+
+        let got = client.get_manifest(&image()).await.unwrap_err();
+        assert!(
+            matches!(&got, ClientError::FailedTokenRequest(response) if response.status == StatusCode::BAD_REQUEST),
+            "{got:?}"
+        );
+        assert_eq!(
+            transport.token_requests()[0].0,
+            "https://harbor.example.com/service/token?scope=repository:team/app:pull&service=harbor-registry"
+        );
+
+        let transport = Harbor::new(CHALLENGE);
+        let client = Client::builder()
+            .transport(transport.clone())
+            .token_params("harbor.example.com", TokenParams::new().omit_scope(true))
+            .build();
+
+        client.get_manifest(&image()).await.unwrap();
+
+        assert_eq!(
+            transport.token_requests(),
+            vec![(
+                "https://harbor.example.com/service/token?service=harbor-registry".to_string(),
+                None
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials() {
+        let transport = Harbor::new(
+            r#"Bearer realm="https://harbor.example.com/service/token",service="harbor-registry""#,
+        );
+        let client = Client::builder()
+            .transport(transport.clone())
+            .token_params(
+                "harbor.example.com",
+                TokenParams::new().credentials("robot", "secret"),
+            )
+            .build();
+
+        client.get_manifest(&image()).await.unwrap();
+
+        assert_eq!(
+            transport.token_requests(),
+            vec![(
+                "https://harbor.example.com/service/token?service=harbor-registry&account=robot"
+                    .to_string(),
+                Some("Basic cm9ib3Q6c2VjcmV0".to_string())
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn built_in_registries_are_not_challenged() {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io",
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/v2/example/app/manifests/1.0",
+                MockResponse::new(StatusCode::UNAUTHORIZED).header(
+                    "WWW-Authenticate",
+                    r#"Bearer realm="https://ghcr.io/token""#,
+                ),
+            );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image: Image = "ghcr.io/example/app:1.0".parse().unwrap();
+
+        client.get_manifest(&image).await.unwrap_err();
+
+        assert_eq!(transport.requests().len(), 2);
+    }
+}
@@ -0,0 +1,510 @@
+//! Reads the registry configuration of containerd, see
+//! [`crate::ClientBuilder::load_containerd_hosts_dir`].
+
+use std::{
+    ffi::OsStr,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    docker::{
+        mirror::Mirror,
+        rate_limit::authority,
+        transport::{
+            HttpError,
+            ReqwestTransport,
+        },
+    },
+    Registry,
+};
+
+/// The file in the directory of a registry that configures it.
+const HOSTS_FILE: &str = "hosts.toml";
+
+/// The directory containerd uses for registries without one of their own.
+const DEFAULT_DIR: &str = "_default";
+
+#[derive(Debug)]
+pub enum Error {
+    ReadDir(PathBuf, std::io::Error),
+    ReadHosts(PathBuf, std::io::Error),
+    ParseHosts(PathBuf, toml::de::Error),
+    InvalidHostUrl {
+        path: PathBuf,
+        url: String,
+        source: url::ParseError,
+    },
+    ReadCa(PathBuf, std::io::Error),
+    ParseCa(PathBuf, HttpError),
+    BuildClient(String, HttpError),
+}
+
+/// A `hosts.toml` file. The settings at the top level apply to the server,
+/// the original registry.
+#[derive(Debug, Deserialize)]
+struct HostsFile {
+    server: Option<String>,
+
+    /// The mirrors keyed by their URL, in the order containerd tries them.
+    #[serde(default)]
+    host: toml::Table,
+
+    #[serde(flatten)]
+    server_options: HostOptions,
+}
+
+/// The settings of the server or of one of the `[host."<url>"]` tables.
+#[derive(Debug, Deserialize)]
+struct HostOptions {
+    capabilities: Option<Vec<String>>,
+    ca: Option<CaFiles>,
+
+    #[serde(default)]
+    skip_verify: bool,
+
+    #[serde(default)]
+    override_path: bool,
+
+    /// Settings like client certificates and headers that are not
+    /// supported.
+    #[serde(flatten)]
+    unsupported: toml::Table,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CaFiles {
+    One(PathBuf),
+    Many(Vec<PathBuf>),
+}
+
+/// The TLS settings of a host that differ from the default ones.
+#[derive(Debug, Clone)]
+pub(super) struct HostTls {
+    /// The host with the port if the URL names one.
+    pub(super) host: String,
+    pub(super) skip_verify: bool,
+    pub(super) ca_files: Vec<PathBuf>,
+    pub(super) client: reqwest::Client,
+}
+
+/// The configuration read from a hosts directory.
+#[derive(Debug, Default)]
+pub(super) struct Hosts {
+    /// The mirrors keyed by the host of the registry they mirror.
+    pub(super) mirrors: Vec<(String, Mirror)>,
+
+    /// The servers that are not the default API base of their registry.
+    pub(super) api_bases: Vec<(String, Url)>,
+
+    pub(super) tls: Vec<HostTls>,
+
+    /// The settings that were ignored, every one is logged as a warning.
+    pub(super) ignored: Vec<String>,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadDir(path, e) => {
+                write!(f, "failed to read hosts directory {}: {e}", path.display())
+            }
+            Self::ReadHosts(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            Self::ParseHosts(path, e) => write!(f, "failed to parse {}: {e}", path.display()),
+            Self::InvalidHostUrl { path, url, source } => {
+                write!(f, "invalid host {url} in {}: {source}", path.display())
+            }
+            Self::ReadCa(path, e) => {
+                write!(f, "failed to read CA certificate {}: {e}", path.display())
+            }
+            Self::ParseCa(path, e) => {
+                write!(f, "failed to parse CA certificate {}: {e}", path.display())
+            }
+            Self::BuildClient(host, e) => {
+                write!(f, "failed to build the HTTP client for {host}: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl CaFiles {
+    fn paths(&self) -> &[PathBuf] {
+        match self {
+            Self::One(path) => std::slice::from_ref(path),
+            Self::Many(paths) => paths,
+        }
+    }
+}
+
+/// Reads the `hosts.toml` file in every directory of `dir`. Directories are
+/// named after the host of the registry they configure, like
+/// `/etc/containerd/certs.d/docker.io`, and read in the order of their names.
+pub(super) fn load(dir: &Path) -> Result<Hosts, Error> {
+    let mut registry_dirs = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| Error::ReadDir(dir.to_path_buf(), e))?;
+
+    registry_dirs.sort();
+
+    let mut hosts = Hosts::default();
+
+    for registry_dir in registry_dirs {
+        let path = registry_dir.join(HOSTS_FILE);
+        if !path.is_file() {
+            continue;
+        }
+
+        let Some(registry_host) = registry_dir.file_name().and_then(OsStr::to_str) else {
+            continue;
+        };
+
+        if registry_host == DEFAULT_DIR {
+            hosts.ignore(format!(
+                "{DEFAULT_DIR}: a default for all registries is not supported"
+            ));
+            continue;
+        }
+
+        hosts.add(registry_host, &registry_dir, &path)?;
+    }
+
+    Ok(hosts)
+}
+
+impl Hosts {
+    fn add(&mut self, registry_host: &str, dir: &Path, path: &Path) -> Result<(), Error> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| Error::ReadHosts(path.to_path_buf(), e))?;
+
+        let file: HostsFile =
+            toml::from_str(&content).map_err(|e| Error::ParseHosts(path.to_path_buf(), e))?;
+
+        let registry = Registry::try_from_host(registry_host);
+        let default_api_base = registry
+            .api_base()
+            .map_err(|source| Error::InvalidHostUrl {
+                path: path.to_path_buf(),
+                url: registry_host.to_string(),
+                source,
+            })?;
+
+        let options = &file.server_options;
+        self.ignore_unsupported(registry_host, "the server", options);
+
+        if options.capabilities.is_some() {
+            self.ignore(format!(
+                "{registry_host}: the capabilities of the server are ignored, it is used for \
+                 every request"
+            ));
+        }
+
+        let server = match &file.server {
+            Some(server) => {
+                let url = host_url(path, server)?;
+
+                if let Some(api_base) = api_root(url, options.override_path) {
+                    api_base
+                } else {
+                    self.ignore(format!(
+                        "{registry_host}: the server {server} is ignored, override_path is only \
+                         supported for paths ending in /v2"
+                    ));
+                    default_api_base.clone()
+                }
+            }
+            None => default_api_base.clone(),
+        };
+
+        self.tls(dir, authority(&server), options)?;
+
+        if server != default_api_base {
+            self.api_bases.push((registry_host.to_string(), server));
+        }
+
+        for (url, options) in file.host {
+            let options: HostOptions = options
+                .try_into()
+                .map_err(|e| Error::ParseHosts(path.to_path_buf(), e))?;
+
+            self.ignore_unsupported(registry_host, &url, &options);
+
+            if let Some(capabilities) = &options.capabilities {
+                for capability in capabilities {
+                    if capability != "pull" && capability != "resolve" {
+                        self.ignore(format!(
+                            "{registry_host}: the {capability} capability of {url} is not \
+                             supported"
+                        ));
+                    }
+                }
+
+                if !["pull", "resolve"]
+                    .iter()
+                    .all(|needed| capabilities.iter().any(|capability| capability == needed))
+                {
+                    self.ignore(format!(
+                        "{registry_host}: {url} is skipped, mirrors are used to resolve tags and \
+                         to pull, so they need both capabilities"
+                    ));
+                    continue;
+                }
+            }
+
+            let Some(api_base) = api_root(host_url(path, &url)?, options.override_path) else {
+                self.ignore(format!(
+                    "{registry_host}: {url} is skipped, override_path is only supported for paths \
+                     ending in /v2"
+                ));
+                continue;
+            };
+
+            self.tls(dir, authority(&api_base), &options)?;
+            self.mirrors
+                .push((registry_host.to_string(), Mirror::new(api_base)));
+        }
+
+        Ok(())
+    }
+
+    /// Records the TLS settings of `host`. Relative CA files are relative to
+    /// the directory of the registry like in containerd.
+    fn tls(&mut self, dir: &Path, host: String, options: &HostOptions) -> Result<(), Error> {
+        let ca_files: Vec<PathBuf> = options
+            .ca
+            .iter()
+            .flat_map(CaFiles::paths)
+            .map(|path| dir.join(path))
+            .collect();
+
+        if !options.skip_verify && ca_files.is_empty() {
+            return Ok(());
+        }
+
+        let mut builder =
+            ReqwestTransport::client_builder().danger_accept_invalid_certs(options.skip_verify);
+
+        for path in &ca_files {
+            let pem = std::fs::read(path).map_err(|e| Error::ReadCa(path.clone(), e))?;
+
+            for certificate in reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| Error::ParseCa(path.clone(), e.into()))?
+            {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| Error::BuildClient(host.clone(), e.into()))?;
+
+        self.tls.push(HostTls {
+            host,
+            skip_verify: options.skip_verify,
+            ca_files,
+            client,
+        });
+
+        Ok(())
+    }
+
+    fn ignore_unsupported(&mut self, registry_host: &str, target: &str, options: &HostOptions) {
+        for key in options.unsupported.keys() {
+            self.ignore(format!(
+                "{registry_host}: the {key} setting of {target} is not supported"
+            ));
+        }
+    }
+
+    fn ignore(&mut self, message: String) {
+        tracing::warn!("ignoring containerd host configuration: {message}");
+        self.ignored.push(message);
+    }
+}
+
+/// Parses the URL of a host. Like containerd, hosts without a scheme use
+/// HTTPS.
+fn host_url(path: &Path, url: &str) -> Result<Url, Error> {
+    let parsed = if url.contains("://") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("https://{url}"))
+    };
+
+    parsed.map_err(|source| Error::InvalidHostUrl {
+        path: path.to_path_buf(),
+        url: url.to_string(),
+        source,
+    })
+}
+
+/// Returns the base URL the client appends `v2/...` to. containerd appends
+/// `/v2` to the path of a host unless it already ends with it, or the path
+/// is used as is with `override_path`. Paths that do not end with `/v2` can
+/// not be used with it as the client always adds the `v2` segment.
+fn api_root(mut url: Url, override_path: bool) -> Option<Url> {
+    let path = url.path().trim_end_matches('/');
+
+    let root = match path.strip_suffix("/v2") {
+        Some(root) => root.to_string(),
+        None if override_path => return None,
+        None => path.to_string(),
+    };
+
+    url.set_path(&format!("{root}/"));
+
+    Some(url)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::path::Path;
+
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        Image,
+    };
+
+    const MANIFEST: &str = include_str!("../../resources/manifest/image/example.json");
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/containerd")
+            .join(name)
+    }
+
+    fn urls(transport: &MockTransport) -> Vec<String> {
+        transport
+            .requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn docker_io_mirror() {
+        const MIRROR: &str = "https://mirror.internal:5000/v2/library/alpine/manifests/3.20";
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            MIRROR,
+            MockResponse::new(StatusCode::OK).body(MANIFEST),
+        );
+
+        let client = Client::builder()
+            .transport(transport.clone())
+            .load_containerd_hosts_dir(fixture("docker-mirror"))
+            .unwrap()
+            .build();
+
+        let image: Image = "alpine:3.20".parse().unwrap();
+        client.get_manifest(&image).await.unwrap();
+
+        assert_eq!(urls(&transport), [MIRROR]);
+    }
+
+    /// The mirror of the internal registry does not have the image, so the
+    /// server is asked next. The push-only host is not used for pulls.
+    #[tokio::test]
+    async fn skip_verify_registry() {
+        const CACHE: &str = "https://cache.internal/v2/team/app/manifests/1.0";
+        const SERVER: &str = "https://registry.internal:5000/v2/team/app/manifests/1.0";
+
+        let transport = MockTransport::new()
+            .with_response(Method::GET, CACHE, MockResponse::new(StatusCode::NOT_FOUND))
+            .with_response(
+                Method::GET,
+                SERVER,
+                MockResponse::new(StatusCode::OK).body(MANIFEST),
+            );
+
+        let client = Client::builder()
+            .transport(transport.clone())
+            .load_containerd_hosts_dir(fixture("internal"))
+            .unwrap()
+            .build();
+
+        let image: Image = "registry.internal:5000/team/app:1.0".parse().unwrap();
+        client.get_manifest(&image).await.unwrap();
+
+        assert_eq!(urls(&transport), [CACHE, SERVER]);
+    }
+
+    #[test]
+    fn tls_and_ignored_settings() {
+        let dir = fixture("internal");
+        let hosts = super::load(&dir).unwrap();
+
+        let tls: Vec<_> = hosts
+            .tls
+            .iter()
+            .map(|tls| (tls.host.as_str(), tls.skip_verify, tls.ca_files.clone()))
+            .collect();
+
+        assert_eq!(
+            tls,
+            [
+                ("registry.internal:5000", true, Vec::new()),
+                (
+                    "cache.internal",
+                    false,
+                    vec![dir.join("registry.internal:5000/cache-ca.pem")]
+                ),
+            ]
+        );
+
+        assert!(hosts.api_bases.is_empty());
+        assert_eq!(
+            hosts.ignored,
+            [
+                "_default: a default for all registries is not supported",
+                "registry.internal:5000: the push capability of https://cache.internal/v2 is not \
+                 supported",
+                "registry.internal:5000: the push capability of https://uploads.internal is not \
+                 supported",
+                "registry.internal:5000: https://uploads.internal is skipped, mirrors are used to \
+                 resolve tags and to pull, so they need both capabilities",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_hosts_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("ghcr.io")).unwrap();
+        std::fs::write(
+            dir.path().join("ghcr.io/hosts.toml"),
+            "[host.\"https://mirror.internal\"]\ncapabilities = \"pull\"\n",
+        )
+        .unwrap();
+
+        let got = super::load(dir.path()).unwrap_err();
+
+        assert!(
+            matches!(&got, super::Error::ParseHosts(path, _) if path.ends_with("ghcr.io/hosts.toml")),
+            "{got}"
+        );
+    }
+}
@@ -0,0 +1,645 @@
+use std::{
+    collections::HashSet,
+    path::{
+        Path,
+        PathBuf,
+    },
+    pin::Pin,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use either::Either;
+use futures::{
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::{
+    docker::{
+        missing_child::{
+            MissingChildPolicy,
+            SkippedChild,
+        },
+        Client,
+        Error,
+        RawResponse,
+    },
+    image::image_name::ImageName,
+    layout,
+    manifest::Entry,
+    Digest,
+    Image,
+    Manifest,
+};
+
+/// The content of a blob as a stream of chunks.
+pub type ContentStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// Numbers the partial files of [`FsContentStore`] so concurrent writes of
+/// the same blob do not share one.
+static PARTIAL_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// `ContentStore` stores blobs by their digest, see [`Client::fetch_into`].
+#[async_trait::async_trait]
+pub trait ContentStore: Send + Sync {
+    /// Returns true if the blob with `digest` is stored.
+    async fn has(&self, digest: &Digest) -> bool;
+
+    /// Stores `content` under `digest`. The stream ends with an error if the
+    /// content does not match the digest, the store must not keep the
+    /// content of a stream that ended with an error.
+    ///
+    /// # Errors
+    /// Returns an error if `content` ends with one or if the content can
+    /// not be stored.
+    async fn put(&self, digest: &Digest, content: ContentStream) -> Result<(), std::io::Error>;
+
+    /// Returns the content of the blob with `digest`, `None` if it is not
+    /// stored.
+    async fn get(&self, digest: &Digest) -> Option<ContentStream>;
+}
+
+/// A [`ContentStore`] in a directory. Blobs are stored at the same paths as
+/// in an OCI image layout, `blobs/sha256/<hex>`, so the store of a layout is
+/// its directory, see [`crate::layout::OciLayout::content_store`]. Only
+/// sha256 digests are supported.
+#[derive(Debug, Clone)]
+pub struct FsContentStore {
+    dir: PathBuf,
+}
+
+/// What [`Client::fetch_into`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchReport {
+    /// The digest of the manifest of the image.
+    pub digest: Digest,
+
+    /// Manifests and blobs that were fetched and stored, in the order they
+    /// were stored.
+    pub stored: Vec<Digest>,
+
+    /// Manifests and blobs the store already had. Blobs in this list were
+    /// not fetched.
+    pub already_present: Vec<Digest>,
+
+    /// The bytes of everything that was stored.
+    pub bytes_stored: u64,
+
+    /// Manifests of the index that were not fetched because the registry
+    /// does not have them, see [`FetchOptions::on_missing_child`].
+    pub skipped: Vec<SkippedChild>,
+}
+
+/// Options for [`Client::fetch_into_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchOptions {
+    on_missing_child: MissingChildPolicy,
+}
+
+impl FsContentStore {
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, digest: &Digest) -> Result<PathBuf, std::io::Error> {
+        layout::blob_path(&self.dir, digest)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    }
+}
+
+#[async_trait::async_trait]
+impl ContentStore for FsContentStore {
+    async fn has(&self, digest: &Digest) -> bool {
+        match self.path(digest) {
+            Ok(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    /// Writes the content to a partial file next to the blob and moves it
+    /// into place once the stream ended without an error.
+    async fn put(&self, digest: &Digest, mut content: ContentStream) -> Result<(), std::io::Error> {
+        let path = self.path(digest)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let partial = path.with_extension(format!(
+            "{}-{}.partial",
+            std::process::id(),
+            PARTIAL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = async {
+            let mut file = tokio::fs::File::create(&partial).await?;
+
+            while let Some(chunk) = content.try_next().await? {
+                file.write_all(&chunk).await?;
+            }
+
+            file.flush().await?;
+
+            tokio::fs::rename(&partial, &path).await
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+
+        result
+    }
+
+    async fn get(&self, digest: &Digest) -> Option<ContentStream> {
+        let file = tokio::fs::File::open(self.path(digest).ok()?).await.ok()?;
+
+        Some(Box::pin(tokio_util::io::ReaderStream::new(file)))
+    }
+}
+
+impl FetchOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What to do with manifests of an index the registry does not have.
+    /// With the default [`MissingChildPolicy::Fail`] the fetch fails, the
+    /// content stored until then is kept.
+    #[must_use]
+    pub fn on_missing_child(mut self, policy: MissingChildPolicy) -> Self {
+        self.on_missing_child = policy;
+        self
+    }
+}
+
+impl Client {
+    /// Fetches the manifest of `image` and every blob it references into
+    /// `store`. The manifest is stored as a blob under the sha256 digest of
+    /// its content. The manifests of an index are fetched for every
+    /// platform, together with their blobs.
+    ///
+    /// Blobs the store already has are not fetched, manifests are always
+    /// fetched to find their blobs but only stored if they are missing.
+    /// Blobs are verified against their digest while they stream into the
+    /// store.
+    ///
+    /// # Errors
+    /// Returns [`Error::StoreContent`] if a blob can not be stored or does
+    /// not match its digest.
+    /// Returns an error if a manifest or blob can not be fetched.
+    pub async fn fetch_into(
+        &self,
+        image: &Image,
+        store: &impl ContentStore,
+    ) -> Result<FetchReport, Error> {
+        self.fetch_into_with(image, store, &FetchOptions::default())
+            .await
+    }
+
+    /// Same as [`Client::fetch_into`] with `options`.
+    ///
+    /// # Errors
+    /// Returns [`Error::StoreContent`] if a blob can not be stored or does
+    /// not match its digest.
+    /// Returns an error if a manifest or blob can not be fetched, unless it
+    /// is a missing manifest of an index that `options` skips.
+    #[tracing::instrument(
+        name = "fetch_into",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            reference = %image.reference(),
+        )
+    )]
+    pub async fn fetch_into_with(
+        &self,
+        image: &Image,
+        store: &impl ContentStore,
+        options: &FetchOptions,
+    ) -> Result<FetchReport, Error> {
+        let raw = self.get_manifest_raw(image).await?;
+
+        let mut report = FetchReport {
+            digest: Digest::sha256(&raw.body),
+            stored: Vec::new(),
+            already_present: Vec::new(),
+            bytes_stored: 0,
+            skipped: Vec::new(),
+        };
+
+        // The manifests of an index are fetched when they are taken from the
+        // stack, together with the entry of the index that references them.
+        let mut pending: Vec<(Image, Either<RawResponse, Entry>)> =
+            vec![(image.clone(), Either::Left(raw))];
+        let mut seen = HashSet::new();
+
+        while let Some((image, raw)) = pending.pop() {
+            let raw = match raw {
+                Either::Left(raw) => raw,
+                Either::Right(entry) => match self.get_manifest_raw(&image).await {
+                    Ok(raw) => raw,
+                    Err(e) => {
+                        let skipped = options.on_missing_child.skip(&entry, &e).ok_or(e)?;
+                        report.skipped.push(skipped);
+                        continue;
+                    }
+                },
+            };
+
+            let body = raw.body.clone();
+            let digest = Digest::sha256(&body);
+
+            if !seen.insert(digest.clone()) {
+                continue;
+            }
+
+            let manifest = Self::response_from_raw(raw)?.manifest;
+
+            if store.has(&digest).await {
+                report.already_present.push(digest);
+            } else {
+                let size = body.len() as u64;
+                let content = futures::stream::once(async { Ok(body) }).boxed();
+
+                store
+                    .put(&digest, content)
+                    .await
+                    .map_err(|e| Error::StoreContent(digest.clone(), e))?;
+
+                report.stored.push(digest);
+                report.bytes_stored += size;
+            }
+
+            let blobs: Vec<Digest> = match manifest {
+                Manifest::Image(manifest) => std::iter::once(manifest.config.digest)
+                    .chain(manifest.layers.into_iter().map(|layer| layer.digest))
+                    .collect(),
+
+                Manifest::Single(single) => single
+                    .fs_layers
+                    .into_iter()
+                    .map(|layer| layer.blob_sum.parse().unwrap_or_else(|e| match e {}))
+                    .collect(),
+
+                Manifest::List(list) => {
+                    pending.extend(list.manifests.into_iter().rev().map(|entry| {
+                        let child = Image {
+                            image_name: ImageName::new(
+                                image.image_name.name.clone(),
+                                Either::Right(entry.digest.clone()),
+                            ),
+                            ..image.clone()
+                        };
+
+                        (child, Either::Right(entry))
+                    }));
+
+                    Vec::new()
+                }
+            };
+
+            for digest in blobs {
+                self.fetch_blob_into(&image, store, digest, &mut report)
+                    .await?;
+            }
+        }
+
+        info!(
+            stored = report.stored.len(),
+            already_present = report.already_present.len(),
+            bytes_stored = report.bytes_stored,
+            "fetched image into content store"
+        );
+
+        Ok(report)
+    }
+
+    async fn fetch_blob_into(
+        &self,
+        image: &Image,
+        store: &impl ContentStore,
+        digest: Digest,
+        report: &mut FetchReport,
+    ) -> Result<(), Error> {
+        if store.has(&digest).await {
+            report.already_present.push(digest);
+            return Ok(());
+        }
+
+        let size = Arc::new(AtomicU64::new(0));
+        let counted = Arc::clone(&size);
+
+        let blob = self.get_blob(image, &digest).await?;
+        let content = blob
+            .map_err(std::io::Error::from)
+            .inspect_ok(move |chunk| {
+                counted.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            })
+            .boxed();
+
+        store
+            .put(&digest, content)
+            .await
+            .map_err(|e| Error::StoreContent(digest.clone(), e))?;
+
+        report.stored.push(digest);
+        report.bytes_stored += size.load(Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use futures::TryStreamExt;
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        ContentStore,
+        FetchOptions,
+        FsContentStore,
+    };
+    use crate::{
+        docker::{
+            missing_child::{
+                MissingChildPolicy,
+                SkippedChild,
+            },
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        manifest::{
+            Architecture,
+            OperatingSystem,
+            Platform,
+        },
+        Client,
+        ClientError,
+        Digest,
+        Image,
+    };
+
+    const BASE: &str = "https://registry.k8s.io/v2/app";
+    const CONFIG: &[u8] = br#"{"architecture":"amd64","os":"linux"}"#;
+    const LAYERS: [&[u8]; 2] = [b"first layer", b"second layer"];
+
+    /// An image manifest with the second layer listed twice.
+    fn manifest() -> String {
+        let layers: Vec<_> = [LAYERS[0], LAYERS[1], LAYERS[1]]
+            .iter()
+            .map(|layer| {
+                serde_json::json!({
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar",
+                    "size": layer.len(),
+                    "digest": Digest::sha256(layer),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": CONFIG.len(),
+                "digest": Digest::sha256(CONFIG),
+            },
+            "layers": layers,
+        })
+        .to_string()
+    }
+
+    fn transport(config: &'static [u8]) -> MockTransport {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(StatusCode::OK).body(manifest()),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{}", Digest::sha256(CONFIG)),
+                MockResponse::new(StatusCode::OK).body(config),
+            );
+
+        LAYERS.iter().fold(transport, |transport, layer| {
+            transport.with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{}", Digest::sha256(layer)),
+                MockResponse::new(StatusCode::OK).body(*layer),
+            )
+        })
+    }
+
+    async fn read(store: &FsContentStore, digest: &Digest) -> Vec<u8> {
+        let chunks: Vec<bytes::Bytes> = store
+            .get(digest)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        chunks.concat()
+    }
+
+    #[tokio::test]
+    async fn fetch_into_and_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsContentStore::new(dir.path());
+
+        let transport = transport(CONFIG);
+        let client = Client::builder().transport(transport.clone()).build();
+        let image: Image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let body = manifest();
+        let manifest = Digest::sha256(body.as_bytes());
+        let blobs = [
+            Digest::sha256(CONFIG),
+            Digest::sha256(LAYERS[0]),
+            Digest::sha256(LAYERS[1]),
+        ];
+
+        let first = client.fetch_into(&image, &store).await.unwrap();
+
+        assert_eq!(first.digest, manifest);
+        assert_eq!(
+            first.stored,
+            [
+                manifest.clone(),
+                blobs[0].clone(),
+                blobs[1].clone(),
+                blobs[2].clone()
+            ]
+        );
+        assert_eq!(
+            first.already_present,
+            [blobs[2].clone()],
+            "the repeated layer is only fetched once"
+        );
+        assert_eq!(read(&store, &blobs[1]).await, LAYERS[0]);
+        assert_eq!(read(&store, &manifest).await, body.as_bytes());
+        assert_eq!(transport.requests().len(), 4);
+
+        let second = client.fetch_into(&image, &store).await.unwrap();
+
+        assert!(second.stored.is_empty());
+        assert_eq!(second.bytes_stored, 0);
+        assert_eq!(second.already_present.len(), 5);
+        assert_eq!(
+            transport.requests().len(),
+            5,
+            "only the manifest is fetched again"
+        );
+    }
+
+    #[tokio::test]
+    async fn digest_mismatch_is_not_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsContentStore::new(dir.path());
+
+        let client = Client::builder()
+            .transport(transport(b"tampered config"))
+            .build();
+        let image: Image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client.fetch_into(&image, &store).await.unwrap_err();
+
+        let config = Digest::sha256(CONFIG);
+        assert!(
+            matches!(&got, ClientError::StoreContent(digest, _) if *digest == config),
+            "{got}"
+        );
+        assert!(!store.has(&config).await);
+
+        let files = std::fs::read_dir(dir.path().join("blobs/sha256"))
+            .unwrap()
+            .count();
+        assert_eq!(files, 1, "only the manifest is stored, no partial files");
+    }
+
+    /// An index at `2.0` with the image of [`manifest`] and a second
+    /// platform whose manifest was garbage collected.
+    fn dead_child() -> (MockTransport, Digest) {
+        let image = manifest();
+        let dead = Digest::sha256(b"garbage collected");
+
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": image.len(),
+                    "digest": Digest::sha256(image.as_bytes()),
+                    "platform": { "architecture": "amd64", "os": "linux" },
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": 17,
+                    "digest": dead,
+                    "platform": { "architecture": "arm64", "os": "linux" },
+                },
+            ],
+        })
+        .to_string();
+
+        let transport = transport(CONFIG)
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/2.0"),
+                MockResponse::new(StatusCode::OK).body(index),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{}", Digest::sha256(image.as_bytes())),
+                MockResponse::new(StatusCode::OK).body(image),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{dead}"),
+                MockResponse::new(StatusCode::NOT_FOUND).body(
+                    r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown"}]}"#,
+                ),
+            );
+
+        (transport, dead)
+    }
+
+    #[tokio::test]
+    async fn missing_child_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsContentStore::new(dir.path());
+
+        let (transport, _) = dead_child();
+        let client = Client::builder().transport(transport).build();
+        let image: Image = "registry.k8s.io/app:2.0".parse().unwrap();
+
+        let got = client.fetch_into(&image, &store).await.unwrap_err();
+
+        assert!(matches!(got, ClientError::ManifestNotFound(_)), "{got}");
+    }
+
+    #[tokio::test]
+    async fn missing_child_skipped() {
+        for policy in [
+            MissingChildPolicy::Skip,
+            MissingChildPolicy::SkipWithWarning,
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let store = FsContentStore::new(dir.path());
+
+            let (transport, dead) = dead_child();
+            let client = Client::builder().transport(transport).build();
+            let image: Image = "registry.k8s.io/app:2.0".parse().unwrap();
+
+            let got = client
+                .fetch_into_with(
+                    &image,
+                    &store,
+                    &FetchOptions::new().on_missing_child(policy),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(
+                got.skipped,
+                [SkippedChild {
+                    digest: dead,
+                    platform: Some(Platform::new(OperatingSystem::Linux, Architecture::Arm64)),
+                }],
+                "{policy:?}"
+            );
+
+            // The index, the image manifest, its config and two layers.
+            assert_eq!(got.stored.len(), 5);
+            assert!(store.has(&Digest::sha256(CONFIG)).await);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use base64::Engine as _;
+use either::Either;
+use p256::{
+    ecdsa::{
+        signature::Verifier as _,
+        DerSignature,
+        VerifyingKey,
+    },
+    pkcs8::DecodePublicKey as _,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    docker::{
+        bundle::{
+            signature_image,
+            SIGNATURE_ANNOTATION,
+        },
+        media_types::MediaType,
+        Client,
+        Error,
+    },
+    Digest,
+    Image,
+    Manifest,
+};
+
+/// The result of [`Client::verify_cosign_signature`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerificationReport {
+    /// The digest the reference of the image resolved to. This is what the
+    /// signatures have to be for.
+    pub digest: Digest,
+
+    /// One entry per signature found for the image. Empty if the image is
+    /// not signed.
+    pub signatures: Vec<SignatureCheck>,
+}
+
+/// The outcome of checking a single signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SignatureCheck {
+    /// The digest of the simple signing payload that was signed.
+    pub payload: Digest,
+
+    pub result: Result<(), Failure>,
+}
+
+/// Why a signature was not accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Failure {
+    MissingSignature,
+    InvalidSignatureEncoding,
+    InvalidPayload(String),
+    DigestMismatch { expected: Digest, signed: Digest },
+    InvalidSignature,
+}
+
+/// The simple signing payload cosign signs, see
+/// <https://github.com/containers/image/blob/main/docs/containers-signature.5.md>.
+#[derive(Debug, Deserialize)]
+struct Payload {
+    critical: Critical,
+}
+
+#[derive(Debug, Deserialize)]
+struct Critical {
+    image: PayloadImage,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayloadImage {
+    #[serde(rename = "docker-manifest-digest")]
+    docker_manifest_digest: Digest,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingSignature => write!(f, "layer has no signature annotation"),
+            Self::InvalidSignatureEncoding => write!(f, "signature is not base64 encoded DER"),
+            Self::InvalidPayload(e) => write!(f, "payload is not a simple signing payload: {e}"),
+            Self::DigestMismatch { expected, signed } => {
+                write!(f, "payload signs {signed} instead of {expected}")
+            }
+            Self::InvalidSignature => write!(f, "signature does not match the public key"),
+        }
+    }
+}
+
+impl VerificationReport {
+    /// Returns true if at least one signature was verified.
+    #[must_use]
+    pub fn is_verified(&self) -> bool {
+        self.signatures
+            .iter()
+            .any(|signature| signature.result.is_ok())
+    }
+}
+
+impl Client {
+    /// Verifies the cosign signatures of `image` with the ECDSA P-256 public
+    /// key in `public_key_pem`, the key `cosign generate-key-pair` creates.
+    /// Keyless signatures are not supported.
+    ///
+    /// The signatures are read from the `sha256-<digest>.sig` tag cosign
+    /// pushes next to the image. Each one passes if its payload is for the
+    /// digest the reference of `image` currently resolves to and the
+    /// signature over the payload matches the key. An image without
+    /// signatures results in an empty report.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidCosignPublicKey`] if the key can not be parsed.
+    /// Returns an error if the manifest of the image or the signatures can
+    /// not be fetched.
+    #[tracing::instrument(name = "verify_cosign_signature", skip_all, fields(image = %image))]
+    pub async fn verify_cosign_signature(
+        &self,
+        image: &Image,
+        public_key_pem: &str,
+    ) -> Result<VerificationReport, Error> {
+        let key = VerifyingKey::from_public_key_pem(public_key_pem)
+            .map_err(Error::InvalidCosignPublicKey)?;
+
+        let digest = match &image.image_name.identifier {
+            Either::Right(digest) => digest.clone(),
+            Either::Left(_) => {
+                let raw = self.get_manifest_raw(image).await?;
+
+                match raw.digest {
+                    Some(digest) => digest.parse().map_err(Error::ParseDockerContentDigest)?,
+                    None => Digest::sha256(&raw.body),
+                }
+            }
+        };
+
+        let manifest = match self.get_manifest(&signature_image(image, &digest)).await {
+            Ok(response) => response.manifest,
+            Err(Error::ManifestNotFound(_)) => {
+                return Ok(VerificationReport {
+                    digest,
+                    signatures: Vec::new(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+
+        let Manifest::Image(manifest) = manifest else {
+            return Ok(VerificationReport {
+                digest,
+                signatures: Vec::new(),
+            });
+        };
+
+        let mut signatures = Vec::new();
+
+        for layer in manifest
+            .layers
+            .iter()
+            .filter(|layer| layer.media_type == MediaType::CosignSimpleSigning.as_str())
+        {
+            let payload_digest = layer.digest.clone();
+
+            let result = match layer.annotations.get(SIGNATURE_ANNOTATION) {
+                Some(signature) => {
+                    let payload = self.get_descriptor_bytes(image, layer).await?;
+
+                    check_signature(&key, &digest, signature, &payload)
+                }
+
+                None => Err(Failure::MissingSignature),
+            };
+
+            signatures.push(SignatureCheck {
+                payload: payload_digest,
+                result,
+            });
+        }
+
+        Ok(VerificationReport { digest, signatures })
+    }
+}
+
+/// Checks that `payload` is for `expected` and that `signature`, the base64
+/// encoded DER signature of a layer, signs it with `key`.
+fn check_signature(
+    key: &VerifyingKey,
+    expected: &Digest,
+    signature: &str,
+    payload: &[u8],
+) -> Result<(), Failure> {
+    let signed: Payload =
+        serde_json::from_slice(payload).map_err(|e| Failure::InvalidPayload(e.to_string()))?;
+
+    let signed = signed.critical.image.docker_manifest_digest;
+
+    if !signed.is_equivalent(expected) {
+        return Err(Failure::DigestMismatch {
+            expected: expected.clone(),
+            signed,
+        });
+    }
+
+    let signature = base64::engine::general_purpose::STANDARD
+        .decode(signature)
+        .ok()
+        .and_then(|der| DerSignature::from_bytes(&der).ok())
+        .ok_or(Failure::InvalidSignatureEncoding)?;
+
+    key.verify(payload, &signature)
+        .map_err(|_| Failure::InvalidSignature)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::Failure;
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        ClientError,
+        Digest,
+    };
+
+    const BASE: &str = "https://registry.k8s.io/v2/app";
+    const MANIFEST: &str = include_str!("../../resources/manifest/image/example.json");
+    const PUBLIC_KEY: &str = include_str!("../../resources/cosign/cosign.pub");
+    const OTHER_KEY: &str = include_str!("../../resources/cosign/other.pub");
+
+    /// Payloads and their signatures, generated once with `openssl dgst
+    /// -sha256 -sign` which produces the same DER signatures cosign does.
+    const SIGNED: (&str, &str) = (
+        include_str!("../../resources/cosign/signed.json"),
+        include_str!("../../resources/cosign/signed.sig"),
+    );
+    const OTHER_DIGEST: (&str, &str) = (
+        include_str!("../../resources/cosign/other-digest.json"),
+        include_str!("../../resources/cosign/other-digest.sig"),
+    );
+
+    fn transport(payloads: &[(&str, &str)]) -> MockTransport {
+        let digest = Digest::sha256(MANIFEST.as_bytes());
+
+        let layers: Vec<_> = payloads
+            .iter()
+            .map(|(payload, signature)| {
+                serde_json::json!({
+                    "mediaType": "application/vnd.dev.cosign.simplesigning.v1+json",
+                    "size": payload.len(),
+                    "digest": Digest::sha256(payload.as_bytes()).to_string(),
+                    "annotations": { "dev.cosignproject.cosign/signature": signature }
+                })
+            })
+            .collect();
+
+        let signature_manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": 233,
+                "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111"
+            },
+            "layers": layers
+        });
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", &digest.to_string())
+                    .body(MANIFEST),
+            )
+            .with_response(
+                Method::GET,
+                &format!(
+                    "{BASE}/manifests/{}.sig",
+                    digest.to_string().replace(':', "-")
+                ),
+                MockResponse::new(StatusCode::OK).body(signature_manifest.to_string()),
+            );
+
+        payloads.iter().fold(transport, |transport, (payload, _)| {
+            transport.with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{}", Digest::sha256(payload.as_bytes())),
+                MockResponse::new(StatusCode::OK).body(payload.to_string()),
+            )
+        })
+    }
+
+    #[tokio::test]
+    async fn valid_signature() {
+        let client = Client::builder().transport(transport(&[SIGNED])).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .verify_cosign_signature(&image, PUBLIC_KEY)
+            .await
+            .unwrap();
+
+        assert!(got.is_verified());
+        assert_eq!(got.digest, Digest::sha256(MANIFEST.as_bytes()));
+        assert_eq!(got.signatures.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_per_signature() {
+        let client = Client::builder()
+            .transport(transport(&[
+                OTHER_DIGEST,
+                (SIGNED.0, OTHER_DIGEST.1),
+                SIGNED,
+            ]))
+            .build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .verify_cosign_signature(&image, PUBLIC_KEY)
+            .await
+            .unwrap();
+
+        let results: Vec<_> = got
+            .signatures
+            .into_iter()
+            .map(|signature| signature.result)
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Err(Failure::DigestMismatch {
+                    expected: Digest::sha256(MANIFEST.as_bytes()),
+                    signed:
+                        "sha256:0000000000000000000000000000000000000000000000000000000000000000"
+                            .parse()
+                            .unwrap(),
+                }),
+                Err(Failure::InvalidSignature),
+                Ok(()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_key() {
+        let client = Client::builder().transport(transport(&[SIGNED])).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .verify_cosign_signature(&image, OTHER_KEY)
+            .await
+            .unwrap();
+
+        assert!(!got.is_verified());
+        assert_eq!(got.signatures[0].result, Err(Failure::InvalidSignature));
+    }
+
+    #[tokio::test]
+    async fn unsigned_image() {
+        let digest = Digest::sha256(MANIFEST.as_bytes());
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", &digest.to_string())
+                    .body(MANIFEST),
+            )
+            .with_response(
+                Method::GET,
+                &format!(
+                    "{BASE}/manifests/{}.sig",
+                    digest.to_string().replace(':', "-")
+                ),
+                MockResponse::new(StatusCode::NOT_FOUND),
+            );
+        let client = Client::builder().transport(transport).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .verify_cosign_signature(&image, PUBLIC_KEY)
+            .await
+            .unwrap();
+
+        assert!(got.signatures.is_empty());
+        assert!(!got.is_verified());
+    }
+
+    #[tokio::test]
+    async fn invalid_key() {
+        let client = Client::builder().transport(MockTransport::new()).build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .verify_cosign_signature(&image, "not a key")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::InvalidCosignPublicKey(_)));
+    }
+}
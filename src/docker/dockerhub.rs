@@ -0,0 +1,399 @@
+use std::sync::Arc;
+
+use chrono::{
+    DateTime,
+    Utc,
+};
+use http::{
+    header::{
+        HeaderMap,
+        HeaderValue,
+        AUTHORIZATION,
+        CONTENT_TYPE,
+    },
+    Method,
+};
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::{
+    docker::{
+        read_body,
+        token::Token,
+        transport::Request,
+        Client,
+        Error,
+        FailedResponse,
+    },
+    image::append_segments,
+    Digest,
+    Image,
+    Registry,
+};
+
+/// Base URL of the Docker Hub API. This is a different API than the
+/// distribution API served at [`Registry::api_host`].
+const HUB_API: &str = "https://hub.docker.com/";
+
+/// Number of tags requested per page, the maximum Docker Hub allows.
+const PAGE_SIZE: &str = "100";
+
+/// A repository on Docker Hub as returned by the Hub API.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Repository {
+    pub namespace: String,
+    pub name: String,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub full_description: Option<String>,
+
+    pub star_count: u64,
+    pub pull_count: u64,
+
+    #[serde(default)]
+    pub last_updated: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub is_private: bool,
+}
+
+/// A tag of a repository on Docker Hub as returned by the Hub API.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Tag {
+    pub name: String,
+
+    #[serde(default)]
+    pub digest: Option<Digest>,
+
+    #[serde(default)]
+    pub full_size: Option<u64>,
+
+    #[serde(default)]
+    pub last_updated: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub tag_last_pushed: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub tag_last_pulled: Option<DateTime<Utc>>,
+}
+
+/// Credentials to log in to the Hub API, required for private repositories.
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// The Hub API state of a client. The JWT is fetched once on first use and
+/// shared by all clones of the client.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Hub {
+    credentials: Option<Credentials>,
+    jwt: Arc<OnceCell<HeaderValue>>,
+}
+
+/// A page of a Hub API listing.
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    #[serde(default)]
+    next: Option<Url>,
+    results: Vec<T>,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Hub {
+    pub(super) fn new(credentials: Option<Credentials>) -> Self {
+        Self {
+            credentials,
+            jwt: Arc::default(),
+        }
+    }
+}
+
+impl Client {
+    /// Fetches the Docker Hub metadata of the repository of `image` like its
+    /// description and star and pull counts.
+    ///
+    /// # Errors
+    /// Returns [`Error::UnsupportedByRegistry`] if the image is not on Docker
+    /// Hub.
+    /// Returns an error if the client is offline, the login fails or the
+    /// request fails.
+    #[tracing::instrument(name = "dockerhub_repository", skip_all, fields(image = %image))]
+    pub async fn dockerhub_repository(&self, image: &Image) -> Result<Repository, Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        let url = hub_url(image, &[])?;
+
+        self.get_hub(url).await
+    }
+
+    /// Fetches all tags of the repository of `image` from the Docker Hub API,
+    /// following its pagination. Unlike [`Client::list_tags`] the tags
+    /// include when they were last pushed and pulled.
+    ///
+    /// # Errors
+    /// Returns [`Error::UnsupportedByRegistry`] if the image is not on Docker
+    /// Hub.
+    /// Returns an error if the client is offline, the login fails or a
+    /// request fails.
+    #[tracing::instrument(name = "dockerhub_tags", skip_all, fields(image = %image))]
+    pub async fn dockerhub_tags(&self, image: &Image) -> Result<Vec<Tag>, Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        let mut url = hub_url(image, &["tags"])?;
+        url.query_pairs_mut().append_pair("page_size", PAGE_SIZE);
+
+        let mut tags = Vec::new();
+        let mut next = Some(url);
+
+        while let Some(url) = next {
+            let page: Page<Tag> = self.get_hub(url).await?;
+
+            tags.extend(page.results);
+            next = page.next;
+        }
+
+        Ok(tags)
+    }
+
+    async fn get_hub<T: DeserializeOwned>(&self, url: Url) -> Result<T, Error> {
+        if self.inner.offline {
+            return Err(Error::Offline);
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(jwt) = self.hub_jwt().await? {
+            headers.insert(AUTHORIZATION, jwt);
+        }
+
+        let response = self
+            .execute(Request::new(Method::GET, url).headers(headers))
+            .await
+            .map_err(Error::GetHub)?;
+
+        let status = response.status;
+
+        if !status.is_success() {
+            return Err(Error::FailedHubRequest(
+                FailedResponse::read(response, self.inner.max_manifest_size).await,
+            ));
+        }
+
+        let body = read_body(response, self.inner.max_tag_list_size, Error::GetHub).await?;
+
+        serde_json::from_slice(&body).map_err(Error::DeserializeHub)
+    }
+
+    /// Logs in to the Hub API if credentials are configured.
+    async fn hub_jwt(&self) -> Result<Option<HeaderValue>, Error> {
+        let Some(credentials) = &self.inner.dockerhub.credentials else {
+            return Ok(None);
+        };
+
+        let jwt = self
+            .inner
+            .dockerhub
+            .jwt
+            .get_or_try_init(|| self.hub_login(credentials))
+            .await?;
+
+        Ok(Some(jwt.clone()))
+    }
+
+    #[tracing::instrument(name = "dockerhub_login", skip_all)]
+    async fn hub_login(&self, credentials: &Credentials) -> Result<HeaderValue, Error> {
+        let url = append_segments(
+            Url::parse(HUB_API).map_err(Error::InvalidHubUrl)?,
+            &["v2".to_string(), "users".to_string(), "login".to_string()],
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let mut request = Request::new(Method::POST, url).headers(headers);
+        request.body = Some(
+            serde_json::json!({
+                "username": credentials.username,
+                "password": credentials.password,
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = self.execute(request).await.map_err(Error::GetHub)?;
+        let status = response.status;
+
+        if !status.is_success() {
+            return Err(Error::FailedHubRequest(
+                FailedResponse::read(response, self.inner.max_manifest_size).await,
+            ));
+        }
+
+        let body = read_body(response, self.inner.max_manifest_size, Error::GetHub).await?;
+
+        let token: Token = serde_json::from_slice(&body)
+            .map_err(|e| Error::DeserializeToken(e, String::from_utf8_lossy(&body).into_owned()))?;
+
+        let headers: HeaderMap = token.try_into().map_err(Error::ParseAuthorizationHeader)?;
+
+        headers
+            .get(AUTHORIZATION)
+            .cloned()
+            .ok_or(Error::MissingHubToken)
+    }
+}
+
+/// Returns the Hub API URL of the repository of `image` with `extra`
+/// segments appended. Hub URLs end with a slash.
+fn hub_url(image: &Image, extra: &[&str]) -> Result<Url, Error> {
+    if image.registry != Registry::DockerHub {
+        return Err(Error::UnsupportedByRegistry(image.registry.clone()));
+    }
+
+    let mut segments = vec!["v2".to_string(), "repositories".to_string()];
+    segments.extend(image.repository_segments().map_err(Error::InvalidPath)?);
+    segments.extend(extra.iter().map(ToString::to_string));
+    segments.push(String::new());
+
+    Ok(append_segments(
+        Url::parse(HUB_API).map_err(Error::InvalidHubUrl)?,
+        &segments,
+    ))
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        ClientError,
+        Image,
+        Registry,
+    };
+
+    const REPOSITORY_URL: &str = "https://hub.docker.com/v2/repositories/library/alpine/";
+    const FIRST_PAGE: &str =
+        "https://hub.docker.com/v2/repositories/library/alpine/tags/?page_size=100";
+    const SECOND_PAGE: &str =
+        "https://hub.docker.com/v2/repositories/library/alpine/tags/?page=2&page_size=2";
+
+    fn image() -> Image {
+        "alpine:3.20".parse().unwrap()
+    }
+
+    fn transport() -> MockTransport {
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                REPOSITORY_URL,
+                MockResponse::new(StatusCode::OK).body(include_str!(
+                    "../../resources/registry/dockerhub/hub-repository.json"
+                )),
+            )
+            .with_response(
+                Method::GET,
+                FIRST_PAGE,
+                MockResponse::new(StatusCode::OK).body(include_str!(
+                    "../../resources/registry/dockerhub/hub-tags-page-1.json"
+                )),
+            )
+            .with_response(
+                Method::GET,
+                SECOND_PAGE,
+                MockResponse::new(StatusCode::OK).body(include_str!(
+                    "../../resources/registry/dockerhub/hub-tags-page-2.json"
+                )),
+            )
+    }
+
+    #[tokio::test]
+    async fn repository() {
+        let client = Client::builder().transport(transport()).build();
+
+        let got = client.dockerhub_repository(&image()).await.unwrap();
+
+        insta::assert_json_snapshot!(got);
+    }
+
+    #[tokio::test]
+    async fn tags_follow_pages() {
+        let transport = transport();
+        let client = Client::builder().transport(transport.clone()).build();
+
+        let got = client.dockerhub_tags(&image()).await.unwrap();
+
+        insta::assert_json_snapshot!(got);
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn login() {
+        let transport = transport().with_response(
+            Method::POST,
+            "https://hub.docker.com/v2/users/login",
+            MockResponse::new(StatusCode::OK).body(r#"{"token":"hub-jwt"}"#),
+        );
+        let client = Client::builder()
+            .transport(transport.clone())
+            .dockerhub_credentials("user", "password")
+            .build();
+
+        client.dockerhub_repository(&image()).await.unwrap();
+        client.dockerhub_tags(&image()).await.unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(
+            requests[0].body.as_deref(),
+            Some(br#"{"password":"password","username":"user"}"#.as_slice())
+        );
+
+        for request in &requests[1..] {
+            assert_eq!(
+                request.headers.get("Authorization").unwrap(),
+                "Bearer hub-jwt"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unsupported_registry() {
+        let client = Client::builder().transport(MockTransport::new()).build();
+        let image = "ghcr.io/aquasecurity/trivy:0.52.0".parse().unwrap();
+
+        let got = client.dockerhub_tags(&image).await.unwrap_err();
+
+        assert!(matches!(
+            got,
+            ClientError::UnsupportedByRegistry(Registry::Github)
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::HashMap,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
+};
+
+use futures::{
+    StreamExt,
+    TryStreamExt,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::{
+    info,
+    warn,
+};
+
+use crate::{
+    docker::{
+        blob,
+        cancellation::{
+            Cancellation,
+            Reason,
+        },
+        progress::{
+            ChunksOnly,
+            NoProgress,
+            Progress,
+        },
+        Client,
+        Error,
+    },
+    manifest,
+    Digest,
+    Image,
+};
+
+/// A layer written to disk by [`Client::download_layers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedLayer {
+    pub digest: Digest,
+    pub path: PathBuf,
+    pub size: u64,
+
+    /// True if the layer was already downloaded for an earlier layer with the
+    /// same digest and was linked or copied from there.
+    pub deduplicated: bool,
+}
+
+/// A layer that could not be downloaded.
+#[derive(Debug)]
+pub struct FailedLayer {
+    pub digest: Digest,
+    pub error: LayerError,
+}
+
+#[derive(Debug)]
+pub enum LayerError {
+    Fetch(Box<Error>),
+    ReadBlob(blob::Error),
+    CreateFile(PathBuf, std::io::Error),
+    WriteFile(PathBuf, std::io::Error),
+    RenameFile(PathBuf, std::io::Error),
+    LinkFile(PathBuf, std::io::Error),
+}
+
+/// The result of a layer download, or why it was stopped.
+type Outcome = Result<Result<u64, LayerError>, Reason>;
+
+impl std::fmt::Display for FailedLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.digest, self.error)
+    }
+}
+
+impl std::fmt::Display for LayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fetch(e) => write!(f, "failed to fetch blob: {e}"),
+            Self::ReadBlob(e) => write!(f, "failed to read blob: {e}"),
+            Self::CreateFile(path, e) => {
+                write!(f, "failed to create file {}: {e}", path.display())
+            }
+            Self::WriteFile(path, e) => write!(f, "failed to write file {}: {e}", path.display()),
+            Self::RenameFile(path, e) => {
+                write!(f, "failed to rename file {}: {e}", path.display())
+            }
+            Self::LinkFile(path, e) => write!(f, "failed to link file {}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for LayerError {}
+
+impl Client {
+    /// Downloads the layers of `manifest` into `dest_dir`, fetching at most
+    /// `concurrency` layers at the same time and no more than the
+    /// [`crate::ClientBuilder::concurrency_limit`] shared by all clones of
+    /// the client. Layers that appear more than
+    /// once are only fetched once and hard linked, or copied if linking is
+    /// not possible, for the repeats. Every layer is verified against its
+    /// digest before it is moved to its final path.
+    ///
+    /// The returned layers are in the same order as in the manifest. Files
+    /// are named after the position of the layer and its digest.
+    ///
+    /// # Errors
+    /// Returns [`Error::DownloadLayers`] listing every layer that failed to
+    /// download. Layers that were downloaded successfully are left in
+    /// `dest_dir`.
+    #[tracing::instrument(skip(self, manifest))]
+    pub async fn download_layers(
+        &self,
+        image: &Image,
+        manifest: &manifest::Image,
+        dest_dir: &Path,
+        concurrency: usize,
+    ) -> Result<Vec<DownloadedLayer>, Error> {
+        self.download_layers_with_progress(
+            image,
+            manifest,
+            dest_dir,
+            concurrency,
+            Arc::new(NoProgress),
+        )
+        .await
+    }
+
+    /// Same as [`Client::download_layers`] but reports the combined transfer
+    /// of all layers to `progress`. The total is the sum of the sizes of the
+    /// distinct layers in the manifest.
+    ///
+    /// # Errors
+    /// Returns [`Error::DownloadLayers`] listing every layer that failed to
+    /// download.
+    pub async fn download_layers_with_progress(
+        &self,
+        image: &Image,
+        manifest: &manifest::Image,
+        dest_dir: &Path,
+        concurrency: usize,
+        progress: Arc<dyn Progress>,
+    ) -> Result<Vec<DownloadedLayer>, Error> {
+        self.download_layers_with_cancellation(
+            image,
+            manifest,
+            dest_dir,
+            concurrency,
+            progress,
+            &Cancellation::default(),
+        )
+        .await
+    }
+
+    /// Same as [`Client::download_layers_with_progress`] but stops once
+    /// `cancellation` fires. Layers that are still downloading are abandoned
+    /// and their partial files removed, layers that were not started yet are
+    /// skipped.
+    ///
+    /// # Errors
+    /// Returns [`Error::Cancelled`] with the layers that were downloaded
+    /// completely before the download was stopped. They are left in
+    /// `dest_dir`.
+    /// Returns [`Error::DownloadLayers`] listing every layer that failed to
+    /// download.
+    #[tracing::instrument(skip(self, manifest, progress, cancellation))]
+    pub async fn download_layers_with_cancellation(
+        &self,
+        image: &Image,
+        manifest: &manifest::Image,
+        dest_dir: &Path,
+        concurrency: usize,
+        progress: Arc<dyn Progress>,
+        cancellation: &Cancellation,
+    ) -> Result<Vec<DownloadedLayer>, Error> {
+        let layers = manifest
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| {
+                let digest = layer.digest.clone();
+
+                let path = dest_dir.join(format!(
+                    "{index:03}-{}",
+                    digest.normalized().to_string().replace(':', "-")
+                ));
+
+                Ok((digest, path))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // The first layer with a digest is downloaded, later ones reuse it.
+        let mut first_paths: HashMap<Digest, (PathBuf, u64)> = HashMap::new();
+        let mut total = 0;
+        for ((digest, path), layer) in layers.iter().zip(&manifest.layers) {
+            first_paths.entry(digest.normalized()).or_insert_with(|| {
+                total += layer.size;
+                (path.clone(), layer.size)
+            });
+        }
+
+        progress.on_start(Some(total));
+
+        let mut results = self
+            .download_distinct_layers(
+                image,
+                &first_paths,
+                concurrency,
+                progress.clone(),
+                cancellation,
+            )
+            .await?;
+
+        let mut sizes = HashMap::new();
+        let mut downloaded = Vec::with_capacity(layers.len());
+        let mut failed = Vec::new();
+
+        for (digest, path) in layers {
+            let key = digest.normalized();
+            let (first, _) = &first_paths[&key];
+
+            if path == *first {
+                match results.remove(&key) {
+                    Some(Ok(size)) => {
+                        sizes.insert(key, size);
+                        downloaded.push(DownloadedLayer {
+                            digest,
+                            path,
+                            size,
+                            deduplicated: false,
+                        });
+                    }
+
+                    Some(Err(error)) => failed.push(FailedLayer { digest, error }),
+
+                    None => {}
+                }
+
+                continue;
+            }
+
+            // Repeats of a failed layer are already reported with the first
+            // one.
+            let Some(&size) = sizes.get(&key) else {
+                continue;
+            };
+
+            match link_or_copy(first, &path).await {
+                Ok(()) => downloaded.push(DownloadedLayer {
+                    digest,
+                    path,
+                    size,
+                    deduplicated: true,
+                }),
+
+                Err(error) => failed.push(FailedLayer { digest, error }),
+            }
+        }
+
+        if failed.is_empty() {
+            progress.on_finish();
+
+            Ok(downloaded)
+        } else {
+            Err(Error::DownloadLayers(failed))
+        }
+    }
+
+    /// Downloads every layer of `first_paths` and returns the result per
+    /// digest. Fails with [`Error::Cancelled`] if `cancellation` fired before
+    /// all downloads finished.
+    async fn download_distinct_layers(
+        &self,
+        image: &Image,
+        first_paths: &HashMap<Digest, (PathBuf, u64)>,
+        concurrency: usize,
+        progress: Arc<dyn Progress>,
+        cancellation: &Cancellation,
+    ) -> Result<HashMap<Digest, Result<u64, LayerError>>, Error> {
+        let outcomes: Vec<(Digest, PathBuf, Outcome)> = futures::stream::iter(first_paths.clone())
+            .map(|(digest, (path, size))| {
+                let progress = Arc::new(ChunksOnly(progress.clone()));
+
+                async move {
+                    let result = cancellation
+                        .run(async {
+                            let _permit = self.concurrency_permit().await;
+                            self.download_layer(image, &digest, size, &path, progress)
+                                .await
+                        })
+                        .await;
+                    (digest, path, result)
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut results = HashMap::new();
+        let mut completed = Vec::new();
+        let mut stopped = None;
+
+        for (digest, path, outcome) in outcomes {
+            match outcome {
+                Ok(result) => {
+                    if result.is_ok() {
+                        completed.push(digest.clone());
+                    }
+
+                    results.insert(digest, result);
+                }
+
+                Err(reason) => {
+                    stopped = Some(reason);
+
+                    // Ignore the error as the download might not have started.
+                    let _ = tokio::fs::remove_file(path.with_extension("partial")).await;
+                }
+            }
+        }
+
+        match stopped {
+            Some(reason) => Err(reason.error().with_completed(completed)),
+            None => Ok(results),
+        }
+    }
+
+    /// Streams a single blob of `expected_size` bytes into a temporary file
+    /// next to `path` and moves it into place once the digest was verified.
+    async fn download_layer(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        expected_size: u64,
+        path: &Path,
+        progress: Arc<dyn Progress>,
+    ) -> Result<u64, LayerError> {
+        let partial = path.with_extension("partial");
+
+        let result = async {
+            let blob = self
+                .fetch_blob(
+                    image,
+                    digest,
+                    self.inner.max_blob_size,
+                    Some(expected_size),
+                    progress,
+                )
+                .await
+                .map_err(|e| LayerError::Fetch(Box::new(e)))?;
+
+            let mut file = tokio::fs::File::create(&partial)
+                .await
+                .map_err(|e| LayerError::CreateFile(partial.clone(), e))?;
+
+            let mut blob = std::pin::pin!(blob.map_err(LayerError::ReadBlob));
+            let mut size = 0;
+
+            while let Some(chunk) = blob.try_next().await? {
+                size += chunk.len() as u64;
+
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| LayerError::WriteFile(partial.clone(), e))?;
+            }
+
+            file.flush()
+                .await
+                .map_err(|e| LayerError::WriteFile(partial.clone(), e))?;
+
+            tokio::fs::rename(&partial, path)
+                .await
+                .map_err(|e| LayerError::RenameFile(partial.clone(), e))?;
+
+            Ok(size)
+        }
+        .await;
+
+        match &result {
+            Ok(size) => info!(%digest, size, "downloaded layer"),
+
+            Err(e) => {
+                warn!(%digest, error = %e, "failed to download layer");
+
+                // Ignore the error as the file might not have been created.
+                let _ = tokio::fs::remove_file(&partial).await;
+            }
+        }
+
+        result
+    }
+}
+
+async fn link_or_copy(from: &Path, to: &Path) -> Result<(), LayerError> {
+    if tokio::fs::hard_link(from, to).await.is_ok() {
+        return Ok(());
+    }
+
+    tokio::fs::copy(from, to)
+        .await
+        .map(|_| ())
+        .map_err(|e| LayerError::LinkFile(to.to_path_buf(), e))
+}
@@ -0,0 +1,75 @@
+use async_compression::tokio::bufread::GzipDecoder;
+#[cfg(feature = "zstd")]
+use async_compression::tokio::bufread::ZstdDecoder;
+use bytes::Bytes;
+use futures::{
+    stream::BoxStream,
+    StreamExt,
+    TryStreamExt,
+};
+use http::header::{
+    HeaderMap,
+    CONTENT_ENCODING,
+};
+use tokio::io::AsyncRead;
+use tokio_util::io::{
+    ReaderStream,
+    StreamReader,
+};
+
+use crate::docker::transport::{
+    Error,
+    Response,
+};
+
+/// Returns the body of `response` without the `Content-Encoding` the server
+/// applied, so digests and size limits apply to the bytes the registry
+/// stores. reqwest is built without its decompression features, with a
+/// custom client that has them the header is already gone and the body is
+/// returned as is.
+///
+/// Only JSON bodies like manifests, tokens and tag lists are decoded. Blobs
+/// are not, some storage backends label compressed layers with
+/// `Content-Encoding: gzip` although the compressed bytes are the blob.
+///
+/// # Errors
+/// Returns [`Error::UnsupportedContentEncoding`] for encodings other than
+/// `gzip`, `zstd` with the `zstd` feature and `identity`. Reading the body
+/// fails with [`Error::Decode`] if it is not valid for its encoding.
+pub(super) fn decoded_body(
+    response: Response,
+) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
+    let encoding = content_encoding(&response.headers);
+
+    let reader = |response: Response| {
+        StreamReader::new(response.into_stream().map_err(std::io::Error::other))
+    };
+
+    match encoding.as_deref() {
+        None | Some("identity") => Ok(response.into_stream().boxed()),
+
+        Some("gzip" | "x-gzip") => Ok(stream(GzipDecoder::new(reader(response)))),
+
+        #[cfg(feature = "zstd")]
+        Some("zstd") => Ok(stream(ZstdDecoder::new(reader(response)))),
+
+        Some(encoding) => Err(Error::UnsupportedContentEncoding(encoding.to_string())),
+    }
+}
+
+fn content_encoding(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONTENT_ENCODING)
+        .and_then(|header| header.to_str().ok())
+        .map(|header| header.trim().to_ascii_lowercase())
+}
+
+fn stream(reader: impl AsyncRead + Send + 'static) -> BoxStream<'static, Result<Bytes, Error>> {
+    ReaderStream::new(reader).map_err(from_io).boxed()
+}
+
+/// Errors of the body itself are passed through unchanged, everything else
+/// is a decoding error.
+fn from_io(e: std::io::Error) -> Error {
+    e.downcast::<Error>().unwrap_or_else(Error::Decode)
+}
@@ -1,58 +1,548 @@
+use http::{
+    header::HeaderMap,
+    StatusCode,
+};
 use url::Url;
 
-use crate::docker::token_cache;
+use crate::docker::{
+    cancellation,
+    download,
+    layer,
+    manifest_cache,
+    read_text,
+    token,
+    token_cache,
+    transport,
+};
+
+/// Number of characters of the body [`FailedResponse`] shows in its display.
+const DISPLAYED_BODY_LENGTH: usize = 200;
 
 #[derive(Debug)]
 pub enum Error {
-    GetManifest(reqwest::Error),
+    GetManifest(transport::Error),
+    InvalidPath(crate::image::UrlError),
     InvalidManifestUrl(url::ParseError),
-    ExtractManifestBody(reqwest::Error),
-    FailedManifestRequest(reqwest::StatusCode, String),
+    ExtractManifestBody(transport::Error),
+    FailedManifestRequest(Box<FailedResponse>),
     DeserializeManifestBody(serde_json::Error, String),
-    ParseManifestAcceptHeader(reqwest::header::InvalidHeaderValue),
+    ParseManifestAcceptHeader(http::header::InvalidHeaderValue),
+    ParseIfNoneMatchHeader(http::header::InvalidHeaderValue),
     ManifestNotFound(Url),
+    ManifestNotModified(Url),
+    RepositoryNotFound(Url),
     MissingDockerContentDigestHeader,
-    ParseDockerContentDigestHeader(reqwest::header::ToStrError),
+    ParseDockerContentDigestHeader(http::header::ToStrError),
+    ParseDockerContentDigest(crate::image::image_name::digest::FromStrError),
+    UpdateCheckRequiresTag(crate::Image),
+    NotAnImageManifest(crate::Digest),
+    NoMatchingPlatform {
+        image: crate::Image,
+        platform: Box<crate::manifest::Platform>,
+    },
+    UnsupportedDigestAlgorithm(crate::image::image_name::digest::UnsupportedDigestAlgorithm),
+    DigestMismatch {
+        requested: crate::Digest,
+        received: crate::Digest,
+        url: Url,
+    },
+    DescriptorMismatch {
+        expected_size: u64,
+        actual_size: u64,
+        expected_digest: crate::Digest,
+        actual_digest: crate::Digest,
+    },
+    GetBlob(transport::Error),
+    InvalidBlobUrl(url::ParseError),
+    BlobNotFound(Url),
+    FailedBlobRequest(Box<FailedResponse>),
+    ReadBlob(super::blob::Error),
+    DeserializeConfig(serde_json::Error),
+    DeserializeBlob(serde_json::Error, String),
+    DecodeInlineData(base64::DecodeError),
+    ReadLayout(std::path::PathBuf, std::io::Error),
+    DeserializeLayout(std::path::PathBuf, serde_json::Error),
+    LayoutManifestNotFound(String),
+    UnsupportedLayoutDigest(crate::Digest),
+    UploadBlob(transport::Error),
+    ReadUpload(std::io::Error),
+    InvalidPushHeader(http::header::InvalidHeaderValue),
+    MissingUploadLocation,
+    InvalidUploadUrl(url::ParseError),
+    FailedBlobUpload(Box<FailedResponse>),
+    PushManifest(transport::Error),
+    ArchiveImageNotFound(crate::Image),
+    CompressLayer(std::io::Error),
+    SerializeManifest(serde_json::Error),
+    FailedManifestPush(Box<FailedResponse>),
+
+    /// The registry rejected a manifest push because `tag` already exists in
+    /// a repository with immutable tags, see
+    /// [`crate::Client::is_tag_immutable_error`].
+    TagImmutable {
+        tag: String,
+        response: Box<FailedResponse>,
+    },
+    ParseAttestation(crate::attestation::ParseError),
+    #[cfg(feature = "cosign-verify")]
+    InvalidCosignPublicKey(p256::pkcs8::spki::Error),
+    DecodeLayer(layer::Error),
+    StoreContent(crate::Digest, std::io::Error),
+    DownloadLayers(Vec<download::FailedLayer>),
+    Cancelled(cancellation::Cancelled),
+    GetTags(transport::Error),
+    InvalidTagsUrl(url::ParseError),
+    ExtractTagsBody(transport::Error),
+    TagsNotFound(Url),
+    FailedTagsRequest(Box<FailedResponse>),
+    RateLimited {
+        status: Box<super::rate_limit_status::RateLimitStatus>,
+        response: Box<FailedResponse>,
+    },
+    DeserializeTags(serde_json::Error),
+    OfflineCacheMiss {
+        image: crate::Image,
+    },
+    Offline,
+    ShutdownTimeout(std::time::Duration),
+    Ping(transport::Error),
+    InvalidPingUrl(url::ParseError),
+    NotARegistry(Url),
+    NotAV2Registry {
+        url: Url,
+        content_type: Option<String>,
+    },
+    FailedPingRequest(Box<FailedResponse>),
+    UnsupportedByRegistry(crate::Registry),
+    RegistryDenied {
+        registry: crate::Registry,
+    },
+    InvalidHubUrl(url::ParseError),
+    GetHub(transport::Error),
+    FailedHubRequest(Box<FailedResponse>),
+    DeserializeHub(serde_json::Error),
+    MissingHubToken,
+    BodyTooLarge {
+        limit: u64,
+        url: Url,
+    },
+    Shared(std::sync::Arc<Error>),
 
     InvalidTokenUrl(url::ParseError),
-    GetToken(reqwest::Error),
-    ExtractTokenBody(reqwest::Error),
+    GetToken(transport::Error),
+    FailedTokenRequest(Box<FailedResponse>),
+    ExtractTokenBody(transport::Error),
     DeserializeToken(serde_json::Error, String),
-    ParseAuthorizationHeader(reqwest::header::InvalidHeaderValue),
+
+    /// The registry denied a request sent with a token that was issued for
+    /// another audience than the service it was requested for, `error` is
+    /// the error of the denied request.
+    TokenAudienceMismatch {
+        mismatch: Box<token::AudienceMismatch>,
+        error: Box<Error>,
+    },
+
+    /// The token request failed and the request sent without a token was
+    /// not served either, see
+    /// [`crate::ClientBuilder::try_anonymous_on_token_failure`].
+    AnonymousFallbackFailed {
+        token: Box<Error>,
+        anonymous: Box<Error>,
+    },
+    ParseAuthorizationHeader(http::header::InvalidHeaderValue),
     InvalidImageUrl(crate::image::FromUrlError),
     FetchToken(token_cache::FetchError),
     StoreToken(token_cache::StoreError),
+    PersistTokens(token_cache::PersistError),
+    FetchManifest(manifest_cache::FetchError),
+    StoreManifest(manifest_cache::StoreError),
+}
+
+/// The error codes of the distribution specification, sent in the body of
+/// failed responses as `{"errors": [{"code": "MANIFEST_UNKNOWN", ...}]}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    BlobUnknown,
+    BlobUploadInvalid,
+    BlobUploadUnknown,
+    DigestInvalid,
+    ManifestBlobUnknown,
+    ManifestInvalid,
+    ManifestUnknown,
+    NameInvalid,
+    NameUnknown,
+    SizeInvalid,
+    TagInvalid,
+    Unauthorized,
+    Denied,
+    Unsupported,
+    TooManyRequests,
+
+    /// A code that is not part of the specification.
+    Other(String),
+}
+
+/// The body of a failed response as the distribution specification
+/// describes it.
+#[derive(Debug, serde::Deserialize)]
+struct ErrorBody {
+    errors: Vec<ErrorBodyEntry>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ErrorBodyEntry {
+    code: String,
+}
+
+/// A response with an unexpected status. Next to the body it keeps the
+/// headers, which often carry what the operator of a registry needs to look
+/// into a failure like rate limit state, `Retry-After`, `WWW-Authenticate` or
+/// a request ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedResponse {
+    pub status: StatusCode,
+    pub url: Url,
+
+    /// The response headers in the order the registry sent them. Values that
+    /// are not valid UTF-8 are converted lossily.
+    pub headers: Vec<(String, String)>,
+
+    pub body: String,
+}
+
+impl FailedResponse {
+    pub(super) fn new(status: StatusCode, url: Url, headers: &HeaderMap, body: String) -> Self {
+        Self {
+            status,
+            url,
+            headers: headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect(),
+            body,
+        }
+    }
+
+    /// Reads the body of `response` up to `limit` bytes. A body that can not
+    /// be read is left empty as the status is what matters.
+    pub(super) async fn read(response: transport::Response, limit: u64) -> Box<Self> {
+        let status = response.status;
+        let url = response.url.clone();
+        let headers = response.headers.clone();
+
+        let body = read_text(response, limit, super::Error::ExtractManifestBody)
+            .await
+            .unwrap_or_default();
+
+        Box::new(Self::new(status, url, &headers, body))
+    }
+
+    /// Returns the code of the first error in the body. Bodies in the format
+    /// Quay uses for authentication errors are mapped onto the standard
+    /// codes.
+    #[must_use]
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        match serde_json::from_str::<ErrorBody>(&self.body) {
+            Ok(body) => body
+                .errors
+                .into_iter()
+                .next()
+                .map(|entry| ErrorCode::from(entry.code.as_str())),
+
+            Err(_) => super::quay::error_code(self.status, &self.body),
+        }
+    }
+
+    /// Returns the first value of the header with the given name, ignoring
+    /// its case.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl From<&str> for ErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "BLOB_UNKNOWN" => Self::BlobUnknown,
+            "BLOB_UPLOAD_INVALID" => Self::BlobUploadInvalid,
+            "BLOB_UPLOAD_UNKNOWN" => Self::BlobUploadUnknown,
+            "DIGEST_INVALID" => Self::DigestInvalid,
+            "MANIFEST_BLOB_UNKNOWN" => Self::ManifestBlobUnknown,
+            "MANIFEST_INVALID" => Self::ManifestInvalid,
+            "MANIFEST_UNKNOWN" => Self::ManifestUnknown,
+            "NAME_INVALID" => Self::NameInvalid,
+            "NAME_UNKNOWN" => Self::NameUnknown,
+            "SIZE_INVALID" => Self::SizeInvalid,
+            "TAG_INVALID" => Self::TagInvalid,
+            "UNAUTHORIZED" => Self::Unauthorized,
+            "DENIED" => Self::Denied,
+            "UNSUPPORTED" => Self::Unsupported,
+            "TOOMANYREQUESTS" => Self::TooManyRequests,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl Error {
+    /// Returns true if the manifest, blob, tag list or repository a request
+    /// was for does not exist.
+    #[must_use]
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Self::ManifestNotFound(_)
+            | Self::BlobNotFound(_)
+            | Self::TagsNotFound(_)
+            | Self::RepositoryNotFound(_) => true,
+            Self::Shared(error) => error.is_not_found(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if a manifest request failed because the registry does
+    /// not have the manifest, with `404` or the `MANIFEST_UNKNOWN` error
+    /// code.
+    #[must_use]
+    pub fn is_missing_manifest(&self) -> bool {
+        match self {
+            Self::ManifestNotFound(_) => true,
+            Self::FailedManifestRequest(response) => {
+                response.error_code() == Some(ErrorCode::ManifestUnknown)
+            }
+            Self::Shared(error) => error.is_missing_manifest(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the token endpoint could not be reached or failed
+    /// with a server error. A token endpoint that denies the request is not
+    /// unavailable.
+    pub(super) fn is_token_unavailable(&self) -> bool {
+        match self {
+            Self::GetToken(_) | Self::ExtractTokenBody(_) => true,
+            Self::FailedTokenRequest(response) => response.status.is_server_error(),
+            Self::Shared(error) => error.is_token_unavailable(),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for FailedResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "status {} from {}", self.status, self.url)?;
+
+        let body = self.body.trim();
+
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        match body.char_indices().nth(DISPLAYED_BODY_LENGTH) {
+            Some((end, _)) => write!(f, ", body: {}...", &body[..end]),
+            None => write!(f, ", body: {body}"),
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
+    #[expect(clippy::too_many_lines, reason = "one arm per variant")]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::GetManifest(e) => write!(f, "Failed to get manifest: {e}"),
+            Self::InvalidPath(e) => write!(f, "Invalid image for a registry URL: {e}"),
             Self::InvalidManifestUrl(e) => write!(f, "Invalid manifest URL: {e}"),
             Self::ExtractManifestBody(e) => write!(f, "Failed to extract manifest body: {e}"),
-            Self::FailedManifestRequest(e, s) => {
-                write!(f, "Failed manifest request: status: {e}, body: {s}")
-            }
+            Self::FailedManifestRequest(r) => write!(f, "Failed manifest request: {r}"),
             Self::DeserializeManifestBody(e, s) => {
                 write!(f, "Failed to deserialize manifest body: {e}, body: {s}")
             }
             Self::ParseManifestAcceptHeader(e) => {
                 write!(f, "Failed to parse manifest accept header: {e}")
             }
+            Self::ParseIfNoneMatchHeader(e) => {
+                write!(f, "Failed to parse If-None-Match header: {e}")
+            }
             Self::ManifestNotFound(u) => write!(f, "Manifest at url {u} was not found"),
+            Self::ManifestNotModified(u) => write!(f, "Manifest at url {u} was not modified"),
+            Self::RepositoryNotFound(u) => {
+                write!(f, "Repository of token request {u} was not found")
+            }
             Self::MissingDockerContentDigestHeader => {
                 write!(f, "Missing Docker content digest header")
             }
             Self::ParseDockerContentDigestHeader(e) => {
                 write!(f, "Failed to parse Docker content digest header: {e}")
             }
+            Self::ParseDockerContentDigest(e) => {
+                write!(f, "Failed to parse Docker content digest: {e}")
+            }
+            Self::NoMatchingPlatform { image, platform } => write!(
+                f,
+                "Manifest list of {image} has no entry for platform {platform}"
+            ),
+            Self::UnsupportedDigestAlgorithm(e) => write!(f, "Can not verify content: {e}"),
+            Self::DigestMismatch {
+                requested,
+                received,
+                url,
+            } => write!(
+                f,
+                "Registry answered the request for {requested} at {url} with a manifest with \
+                 digest {received}"
+            ),
+            Self::DescriptorMismatch {
+                expected_size,
+                actual_size,
+                expected_digest,
+                actual_digest,
+            } => write!(
+                f,
+                "Manifest does not match its descriptor: expected {expected_size} bytes with \
+                 digest {expected_digest}, got {actual_size} bytes with digest {actual_digest}"
+            ),
+            Self::BodyTooLarge { limit, url } => write!(
+                f,
+                "Response body of {url} is larger than the limit of {limit} bytes"
+            ),
+            Self::Shared(e) => write!(f, "Shared request failed: {e}"),
+            Self::OfflineCacheMiss { image } => write!(
+                f,
+                "Client is offline and {image} is not available in the cache"
+            ),
+            Self::Offline => write!(f, "Client is offline"),
+            Self::ShutdownTimeout(timeout) => {
+                write!(f, "Client did not shut down within {timeout:?}")
+            }
+            Self::Ping(e) => write!(f, "Failed to ping registry: {e}"),
+            Self::InvalidPingUrl(e) => write!(f, "Invalid ping URL: {e}"),
+            Self::NotARegistry(u) => {
+                write!(f, "Host at url {u} does not serve the registry API")
+            }
+            Self::NotAV2Registry { url, content_type } => {
+                write!(
+                    f,
+                    "Host at url {url} does not speak the v2 registry API, it answered with \
+                     content type {} and without a registry/2.0 API version. It might be a web \
+                     server, a reverse proxy or a v1 registry",
+                    content_type.as_deref().unwrap_or("none")
+                )
+            }
+            Self::FailedPingRequest(r) => write!(f, "Failed ping request: {r}"),
+            Self::UnsupportedByRegistry(registry) => {
+                write!(f, "Operation is not supported by registry {registry}")
+            }
+            Self::RegistryDenied { registry } => {
+                write!(
+                    f,
+                    "Registry {registry} is not allowed by the registry policy"
+                )
+            }
+            Self::InvalidHubUrl(e) => write!(f, "Invalid Docker Hub API URL: {e}"),
+            Self::GetHub(e) => write!(f, "Failed to request the Docker Hub API: {e}"),
+            Self::FailedHubRequest(r) => write!(f, "Failed Docker Hub API request: {r}"),
+            Self::DeserializeHub(e) => {
+                write!(f, "Failed to deserialize Docker Hub API response: {e}")
+            }
+            Self::MissingHubToken => write!(f, "Docker Hub login did not return a token"),
+            Self::GetBlob(e) => write!(f, "Failed to get blob: {e}"),
+            Self::InvalidBlobUrl(e) => write!(f, "Invalid blob URL: {e}"),
+            Self::BlobNotFound(u) => write!(f, "Blob at url {u} was not found"),
+            Self::FailedBlobRequest(r) => write!(f, "Failed blob request: {r}"),
+            Self::ReadBlob(e) => write!(f, "Failed to read blob: {e}"),
+            Self::DeserializeConfig(e) => write!(f, "Failed to deserialize image config: {e}"),
+            Self::DeserializeBlob(e, s) => {
+                write!(f, "Failed to deserialize blob: {e}, body: {s}")
+            }
+            Self::DecodeInlineData(e) => {
+                write!(f, "Failed to decode data embedded in descriptor: {e}")
+            }
+            Self::ReadLayout(p, e) => {
+                write!(f, "Failed to read {} of OCI layout: {e}", p.display())
+            }
+            Self::DeserializeLayout(p, e) => {
+                write!(
+                    f,
+                    "Failed to deserialize {} of OCI layout: {e}",
+                    p.display()
+                )
+            }
+            Self::LayoutManifestNotFound(r) => {
+                write!(f, "OCI layout has no single manifest for reference {r}")
+            }
+            Self::UnsupportedLayoutDigest(d) => {
+                write!(f, "Unsupported digest {d} in OCI layout")
+            }
+            Self::UploadBlob(e) => write!(f, "Failed to upload blob: {e}"),
+            Self::ReadUpload(e) => write!(f, "Failed to read blob to upload: {e}"),
+            Self::InvalidPushHeader(e) => write!(f, "Invalid header for push request: {e}"),
+            Self::MissingUploadLocation => write!(f, "Missing location header of blob upload"),
+            Self::InvalidUploadUrl(e) => write!(f, "Invalid blob upload URL: {e}"),
+            Self::FailedBlobUpload(r) => write!(f, "Failed blob upload: {r}"),
+            Self::PushManifest(e) => write!(f, "Failed to push manifest: {e}"),
+            Self::ArchiveImageNotFound(image) => {
+                write!(f, "Archive has no single image for {image}")
+            }
+            Self::CompressLayer(e) => write!(f, "Failed to compress layer: {e}"),
+            Self::SerializeManifest(e) => write!(f, "Failed to serialize manifest: {e}"),
+            Self::FailedManifestPush(r) => write!(f, "Failed manifest push: {r}"),
+            Self::TagImmutable { tag, response } => {
+                write!(f, "Tag {tag} already exists and is immutable: {response}")
+            }
+            Self::ParseAttestation(e) => write!(f, "Failed to parse attestation: {e}"),
+            #[cfg(feature = "cosign-verify")]
+            Self::InvalidCosignPublicKey(e) => write!(f, "Invalid cosign public key: {e}"),
+            Self::DecodeLayer(e) => write!(f, "Failed to decode layer: {e}"),
+            Self::StoreContent(d, e) => write!(f, "Failed to store {d} in content store: {e}"),
+            Self::DownloadLayers(failed) => {
+                write!(f, "Failed to download {} layers", failed.len())?;
+
+                for layer in failed {
+                    write!(f, ", {layer}")?;
+                }
+
+                Ok(())
+            }
+            Self::Cancelled(cancelled) => write!(f, "Operation was {cancelled}"),
+            Self::GetTags(e) => write!(f, "Failed to get tags: {e}"),
+            Self::InvalidTagsUrl(e) => write!(f, "Invalid tags URL: {e}"),
+            Self::ExtractTagsBody(e) => write!(f, "Failed to extract tags body: {e}"),
+            Self::TagsNotFound(u) => write!(f, "Tags at url {u} were not found"),
+            Self::FailedTagsRequest(r) => write!(f, "Failed tags request: {r}"),
+            Self::RateLimited { status, response } => write!(
+                f,
+                "Rate limited by registry, {} of {} requests left, resets around {}: {response}",
+                status.remaining, status.limit, status.estimated_reset
+            ),
+            Self::DeserializeTags(e) => write!(f, "Failed to deserialize tags: {e}"),
+            Self::NotAnImageManifest(digest) => {
+                write!(f, "Manifest {digest} is not an image manifest")
+            }
+            Self::UpdateCheckRequiresTag(image) => write!(
+                f,
+                "Can not check {image} for updates as it is referenced by digest instead of a tag"
+            ),
 
             Self::InvalidTokenUrl(e) => write!(f, "Invalid token URL: {e}"),
             Self::GetToken(e) => write!(f, "Failed to get token: {e}"),
+            Self::FailedTokenRequest(r) => write!(f, "Failed token request: {r}"),
             Self::ExtractTokenBody(e) => write!(f, "Failed to extract token body: {e}"),
             Self::DeserializeToken(e, s) => {
                 write!(f, "Failed to deserialize token: {e}, body: {s}")
             }
+            Self::TokenAudienceMismatch { mismatch, error } => write!(f, "{error}, {mismatch}"),
+            Self::AnonymousFallbackFailed { token, anonymous } => write!(
+                f,
+                "Failed to get token: {token}, the request without a token failed as well: \
+                 {anonymous}"
+            ),
             Self::ParseAuthorizationHeader(e) => {
                 write!(f, "Failed to parse authorization header: {e}")
             }
@@ -61,6 +551,9 @@ impl std::fmt::Display for Error {
             }
             Self::FetchToken(e) => write!(f, "Failed to fetch token from cache: {e}"),
             Self::StoreToken(e) => write!(f, "Failed to store token in cache: {e}"),
+            Self::PersistTokens(e) => write!(f, "Failed to persist token cache: {e}"),
+            Self::FetchManifest(e) => write!(f, "Failed to fetch manifest from cache: {e}"),
+            Self::StoreManifest(e) => write!(f, "Failed to store manifest in cache: {e}"),
         }
     }
 }
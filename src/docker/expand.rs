@@ -0,0 +1,492 @@
+use either::Either;
+use futures::StreamExt;
+
+use crate::{
+    docker::{
+        missing_child::{
+            MissingChildPolicy,
+            SkippedChild,
+        },
+        platform::verify,
+        Client,
+        Error,
+    },
+    image::image_name::ImageName,
+    manifest::{
+        self,
+        Entry,
+        Platform,
+    },
+    Digest,
+    Image,
+    ImageConfig,
+    Manifest,
+};
+
+/// How many platforms [`Client::expand_index`] fetches at the same time.
+const EXPAND_CONCURRENCY: usize = 4;
+
+/// The result of [`Client::expand_index`].
+#[derive(Debug)]
+pub struct ExpandedImage {
+    /// The digest the registry returned for the index, or for the manifest
+    /// if the image is not a list.
+    pub digest: Option<String>,
+
+    /// One entry per platform in the order of the index.
+    pub platforms: Vec<ExpandedPlatform>,
+
+    /// Platforms that were left out because their manifest is missing, see
+    /// [`ExpandOptions::on_missing_child`].
+    pub skipped: Vec<SkippedChild>,
+}
+
+/// Options for [`Client::expand_index_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpandOptions {
+    on_missing_child: MissingChildPolicy,
+}
+
+/// A platform of an [`ExpandedImage`]. Fetching its manifest and config can
+/// fail without affecting the other platforms.
+#[derive(Debug)]
+pub struct ExpandedPlatform {
+    pub platform: Platform,
+
+    /// Digest and size of the image manifest as announced by the index.
+    pub digest: Digest,
+    pub size: u64,
+
+    pub image: Result<PlatformImage, Error>,
+}
+
+/// The manifest and config of a single platform.
+#[derive(Debug, Clone)]
+pub struct PlatformImage {
+    pub manifest: manifest::Image,
+    pub config: ImageConfig,
+}
+
+impl ExpandedImage {
+    /// Returns the first platform that satisfies `platform`, see
+    /// [`Platform::matches`].
+    #[must_use]
+    pub fn get(&self, platform: &Platform) -> Option<&ExpandedPlatform> {
+        self.platforms
+            .iter()
+            .find(|expanded| platform.matches(&expanded.platform))
+    }
+}
+
+impl ExpandOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What to do with platforms whose manifest is missing. With the
+    /// default [`MissingChildPolicy::Fail`] they are kept with the error in
+    /// [`ExpandedPlatform::image`].
+    #[must_use]
+    pub fn on_missing_child(mut self, policy: MissingChildPolicy) -> Self {
+        self.on_missing_child = policy;
+        self
+    }
+}
+
+impl Client {
+    /// Fetches the manifest list of `image` together with the image manifest
+    /// and config of every platform it contains, up to four platforms at the
+    /// same time. Attestation manifests and entries without a platform are
+    /// skipped. An image that is not a list expands to a single platform
+    /// taken from its config.
+    ///
+    /// A platform that can not be fetched, for example because its manifest
+    /// is missing, only fails its own [`ExpandedPlatform::image`].
+    ///
+    /// # Errors
+    /// Returns an error if the manifest of `image` can not be fetched, or if
+    /// it is not a list and its config can not be fetched.
+    pub async fn expand_index(&self, image: &Image) -> Result<ExpandedImage, Error> {
+        self.expand_index_with(image, &ExpandOptions::default())
+            .await
+    }
+
+    /// Same as [`Client::expand_index`] with `options`.
+    ///
+    /// # Errors
+    /// Returns an error if the manifest of `image` can not be fetched, or if
+    /// it is not a list and its config can not be fetched.
+    #[tracing::instrument(
+        name = "expand_index",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            reference = %image.image_name.identifier,
+        )
+    )]
+    pub async fn expand_index_with(
+        &self,
+        image: &Image,
+        options: &ExpandOptions,
+    ) -> Result<ExpandedImage, Error> {
+        let raw = self.get_manifest_raw(image).await?;
+        let digest = raw.digest.clone();
+        let size = raw.body.len() as u64;
+        let manifest_digest = Digest::sha256(&raw.body);
+
+        let list = match Self::response_from_raw(raw)?.manifest {
+            Manifest::List(list) => list,
+
+            Manifest::Image(manifest) => {
+                let config: ImageConfig = self.get_blob_json(image, &manifest.config).await?;
+
+                let mut platform = Platform::new(config.os, config.architecture);
+                if let Some(variant) = &config.variant {
+                    platform = platform.with_variant(variant);
+                }
+
+                return Ok(ExpandedImage {
+                    digest,
+                    platforms: vec![ExpandedPlatform {
+                        platform,
+                        digest: manifest_digest,
+                        size,
+                        image: Ok(PlatformImage { manifest, config }),
+                    }],
+                    skipped: Vec::new(),
+                });
+            }
+
+            Manifest::Single(_) => return Err(Error::NotAnImageManifest(manifest_digest)),
+        };
+
+        let expanded = futures::stream::iter(list.manifests.iter().filter_map(|entry| {
+            let platform = entry.platform.clone()?;
+
+            entry
+                .attestation_for()
+                .is_none()
+                .then_some((entry, platform))
+        }))
+        .map(|(entry, platform)| async move {
+            let image = self.platform_image(image, entry).await;
+
+            if let Err(e) = &image {
+                if let Some(skipped) = options.on_missing_child.skip(entry, e) {
+                    return Either::Right(skipped);
+                }
+            }
+
+            Either::Left(ExpandedPlatform {
+                platform,
+                digest: entry.digest.clone(),
+                size: entry.size,
+                image,
+            })
+        })
+        .buffered(EXPAND_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+        let mut platforms = Vec::new();
+        let mut skipped = Vec::new();
+
+        for expanded in expanded {
+            match expanded {
+                Either::Left(platform) => platforms.push(platform),
+                Either::Right(child) => skipped.push(child),
+            }
+        }
+
+        Ok(ExpandedImage {
+            digest,
+            platforms,
+            skipped,
+        })
+    }
+
+    async fn platform_image(&self, image: &Image, entry: &Entry) -> Result<PlatformImage, Error> {
+        let digest = entry.digest.clone();
+        let followed = Image {
+            image_name: ImageName::new(
+                image.image_name.name.clone(),
+                Either::Right(digest.clone()),
+            ),
+            ..image.clone()
+        };
+
+        let raw = self.get_manifest_raw(&followed).await?;
+
+        if self.inner.verify_descriptors {
+            verify(entry, &raw.body)?;
+        }
+
+        let Manifest::Image(manifest) = Self::response_from_raw(raw)?.manifest else {
+            return Err(Error::NotAnImageManifest(digest));
+        };
+
+        let config = self.get_blob_json(image, &manifest.config).await?;
+
+        Ok(PlatformImage { manifest, config })
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        ExpandOptions,
+        ExpandedImage,
+    };
+    use crate::{
+        docker::{
+            missing_child::{
+                MissingChildPolicy,
+                SkippedChild,
+            },
+            stats::measure,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        manifest::{
+            Architecture,
+            OperatingSystem,
+            Platform,
+        },
+        Client,
+        ClientError,
+        Digest,
+        Image,
+    };
+
+    const BASE: &str = "https://registry.access.redhat.com/v2/ubi8";
+
+    fn image() -> Image {
+        "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+    }
+
+    /// Returns the manifest of an image for `architecture` and adds it and
+    /// its config to `transport`.
+    fn serve_image(transport: MockTransport, architecture: &str) -> (MockTransport, String) {
+        let config = serde_json::json!({
+            "architecture": architecture,
+            "os": "linux",
+            "rootfs": { "type": "layers", "diff_ids": [] }
+        })
+        .to_string();
+        let config_digest = Digest::sha256(config.as_bytes());
+
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": config.len(),
+                "digest": config_digest.to_string()
+            },
+            "layers": []
+        })
+        .to_string();
+
+        let transport = transport
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{}", Digest::sha256(manifest.as_bytes())),
+                MockResponse::new(StatusCode::OK).body(manifest.clone()),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/blobs/{config_digest}"),
+                MockResponse::new(StatusCode::OK).body(config),
+            );
+
+        (transport, manifest)
+    }
+
+    fn entry(manifest: &str, architecture: &str) -> serde_json::Value {
+        serde_json::json!({
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "size": manifest.len(),
+            "digest": Digest::sha256(manifest.as_bytes()).to_string(),
+            "platform": { "architecture": architecture, "os": "linux" }
+        })
+    }
+
+    /// An index for amd64, arm64 and s390x with an attestation. The arm64
+    /// manifest is not served, its request is answered with `dead`.
+    fn dead_child(dead: MockResponse) -> (MockTransport, String) {
+        let (transport, amd64) = serve_image(MockTransport::new(), "amd64");
+        let (transport, s390x) = serve_image(transport, "s390x");
+        let (_, arm64) = serve_image(MockTransport::new(), "arm64");
+
+        let list = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                entry(&amd64, "amd64"),
+                entry(&arm64, "arm64"),
+                entry(&s390x, "s390x"),
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": 10,
+                    "digest": "sha256:0000000000000000000000000000000000000000000000000000000000000000",
+                    "platform": { "architecture": "unknown", "os": "unknown" },
+                    "annotations": {
+                        "vnd.docker.reference.digest": Digest::sha256(amd64.as_bytes()).to_string(),
+                        "vnd.docker.reference.type": "attestation-manifest"
+                    }
+                }
+            ]
+        });
+
+        let transport = transport
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{}", Digest::sha256(arm64.as_bytes())),
+                dead,
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/8.9"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", "sha256:list")
+                    .body(list.to_string()),
+            );
+
+        (transport, arm64)
+    }
+
+    fn platforms(expanded: &ExpandedImage) -> Vec<(String, bool)> {
+        expanded
+            .platforms
+            .iter()
+            .map(|expanded| (expanded.platform.to_string(), expanded.image.is_ok()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn partial_failure() {
+        let (transport, arm64) = dead_child(MockResponse::new(StatusCode::NOT_FOUND));
+
+        let client = Client::builder().transport(transport).build();
+        let got = client.expand_index(&image()).await.unwrap();
+
+        assert_eq!(got.digest.as_deref(), Some("sha256:list"));
+        assert_eq!(
+            platforms(&got),
+            [
+                ("linux/amd64".to_string(), true),
+                ("linux/arm64".to_string(), false),
+                ("linux/s390x".to_string(), true),
+            ]
+        );
+        assert!(got.skipped.is_empty());
+
+        let arm64_platform = Platform::new(OperatingSystem::Linux, Architecture::Arm64);
+        let failed = got.get(&arm64_platform).unwrap();
+        assert_eq!(failed.digest, Digest::sha256(arm64.as_bytes()));
+        assert!(matches!(
+            failed.image,
+            Err(ClientError::ManifestNotFound(_))
+        ));
+
+        let amd64_platform = Platform::new(OperatingSystem::Linux, Architecture::Amd64);
+        let expanded = got.get(&amd64_platform).unwrap().image.as_ref().unwrap();
+        assert_eq!(expanded.config.architecture, Architecture::Amd64);
+    }
+
+    #[tokio::test]
+    async fn skip_missing_child() {
+        for policy in [
+            MissingChildPolicy::Skip,
+            MissingChildPolicy::SkipWithWarning,
+        ] {
+            let (transport, arm64) = dead_child(MockResponse::new(StatusCode::NOT_FOUND));
+            let client = Client::builder().transport(transport).build();
+
+            let (got, stats) = measure(
+                client.expand_index_with(&image(), &ExpandOptions::new().on_missing_child(policy)),
+            )
+            .await;
+            let got = got.unwrap();
+
+            assert_eq!(
+                platforms(&got),
+                [
+                    ("linux/amd64".to_string(), true),
+                    ("linux/s390x".to_string(), true),
+                ],
+                "{policy:?}"
+            );
+            assert_eq!(
+                got.skipped,
+                [SkippedChild {
+                    digest: Digest::sha256(arm64.as_bytes()),
+                    platform: Some(Platform::new(OperatingSystem::Linux, Architecture::Arm64)),
+                }]
+            );
+            assert_eq!(stats.skipped_children, 1);
+        }
+    }
+
+    /// Only missing manifests are skipped, a registry that fails is still
+    /// reported in the platform.
+    #[tokio::test]
+    async fn skip_only_missing_children() {
+        let unknown = MockResponse::new(StatusCode::BAD_REQUEST)
+            .body(r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown"}]}"#);
+        let (transport, _) = dead_child(unknown);
+        let client = Client::builder().transport(transport).build();
+        let options = ExpandOptions::new().on_missing_child(MissingChildPolicy::Skip);
+
+        let got = client.expand_index_with(&image(), &options).await.unwrap();
+        assert_eq!(got.platforms.len(), 2);
+        assert_eq!(got.skipped.len(), 1);
+
+        let (transport, _) = dead_child(MockResponse::new(StatusCode::INTERNAL_SERVER_ERROR));
+        let client = Client::builder().transport(transport).build();
+
+        let got = client.expand_index_with(&image(), &options).await.unwrap();
+        assert_eq!(
+            platforms(&got),
+            [
+                ("linux/amd64".to_string(), true),
+                ("linux/arm64".to_string(), false),
+                ("linux/s390x".to_string(), true),
+            ]
+        );
+        assert!(got.skipped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn single_image() {
+        let (transport, manifest) = serve_image(MockTransport::new(), "arm64");
+        let transport = transport.with_response(
+            Method::GET,
+            &format!("{BASE}/manifests/8.9"),
+            MockResponse::new(StatusCode::OK).body(manifest.clone()),
+        );
+
+        let client = Client::builder().transport(transport).build();
+        let got = client.expand_index(&image()).await.unwrap();
+
+        let [platform] = got.platforms.as_slice() else {
+            panic!("expected a single platform");
+        };
+
+        assert_eq!(platform.platform.to_string(), "linux/arm64");
+        assert_eq!(platform.digest, Digest::sha256(manifest.as_bytes()));
+        assert_eq!(platform.size, manifest.len() as u64);
+        assert!(platform.image.is_ok());
+    }
+}
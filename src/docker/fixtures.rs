@@ -0,0 +1,545 @@
+//! Refreshes the manifest fixtures under `resources/` from the registries
+//! they were captured from. The refresh needs network access and is run on
+//! demand:
+//!
+//! ```text
+//! cargo test --lib -- --ignored refresh_fixtures
+//! ```
+//!
+//! Set [`ONLY_ENV`] to refresh only some of the fixtures and [`CHECK_ENV`]
+//! to report stale fixtures without writing them. A fixture whose manifest
+//! no longer deserializes, or deserializes as a different kind of manifest,
+//! fails the refresh and is left untouched.
+
+use std::{
+    collections::HashSet,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+use futures::StreamExt;
+
+use crate::{
+    docker::{
+        Client,
+        Error as ClientError,
+    },
+    Image,
+    Manifest,
+};
+
+/// Environment variable with a comma separated list of fixture paths, only
+/// fixtures whose path contains one of them are refreshed.
+pub const ONLY_ENV: &str = "FIXTURES_ONLY";
+
+/// Environment variable that reports stale fixtures instead of writing them
+/// when set to `1`.
+pub const CHECK_ENV: &str = "FIXTURES_CHECK";
+
+/// How many manifests are fetched at the same time by default.
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Numbers the partial files so refreshes running at the same time do not
+/// share one.
+static PARTIAL_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// The fixtures that are refreshed from a registry. Fixtures that were
+/// written by hand, like the lenient or canonical manifests, are not listed.
+const FIXTURES: &[Fixture] = &[
+    Fixture::new(
+        "docker.io/library/alpine:3.20",
+        "registry/dockerhub/alpine.json",
+        Kind::List,
+    ),
+    Fixture::new(
+        "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+        "registry/github/cosign.json",
+        Kind::List,
+    ),
+    Fixture::new(
+        "registry.access.redhat.com/ubi8:8.9",
+        "registry/redhat/ubi8.json",
+        Kind::List,
+    ),
+    Fixture::new(
+        "mcr.microsoft.com/playwright:v1.48.2-noble",
+        "registry/microsoft/playwright.json",
+        Kind::List,
+    ),
+    Fixture::new(
+        "ghcr.io/aquasecurity/trivy:0.52.0",
+        "manifest/list/trivy.json",
+        Kind::List,
+    ),
+    Fixture::new(
+        "quay.io/openshift-community-operators/external-secrets-operator:v0.9.9",
+        "manifest/single/external-secrets-operator.json",
+        Kind::Single,
+    ),
+];
+
+/// A manifest fixture and the image it is fetched from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fixture {
+    image: &'static str,
+
+    /// The path of the fixture relative to `resources/`.
+    path: &'static str,
+
+    /// The kind of manifest the fixture holds.
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Image,
+    List,
+
+    /// Schema 1 manifests are signed over their exact bytes and are written
+    /// as they were received.
+    Single,
+}
+
+/// What a refresh does, see [`Config::from_env`].
+#[derive(Debug, Clone)]
+struct Config {
+    /// The directory the fixture paths are relative to.
+    root: PathBuf,
+    fixtures: Vec<Fixture>,
+    concurrency: usize,
+
+    /// Reports stale fixtures as [`Outcome::Stale`] instead of writing them.
+    check: bool,
+}
+
+#[derive(Debug)]
+enum Error {
+    DuplicatePath(&'static str),
+    ParseImage(&'static str, crate::image::FromStrError),
+    Fetch(&'static str, Box<ClientError>),
+    Deserialize(&'static str, serde_json::Error),
+    WrongKind {
+        path: &'static str,
+        expected: Kind,
+        got: Kind,
+    },
+    Read(PathBuf, std::io::Error),
+    Write(PathBuf, std::io::Error),
+}
+
+/// What happened to a fixture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Unchanged,
+    Updated,
+    Created,
+
+    /// The fixture differs from the registry but was not written, see
+    /// [`Config::check`].
+    Stale,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Image => write!(f, "image manifest"),
+            Self::List => write!(f, "manifest list"),
+            Self::Single => write!(f, "schema 1 manifest"),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicatePath(path) => write!(f, "fixture {path} is listed more than once"),
+            Self::ParseImage(image, e) => write!(f, "failed to parse image {image}: {e}"),
+            Self::Fetch(image, e) => write!(f, "failed to fetch manifest of {image}: {e}"),
+            Self::Deserialize(path, e) => {
+                write!(f, "manifest for {path} no longer deserializes: {e}")
+            }
+            Self::WrongKind {
+                path,
+                expected,
+                got,
+            } => write!(f, "expected a {expected} for {path} but got a {got}"),
+            Self::Read(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            Self::Write(path, e) => write!(f, "failed to write {}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Fixture {
+    const fn new(image: &'static str, path: &'static str, kind: Kind) -> Self {
+        Self { image, path, kind }
+    }
+}
+
+impl Kind {
+    fn of(manifest: &Manifest) -> Self {
+        match manifest {
+            Manifest::Image(_) => Self::Image,
+            Manifest::List(_) => Self::List,
+            Manifest::Single(_) => Self::Single,
+        }
+    }
+}
+
+impl Config {
+    /// All fixtures under the `resources/` directory of the crate, narrowed
+    /// down by [`ONLY_ENV`] and checked only if [`CHECK_ENV`] is `1`.
+    fn from_env() -> Self {
+        let only = std::env::var(ONLY_ENV).unwrap_or_default();
+        let only: Vec<&str> = only
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .collect();
+
+        let fixtures = FIXTURES
+            .iter()
+            .filter(|fixture| {
+                only.is_empty() || only.iter().any(|path| fixture.path.contains(path))
+            })
+            .copied()
+            .collect();
+
+        Self {
+            root: Path::new(env!("CARGO_MANIFEST_DIR")).join("resources"),
+            fixtures,
+            concurrency: DEFAULT_CONCURRENCY,
+            check: std::env::var(CHECK_ENV).is_ok_and(|value| value == "1"),
+        }
+    }
+
+    /// Fails if two fixtures would be written to the same file.
+    fn validate(&self) -> Result<(), Error> {
+        let mut seen = HashSet::new();
+
+        for fixture in &self.fixtures {
+            if !seen.insert(fixture.path) {
+                return Err(Error::DuplicatePath(fixture.path));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Refreshes every fixture of `config`, up to [`Config::concurrency`] at the
+/// same time. Returns one result per fixture in the order of the config.
+async fn refresh(
+    client: &Client,
+    config: &Config,
+) -> Result<Vec<(Fixture, Result<Outcome, Error>)>, Error> {
+    config.validate()?;
+
+    let results = futures::stream::iter(config.fixtures.iter().copied())
+        .map(|fixture| async move { (fixture, refresh_one(client, config, fixture).await) })
+        .buffered(config.concurrency.max(1))
+        .collect()
+        .await;
+
+    Ok(results)
+}
+
+async fn refresh_one(client: &Client, config: &Config, fixture: Fixture) -> Result<Outcome, Error> {
+    let image: Image = fixture
+        .image
+        .parse()
+        .map_err(|e| Error::ParseImage(fixture.image, e))?;
+
+    let raw = client
+        .get_manifest_raw(&image)
+        .await
+        .map_err(|e| Error::Fetch(fixture.image, Box::new(e)))?;
+
+    let content = match fixture.kind {
+        Kind::Single => raw.body.to_vec(),
+        Kind::Image | Kind::List => {
+            normalize(&raw.body).map_err(|e| Error::Deserialize(fixture.path, e))?
+        }
+    };
+
+    // The fixture is checked as it is written, that is what the tests read.
+    check_kind(fixture, &content)?;
+
+    let path = config.root.join(fixture.path);
+
+    let existing = match std::fs::read(&path) {
+        Ok(existing) => Some(existing),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(Error::Read(path, e)),
+    };
+
+    let outcome = match existing {
+        Some(existing) if existing == content => return Ok(Outcome::Unchanged),
+        _ if config.check => return Ok(Outcome::Stale),
+        Some(_) => Outcome::Updated,
+        None => Outcome::Created,
+    };
+
+    write_atomic(&path, &content).map_err(|e| Error::Write(path, e))?;
+
+    Ok(outcome)
+}
+
+/// Fails if `content` does not deserialize into the kind of manifest the
+/// fixture holds.
+fn check_kind(fixture: Fixture, content: &[u8]) -> Result<(), Error> {
+    let manifest: Manifest =
+        serde_json::from_slice(content).map_err(|e| Error::Deserialize(fixture.path, e))?;
+
+    let got = Kind::of(&manifest);
+    if got != fixture.kind {
+        return Err(Error::WrongKind {
+            path: fixture.path,
+            expected: fixture.kind,
+            got,
+        });
+    }
+
+    Ok(())
+}
+
+/// Pretty prints `body` with the keys of every object sorted, so a refresh
+/// only shows up in a diff if the content changed. Arrays keep their order,
+/// the order of layers and manifests matters.
+fn normalize(body: &[u8]) -> Result<Vec<u8>, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_slice(body)?;
+
+    let mut content = serde_json::to_vec_pretty(&sort_keys(value))?;
+    content.push(b'\n');
+
+    Ok(content)
+}
+
+/// `serde_json` only sorts keys without its `preserve_order` feature, which
+/// another dependency might enable.
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let mut entries: Vec<(String, serde_json::Value)> = object.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+        }
+
+        value => value,
+    }
+}
+
+/// Writes `content` to a partial file next to `path` and renames it, so a
+/// refresh never leaves a partial fixture behind and refreshes running at
+/// the same time do not interleave.
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let partial = path.with_extension(format!(
+        "{}-{}.partial",
+        std::process::id(),
+        PARTIAL_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = std::fs::write(&partial, content).and_then(|()| std::fs::rename(&partial, path));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+
+    result
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        check_kind,
+        normalize,
+        refresh,
+        Config,
+        Error,
+        Fixture,
+        Kind,
+        Outcome,
+        FIXTURES,
+    };
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+    };
+
+    const LIST: &str = include_str!("../../resources/manifest/list/example.json");
+    const IMAGE: &str = include_str!("../../resources/manifest/image/example.json");
+
+    fn config(root: &std::path::Path, fixtures: &[Fixture]) -> Config {
+        Config {
+            root: root.to_path_buf(),
+            fixtures: fixtures.to_vec(),
+            concurrency: 2,
+            check: false,
+        }
+    }
+
+    fn transport() -> MockTransport {
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                "https://registry.k8s.io/v2/pause/manifests/3.9",
+                MockResponse::new(StatusCode::OK).body(LIST),
+            )
+            .with_response(
+                Method::GET,
+                "https://registry.k8s.io/v2/pause/manifests/3.10",
+                MockResponse::new(StatusCode::OK).body(IMAGE),
+            )
+    }
+
+    const PAUSE: Fixture = Fixture::new("registry.k8s.io/pause:3.9", "k8s/pause.json", Kind::List);
+
+    /// Every fixture that is refreshed still deserializes into the kind of
+    /// manifest it is listed with.
+    #[test]
+    fn committed_fixtures_deserialize() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+
+        for fixture in FIXTURES {
+            let content = std::fs::read(root.join(fixture.path)).unwrap();
+
+            check_kind(*fixture, &content).unwrap();
+        }
+    }
+
+    #[test]
+    fn normalize_sorts_keys() {
+        let got = normalize(br#"{"b":{"z":1,"a":[{"y":2,"x":3},"v"]},"a":null}"#).unwrap();
+
+        assert_eq!(
+            String::from_utf8(got.clone()).unwrap(),
+            "{\n  \"a\": null,\n  \"b\": {\n    \"a\": [\n      {\n        \"x\": 3,\n        \
+             \"y\": 2\n      },\n      \"v\"\n    ],\n    \"z\": 1\n  }\n}\n"
+        );
+        assert_eq!(normalize(&got).unwrap(), got);
+    }
+
+    #[tokio::test]
+    async fn writes_and_keeps_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), &[PAUSE]);
+        let client = Client::builder().transport(transport()).build();
+
+        let first = refresh(&client, &config).await.unwrap();
+        assert!(matches!(first[0].1, Ok(Outcome::Created)));
+
+        let written = std::fs::read(dir.path().join(PAUSE.path)).unwrap();
+        assert_eq!(written, normalize(LIST.as_bytes()).unwrap());
+
+        let second = refresh(&client, &config).await.unwrap();
+        assert!(matches!(second[0].1, Ok(Outcome::Unchanged)));
+
+        std::fs::write(dir.path().join(PAUSE.path), LIST).unwrap();
+        let check = Config {
+            check: true,
+            ..config.clone()
+        };
+        let third = refresh(&client, &check).await.unwrap();
+        assert!(matches!(third[0].1, Ok(Outcome::Stale)));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(PAUSE.path)).unwrap(),
+            LIST
+        );
+
+        let fourth = refresh(&client, &config).await.unwrap();
+        assert!(matches!(fourth[0].1, Ok(Outcome::Updated)));
+
+        let files = std::fs::read_dir(dir.path().join("k8s")).unwrap().count();
+        assert_eq!(files, 1, "no partial files are left behind");
+    }
+
+    #[tokio::test]
+    async fn changed_kind_is_not_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let changed = Fixture::new("registry.k8s.io/pause:3.10", "k8s/changed.json", Kind::List);
+        let config = config(dir.path(), &[changed, PAUSE]);
+        let client = Client::builder().transport(transport()).build();
+
+        let got = refresh(&client, &config).await.unwrap();
+
+        assert!(
+            matches!(
+                &got[0].1,
+                Err(Error::WrongKind {
+                    expected: Kind::List,
+                    got: Kind::Image,
+                    ..
+                })
+            ),
+            "{:?}",
+            got[0].1
+        );
+        assert!(matches!(got[1].1, Ok(Outcome::Created)));
+        assert!(!dir.path().join(changed.path).exists());
+    }
+
+    #[tokio::test]
+    async fn duplicate_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), &[PAUSE, PAUSE]);
+        let client = Client::builder().transport(transport()).build();
+
+        let got = refresh(&client, &config).await.unwrap_err();
+
+        assert!(
+            matches!(got, Error::DuplicatePath("k8s/pause.json")),
+            "{got}"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access to the registries of the fixtures"]
+    async fn refresh_fixtures() {
+        let config = Config::from_env();
+        let client = Client::new();
+
+        let results = refresh(&client, &config).await.unwrap();
+
+        let failures: Vec<String> = results
+            .into_iter()
+            .filter_map(|(fixture, result)| match result {
+                Ok(Outcome::Stale) => Some(format!("{} is stale", fixture.path)),
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            })
+            .collect();
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{
+    future::Either,
+    Stream,
+};
+use http::Method;
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    docker::{
+        blob,
+        content_length,
+        progress::NoProgress,
+        transport::Request,
+        Client,
+        Error,
+    },
+    manifest,
+    Digest,
+    Image,
+};
+
+impl Client {
+    /// Streams the compressed blob of `layer` like [`Client::get_blob`].
+    /// Foreign layers, like the base layers of older Windows images, list
+    /// `urls` they are distributed from. Those are tried in order before the
+    /// registry and are sent without the credentials of the registry. URLs
+    /// that fail or do not answer with success are skipped.
+    ///
+    /// The content is verified against the digest of the layer no matter
+    /// where it came from, a mismatch is not retried at the next URL.
+    ///
+    /// # Errors
+    /// Returns an error if the digest of the layer is invalid.
+    /// Returns an error if a URL serves a blob larger than the configured
+    /// limit, see [`crate::ClientBuilder::max_blob_size`].
+    /// Returns an error if none of the URLs serves the blob and it can not be
+    /// fetched from the registry.
+    pub async fn get_layer_blob(
+        &self,
+        image: &Image,
+        layer: &manifest::Layer,
+    ) -> Result<impl Stream<Item = Result<Bytes, blob::Error>> + Send + 'static, Error> {
+        let digest = layer.digest.clone();
+
+        digest
+            .algorithm()
+            .map_err(Error::UnsupportedDigestAlgorithm)?;
+
+        if !self.inner.offline {
+            for url in layer.urls.iter().flatten() {
+                if let Some(stream) = self.fetch_foreign(url, &digest, layer.size).await? {
+                    return Ok(Either::Left(stream));
+                }
+            }
+        }
+
+        let stream = self
+            .fetch_blob(
+                image,
+                &digest,
+                self.inner.max_blob_size,
+                Some(layer.size),
+                Arc::new(NoProgress),
+            )
+            .await?;
+
+        Ok(Either::Right(stream))
+    }
+
+    /// Fetches a foreign layer of `size` bytes from `url`. Returns `None` if
+    /// the URL does not serve the blob.
+    async fn fetch_foreign(
+        &self,
+        url: &Url,
+        digest: &Digest,
+        size: u64,
+    ) -> Result<Option<impl Stream<Item = Result<Bytes, blob::Error>> + Send + 'static>, Error>
+    {
+        let response = match self.execute(Request::new(Method::GET, url.clone())).await {
+            Ok(response) if response.status.is_success() => response,
+
+            Ok(response) => {
+                warn!(
+                    url = %url,
+                    status = %response.status,
+                    "foreign layer url did not serve the blob, trying next url"
+                );
+                return Ok(None);
+            }
+
+            Err(e) => {
+                warn!(
+                    url = %url,
+                    error = %e,
+                    "foreign layer request failed, trying next url"
+                );
+                return Ok(None);
+            }
+        };
+
+        let url = response.url.clone();
+        let total = content_length(&response.headers);
+
+        if let (Some(total), Some(limit)) = (total, self.inner.max_blob_size) {
+            if total > limit {
+                return Err(Error::BodyTooLarge { limit, url });
+            }
+        }
+
+        Ok(Some(self.blob_body(
+            response,
+            digest,
+            self.inner.max_blob_size,
+            Some(size),
+        )))
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use futures::TryStreamExt;
+    use http::{
+        header::AUTHORIZATION,
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        manifest::{
+            self,
+            Architecture,
+            OperatingSystem,
+            Platform,
+            Size,
+        },
+        Client,
+        Digest,
+        Image,
+        Manifest,
+    };
+
+    const CONTENT: &str = "foreign layer";
+    const FIRST: &str = "https://go.microsoft.com/fwlink/?linkid=1";
+    const SECOND: &str = "https://mcr.microsoft.com/v2/windows/servercore/blobs/second";
+
+    fn digest() -> Digest {
+        Digest::sha256(CONTENT.as_bytes())
+    }
+
+    fn layer() -> manifest::Layer {
+        manifest::Layer {
+            urls: Some(vec![FIRST.parse().unwrap(), SECOND.parse().unwrap()]),
+            ..manifest::Layer::new(
+                "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip",
+                CONTENT.len() as u64,
+                digest(),
+            )
+        }
+    }
+
+    async fn read(
+        stream: impl futures::Stream<Item = Result<bytes::Bytes, crate::docker::blob::Error>>,
+    ) -> String {
+        let chunks: Vec<bytes::Bytes> = stream.try_collect().await.unwrap();
+
+        String::from_utf8(chunks.concat()).unwrap()
+    }
+
+    fn requested_urls(transport: &MockTransport) -> Vec<String> {
+        transport
+            .requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn urls_first() {
+        let transport = MockTransport::new()
+            .with_response(Method::GET, FIRST, MockResponse::new(StatusCode::NOT_FOUND))
+            .with_response(
+                Method::GET,
+                SECOND,
+                MockResponse::new(StatusCode::OK).body(CONTENT),
+            );
+
+        let client = Client::builder().transport(transport.clone()).build();
+        let image: Image = "mcr.microsoft.com/windows/servercore:ltsc2019"
+            .parse()
+            .unwrap();
+
+        let got = read(client.get_layer_blob(&image, &layer()).await.unwrap()).await;
+
+        assert_eq!(got, CONTENT);
+        assert_eq!(requested_urls(&transport), [FIRST, SECOND]);
+    }
+
+    #[tokio::test]
+    async fn registry_second() {
+        const TOKEN: &str =
+            "https://ghcr.io/token?scope=repository:example/servercore:pull&service=ghcr.io";
+        let blob = format!("https://ghcr.io/v2/example/servercore/blobs/{}", digest());
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                FIRST,
+                MockResponse::new(StatusCode::SERVICE_UNAVAILABLE),
+            )
+            .with_response(
+                Method::GET,
+                SECOND,
+                MockResponse::new(StatusCode::FORBIDDEN),
+            )
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                &blob,
+                MockResponse::new(StatusCode::OK).body(CONTENT),
+            );
+
+        let client = Client::builder().transport(transport.clone()).build();
+        let image: Image = "ghcr.io/example/servercore:ltsc2019".parse().unwrap();
+
+        let got = read(client.get_layer_blob(&image, &layer()).await.unwrap()).await;
+
+        assert_eq!(got, CONTENT);
+        assert_eq!(
+            requested_urls(&transport),
+            [FIRST, SECOND, TOKEN, blob.as_str()]
+        );
+
+        // Only the registry gets the credentials.
+        let authorized: Vec<bool> = transport
+            .requests()
+            .iter()
+            .map(|request| request.headers.contains_key(AUTHORIZATION))
+            .collect();
+        assert_eq!(authorized, [false, false, false, true]);
+    }
+
+    /// Resolves the nanoserver list to the image for Windows Server 2022 and
+    /// reads its foreign base layer.
+    #[tokio::test]
+    async fn windows_end_to_end() {
+        const LIST: &str = include_str!("../../resources/manifest/list/nanoserver.json");
+        const BASE: &str = "https://mcr.microsoft.com/v2/windows/nanoserver";
+
+        let list: manifest::List = serde_json::from_str(LIST).unwrap();
+        let ltsc2022 = &list.manifests[1].digest;
+
+        let mut image = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "config": {
+                "mediaType": "application/vnd.docker.container.image.v1+json",
+                "size": 2,
+                "digest": Digest::sha256(b"{}").to_string()
+            },
+            "layers": [layer()]
+        });
+        image["layers"][0]["size"] = serde_json::json!(5_368_709_120_u64);
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/ltsc2022"),
+                MockResponse::new(StatusCode::OK).body(LIST),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{ltsc2022}"),
+                MockResponse::new(StatusCode::OK).body(image.to_string()),
+            )
+            .with_response(
+                Method::GET,
+                FIRST,
+                MockResponse::new(StatusCode::OK).body(CONTENT),
+            );
+
+        // The digests in the fixture belong to the manifests on the
+        // registry, not to the one served here.
+        let client = Client::builder()
+            .transport(transport)
+            .verify_descriptors(false)
+            .allow_digest_mismatch(true)
+            .build();
+        let reference: Image = "mcr.microsoft.com/windows/nanoserver:ltsc2022"
+            .parse()
+            .unwrap();
+        let platform = Platform::new(OperatingSystem::Windows, Architecture::Amd64)
+            .with_os_version("10.0.20348");
+
+        let got = client
+            .get_manifest_for_platform(&reference, &platform)
+            .await
+            .unwrap();
+
+        let Manifest::Image(manifest) = got.manifest else {
+            panic!("expected an image manifest");
+        };
+        assert_eq!(Size(manifest.total_size()).to_string(), "5.00 GiB");
+
+        let content = read(
+            client
+                .get_layer_blob(&reference, &manifest.layers[0])
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(content, CONTENT);
+    }
+}
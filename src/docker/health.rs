@@ -0,0 +1,413 @@
+use std::time::Duration;
+
+use http::{
+    header::{
+        HeaderMap,
+        ACCEPT,
+    },
+    Method,
+    StatusCode,
+};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::{
+    docker::{
+        auth::TokenRequest,
+        negotiation::ManifestPreference,
+        read_body,
+        token::{
+            self,
+            CacheKey,
+            Token,
+        },
+        transport::Request,
+        Client,
+        Error,
+    },
+    image::append_segments,
+    Image,
+    Registry,
+};
+
+/// The result of [`Client::health_check`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    pub registry: Registry,
+
+    /// How much longer the first request to `/v2/` took than the second
+    /// one, which is roughly the time to resolve the host and to connect.
+    /// Close to zero if the client already had a connection to the
+    /// registry.
+    pub connect: Stage,
+
+    /// The round trip time of a request to `/v2/` over an open connection.
+    pub ping: Stage,
+
+    /// Fetching an anonymous token for a public repository. Skipped for
+    /// registries that do not use tokens.
+    pub token: Stage,
+
+    /// A `HEAD` request for the manifest of a small public image. Skipped for
+    /// registries no such image is known for.
+    pub manifest: Stage,
+}
+
+/// The outcome and duration of one step of a [`HealthReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stage {
+    pub outcome: Outcome,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Ok,
+    Skipped,
+    Failed(String),
+}
+
+impl HealthReport {
+    /// Returns true if no stage failed.
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        [&self.connect, &self.ping, &self.token, &self.manifest]
+            .iter()
+            .all(|stage| !matches!(stage.outcome, Outcome::Failed(_)))
+    }
+}
+
+impl Stage {
+    fn ok(latency: Duration) -> Self {
+        Self {
+            outcome: Outcome::Ok,
+            latency: Some(latency),
+        }
+    }
+
+    fn skipped() -> Self {
+        Self {
+            outcome: Outcome::Skipped,
+            latency: None,
+        }
+    }
+
+    fn failed(error: impl std::fmt::Display, latency: Option<Duration>) -> Self {
+        Self {
+            outcome: Outcome::Failed(error.to_string()),
+            latency,
+        }
+    }
+}
+
+impl Client {
+    /// Probes how healthy and how fast `registry` is. Requests `/v2/` twice
+    /// to separate connecting from the round trip time, fetches an anonymous
+    /// token for a well-known public repository and requests the manifest of
+    /// a small public image with it. Requests go to the registry itself, not
+    /// to its mirrors, and bypass the token cache.
+    ///
+    /// Failing stages are reported in the [`HealthReport`], later stages are
+    /// still run.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline.
+    /// Returns an error if the URL of the registry is invalid.
+    #[tracing::instrument(name = "health_check", skip_all, fields(registry = %registry))]
+    pub async fn health_check(&self, registry: &Registry) -> Result<HealthReport, Error> {
+        self.check_registry_policy(registry)?;
+
+        if self.inner.offline {
+            return Err(Error::Offline);
+        }
+
+        let base = self
+            .inner
+            .mirrors
+            .upstream(registry)
+            .map_err(Error::InvalidPingUrl)?;
+        let ping_url = base.join("v2/").map_err(Error::InvalidPingUrl)?;
+
+        let first = self
+            .probe_ping(Request::new(Method::GET, ping_url.clone()))
+            .await;
+        let ping = self.probe_ping(Request::new(Method::GET, ping_url)).await;
+
+        let connect = match (&first.outcome, first.latency, ping.latency) {
+            (Outcome::Ok, Some(first), Some(second)) => Stage::ok(first.saturating_sub(second)),
+            _ => first,
+        };
+
+        let probe = probe_image(registry);
+
+        let (token, mut headers) = match &probe {
+            Some(image) => self.probe_token(image).await,
+            None => (Stage::skipped(), HeaderMap::new()),
+        };
+
+        let manifest = match &probe {
+            Some(image) => {
+                let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
+                let url = append_segments(base, &segments);
+
+                headers.insert(
+                    ACCEPT,
+                    ManifestPreference::Any
+                        .accept()
+                        .parse()
+                        .map_err(Error::ParseManifestAcceptHeader)?,
+                );
+
+                self.probe_manifest(Request::new(Method::HEAD, url).headers(headers))
+                    .await
+            }
+
+            None => Stage::skipped(),
+        };
+
+        Ok(HealthReport {
+            registry: registry.clone(),
+            connect,
+            ping,
+            token,
+            manifest,
+        })
+    }
+
+    /// An answer to `/v2/` is fine as long as the registry asks for
+    /// authentication at most.
+    async fn probe_ping(&self, request: Request) -> Stage {
+        let start = Instant::now();
+
+        match self.execute(request).await {
+            Ok(response)
+                if response.status.is_success() || response.status == StatusCode::UNAUTHORIZED =>
+            {
+                Stage::ok(start.elapsed())
+            }
+
+            Ok(response) => Stage::failed(response.status, Some(start.elapsed())),
+            Err(e) => Stage::failed(e, Some(start.elapsed())),
+        }
+    }
+
+    /// Fetches a token for pulling `image` and returns the headers to use
+    /// it.
+    async fn probe_token(&self, image: &Image) -> (Stage, HeaderMap) {
+        let request = token::request(&image.registry, [&CacheKey::from(image)]);
+
+        let url = match request.as_ref().map(TokenRequest::url) {
+            Some(Ok(url)) => url,
+            Some(Err(e)) => return (Stage::failed(e, None), HeaderMap::new()),
+            None => return (Stage::skipped(), HeaderMap::new()),
+        };
+
+        let start = Instant::now();
+
+        let response = match self.execute(Request::new(Method::GET, url)).await {
+            Ok(response) if response.status.is_success() => response,
+            Ok(response) => {
+                return (
+                    Stage::failed(response.status, Some(start.elapsed())),
+                    HeaderMap::new(),
+                )
+            }
+            Err(e) => return (Stage::failed(e, Some(start.elapsed())), HeaderMap::new()),
+        };
+
+        let headers = async {
+            let body = read_body(
+                response,
+                self.inner.max_manifest_size,
+                Error::ExtractTokenBody,
+            )
+            .await?;
+
+            let token: Token = serde_json::from_slice(&body).map_err(|e| {
+                Error::DeserializeToken(e, String::from_utf8_lossy(&body).into_owned())
+            })?;
+
+            token.try_into().map_err(Error::ParseAuthorizationHeader)
+        }
+        .await;
+
+        match headers {
+            Ok(headers) => (Stage::ok(start.elapsed()), headers),
+            Err(e) => (Stage::failed(e, Some(start.elapsed())), HeaderMap::new()),
+        }
+    }
+
+    async fn probe_manifest(&self, request: Request) -> Stage {
+        let start = Instant::now();
+
+        match self.execute(request).await {
+            Ok(response) if response.status.is_success() => Stage::ok(start.elapsed()),
+            Ok(response) => Stage::failed(response.status, Some(start.elapsed())),
+            Err(e) => Stage::failed(e, Some(start.elapsed())),
+        }
+    }
+}
+
+/// A small public image of `registry` that is unlikely to go away.
+fn probe_image(registry: &Registry) -> Option<Image> {
+    let image = match registry {
+        Registry::DockerHub => "docker.io/library/alpine:latest",
+        Registry::Github => "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+        Registry::Google => "gcr.io/distroless/static:latest",
+        Registry::K8s => "registry.k8s.io/pause:3.9",
+        Registry::Microsoft => "mcr.microsoft.com/mcr/hello-world:latest",
+        Registry::Quay => "quay.io/prometheus/busybox:latest",
+        Registry::RedHat => "registry.access.redhat.com/ubi8:latest",
+        Registry::Custom(_) => return None,
+    };
+
+    image.parse().ok()
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::time::Duration;
+
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::docker::transport::{
+        MockResponse,
+        MockTransport,
+    };
+
+    const PING: &str = "https://ghcr.io/v2/";
+    const TOKEN: &str =
+        "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io";
+    const MANIFEST: &str = "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0";
+
+    fn without_latency(stage: &Stage) -> Stage {
+        Stage {
+            latency: None,
+            ..stage.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn healthy() {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                PING,
+                MockResponse::new(StatusCode::UNAUTHORIZED),
+            )
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"ghcr-token"}"#),
+            )
+            .with_response(Method::HEAD, MANIFEST, MockResponse::new(StatusCode::OK));
+
+        let client = Client::builder().transport(transport).build();
+        let report = client.health_check(&Registry::Github).await.unwrap();
+
+        assert!(report.is_healthy());
+        for stage in [
+            &report.connect,
+            &report.ping,
+            &report.token,
+            &report.manifest,
+        ] {
+            assert_eq!(stage.outcome, Outcome::Ok);
+            assert!(stage.latency.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn token_endpoint_down() {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                PING,
+                MockResponse::new(StatusCode::UNAUTHORIZED),
+            )
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::SERVICE_UNAVAILABLE),
+            )
+            .with_response(
+                Method::HEAD,
+                MANIFEST,
+                MockResponse::new(StatusCode::UNAUTHORIZED),
+            );
+
+        let client = Client::builder().transport(transport).build();
+        let report = client.health_check(&Registry::Github).await.unwrap();
+
+        assert!(!report.is_healthy());
+        assert_eq!(report.ping.outcome, Outcome::Ok);
+        assert!(matches!(report.token.outcome, Outcome::Failed(_)));
+        assert_eq!(
+            without_latency(&report.manifest),
+            Stage::failed(StatusCode::UNAUTHORIZED, None)
+        );
+    }
+
+    #[tokio::test]
+    async fn registry_down() {
+        let client = Client::builder().transport(MockTransport::new()).build();
+        let report = client.health_check(&Registry::K8s).await.unwrap();
+
+        assert!(!report.is_healthy());
+        assert!(matches!(report.connect.outcome, Outcome::Failed(_)));
+        assert!(matches!(report.ping.outcome, Outcome::Failed(_)));
+        assert_eq!(report.token, Stage::skipped());
+        assert!(matches!(report.manifest.outcome, Outcome::Failed(_)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latency() {
+        const K8S_PING: &str = "https://registry.k8s.io/v2/";
+        const K8S_MANIFEST: &str = "https://registry.k8s.io/v2/pause/manifests/3.9";
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                K8S_PING,
+                MockResponse::new(StatusCode::OK).delay(Duration::from_millis(20)),
+            )
+            .with_response(
+                Method::HEAD,
+                K8S_MANIFEST,
+                MockResponse::new(StatusCode::OK).delay(Duration::from_millis(50)),
+            );
+
+        let client = Client::builder().transport(transport).build();
+        let report = client.health_check(&Registry::K8s).await.unwrap();
+
+        assert!(report.is_healthy());
+        assert_eq!(report.token, Stage::skipped());
+        assert!(report.ping.latency.unwrap() >= Duration::from_millis(20));
+        assert!(report.manifest.latency.unwrap() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn serialize() {
+        let report = HealthReport {
+            registry: Registry::Github,
+            connect: Stage::ok(Duration::from_millis(35)),
+            ping: Stage::ok(Duration::from_millis(12)),
+            token: Stage::failed(
+                StatusCode::SERVICE_UNAVAILABLE,
+                Some(Duration::from_millis(8)),
+            ),
+            manifest: Stage::skipped(),
+        };
+
+        insta::assert_json_snapshot!(report);
+    }
+}
@@ -0,0 +1,102 @@
+//! Registries that reject overwriting a tag of a repository with immutable
+//! tags, see [`crate::Client::is_tag_immutable_error`].
+
+use http::StatusCode;
+
+use crate::docker::FailedResponse;
+
+/// A failed response in the format of the distribution specification, with
+/// the message that tells an immutable tag apart from other errors with the
+/// same code.
+#[derive(Debug, serde::Deserialize)]
+struct ErrorBody {
+    errors: Vec<ErrorBodyEntry>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ErrorBodyEntry {
+    code: String,
+
+    #[serde(default)]
+    message: String,
+}
+
+/// Returns true if `response` rejects a manifest push because the tag is
+/// immutable. ECR answers with `400` and the `TAG_INVALID` code, Harbor with
+/// `412` and the `PRECONDITION` code. Both codes are used for other errors as
+/// well, so the message has to mention immutability.
+pub(super) fn is_tag_immutable(response: &FailedResponse) -> bool {
+    let code = match response.status {
+        StatusCode::BAD_REQUEST => "TAG_INVALID",
+        StatusCode::PRECONDITION_FAILED => "PRECONDITION",
+        _ => return false,
+    };
+
+    let Ok(body) = serde_json::from_str::<ErrorBody>(&response.body) else {
+        return false;
+    };
+
+    body.errors
+        .iter()
+        .any(|entry| entry.code == code && entry.message.to_lowercase().contains("immutable"))
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        header::HeaderMap,
+        StatusCode,
+    };
+
+    use super::*;
+
+    fn response(status: StatusCode, body: &str) -> FailedResponse {
+        FailedResponse::new(
+            status,
+            "https://registry.example.com/v2/app/manifests/1.0"
+                .parse()
+                .unwrap(),
+            &HeaderMap::new(),
+            body.to_string(),
+        )
+    }
+
+    #[test]
+    fn registries() {
+        for (status, body, expected) in [
+            (
+                StatusCode::BAD_REQUEST,
+                include_str!("../../resources/registry/ecr/error-tag-immutable.json"),
+                true,
+            ),
+            (
+                StatusCode::PRECONDITION_FAILED,
+                include_str!("../../resources/registry/harbor/error-tag-immutable.json"),
+                true,
+            ),
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"errors": [{"code": "TAG_INVALID", "message": "manifest tag did not match URI"}]}"#,
+                false,
+            ),
+            (
+                StatusCode::PRECONDITION_FAILED,
+                r#"{"errors": [{"code": "PRECONDITION", "message": "If-Match does not match"}]}"#,
+                false,
+            ),
+            (
+                StatusCode::FORBIDDEN,
+                include_str!("../../resources/registry/ecr/error-tag-immutable.json"),
+                false,
+            ),
+            (StatusCode::BAD_REQUEST, "not json", false),
+        ] {
+            assert_eq!(
+                is_tag_immutable(&response(status, body)),
+                expected,
+                "{body}"
+            );
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+};
+
+use http::Method;
+use tokio::sync::watch;
+use url::Url;
+
+use crate::docker::{
+    token,
+    Error,
+};
+
+/// The result a request shares with the callers that waited for it.
+type Shared<T> = Result<T, Arc<Error>>;
+
+type Requests<T> = Arc<Mutex<HashMap<RequestKey, watch::Receiver<Option<Shared<T>>>>>>;
+
+/// Identifies requests that can share a response: the same method to the
+/// same URLs with the same credentials. `urls` are all endpoints a request
+/// may try, like the mirrors of a registry, and `scope` is the repository
+/// whose token authorizes the request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct RequestKey {
+    method: Method,
+    urls: Vec<Url>,
+    scope: Option<token::CacheKey>,
+}
+
+/// `InFlight` coalesces identical requests. The first caller for a key sends
+/// the request while later callers wait for it and get a clone of its
+/// result. Keys are removed as soon as the request is done, so a request
+/// that starts afterwards is sent again.
+#[derive(Debug)]
+pub(super) struct InFlight<T> {
+    requests: Requests<T>,
+}
+
+/// What a caller of [`InFlight::run`] has to do.
+enum Role<T> {
+    /// Send the request and publish the result.
+    Leader(watch::Sender<Option<Shared<T>>>),
+
+    /// Wait for the result of the leader.
+    Follower(watch::Receiver<Option<Shared<T>>>),
+}
+
+/// Removes the key of the leader when its request is done or dropped.
+struct Guard<T> {
+    key: RequestKey,
+    requests: Requests<T>,
+}
+
+impl RequestKey {
+    pub(super) fn new(method: Method, urls: Vec<Url>, scope: Option<token::CacheKey>) -> Self {
+        Self {
+            method,
+            urls,
+            scope,
+        }
+    }
+}
+
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        Self {
+            requests: Arc::default(),
+        }
+    }
+}
+
+impl<T> Clone for InFlight<T> {
+    fn clone(&self) -> Self {
+        Self {
+            requests: Arc::clone(&self.requests),
+        }
+    }
+}
+
+impl<T: Clone> InFlight<T> {
+    /// Runs `request` unless an identical request is in flight, in which case
+    /// its result is returned instead. If the first caller is dropped before
+    /// its request is done one of the waiting callers sends it again.
+    ///
+    /// When other callers waited for a failed request, every caller gets the
+    /// error wrapped in [`Error::Shared`]. A caller that was alone gets the
+    /// error as it is.
+    pub(super) async fn run<F, Fut>(&self, key: RequestKey, request: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let sender = loop {
+            match self.join(&key) {
+                Role::Leader(sender) => break sender,
+
+                Role::Follower(mut receiver) => {
+                    // Fails if the leader was dropped, then try again.
+                    if let Ok(result) = receiver.wait_for(Option::is_some).await {
+                        if let Some(shared) = result.as_ref() {
+                            return shared.clone().map_err(Error::Shared);
+                        }
+                    }
+                }
+            }
+        };
+
+        let guard = Guard {
+            key,
+            requests: Arc::clone(&self.requests),
+        };
+
+        let result = request().await;
+
+        // Nobody can join once the key is removed, so the remaining receivers
+        // are exactly the callers that wait for this result.
+        drop(guard);
+
+        if sender.receiver_count() == 0 {
+            return result;
+        }
+
+        let shared = result.map_err(Arc::new);
+        sender.send_replace(Some(shared.clone()));
+
+        shared.map_err(Error::Shared)
+    }
+
+    fn join(&self, key: &RequestKey) -> Role<T> {
+        let mut requests = self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(receiver) = requests.get(key) {
+            return Role::Follower(receiver.clone());
+        }
+
+        let (sender, receiver) = watch::channel(None);
+        requests.insert(key.clone(), receiver);
+
+        Role::Leader(sender)
+    }
+}
+
+impl<T> InFlight<T> {
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+impl<T> Drop for Guard<T> {
+    fn drop(&mut self) {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::{
+        sync::atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        time::Duration,
+    };
+
+    use http::Method;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn key() -> RequestKey {
+        RequestKey::new(
+            Method::GET,
+            vec!["https://registry.k8s.io/v2/app/manifests/1.0"
+                .parse()
+                .unwrap()],
+            None,
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_leader_is_replaced() {
+        let in_flight = InFlight::default();
+        let calls = AtomicUsize::new(0);
+
+        let request = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            Ok(1)
+        };
+
+        let leader = in_flight.run(key(), request);
+        let follower = in_flight.run(key(), request);
+
+        // The leader gives up halfway, the follower has to send the request
+        // on its own.
+        let (leader, follower) = tokio::join!(
+            tokio::time::timeout(Duration::from_millis(500), leader),
+            follower
+        );
+
+        assert!(leader.is_err());
+        assert_eq!(1, follower.unwrap());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        assert_eq!(0, in_flight.len());
+    }
+}
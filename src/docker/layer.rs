@@ -0,0 +1,152 @@
+use std::{
+    pin::Pin,
+    task::{
+        ready,
+        Context,
+        Poll,
+    },
+};
+
+use async_compression::tokio::bufread::GzipDecoder;
+#[cfg(feature = "zstd")]
+use async_compression::tokio::bufread::ZstdDecoder;
+use bytes::Bytes;
+use futures::Stream;
+use tokio::io::{
+    AsyncRead,
+    ReadBuf,
+};
+use tokio_util::io::StreamReader;
+
+use crate::docker::media_types::MediaType;
+
+/// A decompressed layer tarball.
+pub type Tar = Pin<Box<dyn AsyncRead + Send>>;
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownMediaType(String),
+    UnsupportedCompression(Compression),
+}
+
+/// Decoders can fill the read buffer and fail in the same call when the blob
+/// stream errors at its end, which `AsyncRead` does not allow. `Decoded`
+/// decodes into a scratch buffer first so the caller either gets data or an
+/// error.
+struct Decoded<R> {
+    decoder: R,
+    scratch: Vec<u8>,
+}
+
+/// The compression of a layer blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownMediaType(media_type) => {
+                write!(f, "unknown layer media type: {media_type}")
+            }
+            Self::UnsupportedCompression(compression) => write!(
+                f,
+                "layer compression {compression:?} is not supported, enable the zstd feature"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl<R> Decoded<R> {
+    fn new(decoder: R) -> Self {
+        Self {
+            decoder,
+            scratch: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Decoded<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+
+        let wanted = buf.remaining();
+        if this.scratch.len() < wanted {
+            this.scratch.resize(wanted, 0);
+        }
+
+        let mut scratch = ReadBuf::new(&mut this.scratch[..wanted]);
+        ready!(Pin::new(&mut this.decoder).poll_read(cx, &mut scratch))?;
+        buf.put_slice(scratch.filled());
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Compression {
+    /// Returns the compression used by layers with the given media type.
+    ///
+    /// # Errors
+    /// Returns an error if the media type is not a known layer media type.
+    pub fn from_media_type(media_type: &str) -> Result<Self, Error> {
+        match MediaType::from(media_type) {
+            MediaType::OciLayer | MediaType::OciLayerNondistributable => Ok(Self::None),
+
+            MediaType::DockerLayer
+            | MediaType::DockerForeignLayer
+            | MediaType::OciLayerGzip
+            | MediaType::OciLayerNondistributableGzip => Ok(Self::Gzip),
+
+            MediaType::OciLayerZstd | MediaType::OciLayerNondistributableZstd => Ok(Self::Zstd),
+
+            _ => Err(Error::UnknownMediaType(media_type.to_string())),
+        }
+    }
+}
+
+/// Decompresses a layer blob into its tarball.
+///
+/// # Errors
+/// Returns an error if the compression is zstd and the `zstd` feature is not
+/// enabled.
+pub fn decode<S, E>(stream: S, compression: Compression) -> Result<Tar, Error>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<std::io::Error>,
+{
+    let reader = Box::pin(StreamReader::new(stream));
+
+    // Decoders read until the end of the blob instead of stopping after the
+    // first member so errors at the end of the stream, like a digest
+    // mismatch, are not swallowed.
+    match compression {
+        Compression::None => Ok(Box::pin(reader)),
+
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+
+            Ok(Box::pin(Decoded::new(decoder)))
+        }
+
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(reader);
+            decoder.multiple_members(true);
+
+            Ok(Box::pin(Decoded::new(decoder)))
+        }
+
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => Err(Error::UnsupportedCompression(compression)),
+    }
+}
@@ -0,0 +1,623 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tokio::sync::Mutex;
+
+#[cfg(feature = "redis_cache")]
+use redis::AsyncCommands;
+#[cfg(feature = "redis_cache")]
+use tracing::{
+    info_span,
+    warn,
+    Instrument,
+};
+
+use crate::{
+    docker::negotiation::Negotiation,
+    Digest,
+    Image,
+};
+
+/// Prefix of the Redis keys of [`RedisDigestCache`].
+#[cfg(feature = "redis_cache")]
+const REDIS_PREFIX: &str = "manifest";
+
+/// Default number of manifests kept by [`MemoryManifestCache`].
+pub const DEFAULT_MEMORY_CAPACITY: NonZeroUsize = match NonZeroUsize::new(1024) {
+    Some(capacity) => capacity,
+    None => unreachable!(),
+};
+
+/// Default time manifests requested by tag are kept by
+/// [`MemoryManifestCache`].
+pub const DEFAULT_TAG_TTL: Duration = Duration::from_mins(1);
+
+#[derive(Debug)]
+pub enum FetchError {
+    DeserializeEntry(serde_json::Error),
+    #[cfg(feature = "redis_cache")]
+    GetConnection(redis::RedisError),
+    #[cfg(feature = "redis_cache")]
+    GetValue(redis::RedisError),
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    #[cfg(feature = "redis_cache")]
+    GetConnection(redis::RedisError),
+    SerializeEntry(serde_json::Error),
+    #[cfg(feature = "redis_cache")]
+    SetValue(redis::RedisError),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub(super) struct CacheKey {
+    image: Image,
+}
+
+/// A manifest response as it was returned by the registry.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(super) struct Entry {
+    pub(super) body: String,
+    pub(super) content_type: Option<String>,
+    pub(super) digest: Option<String>,
+
+    #[serde(default)]
+    pub(super) negotiation: Negotiation,
+
+    #[serde(default)]
+    pub(super) digest_verified: bool,
+}
+
+/// Hit and miss counters of a manifest cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[async_trait::async_trait]
+pub(super) trait Cache: std::fmt::Debug + Send + Sync + dyn_clone::DynClone {
+    async fn fetch(&self, key: &CacheKey) -> Result<Option<Entry>, FetchError>;
+    async fn store(&self, key: CacheKey, entry: Entry) -> Result<(), StoreError>;
+
+    fn stats(&self) -> Option<Stats> {
+        None
+    }
+}
+
+dyn_clone::clone_trait_object!(Cache);
+
+/// `NoCache` is a manifest cache that does not cache manifests.
+#[derive(Debug, Default, Clone)]
+pub(super) struct NoCache;
+
+/// `MemoryManifestCache` keeps the most recently used manifests in memory.
+/// Manifests requested by digest never change and are kept until they are
+/// evicted, manifests requested by tag expire after the configured TTL.
+#[derive(Debug, Clone)]
+pub struct MemoryManifestCache {
+    capacity: NonZeroUsize,
+    tag_ttl: Duration,
+    entries: Arc<Mutex<MemoryEntries>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryEntries {
+    clock: u64,
+    entries: HashMap<CacheKey, MemoryEntry>,
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    entry: Entry,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+#[cfg(feature = "redis_cache")]
+/// `RedisDigestCache` is a manifest cache that stores manifests requested by
+/// digest in Redis. As the content behind a digest can never change, entries
+/// are stored without expiration. Manifests requested by tag are never
+/// stored.
+///
+/// Entries are stored under `manifest:<registry>:<digest>`, for example
+/// `manifest:ghcr.io:sha256:...`.
+#[derive(Debug, Clone)]
+pub(super) struct RedisDigestCache {
+    client: redis::Client,
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DeserializeEntry(e) => write!(f, "failed to deserialize cache entry: {e}"),
+            #[cfg(feature = "redis_cache")]
+            Self::GetConnection(e) => write!(f, "failed to get redis connection: {e}"),
+            #[cfg(feature = "redis_cache")]
+            Self::GetValue(e) => write!(f, "failed to get value from redis: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "redis_cache")]
+            Self::GetConnection(e) => write!(f, "failed to get redis connection: {e}"),
+            Self::SerializeEntry(e) => write!(f, "failed to serialize cache entry: {e}"),
+            #[cfg(feature = "redis_cache")]
+            Self::SetValue(e) => write!(f, "failed to set value in redis: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<&Image> for CacheKey {
+    fn from(image: &Image) -> Self {
+        Self {
+            image: image.clone(),
+        }
+    }
+}
+
+impl CacheKey {
+    /// Returns the digest if the manifest was requested by digest.
+    pub(super) fn digest(&self) -> Option<&Digest> {
+        self.image.image_name.identifier.as_ref().right()
+    }
+}
+
+#[cfg(feature = "redis_cache")]
+impl Entry {
+    /// Checks that the body still hashes to the given digest. Digests with
+    /// an algorithm that can not be verified never match.
+    pub(super) fn verify(&self, digest: &Digest) -> bool {
+        digest
+            .algorithm()
+            .is_ok_and(|algorithm| algorithm.digest(self.body.as_bytes()).is_equivalent(digest))
+    }
+}
+
+#[async_trait::async_trait]
+impl Cache for NoCache {
+    async fn fetch(&self, _key: &CacheKey) -> Result<Option<Entry>, FetchError> {
+        Ok(None)
+    }
+
+    async fn store(&self, _key: CacheKey, _entry: Entry) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
+
+impl Default for MemoryManifestCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_CAPACITY, DEFAULT_TAG_TTL)
+    }
+}
+
+impl MemoryManifestCache {
+    /// Creates a cache holding at most `capacity` manifests where manifests
+    /// requested by tag expire after `tag_ttl`.
+    #[must_use]
+    pub fn new(capacity: NonZeroUsize, tag_ttl: Duration) -> Self {
+        Self {
+            capacity,
+            tag_ttl,
+            entries: Arc::default(),
+            hits: Arc::default(),
+            misses: Arc::default(),
+        }
+    }
+
+    /// Returns the number of manifests currently held by the cache.
+    pub async fn len(&self) -> usize {
+        self.entries.lock().await.entries.len()
+    }
+
+    /// Returns true if the cache holds no manifests.
+    pub async fn is_empty(&self) -> bool {
+        self.entries.lock().await.entries.is_empty()
+    }
+
+    fn is_expired(&self, key: &CacheKey, entry: &MemoryEntry) -> bool {
+        key.digest().is_none() && entry.stored_at.elapsed() > self.tag_ttl
+    }
+}
+
+#[async_trait::async_trait]
+impl Cache for MemoryManifestCache {
+    #[tracing::instrument(skip(self))]
+    async fn fetch(&self, key: &CacheKey) -> Result<Option<Entry>, FetchError> {
+        let mut entries = self.entries.lock().await;
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        let expired = entries
+            .entries
+            .get(key)
+            .is_some_and(|entry| self.is_expired(key, entry));
+
+        if expired {
+            entries.entries.remove(key);
+        }
+
+        let result = entries.entries.get_mut(key).map(|entry| {
+            entry.last_used = clock;
+            entry.entry.clone()
+        });
+
+        if result.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(skip(self, entry))]
+    async fn store(&self, key: CacheKey, entry: Entry) -> Result<(), StoreError> {
+        let mut entries = self.entries.lock().await;
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        if !entries.entries.contains_key(&key) && entries.entries.len() >= self.capacity.get() {
+            let least_recently_used = entries
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+
+            if let Some(least_recently_used) = least_recently_used {
+                entries.entries.remove(&least_recently_used);
+            }
+        }
+
+        entries.entries.insert(
+            key,
+            MemoryEntry {
+                entry,
+                stored_at: Instant::now(),
+                last_used: clock,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        Some(Stats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(feature = "redis_cache")]
+impl RedisDigestCache {
+    #[must_use]
+    pub fn new(client: redis::Client) -> Self {
+        Self { client }
+    }
+
+    fn redis_key(key: &CacheKey, digest: &Digest) -> String {
+        let registry = key.image.registry.registry_domain();
+        let digest = digest.normalized();
+
+        format!("{REDIS_PREFIX}:{registry}:{digest}")
+    }
+}
+
+#[cfg(feature = "redis_cache")]
+#[async_trait::async_trait]
+impl Cache for RedisDigestCache {
+    #[tracing::instrument(skip(self))]
+    async fn fetch(&self, key: &CacheKey) -> Result<Option<Entry>, FetchError> {
+        let Some(digest) = key.digest() else {
+            return Ok(None);
+        };
+
+        let mut connection = self
+            .client
+            .get_multiplexed_async_connection()
+            .instrument(info_span!("get redis connection"))
+            .await
+            .map_err(FetchError::GetConnection)?;
+
+        let value: Option<String> = connection
+            .get(Self::redis_key(key, digest))
+            .instrument(info_span!("get value"))
+            .await
+            .map_err(FetchError::GetValue)?;
+
+        let Some(value) = value else {
+            return Ok(None);
+        };
+
+        let entry: Entry = serde_json::from_str(&value).map_err(FetchError::DeserializeEntry)?;
+
+        if !entry.verify(digest) {
+            warn!("cached manifest for {digest} does not match its digest, ignoring cache entry");
+            return Ok(None);
+        }
+
+        Ok(Some(entry))
+    }
+
+    #[tracing::instrument(skip(self, entry))]
+    async fn store(&self, key: CacheKey, entry: Entry) -> Result<(), StoreError> {
+        let Some(digest) = key.digest() else {
+            return Ok(());
+        };
+
+        let mut connection = self
+            .client
+            .get_multiplexed_async_connection()
+            .instrument(info_span!("get redis connection"))
+            .await
+            .map_err(StoreError::GetConnection)?;
+
+        let value = serde_json::to_string(&entry).map_err(StoreError::SerializeEntry)?;
+
+        connection
+            .set::<String, String, ()>(Self::redis_key(&key, digest), value)
+            .instrument(info_span!("set value"))
+            .await
+            .map_err(StoreError::SetValue)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    #[cfg(feature = "redis_cache")]
+    mod entry {
+        use crate::{
+            docker::{
+                manifest_cache::Entry,
+                negotiation::Negotiation,
+            },
+            Digest,
+        };
+
+        const BODY: &str = r#"{"schemaVersion":2}"#;
+
+        #[test]
+        fn verify() {
+            let entry = Entry {
+                body: BODY.to_string(),
+                content_type: None,
+                digest: None,
+                negotiation: Negotiation::default(),
+                digest_verified: false,
+            };
+
+            let digest = Digest::sha256(BODY.as_bytes());
+            assert!(entry.verify(&digest));
+
+            let corrupted = Entry {
+                body: format!("{BODY} "),
+                ..entry
+            };
+            assert!(!corrupted.verify(&digest));
+        }
+    }
+
+    mod memory_manifest_cache {
+        use std::{
+            num::NonZeroUsize,
+            time::Duration,
+        };
+
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                manifest_cache::{
+                    Cache,
+                    Entry,
+                    MemoryManifestCache,
+                    Stats,
+                },
+                negotiation::Negotiation,
+            },
+            Image,
+        };
+
+        fn entry() -> Entry {
+            Entry {
+                body: r#"{"schemaVersion":2}"#.to_string(),
+                content_type: None,
+                digest: None,
+                negotiation: Negotiation::default(),
+                digest_verified: false,
+            }
+        }
+
+        fn image(tag: &str) -> Image {
+            format!("registry.access.redhat.com/ubi8:{tag}")
+                .parse()
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn evicts_least_recently_used() {
+            let cache =
+                MemoryManifestCache::new(NonZeroUsize::new(2).unwrap(), Duration::from_mins(1));
+
+            cache.store((&image("1")).into(), entry()).await.unwrap();
+            cache.store((&image("2")).into(), entry()).await.unwrap();
+
+            // Use the first entry so the second one is the least recently used.
+            assert!(cache.fetch(&(&image("1")).into()).await.unwrap().is_some());
+
+            cache.store((&image("3")).into(), entry()).await.unwrap();
+
+            assert_eq!(2, cache.len().await);
+            assert!(cache.fetch(&(&image("1")).into()).await.unwrap().is_some());
+            assert!(cache.fetch(&(&image("2")).into()).await.unwrap().is_none());
+            assert!(cache.fetch(&(&image("3")).into()).await.unwrap().is_some());
+
+            let expected = Stats { hits: 3, misses: 1 };
+            assert_eq!(Some(expected), cache.stats());
+        }
+
+        #[tokio::test]
+        async fn tag_expires() {
+            let cache = MemoryManifestCache::new(NonZeroUsize::new(2).unwrap(), Duration::ZERO);
+
+            let digest_image: Image =
+                "registry.access.redhat.com/ubi8@sha256:\
+                 2247f14d217577b451727b3015f95e97d47941e96b99806f8589a34c43112ec3"
+                    .parse()
+                    .unwrap();
+
+            cache.store((&image("1")).into(), entry()).await.unwrap();
+            cache.store((&digest_image).into(), entry()).await.unwrap();
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            assert!(cache.fetch(&(&image("1")).into()).await.unwrap().is_none());
+            assert!(cache
+                .fetch(&(&digest_image).into())
+                .await
+                .unwrap()
+                .is_some());
+        }
+    }
+
+    #[cfg(feature = "redis_cache")]
+    mod redis_digest_cache {
+        use wiremock::{
+            matchers::{
+                method,
+                path,
+            },
+            Mock,
+            MockServer,
+            ResponseTemplate,
+        };
+
+        use crate::{
+            docker::{
+                manifest_cache::{
+                    Cache,
+                    Entry,
+                    RedisDigestCache,
+                },
+                negotiation::Negotiation,
+            },
+            Client,
+            Digest,
+            Image,
+        };
+
+        const BODY: &str = include_str!("../../resources/manifest/image/example.json");
+
+        const DIGEST: &str =
+            "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f";
+
+        #[test]
+        fn key_layout() {
+            let image: Image = format!("ghcr.io/sigstore/cosign/cosign@{DIGEST}")
+                .parse()
+                .unwrap();
+            let digest: Digest = DIGEST.parse().unwrap();
+
+            assert_eq!(
+                RedisDigestCache::redis_key(&(&image).into(), &digest),
+                format!("manifest:ghcr.io:{DIGEST}")
+            );
+        }
+
+        #[tokio::test]
+        async fn tag_not_stored() {
+            // Nothing listens on this port, so any attempt to talk to redis would
+            // fail.
+            let client = redis::Client::open("redis://127.0.0.1:1").unwrap();
+            let cache = RedisDigestCache::new(client);
+
+            let image: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+            let entry = Entry {
+                body: BODY.to_string(),
+                content_type: None,
+                digest: None,
+                negotiation: Negotiation::default(),
+                digest_verified: false,
+            };
+
+            cache.store((&image).into(), entry).await.unwrap();
+            assert!(cache.fetch(&(&image).into()).await.unwrap().is_none());
+        }
+
+        #[tokio::test]
+        #[ignore = "requires a running docker daemon"]
+        async fn second_fetch_from_cache() {
+            use testcontainers_modules::{
+                redis::Redis,
+                testcontainers::runners::AsyncRunner,
+            };
+
+            let container = Redis::default().start().await.unwrap();
+            let port = container.get_host_port_ipv4(6379).await.unwrap();
+            let redis_client = redis::Client::open(format!("redis://127.0.0.1:{port}")).unwrap();
+
+            let digest = Digest::sha256(BODY.as_bytes());
+            let server = MockServer::start().await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/ubi8/manifests/{digest}")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("Docker-Content-Digest", digest.to_string())
+                        .set_body_string(BODY),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let mut client = Client::new();
+            client.set_manifest_cache_redis(redis_client);
+
+            let image: Image = format!("registry.access.redhat.com/ubi8@{digest}")
+                .parse()
+                .unwrap();
+            let url = format!("{}/v2/ubi8/manifests/{digest}", server.uri())
+                .parse()
+                .unwrap();
+
+            let first = client.get_manifest_url(&url, &image).await.unwrap();
+            let second = client.get_manifest_url(&url, &image).await.unwrap();
+
+            assert_eq!(
+                serde_json::to_string(&first).unwrap(),
+                serde_json::to_string(&second).unwrap()
+            );
+        }
+    }
+}
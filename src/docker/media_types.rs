@@ -0,0 +1,277 @@
+//! The media types of manifests and blobs, the `Accept` lists the client
+//! sends and which manifest types satisfy a [`ManifestPreference`].
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::docker::negotiation::ManifestPreference;
+
+/// The manifest types of the `Accept` header of manifest requests by
+/// default. Blob types are left out, some registries answer with a schema1
+/// manifest as soon as they see a type that is not a manifest.
+pub const MANIFEST_ACCEPT: [MediaType; 4] = [
+    MediaType::DockerManifestList,
+    MediaType::DockerManifest,
+    MediaType::OciIndex,
+    MediaType::OciManifest,
+];
+
+/// The types of the blobs of Docker images, for `Accept` headers of blob
+/// requests. They never belong into [`MANIFEST_ACCEPT`].
+pub const BLOB_ACCEPT: [MediaType; 4] = [
+    MediaType::DockerContainerConfig,
+    MediaType::DockerLayer,
+    MediaType::DockerForeignLayer,
+    MediaType::DockerPlugin,
+];
+
+/// The manifest list and image index types.
+pub(super) const INDEX: [MediaType; 2] = [MediaType::DockerManifestList, MediaType::OciIndex];
+
+/// The single image manifest types.
+pub(super) const IMAGE: [MediaType; 2] = [MediaType::DockerManifest, MediaType::OciManifest];
+
+/// Which manifest types satisfy a preference: the types asked for first and
+/// the ones that are accepted with a lower quality.
+const COMPATIBILITY: [(ManifestPreference, &[MediaType], &[MediaType]); 3] = [
+    (ManifestPreference::Any, &MANIFEST_ACCEPT, &[]),
+    (ManifestPreference::Index, &INDEX, &IMAGE),
+    (ManifestPreference::SinglePlatform, &IMAGE, &[]),
+];
+
+/// A media type of a manifest or blob, the value of an `Accept` or
+/// `Content-Type` header without its parameters. Serializes to the media
+/// type string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum MediaType {
+    /// `application/vnd.docker.distribution.manifest.v1+json`
+    DockerManifestSchema1,
+
+    /// `application/vnd.docker.distribution.manifest.v1+prettyjws`
+    DockerManifestSchema1Signed,
+
+    /// `application/vnd.docker.distribution.manifest.v2+json`
+    DockerManifest,
+
+    /// `application/vnd.docker.distribution.manifest.list.v2+json`
+    DockerManifestList,
+
+    /// `application/vnd.oci.image.manifest.v1+json`
+    OciManifest,
+
+    /// `application/vnd.oci.image.index.v1+json`
+    OciIndex,
+
+    /// `application/vnd.docker.container.image.v1+json`
+    DockerContainerConfig,
+
+    /// `application/vnd.docker.image.rootfs.diff.tar.gzip`
+    DockerLayer,
+
+    /// `application/vnd.docker.image.rootfs.foreign.diff.tar.gzip`
+    DockerForeignLayer,
+
+    /// `application/vnd.docker.plugin.v1+json`
+    DockerPlugin,
+
+    /// `application/vnd.oci.image.layer.v1.tar`
+    OciLayer,
+
+    /// `application/vnd.oci.image.layer.v1.tar+gzip`
+    OciLayerGzip,
+
+    /// `application/vnd.oci.image.layer.v1.tar+zstd`
+    OciLayerZstd,
+
+    /// `application/vnd.oci.image.layer.nondistributable.v1.tar`
+    OciLayerNondistributable,
+
+    /// `application/vnd.oci.image.layer.nondistributable.v1.tar+gzip`
+    OciLayerNondistributableGzip,
+
+    /// `application/vnd.oci.image.layer.nondistributable.v1.tar+zstd`
+    OciLayerNondistributableZstd,
+
+    /// `application/vnd.dev.cosign.simplesigning.v1+json`, the layers of a
+    /// cosign signature manifest.
+    CosignSimpleSigning,
+
+    Other(String),
+}
+
+impl MediaType {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::DockerManifestSchema1 => "application/vnd.docker.distribution.manifest.v1+json",
+            Self::DockerManifestSchema1Signed => {
+                "application/vnd.docker.distribution.manifest.v1+prettyjws"
+            }
+            Self::DockerManifest => "application/vnd.docker.distribution.manifest.v2+json",
+            Self::DockerManifestList => "application/vnd.docker.distribution.manifest.list.v2+json",
+            Self::OciManifest => "application/vnd.oci.image.manifest.v1+json",
+            Self::OciIndex => "application/vnd.oci.image.index.v1+json",
+            Self::DockerContainerConfig => "application/vnd.docker.container.image.v1+json",
+            Self::DockerLayer => "application/vnd.docker.image.rootfs.diff.tar.gzip",
+            Self::DockerForeignLayer => "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip",
+            Self::DockerPlugin => "application/vnd.docker.plugin.v1+json",
+            Self::OciLayer => "application/vnd.oci.image.layer.v1.tar",
+            Self::OciLayerGzip => "application/vnd.oci.image.layer.v1.tar+gzip",
+            Self::OciLayerZstd => "application/vnd.oci.image.layer.v1.tar+zstd",
+            Self::OciLayerNondistributable => {
+                "application/vnd.oci.image.layer.nondistributable.v1.tar"
+            }
+            Self::OciLayerNondistributableGzip => {
+                "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip"
+            }
+            Self::OciLayerNondistributableZstd => {
+                "application/vnd.oci.image.layer.nondistributable.v1.tar+zstd"
+            }
+            Self::CosignSimpleSigning => "application/vnd.dev.cosign.simplesigning.v1+json",
+            Self::Other(media_type) => media_type,
+        }
+    }
+
+    #[must_use]
+    pub fn is_schema1(&self) -> bool {
+        matches!(
+            self,
+            Self::DockerManifestSchema1 | Self::DockerManifestSchema1Signed
+        )
+    }
+
+    /// Returns `true` for manifest lists and image indexes.
+    #[must_use]
+    pub fn is_index(&self) -> bool {
+        INDEX.contains(self)
+    }
+
+    /// Returns `true` if a response of this type satisfies `preference`.
+    #[must_use]
+    pub fn satisfies(&self, preference: ManifestPreference) -> bool {
+        let (preferred, fallback) = compatible(preference);
+
+        preferred.contains(self) || fallback.contains(self)
+    }
+}
+
+impl From<&str> for MediaType {
+    /// Parses a header value, parameters like `charset` or `q` are dropped.
+    fn from(value: &str) -> Self {
+        let media_type = value.split(';').next().unwrap_or_default().trim();
+
+        [
+            Self::DockerManifestSchema1,
+            Self::DockerManifestSchema1Signed,
+            Self::DockerManifest,
+            Self::DockerManifestList,
+            Self::OciManifest,
+            Self::OciIndex,
+            Self::DockerContainerConfig,
+            Self::DockerLayer,
+            Self::DockerForeignLayer,
+            Self::DockerPlugin,
+            Self::OciLayer,
+            Self::OciLayerGzip,
+            Self::OciLayerZstd,
+            Self::OciLayerNondistributable,
+            Self::OciLayerNondistributableGzip,
+            Self::OciLayerNondistributableZstd,
+            Self::CosignSimpleSigning,
+        ]
+        .into_iter()
+        .find(|known| known.as_str().eq_ignore_ascii_case(media_type))
+        .unwrap_or_else(|| Self::Other(media_type.to_string()))
+    }
+}
+
+impl From<String> for MediaType {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+impl From<MediaType> for String {
+    fn from(media_type: MediaType) -> Self {
+        media_type.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The types asked for first and the ones accepted with a lower quality for
+/// `preference`, see [`COMPATIBILITY`].
+pub(super) fn compatible(
+    preference: ManifestPreference,
+) -> (&'static [MediaType], &'static [MediaType]) {
+    COMPATIBILITY
+        .iter()
+        .find(|(entry, ..)| *entry == preference)
+        .map_or((&[], &[]), |(_, preferred, fallback)| (preferred, fallback))
+}
+
+/// Joins `media_types` for an `Accept` header.
+pub(super) fn join(media_types: &[MediaType]) -> String {
+    media_types
+        .iter()
+        .map(MediaType::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn manifest_accept_has_no_blob_types() {
+        for media_type in MANIFEST_ACCEPT {
+            assert!(!BLOB_ACCEPT.contains(&media_type), "{media_type}");
+            assert!(!media_type.as_str().contains("rootfs"), "{media_type}");
+            assert!(!media_type.as_str().contains("layer"), "{media_type}");
+        }
+
+        let accept = ManifestPreference::Any.accept();
+        for media_type in BLOB_ACCEPT {
+            assert!(!accept.contains(media_type.as_str()), "{media_type}");
+        }
+    }
+
+    #[test]
+    fn layer_types() {
+        for media_type in [
+            MediaType::OciLayer,
+            MediaType::OciLayerGzip,
+            MediaType::OciLayerZstd,
+            MediaType::OciLayerNondistributable,
+            MediaType::OciLayerNondistributableGzip,
+            MediaType::OciLayerNondistributableZstd,
+            MediaType::CosignSimpleSigning,
+        ] {
+            assert_eq!(MediaType::from(media_type.as_str()), media_type);
+        }
+    }
+
+    #[test]
+    fn compatibility() {
+        assert!(MediaType::OciIndex.satisfies(ManifestPreference::Any));
+        assert!(MediaType::OciManifest.satisfies(ManifestPreference::Index));
+        assert!(!MediaType::OciIndex.satisfies(ManifestPreference::SinglePlatform));
+        assert!(!MediaType::DockerManifestSchema1Signed.satisfies(ManifestPreference::Any));
+        assert!(!MediaType::DockerLayer.satisfies(ManifestPreference::Any));
+
+        assert_eq!(
+            compatible(ManifestPreference::Index),
+            (&INDEX[..], &IMAGE[..])
+        );
+    }
+}
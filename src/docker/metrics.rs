@@ -0,0 +1,187 @@
+//! Measurements of the requests of a client for metrics systems, see
+//! [`crate::ClientBuilder::metrics`].
+
+use std::time::Duration;
+
+use http::StatusCode;
+use url::Url;
+
+use crate::Registry;
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusMetrics;
+
+/// The kind of request, derived from the URL. Used as a label instead of the
+/// URL so the number of label values does not grow with the repositories a
+/// client pulls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// The API version check, `/v2/`.
+    Ping,
+
+    /// A request to a token endpoint.
+    Token,
+    Manifest,
+    Blob,
+
+    /// A blob upload session.
+    Upload,
+    Tags,
+    Referrers,
+
+    /// Anything else, for example blob storage a registry redirects to.
+    Other,
+}
+
+/// The direction of bytes counted with [`Metrics::bytes_transferred`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Response bodies.
+    Download,
+
+    /// Request bodies.
+    Upload,
+}
+
+/// A request the client sent, passed to [`Metrics::request`]. Every hop of
+/// a redirect is a request of its own.
+#[derive(Debug, Clone)]
+pub struct RequestMetric<'a> {
+    /// The host the request was sent to, with the port if the URL names
+    /// one.
+    pub host: &'a str,
+    pub endpoint: Endpoint,
+
+    /// `None` if the request failed before a response was received.
+    pub status: Option<StatusCode>,
+
+    /// The time until the response headers were received.
+    pub duration: Duration,
+}
+
+/// `Metrics` receives what a client does, for example to export it to a
+/// metrics system. Unlike [`crate::docker::stats::measure`] it covers every
+/// operation of the client. The methods are called on the request path, so
+/// they should only update counters.
+pub trait Metrics: std::fmt::Debug + Send + Sync {
+    fn request(&self, _request: &RequestMetric<'_>) {}
+
+    /// A token for `registry` was served from the token cache.
+    fn token_cache_hit(&self, _registry: &Registry) {}
+
+    /// Bytes of a request or response body. Response bodies are counted
+    /// while they are read, so this is called once per chunk.
+    fn bytes_transferred(&self, _host: &str, _direction: Direction, _bytes: u64) {}
+}
+
+impl Endpoint {
+    /// Classifies a request by its URL. Token requests carry the `service`
+    /// or `scope` query parameter, registry requests are recognized by the
+    /// segments of the distribution API at the end of their path.
+    #[must_use]
+    pub fn of(url: &Url) -> Self {
+        if url
+            .query_pairs()
+            .any(|(key, _)| key == "service" || key == "scope")
+        {
+            return Self::Token;
+        }
+
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(Iterator::collect)
+            .unwrap_or_default();
+
+        match segments.as_slice() {
+            ["v2", ""] | ["v2"] => Self::Ping,
+            [.., "blobs", "uploads", _] => Self::Upload,
+            [.., "manifests", _] => Self::Manifest,
+            [.., "blobs", _] => Self::Blob,
+            [.., "tags", "list"] => Self::Tags,
+            [.., "referrers", _] => Self::Referrers,
+            _ => Self::Other,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ping => "ping",
+            Self::Token => "token",
+            Self::Manifest => "manifest",
+            Self::Blob => "blob",
+            Self::Upload => "upload",
+            Self::Tags => "tags",
+            Self::Referrers => "referrers",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl Direction {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::Endpoint;
+
+    #[test]
+    fn endpoint_of() {
+        let cases = [
+            ("https://ghcr.io/v2/", Endpoint::Ping),
+            (
+                "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io",
+                Endpoint::Token,
+            ),
+            (
+                "https://quay.io/v2/auth?service=quay.io&scope=repository:app:pull",
+                Endpoint::Token,
+            ),
+            (
+                "https://ghcr.io/v2/example/manifests/manifests/1.0",
+                Endpoint::Manifest,
+            ),
+            (
+                "https://ghcr.io/v2/example/app/blobs/sha256:abc",
+                Endpoint::Blob,
+            ),
+            (
+                "https://ghcr.io/v2/example/app/blobs/uploads/",
+                Endpoint::Upload,
+            ),
+            (
+                "https://ghcr.io/v2/example/app/blobs/uploads/session",
+                Endpoint::Upload,
+            ),
+            (
+                "https://ghcr.io/v2/example/app/tags/list?n=100",
+                Endpoint::Tags,
+            ),
+            (
+                "https://ghcr.io/v2/example/app/referrers/sha256:abc",
+                Endpoint::Referrers,
+            ),
+            (
+                "https://pkg-containers.githubusercontent.com/blob",
+                Endpoint::Other,
+            ),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(Endpoint::of(&url.parse().unwrap()), expected, "{url}");
+        }
+    }
+}
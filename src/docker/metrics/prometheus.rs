@@ -0,0 +1,274 @@
+use prometheus::{
+    HistogramOpts,
+    HistogramVec,
+    IntCounterVec,
+    Opts,
+};
+
+use crate::{
+    docker::metrics::{
+        Direction,
+        Metrics,
+        RequestMetric,
+    },
+    Registry,
+};
+
+/// `PrometheusMetrics` exports the [`Metrics`] of a client as Prometheus
+/// metrics:
+///
+/// - `requests_total{registry, endpoint, status}`, the status is `error` for
+///   requests without a response,
+/// - `request_duration_seconds{registry, endpoint}`,
+/// - `token_cache_hits_total{registry}`,
+/// - `bytes_transferred_total{direction}`.
+///
+/// The `registry` label is the host a request was sent to and `endpoint`
+/// one of [`crate::docker::metrics::Endpoint`], repository names are never
+/// used as labels. Use a registry created with
+/// [`prometheus::Registry::new_custom`] to prefix the names.
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    token_cache_hits: IntCounterVec,
+    bytes_transferred: IntCounterVec,
+}
+
+impl PrometheusMetrics {
+    /// Creates the metrics and registers them with `registry`.
+    ///
+    /// # Errors
+    /// Returns an error if metrics with the same names are already
+    /// registered.
+    pub fn new(registry: &prometheus::Registry) -> Result<Self, prometheus::Error> {
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Requests sent to registries"),
+            &["registry", "endpoint", "status"],
+        )?;
+
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "request_duration_seconds",
+                "Time until the response headers of a registry request were received",
+            ),
+            &["registry", "endpoint"],
+        )?;
+
+        let token_cache_hits = IntCounterVec::new(
+            Opts::new(
+                "token_cache_hits_total",
+                "Tokens served from the token cache",
+            ),
+            &["registry"],
+        )?;
+
+        let bytes_transferred = IntCounterVec::new(
+            Opts::new(
+                "bytes_transferred_total",
+                "Bytes of request and response bodies",
+            ),
+            &["direction"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(token_cache_hits.clone()))?;
+        registry.register(Box::new(bytes_transferred.clone()))?;
+
+        Ok(Self {
+            requests,
+            request_duration,
+            token_cache_hits,
+            bytes_transferred,
+        })
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn request(&self, request: &RequestMetric<'_>) {
+        let endpoint = request.endpoint.as_str();
+        let status = request
+            .status
+            .map_or_else(|| "error".to_string(), |status| status.as_u16().to_string());
+
+        self.requests
+            .with_label_values(&[request.host, endpoint, &status])
+            .inc();
+
+        self.request_duration
+            .with_label_values(&[request.host, endpoint])
+            .observe(request.duration.as_secs_f64());
+    }
+
+    fn token_cache_hit(&self, registry: &Registry) {
+        self.token_cache_hits
+            .with_label_values(&[registry.registry_domain()])
+            .inc();
+    }
+
+    fn bytes_transferred(&self, _host: &str, direction: Direction, bytes: u64) {
+        self.bytes_transferred
+            .with_label_values(&[direction.as_str()])
+            .inc_by(bytes);
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use futures::TryStreamExt;
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::PrometheusMetrics;
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        Digest,
+        Image,
+    };
+
+    const MANIFEST: &str = include_str!("../../../resources/manifest/image/example.json");
+    const TOKEN: &str = "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io";
+
+    /// The samples of every metric keyed by name and labels, histograms by
+    /// their sample count.
+    fn scrape(registry: &prometheus::Registry) -> BTreeMap<String, u64> {
+        let mut samples = BTreeMap::new();
+
+        for family in registry.gather() {
+            for metric in family.get_metric() {
+                let labels: Vec<String> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| format!("{}={}", label.name(), label.value()))
+                    .collect();
+
+                let value = if family.get_field_type() == prometheus::proto::MetricType::HISTOGRAM {
+                    metric.get_histogram().get_sample_count()
+                } else {
+                    #[expect(
+                        clippy::cast_possible_truncation,
+                        clippy::cast_sign_loss,
+                        reason = "the counters are small whole numbers"
+                    )]
+                    let value = metric.get_counter().get_value() as u64;
+                    value
+                };
+
+                samples.insert(format!("{}{{{}}}", family.name(), labels.join(",")), value);
+            }
+        }
+
+        samples
+    }
+
+    #[tokio::test]
+    async fn mocked_workload() {
+        let digest = Digest::sha256(b"blob");
+        let blob = format!("https://ghcr.io/v2/example/app/blobs/{digest}");
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/v2/example/app/manifests/1.0",
+                MockResponse::new(StatusCode::OK).body(MANIFEST),
+            )
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/v2/example/app/manifests/2.0",
+                MockResponse::new(StatusCode::NOT_FOUND),
+            )
+            .with_response(
+                Method::GET,
+                &blob,
+                MockResponse::new(StatusCode::OK).body("blob"),
+            );
+
+        let registry = prometheus::Registry::new();
+        let client = Client::builder()
+            .transport(transport)
+            .metrics(PrometheusMetrics::new(&registry).unwrap())
+            .build();
+
+        let image: Image = "ghcr.io/example/app:1.0".parse().unwrap();
+        client.get_manifest(&image).await.unwrap();
+
+        let missing: Image = "ghcr.io/example/app:2.0".parse().unwrap();
+        client.get_manifest(&missing).await.unwrap_err();
+
+        let chunks: Vec<bytes::Bytes> = client
+            .get_blob(&image, &digest)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"blob");
+
+        let token_bytes = r#"{"token":"registry-token"}"#.len() as u64;
+        let expected: BTreeMap<String, u64> = [
+            (
+                "bytes_transferred_total{direction=download}",
+                token_bytes + MANIFEST.len() as u64 + 4,
+            ),
+            (
+                "request_duration_seconds{endpoint=blob,registry=ghcr.io}",
+                1,
+            ),
+            (
+                "request_duration_seconds{endpoint=manifest,registry=ghcr.io}",
+                2,
+            ),
+            (
+                "request_duration_seconds{endpoint=token,registry=ghcr.io}",
+                1,
+            ),
+            (
+                "requests_total{endpoint=blob,registry=ghcr.io,status=200}",
+                1,
+            ),
+            (
+                "requests_total{endpoint=manifest,registry=ghcr.io,status=200}",
+                1,
+            ),
+            (
+                "requests_total{endpoint=manifest,registry=ghcr.io,status=404}",
+                1,
+            ),
+            (
+                "requests_total{endpoint=token,registry=ghcr.io,status=200}",
+                1,
+            ),
+            ("token_cache_hits_total{registry=ghcr.io}", 2),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+
+        assert_eq!(scrape(&registry), expected);
+    }
+
+    #[test]
+    fn registered_twice() {
+        let registry = prometheus::Registry::new();
+
+        PrometheusMetrics::new(&registry).unwrap();
+
+        assert!(PrometheusMetrics::new(&registry).is_err());
+    }
+}
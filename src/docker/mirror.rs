@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use http::header::{
+    HeaderValue,
+    InvalidHeaderValue,
+};
+use url::Url;
+
+use crate::{
+    image::append_segments,
+    Image,
+    Registry,
+};
+
+/// A mirror serving the content of a registry, for example a pull-through
+/// cache. Requests are sent to the mirror with the same repository path as
+/// they would be sent to the original registry.
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    url: Url,
+    authentication: Authentication,
+}
+
+/// How requests to a mirror are authenticated.
+#[derive(Clone, Default)]
+pub enum Authentication {
+    /// No credentials are sent to the mirror.
+    #[default]
+    Anonymous,
+
+    /// The mirror accepts the tokens of the original registry.
+    Upstream,
+
+    /// The mirror uses HTTP basic authentication.
+    Basic { username: String, password: String },
+
+    /// The mirror accepts a static bearer token.
+    Bearer(String),
+}
+
+/// A base URL to try a request against together with its credentials.
+#[derive(Debug, Clone)]
+pub(super) struct Endpoint {
+    pub(super) url: Url,
+    pub(super) authentication: Authentication,
+}
+
+/// The mirrors and API base overrides configured for a client keyed by the
+/// domain of the registry they apply to.
+#[derive(Debug, Clone)]
+pub(super) struct Mirrors {
+    by_host: HashMap<String, Vec<Mirror>>,
+    api_bases: HashMap<String, Url>,
+    fallback_to_upstream: bool,
+}
+
+impl std::fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Anonymous => f.write_str("Anonymous"),
+            Self::Upstream => f.write_str("Upstream"),
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Self::Bearer(_) => f.write_str("Bearer(..)"),
+        }
+    }
+}
+
+impl Default for Mirrors {
+    fn default() -> Self {
+        Self {
+            by_host: HashMap::new(),
+            api_bases: HashMap::new(),
+            fallback_to_upstream: true,
+        }
+    }
+}
+
+impl From<Url> for Mirror {
+    fn from(url: Url) -> Self {
+        Self::new(url)
+    }
+}
+
+impl Mirror {
+    /// Creates an anonymous mirror at the given base URL.
+    #[must_use]
+    pub fn new(url: Url) -> Self {
+        Self {
+            url: with_trailing_slash(url),
+            authentication: Authentication::Anonymous,
+        }
+    }
+
+    #[must_use]
+    pub fn authentication(mut self, authentication: Authentication) -> Self {
+        self.authentication = authentication;
+        self
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+}
+
+impl Endpoint {
+    /// An endpoint of the original registry.
+    pub(super) fn upstream(url: Url) -> Self {
+        Self {
+            url,
+            authentication: Authentication::Upstream,
+        }
+    }
+}
+
+impl Authentication {
+    /// Returns the value of the `Authorization` header for static
+    /// credentials. Returns `None` for anonymous and upstream authentication.
+    pub(super) fn header_value(&self) -> Option<Result<HeaderValue, InvalidHeaderValue>> {
+        match self {
+            Self::Anonymous | Self::Upstream => None,
+            Self::Basic { username, password } => {
+                let credentials = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{username}:{password}"));
+
+                Some(format!("Basic {credentials}").parse())
+            }
+            Self::Bearer(token) => Some(format!("Bearer {token}").parse()),
+        }
+    }
+}
+
+impl Mirrors {
+    /// Adds a mirror for the registry with the given host. Mirrors are tried
+    /// in the order they were added.
+    pub(super) fn add(&mut self, original_host: &str, mirror: Mirror) {
+        self.by_host
+            .entry(canonical_host(original_host))
+            .or_default()
+            .push(mirror);
+    }
+
+    /// Sends requests that reach the registry with the given host to
+    /// `api_base` instead of [`Registry::api_base`].
+    pub(super) fn set_api_base(&mut self, original_host: &str, api_base: Url) {
+        self.api_bases
+            .insert(canonical_host(original_host), with_trailing_slash(api_base));
+    }
+
+    /// Returns the base URL of the original registry.
+    pub(super) fn upstream(&self, registry: &Registry) -> Result<Url, url::ParseError> {
+        match self.api_bases.get(registry.registry_domain()) {
+            Some(api_base) => Ok(api_base.clone()),
+            None => registry.api_base(),
+        }
+    }
+
+    pub(super) fn set_fallback_to_upstream(&mut self, fallback_to_upstream: bool) {
+        self.fallback_to_upstream = fallback_to_upstream;
+    }
+
+    /// Returns the endpoints to try for the path `segments` in order. The
+    /// original registry comes last and is skipped if mirrors are
+    /// configured and the fallback is disabled. The last endpoint is
+    /// returned separately as its response is used even if it is an error.
+    pub(super) fn endpoints(
+        &self,
+        image: &Image,
+        segments: &[String],
+    ) -> Result<(Vec<Endpoint>, Endpoint), url::ParseError> {
+        let registry_domain = image.registry.registry_domain();
+
+        let mirrors = self
+            .by_host
+            .get(registry_domain)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut endpoints = mirrors
+            .iter()
+            .map(|mirror| Endpoint {
+                url: append_segments(mirror.url.clone(), segments),
+                authentication: mirror.authentication.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let upstream =
+            Endpoint::upstream(append_segments(self.upstream(&image.registry)?, segments));
+
+        if self.fallback_to_upstream {
+            return Ok((endpoints, upstream));
+        }
+
+        match endpoints.pop() {
+            Some(last) => Ok((endpoints, last)),
+            None => Ok((endpoints, upstream)),
+        }
+    }
+}
+
+/// Accepts aliases like docker.io for registries we know about.
+fn canonical_host(original_host: &str) -> String {
+    original_host.parse::<Registry>().map_or_else(
+        |_| original_host.to_string(),
+        |registry| registry.registry_domain().to_string(),
+    )
+}
+
+/// Makes sure joining the repository path keeps any path prefix of the URL.
+fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+
+    url
+}
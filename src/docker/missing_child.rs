@@ -0,0 +1,76 @@
+//! What operations that follow the entries of a manifest list do with an
+//! entry whose manifest the registry does not have, see
+//! [`MissingChildPolicy`].
+
+use tracing::{
+    debug,
+    warn,
+};
+
+use crate::{
+    docker::{
+        stats::{
+            self,
+            Event,
+        },
+        Error,
+    },
+    manifest::{
+        Entry,
+        Platform,
+    },
+    Digest,
+};
+
+/// What [`crate::Client::expand_index_with`] and
+/// [`crate::Client::fetch_into_with`] do with an entry of a manifest list
+/// whose manifest is missing, for example because the registry garbage
+/// collected it while the list still references it. A manifest is missing if
+/// the registry answers with `404` or the `MANIFEST_UNKNOWN` error code,
+/// other errors are never skipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingChildPolicy {
+    /// Handles the missing manifest like any other error of the operation.
+    #[default]
+    Fail,
+
+    /// Leaves the entry out and reports it as a [`SkippedChild`].
+    Skip,
+
+    /// Like [`MissingChildPolicy::Skip`] but also logs a warning.
+    SkipWithWarning,
+}
+
+/// An entry of a manifest list that was left out because its manifest is
+/// missing, see [`MissingChildPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedChild {
+    pub digest: Digest,
+    pub platform: Option<Platform>,
+}
+
+impl MissingChildPolicy {
+    /// Returns the entry to report as skipped if `error` says that its
+    /// manifest is missing and the policy skips those, `None` if the error
+    /// has to be handled as usual.
+    pub(super) fn skip(self, entry: &Entry, error: &Error) -> Option<SkippedChild> {
+        if self == Self::Fail || !error.is_missing_manifest() {
+            return None;
+        }
+
+        let platform = entry.platform.as_ref().map(ToString::to_string);
+
+        if self == Self::SkipWithWarning {
+            warn!(digest = %entry.digest, ?platform, "skipping missing manifest of a manifest list");
+        } else {
+            debug!(digest = %entry.digest, ?platform, "skipping missing manifest of a manifest list");
+        }
+
+        stats::record(Event::SkippedChild);
+
+        Some(SkippedChild {
+            digest: entry.digest.clone(),
+            platform: entry.platform.clone(),
+        })
+    }
+}
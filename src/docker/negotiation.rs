@@ -0,0 +1,489 @@
+//! Content negotiation for manifest requests.
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::media_types::{
+    self,
+    MediaType,
+    IMAGE,
+    INDEX,
+};
+
+/// Which kind of manifest to ask the registry for, see
+/// [`crate::Client::get_manifest_with_preference`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ManifestPreference {
+    /// Accept every manifest type, the registry decides.
+    #[default]
+    Any,
+
+    /// Prefer a manifest list or image index over a single image manifest.
+    Index,
+
+    /// Only accept single image manifests. Registries that store a list
+    /// for the reference usually answer with the manifest of their default
+    /// platform.
+    SinglePlatform,
+}
+
+/// How the manifest of a [`crate::docker::Response`] was negotiated with the
+/// registry: what the client asked for and what it got.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Stored")]
+pub struct Negotiation {
+    /// The media types of the `Accept` header of the request that returned
+    /// the manifest, in the order they were sent.
+    pub requested: Vec<MediaType>,
+
+    /// The `Content-Type` of the manifest, `None` if the registry did not
+    /// send one.
+    pub received: Option<MediaType>,
+
+    /// `true` if the registry ignored the `Accept` header and answered with
+    /// a schema1 manifest, so the request was sent again with only v2 and
+    /// OCI manifest types.
+    pub retried: bool,
+}
+
+/// The serialization of [`Negotiation`]. Earlier releases serialized an
+/// enum, manifest caches can still hold it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Fields {
+        #[serde(default)]
+        requested: Vec<MediaType>,
+
+        #[serde(default)]
+        received: Option<MediaType>,
+
+        #[serde(default)]
+        retried: bool,
+    },
+
+    Legacy(Legacy),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Legacy {
+    Accepted,
+    RetriedWithoutSchema1,
+}
+
+impl From<Stored> for Negotiation {
+    fn from(stored: Stored) -> Self {
+        match stored {
+            Stored::Fields {
+                requested,
+                received,
+                retried,
+            } => Self {
+                requested,
+                received,
+                retried,
+            },
+
+            Stored::Legacy(legacy) => Self {
+                retried: matches!(legacy, Legacy::RetriedWithoutSchema1),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Negotiation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let requested = self
+            .requested
+            .iter()
+            .map(MediaType::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        write!(f, "requested [{requested}], received ")?;
+
+        match &self.received {
+            Some(received) => write!(f, "{received}")?,
+            None => f.write_str("no content type")?,
+        }
+
+        if self.retried {
+            f.write_str(", retried without schema1")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Negotiation {
+    /// The negotiation of a request sent with `accept` that was answered
+    /// with `content_type`.
+    pub(super) fn new(accept: &str, content_type: Option<&str>) -> Self {
+        Self {
+            requested: accept
+                .split(',')
+                .filter(|media_type| !media_type.trim().is_empty())
+                .map(MediaType::from)
+                .collect(),
+            received: content_type.map(MediaType::from),
+            retried: false,
+        }
+    }
+
+    /// `true` unless the request was retried, the snapshot view of a
+    /// response only shows negotiations that needed a retry.
+    pub(super) fn is_accepted(&self) -> bool {
+        !self.retried
+    }
+}
+
+impl ManifestPreference {
+    /// The value of the `Accept` header for the first request, see
+    /// [`media_types::compatible`].
+    pub(super) fn accept(self) -> String {
+        let (preferred, fallback) = media_types::compatible(self);
+
+        preferred
+            .iter()
+            .map(ToString::to_string)
+            .chain(
+                fallback
+                    .iter()
+                    .map(|media_type| format!("{media_type};q=0.5")),
+            )
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The value of the `Accept` header for the retry after the registry
+    /// answered with a schema1 manifest. Only lists the manifest types,
+    /// some registries fall back to schema1 as soon as they see a type they
+    /// do not know.
+    pub(super) fn retry_accept(self) -> String {
+        match self {
+            Self::Any | Self::Index => media_types::join(&[INDEX, IMAGE].concat()),
+            Self::SinglePlatform => media_types::join(&IMAGE),
+        }
+    }
+}
+
+/// Returns `true` if `content_type` is one of the deprecated schema1
+/// manifest types. Parameters like `charset` are ignored.
+pub(super) fn is_schema1(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| MediaType::from(content_type).is_schema1())
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use http::{
+        header::ACCEPT,
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        docker::transport::{
+            self,
+            MockResponse,
+            MockTransport,
+            Request,
+            Response,
+            Transport,
+        },
+        Client,
+        Image,
+        Manifest,
+    };
+
+    const URL: &str = "https://registry.access.redhat.com/v2/ubi8/manifests/8.9";
+
+    const SCHEMA1: &str = r#"{
+        "schemaVersion": 1,
+        "name": "ubi8",
+        "tag": "8.9",
+        "architecture": "amd64",
+        "fsLayers": [],
+        "history": []
+    }"#;
+
+    const V2: &str = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+        "config": {
+            "mediaType": "application/vnd.docker.container.image.v1+json",
+            "size": 2,
+            "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        },
+        "layers": []
+    }"#;
+
+    /// Answers the first `schema1_answers` requests with a schema1 manifest
+    /// whatever the `Accept` header lists, like some older registries do.
+    #[derive(Debug, Default)]
+    struct IgnoresAccept {
+        schema1_answers: usize,
+        accept: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for IgnoresAccept {
+        async fn execute(&self, request: Request) -> Result<Response, transport::Error> {
+            let accept = request
+                .headers
+                .get(ACCEPT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+
+            let answered = {
+                let mut requests = self.accept.lock().unwrap();
+                requests.push(accept);
+                requests.len() - 1
+            };
+
+            let response = if answered < self.schema1_answers {
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.v1+prettyjws",
+                    )
+                    .body(SCHEMA1)
+            } else {
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.v2+json",
+                    )
+                    .body(V2)
+            };
+
+            MockTransport::new()
+                .with_response(Method::GET, URL, response)
+                .execute(request)
+                .await
+        }
+    }
+
+    fn image() -> Image {
+        "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+    }
+
+    #[test]
+    fn schema1() {
+        assert!(is_schema1(Some(
+            "application/vnd.docker.distribution.manifest.v1+prettyjws"
+        )));
+        assert!(is_schema1(Some(
+            "application/vnd.docker.distribution.manifest.v1+json; charset=utf-8"
+        )));
+        assert!(!is_schema1(Some(
+            "application/vnd.docker.distribution.manifest.v2+json"
+        )));
+        assert!(!is_schema1(None));
+    }
+
+    #[test]
+    fn accept() {
+        assert_eq!(
+            ManifestPreference::Index.accept(),
+            "application/vnd.docker.distribution.manifest.list.v2+json, \
+             application/vnd.oci.image.index.v1+json, \
+             application/vnd.docker.distribution.manifest.v2+json;q=0.5, \
+             application/vnd.oci.image.manifest.v1+json;q=0.5"
+        );
+        assert_eq!(
+            ManifestPreference::SinglePlatform.retry_accept(),
+            "application/vnd.docker.distribution.manifest.v2+json, \
+             application/vnd.oci.image.manifest.v1+json"
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_without_schema1() {
+        let transport = IgnoresAccept {
+            schema1_answers: 1,
+            ..IgnoresAccept::default()
+        };
+        let accept = Arc::clone(&transport.accept);
+        let client = Client::builder()
+            .transport(transport)
+            .manifest_cache_memory()
+            .build();
+
+        let got = client.get_manifest(&image()).await.unwrap();
+
+        let negotiation = Negotiation {
+            requested: vec![
+                MediaType::DockerManifestList,
+                MediaType::OciIndex,
+                MediaType::DockerManifest,
+                MediaType::OciManifest,
+            ],
+            received: Some(MediaType::DockerManifest),
+            retried: true,
+        };
+
+        assert!(matches!(got.manifest, Manifest::Image(_)));
+        assert_eq!(got.negotiation, negotiation);
+        assert_eq!(
+            accept.lock().unwrap()[1],
+            ManifestPreference::Any.retry_accept()
+        );
+
+        // The negotiated manifest is cached.
+        let cached = client.get_manifest(&image()).await.unwrap();
+        assert!(matches!(cached.manifest, Manifest::Image(_)));
+        assert_eq!(cached.negotiation, negotiation);
+        assert_eq!(accept.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_once() {
+        let transport = IgnoresAccept {
+            schema1_answers: usize::MAX,
+            ..IgnoresAccept::default()
+        };
+        let accept = Arc::clone(&transport.accept);
+        let client = Client::builder().transport(transport).build();
+
+        let got = client.get_manifest(&image()).await.unwrap();
+
+        assert!(matches!(got.manifest, Manifest::Single(_)));
+        assert!(got.negotiation.retried);
+        assert_eq!(
+            got.negotiation.received,
+            Some(MediaType::DockerManifestSchema1Signed)
+        );
+        assert_eq!(accept.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn preference() {
+        let transport = IgnoresAccept::default();
+        let accept = Arc::clone(&transport.accept);
+        let client = Client::builder().transport(transport).build();
+
+        let got = client
+            .get_manifest_with_preference(&image(), ManifestPreference::SinglePlatform)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            got.negotiation,
+            Negotiation {
+                requested: vec![MediaType::DockerManifest, MediaType::OciManifest],
+                received: Some(MediaType::DockerManifest),
+                retried: false,
+            }
+        );
+        assert_eq!(
+            *accept.lock().unwrap(),
+            [ManifestPreference::SinglePlatform.accept()]
+        );
+    }
+
+    /// Fetches `body` from a registry that answers with `content_type`.
+    async fn fetch(content_type: &str, body: &str) -> crate::docker::Response {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            URL,
+            MockResponse::new(StatusCode::OK)
+                .header("Content-Type", content_type)
+                .body(body.to_string()),
+        );
+
+        Client::builder()
+            .transport(transport)
+            .build()
+            .get_manifest(&image())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn oci_index() {
+        let got = fetch(
+            "application/vnd.oci.image.index.v1+json",
+            include_str!("../../resources/manifest/list/vaultwarden.json"),
+        )
+        .await;
+
+        assert_eq!(
+            got.negotiation,
+            Negotiation {
+                requested: media_types::MANIFEST_ACCEPT.to_vec(),
+                received: Some(MediaType::OciIndex),
+                retried: false,
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&got.negotiation).unwrap()["received"],
+            "application/vnd.oci.image.index.v1+json"
+        );
+    }
+
+    #[tokio::test]
+    async fn schema1_fixture() {
+        let got = fetch(
+            "application/vnd.docker.distribution.manifest.v1+json; charset=utf-8",
+            SCHEMA1,
+        )
+        .await;
+
+        // The retry got the same answer.
+        assert!(matches!(got.manifest, Manifest::Single(_)));
+        assert_eq!(
+            got.negotiation.to_string(),
+            "requested [application/vnd.docker.distribution.manifest.list.v2+json, \
+             application/vnd.oci.image.index.v1+json, \
+             application/vnd.docker.distribution.manifest.v2+json, \
+             application/vnd.oci.image.manifest.v1+json], received \
+             application/vnd.docker.distribution.manifest.v1+json, retried without schema1"
+        );
+    }
+
+    #[test]
+    fn media_type() {
+        assert_eq!(
+            MediaType::from("application/vnd.OCI.image.index.v1+json; charset=utf-8"),
+            MediaType::OciIndex
+        );
+        assert_eq!(
+            MediaType::from(" application/json "),
+            MediaType::Other("application/json".to_string())
+        );
+        assert_eq!(
+            MediaType::from("application/vnd.docker.distribution.manifest.v2+json;q=0.5"),
+            MediaType::DockerManifest
+        );
+    }
+
+    #[test]
+    fn deserialize_legacy() {
+        let got: Negotiation = serde_json::from_str(r#""retried_without_schema1""#).unwrap();
+        assert!(got.retried);
+
+        let got: Negotiation = serde_json::from_str(r#""accepted""#).unwrap();
+        assert_eq!(got, Negotiation::default());
+
+        let negotiation = Negotiation::new(
+            &ManifestPreference::SinglePlatform.accept(),
+            Some("application/vnd.oci.image.manifest.v1+json"),
+        );
+        let got: Negotiation =
+            serde_json::from_str(&serde_json::to_string(&negotiation).unwrap()).unwrap();
+        assert_eq!(got, negotiation);
+    }
+}
@@ -0,0 +1,378 @@
+//! Per-call options for [`Client::get_manifest_with`].
+
+use http::{
+    header::{
+        HeaderMap,
+        IF_NONE_MATCH,
+    },
+    Method,
+};
+
+use crate::{
+    docker::{
+        api::RegistryApi,
+        media_types::{
+            self,
+            MediaType,
+        },
+        Client,
+        Error,
+        RawResponse,
+        Response,
+    },
+    manifest::Platform,
+    Image,
+    ManifestPreference,
+};
+
+/// Options for a single manifest request, see [`Client::get_manifest_with`].
+/// The default options fetch the manifest like [`Client::get_manifest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GetManifestOptions {
+    accept: Option<Vec<MediaType>>,
+    bypass_cache: bool,
+    if_none_match: Option<String>,
+    platform: Option<Platform>,
+    require_digest: bool,
+}
+
+impl GetManifestOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `media_types` as the `Accept` header instead of every manifest
+    /// type the client knows. The answer is neither read from nor stored in
+    /// the manifest cache and a schema1 answer is not retried.
+    #[must_use]
+    pub fn accept<I>(mut self, media_types: I) -> Self
+    where
+        I: IntoIterator<Item = MediaType>,
+    {
+        self.accept = Some(media_types.into_iter().collect());
+        self
+    }
+
+    /// Skips the manifest cache and asks the registry. The answer still
+    /// replaces the cached manifest.
+    #[must_use]
+    pub fn bypass_cache(mut self, bypass_cache: bool) -> Self {
+        self.bypass_cache = bypass_cache;
+        self
+    }
+
+    /// Sends `etag` as the `If-None-Match` header, usually a digest returned
+    /// earlier. Registries that support it answer with
+    /// [`Error::ManifestNotModified`] if the manifest did not change. Like
+    /// [`GetManifestOptions::bypass_cache`] the manifest cache is skipped.
+    #[must_use]
+    pub fn if_none_match(mut self, etag: impl Into<String>) -> Self {
+        self.if_none_match = Some(etag.into());
+        self
+    }
+
+    /// Follows a manifest list to the entry for `platform`, like
+    /// [`Client::get_manifest_for_platform`]. The other options only apply
+    /// to the request for the list.
+    #[must_use]
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Fails with [`Error::MissingDockerContentDigestHeader`] if the
+    /// registry did not send the digest of the manifest that is returned.
+    #[must_use]
+    pub fn require_digest(mut self, require_digest: bool) -> Self {
+        self.require_digest = require_digest;
+        self
+    }
+
+    fn uses_cache(&self) -> bool {
+        self.accept.is_none() && !self.bypass_cache && self.if_none_match.is_none()
+    }
+
+    /// The value of the `Accept` header.
+    fn accept_header(&self) -> String {
+        self.accept.as_ref().map_or_else(
+            || ManifestPreference::Any.accept(),
+            |media_types| media_types::join(media_types),
+        )
+    }
+
+    /// The headers sent besides `Accept`.
+    fn headers(&self) -> Result<HeaderMap, Error> {
+        let mut headers = HeaderMap::new();
+
+        if let Some(etag) = &self.if_none_match {
+            headers.insert(
+                IF_NONE_MATCH,
+                etag.parse().map_err(Error::ParseIfNoneMatchHeader)?,
+            );
+        }
+
+        Ok(headers)
+    }
+}
+
+impl Client {
+    /// Same as [`Client::get_manifest`] but with per-call `options`.
+    ///
+    /// # Errors
+    /// Returns [`Error::ManifestNotModified`] if
+    /// [`GetManifestOptions::if_none_match`] is set and the manifest did not
+    /// change.
+    /// Returns [`Error::MissingDockerContentDigestHeader`] if
+    /// [`GetManifestOptions::require_digest`] is set and the registry did
+    /// not send a digest.
+    /// Returns [`Error::NoMatchingPlatform`] if
+    /// [`GetManifestOptions::platform`] is set and the list has no entry for
+    /// it.
+    /// Returns an error if the request fails or the response is not a valid
+    /// manifest, see [`Client::get_manifest`].
+    pub async fn get_manifest_with(
+        &self,
+        image: &Image,
+        options: &GetManifestOptions,
+    ) -> Result<Response, Error> {
+        let raw = if options.uses_cache() {
+            self.get_manifest_raw(image).await?
+        } else {
+            self.fetch_manifest_with(image, options).await?
+        };
+
+        let mut response = Self::response_from_raw(raw)?;
+
+        if let Some(platform) = &options.platform {
+            response = self
+                .resolve(image, response, platform)
+                .await?
+                .into_response();
+        }
+
+        if options.require_digest && response.digest.is_none() {
+            return Err(Error::MissingDockerContentDigestHeader);
+        }
+
+        Ok(response)
+    }
+
+    async fn fetch_manifest_with(
+        &self,
+        image: &Image,
+        options: &GetManifestOptions,
+    ) -> Result<RawResponse, Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
+
+        let (mirrors, last) = self
+            .inner
+            .mirrors
+            .endpoints(image, &segments)
+            .map_err(Error::InvalidManifestUrl)?;
+
+        let api = RegistryApi::for_client(self);
+        let accept = options.accept_header();
+        let headers = options.headers()?;
+
+        let (endpoint, response) = self
+            .send_request(
+                image,
+                mirrors,
+                last,
+                |url, mut auth| {
+                    auth.extend(headers.clone());
+                    api.manifest_request(Method::GET, url, auth, &accept)
+                },
+                Error::FailedManifestRequest,
+            )
+            .await?;
+
+        let received = self
+            .receive_manifest(image, endpoint.clone(), &accept, response)
+            .await?;
+
+        if options.accept.is_some() {
+            return RawResponse::from_received(received);
+        }
+
+        let received = self
+            .negotiate(image, endpoint, ManifestPreference::Any, received)
+            .await;
+
+        self.inner
+            .manifest_cache
+            .store(image.into(), received.entry.clone())
+            .await
+            .map_err(Error::StoreManifest)?;
+
+        RawResponse::from_received(received)
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        header::{
+            ACCEPT,
+            IF_NONE_MATCH,
+        },
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::GetManifestOptions;
+    use crate::{
+        docker::{
+            media_types::MediaType,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        manifest::{
+            Architecture,
+            OperatingSystem,
+            Platform,
+        },
+        Client,
+        ClientError,
+        Digest,
+        Image,
+        Manifest,
+    };
+
+    const LIST_URL: &str = "https://registry.access.redhat.com/v2/ubi8/manifests/8.9";
+    const IMAGE: &str = include_str!("../../resources/manifest/image/example.json");
+    const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+    fn image() -> Image {
+        "registry.access.redhat.com/ubi8:8.9".parse().unwrap()
+    }
+
+    /// Serves a list with an arm64 entry for `IMAGE`. Only `IMAGE` is served
+    /// with a digest header.
+    fn transport() -> MockTransport {
+        let digest = Digest::sha256(IMAGE.as_bytes());
+
+        let list = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_INDEX,
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": IMAGE.len(),
+                    "digest": digest.to_string(),
+                    "platform": { "architecture": "arm64", "os": "linux" }
+                }
+            ]
+        });
+
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                LIST_URL,
+                MockResponse::new(StatusCode::OK).body(list.to_string()),
+            )
+            .with_response(
+                Method::GET,
+                &format!("https://registry.access.redhat.com/v2/ubi8/manifests/{digest}"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", &digest.to_string())
+                    .body(IMAGE),
+            )
+    }
+
+    fn list_requests(transport: &MockTransport) -> Vec<crate::docker::transport::Request> {
+        transport
+            .requests()
+            .into_iter()
+            .filter(|request| request.url.as_str() == LIST_URL)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn combined() {
+        let transport = transport();
+        let client = Client::builder()
+            .transport(transport.clone())
+            .manifest_cache_memory()
+            .build();
+
+        // Fills the cache with the list.
+        client.get_manifest(&image()).await.unwrap();
+
+        let options = GetManifestOptions::new()
+            .accept([MediaType::OciIndex])
+            .bypass_cache(true)
+            .if_none_match("\"sha256:old\"")
+            .platform(Platform::new(OperatingSystem::Linux, Architecture::Arm64))
+            .require_digest(true);
+
+        let got = client.get_manifest_with(&image(), &options).await.unwrap();
+
+        assert!(matches!(got.manifest, Manifest::Image(_)));
+        assert_eq!(
+            got.digest,
+            Some(Digest::sha256(IMAGE.as_bytes()).to_string())
+        );
+
+        let requests = list_requests(&transport);
+        assert_eq!(requests.len(), 2, "the cache was not bypassed");
+        assert_eq!(requests[1].headers[ACCEPT], OCI_INDEX);
+        assert_eq!(requests[1].headers[IF_NONE_MATCH], "\"sha256:old\"");
+    }
+
+    #[tokio::test]
+    async fn defaults_use_cache() {
+        let transport = transport();
+        let client = Client::builder()
+            .transport(transport.clone())
+            .manifest_cache_memory()
+            .build();
+
+        client.get_manifest(&image()).await.unwrap();
+        client
+            .get_manifest_with(&image(), &GetManifestOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(list_requests(&transport).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn require_digest() {
+        let client = Client::builder().transport(transport()).build();
+
+        let got = client
+            .get_manifest_with(&image(), &GetManifestOptions::new().require_digest(true))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::MissingDockerContentDigestHeader));
+    }
+
+    #[tokio::test]
+    async fn not_modified() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            LIST_URL,
+            MockResponse::new(StatusCode::NOT_MODIFIED),
+        );
+        let client = Client::builder().transport(transport).build();
+
+        let got = client
+            .get_manifest_with(
+                &image(),
+                &GetManifestOptions::new().if_none_match("\"sha256:list\""),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::ManifestNotModified(_)));
+    }
+}
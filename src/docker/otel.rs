@@ -0,0 +1,129 @@
+use http::header::{
+    HeaderMap,
+    HeaderValue,
+};
+use opentelemetry::trace::TraceContextExt;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Adds the W3C `traceparent` and `tracestate` headers for the OpenTelemetry
+/// context of `span`. Does nothing if the span is not part of a trace.
+pub(super) fn inject(span: &tracing::Span, headers: &mut HeaderMap) {
+    let context = span.context();
+    let span = context.span();
+    let span_context = span.span_context();
+
+    if !span_context.is_valid() {
+        return;
+    }
+
+    let traceparent = format!(
+        "00-{}-{}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags()
+    );
+
+    if let Ok(value) = HeaderValue::from_str(&traceparent) {
+        headers.insert("traceparent", value);
+    }
+
+    let tracestate = span_context.trace_state().header();
+
+    if !tracestate.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&tracestate) {
+            headers.insert("tracestate", value);
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use opentelemetry::{
+        trace::TracerProvider,
+        Value,
+    };
+    use opentelemetry_sdk::trace::{
+        InMemorySpanExporter,
+        SdkTracerProvider,
+    };
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+    };
+
+    #[tokio::test]
+    async fn traceparent_and_attributes() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+            MockResponse::new(StatusCode::OK)
+                .body(include_str!("../../resources/registry/redhat/ubi8.json")),
+        );
+
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+        client.get_manifest(&image).await.unwrap();
+
+        provider.force_flush().unwrap();
+        let spans = exporter.get_finished_spans().unwrap();
+        let request = spans
+            .iter()
+            .find(|span| span.name == "http.request")
+            .unwrap();
+
+        let traceparent = transport.requests()[0]
+            .headers
+            .get("traceparent")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            traceparent,
+            format!(
+                "00-{}-{}-01",
+                request.span_context.trace_id(),
+                request.span_context.span_id()
+            )
+        );
+
+        let attribute = |key: &str| {
+            request
+                .attributes
+                .iter()
+                .find(|attribute| attribute.key.as_str() == key)
+                .map(|attribute| attribute.value.clone())
+        };
+
+        assert_eq!(
+            attribute("server.address"),
+            Some(Value::from("registry.access.redhat.com"))
+        );
+        assert_eq!(
+            attribute("http.response.status_code"),
+            Some(Value::I64(200))
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use http::{
+    header::{
+        HeaderMap,
+        CONTENT_TYPE,
+    },
+    Method,
+    StatusCode,
+};
+use url::Url;
+
+use crate::{
+    docker::{
+        challenge,
+        transport::Request,
+        Client,
+        Error,
+        FailedResponse,
+    },
+    Registry,
+};
+
+const API_VERSION_HEADER: &str = "Docker-Distribution-API-Version";
+const SUPPORTED_API_VERSION: &str = "registry/2.0";
+
+/// The result of [`Client::ping`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingResult {
+    /// The value of the `Docker-Distribution-API-Version` header, usually
+    /// `registry/2.0`.
+    pub api_version: Option<String>,
+
+    /// True if the registry asked for authentication and accepted the token
+    /// the client fetched for it.
+    pub authenticated: bool,
+
+    /// The round trip time of the first request to `/v2/`.
+    pub latency: Duration,
+}
+
+impl Client {
+    /// Checks that the registry speaks the distribution API by requesting
+    /// `/v2/`. If the registry answers with a bearer challenge a token is
+    /// fetched and the request is repeated with it.
+    ///
+    /// # Errors
+    /// Returns [`Error::NotARegistry`] if the host answers `/v2/` with not
+    /// found.
+    /// Returns [`Error::NotAV2Registry`] if the answer is an HTML page or
+    /// does not announce the `registry/2.0` API version.
+    /// Returns an error if the client is offline.
+    /// Returns an error if a request fails or the token can not be fetched.
+    #[tracing::instrument(name = "ping", skip_all, fields(registry = %registry))]
+    pub async fn ping(&self, registry: &Registry) -> Result<PingResult, Error> {
+        self.check_registry_policy(registry)?;
+
+        if self.inner.offline {
+            return Err(Error::Offline);
+        }
+
+        let url = self
+            .inner
+            .mirrors
+            .upstream(registry)
+            .and_then(|base| base.join("v2/"))
+            .map_err(Error::InvalidPingUrl)?;
+
+        let start = Instant::now();
+
+        let response = self
+            .execute(Request::new(Method::GET, url.clone()))
+            .await
+            .map_err(Error::Ping)?;
+
+        let latency = start.elapsed();
+        let api_version = api_version(&response.headers);
+
+        let (response, authenticated) = match challenge::of(&response) {
+            Some(challenge) => {
+                let headers = self
+                    .get_challenge_token(registry, &challenge)
+                    .await?
+                    .try_into()
+                    .map_err(Error::ParseAuthorizationHeader)?;
+
+                let response = self
+                    .execute(Request::new(Method::GET, url.clone()).headers(headers))
+                    .await
+                    .map_err(Error::Ping)?;
+
+                let authenticated = response.status.is_success();
+
+                (response, authenticated)
+            }
+
+            None => (response, false),
+        };
+
+        let status = response.status;
+
+        if status == StatusCode::NOT_FOUND {
+            return Err(Error::NotARegistry(url));
+        }
+
+        let api_version = api_version.or_else(|| self::api_version(&response.headers));
+        self.check_api_version(
+            registry,
+            &url,
+            &response.headers,
+            api_version.as_deref(),
+            true,
+        )?;
+
+        // An unauthorized answer still means the host speaks the API.
+        if !status.is_success() && status != StatusCode::UNAUTHORIZED {
+            return Err(Error::FailedPingRequest(
+                FailedResponse::read(response, self.inner.max_manifest_size).await,
+            ));
+        }
+
+        Ok(PingResult {
+            api_version,
+            authenticated,
+            latency,
+        })
+    }
+
+    /// Fails with [`Error::NotAV2Registry`] if the response is an HTML page or
+    /// announces an API version other than `registry/2.0`, which happens with
+    /// reverse proxies and v1 registries. With `require_header` a missing
+    /// version header fails as well. Registries configured with
+    /// [`crate::ClientBuilder::skip_api_version_check`] are not checked.
+    pub(super) fn check_api_version(
+        &self,
+        registry: &Registry,
+        url: &Url,
+        headers: &HeaderMap,
+        api_version: Option<&str>,
+        require_header: bool,
+    ) -> Result<(), Error> {
+        if self.inner.api_version_exempt.contains(registry) {
+            return Ok(());
+        }
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+
+        let is_html = content_type.is_some_and(|content_type| {
+            content_type
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("text/html")
+        });
+
+        let compatible = match api_version {
+            Some(version) => version.trim().eq_ignore_ascii_case(SUPPORTED_API_VERSION),
+            None => !require_header,
+        };
+
+        if is_html || !compatible {
+            return Err(Error::NotAV2Registry {
+                url: url.clone(),
+                content_type: content_type.map(String::from),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn api_version(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(API_VERSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        header::{
+            HeaderMap,
+            AUTHORIZATION,
+        },
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            self,
+            MockResponse,
+            MockTransport,
+            Request,
+            Response,
+            Transport,
+        },
+        Client,
+        ClientError,
+        Registry,
+    };
+
+    const CHALLENGE: &str = r#"Bearer realm="https://ghcr.io/token",service="ghcr.io""#;
+
+    /// Answers `/v2/` with a challenge unless the request carries the token
+    /// served by the token endpoint.
+    #[derive(Debug)]
+    struct Challenging;
+
+    #[async_trait::async_trait]
+    impl Transport for Challenging {
+        async fn execute(&self, request: Request) -> Result<Response, transport::Error> {
+            let authorized = request
+                .headers
+                .get(AUTHORIZATION)
+                .is_some_and(|value| value == "Bearer ping-token");
+
+            let response = match request.url.as_str() {
+                "https://ghcr.io/token?service=ghcr.io" => {
+                    MockResponse::new(StatusCode::OK).body(r#"{"token":"ping-token"}"#)
+                }
+
+                "https://ghcr.io/v2/" if authorized => MockResponse::new(StatusCode::OK)
+                    .header("Docker-Distribution-API-Version", "registry/2.0"),
+
+                "https://ghcr.io/v2/" => MockResponse::new(StatusCode::UNAUTHORIZED)
+                    .header("Docker-Distribution-API-Version", "registry/2.0")
+                    .header("WWW-Authenticate", CHALLENGE),
+
+                url => return Err(transport::Error::Unmatched(url.to_string())),
+            };
+
+            MockTransport::new()
+                .with_response(Method::GET, request.url.as_str(), response)
+                .execute(request)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticated() {
+        let client = Client::builder().transport(Challenging).build();
+
+        let got = client.ping(&Registry::Github).await.unwrap();
+
+        assert!(got.authenticated);
+        assert_eq!(got.api_version.as_deref(), Some("registry/2.0"));
+    }
+
+    #[tokio::test]
+    async fn anonymous() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://registry.k8s.io/v2/",
+            MockResponse::new(StatusCode::OK)
+                .header("Docker-Distribution-API-Version", "registry/2.0"),
+        );
+        let client = Client::builder().transport(transport.clone()).build();
+
+        let got = client.ping(&Registry::K8s).await.unwrap();
+
+        assert!(!got.authenticated);
+        assert_eq!(got.api_version.as_deref(), Some("registry/2.0"));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn not_a_registry() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://mcr.microsoft.com/v2/",
+            MockResponse::new(StatusCode::NOT_FOUND),
+        );
+        let client = Client::builder().transport(transport).build();
+
+        let got = client.ping(&Registry::Microsoft).await.unwrap_err();
+
+        assert!(
+            matches!(got, ClientError::NotARegistry(url) if url.as_str() == "https://mcr.microsoft.com/v2/")
+        );
+    }
+
+    #[tokio::test]
+    async fn html_page() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://registry.k8s.io/v2/",
+            MockResponse::new(StatusCode::OK)
+                .header("Content-Type", "text/html; charset=utf-8")
+                .body("<html><body>Welcome</body></html>"),
+        );
+        let client = Client::builder().transport(transport).build();
+
+        let got = client.ping(&Registry::K8s).await.unwrap_err();
+
+        assert!(matches!(
+            got,
+            ClientError::NotAV2Registry { content_type: Some(content_type), .. }
+                if content_type == "text/html; charset=utf-8"
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_api_version() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://registry.k8s.io/v2/",
+            MockResponse::new(StatusCode::OK)
+                .header("Content-Type", "application/json")
+                .body("{}"),
+        );
+
+        let client = Client::builder().transport(transport.clone()).build();
+        let got = client.ping(&Registry::K8s).await.unwrap_err();
+
+        assert!(matches!(got, ClientError::NotAV2Registry { .. }));
+
+        let client = Client::builder()
+            .transport(transport)
+            .skip_api_version_check("registry.k8s.io")
+            .build();
+        let got = client.ping(&Registry::K8s).await.unwrap();
+
+        assert_eq!(got.api_version, None);
+    }
+
+    #[tokio::test]
+    async fn incompatible_api_version() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            "https://registry.k8s.io/v2/",
+            MockResponse::new(StatusCode::OK)
+                .header("Docker-Distribution-API-Version", "registry/1.0"),
+        );
+        let client = Client::builder().transport(transport).build();
+
+        let got = client.ping(&Registry::K8s).await.unwrap_err();
+
+        assert!(matches!(got, ClientError::NotAV2Registry { .. }));
+    }
+
+    #[test]
+    fn parse_challenge() {
+        let mut headers = HeaderMap::new();
+        headers.insert("WWW-Authenticate", CHALLENGE.parse().unwrap());
+
+        let got = crate::docker::challenge::parse(&headers).unwrap();
+
+        assert_eq!(got.realm, "https://ghcr.io/token");
+        assert_eq!(got.service.as_deref(), Some("ghcr.io"));
+    }
+
+    #[test]
+    fn basic_challenge() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "WWW-Authenticate",
+            r#"Basic realm="registry""#.parse().unwrap(),
+        );
+
+        assert_eq!(crate::docker::challenge::parse(&headers), None);
+    }
+}
@@ -0,0 +1,338 @@
+use either::Either;
+
+use crate::{
+    docker::{
+        Client,
+        Error,
+        Response,
+    },
+    image::image_name::ImageName,
+    manifest::{
+        Entry,
+        Platform,
+    },
+    Image,
+    Manifest,
+};
+
+/// The result of [`Client::get_manifest_resolved`].
+#[derive(Debug, Clone)]
+pub enum ResolvedManifest {
+    /// The image pointed to a manifest list, `response` is the manifest of the
+    /// entry for the default platform.
+    Followed {
+        list_digest: Option<String>,
+        response: Response,
+    },
+
+    /// The image did not point to a manifest list.
+    Original(Response),
+}
+
+impl ResolvedManifest {
+    #[must_use]
+    pub fn response(&self) -> &Response {
+        match self {
+            Self::Followed { response, .. } | Self::Original(response) => response,
+        }
+    }
+
+    #[must_use]
+    pub fn into_response(self) -> Response {
+        match self {
+            Self::Followed { response, .. } | Self::Original(response) => response,
+        }
+    }
+}
+
+impl Client {
+    /// Returns the platform used to resolve manifest lists if no platform is
+    /// given explicitly.
+    #[must_use]
+    pub fn default_platform(&self) -> &Platform {
+        &self.inner.default_platform
+    }
+
+    /// Fetches the manifest of `image`. If it is a manifest list the entry
+    /// for `platform` is fetched instead and checked against the size and
+    /// digest of the entry. Manifests that are not lists are returned as they
+    /// are.
+    ///
+    /// # Errors
+    /// Returns [`Error::NoMatchingPlatform`] if the list has no entry for the
+    /// platform.
+    /// Returns [`Error::DescriptorMismatch`] if the fetched manifest does not
+    /// match the entry, see [`crate::ClientBuilder::verify_descriptors`].
+    /// Returns an error if one of the manifests can not be fetched, see
+    /// [`Client::get_manifest`].
+    pub async fn get_manifest_for_platform(
+        &self,
+        image: &Image,
+        platform: &Platform,
+    ) -> Result<Response, Error> {
+        let response = self.get_manifest(image).await?;
+
+        Ok(self
+            .resolve(image, response, platform)
+            .await?
+            .into_response())
+    }
+
+    /// Fetches the manifest of `image` and follows manifest lists to the entry
+    /// for the default platform of the client, see
+    /// [`crate::ClientBuilder::default_platform`].
+    ///
+    /// # Errors
+    /// Returns [`Error::NoMatchingPlatform`] if the list has no entry for the
+    /// default platform.
+    /// Returns [`Error::DescriptorMismatch`] if the fetched manifest does not
+    /// match the entry.
+    /// Returns an error if one of the manifests can not be fetched, see
+    /// [`Client::get_manifest`].
+    pub async fn get_manifest_resolved(&self, image: &Image) -> Result<ResolvedManifest, Error> {
+        let response = self.get_manifest(image).await?;
+
+        self.resolve(image, response, &self.inner.default_platform)
+            .await
+    }
+
+    pub(super) async fn resolve(
+        &self,
+        image: &Image,
+        response: Response,
+        platform: &Platform,
+    ) -> Result<ResolvedManifest, Error> {
+        let Manifest::List(list) = &response.manifest else {
+            return Ok(ResolvedManifest::Original(response));
+        };
+
+        let entry = list
+            .find(platform)
+            .ok_or_else(|| Error::NoMatchingPlatform {
+                image: image.clone(),
+                platform: Box::new(platform.clone()),
+            })?;
+
+        let digest = entry.digest.clone();
+        let followed = Image {
+            image_name: ImageName::new(image.image_name.name.clone(), Either::Right(digest)),
+            ..image.clone()
+        };
+
+        let raw = self.get_manifest_raw(&followed).await?;
+
+        if self.inner.verify_descriptors {
+            verify(entry, &raw.body)?;
+        }
+
+        Ok(ResolvedManifest::Followed {
+            response: Self::response_from_raw(raw)?,
+            list_digest: response.digest,
+        })
+    }
+}
+
+/// Checks that `body` has the size and digest the list entry announced.
+pub(super) fn verify(entry: &Entry, body: &[u8]) -> Result<(), Error> {
+    let expected_digest = entry.digest.clone();
+    let actual_digest = expected_digest
+        .algorithm()
+        .map_err(Error::UnsupportedDigestAlgorithm)?
+        .digest(body);
+    let actual_size = body.len() as u64;
+
+    if actual_size != entry.size || !actual_digest.is_equivalent(&expected_digest) {
+        return Err(Error::DescriptorMismatch {
+            expected_size: entry.size,
+            actual_size,
+            expected_digest,
+            actual_digest,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::{
+            platform::ResolvedManifest,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        manifest::{
+            Architecture,
+            OperatingSystem,
+            Platform,
+        },
+        Client,
+        ClientError,
+        Digest,
+        Manifest,
+    };
+
+    const LIST_URL: &str = "https://registry.access.redhat.com/v2/ubi8/manifests/8.9";
+    const IMAGE: &str = include_str!("../../resources/manifest/image/example.json");
+
+    /// Serves a list with an amd64 and an arm64 entry that both describe
+    /// `IMAGE`. The arm64 entry is served with `body`.
+    fn transport(body: &str) -> MockTransport {
+        let digest = Digest::sha256(IMAGE.as_bytes());
+
+        let list = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": IMAGE.len(),
+                    "digest": "sha256:0000000000000000000000000000000000000000000000000000000000000000",
+                    "platform": { "architecture": "amd64", "os": "linux" }
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": IMAGE.len(),
+                    "digest": digest.to_string(),
+                    "platform": { "architecture": "arm64", "os": "linux", "variant": "v8" }
+                }
+            ]
+        });
+
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                LIST_URL,
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", "sha256:list")
+                    .body(list.to_string()),
+            )
+            .with_response(
+                Method::GET,
+                &format!("https://registry.access.redhat.com/v2/ubi8/manifests/{digest}"),
+                MockResponse::new(StatusCode::OK)
+                    .header("Docker-Content-Digest", &digest.to_string())
+                    .body(body.to_string()),
+            )
+    }
+
+    fn arm64() -> Platform {
+        Platform::new(OperatingSystem::Linux, Architecture::Arm64)
+    }
+
+    #[tokio::test]
+    async fn follows_default_platform() {
+        let client = Client::builder()
+            .transport(transport(IMAGE))
+            .default_platform(arm64())
+            .build();
+        let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+        let got = client.get_manifest_resolved(&image).await.unwrap();
+
+        let ResolvedManifest::Followed {
+            list_digest,
+            response,
+        } = got
+        else {
+            panic!("expected the list to be followed");
+        };
+
+        assert_eq!(list_digest.as_deref(), Some("sha256:list"));
+        assert_eq!(
+            response.digest,
+            Some(Digest::sha256(IMAGE.as_bytes()).to_string())
+        );
+        assert!(matches!(response.manifest, Manifest::Image(_)));
+    }
+
+    #[tokio::test]
+    async fn descriptor_mismatch() {
+        // The body does not match the digest it is requested by either, let
+        // it through to reach the check against the list entry.
+        let short = &IMAGE[..IMAGE.len() - 1];
+        let client = Client::builder()
+            .transport(transport(short))
+            .allow_digest_mismatch(true)
+            .build();
+        let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+        let got = client
+            .get_manifest_for_platform(&image, &arm64())
+            .await
+            .unwrap_err();
+
+        let ClientError::DescriptorMismatch {
+            expected_size,
+            actual_size,
+            expected_digest,
+            actual_digest,
+        } = got
+        else {
+            panic!("expected a descriptor mismatch, got {got}");
+        };
+
+        assert_eq!(expected_size, IMAGE.len() as u64);
+        assert_eq!(actual_size, short.len() as u64);
+        assert_eq!(expected_digest, Digest::sha256(IMAGE.as_bytes()));
+        assert_eq!(actual_digest, Digest::sha256(short.as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn descriptor_mismatch_ignored() {
+        let short = &IMAGE[..IMAGE.len() - 1];
+        let client = Client::builder()
+            .transport(transport(short))
+            .verify_descriptors(false)
+            .allow_digest_mismatch(true)
+            .build();
+        let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+        let got = client
+            .get_manifest_for_platform(&image, &arm64())
+            .await
+            .unwrap();
+
+        assert!(matches!(got.manifest, Manifest::Image(_)));
+    }
+
+    #[tokio::test]
+    async fn no_matching_platform() {
+        let client = Client::builder().transport(transport(IMAGE)).build();
+        let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+        let platform = Platform::new(OperatingSystem::Windows, Architecture::Amd64);
+
+        let got = client
+            .get_manifest_for_platform(&image, &platform)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::NoMatchingPlatform { .. }));
+    }
+
+    #[tokio::test]
+    async fn original_manifest() {
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            LIST_URL,
+            MockResponse::new(StatusCode::OK)
+                .body(include_str!("../../resources/manifest/image/example.json")),
+        );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+
+        let got = client.get_manifest_resolved(&image).await.unwrap();
+
+        assert!(matches!(got, ResolvedManifest::Original(_)));
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::Registry;
+
+/// Which registries a [`crate::Client`] may talk to, set with
+/// [`crate::ClientBuilder::registry_policy`]. Operations on any other
+/// registry fail with [`crate::docker::Error::RegistryDenied`] before a
+/// request is sent.
+///
+/// A registry is denied if it matches a deny pattern, or if there are allow
+/// patterns and it matches none of them. The default policy allows every
+/// registry.
+///
+/// Patterns are either a host like `ghcr.io` or `localhost:5000`, or a
+/// wildcard like `*.internal.example.com` that matches every subdomain on
+/// any port, but not `internal.example.com` itself. Hosts are compared
+/// case-insensitively and `docker.io` and `index.docker.io` both name Docker
+/// Hub.
+///
+/// Only the registry of an image is checked, configured mirrors are trusted.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    Registry(Registry),
+
+    /// The suffix including its leading dot, `.internal.example.com`.
+    Suffix(String),
+}
+
+impl Policy {
+    /// Allows every registry, same as [`Policy::default`].
+    #[must_use]
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Allows registries matching `pattern`. Once a pattern is allowed, all
+    /// registries that are not allowed explicitly are denied.
+    #[must_use]
+    pub fn allow(mut self, pattern: &str) -> Self {
+        self.allow.push(Pattern::new(pattern));
+        self
+    }
+
+    /// Denies registries matching `pattern`, even if they are allowed.
+    #[must_use]
+    pub fn deny(mut self, pattern: &str) -> Self {
+        self.deny.push(Pattern::new(pattern));
+        self
+    }
+
+    #[must_use]
+    pub fn is_allowed(&self, registry: &Registry) -> bool {
+        let matches = |pattern: &Pattern| pattern.matches(registry);
+
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        let pattern = pattern.trim().to_ascii_lowercase();
+
+        match pattern.strip_prefix('*') {
+            Some(suffix) if suffix.starts_with('.') => Self::Suffix(suffix.to_string()),
+            _ => Self::Registry(Registry::try_from_host(&pattern)),
+        }
+    }
+
+    fn matches(&self, registry: &Registry) -> bool {
+        match self {
+            Self::Registry(expected) => {
+                *expected
+                    == Registry::try_from_host(&registry.registry_domain().to_ascii_lowercase())
+            }
+
+            Self::Suffix(suffix) => {
+                let domain = registry.registry_domain().to_ascii_lowercase();
+                let host = domain.split(':').next().unwrap_or_default();
+
+                host.ends_with(suffix.as_str())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(host: &str) -> Registry {
+        Registry::try_from_host(host)
+    }
+
+    #[test]
+    fn allow_all() {
+        let policy = Policy::default();
+
+        assert!(policy.is_allowed(&Registry::DockerHub));
+        assert!(policy.is_allowed(&host("registry.example.com:5000")));
+    }
+
+    #[test]
+    fn allowlist() {
+        let policy = Policy::default().allow("ghcr.io").allow("docker.io");
+
+        assert!(policy.is_allowed(&Registry::Github));
+        assert!(policy.is_allowed(&Registry::DockerHub));
+        assert!(!policy.is_allowed(&Registry::Quay));
+        assert!(!policy.is_allowed(&host("ghcr.io.example.com")));
+    }
+
+    #[test]
+    fn denylist() {
+        let policy = Policy::allow_all()
+            .deny("quay.io")
+            .deny("*.untrusted.example");
+
+        assert!(policy.is_allowed(&Registry::Github));
+        assert!(!policy.is_allowed(&Registry::Quay));
+        assert!(!policy.is_allowed(&host("cache.untrusted.example")));
+    }
+
+    #[test]
+    fn wildcard() {
+        let policy = Policy::default().allow("*.Internal.Example.com");
+
+        assert!(policy.is_allowed(&host("registry.internal.example.com")));
+        assert!(policy.is_allowed(&host("a.b.internal.example.com:5000")));
+        assert!(!policy.is_allowed(&host("internal.example.com")));
+        assert!(!policy.is_allowed(&host("evilinternal.example.com")));
+        assert!(!policy.is_allowed(&Registry::DockerHub));
+    }
+
+    #[test]
+    fn deny_wins() {
+        let policy = Policy::default()
+            .allow("*.example.com")
+            .deny("legacy.example.com");
+
+        assert!(policy.is_allowed(&host("new.example.com")));
+        assert!(!policy.is_allowed(&host("legacy.example.com")));
+    }
+
+    #[test]
+    fn exact_port() {
+        let policy = Policy::default().allow("localhost:5000");
+
+        assert!(policy.is_allowed(&host("localhost:5000")));
+        assert!(!policy.is_allowed(&host("localhost:5001")));
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{
+    Stream,
+    StreamExt,
+};
+
+/// `Progress` receives updates while a transfer is running, for example to
+/// drive a progress bar. All methods do nothing by default.
+///
+/// Methods are called from the streaming loop of the transfer so they should
+/// return quickly.
+pub trait Progress: Send + Sync {
+    /// Called once before the first chunk with the total size of the
+    /// transfer if it is known.
+    fn on_start(&self, _total: Option<u64>) {}
+
+    /// Called for every chunk with its size in bytes.
+    fn on_chunk(&self, _bytes: u64) {}
+
+    /// Called once after the last chunk if the transfer succeeded.
+    fn on_finish(&self) {}
+}
+
+/// `NoProgress` ignores all updates.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+/// Forwards only the chunks to the inner progress. Used when a transfer is
+/// part of a larger operation that reports its own start and finish.
+pub(super) struct ChunksOnly(pub(super) Arc<dyn Progress>);
+
+impl Progress for NoProgress {}
+
+impl Progress for ChunksOnly {
+    fn on_chunk(&self, bytes: u64) {
+        self.0.on_chunk(bytes);
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl Progress for indicatif::ProgressBar {
+    fn on_start(&self, total: Option<u64>) {
+        if let Some(total) = total {
+            self.set_length(total);
+        }
+    }
+
+    fn on_chunk(&self, bytes: u64) {
+        self.inc(bytes);
+    }
+
+    fn on_finish(&self) {
+        self.finish();
+    }
+}
+
+/// Reports every chunk of `stream` to `progress` and calls
+/// [`Progress::on_finish`] once the stream ended without an error.
+pub(super) fn track<E>(
+    stream: impl Stream<Item = Result<Bytes, E>> + Send + 'static,
+    progress: Arc<dyn Progress>,
+) -> impl Stream<Item = Result<Bytes, E>> + Send + 'static {
+    let state = Some((Box::pin(stream), progress));
+
+    futures::stream::unfold(state, |state| async move {
+        let (mut stream, progress) = state?;
+
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                progress.on_chunk(chunk.len() as u64);
+                Some((Ok(chunk), Some((stream, progress))))
+            }
+
+            Some(Err(e)) => Some((Err(e), None)),
+
+            None => {
+                progress.on_finish();
+                None
+            }
+        }
+    })
+}
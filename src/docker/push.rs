@@ -0,0 +1,2076 @@
+use std::{
+    collections::BTreeMap,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
+};
+
+use async_compression::tokio::bufread::GzipEncoder;
+use bytes::Bytes;
+use either::Either;
+use futures::{
+    future::BoxFuture,
+    FutureExt,
+};
+use http::{
+    header::{
+        HeaderMap,
+        HeaderValue,
+        CONTENT_RANGE,
+        CONTENT_TYPE,
+        ETAG,
+        IF_MATCH,
+        LOCATION,
+    },
+    Method,
+    StatusCode,
+};
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    archive::DockerArchive,
+    docker::{
+        api::RegistryApi,
+        auth::Action,
+        cancellation::{
+            Cancellation,
+            Reason,
+        },
+        immutable,
+        media_types::MediaType,
+        negotiation::ManifestPreference,
+        progress::{
+            NoProgress,
+            Progress,
+        },
+        text,
+        transport::{
+            self,
+            Request,
+        },
+        warning,
+        Client,
+        Error,
+        FailedResponse,
+        DEFAULT_MAX_MANIFEST_SIZE,
+    },
+    image::{
+        append_segments,
+        image_name::digest::{
+            Algorithm,
+            Hasher,
+        },
+    },
+    manifest,
+    Digest,
+    Image,
+    ImageName,
+    Tag,
+};
+
+/// Annotation of `index.json` entries that holds the tag of the entry.
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Response header of a manifest push, set by registries that track the
+/// `subject` of the manifest themselves.
+const OCI_SUBJECT_HEADER: &str = "OCI-Subject";
+
+/// A content descriptor of an OCI layout. Unlike [`crate::manifest::Entry`]
+/// the platform is optional.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: Digest,
+    size: u64,
+
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+/// `index.json` of a layout or a nested image index.
+#[derive(Debug, Deserialize)]
+struct Index {
+    manifests: Vec<Descriptor>,
+}
+
+/// The parts of an image manifest that reference blobs.
+#[derive(Debug, Deserialize)]
+struct ImageManifest {
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+impl Client {
+    /// Pushes the image stored in the OCI image layout at `dir` to
+    /// `destination`. The manifest is picked from `index.json` by the tag or
+    /// digest of `destination`, a layout with a single manifest is pushed
+    /// under any tag.
+    ///
+    /// Blobs the repository already has are skipped, all others are checked
+    /// against their digest before they are uploaded. Blobs larger than the
+    /// upload chunk size of the client are uploaded in chunks. Manifests of an
+    /// index are pushed by digest before the index itself. Returns the digest
+    /// of the pushed manifest.
+    ///
+    /// # Errors
+    /// Returns [`Error::LayoutManifestNotFound`] if no manifest of the layout
+    /// matches `destination`.
+    /// Returns [`Error::DescriptorMismatch`] if a file of the layout does not
+    /// match its descriptor.
+    /// Returns an error if the client is offline, the layout can not be read
+    /// or a request fails.
+    pub async fn push_oci_layout(&self, dir: &Path, destination: &Image) -> Result<Digest, Error> {
+        self.push_oci_layout_with_cancellation(dir, destination, &Cancellation::default())
+            .await
+    }
+
+    /// Same as [`Client::push_oci_layout`] but stops once `cancellation`
+    /// fires. A blob upload that was already started is deleted from the
+    /// registry.
+    ///
+    /// # Errors
+    /// Returns [`Error::Cancelled`] with the blobs and manifests that were
+    /// pushed before the push was stopped.
+    /// Returns the errors of [`Client::push_oci_layout`].
+    #[tracing::instrument(
+        name = "push_oci_layout",
+        skip_all,
+        fields(
+            dir = %dir.display(),
+            image = %destination,
+        )
+    )]
+    pub async fn push_oci_layout_with_cancellation(
+        &self,
+        dir: &Path,
+        destination: &Image,
+        cancellation: &Cancellation,
+    ) -> Result<Digest, Error> {
+        self.check_registry_policy(&destination.registry)?;
+
+        let index: Index = read_json(&dir.join("index.json")).await?;
+
+        let descriptor = select_manifest(&index.manifests, destination)?;
+
+        let mut completed = Vec::new();
+
+        self.push_layout_manifest(
+            dir,
+            destination.clone(),
+            descriptor,
+            cancellation,
+            &mut completed,
+        )
+        .await
+        .map_err(|e| e.with_completed(completed))
+    }
+
+    /// Pushes the image of a `docker save` tarball to `destination`. The
+    /// image is picked from the archive by the name and tag of
+    /// `destination`, an archive with a single image is pushed under any name.
+    ///
+    /// The uncompressed layers of the archive are gzip compressed before they
+    /// are uploaded, blobs the repository already has are skipped. The image
+    /// is pushed as a Docker v2 manifest whose digest is returned.
+    ///
+    /// # Errors
+    /// Returns [`Error::ArchiveImageNotFound`] if no image of the archive
+    /// matches `destination`.
+    /// Returns an error if the client is offline, a layer can not be
+    /// compressed or a request fails.
+    pub async fn push_archive(
+        &self,
+        archive: &DockerArchive,
+        destination: &Image,
+    ) -> Result<Digest, Error> {
+        self.push_archive_with_cancellation(archive, destination, &Cancellation::default())
+            .await
+    }
+
+    /// Same as [`Client::push_archive`] but stops once `cancellation` fires.
+    /// A blob upload that was already started is deleted from the registry.
+    ///
+    /// # Errors
+    /// Returns [`Error::Cancelled`] with the blobs that were pushed before
+    /// the push was stopped.
+    /// Returns the errors of [`Client::push_archive`].
+    #[tracing::instrument(name = "push_archive", skip_all, fields(image = %destination))]
+    pub async fn push_archive_with_cancellation(
+        &self,
+        archive: &DockerArchive,
+        destination: &Image,
+        cancellation: &Cancellation,
+    ) -> Result<Digest, Error> {
+        self.check_registry_policy(&destination.registry)?;
+
+        let mut completed = Vec::new();
+
+        self.push_archive_image(archive, destination, cancellation, &mut completed)
+            .await
+            .map_err(|e| e.with_completed(completed))
+    }
+
+    async fn push_archive_image(
+        &self,
+        archive: &DockerArchive,
+        destination: &Image,
+        cancellation: &Cancellation,
+        completed: &mut Vec<Digest>,
+    ) -> Result<Digest, Error> {
+        let image = archive
+            .find(destination)
+            .ok_or_else(|| Error::ArchiveImageNotFound(destination.clone()))?;
+
+        let mut layers = Vec::with_capacity(image.layers.len());
+
+        for layer in &image.layers {
+            cancellation.check().map_err(Reason::error)?;
+
+            let mut compressed = Vec::new();
+            GzipEncoder::new(layer.tar().as_ref())
+                .read_to_end(&mut compressed)
+                .await
+                .map_err(Error::CompressLayer)?;
+
+            let digest = Digest::sha256(&compressed);
+
+            layers.push(manifest::Layer::new(
+                MediaType::DockerLayer.as_str(),
+                compressed.len() as u64,
+                digest.clone(),
+            ));
+
+            if !self
+                .blob_exists_unless_stopped(destination, &digest, cancellation)
+                .await?
+            {
+                self.push_blob_with_cancellation(
+                    destination,
+                    &digest,
+                    compressed.into(),
+                    cancellation,
+                )
+                .await?;
+
+                completed.push(digest);
+            }
+        }
+
+        let config_digest = image.config_digest();
+
+        if !self
+            .blob_exists_unless_stopped(destination, &config_digest, cancellation)
+            .await?
+        {
+            self.push_blob_with_cancellation(
+                destination,
+                &config_digest,
+                image.config_blob().clone(),
+                cancellation,
+            )
+            .await?;
+
+            completed.push(config_digest.clone());
+        }
+
+        let manifest = manifest::Image {
+            schema_version: manifest::SchemaVersion::V2,
+            media_type: MediaType::DockerManifest.to_string(),
+            config: manifest::Config::new(
+                MediaType::DockerContainerConfig.as_str(),
+                image.config_blob().len() as u64,
+                config_digest,
+            ),
+            layers,
+        };
+
+        let body = serde_json::to_vec(&manifest).map_err(Error::SerializeManifest)?;
+
+        cancellation
+            .run(self.push_manifest(destination, MediaType::DockerManifest.as_str(), body.into()))
+            .await
+            .map_err(Reason::error)?
+    }
+
+    /// Returns true if the repository of `image` has the blob with the given
+    /// digest.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline or the request fails.
+    pub async fn blob_exists(&self, image: &Image, digest: &Digest) -> Result<bool, Error> {
+        let segments = image.blob_segments(digest).map_err(Error::InvalidPath)?;
+
+        let url = self
+            .inner
+            .mirrors
+            .upstream(&image.registry)
+            .map(|base| append_segments(base, &segments))
+            .map_err(Error::InvalidBlobUrl)?;
+
+        let response = RegistryApi::for_client(self)
+            .blob_request(Method::HEAD, url, self.push_headers(image).await?)
+            .await?;
+
+        warning::collect(&response.headers);
+
+        match response.status {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => Err(Error::FailedBlobRequest(
+                FailedResponse::read(response, DEFAULT_MAX_MANIFEST_SIZE).await,
+            )),
+        }
+    }
+
+    /// Uploads `content` as a blob with the given digest to the repository of
+    /// `image`. Content larger than the upload chunk size of the client is
+    /// sent in chunks.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline, a request fails or the
+    /// registry rejects the upload.
+    pub async fn push_blob(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        content: Bytes,
+    ) -> Result<(), Error> {
+        self.push_blob_with_cancellation(image, digest, content, &Cancellation::default())
+            .await
+    }
+
+    /// Same as [`Client::push_blob`] but stops once `cancellation` fires. It
+    /// is checked before every chunk, an upload that was already started is
+    /// deleted from the registry.
+    ///
+    /// # Errors
+    /// Returns [`Error::Cancelled`] if the upload was stopped.
+    /// Returns the errors of [`Client::push_blob`].
+    #[tracing::instrument(
+        name = "push_blob",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            digest = %digest,
+        )
+    )]
+    pub async fn push_blob_with_cancellation(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        content: Bytes,
+        cancellation: &Cancellation,
+    ) -> Result<(), Error> {
+        let mut location = self.start_upload(image, cancellation).await?;
+
+        let chunk_size = usize::try_from(self.inner.upload_chunk_size).unwrap_or(usize::MAX);
+        let mut body = content.clone();
+
+        if content.len() > chunk_size {
+            for start in (0..content.len()).step_by(chunk_size) {
+                let chunk = content.slice(start..content.len().min(start + chunk_size));
+
+                location = self
+                    .upload_chunk(image, location, start as u64, chunk, cancellation)
+                    .await?;
+            }
+
+            body = Bytes::new();
+        }
+
+        self.finish_upload(image, &location, digest, body, cancellation)
+            .await
+    }
+
+    /// Uploads the content of `reader` as a blob to the repository of `image`
+    /// without knowing its digest up front, for example for a layer that is
+    /// compressed while it is uploaded. The content is sent in chunks of the
+    /// upload chunk size of the client and hashed on the way. Returns the
+    /// digest and the size of the uploaded blob.
+    ///
+    /// # Errors
+    /// Returns an error if reading from `reader` fails.
+    /// Returns the errors of [`Client::push_blob`].
+    pub async fn upload_blob_unhashed(
+        &self,
+        image: &Image,
+        reader: impl AsyncRead + Unpin,
+    ) -> Result<(Digest, u64), Error> {
+        self.upload_blob_unhashed_with_progress(image, reader, Arc::new(NoProgress))
+            .await
+    }
+
+    /// Same as [`Client::upload_blob_unhashed`] but reports every uploaded
+    /// chunk to `progress`. The total is not known when the upload starts.
+    ///
+    /// # Errors
+    /// Returns the errors of [`Client::upload_blob_unhashed`].
+    #[tracing::instrument(
+        name = "upload_blob_unhashed",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+        )
+    )]
+    pub async fn upload_blob_unhashed_with_progress(
+        &self,
+        image: &Image,
+        mut reader: impl AsyncRead + Unpin,
+        progress: Arc<dyn Progress>,
+    ) -> Result<(Digest, u64), Error> {
+        let cancellation = Cancellation::default();
+        let mut location = self.start_upload(image, &cancellation).await?;
+
+        let chunk_size = usize::try_from(self.inner.upload_chunk_size).unwrap_or(usize::MAX);
+        let mut hasher = Hasher::new(Algorithm::Sha256);
+        let mut size = 0;
+
+        progress.on_start(None);
+
+        loop {
+            let chunk = read_chunk(&mut reader, chunk_size).await?;
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            hasher.update(&chunk);
+            let len = chunk.len() as u64;
+
+            location = self
+                .upload_chunk(image, location, size, chunk, &cancellation)
+                .await?;
+
+            size += len;
+            progress.on_chunk(len);
+        }
+
+        let digest = hasher.finalize();
+
+        self.finish_upload(image, &location, &digest, Bytes::new(), &cancellation)
+            .await?;
+
+        progress.on_finish();
+
+        Ok((digest, size))
+    }
+
+    /// Pushes `body` as a manifest with the given media type under the tag or
+    /// digest of `image` and returns its digest.
+    ///
+    /// A manifest with a `subject`, like a signature or an SBOM, is a
+    /// referrer of the manifest its subject points to. Registries that
+    /// support the referrers API confirm that they keep track of it with the
+    /// `OCI-Subject` response header. Without that header the referrers tag
+    /// schema of the distribution specification is maintained instead: the
+    /// image index tagged `sha256-<hex of the subject digest>` is fetched or
+    /// created, a descriptor of the manifest is appended and the index is
+    /// pushed again.
+    ///
+    /// Updating that index is a read-modify-write without a lock, two clients
+    /// that push a referrer for the same subject at the same time can both
+    /// read the same index and the later push drops the descriptor of the
+    /// earlier one. If the registry sends an `ETag` with the index it is
+    /// pushed with `If-Match`, so the registry rejects the second push with
+    /// `412 Precondition Failed` instead, which is returned as
+    /// [`Error::FailedManifestPush`] and can be retried. Registries without
+    /// `ETag` support do not detect the race.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline, the request fails or the
+    /// registry rejects the manifest.
+    /// Returns an error if the referrers index can not be fetched, parsed or
+    /// pushed. The manifest itself was pushed in that case.
+    #[tracing::instrument(name = "push_manifest", skip_all, fields(image = %image))]
+    pub async fn push_manifest(
+        &self,
+        image: &Image,
+        media_type: &str,
+        body: Bytes,
+    ) -> Result<Digest, Error> {
+        let referrer = referrer_descriptor(media_type, &body);
+
+        let (digest, response) = self
+            .put_manifest(image, media_type, body, HeaderMap::new())
+            .await?;
+
+        if let Some((subject, mut descriptor)) = referrer {
+            if response.headers.contains_key(OCI_SUBJECT_HEADER) {
+                return Ok(digest);
+            }
+
+            descriptor["digest"] = serde_json::Value::String(digest.to_string());
+            self.append_referrer(image, &subject, descriptor).await?;
+        }
+
+        Ok(digest)
+    }
+
+    /// Adds `descriptor` to the fallback referrers index of `subject` in the
+    /// repository of `image`, see [`Client::push_manifest`].
+    async fn append_referrer(
+        &self,
+        image: &Image,
+        subject: &Digest,
+        descriptor: serde_json::Value,
+    ) -> Result<(), Error> {
+        let tag = Image {
+            image_name: ImageName::new(
+                image.image_name.name.clone(),
+                Either::Left(referrers_tag(subject)),
+            ),
+            ..image.clone()
+        };
+
+        let segments = tag.manifest_segments().map_err(Error::InvalidPath)?;
+
+        let url = self
+            .inner
+            .mirrors
+            .upstream(&tag.registry)
+            .map(|base| append_segments(base, &segments))
+            .map_err(Error::InvalidManifestUrl)?;
+
+        let api = RegistryApi::for_client(self);
+        let response = api
+            .manifest_request(
+                Method::GET,
+                url,
+                self.push_headers(&tag).await?,
+                MediaType::OciIndex.as_str(),
+            )
+            .await?;
+
+        warning::collect(&response.headers);
+
+        let (mut index, etag) = match response.status {
+            StatusCode::NOT_FOUND => (
+                serde_json::json!({
+                    "schemaVersion": 2,
+                    "mediaType": MediaType::OciIndex,
+                    "manifests": [],
+                }),
+                None,
+            ),
+
+            status if status.is_success() => {
+                let etag = response.headers.get(ETAG).cloned();
+                let body = text(api.read_manifest(response).await?);
+
+                let index = serde_json::from_str(&body)
+                    .map_err(|e| Error::DeserializeManifestBody(e, body))?;
+
+                (index, etag)
+            }
+
+            _ => return Err(failed(response, Error::FailedManifestRequest).await),
+        };
+
+        let Some(manifests) = index
+            .get_mut("manifests")
+            .and_then(serde_json::Value::as_array_mut)
+        else {
+            let body = index.to_string();
+            return Err(Error::DeserializeManifestBody(
+                serde::de::Error::missing_field("manifests"),
+                body,
+            ));
+        };
+
+        if manifests
+            .iter()
+            .any(|manifest| manifest.get("digest") == descriptor.get("digest"))
+        {
+            return Ok(());
+        }
+
+        manifests.push(descriptor);
+
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = etag {
+            headers.insert(IF_MATCH, etag);
+        }
+
+        self.put_manifest(
+            &tag,
+            MediaType::OciIndex.as_str(),
+            index.to_string().into(),
+            headers,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn put_manifest(
+        &self,
+        image: &Image,
+        media_type: &str,
+        body: Bytes,
+        headers: HeaderMap,
+    ) -> Result<(Digest, transport::Response), Error> {
+        let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
+
+        let url = self
+            .inner
+            .mirrors
+            .upstream(&image.registry)
+            .map(|base| append_segments(base, &segments))
+            .map_err(Error::InvalidManifestUrl)?;
+
+        let mut auth = self.push_headers(image).await?;
+        auth.extend(headers);
+
+        let (digest, response) = RegistryApi::for_client(self)
+            .manifest_put_request(url, media_type, body, auth)
+            .await?;
+
+        warning::collect(&response.headers);
+
+        if response.status != StatusCode::CREATED {
+            let response = FailedResponse::read(response, DEFAULT_MAX_MANIFEST_SIZE).await;
+
+            return Err(match &image.image_name.identifier {
+                Either::Left(tag) if immutable::is_tag_immutable(&response) => {
+                    Error::TagImmutable {
+                        tag: tag.to_string(),
+                        response,
+                    }
+                }
+                _ => Error::FailedManifestPush(response),
+            });
+        }
+
+        Ok((digest, response))
+    }
+
+    /// Returns true if the tag or digest of `image` exists in the original
+    /// registry, checked with a HEAD request. Push flows can use it to skip
+    /// tags that can not be overwritten, see
+    /// [`Client::is_tag_immutable_error`].
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline or the request fails.
+    /// Returns an error if the response status is neither successful nor
+    /// `404`.
+    #[tracing::instrument(name = "tag_exists", skip_all, fields(image = %image))]
+    pub async fn tag_exists(&self, image: &Image) -> Result<bool, Error> {
+        let segments = image.manifest_segments().map_err(Error::InvalidPath)?;
+
+        let url = self
+            .inner
+            .mirrors
+            .upstream(&image.registry)
+            .map(|base| append_segments(base, &segments))
+            .map_err(Error::InvalidManifestUrl)?;
+
+        let response = RegistryApi::for_client(self)
+            .manifest_request(
+                Method::HEAD,
+                url,
+                self.push_headers(image).await?,
+                &ManifestPreference::Any.accept(),
+            )
+            .await?;
+
+        warning::collect(&response.headers);
+
+        match response.status {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => Err(failed(response, Error::FailedManifestRequest).await),
+        }
+    }
+
+    /// Returns true if `error` is a manifest push the registry rejected
+    /// because the tag already exists and the repository does not allow
+    /// overwriting tags, as ECR and Harbor do for repositories with
+    /// immutable tags.
+    #[must_use]
+    pub fn is_tag_immutable_error(error: &Error) -> bool {
+        match error {
+            Error::TagImmutable { .. } => true,
+            Error::Shared(error) => Self::is_tag_immutable_error(error),
+            _ => false,
+        }
+    }
+
+    /// Pushes the blobs and nested manifests of `descriptor` and then the
+    /// manifest itself to `target`.
+    fn push_layout_manifest<'a>(
+        &'a self,
+        dir: &'a Path,
+        target: Image,
+        descriptor: &'a Descriptor,
+        cancellation: &'a Cancellation,
+        completed: &'a mut Vec<Digest>,
+    ) -> BoxFuture<'a, Result<Digest, Error>> {
+        async move {
+            let body = read_blob(dir, descriptor).await?;
+
+            if MediaType::from(descriptor.media_type.as_str()).is_index() {
+                let index: Index = deserialize(dir, &descriptor.digest, &body)?;
+
+                for child in &index.manifests {
+                    let child_target = Image {
+                        image_name: ImageName::new(
+                            target.image_name.name.clone(),
+                            Either::Right(child.digest.clone()),
+                        ),
+                        ..target.clone()
+                    };
+
+                    self.push_layout_manifest(dir, child_target, child, cancellation, completed)
+                        .await?;
+                }
+            } else {
+                let manifest: ImageManifest = deserialize(dir, &descriptor.digest, &body)?;
+
+                for blob in std::iter::once(&manifest.config).chain(&manifest.layers) {
+                    if self
+                        .blob_exists_unless_stopped(&target, &blob.digest, cancellation)
+                        .await?
+                    {
+                        continue;
+                    }
+
+                    let content = read_blob(dir, blob).await?;
+                    self.push_blob_with_cancellation(&target, &blob.digest, content, cancellation)
+                        .await?;
+
+                    completed.push(blob.digest.clone());
+                }
+            }
+
+            let digest = cancellation
+                .run(self.push_manifest(&target, &descriptor.media_type, body))
+                .await
+                .map_err(Reason::error)??;
+
+            completed.push(digest.clone());
+
+            Ok(digest)
+        }
+        .boxed()
+    }
+
+    async fn blob_exists_unless_stopped(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        cancellation: &Cancellation,
+    ) -> Result<bool, Error> {
+        cancellation
+            .run(self.blob_exists(image, digest))
+            .await
+            .map_err(Reason::error)?
+    }
+
+    /// Starts an upload session in the repository of `image` and returns its
+    /// location.
+    async fn start_upload(&self, image: &Image, cancellation: &Cancellation) -> Result<Url, Error> {
+        let mut segments = vec!["v2".to_string()];
+        segments.extend(image.repository_segments().map_err(Error::InvalidPath)?);
+        segments.extend(["blobs", "uploads", ""].map(String::from));
+
+        let url = self
+            .inner
+            .mirrors
+            .upstream(&image.registry)
+            .map(|base| append_segments(base, &segments))
+            .map_err(Error::InvalidUploadUrl)?;
+
+        let response = cancellation
+            .run(self.push_request(Request::new(Method::POST, url), image, Error::UploadBlob))
+            .await
+            .map_err(Reason::error)??;
+
+        upload_location(response, StatusCode::ACCEPTED).await
+    }
+
+    /// Sends `chunk` starting at byte `start` of the blob and returns the
+    /// location of the next request.
+    async fn upload_chunk(
+        &self,
+        image: &Image,
+        location: Url,
+        start: u64,
+        chunk: Bytes,
+        cancellation: &Cancellation,
+    ) -> Result<Url, Error> {
+        let mut headers = octet_stream();
+        headers.insert(
+            CONTENT_RANGE,
+            HeaderValue::from_str(&format!("{start}-{}", start + chunk.len() as u64 - 1))
+                .map_err(Error::InvalidPushHeader)?,
+        );
+
+        let mut request = Request::new(Method::PATCH, location.clone()).headers(headers);
+        request.body = Some(chunk);
+
+        let response = self
+            .upload_step(request, image, &location, cancellation)
+            .await?;
+
+        upload_location(response, StatusCode::ACCEPTED).await
+    }
+
+    /// Completes the upload at `location` with the last part of the blob in
+    /// `body`.
+    async fn finish_upload(
+        &self,
+        image: &Image,
+        location: &Url,
+        digest: &Digest,
+        body: Bytes,
+        cancellation: &Cancellation,
+    ) -> Result<(), Error> {
+        let mut finish = location.clone();
+        finish
+            .query_pairs_mut()
+            .append_pair("digest", &digest.to_string());
+
+        let mut request = Request::new(Method::PUT, finish).headers(octet_stream());
+        request.body = Some(body);
+
+        let response = self
+            .upload_step(request, image, location, cancellation)
+            .await?;
+
+        if response.status != StatusCode::CREATED {
+            return Err(failed(response, Error::FailedBlobUpload).await);
+        }
+
+        Ok(())
+    }
+
+    /// Sends a request of a started upload. If `cancellation` fires before
+    /// or while it is sent, the upload session at `location` is deleted so
+    /// the registry does not keep the partial upload around.
+    async fn upload_step(
+        &self,
+        request: Request,
+        image: &Image,
+        location: &Url,
+        cancellation: &Cancellation,
+    ) -> Result<transport::Response, Error> {
+        match cancellation
+            .run(self.push_request(request, image, Error::UploadBlob))
+            .await
+        {
+            Ok(response) => response,
+
+            Err(reason) => {
+                let delete = Request::new(Method::DELETE, location.clone());
+
+                match self.push_request(delete, image, Error::UploadBlob).await {
+                    Ok(response) if response.status.is_success() => {}
+                    Ok(response) => {
+                        warn!(status = %response.status, "failed to delete cancelled upload");
+                    }
+                    Err(e) => warn!(error = %e, "failed to delete cancelled upload"),
+                }
+
+                Err(reason.error())
+            }
+        }
+    }
+
+    /// The headers with a token that grants pushing to the repository of
+    /// `image`, for requests to its original registry.
+    async fn push_headers(&self, image: &Image) -> Result<HeaderMap, Error> {
+        self.check_registry_policy(&image.registry)?;
+
+        if self.inner.offline {
+            return Err(Error::Offline);
+        }
+
+        self.get_headers(image, &[Action::Pull, Action::Push]).await
+    }
+
+    /// Sends a request to the original registry of `image` with a token that
+    /// grants pushing to its repository. Pushes never go to mirrors.
+    async fn push_request(
+        &self,
+        mut request: Request,
+        image: &Image,
+        map_err: fn(transport::Error) -> Error,
+    ) -> Result<transport::Response, Error> {
+        request.headers.extend(self.push_headers(image).await?);
+
+        let response = self.execute(request).await.map_err(map_err)?;
+
+        warning::collect(&response.headers);
+
+        Ok(response)
+    }
+}
+
+/// Picks the manifest of `index.json` that `destination` refers to.
+fn select_manifest<'a>(
+    manifests: &'a [Descriptor],
+    destination: &Image,
+) -> Result<&'a Descriptor, Error> {
+    let found = match &destination.image_name.identifier {
+        Either::Left(tag) => manifests.iter().find(|descriptor| {
+            descriptor
+                .annotations
+                .get(REF_NAME_ANNOTATION)
+                .is_some_and(|name| *name == tag.to_string())
+        }),
+
+        Either::Right(digest) => manifests
+            .iter()
+            .find(|descriptor| descriptor.digest.is_equivalent(digest)),
+    };
+
+    match (found, manifests) {
+        (Some(descriptor), _) | (None, [descriptor]) => Ok(descriptor),
+        (None, _) => Err(Error::LayoutManifestNotFound(destination.reference())),
+    }
+}
+
+/// Reads the blob of `descriptor` from the layout and checks it against the
+/// size and digest of the descriptor.
+async fn read_blob(dir: &Path, descriptor: &Descriptor) -> Result<Bytes, Error> {
+    let path = blob_path(dir, &descriptor.digest)?;
+
+    let content = tokio::fs::read(&path)
+        .await
+        .map_err(|e| Error::ReadLayout(path, e))?;
+
+    let actual_digest = descriptor
+        .digest
+        .algorithm()
+        .map_err(Error::UnsupportedDigestAlgorithm)?
+        .digest(&content);
+    let actual_size = content.len() as u64;
+
+    if actual_size != descriptor.size || !actual_digest.is_equivalent(&descriptor.digest) {
+        return Err(Error::DescriptorMismatch {
+            expected_size: descriptor.size,
+            actual_size,
+            expected_digest: descriptor.digest.clone(),
+            actual_digest,
+        });
+    }
+
+    Ok(content.into())
+}
+
+/// Returns the path of a blob in the layout, `blobs/<algorithm>/<hex>`. Only
+/// sha256 and sha512 digests are supported as they are verified before
+/// uploading.
+fn blob_path(dir: &Path, digest: &Digest) -> Result<PathBuf, Error> {
+    let digest_string = digest.normalized().to_string();
+
+    let hex_len = match digest.algorithm() {
+        Ok(Algorithm::Sha256) => 64,
+        Ok(Algorithm::Sha512) => 128,
+        Err(_) => return Err(Error::UnsupportedLayoutDigest(digest.clone())),
+    };
+
+    match digest_string.split_once(':') {
+        Some((algorithm, hex))
+            if hex.len() == hex_len && hex.bytes().all(|byte| byte.is_ascii_hexdigit()) =>
+        {
+            Ok(dir.join("blobs").join(algorithm).join(hex))
+        }
+
+        _ => Err(Error::UnsupportedLayoutDigest(digest.clone())),
+    }
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let content = tokio::fs::read(path)
+        .await
+        .map_err(|e| Error::ReadLayout(path.to_path_buf(), e))?;
+
+    serde_json::from_slice(&content).map_err(|e| Error::DeserializeLayout(path.to_path_buf(), e))
+}
+
+fn deserialize<T: DeserializeOwned>(dir: &Path, digest: &Digest, body: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(body)
+        .map_err(|e| Error::DeserializeLayout(blob_path(dir, digest).unwrap_or_default(), e))
+}
+
+/// Returns the subject of a referrer manifest together with the descriptor
+/// its referrers index entry gets, still without the digest. The artifact
+/// type falls back to the media type of the config like the referrers API
+/// does.
+fn referrer_descriptor(media_type: &str, body: &[u8]) -> Option<(Digest, serde_json::Value)> {
+    let manifest: serde_json::Value = serde_json::from_slice(body).ok()?;
+
+    let subject = manifest
+        .get("subject")?
+        .get("digest")?
+        .as_str()?
+        .parse()
+        .ok()?;
+
+    let mut descriptor = serde_json::json!({
+        "mediaType": media_type,
+        "size": body.len(),
+    });
+
+    let artifact_type = manifest
+        .get("artifactType")
+        .or_else(|| manifest.get("config")?.get("mediaType"));
+
+    if let Some(artifact_type) = artifact_type {
+        descriptor["artifactType"] = artifact_type.clone();
+    }
+
+    if let Some(annotations) = manifest.get("annotations") {
+        descriptor["annotations"] = annotations.clone();
+    }
+
+    Some((subject, descriptor))
+}
+
+/// The tag of the fallback referrers index of `subject`, `<algorithm>-<hex>`.
+fn referrers_tag(subject: &Digest) -> Tag {
+    Tag::Specific(subject.to_string().replacen(':', "-", 1).into())
+}
+
+/// Reads up to `size` bytes, less only at the end of `reader`.
+async fn read_chunk(reader: &mut (impl AsyncRead + Unpin), size: usize) -> Result<Bytes, Error> {
+    let mut chunk = Vec::new();
+
+    reader
+        .take(size as u64)
+        .read_to_end(&mut chunk)
+        .await
+        .map_err(Error::ReadUpload)?;
+
+    Ok(chunk.into())
+}
+
+fn octet_stream() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+
+    headers
+}
+
+/// Returns the URL the next request of an upload goes to. Registries may
+/// answer with a location relative to the request.
+async fn upload_location(
+    response: transport::Response,
+    expected: StatusCode,
+) -> Result<Url, Error> {
+    if response.status != expected {
+        return Err(failed(response, Error::FailedBlobUpload).await);
+    }
+
+    let location = response
+        .headers
+        .get(LOCATION)
+        .ok_or(Error::MissingUploadLocation)?
+        .to_str()
+        .map_err(|_| Error::MissingUploadLocation)?;
+
+    response.url.join(location).map_err(Error::InvalidUploadUrl)
+}
+
+async fn failed(response: transport::Response, error: fn(Box<FailedResponse>) -> Error) -> Error {
+    error(FailedResponse::read(response, DEFAULT_MAX_MANIFEST_SIZE).await)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::{
+        path::Path,
+        sync::{
+            atomic::{
+                AtomicU64,
+                Ordering,
+            },
+            Arc,
+        },
+    };
+
+    use async_compression::tokio::bufread::GzipEncoder;
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        docker::{
+            cancellation::{
+                Cancellation,
+                Cancelled,
+                Reason,
+            },
+            interceptor::{
+                RequestInterceptor,
+                RequestParts,
+            },
+            progress::Progress,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        Client,
+        ClientError,
+        Digest,
+    };
+
+    const BASE: &str = "https://registry.k8s.io/v2/app";
+    const CONFIG: &str =
+        r#"{"architecture":"amd64","os":"linux","rootfs":{"type":"layers","diff_ids":[]}}"#;
+    const LAYER: &[u8] = b"not really a tarball";
+
+    /// The digests of a layout written by [`write_layout`].
+    struct Layout {
+        config: Digest,
+        layer: Digest,
+        manifest: Digest,
+        index: Digest,
+    }
+
+    fn write_blob(dir: &Path, content: &[u8]) -> Digest {
+        let digest = Digest::sha256(content);
+        let hex = digest.to_string().replace("sha256:", "");
+
+        std::fs::create_dir_all(dir.join("blobs/sha256")).unwrap();
+        std::fs::write(dir.join("blobs/sha256").join(hex), content).unwrap();
+
+        digest
+    }
+
+    /// Writes a layout with an image index tagged `1.0` that contains a
+    /// single image with one layer.
+    fn write_layout(dir: &Path) -> Layout {
+        let config = write_blob(dir, CONFIG.as_bytes());
+        let layer = write_blob(dir, LAYER);
+
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": CONFIG.len(),
+                "digest": config.to_string()
+            },
+            "layers": [{
+                "mediaType": "application/vnd.oci.image.layer.v1.tar",
+                "size": LAYER.len(),
+                "digest": layer.to_string()
+            }]
+        })
+        .to_string();
+        let manifest_digest = write_blob(dir, manifest.as_bytes());
+
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": manifest.len(),
+                "digest": manifest_digest.to_string(),
+                "platform": { "architecture": "amd64", "os": "linux" }
+            }]
+        })
+        .to_string();
+        let index_digest = write_blob(dir, index.as_bytes());
+
+        let layout_index = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": "application/vnd.oci.image.index.v1+json",
+                "size": index.len(),
+                "digest": index_digest.to_string(),
+                "annotations": { "org.opencontainers.image.ref.name": "1.0" }
+            }]
+        });
+        std::fs::write(dir.join("index.json"), layout_index.to_string()).unwrap();
+        std::fs::write(dir.join("oci-layout"), r#"{"imageLayoutVersion":"1.0.0"}"#).unwrap();
+
+        Layout {
+            config,
+            layer,
+            manifest: manifest_digest,
+            index: index_digest,
+        }
+    }
+
+    /// A registry that has the config but not the layer.
+    fn transport(layout: &Layout) -> MockTransport {
+        let upload = format!("{BASE}/blobs/uploads/session");
+
+        MockTransport::new()
+            .with_response(
+                Method::HEAD,
+                &format!("{BASE}/blobs/{}", layout.config),
+                MockResponse::new(StatusCode::OK),
+            )
+            .with_response(
+                Method::HEAD,
+                &format!("{BASE}/blobs/{}", layout.layer),
+                MockResponse::new(StatusCode::NOT_FOUND),
+            )
+            .with_response(
+                Method::POST,
+                &format!("{BASE}/blobs/uploads/"),
+                MockResponse::new(StatusCode::ACCEPTED)
+                    .header("Location", "/v2/app/blobs/uploads/session"),
+            )
+            .with_response(
+                Method::PATCH,
+                &upload,
+                MockResponse::new(StatusCode::ACCEPTED).header("Location", &upload),
+            )
+            .with_response(
+                Method::PUT,
+                &finish_url(&layout.layer),
+                MockResponse::new(StatusCode::CREATED),
+            )
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{}", layout.manifest),
+                MockResponse::new(StatusCode::CREATED),
+            )
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(StatusCode::CREATED),
+            )
+    }
+
+    /// The digest in the query is form encoded.
+    fn finish_url(digest: &Digest) -> String {
+        format!(
+            "{BASE}/blobs/uploads/session?digest={}",
+            digest.to_string().replace(':', "%3A")
+        )
+    }
+
+    fn requests(transport: &MockTransport) -> Vec<String> {
+        transport
+            .requests()
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.url))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn pushes_missing_blobs_and_nested_manifests_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = write_layout(dir.path());
+        let transport = transport(&layout);
+        let client = Client::builder().transport(transport.clone()).build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .push_oci_layout(dir.path(), &destination)
+            .await
+            .unwrap();
+
+        assert_eq!(got, layout.index);
+        assert_eq!(
+            requests(&transport),
+            vec![
+                format!("HEAD {BASE}/blobs/{}", layout.config),
+                format!("HEAD {BASE}/blobs/{}", layout.layer),
+                format!("POST {BASE}/blobs/uploads/"),
+                format!("PUT {}", finish_url(&layout.layer)),
+                format!("PUT {BASE}/manifests/{}", layout.manifest),
+                format!("PUT {BASE}/manifests/1.0"),
+            ]
+        );
+
+        let requests = transport.requests();
+        assert_eq!(requests[3].body.as_deref(), Some(LAYER));
+        assert_eq!(
+            requests[5].headers.get("Content-Type").unwrap(),
+            "application/vnd.oci.image.index.v1+json"
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_large_blobs_in_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = write_layout(dir.path());
+        let transport = transport(&layout);
+        let client = Client::builder()
+            .transport(transport.clone())
+            .upload_chunk_size(8)
+            .build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        client
+            .push_oci_layout(dir.path(), &destination)
+            .await
+            .unwrap();
+
+        let chunks: Vec<(String, Vec<u8>)> = transport
+            .requests()
+            .into_iter()
+            .filter(|request| request.method == Method::PATCH)
+            .map(|request| {
+                (
+                    request.headers["Content-Range"]
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                    request.body.unwrap().to_vec(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            chunks,
+            vec![
+                ("0-7".to_string(), LAYER[..8].to_vec()),
+                ("8-15".to_string(), LAYER[8..16].to_vec()),
+                ("16-19".to_string(), LAYER[16..].to_vec()),
+            ]
+        );
+
+        let finish = transport
+            .requests()
+            .into_iter()
+            .find(|request| request.method == Method::PUT)
+            .unwrap();
+        assert_eq!(finish.body.as_deref(), Some(&b""[..]));
+    }
+
+    #[tokio::test]
+    async fn upload_blob_unhashed() {
+        #[derive(Debug, Default)]
+        struct Counting {
+            bytes: AtomicU64,
+            finishes: AtomicU64,
+        }
+
+        impl Progress for Counting {
+            fn on_chunk(&self, bytes: u64) {
+                self.bytes.fetch_add(bytes, Ordering::SeqCst);
+            }
+
+            fn on_finish(&self) {
+                self.finishes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let upload = format!("{BASE}/blobs/uploads/session");
+        let expected = Digest::sha256(LAYER);
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::POST,
+                &format!("{BASE}/blobs/uploads/"),
+                MockResponse::new(StatusCode::ACCEPTED).header("Location", &upload),
+            )
+            .with_response(
+                Method::PATCH,
+                &upload,
+                MockResponse::new(StatusCode::ACCEPTED).header("Location", &upload),
+            )
+            .with_response(
+                Method::PUT,
+                &finish_url(&expected),
+                MockResponse::new(StatusCode::CREATED),
+            );
+
+        let client = Client::builder()
+            .transport(transport.clone())
+            .upload_chunk_size(8)
+            .build();
+        let image = "registry.k8s.io/app:1.0".parse().unwrap();
+        let progress = Arc::new(Counting::default());
+
+        let got = client
+            .upload_blob_unhashed_with_progress(&image, LAYER, progress.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(got, (expected.clone(), LAYER.len() as u64));
+
+        let requests = transport.requests();
+        let streamed: Vec<u8> = requests
+            .iter()
+            .filter(|request| request.method == Method::PATCH)
+            .flat_map(|request| request.body.clone().unwrap())
+            .collect();
+        assert_eq!(Digest::sha256(&streamed), expected);
+        assert_eq!(requests.last().unwrap().url.as_str(), finish_url(&expected));
+
+        assert_eq!(progress.bytes.load(Ordering::SeqCst), LAYER.len() as u64);
+        assert_eq!(progress.finishes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn corrupt_blob_is_not_uploaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = write_layout(dir.path());
+        let hex = layout.layer.to_string().replace("sha256:", "");
+        std::fs::write(dir.path().join("blobs/sha256").join(hex), b"tampered").unwrap();
+
+        let transport = transport(&layout);
+        let client = Client::builder().transport(transport.clone()).build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .push_oci_layout(dir.path(), &destination)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::DescriptorMismatch { .. }));
+        assert!(transport
+            .requests()
+            .iter()
+            .all(|request| request.method == Method::HEAD));
+    }
+
+    #[tokio::test]
+    async fn unknown_tag_in_layout_with_several_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        write_layout(dir.path());
+        std::fs::write(
+            dir.path().join("index.json"),
+            serde_json::json!({
+                "schemaVersion": 2,
+                "manifests": [
+                    { "mediaType": "application/vnd.oci.image.manifest.v1+json", "size": 1, "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111" },
+                    { "mediaType": "application/vnd.oci.image.manifest.v1+json", "size": 1, "digest": "sha256:2222222222222222222222222222222222222222222222222222222222222222" }
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let client = Client::builder().transport(MockTransport::new()).build();
+        let destination = "registry.k8s.io/app:2.0".parse().unwrap();
+
+        let got = client
+            .push_oci_layout(dir.path(), &destination)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(got, ClientError::LayoutManifestNotFound(reference) if reference == "2.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn archive_layers_are_compressed() {
+        use async_compression::tokio::bufread::GzipDecoder;
+        use tokio::io::AsyncReadExt;
+
+        let archive = crate::DockerArchive::open(
+            &include_bytes!("../../resources/archive/busybox-legacy.tar")[..],
+        )
+        .unwrap();
+        let image = &archive.images()[0];
+
+        let mut compressed = Vec::new();
+        GzipEncoder::new(image.layers[0].tar().as_ref())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        let layer = Digest::sha256(&compressed);
+        let config = image.config_digest();
+
+        let transport = [&layer, &config].into_iter().fold(
+            MockTransport::new()
+                .with_response(
+                    Method::POST,
+                    &format!("{BASE}/blobs/uploads/"),
+                    MockResponse::new(StatusCode::ACCEPTED)
+                        .header("Location", "/v2/app/blobs/uploads/session"),
+                )
+                .with_response(
+                    Method::PUT,
+                    &format!("{BASE}/manifests/1.0"),
+                    MockResponse::new(StatusCode::CREATED),
+                ),
+            |transport, digest| {
+                transport
+                    .with_response(
+                        Method::HEAD,
+                        &format!("{BASE}/blobs/{digest}"),
+                        MockResponse::new(StatusCode::NOT_FOUND),
+                    )
+                    .with_response(
+                        Method::PUT,
+                        &finish_url(digest),
+                        MockResponse::new(StatusCode::CREATED),
+                    )
+            },
+        );
+        let client = Client::builder().transport(transport.clone()).build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client.push_archive(&archive, &destination).await.unwrap();
+
+        let requests = transport.requests();
+        let pushed_layer = requests
+            .iter()
+            .find(|request| request.url.as_str() == finish_url(&layer))
+            .unwrap();
+        let mut decompressed = Vec::new();
+        GzipDecoder::new(pushed_layer.body.as_deref().unwrap())
+            .read_to_end(&mut decompressed)
+            .await
+            .unwrap();
+        assert_eq!(Digest::sha256(&decompressed), image.layers[0].diff_id);
+
+        let manifest = requests.last().unwrap();
+        let body = manifest.body.as_deref().unwrap();
+        assert_eq!(got, Digest::sha256(body));
+        assert_eq!(
+            manifest.headers.get("Content-Type").unwrap(),
+            "application/vnd.docker.distribution.manifest.v2+json"
+        );
+
+        let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(body["config"]["digest"], config.to_string());
+        assert_eq!(body["layers"][0]["digest"], layer.to_string());
+        assert_eq!(body["layers"][0]["size"], compressed.len());
+    }
+
+    /// Cancels `token` once a request with `method` to a URL ending in
+    /// `path` is sent.
+    #[derive(Debug)]
+    struct CancelOn {
+        method: Method,
+        path: String,
+        token: CancellationToken,
+    }
+
+    #[async_trait::async_trait]
+    impl RequestInterceptor for CancelOn {
+        async fn before(&self, request: &mut RequestParts) {
+            if request.method == self.method && request.url.path().ends_with(&self.path) {
+                self.token.cancel();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_upload_is_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = write_layout(dir.path());
+        let upload = format!("{BASE}/blobs/uploads/session");
+        let transport = transport(&layout).with_response(
+            Method::DELETE,
+            &upload,
+            MockResponse::new(StatusCode::NO_CONTENT),
+        );
+        let token = CancellationToken::new();
+        let client = Client::builder()
+            .transport(transport.clone())
+            .interceptor(CancelOn {
+                method: Method::PATCH,
+                path: "/session".to_string(),
+                token: token.clone(),
+            })
+            .upload_chunk_size(8)
+            .build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .push_oci_layout_with_cancellation(
+                dir.path(),
+                &destination,
+                &Cancellation::new().token(token),
+            )
+            .await
+            .unwrap_err();
+
+        let ClientError::Cancelled(cancelled) = got else {
+            panic!("expected a cancelled push, got {got}");
+        };
+        assert_eq!(cancelled.reason, Reason::Cancelled);
+        assert_eq!(cancelled.completed, Vec::new());
+
+        // Only the first of three chunks is sent before the upload is deleted.
+        assert_eq!(
+            requests(&transport),
+            vec![
+                format!("HEAD {BASE}/blobs/{}", layout.config),
+                format!("HEAD {BASE}/blobs/{}", layout.layer),
+                format!("POST {BASE}/blobs/uploads/"),
+                format!("PATCH {upload}"),
+                format!("DELETE {upload}"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_push_reports_completed_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = write_layout(dir.path());
+        let transport = transport(&layout);
+        let token = CancellationToken::new();
+        let client = Client::builder()
+            .transport(transport.clone())
+            .interceptor(CancelOn {
+                method: Method::PUT,
+                path: format!("/manifests/{}", layout.manifest),
+                token: token.clone(),
+            })
+            .build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+
+        let got = client
+            .push_oci_layout_with_cancellation(
+                dir.path(),
+                &destination,
+                &Cancellation::new().token(token),
+            )
+            .await
+            .unwrap_err();
+
+        let ClientError::Cancelled(cancelled) = got else {
+            panic!("expected a cancelled push, got {got}");
+        };
+        assert_eq!(
+            cancelled.completed,
+            vec![layout.layer, layout.manifest.clone()]
+        );
+        assert_eq!(
+            transport.requests().last().unwrap().url.path(),
+            format!("/v2/app/manifests/{}", layout.manifest)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_deadline_sends_nothing() {
+        let client = Client::builder().transport(MockTransport::new()).build();
+        let destination = "registry.k8s.io/app:1.0".parse().unwrap();
+        let content = bytes::Bytes::from_static(LAYER);
+
+        let got = client
+            .push_blob_with_cancellation(
+                &destination,
+                &Digest::sha256(LAYER),
+                content,
+                &Cancellation::new().timeout(std::time::Duration::ZERO),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            got,
+            ClientError::Cancelled(Cancelled {
+                reason: Reason::DeadlineExceeded,
+                ..
+            })
+        ));
+    }
+
+    const SUBJECT: &str = "sha256:7031c1b283388d2c2e09b57badb803c05ebed362dc88d84b480cc47f72a21097";
+    const REFERRERS_TAG: &str =
+        "sha256-7031c1b283388d2c2e09b57badb803c05ebed362dc88d84b480cc47f72a21097";
+    const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+    fn signature() -> bytes::Bytes {
+        serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "artifactType": "application/vnd.dev.cosign.artifact.sig.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "size": 2,
+                "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+            },
+            "layers": [],
+            "subject": {
+                "mediaType": OCI_MANIFEST,
+                "size": 1234,
+                "digest": SUBJECT
+            },
+            "annotations": { "created": "2024-09-01T12:00:00Z" }
+        })
+        .to_string()
+        .into()
+    }
+
+    #[tokio::test]
+    async fn referrer_handled_by_registry() {
+        let body = signature();
+        let digest = Digest::sha256(&body);
+
+        let transport = MockTransport::new().with_response(
+            Method::PUT,
+            &format!("{BASE}/manifests/{digest}"),
+            MockResponse::new(StatusCode::CREATED).header("OCI-Subject", SUBJECT),
+        );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = format!("registry.k8s.io/app@{digest}").parse().unwrap();
+
+        let got = client
+            .push_manifest(&image, OCI_MANIFEST, body)
+            .await
+            .unwrap();
+
+        assert_eq!(got, digest);
+        assert_eq!(
+            requests(&transport),
+            vec![format!("PUT {BASE}/manifests/{digest}")]
+        );
+    }
+
+    #[tokio::test]
+    async fn token_with_push_scope() {
+        const TOKEN: &str =
+            "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull,push&service=ghcr.io";
+
+        let body = signature();
+        let digest = Digest::sha256(&body);
+        let manifest = format!("https://ghcr.io/v2/sigstore/cosign/cosign/manifests/{digest}");
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"push-token"}"#),
+            )
+            .with_response(
+                Method::PUT,
+                &manifest,
+                MockResponse::new(StatusCode::CREATED).header("OCI-Subject", SUBJECT),
+            );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = format!("ghcr.io/sigstore/cosign/cosign@{digest}")
+            .parse()
+            .unwrap();
+
+        client
+            .push_manifest(&image, OCI_MANIFEST, body)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            requests(&transport),
+            vec![format!("GET {TOKEN}"), format!("PUT {manifest}")]
+        );
+        assert_eq!(
+            "Bearer push-token",
+            transport.requests()[1]
+                .headers
+                .get("Authorization")
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn referrer_creates_fallback_index() {
+        let body = signature();
+        let digest = Digest::sha256(&body);
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{digest}"),
+                MockResponse::new(StatusCode::CREATED),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{REFERRERS_TAG}"),
+                MockResponse::new(StatusCode::NOT_FOUND),
+            )
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{REFERRERS_TAG}"),
+                MockResponse::new(StatusCode::CREATED),
+            );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = format!("registry.k8s.io/app@{digest}").parse().unwrap();
+
+        client
+            .push_manifest(&image, OCI_MANIFEST, body.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            requests(&transport),
+            vec![
+                format!("PUT {BASE}/manifests/{digest}"),
+                format!("GET {BASE}/manifests/{REFERRERS_TAG}"),
+                format!("PUT {BASE}/manifests/{REFERRERS_TAG}"),
+            ]
+        );
+
+        let index = transport.requests().pop().unwrap();
+        assert_eq!(
+            index.headers["Content-Type"],
+            "application/vnd.oci.image.index.v1+json"
+        );
+        assert!(index.headers.get("If-Match").is_none());
+
+        let index: serde_json::Value = serde_json::from_slice(&index.body.unwrap()).unwrap();
+        assert_eq!(
+            index,
+            serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.index.v1+json",
+                "manifests": [{
+                    "mediaType": OCI_MANIFEST,
+                    "digest": digest,
+                    "size": body.len(),
+                    "artifactType": "application/vnd.dev.cosign.artifact.sig.v1+json",
+                    "annotations": { "created": "2024-09-01T12:00:00Z" }
+                }]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn referrer_appends_to_fallback_index() {
+        let body = signature();
+        let digest = Digest::sha256(&body);
+        let existing = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [{
+                "mediaType": OCI_MANIFEST,
+                "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+                "size": 10,
+                "artifactType": "application/spdx+json"
+            }]
+        });
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{digest}"),
+                MockResponse::new(StatusCode::CREATED),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{REFERRERS_TAG}"),
+                MockResponse::new(StatusCode::OK)
+                    .header("ETag", "\"index-v1\"")
+                    .body(existing.to_string()),
+            )
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{REFERRERS_TAG}"),
+                MockResponse::new(StatusCode::CREATED),
+            );
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = format!("registry.k8s.io/app@{digest}").parse().unwrap();
+
+        client
+            .push_manifest(&image, OCI_MANIFEST, body)
+            .await
+            .unwrap();
+
+        let index = transport.requests().pop().unwrap();
+        assert_eq!(index.headers["If-Match"], "\"index-v1\"");
+
+        let index: serde_json::Value = serde_json::from_slice(&index.body.unwrap()).unwrap();
+        let digests: Vec<&str> = index["manifests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|manifest| manifest["digest"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            digests,
+            [
+                "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+                digest.to_string().as_str(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn referrer_index_conflict() {
+        let body = signature();
+        let digest = Digest::sha256(&body);
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{digest}"),
+                MockResponse::new(StatusCode::CREATED),
+            )
+            .with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{REFERRERS_TAG}"),
+                MockResponse::new(StatusCode::OK)
+                    .header("ETag", "\"index-v1\"")
+                    .body(r#"{"schemaVersion":2,"manifests":[]}"#),
+            )
+            .with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/{REFERRERS_TAG}"),
+                MockResponse::new(StatusCode::PRECONDITION_FAILED),
+            );
+        let client = Client::builder().transport(transport).build();
+        let image = format!("registry.k8s.io/app@{digest}").parse().unwrap();
+
+        let got = client
+            .push_manifest(&image, OCI_MANIFEST, body)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(got, ClientError::FailedManifestPush(response) if response.status == StatusCode::PRECONDITION_FAILED)
+        );
+    }
+
+    #[tokio::test]
+    async fn immutable_tag() {
+        for (status, body) in [
+            (
+                StatusCode::BAD_REQUEST,
+                include_str!("../../resources/registry/ecr/error-tag-immutable.json"),
+            ),
+            (
+                StatusCode::PRECONDITION_FAILED,
+                include_str!("../../resources/registry/harbor/error-tag-immutable.json"),
+            ),
+        ] {
+            let transport = MockTransport::new().with_response(
+                Method::PUT,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(status).body(body),
+            );
+            let client = Client::builder().transport(transport).build();
+            let image = "registry.k8s.io/app:1.0".parse().unwrap();
+
+            let got = client
+                .push_manifest(&image, OCI_MANIFEST, signature())
+                .await
+                .unwrap_err();
+
+            assert!(Client::is_tag_immutable_error(&got), "{got}");
+            assert!(matches!(got, ClientError::TagImmutable { tag, .. } if tag == "1.0"));
+        }
+    }
+
+    #[tokio::test]
+    async fn immutable_body_for_digest() {
+        let body = signature();
+        let digest = Digest::sha256(&body);
+
+        let transport = MockTransport::new().with_response(
+            Method::PUT,
+            &format!("{BASE}/manifests/{digest}"),
+            MockResponse::new(StatusCode::BAD_REQUEST).body(include_str!(
+                "../../resources/registry/ecr/error-tag-immutable.json"
+            )),
+        );
+        let client = Client::builder().transport(transport).build();
+        let image = format!("registry.k8s.io/app@{digest}").parse().unwrap();
+
+        let got = client
+            .push_manifest(&image, OCI_MANIFEST, body)
+            .await
+            .unwrap_err();
+
+        assert!(!Client::is_tag_immutable_error(&got));
+        assert!(matches!(got, ClientError::FailedManifestPush(_)));
+    }
+
+    #[tokio::test]
+    async fn tag_exists() {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::HEAD,
+                &format!("{BASE}/manifests/1.0"),
+                MockResponse::new(StatusCode::OK),
+            )
+            .with_response(
+                Method::HEAD,
+                &format!("{BASE}/manifests/2.0"),
+                MockResponse::new(StatusCode::NOT_FOUND),
+            )
+            .with_response(
+                Method::HEAD,
+                &format!("{BASE}/manifests/3.0"),
+                MockResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            );
+        let client = Client::builder().transport(transport).build();
+
+        let exists = client
+            .tag_exists(&"registry.k8s.io/app:1.0".parse().unwrap())
+            .await
+            .unwrap();
+        assert!(exists);
+
+        let missing = client
+            .tag_exists(&"registry.k8s.io/app:2.0".parse().unwrap())
+            .await
+            .unwrap();
+        assert!(!missing);
+
+        let failed = client
+            .tag_exists(&"registry.k8s.io/app:3.0".parse().unwrap())
+            .await;
+        assert!(matches!(failed, Err(ClientError::FailedManifestRequest(_))));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running docker daemon"]
+    async fn registry_round_trip() {
+        use testcontainers_modules::testcontainers::{
+            core::{
+                IntoContainerPort,
+                WaitFor,
+            },
+            runners::AsyncRunner,
+            GenericImage,
+        };
+
+        let container = GenericImage::new("registry", "2")
+            .with_exposed_port(5000.tcp())
+            .with_wait_for(WaitFor::message_on_stderr("listening on"))
+            .start()
+            .await
+            .unwrap();
+        let port = container.get_host_port_ipv4(5000).await.unwrap();
+        let host = format!("localhost:{port}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let layout = write_layout(dir.path());
+        let client = Client::builder()
+            .registry_api_base(&host, format!("http://{host}").parse().unwrap())
+            .build();
+        let destination = format!("{host}/app:1.0").parse().unwrap();
+
+        let pushed = client
+            .push_oci_layout(dir.path(), &destination)
+            .await
+            .unwrap();
+        let fetched = client.get_manifest_raw(&destination).await.unwrap();
+
+        assert_eq!(pushed, layout.index);
+        assert_eq!(Digest::sha256(&fetched.body), layout.index);
+
+        // Pushing again only checks for the blobs.
+        client
+            .push_oci_layout(dir.path(), &destination)
+            .await
+            .unwrap();
+    }
+}
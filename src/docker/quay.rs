@@ -0,0 +1,170 @@
+//! Places where Quay deviates from the distribution specification.
+
+use http::StatusCode;
+use url::Url;
+
+use crate::{
+    docker::error::ErrorCode,
+    Registry,
+};
+
+/// Quay answers requests for repositories that do not exist, including
+/// anonymous token requests for them, with `400 Bad Request` instead of
+/// `401` or `404`.
+pub(super) fn is_missing_repository(registry: &Registry, status: StatusCode) -> bool {
+    *registry == Registry::Quay && status == StatusCode::BAD_REQUEST
+}
+
+/// Returns the URL of the next page announced by a `next_page` field in the
+/// body of a tag list, which Quay sends instead of a `Link` header for some
+/// endpoints. The field is either a URL, resolved against the current page,
+/// or an opaque page token that is sent back as the `next_page` parameter.
+pub(super) fn next_page(next_page: &str, base: &Url) -> Option<Url> {
+    let next_page = next_page.trim();
+
+    if next_page.is_empty() {
+        return None;
+    }
+
+    if next_page.contains(['/', '?']) {
+        return base.join(next_page).ok();
+    }
+
+    let mut url = base.clone();
+    let query: Vec<(String, String)> = base
+        .query_pairs()
+        .filter(|(name, _)| name != "next_page")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("next_page", next_page);
+
+    Some(url)
+}
+
+/// The error body of Quay's token endpoint and API, for example
+/// `{"error": "Invalid bearer token format"}` or
+/// `{"error_type": "invalid_token", "error_message": "..."}`.
+#[derive(Debug, serde::Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    error: Option<String>,
+
+    #[serde(default)]
+    error_type: Option<String>,
+}
+
+/// Maps an error body in Quay's format onto the error codes of the
+/// distribution specification. The type of the error is used where Quay
+/// sends one, otherwise the status decides.
+pub(super) fn error_code(status: StatusCode, body: &str) -> Option<ErrorCode> {
+    let body: ErrorBody = serde_json::from_str(body).ok()?;
+
+    match body.error_type.as_deref() {
+        Some("invalid_token" | "expired_token") => return Some(ErrorCode::Unauthorized),
+        Some("insufficient_scope" | "forbidden") => return Some(ErrorCode::Denied),
+        Some("not_found") => return Some(ErrorCode::NameUnknown),
+        _ => {}
+    }
+
+    if body.error.is_none() && body.error_type.is_none() {
+        return None;
+    }
+
+    match status {
+        StatusCode::UNAUTHORIZED => Some(ErrorCode::Unauthorized),
+        StatusCode::FORBIDDEN => Some(ErrorCode::Denied),
+        StatusCode::NOT_FOUND => Some(ErrorCode::NameUnknown),
+        StatusCode::TOO_MANY_REQUESTS => Some(ErrorCode::TooManyRequests),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn base() -> Url {
+        "https://quay.io/v2/prometheus/prometheus/tags/list?n=2"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn next_page_token() {
+        assert_eq!(
+            next_page("Z2FtbWE", &base()).unwrap().as_str(),
+            "https://quay.io/v2/prometheus/prometheus/tags/list?n=2&next_page=Z2FtbWE"
+        );
+
+        let second: Url = "https://quay.io/v2/prometheus/prometheus/tags/list?n=2&next_page=a"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            next_page("b", &second).unwrap().as_str(),
+            "https://quay.io/v2/prometheus/prometheus/tags/list?n=2&next_page=b"
+        );
+    }
+
+    #[test]
+    fn next_page_url() {
+        assert_eq!(
+            next_page(
+                "/v2/prometheus/prometheus/tags/list?n=2&last=v2.1.0",
+                &base()
+            )
+            .unwrap()
+            .as_str(),
+            "https://quay.io/v2/prometheus/prometheus/tags/list?n=2&last=v2.1.0"
+        );
+        assert_eq!(next_page("", &base()), None);
+    }
+
+    #[test]
+    fn error_codes() {
+        for (status, body, expected) in [
+            (
+                StatusCode::UNAUTHORIZED,
+                include_str!("../../resources/registry/quay/error-invalid-token.json"),
+                Some(ErrorCode::Unauthorized),
+            ),
+            (
+                StatusCode::FORBIDDEN,
+                include_str!("../../resources/registry/quay/error-forbidden.json"),
+                Some(ErrorCode::Denied),
+            ),
+            (
+                StatusCode::UNAUTHORIZED,
+                r#"{"error": "Invalid bearer token format"}"#,
+                Some(ErrorCode::Unauthorized),
+            ),
+            (StatusCode::BAD_REQUEST, r#"{"error": "x"}"#, None),
+            (StatusCode::UNAUTHORIZED, r#"{"detail": "x"}"#, None),
+            (StatusCode::UNAUTHORIZED, "not json", None),
+        ] {
+            assert_eq!(error_code(status, body), expected, "{body}");
+        }
+    }
+
+    #[test]
+    fn missing_repository() {
+        assert!(is_missing_repository(
+            &Registry::Quay,
+            StatusCode::BAD_REQUEST
+        ));
+        assert!(!is_missing_repository(
+            &Registry::Quay,
+            StatusCode::UNAUTHORIZED
+        ));
+        assert!(!is_missing_repository(
+            &Registry::Github,
+            StatusCode::BAD_REQUEST
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    sync::Mutex,
+    time::Instant,
+};
+use url::Url;
+
+use crate::{
+    docker::{
+        auth::TokenRequest,
+        mirror::Mirrors,
+        transport,
+    },
+    Registry,
+};
+
+/// A rate limit configured with [`crate::ClientBuilder::rate_limit`].
+#[derive(Debug, Clone)]
+pub(super) struct Limit {
+    registry_host: String,
+    requests_per_interval: u32,
+    interval: Duration,
+}
+
+/// The buckets of all rate limited hosts. Clones share their buckets so
+/// cloned clients stay under the limit together.
+#[derive(Debug, Clone, Default)]
+pub(super) struct RateLimits {
+    by_host: HashMap<String, Arc<Bucket>>,
+    timeout: Option<Duration>,
+}
+
+/// A token bucket implemented as a generic cell rate algorithm. `next` is the
+/// time the bucket is empty again if no request is sent until then, a
+/// request is allowed as long as that is at most `burst` in the future.
+#[derive(Debug)]
+struct Bucket {
+    per_request: Duration,
+    burst: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Limit {
+    pub(super) fn new(registry_host: &str, requests_per_interval: u32, interval: Duration) -> Self {
+        Self {
+            registry_host: registry_host.to_string(),
+            requests_per_interval: requests_per_interval.max(1),
+            interval,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Creates a bucket per limit that is shared by every host requests for
+    /// the registry go to: the API host, an API base override and the token
+    /// endpoint. Mirrors are not limited.
+    pub(super) fn new(limits: &[Limit], mirrors: &Mirrors, timeout: Option<Duration>) -> Self {
+        let mut by_host = HashMap::new();
+
+        for limit in limits {
+            let bucket = Arc::new(Bucket::new(limit.requests_per_interval, limit.interval));
+            let registry = Registry::try_from_host(&limit.registry_host);
+
+            let mut hosts = vec![
+                limit.registry_host.clone(),
+                registry.registry_domain().to_string(),
+                registry.api_host().to_string(),
+            ];
+
+            hosts.extend(mirrors.upstream(&registry).ok().as_ref().map(authority));
+            hosts.extend(
+                TokenRequest::new(&registry, &[], None)
+                    .and_then(|request| request.url().ok())
+                    .as_ref()
+                    .map(authority),
+            );
+
+            for host in hosts {
+                by_host.insert(host, Arc::clone(&bucket));
+            }
+        }
+
+        Self { by_host, timeout }
+    }
+
+    /// Waits until a request to `url` is allowed. Fails with
+    /// [`transport::Error::RateLimitTimeout`] if that takes longer than the
+    /// configured timeout.
+    pub(super) async fn acquire(&self, url: &Url) -> Result<(), transport::Error> {
+        let host = authority(url);
+
+        let Some(bucket) = self.by_host.get(&host) else {
+            return Ok(());
+        };
+
+        if let Some(timeout) = self.timeout {
+            return tokio::time::timeout(timeout, bucket.acquire())
+                .await
+                .map_err(|_| transport::Error::RateLimitTimeout(host));
+        }
+
+        bucket.acquire().await;
+
+        Ok(())
+    }
+}
+
+impl Bucket {
+    fn new(requests_per_interval: u32, interval: Duration) -> Self {
+        let per_request = interval / requests_per_interval;
+
+        Self {
+            per_request,
+            burst: per_request * (requests_per_interval - 1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// The lock is held while sleeping, the mutex of tokio is fair so
+    /// requests are let through in the order they arrived.
+    async fn acquire(&self) {
+        let mut next = self.next.lock().await;
+
+        let now = Instant::now();
+        let start = (*next).max(now);
+
+        if let Some(allowed) = start.checked_sub(self.burst) {
+            tokio::time::sleep_until(allowed).await;
+        }
+
+        *next = start + self.per_request;
+    }
+}
+
+/// The host and port of `url`, which is how limits are looked up.
+pub(super) fn authority(url: &Url) -> String {
+    url[url::Position::BeforeHost..url::Position::AfterPort].to_string()
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::time::Duration;
+
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+    use tokio::time::Instant;
+
+    use crate::{
+        docker::transport::{
+            self,
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        ClientError,
+        Image,
+    };
+
+    const MANIFEST: &str = include_str!("../../resources/manifest/image/example.json");
+
+    fn transport(tags: usize) -> MockTransport {
+        (0..tags).fold(MockTransport::new(), |transport, tag| {
+            transport.with_response(
+                Method::GET,
+                &format!("https://registry.k8s.io/v2/app/manifests/{tag}"),
+                MockResponse::new(StatusCode::OK).body(MANIFEST),
+            )
+        })
+    }
+
+    fn image(tag: usize) -> Image {
+        format!("registry.k8s.io/app:{tag}").parse().unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_wait_in_order() {
+        let transport = transport(20);
+        let client = Client::builder()
+            .transport(transport.clone())
+            .rate_limit("registry.k8s.io", 10, Duration::from_secs(1))
+            .build();
+        let clone = client.clone();
+
+        let start = Instant::now();
+
+        // Half of the requests go through a clone, which shares the limit.
+        let results = futures::future::join_all((0..20).map(|tag| {
+            let client = if tag % 2 == 0 { &client } else { &clone };
+            async move { client.get_manifest(&image(tag)).await }
+        }))
+        .await;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        let order: Vec<String> = transport
+            .requests()
+            .iter()
+            .map(|request| request.url.path().to_string())
+            .collect();
+        let expected: Vec<String> = (0..20)
+            .map(|tag| format!("/v2/app/manifests/{tag}"))
+            .collect();
+        assert_eq!(order, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn other_hosts_are_not_limited() {
+        let client = Client::builder()
+            .transport(transport(20))
+            .rate_limit("docker.io", 1, Duration::from_mins(1))
+            .build();
+
+        let start = Instant::now();
+
+        for tag in 0..20 {
+            client.get_manifest(&image(tag)).await.unwrap();
+        }
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout() {
+        let client = Client::builder()
+            .transport(transport(2))
+            .rate_limit("registry.k8s.io", 1, Duration::from_secs(10))
+            .rate_limit_timeout(Duration::from_secs(1))
+            .build();
+
+        client.get_manifest(&image(0)).await.unwrap();
+        let got = client.get_manifest(&image(1)).await.unwrap_err();
+
+        assert!(matches!(
+            got,
+            ClientError::GetManifest(transport::Error::RateLimitTimeout(host)) if host == "registry.k8s.io"
+        ));
+    }
+}
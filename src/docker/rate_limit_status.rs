@@ -0,0 +1,341 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    time::Duration,
+};
+
+use chrono::{
+    DateTime,
+    Utc,
+};
+use http::header::{
+    HeaderMap,
+    RETRY_AFTER,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::Registry;
+
+const LIMIT_HEADER: &str = "ratelimit-limit";
+const REMAINING_HEADER: &str = "ratelimit-remaining";
+const RESET_HEADER: &str = "ratelimit-reset";
+const SOURCE_HEADER: &str = "docker-ratelimit-source";
+
+/// The pull quota of a registry as announced in the `ratelimit-limit` and
+/// `ratelimit-remaining` headers, like Docker Hub sends them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitStatus {
+    /// Requests allowed per window.
+    pub limit: u64,
+
+    /// Requests left in the current window.
+    pub remaining: u64,
+
+    /// Length of the window, requests older than that do not count anymore.
+    pub window: Duration,
+
+    /// When the quota is available again. Taken from a `ratelimit-reset` or
+    /// `Retry-After` header if the registry sent one. Otherwise the window is
+    /// assumed to have started when the client first saw the remaining count
+    /// go down, so the estimate is the latest time the counted requests leave
+    /// the window.
+    pub estimated_reset: DateTime<Utc>,
+
+    /// What the quota is counted for, the `docker-ratelimit-source` header.
+    /// The IP address for anonymous requests, an account ID otherwise.
+    pub source_ip_scope: Option<String>,
+}
+
+/// The last [`RateLimitStatus`] of every registry together with when its
+/// window started. Clones share their state.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Observed {
+    by_registry: Arc<Mutex<HashMap<Registry, Observation>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Observation {
+    status: RateLimitStatus,
+    window_start: DateTime<Utc>,
+}
+
+/// The rate limit headers of a single response.
+#[derive(Debug, PartialEq, Eq)]
+struct Headers {
+    limit: u64,
+    remaining: u64,
+    window: Duration,
+    reset_after: Option<Duration>,
+    source: Option<String>,
+}
+
+impl Observed {
+    /// Records the rate limit headers of a response from `registry` and
+    /// returns the resulting status, `None` if the response has none.
+    pub(super) fn observe(
+        &self,
+        registry: &Registry,
+        headers: &HeaderMap,
+    ) -> Option<RateLimitStatus> {
+        let headers = Headers::parse(headers, Utc::now())?;
+
+        let mut by_registry = self
+            .by_registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let observation = estimate(headers, by_registry.get(registry), Utc::now());
+        let status = observation.status.clone();
+        by_registry.insert(registry.clone(), observation);
+
+        Some(status)
+    }
+
+    pub(super) fn snapshot(&self) -> HashMap<Registry, RateLimitStatus> {
+        self.by_registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(registry, observation)| (registry.clone(), observation.status.clone()))
+            .collect()
+    }
+}
+
+impl Headers {
+    fn parse(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Self> {
+        let (limit, limit_window) = quota(header(headers, LIMIT_HEADER)?)?;
+        let (remaining, remaining_window) = quota(header(headers, REMAINING_HEADER)?)?;
+
+        let window = limit_window.or(remaining_window)?;
+
+        let reset_after = header(headers, RESET_HEADER)
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs)
+            .or_else(|| retry_after(header(headers, RETRY_AFTER.as_str())?, now));
+
+        // Docker Hub appends the kind of the source, `1.2.3.4;ip`.
+        let source = header(headers, SOURCE_HEADER)
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+
+        Some(Self {
+            limit,
+            remaining,
+            window,
+            reset_after,
+            source,
+        })
+    }
+}
+
+/// Keeps the start of the window while the remaining count goes down. A
+/// count that went up or a window that is over starts a new one.
+fn estimate(headers: Headers, previous: Option<&Observation>, now: DateTime<Utc>) -> Observation {
+    let window = chrono::Duration::from_std(headers.window).unwrap_or(chrono::Duration::MAX);
+
+    let window_start = previous
+        .filter(|previous| {
+            headers.remaining <= previous.status.remaining
+                && previous.status.window == headers.window
+                && now < previous.window_start + window
+        })
+        .map_or(now, |previous| previous.window_start);
+
+    let estimated_reset = match headers.reset_after {
+        Some(reset_after) => now + chrono::Duration::from_std(reset_after).unwrap_or(window),
+        None if headers.remaining >= headers.limit => now,
+        None => window_start + window,
+    };
+
+    Observation {
+        status: RateLimitStatus {
+            limit: headers.limit,
+            remaining: headers.remaining,
+            window: headers.window,
+            estimated_reset,
+            source_ip_scope: headers.source,
+        },
+        window_start,
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Parses `100;w=21600` and the list form of the IETF draft
+/// `100, 100;w=21600`, where the entry with a window describes the policy.
+fn quota(value: &str) -> Option<(u64, Option<Duration>)> {
+    let entries: Vec<&str> = value.split(',').map(str::trim).collect();
+
+    let entry = entries
+        .iter()
+        .find(|entry| entry.contains(";w="))
+        .or_else(|| entries.first())?;
+
+    let mut parameters = entry.split(';').map(str::trim);
+    let count = parameters.next()?.parse().ok()?;
+
+    let window = parameters
+        .filter_map(|parameter| parameter.strip_prefix("w="))
+        .find_map(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs);
+
+    Some((count, window))
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::header::HeaderValue;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const SIX_HOURS: Duration = Duration::from_hours(6);
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    http::header::HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-09-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn docker_hub() {
+        let got = Headers::parse(
+            &headers(&[
+                ("ratelimit-limit", "100;w=21600"),
+                ("ratelimit-remaining", "76;w=21600"),
+                ("docker-ratelimit-source", "192.0.2.1"),
+            ]),
+            now(),
+        );
+
+        assert_eq!(
+            got,
+            Some(Headers {
+                limit: 100,
+                remaining: 76,
+                window: SIX_HOURS,
+                reset_after: None,
+                source: Some("192.0.2.1".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn ietf_draft() {
+        let got = Headers::parse(
+            &headers(&[
+                ("ratelimit-limit", "200, 200;w=3600, 1000;w=86400"),
+                ("ratelimit-remaining", "0"),
+                ("ratelimit-reset", "120"),
+                ("docker-ratelimit-source", "0123abcd;account"),
+            ]),
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            (got.limit, got.remaining, got.window),
+            (200, 0, Duration::from_hours(1))
+        );
+        assert_eq!(got.reset_after, Some(Duration::from_mins(2)));
+        assert_eq!(got.source.as_deref(), Some("0123abcd"));
+    }
+
+    #[test]
+    fn retry_after_date() {
+        let got = Headers::parse(
+            &headers(&[
+                ("ratelimit-limit", "100;w=21600"),
+                ("ratelimit-remaining", "0;w=21600"),
+                ("retry-after", "Sun, 01 Sep 2024 12:30:00 GMT"),
+            ]),
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(got.reset_after, Some(Duration::from_mins(30)));
+    }
+
+    #[test]
+    fn missing_headers() {
+        assert_eq!(Headers::parse(&HeaderMap::new(), now()), None);
+        assert_eq!(
+            Headers::parse(&headers(&[("ratelimit-limit", "100;w=21600")]), now()),
+            None
+        );
+        assert_eq!(
+            Headers::parse(
+                &headers(&[("ratelimit-limit", "100"), ("ratelimit-remaining", "1")]),
+                now()
+            ),
+            None,
+            "a quota without a window can not be estimated"
+        );
+    }
+
+    #[test]
+    fn window_starts_at_first_decrease() {
+        let parse = |remaining: &'static str| {
+            Headers::parse(
+                &headers(&[
+                    ("ratelimit-limit", "100;w=21600"),
+                    ("ratelimit-remaining", remaining),
+                ]),
+                now(),
+            )
+            .unwrap()
+        };
+
+        let first = estimate(parse("99;w=21600"), None, now());
+        assert_eq!(first.status.estimated_reset, now() + SIX_HOURS);
+
+        // Later requests do not move the window.
+        let later = now() + chrono::Duration::hours(1);
+        let second = estimate(parse("0;w=21600"), Some(&first), later);
+        assert_eq!(second.status.estimated_reset, now() + SIX_HOURS);
+
+        // The remaining count went up, old requests left the window.
+        let recovered = now() + chrono::Duration::hours(2);
+        let third = estimate(parse("40;w=21600"), Some(&second), recovered);
+        assert_eq!(third.status.estimated_reset, recovered + SIX_HOURS);
+
+        let full = estimate(parse("100;w=21600"), Some(&third), recovered);
+        assert_eq!(full.status.estimated_reset, recovered);
+    }
+}
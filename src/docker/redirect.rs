@@ -0,0 +1,236 @@
+use std::future::Future;
+
+use http::{
+    header::{
+        AUTHORIZATION,
+        CONTENT_LENGTH,
+        CONTENT_TYPE,
+        COOKIE,
+        LOCATION,
+        PROXY_AUTHORIZATION,
+    },
+    Method,
+    StatusCode,
+};
+use tracing::debug;
+
+use crate::docker::{
+    stats::{
+        self,
+        Event,
+    },
+    transport::{
+        Error,
+        Request,
+        Response,
+    },
+};
+
+/// Sends `request` with `send` and follows the redirects the server answers
+/// with. The response of the last hop is returned, its URL is the one that
+/// served it.
+///
+/// # Errors
+/// Returns [`Error::TooManyRedirects`] if the server still redirects after
+/// `limit` redirects. Returns the error of `send` if a hop fails.
+pub(super) async fn follow<F, Fut>(
+    mut request: Request,
+    limit: usize,
+    mut send: F,
+) -> Result<Response, Error>
+where
+    F: FnMut(Request) -> Fut,
+    Fut: Future<Output = Result<Response, Error>>,
+{
+    let mut redirects = 0;
+
+    loop {
+        let response = send(request.clone()).await?;
+
+        let Some(next) = next(&request, &response) else {
+            return Ok(response);
+        };
+
+        if redirects == limit {
+            return Err(Error::TooManyRedirects {
+                url: request.url,
+                limit,
+            });
+        }
+
+        debug!(
+            from = %request.url,
+            to = %next.url,
+            status = %response.status,
+            "following redirect"
+        );
+
+        redirects += 1;
+        stats::record(Event::Redirect);
+        request = next;
+    }
+}
+
+/// Returns the request to send next if `response` redirects `request`.
+/// `None` if the response is not a redirect or has no valid `Location`.
+///
+/// Credentials are only sent along if the target has the same scheme, host
+/// and port as `request`. Registries like registry.k8s.io redirect to blob
+/// storage of another provider that must not see the registry token.
+fn next(request: &Request, response: &Response) -> Option<Request> {
+    let keeps_method = match response.status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => false,
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => true,
+        _ => return None,
+    };
+
+    let location = response.headers.get(LOCATION)?.to_str().ok()?;
+    let url = request.url.join(location).ok()?;
+
+    let mut next = Request::new(request.method.clone(), url).headers(request.headers.clone());
+    next.body.clone_from(&request.body);
+
+    if !keeps_method && request.method != Method::GET && request.method != Method::HEAD {
+        next.method = Method::GET;
+        next.body = None;
+        next.headers.remove(CONTENT_LENGTH);
+        next.headers.remove(CONTENT_TYPE);
+    }
+
+    if next.url.origin() != request.url.origin() {
+        next.headers.remove(AUTHORIZATION);
+        next.headers.remove(PROXY_AUTHORIZATION);
+        next.headers.remove(COOKIE);
+    }
+
+    Some(next)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use bytes::Bytes;
+    use http::{
+        header::{
+            HeaderMap,
+            AUTHORIZATION,
+            LOCATION,
+        },
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::next;
+    use crate::docker::transport::{
+        Request,
+        Response,
+    };
+
+    const URL: &str = "https://registry.k8s.io/v2/pause/blobs/sha256:abc";
+
+    fn request(method: Method) -> Request {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+
+        let mut request = Request::new(method, URL.parse().unwrap()).headers(headers);
+        request.body = Some(Bytes::from_static(b"body"));
+
+        request
+    }
+
+    fn response(status: StatusCode, location: &str) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, location.parse().unwrap());
+
+        Response::new(
+            status,
+            headers,
+            URL.parse().unwrap(),
+            futures::stream::empty(),
+        )
+    }
+
+    #[test]
+    fn cross_host_strips_credentials() {
+        let got = next(
+            &request(Method::GET),
+            &response(
+                StatusCode::FOUND,
+                "https://prod-registry-k8s-io-eu-west-1.s3.dualstack.eu-west-1.amazonaws.com/blob",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            got.url.host_str(),
+            Some("prod-registry-k8s-io-eu-west-1.s3.dualstack.eu-west-1.amazonaws.com")
+        );
+        assert!(!got.headers.contains_key(AUTHORIZATION));
+    }
+
+    #[test]
+    fn same_host_keeps_credentials() {
+        let got = next(
+            &request(Method::GET),
+            &response(StatusCode::TEMPORARY_REDIRECT, "/v2/pause/blobs/other"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            got.url.as_str(),
+            "https://registry.k8s.io/v2/pause/blobs/other"
+        );
+        assert_eq!(got.headers[AUTHORIZATION], "Bearer secret");
+    }
+
+    #[test]
+    fn scheme_downgrade_strips_credentials() {
+        let got = next(
+            &request(Method::GET),
+            &response(StatusCode::FOUND, "http://registry.k8s.io/v2/pause"),
+        )
+        .unwrap();
+
+        assert!(!got.headers.contains_key(AUTHORIZATION));
+    }
+
+    #[test]
+    fn methods() {
+        for (status, method, expected, has_body) in [
+            (StatusCode::SEE_OTHER, Method::POST, Method::GET, false),
+            (StatusCode::FOUND, Method::HEAD, Method::HEAD, true),
+            (
+                StatusCode::TEMPORARY_REDIRECT,
+                Method::PUT,
+                Method::PUT,
+                true,
+            ),
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                Method::POST,
+                Method::POST,
+                true,
+            ),
+        ] {
+            let got = next(&request(method), &response(status, "/next")).unwrap();
+
+            assert_eq!(got.method, expected, "{status}");
+            assert_eq!(got.body.is_some(), has_body, "{status}");
+        }
+    }
+
+    #[test]
+    fn not_a_redirect() {
+        assert!(next(&request(Method::GET), &response(StatusCode::OK, "/next")).is_none());
+        assert!(next(
+            &request(Method::GET),
+            &response(StatusCode::NOT_MODIFIED, "/next")
+        )
+        .is_none());
+
+        let mut without_location = response(StatusCode::FOUND, "/next");
+        without_location.headers.clear();
+        assert!(next(&request(Method::GET), &without_location).is_none());
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+
+use http::header::{
+    HeaderMap,
+    HeaderName,
+    HeaderValue,
+};
+
+use crate::{
+    docker::{
+        auth::TokenRequest,
+        mirror::Mirrors,
+        rate_limit::authority,
+        transport::Request,
+    },
+    Registry,
+};
+
+/// A header configured with [`crate::ClientBuilder::registry_header`].
+#[derive(Debug, Clone)]
+pub(super) struct RegistryHeader {
+    registry_host: String,
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+/// The extra headers of every host that gets them.
+#[derive(Debug, Clone, Default)]
+pub(super) struct RegistryHeaders {
+    by_host: HashMap<String, HeaderMap>,
+
+    /// The headers per registry, for the realms of their challenges that are
+    /// only known once a registry answers with one.
+    by_registry: HashMap<Registry, HeaderMap>,
+}
+
+impl RegistryHeader {
+    /// The value is marked as sensitive, so it is not shown when the headers
+    /// are logged.
+    pub(super) fn new(registry_host: &str, name: HeaderName, mut value: HeaderValue) -> Self {
+        value.set_sensitive(true);
+
+        Self {
+            registry_host: registry_host.to_string(),
+            name,
+            value,
+        }
+    }
+}
+
+impl RegistryHeaders {
+    /// Collects the headers per host the requests for their registry go to:
+    /// the API host, an API base override and the token endpoint, like
+    /// [`crate::ClientBuilder::rate_limit`]. Mirrors do not get them.
+    pub(super) fn new(headers: &[RegistryHeader], mirrors: &Mirrors) -> Self {
+        let mut by_host: HashMap<String, HeaderMap> = HashMap::new();
+        let mut by_registry: HashMap<Registry, HeaderMap> = HashMap::new();
+
+        for header in headers {
+            let registry = Registry::try_from_host(&header.registry_host);
+
+            by_registry
+                .entry(registry.clone())
+                .or_default()
+                .append(header.name.clone(), header.value.clone());
+
+            let mut hosts = vec![
+                header.registry_host.clone(),
+                registry.registry_domain().to_string(),
+                registry.api_host().to_string(),
+            ];
+
+            hosts.extend(mirrors.upstream(&registry).ok().as_ref().map(authority));
+            hosts.extend(
+                TokenRequest::new(&registry, &[], None)
+                    .and_then(|request| request.url().ok())
+                    .as_ref()
+                    .map(authority),
+            );
+
+            hosts.sort();
+            hosts.dedup();
+
+            for host in hosts {
+                by_host
+                    .entry(host)
+                    .or_default()
+                    .append(header.name.clone(), header.value.clone());
+            }
+        }
+
+        Self {
+            by_host,
+            by_registry,
+        }
+    }
+
+    /// Adds the headers configured for the host of `request`. Every hop of a
+    /// redirect is a request of its own, so targets on other hosts do not get
+    /// them.
+    pub(super) fn apply(&self, request: &mut Request) {
+        let Some(headers) = self.by_host.get(&authority(&request.url)) else {
+            return;
+        };
+
+        for (name, value) in headers {
+            request.headers.append(name, value.clone());
+        }
+    }
+
+    /// Adds the headers configured for `registry` to a hop of a token request
+    /// that goes to `realm`, the host of the realm of a challenge `registry`
+    /// answered with. Realms on hosts [`RegistryHeaders::apply`] already
+    /// covers and hops to other hosts do not get them.
+    pub(super) fn apply_to_realm(&self, registry: &Registry, realm: &str, request: &mut Request) {
+        if self.by_host.contains_key(realm) || authority(&request.url) != realm {
+            return;
+        }
+
+        let Some(headers) = self.by_registry.get(registry) else {
+            return;
+        };
+
+        for (name, value) in headers {
+            request.headers.append(name, value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use futures::TryStreamExt;
+    use http::{
+        header::AUTHORIZATION,
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            self,
+            MockResponse,
+            MockTransport,
+            Request,
+            Response,
+            Transport,
+        },
+        Client,
+        Digest,
+        Image,
+    };
+
+    const MANIFEST: &str = include_str!("../../resources/manifest/image/example.json");
+    const TOKEN: &str = "https://ghcr.io/token?scope=repository:example/app:pull&service=ghcr.io";
+    const STORAGE: &str = "https://pkg-containers.githubusercontent.com/blob";
+
+    fn client(transport: &MockTransport) -> Client {
+        Client::builder()
+            .transport(transport.clone())
+            .registry_header(
+                "ghcr.io",
+                "x-org-token".parse().unwrap(),
+                "secret".parse().unwrap(),
+            )
+            .registry_header(
+                "ghcr.io",
+                "x-org-team".parse().unwrap(),
+                "platform".parse().unwrap(),
+            )
+            .build()
+    }
+
+    /// A registry with its token server on another host, manifest requests
+    /// without a token are answered with a challenge for it.
+    #[derive(Debug, Clone, Default)]
+    struct SplitRealm {
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for SplitRealm {
+        async fn execute(&self, request: Request) -> Result<Response, transport::Error> {
+            self.requests.lock().unwrap().push(request.clone());
+
+            let response = match request.url.path() {
+                "/token" => MockResponse::new(StatusCode::OK).body(r#"{"token":"realm-token"}"#),
+
+                "/v2/team/app/manifests/1.0" if request.headers.contains_key(AUTHORIZATION) => {
+                    MockResponse::new(StatusCode::OK).body(MANIFEST)
+                }
+
+                "/v2/team/app/manifests/1.0" => MockResponse::new(StatusCode::UNAUTHORIZED).header(
+                    "WWW-Authenticate",
+                    r#"Bearer realm="https://auth.example.com/token",service="registry""#,
+                ),
+
+                _ => return Err(transport::Error::Unmatched(request.url.to_string())),
+            };
+
+            MockTransport::new()
+                .with_response(Method::GET, request.url.as_str(), response)
+                .execute(request)
+                .await
+        }
+    }
+
+    /// The values of the headers per request, `None` for requests without
+    /// them.
+    fn org_tokens(transport: &MockTransport) -> Vec<(String, Option<String>)> {
+        header_values(transport.requests())
+    }
+
+    fn header_values(requests: Vec<Request>) -> Vec<(String, Option<String>)> {
+        requests
+            .into_iter()
+            .map(|request| {
+                let value = request
+                    .headers
+                    .get("x-org-token")
+                    .map(|value| value.to_str().unwrap().to_string());
+
+                (request.url.to_string(), value)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn manifest_and_token() {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/v2/example/app/manifests/1.0",
+                MockResponse::new(StatusCode::OK).body(MANIFEST),
+            )
+            .with_response(
+                Method::GET,
+                "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                MockResponse::new(StatusCode::OK).body(MANIFEST),
+            );
+
+        let client = client(&transport);
+
+        let ghcr: Image = "ghcr.io/example/app:1.0".parse().unwrap();
+        client.get_manifest(&ghcr).await.unwrap();
+
+        let other: Image = "registry.access.redhat.com/ubi8:8.9".parse().unwrap();
+        client.get_manifest(&other).await.unwrap();
+
+        assert_eq!(
+            org_tokens(&transport),
+            [
+                (TOKEN.to_string(), Some("secret".to_string())),
+                (
+                    "https://ghcr.io/v2/example/app/manifests/1.0".to_string(),
+                    Some("secret".to_string())
+                ),
+                (
+                    "https://registry.access.redhat.com/v2/ubi8/manifests/8.9".to_string(),
+                    None
+                ),
+            ]
+        );
+
+        let manifest = &transport.requests()[1];
+        assert_eq!(manifest.headers["x-org-team"], "platform");
+        assert!(manifest.headers["x-org-token"].is_sensitive());
+    }
+
+    /// Blob storage on another host the registry redirects to does not get
+    /// the headers.
+    #[tokio::test]
+    async fn cross_host_redirect() {
+        let digest = Digest::sha256(b"blob");
+        let blob = format!("https://ghcr.io/v2/example/app/blobs/{digest}");
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                &blob,
+                MockResponse::new(StatusCode::TEMPORARY_REDIRECT).header("Location", STORAGE),
+            )
+            .with_response(
+                Method::GET,
+                STORAGE,
+                MockResponse::new(StatusCode::OK).body("blob"),
+            );
+
+        let client = client(&transport);
+        let image: Image = "ghcr.io/example/app:1.0".parse().unwrap();
+
+        let chunks: Vec<bytes::Bytes> = client
+            .get_blob(&image, &digest)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"blob");
+
+        assert_eq!(
+            org_tokens(&transport),
+            [
+                (TOKEN.to_string(), Some("secret".to_string())),
+                (blob, Some("secret".to_string())),
+                (STORAGE.to_string(), None),
+            ]
+        );
+    }
+
+    /// The realm of a challenge of a registry without a built-in token
+    /// endpoint gets the headers, even on another host.
+    #[tokio::test]
+    async fn challenge_realm_on_other_host() {
+        let transport = SplitRealm::default();
+        let client = Client::builder()
+            .transport(transport.clone())
+            .registry_header(
+                "registry.example.com",
+                "x-org-token".parse().unwrap(),
+                "secret".parse().unwrap(),
+            )
+            .build();
+
+        let image: Image = "registry.example.com/team/app:1.0".parse().unwrap();
+        client.get_manifest(&image).await.unwrap();
+
+        let manifest = "https://registry.example.com/v2/team/app/manifests/1.0".to_string();
+        let requests = transport.requests.lock().unwrap().clone();
+        assert_eq!(
+            header_values(requests),
+            [
+                (manifest.clone(), Some("secret".to_string())),
+                (
+                    "https://auth.example.com/token?service=registry".to_string(),
+                    Some("secret".to_string())
+                ),
+                (manifest, Some("secret".to_string())),
+            ]
+        );
+    }
+}
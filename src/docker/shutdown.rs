@@ -0,0 +1,206 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+
+use crate::docker::{
+    Client,
+    Error,
+};
+
+type Hook = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The callbacks registered with [`crate::ClientBuilder::on_shutdown`].
+#[derive(Clone, Default)]
+pub(super) struct Hooks(Vec<Hook>);
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks").field("len", &self.0.len()).finish()
+    }
+}
+
+impl Hooks {
+    pub(super) fn push<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.0.push(Arc::new(move || Box::pin(hook())));
+    }
+
+    async fn run(&self) {
+        for hook in &self.0 {
+            hook().await;
+        }
+    }
+}
+
+impl Client {
+    /// Flushes the state this client keeps in the background. The token
+    /// cache is saved to the file configured with
+    /// [`crate::ClientBuilder::token_cache_file`] and the callbacks
+    /// registered with [`crate::ClientBuilder::on_shutdown`] are run in the
+    /// order they were added. Redis caches open a connection per request,
+    /// there is nothing to close for them.
+    ///
+    /// Dropping a client without calling `shutdown` is safe. The token file
+    /// is still saved when the last clone is dropped, but only if the cache
+    /// is not in use at that moment, and the shutdown callbacks do not run,
+    /// so cached state may be lost.
+    ///
+    /// Clones share the token cache but every call runs the callbacks, so
+    /// call `shutdown` on one clone only.
+    ///
+    /// # Errors
+    /// Returns an error if the token file can not be written. The callbacks
+    /// run anyway.
+    pub async fn shutdown(self) -> Result<(), Error> {
+        let persisted = self.persist_tokens().await;
+
+        self.inner.shutdown_hooks.run().await;
+
+        persisted
+    }
+
+    /// Same as [`Client::shutdown`] but gives up after `timeout`.
+    ///
+    /// # Errors
+    /// Returns [`Error::ShutdownTimeout`] if the shutdown takes longer than
+    /// `timeout`, callbacks that did not finish yet are cancelled. Returns
+    /// an error if the token file can not be written.
+    pub async fn shutdown_timeout(self, timeout: Duration) -> Result<(), Error> {
+        tokio::time::timeout(timeout, self.shutdown())
+            .await
+            .map_err(|_| Error::ShutdownTimeout(timeout))?
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        ClientError,
+        Image,
+    };
+
+    fn image() -> Image {
+        "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap()
+    }
+
+    fn transport() -> MockTransport {
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io",
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"shutdown-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+                MockResponse::new(StatusCode::OK)
+                    .body(include_str!("../../resources/registry/github/cosign.json")),
+            )
+    }
+
+    #[tokio::test]
+    async fn persists_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+
+        let client = Client::builder()
+            .transport(transport())
+            .token_cache_file(&path)
+            .build();
+        client.get_manifest(&image()).await.unwrap();
+
+        // A clone keeps the cache alive, so nothing is saved on drop.
+        let clone = client.clone();
+        client.shutdown().await.unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("shutdown-token"), "{saved}");
+
+        drop(clone);
+    }
+
+    #[tokio::test]
+    async fn drop_without_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+
+        let client = Client::builder()
+            .transport(transport())
+            .token_cache_file(&path)
+            .token_cache_memory()
+            .build();
+        client.get_manifest(&image()).await.unwrap();
+        drop(client);
+
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn runs_hooks() {
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let client = Client::builder()
+            .transport(MockTransport::new())
+            .on_shutdown({
+                let calls = Arc::clone(&calls);
+                move || {
+                    let calls = Arc::clone(&calls);
+                    async move {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .build();
+
+        drop(client.clone());
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+
+        client.shutdown().await.unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let client = Client::builder()
+            .transport(MockTransport::new())
+            .on_shutdown(|| tokio::time::sleep(Duration::from_mins(1)))
+            .build();
+
+        let got = client
+            .shutdown_timeout(Duration::from_millis(10))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, ClientError::ShutdownTimeout(_)));
+    }
+}
@@ -0,0 +1,125 @@
+//! A view of [`Response`] for snapshot tests, see
+//! [`Response::redacted_for_snapshot`].
+
+use serde::Serialize;
+
+use crate::{
+    docker::{
+        negotiation::Negotiation,
+        Response,
+    },
+    Manifest,
+    RegistryWarning,
+};
+
+/// The parts of a [`Response`] that only change when the manifest changes.
+/// Serializes to the same bytes for the same manifest, no matter when or
+/// from where it was fetched. The maps of the manifest are ordered by key.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseSnapshot<'a> {
+    pub digest: Option<&'a str>,
+    pub manifest: &'a Manifest,
+
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub warnings: &'a [RegistryWarning],
+
+    #[serde(skip_serializing_if = "Negotiation::is_accepted")]
+    pub negotiation: Negotiation,
+
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub digest_verified: bool,
+}
+
+impl Response {
+    /// Returns the view of the response to snapshot in tests, for example
+    /// with `insta::assert_json_snapshot!`. The fields that describe the
+    /// request instead of the manifest are left out: the pull quota, which
+    /// changes with every request, and the host that served the manifest,
+    /// which depends on mirrors and redirects.
+    ///
+    /// The fields of the view are the parts of the serialization of
+    /// [`Response`] that are kept stable. Fields added to [`Response`] later
+    /// are only added to the view if they are as deterministic.
+    #[must_use]
+    pub fn redacted_for_snapshot(&self) -> ResponseSnapshot<'_> {
+        ResponseSnapshot {
+            digest: self.digest.as_deref(),
+            manifest: &self.manifest,
+            warnings: &self.warnings,
+            negotiation: self.negotiation.clone(),
+            digest_verified: self.digest_verified,
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::{
+        assert_eq,
+        assert_ne,
+    };
+
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        Image,
+        Response,
+    };
+
+    const MANIFEST: &str = include_str!("../../resources/manifest/list/example.json");
+    const TOKEN: &str = "https://auth.docker.io/token?service=registry.docker.io&scope=repository:library/alpine:pull&service=registry.docker.io";
+    const URL: &str = "https://registry-1.docker.io/v2/library/alpine/manifests/3.20";
+
+    /// Fetches the fixture with a different pull quota left every time.
+    async fn fetch(remaining: &str) -> Response {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"registry-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                URL,
+                MockResponse::new(StatusCode::OK)
+                    .header("ratelimit-limit", "100;w=21600")
+                    .header("ratelimit-remaining", remaining)
+                    .body(MANIFEST),
+            );
+
+        let image: Image = "alpine:3.20".parse().unwrap();
+
+        Client::builder()
+            .transport(transport)
+            .build()
+            .get_manifest(&image)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn identical_for_the_same_manifest() {
+        let first = fetch("76;w=21600").await;
+        let second = fetch("75;w=21600").await;
+
+        assert_ne!(
+            serde_json::to_string(&first).unwrap(),
+            serde_json::to_string(&second).unwrap()
+        );
+
+        assert_eq!(
+            serde_json::to_string_pretty(&first.redacted_for_snapshot()).unwrap(),
+            serde_json::to_string_pretty(&second.redacted_for_snapshot()).unwrap()
+        );
+
+        insta::assert_json_snapshot!(first.redacted_for_snapshot());
+    }
+}
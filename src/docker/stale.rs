@@ -0,0 +1,320 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+use chrono::{
+    DateTime,
+    Utc,
+};
+use either::Either;
+use futures::{
+    StreamExt,
+    TryStreamExt,
+};
+
+use crate::{
+    docker::{
+        platform::ResolvedManifest,
+        tags::cosign_subject,
+        Client,
+        Error,
+    },
+    manifest::Descriptor,
+    Digest,
+    Image,
+    ImageConfig,
+    ImageName,
+    Manifest,
+    Tag,
+};
+
+/// Number of manifest and config requests that are sent at the same time
+/// when looking for stale tags.
+const STALE_CONCURRENCY: usize = 8;
+
+/// A tag whose image was created before the cutoff passed to
+/// [`Client::stale_tags`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleTag {
+    pub tag: Tag,
+
+    /// The digest the tag points to. For manifest lists this is the digest of
+    /// the list.
+    pub digest: Digest,
+
+    /// When the image was created according to its config. For manifest
+    /// lists this is the image of the default platform of the client.
+    pub created: DateTime<Utc>,
+}
+
+impl Client {
+    /// Returns the tags of the repository of `image` whose image was created
+    /// before `older_than`. Tags of cosign signatures, attestations and SBOMs
+    /// are skipped, as are tags that point to a digest cosign signed or
+    /// attested. Images without a creation time are never stale.
+    ///
+    /// This fetches the manifest of every tag and the config of every
+    /// distinct image, so it sends at least one request per tag. Configs are
+    /// only fetched once per digest and up to 8 requests are in flight at the
+    /// same time.
+    ///
+    /// # Errors
+    /// Returns an error if the tags can not be listed or a manifest or config
+    /// can not be fetched.
+    #[tracing::instrument(
+        name = "stale_tags",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            older_than = %older_than,
+        )
+    )]
+    pub async fn stale_tags(
+        &self,
+        image: &Image,
+        older_than: DateTime<Utc>,
+    ) -> Result<Vec<StaleTag>, Error> {
+        let tags = self.list_tags(image).await?;
+
+        let signed: HashSet<Digest> = tags.iter().filter_map(cosign_subject).collect();
+
+        let candidates: Vec<(Tag, Digest, Descriptor)> =
+            futures::stream::iter(tags.into_iter().filter(|tag| cosign_subject(tag).is_none()))
+                .map(|tag| async move {
+                    let config = self.tag_config(image, &tag).await?;
+
+                    Ok::<_, Error>(config.map(|(digest, config)| (tag, digest, config)))
+                })
+                .buffered(STALE_CONCURRENCY)
+                .try_filter_map(|candidate| async move { Ok(candidate) })
+                .try_filter(|(_, digest, _)| {
+                    futures::future::ready(
+                        !signed.iter().any(|signed| signed.is_equivalent(digest)),
+                    )
+                })
+                .try_collect()
+                .await?;
+
+        // Many tags point to the same image, fetch each config only once.
+        let configs: HashMap<&Digest, &Descriptor> = candidates
+            .iter()
+            .map(|(_, _, config)| (&config.digest, config))
+            .collect();
+
+        let created: HashMap<&Digest, Option<DateTime<Utc>>> = futures::stream::iter(configs)
+            .map(|(digest, descriptor)| async move {
+                let config: ImageConfig = self.get_blob_json(image, descriptor).await?;
+
+                Ok::<_, Error>((digest, config.created))
+            })
+            .buffer_unordered(STALE_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let stale = candidates
+            .iter()
+            .filter_map(|(tag, digest, config)| {
+                let created = created.get(&config.digest).copied().flatten()?;
+
+                (created < older_than).then(|| StaleTag {
+                    tag: tag.clone(),
+                    digest: digest.clone(),
+                    created,
+                })
+            })
+            .collect();
+
+        Ok(stale)
+    }
+
+    /// Returns the digest `tag` points to together with the descriptor of the
+    /// config of its image. Returns `None` for tags without an image config
+    /// like schema 1 manifests.
+    async fn tag_config(
+        &self,
+        image: &Image,
+        tag: &Tag,
+    ) -> Result<Option<(Digest, Descriptor)>, Error> {
+        let tagged = Image {
+            image_name: ImageName::new(image.image_name.name.clone(), Either::Left(tag.clone())),
+            ..image.clone()
+        };
+
+        let (digest, response) = match self.get_manifest_resolved(&tagged).await? {
+            ResolvedManifest::Followed {
+                list_digest,
+                response,
+            } => (list_digest, response),
+
+            ResolvedManifest::Original(response) => (response.digest.clone(), response),
+        };
+
+        let digest = digest
+            .ok_or(Error::MissingDockerContentDigestHeader)?
+            .parse()
+            .map_err(Error::ParseDockerContentDigest)?;
+
+        let Manifest::Image(manifest) = response.manifest else {
+            return Ok(None);
+        };
+
+        Ok(Some((digest, manifest.config)))
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use chrono::{
+        TimeZone,
+        Utc,
+    };
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::{
+            stale::StaleTag,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        Client,
+        Digest,
+        Tag,
+    };
+
+    const BASE: &str = "https://registry.k8s.io/v2/app";
+
+    fn config(created: &str) -> String {
+        serde_json::json!({
+            "created": created,
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": { "type": "layers", "diff_ids": [] }
+        })
+        .to_string()
+    }
+
+    fn manifest(config: &str, marker: &str) -> String {
+        serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": config.len(),
+                "digest": Digest::sha256(config.as_bytes()).to_string()
+            },
+            "layers": [],
+            "annotations": { "marker": marker }
+        })
+        .to_string()
+    }
+
+    /// A repository with two tags of an old image, a new image and an old
+    /// image that has a cosign signature. The two old images share a config.
+    fn transport() -> (MockTransport, Digest) {
+        let old_config = config("2022-06-01T00:00:00Z");
+        let new_config = config("2024-06-01T00:00:00Z");
+
+        let old = manifest(&old_config, "old");
+        let new = manifest(&new_config, "new");
+        let signed = manifest(&old_config, "signed");
+
+        let signed_digest = Digest::sha256(signed.as_bytes());
+        let signature = format!(
+            "{}.sig",
+            signed_digest.to_string().replace("sha256:", "sha256-")
+        );
+
+        let tags = ["old", "old-alias", "new", "signed", &signature];
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            &format!("{BASE}/tags/list"),
+            MockResponse::new(StatusCode::OK)
+                .body(serde_json::json!({ "name": "app", "tags": tags }).to_string()),
+        );
+
+        let transport = [
+            ("old", &old),
+            ("old-alias", &old),
+            ("new", &new),
+            ("signed", &signed),
+        ]
+        .into_iter()
+        .fold(transport, |transport, (tag, body)| {
+            transport.with_response(
+                Method::GET,
+                &format!("{BASE}/manifests/{tag}"),
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Docker-Content-Digest",
+                        &Digest::sha256(body.as_bytes()).to_string(),
+                    )
+                    .body(body.clone()),
+            )
+        });
+
+        let transport =
+            [old_config, new_config]
+                .into_iter()
+                .fold(transport, |transport, config| {
+                    transport.with_response(
+                        Method::GET,
+                        &format!("{BASE}/blobs/{}", Digest::sha256(config.as_bytes())),
+                        MockResponse::new(StatusCode::OK).body(config),
+                    )
+                });
+
+        (transport, Digest::sha256(old.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn filters_new_and_signed() {
+        let (transport, old) = transport();
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = "registry.k8s.io/app:old".parse().unwrap();
+        let cutoff = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+
+        let got = client.stale_tags(&image, cutoff).await.unwrap();
+
+        let created = Utc.with_ymd_and_hms(2022, 6, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            got,
+            vec![
+                StaleTag {
+                    tag: Tag::Specific("old".into()),
+                    digest: old.clone(),
+                    created,
+                },
+                StaleTag {
+                    tag: Tag::Specific("old-alias".into()),
+                    digest: old,
+                    created,
+                },
+            ]
+        );
+
+        // The tag list, four manifests and each of the two configs once.
+        assert_eq!(transport.requests().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn nothing_is_stale_before_the_oldest_image() {
+        let (transport, _) = transport();
+        let client = Client::builder().transport(transport).build();
+        let image = "registry.k8s.io/app:old".parse().unwrap();
+        let cutoff = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+
+        let got = client.stale_tags(&image, cutoff).await.unwrap();
+
+        assert_eq!(got, Vec::new());
+    }
+}
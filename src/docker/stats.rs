@@ -0,0 +1,369 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+tokio::task_local! {
+    static RECORDER: Arc<Recorder>;
+}
+
+/// What the client did while running an operation wrapped in [`measure`].
+/// The counters are taken from the same place the `http.request` spans are
+/// recorded, so they cover every request including token requests and
+/// mirror fallbacks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OperationStats {
+    /// Requests sent to registries, mirrors and token endpoints.
+    pub requests: u64,
+
+    /// Response body bytes read while the operation ran.
+    pub bytes_downloaded: u64,
+
+    /// Request body bytes sent.
+    pub bytes_uploaded: u64,
+
+    /// Manifests and tokens served from the caches of the client.
+    pub cache_hits: u64,
+
+    /// Requests that were sent again to the next endpoint because a mirror
+    /// failed to serve them.
+    pub retries: u64,
+
+    /// Redirects that were followed, every hop is also counted in
+    /// `requests`.
+    pub redirects: u64,
+
+    /// Entries of manifest lists that were left out because their manifest
+    /// is missing, see [`crate::docker::missing_child::MissingChildPolicy`].
+    pub skipped_children: u64,
+
+    /// Wall clock time of the operation.
+    pub duration: Duration,
+}
+
+/// Something the client did that is counted in [`OperationStats`].
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Event {
+    Request { body: u64 },
+    Downloaded(u64),
+    CacheHit,
+    Retry,
+    Redirect,
+    SkippedChild,
+}
+
+#[derive(Debug, Default)]
+struct Recorder {
+    requests: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    cache_hits: AtomicU64,
+    retries: AtomicU64,
+    redirects: AtomicU64,
+    skipped_children: AtomicU64,
+}
+
+/// Runs `operation` and returns its output together with what the client
+/// did for it, for example
+/// `stats::measure(client.download_layers(&image, &manifest, dir, 4))`.
+///
+/// Only work that happens while `operation` is polled is counted. Streams
+/// returned by the operation, like the one of [`crate::Client::get_blob`],
+/// are counted only if they are read inside of it. Measurements can be
+/// nested, the outer one includes the counters of the inner one.
+pub async fn measure<F: Future>(operation: F) -> (F::Output, OperationStats) {
+    let recorder = Arc::new(Recorder::default());
+    let start = Instant::now();
+
+    let output = RECORDER.scope(Arc::clone(&recorder), operation).await;
+
+    let stats = recorder.stats(start.elapsed());
+    let _ = RECORDER.try_with(|outer| outer.add(&stats));
+
+    (output, stats)
+}
+
+/// Counts `event` for the operation that is measured, if any.
+pub(super) fn record(event: Event) {
+    let _ = RECORDER.try_with(|recorder| recorder.record(event));
+}
+
+impl Recorder {
+    fn record(&self, event: Event) {
+        match event {
+            Event::Request { body } => {
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                self.bytes_uploaded.fetch_add(body, Ordering::Relaxed);
+            }
+
+            Event::Downloaded(bytes) => {
+                self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+            }
+
+            Event::CacheHit => {
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            }
+
+            Event::Retry => {
+                self.retries.fetch_add(1, Ordering::Relaxed);
+            }
+
+            Event::Redirect => {
+                self.redirects.fetch_add(1, Ordering::Relaxed);
+            }
+
+            Event::SkippedChild => {
+                self.skipped_children.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn add(&self, stats: &OperationStats) {
+        self.requests.fetch_add(stats.requests, Ordering::Relaxed);
+        self.bytes_downloaded
+            .fetch_add(stats.bytes_downloaded, Ordering::Relaxed);
+        self.bytes_uploaded
+            .fetch_add(stats.bytes_uploaded, Ordering::Relaxed);
+        self.cache_hits
+            .fetch_add(stats.cache_hits, Ordering::Relaxed);
+        self.retries.fetch_add(stats.retries, Ordering::Relaxed);
+        self.redirects.fetch_add(stats.redirects, Ordering::Relaxed);
+        self.skipped_children
+            .fetch_add(stats.skipped_children, Ordering::Relaxed);
+    }
+
+    fn stats(&self, duration: Duration) -> OperationStats {
+        OperationStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            redirects: self.redirects.load(Ordering::Relaxed),
+            skipped_children: self.skipped_children.load(Ordering::Relaxed),
+            duration,
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use bytes::Bytes;
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        manifest,
+        Client,
+        Digest,
+        Image,
+        Manifest,
+    };
+
+    const SOURCE: &str = "https://registry.k8s.io/v2/src";
+    const DESTINATION: &str = "https://registry.k8s.io/v2/dst";
+    const LAYERS: [&[u8]; 2] = [b"first layer", b"second, larger layer"];
+    const MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+    fn manifest() -> String {
+        let layers: Vec<_> = LAYERS
+            .iter()
+            .map(|layer| {
+                serde_json::json!({
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar",
+                    "size": layer.len(),
+                    "digest": Digest::sha256(layer),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE,
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": 0,
+                "digest": Digest::sha256(b""),
+            },
+            "layers": layers,
+        })
+        .to_string()
+    }
+
+    /// Serves the source image upstream and accepts uploads to the
+    /// destination. The mirror has nothing, so every pull falls back once.
+    fn transport(manifest: &str) -> MockTransport {
+        let upload = format!("{DESTINATION}/blobs/uploads/session");
+
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                &format!("{SOURCE}/manifests/{}", Digest::sha256(manifest.as_bytes())),
+                MockResponse::new(StatusCode::OK)
+                    .header("Content-Type", MEDIA_TYPE)
+                    .body(manifest.to_string()),
+            )
+            .with_response(
+                Method::POST,
+                &format!("{DESTINATION}/blobs/uploads/"),
+                MockResponse::new(StatusCode::ACCEPTED)
+                    .header("Location", "/v2/dst/blobs/uploads/session"),
+            )
+            .with_response(
+                Method::PUT,
+                &format!("{DESTINATION}/manifests/1.0"),
+                MockResponse::new(StatusCode::CREATED),
+            );
+
+        LAYERS.iter().fold(transport, |transport, layer| {
+            let digest = Digest::sha256(layer);
+
+            transport
+                .with_response(
+                    Method::GET,
+                    &format!("{SOURCE}/blobs/{digest}"),
+                    MockResponse::new(StatusCode::OK).body(*layer),
+                )
+                .with_response(
+                    Method::PUT,
+                    &format!("{upload}?digest={}", digest.to_string().replace(':', "%3A")),
+                    MockResponse::new(StatusCode::CREATED),
+                )
+        })
+    }
+
+    async fn copy(client: &Client, source: &Image, destination: &Image) {
+        let response = client.get_manifest(source).await.unwrap();
+        let Manifest::Image(image) = response.manifest else {
+            panic!("expected an image");
+        };
+
+        for layer in &image.layers {
+            let digest = layer.digest.clone();
+            let content = client.get_descriptor_bytes(source, layer).await.unwrap();
+
+            client
+                .push_blob(destination, &digest, Bytes::from(content))
+                .await
+                .unwrap();
+        }
+
+        client
+            .push_manifest(destination, MEDIA_TYPE, Bytes::from(manifest()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn copy_two_layers() {
+        let manifest = manifest();
+        let client = Client::builder()
+            .transport(transport(&manifest))
+            .registry_mirror(
+                "registry.k8s.io",
+                "http://mirror.internal:5000".parse::<url::Url>().unwrap(),
+            )
+            .manifest_cache_memory()
+            .build();
+
+        let source: Image = format!(
+            "registry.k8s.io/src@{}",
+            Digest::sha256(manifest.as_bytes())
+        )
+        .parse()
+        .unwrap();
+        let destination: Image = "registry.k8s.io/dst:1.0".parse().unwrap();
+
+        let ((), stats) = Box::pin(measure(copy(&client, &source, &destination))).await;
+
+        let layers: u64 = LAYERS.iter().map(|layer| layer.len() as u64).sum();
+        assert_eq!(
+            OperationStats {
+                duration: Duration::ZERO,
+                ..stats
+            },
+            OperationStats {
+                // A pull from the mirror and upstream for the manifest and
+                // each layer, an upload session and a finish per layer and
+                // the manifest push.
+                requests: 2 + 2 * 2 + 2 * 2 + 1,
+                bytes_downloaded: manifest.len() as u64 + layers,
+                bytes_uploaded: layers + manifest.len() as u64,
+                cache_hits: 0,
+                retries: 3,
+                redirects: 0,
+                skipped_children: 0,
+                duration: Duration::ZERO,
+            }
+        );
+
+        // The manifest was requested by digest, so it is cached now.
+        let (response, stats) = measure(client.get_manifest(&source)).await;
+        assert!(matches!(
+            response.unwrap().manifest,
+            Manifest::Image(manifest::Image { .. })
+        ));
+        assert_eq!(
+            (stats.requests, stats.cache_hits, stats.bytes_downloaded),
+            (0, 1, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn nested_measurements_add_up() {
+        let manifest = manifest();
+        let client = Client::builder().transport(transport(&manifest)).build();
+        let source: Image = format!(
+            "registry.k8s.io/src@{}",
+            Digest::sha256(manifest.as_bytes())
+        )
+        .parse()
+        .unwrap();
+
+        let ((inner, second), outer) = measure(async {
+            client.get_manifest(&source).await.unwrap();
+            measure(Box::pin(client.get_manifest(&source))).await
+        })
+        .await;
+
+        inner.unwrap();
+        assert_eq!(second.requests, 1);
+        assert_eq!(outer.requests, 2);
+        assert_eq!(outer.bytes_downloaded, 2 * manifest.len() as u64);
+    }
+
+    #[test]
+    fn serialize() {
+        let stats = OperationStats {
+            requests: 3,
+            bytes_downloaded: 1024,
+            bytes_uploaded: 0,
+            cache_hits: 1,
+            retries: 0,
+            redirects: 1,
+            skipped_children: 0,
+            duration: Duration::from_millis(1500),
+        };
+
+        insta::assert_json_snapshot!(stats);
+    }
+}
@@ -0,0 +1,337 @@
+use futures::{
+    StreamExt,
+    TryStreamExt,
+};
+use semver::Version;
+
+use crate::{
+    docker::{
+        Client,
+        Error,
+    },
+    Digest,
+    Image,
+    Tag,
+};
+
+/// Upper bound for the tags of each kind that are resolved to a digest when
+/// looking for aliases.
+const MAX_ALIAS_CANDIDATES: usize = 32;
+
+/// Number of HEAD requests that are sent at the same time when resolving
+/// aliases.
+const ALIAS_CONCURRENCY: usize = 8;
+
+/// The tags of a repository grouped by release train, see
+/// [`Client::list_tags_grouped`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagGroups {
+    /// Release trains sorted newest first.
+    pub trains: Vec<ReleaseTrain>,
+
+    /// Tags that are not semantic versions, like `latest`, `main` or `2.53`.
+    pub other: Vec<Tag>,
+
+    /// Tags of `other` that point to the same manifest as other tags. Only
+    /// filled if aliases were resolved.
+    pub aliases: Vec<Alias>,
+}
+
+/// All versions that share their major and minor version, like `1.2.x`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseTrain {
+    pub major: u64,
+    pub minor: u64,
+
+    /// Versions of the train sorted newest first. Pre-releases sort before
+    /// the release they lead up to.
+    pub versions: Vec<VersionedTag>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionedTag {
+    pub version: Version,
+    pub tag: Tag,
+}
+
+/// A tag that currently points to the same manifest as the tags in
+/// `same_as`, for example `latest` pointing to the newest release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    pub tag: Tag,
+    pub digest: Digest,
+    pub same_as: Vec<Tag>,
+}
+
+impl TagGroups {
+    fn from_tags(tags: Vec<Tag>) -> Self {
+        let mut trains: Vec<ReleaseTrain> = Vec::new();
+        let mut other = Vec::new();
+
+        for tag in tags {
+            let Some(version) = parse_version(&tag) else {
+                other.push(tag);
+                continue;
+            };
+
+            let (major, minor) = (version.major, version.minor);
+            let versioned = VersionedTag { version, tag };
+
+            match trains
+                .iter_mut()
+                .find(|train| train.major == major && train.minor == minor)
+            {
+                Some(train) => train.versions.push(versioned),
+                None => trains.push(ReleaseTrain {
+                    major,
+                    minor,
+                    versions: vec![versioned],
+                }),
+            }
+        }
+
+        trains.sort_by_key(|train| std::cmp::Reverse((train.major, train.minor)));
+
+        for train in &mut trains {
+            train.versions.sort_by(|a, b| b.version.cmp(&a.version));
+        }
+
+        Self {
+            trains,
+            other,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn train(&self, major: u64, minor: u64) -> Option<&ReleaseTrain> {
+        self.trains
+            .iter()
+            .find(|train| train.major == major && train.minor == minor)
+    }
+
+    /// Returns the newest tag of the `major.minor` release train.
+    #[must_use]
+    pub fn newest(&self, major: u64, minor: u64) -> Option<&Tag> {
+        self.train(major, minor)?
+            .newest()
+            .map(|versioned| &versioned.tag)
+    }
+}
+
+impl ReleaseTrain {
+    #[must_use]
+    pub fn newest(&self) -> Option<&VersionedTag> {
+        self.versions.first()
+    }
+}
+
+impl Client {
+    /// Lists all tags of the repository of `image` grouped into semantic
+    /// versioning release trains. Tags with a `v` prefix like `v2.53.1` are
+    /// treated as versions as well.
+    ///
+    /// With `resolve_aliases` the tags that are not versions and the newest
+    /// version of each train are resolved to their digest with a HEAD request
+    /// each to find tags that are aliases of other tags. The number of
+    /// requests is bounded but still adds up, so it is opt-in.
+    ///
+    /// # Errors
+    /// Returns an error if the tags can not be listed, see
+    /// [`Client::list_tags`].
+    /// Returns an error if a tag can not be resolved to a digest.
+    pub async fn list_tags_grouped(
+        &self,
+        image: &Image,
+        resolve_aliases: bool,
+    ) -> Result<TagGroups, Error> {
+        let tags = self.list_tags(image).await?;
+
+        let mut groups = TagGroups::from_tags(tags);
+
+        if resolve_aliases {
+            groups.aliases = self.resolve_aliases(image, &groups).await?;
+        }
+
+        Ok(groups)
+    }
+
+    async fn resolve_aliases(
+        &self,
+        image: &Image,
+        groups: &TagGroups,
+    ) -> Result<Vec<Alias>, Error> {
+        let others = groups.other.iter().take(MAX_ALIAS_CANDIDATES);
+
+        let newest = groups
+            .trains
+            .iter()
+            .take(MAX_ALIAS_CANDIDATES)
+            .filter_map(ReleaseTrain::newest)
+            .map(|versioned| &versioned.tag);
+
+        let digests: Vec<(Tag, Digest)> = futures::stream::iter(others.chain(newest))
+            .map(|tag| async move {
+                let digest = self.tag_digest(image, tag).await?;
+
+                Ok(digest.map(|digest| (tag.clone(), digest)))
+            })
+            .buffered(ALIAS_CONCURRENCY)
+            .try_filter_map(|resolved| async move { Ok(resolved) })
+            .try_collect()
+            .await?;
+
+        let aliases = digests
+            .iter()
+            .filter(|(tag, _)| groups.other.contains(tag))
+            .filter_map(|(tag, digest)| {
+                let same_as: Vec<_> = digests
+                    .iter()
+                    .filter(|(other, other_digest)| {
+                        other != tag && other_digest.is_equivalent(digest)
+                    })
+                    .map(|(other, _)| other.clone())
+                    .collect();
+
+                (!same_as.is_empty()).then(|| Alias {
+                    tag: tag.clone(),
+                    digest: digest.clone(),
+                    same_as,
+                })
+            })
+            .collect();
+
+        Ok(aliases)
+    }
+}
+
+fn parse_version(tag: &Tag) -> Option<Version> {
+    let Tag::Specific(tag) = tag else {
+        return None;
+    };
+
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::{
+            tag_groups::Alias,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        Client,
+        Image,
+        Tag,
+    };
+
+    const TOKEN_URL: &str = "https://auth.docker.io/token?service=registry.docker.io&scope=repository:prom/prometheus:pull&service=registry.docker.io";
+    const TAGS_URL: &str = "https://registry-1.docker.io/v2/prom/prometheus/tags/list";
+
+    fn image() -> Image {
+        "prom/prometheus:latest".parse().unwrap()
+    }
+
+    fn tag(tag: &str) -> Tag {
+        tag.parse().unwrap()
+    }
+
+    fn transport() -> MockTransport {
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN_URL,
+                MockResponse::new(StatusCode::OK).body(r#"{"token":"dockerhub-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                TAGS_URL,
+                MockResponse::new(StatusCode::OK).body(include_str!(
+                    "../../resources/registry/dockerhub/prometheus-tags.json"
+                )),
+            )
+    }
+
+    fn with_head(transport: MockTransport, tag: &str, digest: &str) -> MockTransport {
+        transport.with_response(
+            Method::HEAD,
+            &format!("https://registry-1.docker.io/v2/prom/prometheus/manifests/{tag}"),
+            MockResponse::new(StatusCode::OK).header("Docker-Content-Digest", digest),
+        )
+    }
+
+    #[tokio::test]
+    async fn trains() {
+        let transport = transport();
+        let client = Client::builder().transport(transport.clone()).build();
+
+        let got = client.list_tags_grouped(&image(), false).await.unwrap();
+
+        let trains: Vec<_> = got
+            .trains
+            .iter()
+            .map(|train| {
+                let versions: Vec<_> = train.versions.iter().map(|v| v.tag.to_string()).collect();
+                format!("{}.{}: {}", train.major, train.minor, versions.join(" "))
+            })
+            .collect();
+
+        assert_eq!(
+            trains,
+            [
+                "3.0: v3.0.1 v3.0.0 v3.0.0-beta.0",
+                "2.54: v2.54.1 v2.54.0 v2.54.0-rc.0",
+                "2.53: v2.53.2 v2.53.1 v2.53.0",
+                "2.52: v2.52.0",
+            ]
+        );
+        assert_eq!(got.newest(2, 53), Some(&tag("v2.53.2")));
+        assert_eq!(got.other, vec![Tag::Latest, tag("main")]);
+        assert_eq!(got.aliases, Vec::new());
+
+        // Token and tag list only, no HEAD requests.
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn aliases() {
+        const NEWEST: &str = "sha256:3f1d2a";
+
+        let transport = [
+            ("latest", NEWEST),
+            ("main", "sha256:9c0e7b"),
+            ("v3.0.1", NEWEST),
+            ("v2.54.1", "sha256:54a1b2"),
+            ("v2.53.2", "sha256:53c4d5"),
+            ("v2.52.0", "sha256:52e6f7"),
+        ]
+        .into_iter()
+        .fold(transport(), |transport, (tag, digest)| {
+            with_head(transport, tag, digest)
+        });
+
+        let client = Client::builder().transport(transport.clone()).build();
+
+        let got = client.list_tags_grouped(&image(), true).await.unwrap();
+
+        assert_eq!(
+            got.aliases,
+            vec![Alias {
+                tag: Tag::Latest,
+                digest: NEWEST.parse().unwrap(),
+                same_as: vec![tag("v3.0.1")],
+            }]
+        );
+        assert_eq!(transport.requests().len(), 8);
+    }
+}
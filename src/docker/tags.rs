@@ -0,0 +1,718 @@
+use std::borrow::Cow;
+
+use either::Either;
+use futures::{
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use http::header::{
+    HeaderMap,
+    LINK,
+};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    docker::{
+        api::RegistryApi,
+        mirror::Endpoint,
+        quay,
+        Client,
+        Error,
+    },
+    Digest,
+    Image,
+    ImageName,
+    Tag,
+};
+
+/// The body of a tag list response. Tags are borrowed from the response body
+/// where possible so each tag is only allocated once.
+#[derive(Debug, Deserialize)]
+struct TagList<'a> {
+    #[serde(borrow, default)]
+    tags: Option<Vec<Cow<'a, str>>>,
+
+    /// Sent by Quay instead of a `Link` header, see [`quay::next_page`].
+    #[serde(borrow, default)]
+    next_page: Option<Cow<'a, str>>,
+}
+
+/// The page of a tag list to fetch next.
+enum Page {
+    First,
+    Next(Endpoint),
+    Done,
+}
+
+impl Client {
+    /// Lists all tags of the repository of `image`, following the pagination
+    /// of the registry.
+    ///
+    /// # Errors
+    /// Returns an error if the client is offline.
+    /// Returns an error if a request fails or the repository does not exist.
+    /// Returns an error if a page is not a valid tag list.
+    #[tracing::instrument(
+        name = "list_tags",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+        )
+    )]
+    pub async fn list_tags(&self, image: &Image) -> Result<Vec<Tag>, Error> {
+        self.list_tags_stream(image).try_collect().await
+    }
+
+    /// Same as [`Client::list_tags`] but yields the tags page by page. The
+    /// next page is only requested once all tags of the current page were
+    /// consumed so callers can stop early without fetching the whole list.
+    pub fn list_tags_stream<'a>(
+        &'a self,
+        image: &'a Image,
+    ) -> impl Stream<Item = Result<Tag, Error>> + Send + 'a {
+        futures::stream::try_unfold(Page::First, move |page| async move {
+            let (mirrors, last) = match page {
+                Page::First => {
+                    self.check_registry_policy(&image.registry)?;
+
+                    let segments = image.tags_segments().map_err(Error::InvalidPath)?;
+
+                    self.inner
+                        .mirrors
+                        .endpoints(image, &segments)
+                        .map_err(Error::InvalidTagsUrl)?
+                }
+
+                Page::Next(endpoint) => (Vec::new(), endpoint),
+
+                Page::Done => return Ok(None),
+            };
+
+            let (tags, next) = self.get_tags_page(image, mirrors, last).await?;
+
+            Ok(Some((tags, next.map_or(Page::Done, Page::Next))))
+        })
+        .map_ok(|tags| futures::stream::iter(tags.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Returns the tags of the repository of `image` that currently point to
+    /// `digest`, stopping once `limit` tags were found. Tags of signatures,
+    /// attestations and SBOMs pushed by cosign are skipped.
+    ///
+    /// The registry has no reverse index, so this lists the tags and sends a
+    /// HEAD request for every one of them. That is one request per tag plus
+    /// one per page of the tag list, which can take a while and count
+    /// against the rate limit of registries like Docker Hub for large
+    /// repositories. The HEAD requests count against
+    /// [`crate::ClientBuilder::concurrency_limit`].
+    ///
+    /// # Errors
+    /// Returns an error if the tags can not be listed or a HEAD request
+    /// fails.
+    #[tracing::instrument(
+        name = "tags_for_digest",
+        skip_all,
+        fields(
+            registry = %image.registry,
+            repository = %image.repository_path(),
+            digest = %digest,
+        )
+    )]
+    pub async fn tags_for_digest(
+        &self,
+        image: &Image,
+        digest: &Digest,
+        limit: Option<usize>,
+    ) -> Result<Vec<Tag>, Error> {
+        if limit == Some(0) {
+            return Ok(Vec::new());
+        }
+
+        self.list_tags_stream(image)
+            .try_filter(|tag| futures::future::ready(cosign_subject(tag).is_none()))
+            .map_ok(|tag| async move {
+                let _permit = self.concurrency_permit().await;
+                let current = self.tag_digest(image, &tag).await?;
+
+                Ok(current
+                    .is_some_and(|current| current.is_equivalent(digest))
+                    .then_some(tag))
+            })
+            .try_buffered(self.inner.concurrency_limit)
+            .try_filter_map(|tag| async move { Ok(tag) })
+            .take(limit.unwrap_or(usize::MAX))
+            .try_collect()
+            .await
+    }
+
+    /// Returns the digest `tag` of the repository of `image` currently points
+    /// to, or `None` if the tag does not exist.
+    pub(super) async fn tag_digest(
+        &self,
+        image: &Image,
+        tag: &Tag,
+    ) -> Result<Option<Digest>, Error> {
+        let tagged = Image {
+            image_name: ImageName::new(image.image_name.name.clone(), Either::Left(tag.clone())),
+            ..image.clone()
+        };
+
+        let segments = tagged.manifest_segments().map_err(Error::InvalidPath)?;
+
+        let (mirrors, last) = self
+            .inner
+            .mirrors
+            .endpoints(&tagged, &segments)
+            .map_err(Error::InvalidManifestUrl)?;
+
+        self.head_digest(&tagged, mirrors, last).await
+    }
+
+    /// Fetches a single page of a tag list and returns its tags together with
+    /// the endpoint of the next page if there is one.
+    async fn get_tags_page(
+        &self,
+        image: &Image,
+        mirrors: Vec<Endpoint>,
+        last: Endpoint,
+    ) -> Result<(Vec<Tag>, Option<Endpoint>), Error> {
+        let api = RegistryApi::for_client(self);
+
+        let (endpoint, response) = self
+            .send_request(
+                image,
+                mirrors,
+                last,
+                |url, auth| api.tags_request(url, auth),
+                Error::FailedTagsRequest,
+            )
+            .await?;
+
+        let rate_limit = self
+            .inner
+            .rate_limit_status
+            .observe(&image.registry, &response.headers);
+
+        if quay::is_missing_repository(&image.registry, response.status) {
+            return Err(Error::TagsNotFound(endpoint.url));
+        }
+
+        let response = self
+            .check_response(
+                &api,
+                response,
+                rate_limit.as_ref(),
+                Error::TagsNotFound,
+                Error::FailedTagsRequest,
+            )
+            .await?;
+
+        let headers = response.headers.clone();
+        let body = api.read_tags(response).await?;
+
+        let (tags, next) = parse_page(&headers, &endpoint.url, &body)?;
+
+        // The next page is served by the same endpoint with the same
+        // credentials.
+        let next = next.map(|url| Endpoint {
+            url,
+            authentication: endpoint.authentication.clone(),
+        });
+
+        Ok((tags, next))
+    }
+}
+
+/// Parses a page of a tag list fetched from `url` and returns its tags
+/// together with the URL of the next page if there is one.
+pub(super) fn parse_page(
+    headers: &HeaderMap,
+    url: &Url,
+    body: &[u8],
+) -> Result<(Vec<Tag>, Option<Url>), Error> {
+    let page: TagList<'_> = serde_json::from_slice(body).map_err(Error::DeserializeTags)?;
+
+    let next = next_link(headers, url).or_else(|| quay::next_page(page.next_page.as_deref()?, url));
+
+    let tags = page
+        .tags
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| tag.parse().unwrap_or_else(|e| match e {}))
+        .collect();
+
+    Ok((tags, next))
+}
+
+/// Returns the digest of the image a tag cosign uses to store signatures,
+/// attestations and SBOMs refers to, like `sha256-<hex>.sig` or
+/// `sha512-<hex>.sig`. Returns `None` for all other tags.
+pub(super) fn cosign_subject(tag: &Tag) -> Option<Digest> {
+    let Tag::Specific(tag) = tag else {
+        return None;
+    };
+
+    let (algorithm, rest) = tag.split_once('-')?;
+    let (hex, suffix) = rest.split_once('.')?;
+
+    let hex_len = match algorithm {
+        "sha256" => 64,
+        "sha512" => 128,
+        _ => return None,
+    };
+
+    let is_subject = hex.len() == hex_len
+        && hex.bytes().all(|byte| byte.is_ascii_hexdigit())
+        && matches!(suffix, "sig" | "att" | "sbom");
+
+    is_subject.then(|| {
+        format!("{algorithm}:{hex}")
+            .parse()
+            .unwrap_or_else(|e| match e {})
+    })
+}
+
+/// Returns the URL of the next page from a `Link: <url>; rel="next"` header.
+/// Relative URLs are resolved against the URL of the current page.
+fn next_link(headers: &HeaderMap, base: &Url) -> Option<Url> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let (target, params) = link.trim().split_once(';')?;
+
+            let is_next = params
+                .split(';')
+                .filter_map(|param| param.trim().split_once('='))
+                .any(|(name, value)| name.trim() == "rel" && value.trim_matches('"') == "next");
+
+            if !is_next {
+                return None;
+            }
+
+            let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+            base.join(target).ok()
+        })
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    mod next_link {
+        use http::header::{
+            HeaderMap,
+            LINK,
+        };
+        use pretty_assertions::assert_eq;
+        use url::Url;
+
+        use crate::docker::tags::next_link;
+
+        fn base() -> Url {
+            "https://registry.example.com/v2/library/alpine/tags/list"
+                .parse()
+                .unwrap()
+        }
+
+        #[test]
+        fn relative() {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                LINK,
+                r#"</v2/library/alpine/tags/list?last=3.19&n=100>; rel="next""#
+                    .parse()
+                    .unwrap(),
+            );
+
+            assert_eq!(
+                next_link(&headers, &base()).unwrap().as_str(),
+                "https://registry.example.com/v2/library/alpine/tags/list?last=3.19&n=100"
+            );
+        }
+
+        #[test]
+        fn ignores_other_relations() {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                LINK,
+                r#"<https://example.com/docs>; rel="help", <?last=b>; rel=next"#
+                    .parse()
+                    .unwrap(),
+            );
+
+            assert_eq!(
+                next_link(&headers, &base()).unwrap().as_str(),
+                "https://registry.example.com/v2/library/alpine/tags/list?last=b"
+            );
+        }
+
+        #[test]
+        fn missing() {
+            assert_eq!(next_link(&HeaderMap::new(), &base()), None);
+        }
+    }
+
+    mod cosign_subject {
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::tags::cosign_subject,
+            Digest,
+            Tag,
+        };
+
+        #[test]
+        fn algorithms() {
+            let sha256 = Digest::sha256(b"subject");
+            let sha512 = Digest::sha512(b"subject");
+
+            for (digest, suffix) in [(&sha256, "sig"), (&sha512, "att")] {
+                let tag: Tag = format!("{}.{suffix}", digest.to_string().replacen(':', "-", 1))
+                    .parse()
+                    .unwrap();
+
+                assert_eq!(cosign_subject(&tag).as_ref(), Some(digest));
+            }
+
+            for tag in ["sha512-abc.sig", "blake3-0123.sig", "v1.2.3", "sha256-.sig"] {
+                assert_eq!(cosign_subject(&tag.parse().unwrap()), None, "{tag}");
+            }
+        }
+    }
+
+    mod list_tags {
+        use futures::{
+            StreamExt,
+            TryStreamExt,
+        };
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Image,
+            Tag,
+        };
+
+        const FIRST_PAGE: &str = "https://registry.k8s.io/v2/pause/tags/list";
+        const SECOND_PAGE: &str = "https://registry.k8s.io/v2/pause/tags/list?last=3.1&n=2";
+
+        fn image() -> Image {
+            "registry.k8s.io/pause:3.9".parse().unwrap()
+        }
+
+        fn transport() -> MockTransport {
+            MockTransport::new()
+                .with_response(
+                    Method::GET,
+                    FIRST_PAGE,
+                    MockResponse::new(StatusCode::OK)
+                        .header("Link", r#"</v2/pause/tags/list?last=3.1&n=2>; rel="next""#)
+                        .body(r#"{"name":"pause","tags":["3.0","3.1"]}"#),
+                )
+                .with_response(
+                    Method::GET,
+                    SECOND_PAGE,
+                    MockResponse::new(StatusCode::OK)
+                        .body(r#"{"name":"pause","tags":["3.9","latest"]}"#),
+                )
+        }
+
+        #[tokio::test]
+        async fn follows_pages() {
+            let client = Client::builder().transport(transport()).build();
+
+            let got = client.list_tags(&image()).await.unwrap();
+
+            assert_eq!(
+                got,
+                vec![
+                    Tag::Specific("3.0".into()),
+                    Tag::Specific("3.1".into()),
+                    Tag::Specific("3.9".into()),
+                    Tag::Latest,
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn stops_early() {
+            let transport = transport();
+            let client = Client::builder().transport(transport.clone()).build();
+            let image = image();
+
+            let got: Vec<Tag> = client
+                .list_tags_stream(&image)
+                .take(2)
+                .try_collect()
+                .await
+                .unwrap();
+
+            assert_eq!(got.len(), 2);
+            assert_eq!(transport.requests().len(), 1);
+        }
+
+        #[tokio::test]
+        async fn repository_without_tags() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                FIRST_PAGE,
+                MockResponse::new(StatusCode::OK).body(r#"{"name":"pause","tags":null}"#),
+            );
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.list_tags(&image()).await.unwrap();
+
+            assert_eq!(got, Vec::new());
+        }
+
+        #[tokio::test]
+        async fn not_found() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                FIRST_PAGE,
+                MockResponse::new(StatusCode::NOT_FOUND),
+            );
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.list_tags(&image()).await.unwrap_err();
+
+            assert!(matches!(got, ClientError::TagsNotFound(url) if url.as_str() == FIRST_PAGE));
+        }
+    }
+
+    mod quay {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            ClientError,
+            Image,
+            Tag,
+        };
+
+        const TOKEN_URL: &str =
+            "https://quay.io/v2/auth?scope=repository:prometheus/prometheus:pull&service=quay.io";
+        const FIRST_PAGE: &str = "https://quay.io/v2/prometheus/prometheus/tags/list";
+
+        fn image() -> Image {
+            "quay.io/prometheus/prometheus:v2.53.2".parse().unwrap()
+        }
+
+        fn token() -> MockResponse {
+            MockResponse::new(StatusCode::OK).body(r#"{"token":"quay-token"}"#)
+        }
+
+        #[tokio::test]
+        async fn follows_next_page_in_body() {
+            let transport = MockTransport::new()
+                .with_response(Method::GET, TOKEN_URL, token())
+                .with_response(
+                    Method::GET,
+                    FIRST_PAGE,
+                    MockResponse::new(StatusCode::OK).body(include_str!(
+                        "../../resources/registry/quay/tags-page-1.json"
+                    )),
+                )
+                .with_response(
+                    Method::GET,
+                    &format!("{FIRST_PAGE}?next_page=djIuNTMuMA"),
+                    MockResponse::new(StatusCode::OK).body(include_str!(
+                        "../../resources/registry/quay/tags-page-2.json"
+                    )),
+                );
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.list_tags(&image()).await.unwrap();
+
+            assert_eq!(
+                got,
+                ["v2.52.0", "v2.53.0", "v2.53.1", "v2.53.2"]
+                    .map(|tag| Tag::Specific(tag.into()))
+                    .to_vec()
+            );
+        }
+
+        #[tokio::test]
+        async fn missing_repository_on_token_request() {
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                TOKEN_URL,
+                MockResponse::new(StatusCode::BAD_REQUEST).body(include_str!(
+                    "../../resources/registry/quay/error-missing-repository.json"
+                )),
+            );
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.list_tags(&image()).await.unwrap_err();
+
+            assert!(got.is_not_found(), "{got:?}");
+        }
+
+        #[tokio::test]
+        async fn missing_repository_on_request() {
+            let transport = MockTransport::new()
+                .with_response(Method::GET, TOKEN_URL, token())
+                .with_response(
+                    Method::GET,
+                    FIRST_PAGE,
+                    MockResponse::new(StatusCode::BAD_REQUEST).body(include_str!(
+                        "../../resources/registry/quay/error-missing-repository.json"
+                    )),
+                )
+                .with_response(
+                    Method::GET,
+                    "https://quay.io/v2/prometheus/prometheus/manifests/v2.53.2",
+                    MockResponse::new(StatusCode::BAD_REQUEST),
+                );
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.list_tags(&image()).await.unwrap_err();
+            assert!(matches!(got, ClientError::TagsNotFound(_)), "{got:?}");
+
+            let got = client.get_manifest(&image()).await.unwrap_err();
+            assert!(matches!(got, ClientError::ManifestNotFound(_)), "{got:?}");
+        }
+
+        #[tokio::test]
+        async fn auth_error_code() {
+            let transport = MockTransport::new()
+                .with_response(Method::GET, TOKEN_URL, token())
+                .with_response(
+                    Method::GET,
+                    FIRST_PAGE,
+                    MockResponse::new(StatusCode::UNAUTHORIZED).body(include_str!(
+                        "../../resources/registry/quay/error-invalid-token.json"
+                    )),
+                );
+            let client = Client::builder().transport(transport).build();
+
+            let got = client.list_tags(&image()).await.unwrap_err();
+
+            let ClientError::FailedTagsRequest(response) = got else {
+                panic!("expected a failed request, got {got:?}");
+            };
+            assert_eq!(
+                response.error_code(),
+                Some(crate::docker::ErrorCode::Unauthorized)
+            );
+        }
+    }
+
+    mod tags_for_digest {
+        use http::{
+            Method,
+            StatusCode,
+        };
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::transport::{
+                MockResponse,
+                MockTransport,
+            },
+            Client,
+            Digest,
+            Image,
+            Tag,
+        };
+
+        const TAGS_URL: &str = "https://registry.k8s.io/v2/pause/tags/list";
+        const SIGNATURE: &str =
+            "sha256-7031c1b283388d2c2e09b57badb803c05ebed362dc88d84b480cc47f72a21097.sig";
+
+        fn digest(tag: usize) -> Digest {
+            if tag == 5 || tag == 17 {
+                Digest::sha256(b"match")
+            } else {
+                Digest::sha256(tag.to_string().as_bytes())
+            }
+        }
+
+        /// Serves 20 tags named `1` to `20` of which `5` and `17` point to
+        /// the digest that is looked for, plus a cosign signature tag.
+        fn transport() -> MockTransport {
+            let tags: Vec<String> = (1..=20)
+                .map(|tag| tag.to_string())
+                .chain([SIGNATURE.to_string()])
+                .collect();
+
+            let transport = MockTransport::new().with_response(
+                Method::GET,
+                TAGS_URL,
+                MockResponse::new(StatusCode::OK)
+                    .body(serde_json::json!({ "name": "pause", "tags": tags }).to_string()),
+            );
+
+            (1..=20).fold(transport, |transport, tag| {
+                transport.with_response(
+                    Method::HEAD,
+                    &format!("https://registry.k8s.io/v2/pause/manifests/{tag}"),
+                    MockResponse::new(StatusCode::OK)
+                        .header("Docker-Content-Digest", &digest(tag).to_string()),
+                )
+            })
+        }
+
+        fn image() -> Image {
+            "registry.k8s.io/pause:3.9".parse().unwrap()
+        }
+
+        #[tokio::test]
+        async fn all_matches() {
+            let transport = transport();
+            let client = Client::builder().transport(transport.clone()).build();
+
+            let got = client
+                .tags_for_digest(&image(), &digest(5), None)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                got,
+                vec![Tag::Specific("5".into()), Tag::Specific("17".into())]
+            );
+
+            // One tag list request and one HEAD request per tag, the
+            // signature tag is skipped.
+            assert_eq!(transport.requests().len(), 21);
+        }
+
+        #[tokio::test]
+        async fn stops_at_limit() {
+            let transport = transport();
+            let client = Client::builder().transport(transport.clone()).build();
+
+            let got = client
+                .tags_for_digest(&image(), &digest(5), Some(1))
+                .await
+                .unwrap();
+
+            assert_eq!(got, vec![Tag::Specific("5".into())]);
+            assert!(transport.requests().len() < 21);
+        }
+    }
+}
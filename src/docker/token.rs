@@ -1,49 +1,329 @@
+use base64::Engine as _;
 use chrono::{
     DateTime,
     Utc,
 };
-use reqwest::header::HeaderMap;
+use std::{
+    borrow::Cow,
+    sync::Arc,
+};
+
+use http::header::HeaderMap;
 use serde::{
     Deserialize,
     Serialize,
 };
+use tracing::warn;
+use url::Url;
 
-use crate::Image;
+use crate::{
+    docker::auth::{
+        self,
+        Action,
+        TokenRequest,
+    },
+    image::repository::Repository,
+    Image,
+    Registry,
+};
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+/// Tokens are cached per repository and the actions they grant, the tag or
+/// digest of an image does not matter.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub(super) struct CacheKey {
-    image: Image,
+    registry: Registry,
+    repository: Option<Repository>,
+    image_name: Arc<str>,
+
+    /// Tokens saved by earlier versions were all requested for pulling.
+    #[serde(default = "pull")]
+    actions: Vec<Action>,
 }
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[derive(Default, Clone, Deserialize, Serialize)]
 pub(super) struct Token {
     #[serde(rename = "token")]
     pub(super) value: String,
     pub(super) expires_in: Option<i64>,
     pub(super) issued_at: Option<DateTime<Utc>>,
+
+    /// When the client received the token, by the local clock. Tokens cached
+    /// by earlier versions do not have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) received_at: Option<DateTime<Utc>>,
+
+    /// The `service` the token was requested for, if the request named one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) service: Option<String>,
 }
 
-impl std::fmt::Display for CacheKey {
+/// The claims of a token that is a JWT, see [`Token::claims`]. Only what
+/// helps to debug authentication failures is kept, the signature is not
+/// verified.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Claims {
+    /// The `aud` claim, token servers send a single string or a list.
+    #[serde(default, rename = "aud", deserialize_with = "one_or_many")]
+    pub audience: Vec<String>,
+
+    /// The `exp` claim.
+    #[serde(default, rename = "exp", with = "chrono::serde::ts_seconds_option")]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// What the token grants, Docker Hub and the distribution token server
+    /// send it.
+    #[serde(default)]
+    pub access: Vec<Access>,
+}
+
+/// An entry of the `access` claim of a token.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Access {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+
+    #[serde(default)]
+    pub actions: Vec<String>,
+}
+
+/// A token whose `aud` claim does not name the service it was requested for.
+/// Registries reject such tokens, usually because a mirror or a misconfigured
+/// token server minted it for another registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudienceMismatch {
+    /// The `service` of the token request.
+    pub expected: String,
+
+    /// The `aud` claim of the token.
+    pub audience: Vec<String>,
+}
+
+impl std::fmt::Debug for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let registry = self.image.registry.to_string();
-        let namespace = self.image.namespace.as_ref();
-        let repository = self.image.repository.as_ref();
-        let image_name = &self.image.image_name.name;
+        f.debug_struct("Token")
+            .field("value", &"[REDACTED]")
+            .field("expires_in", &self.expires_in)
+            .field("issued_at", &self.issued_at)
+            .field("received_at", &self.received_at)
+            .field("service", &self.service)
+            .finish()
+    }
+}
 
-        write!(f, "{registry}{namespace:?}{repository:?}{image_name}")
+impl std::fmt::Display for AudienceMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "token was issued for audience {} instead of service {}",
+            self.audience.join(", "),
+            self.expected
+        )
+    }
+}
+
+impl std::fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}:{}",
+            self.registry,
+            self.path(),
+            auth::join_actions(&self.actions)
+        )
     }
 }
 
 impl From<&Image> for CacheKey {
     fn from(image: &Image) -> Self {
+        Self::new(image, &[Action::Pull])
+    }
+}
+
+impl CacheKey {
+    /// The key of a token granting `actions` on the repository of `image`.
+    pub(super) fn new(image: &Image, actions: &[Action]) -> Self {
         Self {
-            image: image.clone(),
+            registry: image.registry.clone(),
+            repository: image.api_repository().map(Cow::into_owned),
+            image_name: image.image_name.name.clone(),
+            actions: actions.to_vec(),
+        }
+    }
+
+    /// The scope granting the actions of the key on the repository.
+    fn scope(&self) -> String {
+        auth::repository_scope(&self.path(), &self.actions)
+    }
+
+    fn path(&self) -> String {
+        crate::image::repository_path(self.repository.as_ref(), &self.image_name)
+    }
+}
+
+impl Token {
+    /// Records that the token was received just now, its lifetime counts from
+    /// here on. Logs a warning if the `issued_at` of the token server is more
+    /// than `clock_skew` away from the local clock.
+    pub(super) fn received(mut self, clock_skew: chrono::Duration) -> Self {
+        let now = Utc::now();
+
+        if let Some(issued_at) = self.issued_at {
+            let skew = issued_at - now;
+
+            if skew.abs() > clock_skew {
+                warn!(
+                    skew_seconds = skew.num_seconds(),
+                    "clock of the token server differs from the local clock"
+                );
+            }
+        }
+
+        self.received_at = Some(now);
+        self
+    }
+
+    /// Records the `service` parameter of `url`, the token request the token
+    /// was received for. Logs a warning if the token is for another
+    /// audience, see [`Token::audience_mismatch`]. The token is used anyway
+    /// as token servers with unusual audiences exist.
+    pub(super) fn requested_from(mut self, url: &Url) -> Self {
+        self.service = url
+            .query_pairs()
+            .find(|(name, _)| name == "service")
+            .map(|(_, service)| service.into_owned());
+
+        if let Some(mismatch) = self.audience_mismatch() {
+            warn!(
+                expected = %mismatch.expected,
+                audience = ?mismatch.audience,
+                "token was issued for another audience than the requested service"
+            );
         }
+
+        self
+    }
+
+    /// The claims of the token, `None` if it is not a JWT. Opaque tokens
+    /// like the ones of GitHub have none.
+    pub(super) fn claims(&self) -> Option<Claims> {
+        let mut parts = self.value.split('.');
+        let (_, payload, _) = (parts.next()?, parts.next()?, parts.next()?);
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .ok()?;
+
+        serde_json::from_slice(&payload).ok()
+    }
+
+    /// Returns the mismatch if the token is a JWT with an `aud` claim that
+    /// does not contain the service it was requested for. Tokens without
+    /// claims, audience or service never mismatch.
+    pub(super) fn audience_mismatch(&self) -> Option<AudienceMismatch> {
+        let expected = self.service.as_ref()?;
+        let audience = self.claims()?.audience;
+
+        if audience.is_empty() || audience.contains(expected) {
+            return None;
+        }
+
+        Some(AudienceMismatch {
+            expected: expected.clone(),
+            audience,
+        })
+    }
+
+    /// When the token expires by the local clock, `None` if it never expires.
+    ///
+    /// The lifetime counts from [`Token::received_at`]. Tokens cached before
+    /// that was recorded fall back to the `issued_at` of the token server,
+    /// which is assumed to be off by up to `clock_skew`: they expire
+    /// `clock_skew` early and are expired right away if they were issued
+    /// more than `clock_skew` in the future. Tokens with a lifetime but
+    /// neither time are expired as there is no way to tell if they still are
+    /// valid.
+    pub(super) fn expires_at(&self, clock_skew: chrono::Duration) -> Option<DateTime<Utc>> {
+        let lifetime =
+            chrono::Duration::try_seconds(self.expires_in?).unwrap_or(chrono::Duration::MAX);
+
+        let expires_at = match (self.received_at, self.issued_at) {
+            (Some(received_at), _) => received_at.checked_add_signed(lifetime),
+
+            (None, Some(issued_at)) if issued_at <= Utc::now() + clock_skew => {
+                issued_at.checked_add_signed(lifetime - clock_skew)
+            }
+
+            (None, _) => Some(DateTime::<Utc>::MIN_UTC),
+        };
+
+        Some(expires_at.unwrap_or(DateTime::<Utc>::MAX_UTC))
+    }
+
+    /// Returns true if the token expired before `now`, see
+    /// [`Token::expires_at`].
+    pub(super) fn is_expired(&self, now: DateTime<Utc>, clock_skew: chrono::Duration) -> bool {
+        self.expires_at(clock_skew)
+            .is_some_and(|expires_at| expires_at < now)
+    }
+
+    /// Returns true if the token expires within `margin` from now. Tokens
+    /// without an expiry never expire.
+    pub(super) fn expires_within(
+        &self,
+        margin: chrono::Duration,
+        clock_skew: chrono::Duration,
+    ) -> bool {
+        self.is_expired(Utc::now() + margin, clock_skew)
     }
 }
 
+/// Builds the token request for pulling from one or more repositories of
+/// `registry`. Every repository becomes its own `scope` parameter so a single
+/// token covers all of them, repositories that repeat are only added once.
+/// Returns `None` if the registry does not need authentication.
+pub(super) fn request<'a>(
+    registry: &Registry,
+    keys: impl IntoIterator<Item = &'a CacheKey>,
+) -> Option<TokenRequest> {
+    let mut scopes: Vec<String> = Vec::new();
+
+    for scope in keys.into_iter().map(CacheKey::scope) {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    TokenRequest::new(registry, &scopes, None)
+}
+
+fn pull() -> Vec<Action> {
+    vec![Action::Pull]
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(audience) => vec![audience],
+        OneOrMany::Many(audience) => audience,
+    })
+}
+
 impl TryInto<HeaderMap> for Token {
-    type Error = reqwest::header::InvalidHeaderValue;
+    type Error = http::header::InvalidHeaderValue;
 
     fn try_into(self) -> Result<HeaderMap, Self::Error> {
         let mut headers = HeaderMap::new();
@@ -56,6 +336,80 @@ impl TryInto<HeaderMap> for Token {
 #[cfg(test)]
 #[expect(clippy::unwrap_used, reason = "using unwrap for tests is fine")]
 mod tests {
+    mod token_request {
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            docker::{
+                auth::Action,
+                token::{
+                    request,
+                    CacheKey,
+                },
+            },
+            Image,
+            Registry,
+        };
+
+        fn url(registry: &Registry, images: &[&str]) -> Option<String> {
+            let keys: Vec<CacheKey> = images
+                .iter()
+                .map(|image| (&image.parse::<Image>().unwrap()).into())
+                .collect();
+
+            request(registry, &keys).map(|request| request.url().unwrap().to_string())
+        }
+
+        #[test]
+        fn single_scope() {
+            assert_eq!(
+                url(&Registry::Github, &["ghcr.io/sigstore/cosign/cosign:v2.4.0"]).unwrap(),
+                "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io"
+            );
+        }
+
+        #[test]
+        fn two_scopes() {
+            let got = url(
+                &Registry::DockerHub,
+                &[
+                    "alpine:3.20",
+                    "docker.io/grafana/grafana:11.2.0",
+                    "alpine:3.19",
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(
+                got.split_once('?').unwrap().1,
+                "service=registry.docker.io&scope=repository:library/alpine:pull&scope=repository:\
+                 grafana/grafana:pull&service=registry.docker.io"
+            );
+        }
+
+        #[test]
+        fn no_authentication() {
+            assert_eq!(url(&Registry::K8s, &["registry.k8s.io/pause:3.9"]), None);
+        }
+
+        #[test]
+        fn push_scope() {
+            let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+            let push = CacheKey::new(&image, &[Action::Pull, Action::Push]);
+
+            assert_ne!(push, CacheKey::from(&image));
+            assert_eq!(
+                request(&Registry::Github, [&push])
+                    .unwrap()
+                    .url()
+                    .unwrap()
+                    .as_str(),
+                "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull,push&\
+                 service=ghcr.io"
+            );
+        }
+    }
+
     mod token {
         mod deserialize {
             use crate::docker::Token;
@@ -77,5 +431,192 @@ mod tests {
                 insta::assert_json_snapshot!(got);
             }
         }
+
+        mod expiry {
+            use chrono::{
+                Duration,
+                Utc,
+            };
+
+            use crate::docker::Token;
+
+            fn token(issued_at: Option<chrono::DateTime<Utc>>) -> Token {
+                Token {
+                    value: "token".to_string(),
+                    expires_in: Some(300),
+                    issued_at,
+                    received_at: None,
+                    service: None,
+                }
+            }
+
+            /// The clock of the token server is 5 minutes ahead or behind.
+            #[test]
+            fn received_ignores_server_clock() {
+                for skew in [Duration::minutes(5), Duration::minutes(-5)] {
+                    let token = token(Some(Utc::now() + skew)).received(Duration::minutes(1));
+                    let received_at = token.received_at.unwrap();
+
+                    assert_eq!(
+                        token.expires_at(Duration::minutes(1)),
+                        Some(received_at + Duration::seconds(300)),
+                        "{skew}"
+                    );
+                    assert!(!token.expires_within(Duration::seconds(30), Duration::minutes(1)));
+                    assert!(
+                        token.is_expired(received_at + Duration::seconds(301), Duration::zero())
+                    );
+                }
+            }
+
+            #[test]
+            fn legacy_within_skew() {
+                let now = Utc::now();
+                let skew = Duration::minutes(5);
+
+                // Issued 5 minutes in the future by the clock of the server,
+                // it was received just now and expires in 5 minutes.
+                let ahead = token(Some(now + skew));
+                assert_eq!(ahead.expires_at(skew), Some(now + Duration::seconds(300)));
+                assert!(!ahead.is_expired(now, skew));
+
+                // Issued 5 minutes ago, the server clock might be behind so
+                // the token is not trusted any longer.
+                let behind = token(Some(now - skew));
+                assert!(behind.is_expired(now, skew));
+                assert!(!behind.is_expired(now, Duration::zero()));
+            }
+
+            #[test]
+            fn legacy_beyond_skew() {
+                let now = Utc::now();
+
+                let token = token(Some(now + Duration::minutes(10)));
+
+                assert!(token.is_expired(now, Duration::minutes(5)));
+                assert!(!token.is_expired(now, Duration::minutes(15)));
+            }
+
+            #[test]
+            fn without_times() {
+                assert!(token(None).is_expired(Utc::now(), Duration::minutes(5)));
+
+                let forever = Token {
+                    expires_in: None,
+                    ..token(None)
+                };
+                assert_eq!(forever.expires_at(Duration::minutes(5)), None);
+                assert!(!forever.expires_within(Duration::days(365), Duration::minutes(5)));
+            }
+
+            #[test]
+            fn deserialize_without_received_at() {
+                let got: Token =
+                    serde_json::from_str(r#"{"token":"token","expires_in":300}"#).unwrap();
+                assert_eq!(got.received_at, None);
+
+                let serialized = serde_json::to_value(&got).unwrap();
+                assert!(serialized.get("received_at").is_none());
+            }
+        }
+
+        mod claims {
+            use base64::Engine as _;
+            use chrono::DateTime;
+            use pretty_assertions::assert_eq;
+            use url::Url;
+
+            use crate::docker::{
+                token::{
+                    Access,
+                    AudienceMismatch,
+                    Claims,
+                },
+                Token,
+            };
+
+            const URL: &str = "https://auth.docker.io/token?service=registry.docker.io&scope=repository:library/alpine:pull&service=registry.docker.io";
+
+            fn jwt(claims: &serde_json::Value) -> Token {
+                let claims =
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(claims.to_string());
+
+                Token {
+                    value: format!("eyJhbGciOiJub25lIn0.{claims}.c2lnbmF0dXJl"),
+                    ..Token::default()
+                }
+                .requested_from(&Url::parse(URL).unwrap())
+            }
+
+            #[test]
+            fn matching_audience() {
+                let token = jwt(&serde_json::json!({
+                    "aud": "registry.docker.io",
+                    "exp": 1_725_437_201,
+                    "access": [{
+                        "type": "repository",
+                        "name": "library/alpine",
+                        "actions": ["pull"],
+                    }],
+                }));
+
+                assert_eq!(
+                    token.claims().unwrap(),
+                    Claims {
+                        audience: vec!["registry.docker.io".to_string()],
+                        expires_at: DateTime::from_timestamp(1_725_437_201, 0),
+                        access: vec![Access {
+                            kind: "repository".to_string(),
+                            name: "library/alpine".to_string(),
+                            actions: vec!["pull".to_string()],
+                        }],
+                    }
+                );
+                assert_eq!(token.audience_mismatch(), None);
+
+                let listed = jwt(&serde_json::json!({ "aud": ["other", "registry.docker.io"] }));
+                assert_eq!(listed.audience_mismatch(), None);
+            }
+
+            #[test]
+            fn mismatch() {
+                let token = jwt(&serde_json::json!({ "aud": ["mirror.example.com"] }));
+
+                assert_eq!(
+                    token.audience_mismatch(),
+                    Some(AudienceMismatch {
+                        expected: "registry.docker.io".to_string(),
+                        audience: vec!["mirror.example.com".to_string()],
+                    })
+                );
+            }
+
+            /// Tokens without an audience are nonstandard but not rejected.
+            #[test]
+            fn without_audience() {
+                let token = jwt(&serde_json::json!({ "sub": "" }));
+
+                assert_eq!(token.claims().unwrap(), Claims::default());
+                assert_eq!(token.audience_mismatch(), None);
+            }
+
+            #[test]
+            fn not_a_jwt() {
+                for value in [
+                    "djE6c2lnc3RvcmUvY29zaWduL2Nvc2lnbjoxNzI1NDM2OTAwNTczODMyMzM2",
+                    "a.b.c",
+                    "a.e30.c.d",
+                ] {
+                    let token = Token {
+                        value: value.to_string(),
+                        service: Some("ghcr.io".to_string()),
+                        ..Token::default()
+                    };
+
+                    assert_eq!(token.claims(), None, "{value}");
+                    assert_eq!(token.audience_mismatch(), None, "{value}");
+                }
+            }
+        }
     }
 }
@@ -1,18 +1,41 @@
 use std::{
     collections::HashMap,
-    sync::Arc,
+    io::Write,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Arc,
+    },
 };
 
-use chrono::Utc;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
 use tokio::sync::RwLock;
+use tracing::warn;
+#[cfg(feature = "redis_cache")]
 use tracing::{
     info_span,
     Instrument,
 };
 
-use crate::docker::token::{
-    CacheKey,
-    Token,
+use crate::docker::{
+    token::{
+        CacheKey,
+        Token,
+    },
+    DEFAULT_TOKEN_CLOCK_SKEW,
 };
 
 #[cfg(feature = "redis_cache")]
@@ -21,22 +44,39 @@ use redis::AsyncCommands;
 #[cfg(feature = "redis_cache")]
 const REDIS_PREFIX: &str = "docker-registry-client:token";
 
+/// Expired tokens are removed from a [`MemoryTokenCache`] every this many
+/// stores.
+const SWEEP_INTERVAL: usize = 64;
+
 #[derive(Debug)]
 pub enum FetchError {
+    #[cfg(feature = "redis_cache")]
     CheckExists(redis::RedisError),
     DeserializeToken(serde_json::Error),
+    #[cfg(feature = "redis_cache")]
     GetConnection(redis::RedisError),
+    #[cfg(feature = "redis_cache")]
     GetValue(redis::RedisError),
 }
 
 #[derive(Debug)]
 pub enum StoreError {
+    #[cfg(feature = "redis_cache")]
     GetConnection(redis::RedisError),
     SerializeToken(serde_json::Error),
+    #[cfg(feature = "redis_cache")]
     SetExpiration(redis::RedisError),
+    #[cfg(feature = "redis_cache")]
     SetValue(redis::RedisError),
 }
 
+#[derive(Debug)]
+pub enum PersistError {
+    Serialize(serde_json::Error),
+    Write(PathBuf, std::io::Error),
+    Rename(PathBuf, std::io::Error),
+}
+
 #[async_trait::async_trait]
 pub(super) trait Cache: std::fmt::Debug + Send + Sync + dyn_clone::DynClone {
     async fn fetch(&self, key: &CacheKey) -> Result<Option<Token>, FetchError>;
@@ -49,10 +89,35 @@ dyn_clone::clone_trait_object!(Cache);
 #[derive(Debug, Default, Clone)]
 pub(super) struct NoCache;
 
-/// `MemoryTokenCache` is a token cache that caches tokens in memory.
+/// `MemoryTokenCache` is a token cache that caches tokens in memory. Expired
+/// tokens are removed periodically while storing so repositories that are
+/// not requested again do not stay in memory forever.
 #[derive(Debug, Default, Clone)]
 pub(super) struct MemoryTokenCache {
     cache: Arc<RwLock<HashMap<CacheKey, Token>>>,
+    stores: Arc<AtomicUsize>,
+    max_entries: Option<usize>,
+}
+
+/// A file a [`MemoryTokenCache`] was loaded from, see
+/// [`crate::ClientBuilder::token_cache_file`]. The cache is saved back to it
+/// when the last client using it is dropped.
+#[derive(Debug)]
+pub(super) struct TokenFile {
+    path: PathBuf,
+    cache: MemoryTokenCache,
+}
+
+/// The file format of a saved [`MemoryTokenCache`].
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Saved {
+    tokens: Vec<SavedToken>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SavedToken {
+    key: CacheKey,
+    token: Token,
 }
 
 #[cfg(feature = "redis_cache")]
@@ -65,9 +130,12 @@ pub(super) struct RedisCache {
 impl std::fmt::Display for FetchError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            #[cfg(feature = "redis_cache")]
             Self::CheckExists(e) => write!(f, "failed to check if key exists: {e}"),
             Self::DeserializeToken(e) => write!(f, "failed to deserialize token: {e}"),
+            #[cfg(feature = "redis_cache")]
             Self::GetConnection(e) => write!(f, "failed to get redis connection: {e}"),
+            #[cfg(feature = "redis_cache")]
             Self::GetValue(e) => write!(f, "failed to get value from redis: {e}"),
         }
     }
@@ -78,9 +146,12 @@ impl std::error::Error for FetchError {}
 impl std::fmt::Display for StoreError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            #[cfg(feature = "redis_cache")]
             Self::GetConnection(e) => write!(f, "failed to get redis connection: {e}"),
             Self::SerializeToken(e) => write!(f, "failed to serialize token: {e}"),
+            #[cfg(feature = "redis_cache")]
             Self::SetExpiration(e) => write!(f, "failed to set expiration: {e}"),
+            #[cfg(feature = "redis_cache")]
             Self::SetValue(e) => write!(f, "failed to set value in redis: {e}"),
         }
     }
@@ -88,6 +159,18 @@ impl std::fmt::Display for StoreError {
 
 impl std::error::Error for StoreError {}
 
+impl std::fmt::Display for PersistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Serialize(e) => write!(f, "failed to serialize tokens: {e}"),
+            Self::Write(path, e) => write!(f, "failed to write {}: {e}", path.display()),
+            Self::Rename(path, e) => write!(f, "failed to rename {}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
 #[async_trait::async_trait]
 impl Cache for NoCache {
     async fn fetch(&self, _key: &CacheKey) -> Result<Option<Token>, FetchError> {
@@ -99,38 +182,230 @@ impl Cache for NoCache {
     }
 }
 
+impl MemoryTokenCache {
+    /// Keeps at most `max_entries` tokens. When the limit is exceeded the
+    /// tokens that expire first are removed.
+    pub(super) fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            max_entries: Some(max_entries.max(1)),
+            ..Self::default()
+        }
+    }
+
+    /// Loads the tokens saved with [`MemoryTokenCache::save_to`] from `path`.
+    /// Expired tokens are skipped. A missing file is an empty cache, so is a
+    /// file that can not be read or parsed after logging a warning.
+    pub(super) fn load_from(path: &Path) -> Self {
+        let saved = match std::fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                warn!(path = %path.display(), error = %e, "ignoring corrupted token cache file");
+                Saved::default()
+            }),
+
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Saved::default(),
+
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "failed to read token cache file");
+                Saved::default()
+            }
+        };
+
+        let now = Utc::now();
+        let tokens = saved
+            .tokens
+            .into_iter()
+            .filter(|saved| !is_expired(&saved.token, now))
+            .map(|saved| (saved.key, saved.token))
+            .collect();
+
+        Self {
+            cache: Arc::new(RwLock::new(tokens)),
+            ..Self::default()
+        }
+    }
+
+    /// Saves the tokens that did not expire yet to `path` as JSON. The file
+    /// is only readable by the current user and replaced atomically.
+    ///
+    /// # Errors
+    /// Returns an error if the tokens can not be written.
+    pub(super) async fn save_to(&self, path: &Path) -> Result<(), PersistError> {
+        save(&*self.cache.read().await, path)
+    }
+
+    /// Removes expired tokens and, if the cache is over its limit, the tokens
+    /// that expire first. The keys are collected under the read lock so
+    /// fetches are only blocked while the keys are removed.
+    async fn sweep(&self) {
+        let now = Utc::now();
+
+        let (expired, evict) = {
+            let cache = self.cache.read().await;
+
+            let mut live: Vec<(&CacheKey, Option<DateTime<Utc>>)> = Vec::new();
+            let mut expired = Vec::new();
+            let mut evict = Vec::new();
+
+            for (key, token) in cache.iter() {
+                if is_expired(token, now) {
+                    expired.push(key.clone());
+                } else {
+                    live.push((key, expires_at(token)));
+                }
+            }
+
+            if let Some(max_entries) = self.max_entries {
+                if live.len() > max_entries {
+                    // Tokens without an expiry are evicted last.
+                    live.sort_by_key(|(_, expires_at)| {
+                        (expires_at.is_none(), expires_at.unwrap_or(now))
+                    });
+
+                    let over = live.len() - max_entries;
+                    evict.extend(live.into_iter().take(over).map(|(key, _)| key.clone()));
+                }
+            }
+
+            (expired, evict)
+        };
+
+        if expired.is_empty() && evict.is_empty() {
+            return;
+        }
+
+        let mut cache = self.cache.write().await;
+
+        // A fresh token might have been stored for an expired key in between.
+        for key in expired {
+            if cache.get(&key).is_some_and(|token| is_expired(token, now)) {
+                cache.remove(&key);
+            }
+        }
+
+        for key in evict {
+            cache.remove(&key);
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl Cache for MemoryTokenCache {
-    #[tracing::instrument]
+    #[tracing::instrument(skip(self))]
     async fn fetch(&self, key: &CacheKey) -> Result<Option<Token>, FetchError> {
-        let result = self.cache.read().await.get(key).cloned().and_then(|token| {
-            if let Some(expires_in) = token.expires_in {
-                token
-                    .issued_at
-                    .map(|issued_at| issued_at + chrono::Duration::seconds(expires_in))
-                    .and_then(|expires_at| {
-                        if expires_at < Utc::now() {
-                            None
-                        } else {
-                            Some(token)
-                        }
-                    })
-            } else {
-                Some(token)
-            }
-        });
+        let result = self
+            .cache
+            .read()
+            .await
+            .get(key)
+            .filter(|token| !is_expired(token, Utc::now()))
+            .cloned();
 
         Ok(result)
     }
 
-    #[tracing::instrument]
+    #[tracing::instrument(skip(self, token))]
     async fn store(&self, key: CacheKey, token: Token) -> Result<(), StoreError> {
-        self.cache.write().await.insert(key, token);
+        let len = {
+            let mut cache = self.cache.write().await;
+            cache.insert(key, token);
+            cache.len()
+        };
+
+        let stores = self.stores.fetch_add(1, Ordering::Relaxed);
+        let over_limit = self
+            .max_entries
+            .is_some_and(|max_entries| len > max_entries);
+
+        if stores.is_multiple_of(SWEEP_INTERVAL) || over_limit {
+            self.sweep().await;
+        }
 
         Ok(())
     }
 }
 
+impl TokenFile {
+    pub(super) fn new(path: PathBuf, cache: MemoryTokenCache) -> Self {
+        Self { path, cache }
+    }
+
+    pub(super) async fn save(&self) -> Result<(), PersistError> {
+        self.cache.save_to(&self.path).await
+    }
+}
+
+impl Drop for TokenFile {
+    fn drop(&mut self) {
+        // Drop can not wait for the lock, a cache that is in use is not saved.
+        let Ok(tokens) = self.cache.cache.try_read() else {
+            warn!(path = %self.path.display(), "token cache is in use, not saving it");
+            return;
+        };
+
+        if let Err(e) = save(&tokens, &self.path) {
+            warn!(error = %e, "failed to save token cache");
+        }
+    }
+}
+
+/// Writes `tokens` to a temporary file next to `path` and renames it, so a
+/// crash never leaves a partial file behind.
+fn save(tokens: &HashMap<CacheKey, Token>, path: &Path) -> Result<(), PersistError> {
+    let now = Utc::now();
+    let saved = Saved {
+        tokens: tokens
+            .iter()
+            .filter(|(_, token)| !is_expired(token, now))
+            .map(|(key, token)| SavedToken {
+                key: key.clone(),
+                token: token.clone(),
+            })
+            .collect(),
+    };
+
+    let content = serde_json::to_vec(&saved).map_err(PersistError::Serialize)?;
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    // The mode only applies to new files, a partial file left behind by an
+    // earlier save could have other permissions.
+    match std::fs::remove_file(&partial) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(PersistError::Write(partial, e));
+        }
+        _ => {}
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(&partial)
+        .and_then(|mut file| file.write_all(&content))
+        .map_err(|e| PersistError::Write(partial.clone(), e))?;
+
+    std::fs::rename(&partial, path).map_err(|e| PersistError::Rename(partial, e))
+}
+
+fn expires_at(token: &Token) -> Option<DateTime<Utc>> {
+    token.expires_at(clock_skew())
+}
+
+fn is_expired(token: &Token, now: DateTime<Utc>) -> bool {
+    token.is_expired(now, clock_skew())
+}
+
+/// The caches do not know the [`crate::ClientBuilder::token_clock_skew`] of
+/// the clients using them.
+fn clock_skew() -> chrono::Duration {
+    chrono::Duration::from_std(DEFAULT_TOKEN_CLOCK_SKEW).unwrap_or_default()
+}
+
 #[cfg(feature = "redis_cache")]
 impl RedisCache {
     #[must_use]
@@ -142,7 +417,7 @@ impl RedisCache {
 #[cfg(feature = "redis_cache")]
 #[async_trait::async_trait]
 impl Cache for RedisCache {
-    #[tracing::instrument]
+    #[tracing::instrument(skip(self))]
     async fn fetch(&self, key: &CacheKey) -> Result<Option<Token>, FetchError> {
         let mut connection = self
             .client
@@ -174,7 +449,7 @@ impl Cache for RedisCache {
         Ok(Some(token))
     }
 
-    #[tracing::instrument]
+    #[tracing::instrument(skip(self, token))]
     async fn store(&self, key: CacheKey, token: Token) -> Result<(), StoreError> {
         let mut connection = self
             .client
@@ -204,3 +479,281 @@ impl Cache for RedisCache {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use chrono::{
+        Duration,
+        Utc,
+    };
+    use pretty_assertions::assert_eq;
+
+    use http::{
+        Method,
+        StatusCode,
+    };
+
+    use super::*;
+    use crate::{
+        docker::transport::{
+            MockResponse,
+            MockTransport,
+        },
+        Client,
+        Image,
+    };
+
+    fn key(repository: usize) -> CacheKey {
+        let image: Image = format!("ghcr.io/example/app-{repository}:latest")
+            .parse()
+            .unwrap();
+
+        (&image).into()
+    }
+
+    fn token(expires_in: i64) -> Token {
+        Token {
+            value: "token".to_string(),
+            expires_in: Some(expires_in),
+            issued_at: None,
+            received_at: Some(Utc::now()),
+            service: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn store_evicts_expired_tokens() {
+        let cache = MemoryTokenCache::default();
+
+        {
+            let mut tokens = cache.cache.write().await;
+            for repository in 0..1000 {
+                tokens.insert(key(repository), token(-1));
+            }
+        }
+
+        cache.store(key(1000), token(300)).await.unwrap();
+
+        assert_eq!(cache.cache.read().await.len(), 1);
+        assert!(cache.fetch(&key(1000)).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+
+        let cache = MemoryTokenCache::default();
+        cache.store(key(0), token(300)).await.unwrap();
+        cache.cache.write().await.insert(key(1), token(-1));
+
+        cache.save_to(&path).await.unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = MemoryTokenCache::load_from(&path);
+
+        assert_eq!(loaded.cache.read().await.len(), 1);
+        assert_eq!(loaded.fetch(&key(0)).await.unwrap().unwrap().value, "token");
+    }
+
+    /// A partial file left behind by an earlier save does not keep its
+    /// permissions.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn save_replaces_stale_partial_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+        let partial = dir.path().join("tokens.json.partial");
+
+        std::fs::write(&partial, "stale").unwrap();
+        std::fs::set_permissions(&partial, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let cache = MemoryTokenCache::default();
+        cache.store(key(0), token(300)).await.unwrap();
+        cache.save_to(&path).await.unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!partial.exists());
+
+        let loaded = MemoryTokenCache::load_from(&path);
+        assert_eq!(loaded.fetch(&key(0)).await.unwrap().unwrap().value, "token");
+    }
+
+    #[tokio::test]
+    async fn load_skips_expired_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+
+        let cache = MemoryTokenCache::default();
+        cache.store(key(0), token(300)).await.unwrap();
+        cache
+            .store(
+                key(1),
+                Token {
+                    received_at: Some(Utc::now() - Duration::seconds(10)),
+                    ..token(11)
+                },
+            )
+            .await
+            .unwrap();
+        cache.save_to(&path).await.unwrap();
+
+        // The second token expires while the file is on disk.
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let loaded = MemoryTokenCache::load_from(&path);
+
+        assert!(loaded.fetch(&key(0)).await.unwrap().is_some());
+        assert_eq!(loaded.cache.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn corrupted_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+        std::fs::write(&path, "{not json").unwrap();
+
+        let loaded = MemoryTokenCache::load_from(&path);
+        assert!(loaded.cache.read().await.is_empty());
+
+        let missing = MemoryTokenCache::load_from(&dir.path().join("missing.json"));
+        assert!(missing.cache.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_saves_on_drop() {
+        const TOKEN_URL: &str =
+            "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io";
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+        let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            TOKEN_URL,
+            MockResponse::new(StatusCode::OK).body(r#"{"token":"saved-token"}"#),
+        );
+
+        let client = Client::builder()
+            .transport(transport.clone())
+            .token_cache_file(&path)
+            .build();
+        let clone = client.clone();
+
+        client
+            .prefetch_tokens(std::slice::from_ref(&image))
+            .await
+            .unwrap();
+        drop(client);
+        assert!(!path.exists());
+
+        // The last clone saves the cache.
+        drop(clone);
+
+        let client = Client::builder()
+            .transport(transport.clone())
+            .token_cache_file(&path)
+            .build();
+        client.prefetch_tokens(&[image]).await.unwrap();
+
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn max_entries_evicts_first_to_expire() {
+        let cache = MemoryTokenCache::with_max_entries(2);
+
+        cache.store(key(0), token(100)).await.unwrap();
+        cache.store(key(1), token(300)).await.unwrap();
+        cache
+            .store(
+                key(2),
+                Token {
+                    received_at: Some(Utc::now() - Duration::seconds(10)),
+                    ..token(200)
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(cache.fetch(&key(0)).await.unwrap().is_none());
+        assert!(cache.fetch(&key(1)).await.unwrap().is_some());
+        assert!(cache.fetch(&key(2)).await.unwrap().is_some());
+    }
+
+    /// Files written before the receive time was recorded only have the
+    /// `issued_at` of the token server, which may be off by minutes.
+    #[tokio::test]
+    async fn load_legacy_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+
+        let now = Utc::now();
+        let legacy = |repository: usize, issued_at: Option<chrono::DateTime<Utc>>| {
+            serde_json::json!({
+                "key": key(repository),
+                "token": { "token": "legacy", "expires_in": 3600, "issued_at": issued_at },
+            })
+        };
+
+        let saved = serde_json::json!({
+            "tokens": [
+                legacy(0, Some(now + Duration::minutes(5))),
+                legacy(1, Some(now - Duration::minutes(5))),
+                legacy(2, Some(now + Duration::minutes(10))),
+                legacy(3, None),
+            ],
+        });
+        std::fs::write(&path, saved.to_string()).unwrap();
+
+        let loaded = MemoryTokenCache::load_from(&path);
+
+        // Within the allowed skew the tokens are kept, a token issued further
+        // in the future or without any time can not be trusted.
+        assert!(loaded.fetch(&key(0)).await.unwrap().is_some());
+        assert!(loaded.fetch(&key(1)).await.unwrap().is_some());
+        assert!(loaded.fetch(&key(2)).await.unwrap().is_none());
+        assert!(loaded.fetch(&key(3)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn save_keeps_receive_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+
+        let received = Token {
+            issued_at: Some(Utc::now() - Duration::minutes(5)),
+            ..Token::default()
+        }
+        .received(Duration::minutes(1));
+
+        let cache = MemoryTokenCache::default();
+        cache
+            .store(
+                key(0),
+                Token {
+                    expires_in: Some(300),
+                    ..received.clone()
+                },
+            )
+            .await
+            .unwrap();
+        cache.save_to(&path).await.unwrap();
+
+        let loaded = MemoryTokenCache::load_from(&path);
+        let got = loaded.fetch(&key(0)).await.unwrap().unwrap();
+
+        assert_eq!(got.received_at, received.received_at);
+    }
+}
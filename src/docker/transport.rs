@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use futures::{
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use http::{
+    header::HeaderMap,
+    Method,
+    StatusCode,
+};
+use url::Url;
+
+use crate::docker::{
+    metrics::{
+        Direction,
+        Metrics,
+    },
+    rate_limit::authority,
+    stats::{
+        self,
+        Event,
+    },
+};
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod cassette;
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
+
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::{
+    MockResponse,
+    MockTransport,
+};
+
+type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>;
+
+#[derive(Debug)]
+pub enum Error {
+    Send(HttpError),
+    ReadBody(HttpError),
+    Unmatched(String),
+    Unrecorded(String),
+    RateLimitTimeout(String),
+    TooManyRedirects { url: Url, limit: usize },
+    UnsupportedContentEncoding(String),
+    Decode(std::io::Error),
+}
+
+/// An error of the HTTP client of [`ReqwestTransport`]. It keeps reqwest out
+/// of the public API, only the message, source and kind of the error are
+/// exposed.
+#[derive(Debug)]
+pub struct HttpError(reqwest::Error);
+
+/// A request the client wants to send to a registry or token endpoint.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<Bytes>,
+}
+
+/// The response to a [`Request`]. The body is streamed so callers can stop
+/// reading early.
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub url: Url,
+    body: BodyStream,
+}
+
+/// `Transport` executes the HTTP requests of a [`crate::Client`]. The default
+/// implementation uses reqwest, tests can swap it for [`MockTransport`] (with
+/// the `test-util` feature) to run without network access.
+#[async_trait::async_trait]
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    async fn execute(&self, request: Request) -> Result<Response, Error>;
+}
+
+/// `ReqwestTransport` sends requests over the network with reqwest. The
+/// default client does not follow redirects, [`crate::Client`] follows them
+/// itself so it can decide which hop gets the credentials.
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    by_host: HashMap<String, reqwest::Client>,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Send(e) => write!(f, "failed to send request: {e}"),
+            Self::ReadBody(e) => write!(f, "failed to read response body: {e}"),
+            Self::Unmatched(request) => write!(f, "no response configured for {request}"),
+            Self::Unrecorded(request) => write!(
+                f,
+                "request {request} is not recorded in the cassette, rerun the test with RECORD=1 \
+                 to record it"
+            ),
+            Self::RateLimitTimeout(host) => {
+                write!(f, "timed out waiting for the rate limit of {host}")
+            }
+            Self::TooManyRedirects { url, limit } => {
+                write!(f, "{url} still redirects after following {limit} redirects")
+            }
+            Self::UnsupportedContentEncoding(encoding) => {
+                write!(f, "unsupported content encoding {encoding}")
+            }
+            Self::Decode(e) => write!(f, "failed to decode response body: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl HttpError {
+    /// Returns true if the request timed out.
+    #[must_use]
+    pub fn is_timeout(&self) -> bool {
+        self.0.is_timeout()
+    }
+
+    /// Returns true if the connection to the server could not be
+    /// established.
+    #[must_use]
+    pub fn is_connect(&self) -> bool {
+        self.0.is_connect()
+    }
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(error: reqwest::Error) -> Self {
+        Self(error)
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl std::fmt::Debug for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Request {
+    #[must_use]
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+
+    #[must_use]
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+impl Response {
+    #[must_use]
+    pub fn new(
+        status: StatusCode,
+        headers: HeaderMap,
+        url: Url,
+        body: impl Stream<Item = Result<Bytes, Error>> + Send + 'static,
+    ) -> Self {
+        Self {
+            status,
+            headers,
+            url,
+            body: Box::pin(body),
+        }
+    }
+
+    /// Reads the whole body into memory.
+    ///
+    /// # Errors
+    /// Returns an error if reading the body fails.
+    pub async fn bytes(self) -> Result<Bytes, Error> {
+        let chunks: Vec<Bytes> = self.body.try_collect().await?;
+
+        Ok(chunks.concat().into())
+    }
+
+    /// Reads the whole body into memory as text, replacing invalid UTF-8
+    /// sequences.
+    ///
+    /// # Errors
+    /// Returns an error if reading the body fails.
+    pub async fn text(self) -> Result<String, Error> {
+        let bytes = self.bytes().await?;
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Returns the body as a stream of chunks.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, Error>> + Send {
+        self.body
+    }
+
+    /// Counts the chunks of the body as downloaded bytes for
+    /// [`stats::measure`].
+    pub(super) fn counted(mut self) -> Self {
+        self.body = Box::pin(self.body.inspect_ok(|chunk| {
+            stats::record(Event::Downloaded(chunk.len() as u64));
+        }));
+
+        self
+    }
+
+    /// Counts the chunks of the body as downloaded bytes for `metrics`.
+    pub(super) fn metered(mut self, metrics: Arc<dyn Metrics>, host: String) -> Self {
+        self.body = Box::pin(self.body.inspect_ok(move |chunk| {
+            metrics.bytes_transferred(&host, Direction::Download, chunk.len() as u64);
+        }));
+
+        self
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        let client = Self::client_builder().build().unwrap_or_default();
+
+        Self::new(client)
+    }
+}
+
+impl ReqwestTransport {
+    #[must_use]
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            by_host: HashMap::new(),
+        }
+    }
+
+    /// Sends the requests to `host` through `client` instead, for example
+    /// one with the TLS settings of a registry. The host includes the port
+    /// if the URLs of the requests name one.
+    #[must_use]
+    pub fn host_client(mut self, host: &str, client: reqwest::Client) -> Self {
+        self.by_host.insert(host.to_string(), client);
+        self
+    }
+
+    /// The builder of the default client, which does not follow redirects.
+    pub(super) fn client_builder() -> reqwest::ClientBuilder {
+        reqwest::Client::builder().redirect(reqwest::redirect::Policy::none())
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for ReqwestTransport {
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let client = self
+            .by_host
+            .get(&authority(&request.url))
+            .unwrap_or(&self.client);
+
+        let mut builder = client
+            .request(request.method, request.url.as_str())
+            .headers(request.headers);
+
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send().await.map_err(|e| Error::Send(e.into()))?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let url = response.url().clone();
+        let body = response
+            .bytes_stream()
+            .map(|chunk| chunk.map_err(|e| Error::ReadBody(e.into())));
+
+        Ok(Response::new(status, headers, url, body))
+    }
+}
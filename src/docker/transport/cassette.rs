@@ -0,0 +1,428 @@
+use std::{
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+};
+
+use http::{
+    header::{
+        HeaderMap,
+        HeaderName,
+        HeaderValue,
+        ACCEPT,
+        AUTHORIZATION,
+        SET_COOKIE,
+    },
+    Method,
+    StatusCode,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use url::Url;
+
+use crate::docker::transport::{
+    Error,
+    Request,
+    ReqwestTransport,
+    Response,
+    Transport,
+};
+
+/// Environment variable that switches [`CassetteTransport::from_env`] into
+/// recording mode when set to `1`.
+pub const RECORD_ENV: &str = "RECORD";
+
+const REDACTED: &str = "[REDACTED]";
+
+/// JSON fields of token responses that carry credentials.
+const TOKEN_FIELDS: [&str; 3] = ["token", "access_token", "refresh_token"];
+
+#[derive(Debug)]
+pub enum CassetteError {
+    Read(PathBuf, std::io::Error),
+    Write(PathBuf, std::io::Error),
+    Deserialize(PathBuf, serde_json::Error),
+    Serialize(serde_json::Error),
+}
+
+/// A recorded request and the response the registry returned for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: Url,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept: Option<String>,
+}
+
+/// Bodies are stored as text, non UTF-8 content is replaced with the unicode
+/// replacement character.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// `RecordingTransport` forwards requests to an inner transport and records
+/// every interaction with credentials redacted. Call
+/// [`RecordingTransport::save`] to write the cassette.
+#[derive(Debug, Clone)]
+pub struct RecordingTransport<T> {
+    inner: T,
+    path: PathBuf,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+/// `ReplayTransport` answers requests from a cassette without any network
+/// access. Requests are matched strictly on method, URL and Accept header.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    interactions: Arc<Vec<Interaction>>,
+}
+
+/// Records when [`RECORD_ENV`] is set to `1` and replays otherwise.
+#[derive(Debug, Clone)]
+pub enum CassetteTransport {
+    Recording(RecordingTransport<ReqwestTransport>),
+    Replay(ReplayTransport),
+}
+
+impl std::fmt::Display for CassetteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(path, e) => write!(f, "failed to read cassette {}: {e}", path.display()),
+            Self::Write(path, e) => write!(f, "failed to write cassette {}: {e}", path.display()),
+            Self::Deserialize(path, e) => {
+                write!(f, "failed to deserialize cassette {}: {e}", path.display())
+            }
+            Self::Serialize(e) => write!(f, "failed to serialize cassette: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CassetteError {}
+
+impl RecordedRequest {
+    fn from_parts(method: &Method, url: &Url, headers: &HeaderMap) -> Self {
+        Self {
+            method: method.to_string(),
+            url: url.clone(),
+            accept: headers
+                .get(ACCEPT)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from),
+        }
+    }
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    #[must_use]
+    pub fn new(inner: T, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            interactions: Arc::default(),
+        }
+    }
+
+    /// Returns the interactions recorded so far.
+    #[must_use]
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.interactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Writes all recorded interactions to the cassette file.
+    ///
+    /// # Errors
+    /// Returns an error if the cassette can not be serialized or written.
+    pub fn save(&self) -> Result<(), CassetteError> {
+        let content =
+            serde_json::to_string_pretty(&self.interactions()).map_err(CassetteError::Serialize)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| CassetteError::Write(self.path.clone(), e))?;
+        }
+
+        std::fs::write(&self.path, content + "\n")
+            .map_err(|e| CassetteError::Write(self.path.clone(), e))
+    }
+}
+
+impl ReplayTransport {
+    #[must_use]
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        Self {
+            interactions: Arc::new(interactions),
+        }
+    }
+
+    /// Loads a cassette written by [`RecordingTransport::save`].
+    ///
+    /// # Errors
+    /// Returns an error if the cassette can not be read or deserialized.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        let path = path.as_ref();
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| CassetteError::Read(path.to_path_buf(), e))?;
+
+        let interactions = serde_json::from_str(&content)
+            .map_err(|e| CassetteError::Deserialize(path.to_path_buf(), e))?;
+
+        Ok(Self::new(interactions))
+    }
+}
+
+impl CassetteTransport {
+    /// Records into `path` if [`RECORD_ENV`] is set to `1` and replays from
+    /// `path` otherwise.
+    ///
+    /// # Errors
+    /// Returns an error if the cassette should be replayed but can not be
+    /// loaded.
+    pub fn from_env(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        let path = path.as_ref();
+
+        if std::env::var(RECORD_ENV).is_ok_and(|value| value == "1") {
+            Ok(Self::Recording(RecordingTransport::new(
+                ReqwestTransport::default(),
+                path,
+            )))
+        } else {
+            ReplayTransport::open(path).map(Self::Replay)
+        }
+    }
+
+    /// Saves the cassette when recording, does nothing when replaying.
+    ///
+    /// # Errors
+    /// Returns an error if the cassette can not be written.
+    pub fn finish(&self) -> Result<(), CassetteError> {
+        match self {
+            Self::Recording(recording) => recording.save(),
+            Self::Replay(_) => Ok(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Transport> Transport for RecordingTransport<T> {
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let recorded_request =
+            RecordedRequest::from_parts(&request.method, &request.url, &request.headers);
+
+        let response = self.inner.execute(request).await?;
+
+        let status = response.status;
+        let headers = response.headers.clone();
+        let url = response.url.clone();
+        let body = response.bytes().await?;
+
+        let recorded_response = RecordedResponse {
+            status: status.as_u16(),
+            headers: redact_headers(&headers),
+            body: redact_body(&String::from_utf8_lossy(&body)),
+        };
+
+        self.interactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Interaction {
+                request: recorded_request,
+                response: recorded_response,
+            });
+
+        let body = futures::stream::once(async move { Ok(body) });
+
+        Ok(Response::new(status, headers, url, body))
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for ReplayTransport {
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let wanted = RecordedRequest::from_parts(&request.method, &request.url, &request.headers);
+
+        let interaction = self
+            .interactions
+            .iter()
+            .find(|interaction| interaction.request == wanted)
+            .ok_or_else(|| {
+                Error::Unrecorded(format!(
+                    "{} {} with accept header {:?}",
+                    wanted.method, wanted.url, wanted.accept
+                ))
+            })?;
+
+        let status = StatusCode::from_u16(interaction.response.status)
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &interaction.response.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                headers.append(name, value);
+            }
+        }
+
+        let body = interaction.response.body.clone().into_bytes();
+        let body = futures::stream::once(async move { Ok(body.into()) });
+
+        Ok(Response::new(status, headers, request.url, body))
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for CassetteTransport {
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        match self {
+            Self::Recording(recording) => recording.execute(request).await,
+            Self::Replay(replay) => replay.execute(request).await,
+        }
+    }
+}
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == AUTHORIZATION || name == SET_COOKIE || value.is_sensitive() {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Replaces credentials in token responses. Other bodies are returned
+/// unchanged.
+fn redact_body(body: &str) -> String {
+    let Ok(serde_json::Value::Object(mut object)) = serde_json::from_str(body) else {
+        return body.to_string();
+    };
+
+    let mut redacted = false;
+    for field in TOKEN_FIELDS {
+        if let Some(value) = object.get_mut(field) {
+            *value = serde_json::Value::String(REDACTED.to_string());
+            redacted = true;
+        }
+    }
+
+    if redacted {
+        serde_json::Value::Object(object).to_string()
+    } else {
+        body.to_string()
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        docker::transport::{
+            cassette::{
+                RecordingTransport,
+                ReplayTransport,
+                REDACTED,
+            },
+            Error,
+            MockResponse,
+            MockTransport,
+            Request,
+            Transport,
+        },
+        Client,
+    };
+
+    const TOKEN_URL: &str =
+        "https://ghcr.io/token?scope=repository:sigstore/cosign/cosign:pull&service=ghcr.io";
+
+    const MANIFEST_URL: &str = "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0";
+
+    #[tokio::test]
+    async fn record_redacts_and_replays() {
+        let mock = MockTransport::new()
+            .with_response(
+                Method::GET,
+                TOKEN_URL,
+                MockResponse::new(StatusCode::OK)
+                    .header("Set-Cookie", "session=secret")
+                    .body(r#"{"token":"secret-token"}"#),
+            )
+            .with_response(
+                Method::GET,
+                MANIFEST_URL,
+                MockResponse::new(StatusCode::OK).body(include_str!(
+                    "../../../resources/registry/github/cosign.json"
+                )),
+            );
+
+        let path = std::env::temp_dir().join(format!("cassette-{}.json", std::process::id()));
+        let recording = RecordingTransport::new(mock, &path);
+
+        let image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+
+        let client = Client::builder().transport(recording.clone()).build();
+        let recorded = client.get_manifest(&image).await.unwrap();
+        recording.save().unwrap();
+
+        let cassette = std::fs::read_to_string(&path).unwrap();
+        assert!(!cassette.contains("secret"));
+        assert!(cassette.contains(REDACTED));
+
+        let client = Client::builder()
+            .transport(ReplayTransport::open(&path).unwrap())
+            .build();
+        let replayed = client.get_manifest(&image).await.unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(recorded).unwrap(),
+            serde_json::to_value(replayed).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn unrecorded_request() {
+        let replay = ReplayTransport::new(Vec::new());
+
+        let got = replay
+            .execute(Request::new(Method::GET, MANIFEST_URL.parse().unwrap()))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(got, Error::Unrecorded(_)));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use http::{
+    header::{
+        HeaderMap,
+        HeaderName,
+        HeaderValue,
+    },
+    Method,
+    StatusCode,
+};
+use url::Url;
+
+use crate::docker::transport::{
+    Error,
+    Request,
+    Response,
+    Transport,
+};
+
+/// `MockTransport` answers requests with canned responses keyed by method and
+/// URL and records every request it receives. Requests without a configured
+/// response fail with [`Error::Unmatched`].
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    responses: Arc<Mutex<HashMap<(Method, String), MockResponse>>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+/// A canned response served by [`MockTransport`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    delay: Option<Duration>,
+}
+
+impl MockTransport {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `response` for every request with the given method and URL.
+    #[must_use]
+    pub fn with_response(self, method: Method, url: &str, response: MockResponse) -> Self {
+        // Normalize the URL the same way the client does so callers don't have
+        // to care about encoding details.
+        let url = Url::parse(url).map_or_else(|_| url.to_string(), String::from);
+
+        self.responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((method, url), response);
+
+        self
+    }
+
+    /// Returns all requests received so far in the order they were made.
+    #[must_use]
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl MockResponse {
+    #[must_use]
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            delay: None,
+        }
+    }
+
+    /// Adds a header to the response.
+    ///
+    /// # Panics
+    /// Panics if the name or value are not valid header values.
+    #[must_use]
+    #[expect(clippy::expect_used, reason = "only used to set up tests")]
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(
+            HeaderName::try_from(name).expect("invalid header name"),
+            HeaderValue::try_from(value).expect("invalid header value"),
+        );
+
+        self
+    }
+
+    #[must_use]
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Waits for `delay` before the response is returned, which keeps the
+    /// request in flight for that long.
+    #[must_use]
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for MockTransport {
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let key = (request.method.clone(), request.url.to_string());
+        let url = request.url.clone();
+
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(request);
+
+        let response = self
+            .responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| Error::Unmatched(format!("{} {}", key.0, key.1)))?;
+
+        if let Some(delay) = response.delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        let body = futures::stream::once(async move { Ok(response.body) });
+
+        Ok(Response::new(response.status, response.headers, url, body))
+    }
+}
@@ -0,0 +1,386 @@
+//! Every tag of a repository together with its manifest, see
+//! [`Client::walk_repository`].
+
+use either::Either;
+use futures::{
+    Stream,
+    TryStreamExt,
+};
+
+use crate::{
+    docker::{
+        media_types::MediaType,
+        tags::cosign_subject,
+        Client,
+        Error,
+    },
+    Digest,
+    Image,
+    ImageName,
+    Manifest,
+    Tag,
+};
+
+/// Options for [`Client::walk_repository_with`]. The default options skip
+/// the tags cosign stores signatures, attestations and SBOMs under.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalkOptions {
+    include_signatures: bool,
+}
+
+/// A tag of a repository and the manifest it points to, yielded by
+/// [`Client::walk_repository`].
+#[derive(Debug, Clone)]
+pub struct RepoEntry {
+    pub tag: Tag,
+
+    /// The digest the registry sent for the manifest, or the digest of the
+    /// manifest as it was received if it sent none.
+    pub digest: Digest,
+
+    /// The media type the manifest names. Schema 1 manifests do not name
+    /// one, for those it is the `Content-Type` of the response.
+    pub media_type: MediaType,
+    pub manifest: Manifest,
+}
+
+impl WalkOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also yields the tags like `sha256-<hex>.sig` cosign stores
+    /// signatures, attestations and SBOMs under.
+    #[must_use]
+    pub fn include_signatures(mut self, include_signatures: bool) -> Self {
+        self.include_signatures = include_signatures;
+        self
+    }
+}
+
+impl Client {
+    /// Yields every tag of the repository of `image` together with the
+    /// manifest it points to, see [`Client::walk_repository_with`].
+    pub fn walk_repository<'a>(
+        &'a self,
+        image: &'a Image,
+    ) -> impl Stream<Item = Result<RepoEntry, Error>> + Send + 'a {
+        self.walk_repository_with(image, &WalkOptions::default())
+    }
+
+    /// Yields every tag of the repository of `image` together with the
+    /// manifest it points to, in the order of the tag list. Manifest lists
+    /// are not followed.
+    ///
+    /// Nothing is collected up front: the tag list is read page by page like
+    /// [`Client::list_tags_stream`] and manifests are fetched ahead of the
+    /// consumer, as many as [`crate::ClientBuilder::concurrency_limit`]
+    /// allows. Dropping the stream stops all further requests.
+    ///
+    /// A tag that can not be resolved, for example because it was deleted
+    /// after the tag list was read, yields an error and the walk continues
+    /// with the next tag. An error listing the tags ends the stream.
+    pub fn walk_repository_with<'a>(
+        &'a self,
+        image: &'a Image,
+        options: &WalkOptions,
+    ) -> impl Stream<Item = Result<RepoEntry, Error>> + Send + 'a {
+        let include_signatures = options.include_signatures;
+
+        self.list_tags_stream(image)
+            .try_filter(move |tag| {
+                futures::future::ready(include_signatures || cosign_subject(tag).is_none())
+            })
+            .map_ok(move |tag| self.walk_tag(image, tag))
+            .try_buffered(self.inner.concurrency_limit)
+    }
+
+    async fn walk_tag(&self, image: &Image, tag: Tag) -> Result<RepoEntry, Error> {
+        let _permit = self.concurrency_permit().await;
+
+        let tagged = Image {
+            image_name: ImageName::new(image.image_name.name.clone(), Either::Left(tag.clone())),
+            ..image.clone()
+        };
+
+        let raw = self.get_manifest_raw(&tagged).await?;
+
+        let digest = raw.digest.as_deref().map_or_else(
+            || Digest::sha256(&raw.body),
+            |digest| digest.parse().unwrap_or_else(|e| match e {}),
+        );
+        let content_type = raw.content_type.clone();
+
+        let manifest = Self::response_from_raw(raw)?.manifest;
+
+        Ok(RepoEntry {
+            tag,
+            digest,
+            media_type: media_type(&manifest, content_type.as_deref()),
+            manifest,
+        })
+    }
+}
+
+fn media_type(manifest: &Manifest, content_type: Option<&str>) -> MediaType {
+    match manifest {
+        Manifest::Image(image) => image.media_type.as_str().into(),
+        Manifest::List(list) => list.media_type().into(),
+        Manifest::Single(_) => content_type
+            .map(MediaType::from)
+            .filter(MediaType::is_schema1)
+            .unwrap_or(MediaType::DockerManifestSchema1Signed),
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use futures::{
+        StreamExt,
+        TryStreamExt,
+    };
+    use http::{
+        Method,
+        StatusCode,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        RepoEntry,
+        WalkOptions,
+    };
+    use crate::{
+        docker::{
+            media_types::MediaType,
+            transport::{
+                MockResponse,
+                MockTransport,
+            },
+        },
+        Client,
+        Digest,
+        Image,
+        Manifest,
+        Tag,
+    };
+
+    const IMAGE: &str = include_str!("../../resources/manifest/image/example.json");
+    const LIST: &str = include_str!("../../resources/manifest/list/example.json");
+    const SINGLE: &str =
+        include_str!("../../resources/manifest/single/external-secrets-operator.json");
+
+    const FIRST_PAGE: &str = "https://registry.k8s.io/v2/pause/tags/list";
+    const SECOND_PAGE: &str = "https://registry.k8s.io/v2/pause/tags/list?last=1.0&n=3";
+    const SIGNATURE: &str =
+        "sha256-7031c1b283388d2c2e09b57badb803c05ebed362dc88d84b480cc47f72a21097.sig";
+
+    fn image() -> Image {
+        "registry.k8s.io/pause:3.9".parse().unwrap()
+    }
+
+    fn manifest_url(tag: &str) -> String {
+        format!("https://registry.k8s.io/v2/pause/manifests/{tag}")
+    }
+
+    /// Two pages of tags: an image, a list and a signature on the first, a
+    /// schema 1 manifest without a digest header on the second.
+    fn transport() -> MockTransport {
+        MockTransport::new()
+            .with_response(
+                Method::GET,
+                FIRST_PAGE,
+                MockResponse::new(StatusCode::OK)
+                    .header("Link", r#"</v2/pause/tags/list?last=1.0&n=3>; rel="next""#)
+                    .body(
+                        serde_json::json!({ "name": "pause", "tags": ["latest", SIGNATURE, "1.0"] })
+                            .to_string(),
+                    ),
+            )
+            .with_response(
+                Method::GET,
+                SECOND_PAGE,
+                MockResponse::new(StatusCode::OK).body(r#"{"name":"pause","tags":["0.1"]}"#),
+            )
+            .with_response(
+                Method::GET,
+                &manifest_url("latest"),
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.list.v2+json",
+                    )
+                    .header("Docker-Content-Digest", &Digest::sha256(LIST.as_bytes()).to_string())
+                    .body(LIST),
+            )
+            .with_response(
+                Method::GET,
+                &manifest_url(SIGNATURE),
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.v2+json",
+                    )
+                    .header("Docker-Content-Digest", &Digest::sha256(IMAGE.as_bytes()).to_string())
+                    .body(IMAGE),
+            )
+            .with_response(
+                Method::GET,
+                &manifest_url("1.0"),
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.v2+json",
+                    )
+                    .header("Docker-Content-Digest", &Digest::sha256(IMAGE.as_bytes()).to_string())
+                    .body(IMAGE),
+            )
+            .with_response(
+                Method::GET,
+                &manifest_url("0.1"),
+                MockResponse::new(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        "application/vnd.docker.distribution.manifest.v1+prettyjws",
+                    )
+                    .body(SINGLE),
+            )
+    }
+
+    fn summary(entries: &[RepoEntry]) -> Vec<(String, Digest, MediaType)> {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.tag.to_string(),
+                    entry.digest.clone(),
+                    entry.media_type.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn all_pages() {
+        let client = Client::builder().transport(transport()).build();
+        let image = image();
+
+        let got: Vec<RepoEntry> = client.walk_repository(&image).try_collect().await.unwrap();
+
+        assert_eq!(
+            summary(&got),
+            vec![
+                (
+                    "latest".to_string(),
+                    Digest::sha256(LIST.as_bytes()),
+                    MediaType::DockerManifestList
+                ),
+                (
+                    "1.0".to_string(),
+                    Digest::sha256(IMAGE.as_bytes()),
+                    MediaType::DockerManifest
+                ),
+                (
+                    "0.1".to_string(),
+                    Digest::sha256(SINGLE.as_bytes()),
+                    MediaType::DockerManifestSchema1Signed
+                ),
+            ]
+        );
+        assert!(matches!(got[0].manifest, Manifest::List(_)));
+        assert!(matches!(got[2].manifest, Manifest::Single(_)));
+    }
+
+    #[tokio::test]
+    async fn include_signatures() {
+        let client = Client::builder().transport(transport()).build();
+        let image = image();
+
+        let got: Vec<Tag> = client
+            .walk_repository_with(&image, &WalkOptions::new().include_signatures(true))
+            .map_ok(|entry| entry.tag)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                Tag::Latest,
+                Tag::Specific(SIGNATURE.into()),
+                Tag::Specific("1.0".into()),
+                Tag::Specific("0.1".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_tag_continues() {
+        let transport = MockTransport::new()
+            .with_response(
+                Method::GET,
+                FIRST_PAGE,
+                MockResponse::new(StatusCode::OK)
+                    .body(r#"{"name":"pause","tags":["deleted","1.0"]}"#),
+            )
+            .with_response(
+                Method::GET,
+                &manifest_url("deleted"),
+                MockResponse::new(StatusCode::NOT_FOUND),
+            )
+            .with_response(
+                Method::GET,
+                &manifest_url("1.0"),
+                MockResponse::new(StatusCode::OK).body(IMAGE),
+            );
+        let client = Client::builder().transport(transport).build();
+        let image = image();
+
+        let got: Vec<_> = client.walk_repository(&image).collect().await;
+
+        assert_eq!(got.len(), 2);
+        assert!(got[0].is_err());
+        assert_eq!(got[1].as_ref().unwrap().tag, Tag::Specific("1.0".into()));
+    }
+
+    #[tokio::test]
+    async fn dropped_early() {
+        let tags: Vec<String> = (1..=20).map(|tag| tag.to_string()).collect();
+
+        let transport = MockTransport::new().with_response(
+            Method::GET,
+            FIRST_PAGE,
+            MockResponse::new(StatusCode::OK)
+                .header("Link", r#"</v2/pause/tags/list?last=20&n=20>; rel="next""#)
+                .body(serde_json::json!({ "name": "pause", "tags": tags }).to_string()),
+        );
+        let transport = tags.iter().fold(transport, |transport, tag| {
+            transport.with_response(
+                Method::GET,
+                &manifest_url(tag),
+                MockResponse::new(StatusCode::OK).body(IMAGE),
+            )
+        });
+        let client = Client::builder().transport(transport.clone()).build();
+        let image = image();
+
+        let mut stream = Box::pin(client.walk_repository(&image));
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.tag, Tag::Specific("1".into()));
+
+        // The tag list and at most 8 manifests were requested, the second
+        // page was never asked for.
+        let sent = transport.requests().len();
+        assert!(sent <= 9, "{sent} requests");
+
+        drop(stream);
+        tokio::task::yield_now().await;
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), sent);
+        assert!(requests
+            .iter()
+            .all(|request| !request.url.as_str().contains("last=20")));
+    }
+}
@@ -0,0 +1,225 @@
+use http::header::{
+    HeaderMap,
+    WARNING,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::warn;
+
+/// A `Warning` header sent by a registry, for example about approaching rate
+/// limits or deprecated image formats. Registries expect clients to show
+/// these to their users.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryWarning {
+    pub code: u16,
+    pub agent: String,
+    pub text: String,
+}
+
+impl std::fmt::Display for RegistryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.code, self.agent, self.text)
+    }
+}
+
+/// Parses all `Warning` headers and logs every warning. Malformed entries are
+/// skipped.
+pub(super) fn collect(headers: &HeaderMap) -> Vec<RegistryWarning> {
+    let warnings: Vec<_> = headers
+        .get_all(WARNING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(parse)
+        .collect();
+
+    for warning in &warnings {
+        warn!(
+            code = warning.code,
+            agent = %warning.agent,
+            text = %warning.text,
+            "registry sent a warning"
+        );
+    }
+
+    warnings
+}
+
+/// Parses a header value with the syntax of RFC 7234 section 5.5:
+///
+/// ```text
+/// Warning       = 1#warning-value
+/// warning-value = warn-code SP warn-agent SP warn-text [ SP warn-date ]
+/// ```
+fn parse(value: &str) -> Vec<RegistryWarning> {
+    let mut warnings = Vec::new();
+    let mut rest = value;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+
+        if rest.is_empty() {
+            return warnings;
+        }
+
+        match parse_warning(rest) {
+            Some((warning, remaining)) => {
+                warnings.push(warning);
+                rest = remaining;
+            }
+
+            None => rest = skip_entry(rest),
+        }
+    }
+}
+
+/// Parses a single warning value and returns it together with the input
+/// after it.
+fn parse_warning(input: &str) -> Option<(RegistryWarning, &str)> {
+    let (code, rest) = input.split_once(' ')?;
+    if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse().ok()?;
+
+    let (agent, rest) = rest.split_once(' ')?;
+    if agent.is_empty() {
+        return None;
+    }
+
+    let (text, mut rest) = parse_quoted(rest)?;
+
+    // The date is optional and not exposed.
+    if let Some(date) = rest.strip_prefix(' ').filter(|date| date.starts_with('"')) {
+        rest = parse_quoted(date)?.1;
+    }
+
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with(',') {
+        return None;
+    }
+
+    Some((
+        RegistryWarning {
+            code,
+            agent: agent.to_string(),
+            text,
+        },
+        rest,
+    ))
+}
+
+/// Parses a quoted string with backslash escapes at the start of `input`.
+fn parse_quoted(input: &str) -> Option<(String, &str)> {
+    let mut chars = input.strip_prefix('"')?.char_indices();
+    let mut text = String::new();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((text, &input[index + 2..])),
+            '\\' => text.push(chars.next()?.1),
+            c => text.push(c),
+        }
+    }
+
+    None
+}
+
+/// Skips to the next comma that is not part of a quoted string.
+fn skip_entry(input: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (index, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => return &input[index..],
+            _ => {}
+        }
+    }
+
+    ""
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use http::header::{
+        HeaderMap,
+        WARNING,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::docker::warning::{
+        collect,
+        RegistryWarning,
+    };
+
+    fn warning(code: u16, agent: &str, text: &str) -> RegistryWarning {
+        RegistryWarning {
+            code,
+            agent: agent.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn two_headers_and_a_malformed_one() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            WARNING,
+            r#"299 - "Docker Hub pull rate limit is almost reached""#
+                .parse()
+                .unwrap(),
+        );
+        headers.append(WARNING, "not a warning".parse().unwrap());
+        headers.append(
+            WARNING,
+            r#"299 registry-1.docker.io "image format \"v1\" is deprecated" "Wed, 21 Oct 2015 07:28:00 GMT""#
+                .parse()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            collect(&headers),
+            vec![
+                warning(299, "-", "Docker Hub pull rate limit is almost reached"),
+                warning(
+                    299,
+                    "registry-1.docker.io",
+                    r#"image format "v1" is deprecated"#
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn list_with_malformed_entry() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            WARNING,
+            r#"110 - "stale", 99 - "bad code, with comma", 199 cache "miscellaneous""#
+                .parse()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            collect(&headers),
+            vec![
+                warning(110, "-", "stale"),
+                warning(199, "cache", "miscellaneous"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_text() {
+        let mut headers = HeaderMap::new();
+        headers.append(WARNING, r#"299 - "never ends"#.parse().unwrap());
+
+        assert_eq!(collect(&headers), Vec::new());
+    }
+}
@@ -1,8 +1,17 @@
+use std::{
+    borrow::Cow,
+    hash::{
+        Hash,
+        Hasher,
+    },
+};
+
+use either::Either;
 use serde::{
     Deserialize,
     Serialize,
 };
-use tracing::error;
+use url::Url;
 
 #[expect(
     clippy::module_name_repetitions,
@@ -10,41 +19,91 @@ use tracing::error;
 )]
 pub mod image_name;
 pub mod registry;
+pub mod repository;
+pub mod validate;
 
-use image_name::ImageName;
+use image_name::{
+    digest::Digest,
+    ImageName,
+};
 use registry::Registry;
+use repository::Repository;
 
 #[derive(Debug)]
 pub enum FromStrError {
+    Empty,
+    InvalidCharacter { character: char, offset: usize },
     MissingFirstComponent,
     UnsupportedImageName(String),
     ParseImageName(image_name::FromStrError),
     MissingRegistry,
     MissingImageName,
     ParseRegistry(registry::FromStrError),
+    ParseRepository(repository::FromStrError),
     MissingRepository,
 }
 
 #[derive(Debug)]
 pub enum FromUrlError {}
 
-#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+/// Errors of building the URL of an image. Path segments are validated so a
+/// crafted component can not address a different repository.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UrlError {
+    InvalidBaseUrl(url::ParseError),
+    EmptySegment,
+    DotSegment(String),
+    InvalidCharacter { segment: String, character: char },
+}
+
+/// The scheme used to reach a registry. Registries are served over HTTPS
+/// except for local ones during development.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheme {
+    Http,
+
+    #[default]
+    Https,
+}
+
+/// A reference to an image in a registry, parsed from strings like
+/// `ghcr.io/sigstore/cosign/cosign:v2.4.0`.
+///
+/// Like the docker CLI, the first component of a reference with more than one
+/// is the registry if it looks like a host: it contains a `.` or a `:` or is
+/// `localhost`. Docker Hub repositories whose first component contains a dot,
+/// like `my.company/app`, have to be written with the `docker.io/` prefix,
+/// `docker.io/my.company/app`, or parsed with
+/// [`Image::parse_with_default_registry`].
+///
+/// Images compare and hash by the repository used for requests, so a Docker
+/// Hub image built without a repository equals the parsed one in `library`.
+#[derive(Debug, Clone)]
 pub struct Image {
     pub registry: Registry,
-    pub namespace: Option<String>,
-    pub repository: Option<String>,
+
+    /// The path between the registry and the image name, `None` for images
+    /// directly below the registry like `registry.k8s.io/pause`.
+    pub repository: Option<Repository>,
+
     pub image_name: ImageName,
 }
 
 impl std::fmt::Display for FromStrError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Empty => write!(f, "empty image reference"),
+            Self::InvalidCharacter { character, offset } => write!(
+                f,
+                "invalid character {character:?} at byte offset {offset} of image reference"
+            ),
             Self::MissingFirstComponent => write!(f, "missing first component"),
             Self::UnsupportedImageName(s) => write!(f, "unsupported image name: {s}"),
             Self::ParseImageName(err) => write!(f, "{err}"),
             Self::MissingRegistry => write!(f, "missing registry"),
             Self::MissingImageName => write!(f, "missing image name"),
             Self::ParseRegistry(err) => write!(f, "failed to parse registry: {err}"),
+            Self::ParseRepository(err) => write!(f, "failed to parse repository: {err}"),
             Self::MissingRepository => write!(f, "missing repository"),
         }
     }
@@ -60,10 +119,266 @@ impl std::fmt::Display for FromUrlError {
 
 impl std::error::Error for FromUrlError {}
 
+impl std::fmt::Display for UrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidBaseUrl(e) => write!(f, "invalid base URL: {e}"),
+            Self::EmptySegment => f.write_str("empty path segment"),
+            Self::DotSegment(s) => write!(f, "path segment {s:?} is not allowed"),
+            Self::InvalidCharacter { segment, character } => write!(
+                f,
+                "path segment {segment:?} contains the invalid character {character:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+impl Image {
+    /// Creates an image without a repository, use [`Image::with_repository`]
+    /// to add one.
+    #[must_use]
+    pub fn new(registry: Registry, image_name: ImageName) -> Self {
+        Self {
+            registry,
+            repository: None,
+            image_name,
+        }
+    }
+
+    /// Parses a reference without a registry, every component but the last
+    /// is part of the repository even if it looks like a host.
+    /// `my.company/app:1.0` with [`Registry::DockerHub`] is the image `app`
+    /// in the Docker Hub repository `my.company`. Single component
+    /// references of Docker Hub are in the `library` repository like with
+    /// [`std::str::FromStr`].
+    ///
+    /// # Errors
+    /// Returns an error if a component of the reference is not valid.
+    pub fn parse_with_default_registry(s: &str, registry: Registry) -> Result<Self, FromStrError> {
+        let s = checked(s)?;
+
+        let (repository, image_name) = match s.rsplit_once('/') {
+            Some((repository, image_name)) => (
+                Some(repository.parse().map_err(FromStrError::ParseRepository)?),
+                image_name,
+            ),
+            None => (
+                (registry == Registry::DockerHub).then(Repository::library),
+                s,
+            ),
+        };
+
+        let image_name = image_name.parse().map_err(FromStrError::ParseImageName)?;
+
+        Ok(Self {
+            registry,
+            repository,
+            image_name,
+        })
+    }
+
+    /// Sets the repository of the image, one or more components separated by
+    /// slashes like `sigstore/cosign`.
+    ///
+    /// # Errors
+    /// Returns an error if a component of `repository` is not valid.
+    pub fn with_repository(
+        mut self,
+        repository: impl AsRef<str>,
+    ) -> Result<Self, repository::FromStrError> {
+        self.repository = Some(repository.as_ref().parse()?);
+        Ok(self)
+    }
+
+    /// Returns everything but the last component of the repository, what
+    /// used to be the `namespace` field. `sigstore` for
+    /// `ghcr.io/sigstore/cosign/cosign`.
+    #[must_use]
+    pub fn namespace(&self) -> Option<&str> {
+        self.repository
+            .as_ref()
+            .and_then(|repository| repository.as_str().rsplit_once('/'))
+            .map(|(namespace, _)| namespace)
+    }
+
+    /// Returns the last component of the repository, what used to be the
+    /// `repository` field. `cosign` for `ghcr.io/sigstore/cosign/cosign`.
+    #[must_use]
+    pub fn repository(&self) -> Option<&str> {
+        self.repository.as_ref().map(Repository::last)
+    }
+
+    /// Returns the path of the repository without registry and reference,
+    /// for example `sigstore/cosign/cosign`.
+    #[must_use]
+    pub fn repository_path(&self) -> String {
+        repository_path(self.api_repository().as_deref(), &self.image_name.name)
+    }
+
+    /// The repository used for requests. Official Docker Hub images live in
+    /// the `library` repository, images built without one get it here as the
+    /// parser only adds it to images it parsed.
+    pub(crate) fn api_repository(&self) -> Option<Cow<'_, Repository>> {
+        match (&self.registry, &self.repository) {
+            (_, Some(repository)) => Some(Cow::Borrowed(repository)),
+            (Registry::DockerHub, None) => Some(Cow::Owned(Repository::library())),
+            (_, None) => None,
+        }
+    }
+
+    /// Returns the tag or digest the image is referenced by.
+    #[must_use]
+    pub fn reference(&self) -> String {
+        match &self.image_name.identifier {
+            Either::Left(tag) => tag.to_string(),
+            Either::Right(digest) => digest.to_string(),
+        }
+    }
+
+    /// Returns the URL of the manifest of the image.
+    ///
+    /// # Errors
+    /// Returns an error if a component of the image is not a valid path
+    /// segment.
+    pub fn manifest_url(&self, scheme: Scheme) -> Result<Url, UrlError> {
+        self.url(scheme, &self.manifest_segments()?)
+    }
+
+    /// Returns the URL of a blob in the repository of the image.
+    ///
+    /// # Errors
+    /// Returns an error if a component of the image or the digest is not a
+    /// valid path segment.
+    pub fn blob_url(&self, scheme: Scheme, digest: &Digest) -> Result<Url, UrlError> {
+        self.url(scheme, &self.blob_segments(digest)?)
+    }
+
+    /// Path segments of the manifest relative to the root of the registry.
+    pub(crate) fn manifest_segments(&self) -> Result<Vec<String>, UrlError> {
+        let mut segments = self.api_segments()?;
+        segments.push("manifests".to_string());
+        segments.push(segment(&self.reference())?);
+
+        Ok(segments)
+    }
+
+    /// Path segments of a blob relative to the root of the registry.
+    pub(crate) fn blob_segments(&self, digest: &Digest) -> Result<Vec<String>, UrlError> {
+        let mut segments = self.api_segments()?;
+        segments.push("blobs".to_string());
+        segments.push(segment(&digest.to_string())?);
+
+        Ok(segments)
+    }
+
+    /// Path segments of the referrers of `digest` relative to the root of
+    /// the registry.
+    #[cfg(feature = "client")]
+    pub(crate) fn referrers_segments(&self, digest: &Digest) -> Result<Vec<String>, UrlError> {
+        let mut segments = self.api_segments()?;
+        segments.push("referrers".to_string());
+        segments.push(segment(&digest.to_string())?);
+
+        Ok(segments)
+    }
+
+    /// Path segments of the tag list relative to the root of the registry.
+    #[cfg(feature = "client")]
+    pub(crate) fn tags_segments(&self) -> Result<Vec<String>, UrlError> {
+        let mut segments = self.api_segments()?;
+        segments.push("tags".to_string());
+        segments.push("list".to_string());
+
+        Ok(segments)
+    }
+
+    /// Path segments of the repository path.
+    pub(crate) fn repository_segments(&self) -> Result<Vec<String>, UrlError> {
+        self.api_repository()
+            .iter()
+            .flat_map(|repository| repository.components())
+            .chain([&*self.image_name.name])
+            .map(segment)
+            .collect()
+    }
+
+    fn api_segments(&self) -> Result<Vec<String>, UrlError> {
+        let mut segments = vec!["v2".to_string()];
+        segments.extend(self.repository_segments()?);
+
+        Ok(segments)
+    }
+
+    fn url(&self, scheme: Scheme, segments: &[String]) -> Result<Url, UrlError> {
+        let base = Url::parse(&format!(
+            "{scheme}://{host}/",
+            host = self.registry.api_host()
+        ))
+        .map_err(UrlError::InvalidBaseUrl)?;
+
+        Ok(append_segments(base, segments))
+    }
+}
+
+impl std::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http => f.write_str("http"),
+            Self::Https => f.write_str("https"),
+        }
+    }
+}
+
+/// Joins the repository and the name of an image with a slash.
+pub(crate) fn repository_path(repository: Option<&Repository>, name: &str) -> String {
+    match repository {
+        Some(repository) => format!("{repository}/{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// Appends `segments` to the path of `base`. Characters that are special in
+/// URLs are percent-encoded.
+pub(crate) fn append_segments(mut base: Url, segments: &[String]) -> Url {
+    if let Ok(mut path) = base.path_segments_mut() {
+        path.pop_if_empty().extend(segments);
+    }
+
+    base
+}
+
+/// Validates a single path segment. Slashes, dot segments, whitespace and
+/// control characters would change which resource the URL points to.
+fn segment(segment: &str) -> Result<String, UrlError> {
+    if segment.is_empty() {
+        return Err(UrlError::EmptySegment);
+    }
+
+    if segment == "." || segment == ".." {
+        return Err(UrlError::DotSegment(segment.to_string()));
+    }
+
+    if let Some(character) = segment
+        .chars()
+        .find(|c| *c == '/' || c.is_whitespace() || c.is_control())
+    {
+        return Err(UrlError::InvalidCharacter {
+            segment: segment.to_string(),
+            character,
+        });
+    }
+
+    Ok(segment.to_string())
+}
+
 impl std::str::FromStr for Image {
     type Err = FromStrError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = checked(s)?;
+
         let components = s.split('/').collect::<Vec<_>>();
 
         // alpine
@@ -80,8 +395,7 @@ impl std::str::FromStr for Image {
 
                 Ok(Image {
                     registry: Registry::DockerHub,
-                    namespace: None,
-                    repository: Some("library".to_string()),
+                    repository: Some(Repository::library()),
                     image_name,
                 })
             }
@@ -92,23 +406,33 @@ impl std::str::FromStr for Image {
                 let result = registry_or_repository.parse();
 
                 if let Ok(registry) = result {
+                    #[cfg(feature = "client")]
+                    tracing::debug!(
+                        reference = s,
+                        %registry,
+                        "the first component of the reference is the registry"
+                    );
+
                     let image_name = image_name.parse().map_err(Self::Err::ParseImageName)?;
 
+                    // docker.io/alpine is the same image as alpine.
+                    let repository = (registry == Registry::DockerHub).then(Repository::library);
+
                     Ok(Image {
                         registry,
-                        namespace: None,
-                        repository: None,
+                        repository,
                         image_name,
                     })
                 } else {
                     // Case where we have a repository and a docker image name as the registry
                     // could not be parsed
-                    let repository = (*registry_or_repository).to_string();
+                    let repository = registry_or_repository
+                        .parse()
+                        .map_err(Self::Err::ParseRepository)?;
                     let image_name = image_name.parse().map_err(Self::Err::ParseImageName)?;
 
                     Ok(Image {
                         registry: Registry::DockerHub,
-                        namespace: None,
                         repository: Some(repository),
                         image_name,
                     })
@@ -118,12 +442,11 @@ impl std::str::FromStr for Image {
             // Case where we have a registry, a repository and a docker image name
             [registry, repository, image_name] => {
                 let registry = registry.parse().map_err(Self::Err::ParseRegistry)?;
-                let repository = (*repository).to_string();
+                let repository = repository.parse().map_err(Self::Err::ParseRepository)?;
                 let image_name = image_name.parse().map_err(Self::Err::ParseImageName)?;
 
                 Ok(Image {
                     registry,
-                    namespace: None,
                     repository: Some(repository),
                     image_name,
                 })
@@ -132,48 +455,81 @@ impl std::str::FromStr for Image {
             // Case where we have a registry, a repository and a docker image name and a namespace
             [registry, namespace, repository, image_name] => {
                 let registry = registry.parse().map_err(Self::Err::ParseRegistry)?;
-                let namespace = (*namespace).to_string();
-                let repository = (*repository).to_string();
+                let repository = format!("{namespace}/{repository}")
+                    .parse()
+                    .map_err(Self::Err::ParseRepository)?;
                 let image_name = image_name.parse().map_err(Self::Err::ParseImageName)?;
 
                 Ok(Image {
                     registry,
-                    namespace: Some(namespace),
                     repository: Some(repository),
                     image_name,
                 })
             }
 
             // Other cases are not supported
-            _ => {
-                let err = Self::Err::UnsupportedImageName(s.to_string());
-                error!("{err}");
-
-                Err(err)
-            }
+            _ => Err(Self::Err::UnsupportedImageName(s.to_string())),
         }
     }
 }
 
+/// Strips a trailing newline, references read from files or command output
+/// often end with one, and rejects empty references and references with
+/// whitespace or control characters.
+fn checked(s: &str) -> Result<&str, FromStrError> {
+    let s = s
+        .strip_suffix('\n')
+        .map_or(s, |s| s.strip_suffix('\r').unwrap_or(s));
+
+    if s.is_empty() {
+        return Err(FromStrError::Empty);
+    }
+
+    if let Some((offset, character)) = s
+        .char_indices()
+        .find(|(_, c)| c.is_whitespace() || c.is_control())
+    {
+        return Err(FromStrError::InvalidCharacter { character, offset });
+    }
+
+    Ok(s)
+}
+
 impl std::fmt::Display for Image {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{registry}/{namespace}{repository}{image_name}",
+            "{registry}/{path}{separator}{reference}",
             registry = self.registry.registry_domain(),
-            namespace = match self.namespace {
-                Some(ref namespace) => format!("{namespace}/"),
-                None => String::new(),
-            },
-            repository = match self.repository {
-                Some(ref repository) => format!("{repository}/"),
-                None => String::new(),
+            path = self.repository_path(),
+            separator = if self.image_name.identifier.is_left() {
+                ':'
+            } else {
+                '@'
             },
-            image_name = self.image_name
+            reference = self.reference(),
         )
     }
 }
 
+impl PartialEq for Image {
+    fn eq(&self, other: &Self) -> bool {
+        self.registry == other.registry
+            && self.api_repository() == other.api_repository()
+            && self.image_name == other.image_name
+    }
+}
+
+impl Eq for Image {}
+
+impl Hash for Image {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.registry.hash(state);
+        self.api_repository().hash(state);
+        self.image_name.hash(state);
+    }
+}
+
 impl Serialize for Image {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -203,21 +559,81 @@ mod tests {
         use pretty_assertions::assert_eq;
 
         use crate::{
+            image::{
+                image_name,
+                FromStrError,
+            },
             Image,
             ImageName,
             Registry,
             Tag,
         };
 
+        #[test]
+        fn trailing_newline() {
+            let expected: Image = "nginx:latest".parse().unwrap();
+
+            assert_eq!(expected, "nginx:latest\n".parse::<Image>().unwrap());
+            assert_eq!(expected, "nginx:latest\r\n".parse::<Image>().unwrap());
+        }
+
+        #[test]
+        fn empty() {
+            assert!(matches!("".parse::<Image>(), Err(FromStrError::Empty)));
+            assert!(matches!("\n".parse::<Image>(), Err(FromStrError::Empty)));
+        }
+
+        #[test]
+        fn whitespace() {
+            let got = "nginx :latest".parse::<Image>().unwrap_err();
+
+            assert_eq!(
+                got.to_string(),
+                "invalid character ' ' at byte offset 5 of image reference"
+            );
+
+            assert!(matches!(
+                "  ".parse::<Image>(),
+                Err(FromStrError::InvalidCharacter {
+                    character: ' ',
+                    offset: 0
+                })
+            ));
+            assert!(matches!(
+                "nginx:latest\n\n".parse::<Image>(),
+                Err(FromStrError::InvalidCharacter {
+                    character: '\n',
+                    offset: 12
+                })
+            ));
+            assert!(matches!(
+                "nginx\u{0}:latest".parse::<Image>(),
+                Err(FromStrError::InvalidCharacter {
+                    character: '\u{0}',
+                    offset: 5
+                })
+            ));
+        }
+
+        proptest::proptest! {
+            #[test]
+            fn never_panics(input in "\\PC*|[a-z0-9./:@ \t\r\n-]{0,48}") {
+                if let Ok(image) = input.parse::<Image>() {
+                    let reparsed: Image = image.to_string().parse().unwrap();
+
+                    proptest::prop_assert_eq!(image, reparsed);
+                }
+            }
+        }
+
         #[test]
         fn full_tag() {
             let expected = Image {
                 registry: Registry::Github,
-                namespace: None,
-                repository: Some("aquasecurity".to_string()),
+                repository: Some("aquasecurity".parse().unwrap()),
                 image_name: ImageName {
-                    name: "trivy".to_string(),
-                    identifier: Either::Left(Tag::Specific("0.52.0".to_string())),
+                    name: "trivy".into(),
+                    identifier: Either::Left(Tag::Specific("0.52.0".into())),
                 },
             };
 
@@ -229,11 +645,10 @@ mod tests {
 
             let expected = Image {
                 registry: Registry::Quay,
-                namespace: None,
-                repository: Some("openshift-community-operators".to_string()),
+                repository: Some("openshift-community-operators".parse().unwrap()),
                 image_name: ImageName {
-                    name: "external-secrets-operator".to_string(),
-                    identifier: Either::Left(Tag::Specific("v0.9.9".to_string())),
+                    name: "external-secrets-operator".into(),
+                    identifier: Either::Left(Tag::Specific("v0.9.9".into())),
                 },
             };
 
@@ -248,10 +663,9 @@ mod tests {
         fn just_name() {
             let expected = Image {
                 registry: Registry::DockerHub,
-                namespace: None,
-                repository: Some("library".to_string()),
+                repository: Some("library".parse().unwrap()),
                 image_name: ImageName {
-                    name: "archlinux".to_string(),
+                    name: "archlinux".into(),
                     identifier: Either::Left(Tag::Latest),
                 },
             };
@@ -261,14 +675,28 @@ mod tests {
             assert_eq!(expected, got);
         }
 
+        #[test]
+        fn invalid_name() {
+            for input in ["ghcr.io/a/B-", "ghcr.io/a/b-", "foo/Bar", "Alpine:3.20"] {
+                assert!(
+                    matches!(
+                        input.parse::<Image>(),
+                        Err(FromStrError::ParseImageName(
+                            image_name::FromStrError::InvalidName(_)
+                        ))
+                    ),
+                    "{input}"
+                );
+            }
+        }
+
         #[test]
         fn digest() {
             let expected = Image {
                 registry: Registry::Quay,
-                namespace: None,
-                repository: Some("openshift-community-operators".to_string()),
+                repository: Some("openshift-community-operators".parse().unwrap()),
                 image_name: ImageName {
-                    name: "external-secrets-operator".to_string(),
+                    name: "external-secrets-operator".into(),
                     identifier: Either::Right(
                         "sha256:2247f14d217577b451727b3015f95e97d47941e96b99806f8589a34c43112ec3"
                             .parse()
@@ -302,11 +730,10 @@ mod tests {
 
                 let expected = Image {
                     registry: Registry::DockerHub,
-                    namespace: None,
-                    repository: Some("prom".to_string()),
+                    repository: Some("prom".parse().unwrap()),
                     image_name: ImageName {
-                        name: "prometheus".to_string(),
-                        identifier: Either::Left(Tag::Specific("v2.53.2".to_string())),
+                        name: "prometheus".into(),
+                        identifier: Either::Left(Tag::Specific("v2.53.2".into())),
                     },
                 };
 
@@ -314,6 +741,28 @@ mod tests {
 
                 assert_eq!(expected, got);
             }
+
+            /// The first component of `my.company/app` looks like a host, so
+            /// the Docker Hub organization needs the `docker.io/` prefix.
+            #[test]
+            fn dotted_organization() {
+                let expected = Image {
+                    registry: Registry::DockerHub,
+                    repository: Some("my.company".parse().unwrap()),
+                    image_name: ImageName {
+                        name: "app".into(),
+                        identifier: Either::Left(Tag::Specific("1.0".into())),
+                    },
+                };
+
+                let got = "docker.io/my.company/app:1.0".parse::<Image>().unwrap();
+                assert_eq!(expected, got);
+                assert_eq!(got.to_string(), "index.docker.io/my.company/app:1.0");
+
+                let got = "my.company/app:1.0".parse::<Image>().unwrap();
+                assert_eq!(got.registry, Registry::Custom("my.company".into()));
+                assert_eq!(got.repository, None);
+            }
         }
 
         mod redhat {
@@ -333,11 +782,10 @@ mod tests {
 
                 let expected = Image {
                     registry: Registry::RedHat,
-                    namespace: None,
                     repository: None,
                     image_name: ImageName {
-                        name: "ubi8".to_string(),
-                        identifier: Either::Left(Tag::Specific("8.9".to_string())),
+                        name: "ubi8".into(),
+                        identifier: Either::Left(Tag::Specific("8.9".into())),
                     },
                 };
 
@@ -364,11 +812,10 @@ mod tests {
 
                 let expected = Image {
                     registry: Registry::K8s,
-                    namespace: None,
-                    repository: Some("autoscaling".to_string()),
+                    repository: Some("autoscaling".parse().unwrap()),
                     image_name: ImageName {
-                        name: "vpa-recommender".to_string(),
-                        identifier: Either::Left(Tag::Specific("1.1.2".to_string())),
+                        name: "vpa-recommender".into(),
+                        identifier: Either::Left(Tag::Specific("1.1.2".into())),
                     },
                 };
 
@@ -395,18 +842,486 @@ mod tests {
 
                 let expected = Image {
                     registry: Registry::Github,
-                    namespace: Some("sigstore".to_string()),
-                    repository: Some("cosign".to_string()),
+                    repository: Some("sigstore/cosign".parse().unwrap()),
                     image_name: ImageName {
-                        name: "cosign".to_string(),
-                        identifier: Either::Left(Tag::Specific("v2.4.0".to_string())),
+                        name: "cosign".into(),
+                        identifier: Either::Left(Tag::Specific("v2.4.0".into())),
                     },
                 };
 
                 let got = INPUT.parse::<Image>().unwrap();
 
                 assert_eq!(expected, got);
+                assert_eq!(got.namespace(), Some("sigstore"));
+                assert_eq!(got.repository(), Some("cosign"));
             }
         }
     }
+
+    mod parse_with_default_registry {
+        use either::Either;
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            image::{
+                image_name::ImageName,
+                FromStrError,
+            },
+            Image,
+            Registry,
+            Tag,
+        };
+
+        #[test]
+        fn dotted_organization() {
+            let expected = Image {
+                registry: Registry::DockerHub,
+                repository: Some("my.company".parse().unwrap()),
+                image_name: ImageName {
+                    name: "app".into(),
+                    identifier: Either::Left(Tag::Specific("1.0".into())),
+                },
+            };
+
+            let got = Image::parse_with_default_registry("my.company/app:1.0", Registry::DockerHub)
+                .unwrap();
+
+            assert_eq!(expected, got);
+            assert_eq!(
+                got,
+                "docker.io/my.company/app:1.0".parse::<Image>().unwrap(),
+                "same as with the docker.io/ prefix"
+            );
+        }
+
+        #[test]
+        fn nested_repository() {
+            let got = Image::parse_with_default_registry(
+                "registry.example.com/team/app:1.0",
+                Registry::Github,
+            )
+            .unwrap();
+
+            assert_eq!(got.registry, Registry::Github);
+            assert_eq!(
+                got.repository_path(),
+                "registry.example.com/team/app",
+                "the host-like component is part of the repository"
+            );
+        }
+
+        #[test]
+        fn single_component() {
+            let hub =
+                Image::parse_with_default_registry("alpine:3.20", Registry::DockerHub).unwrap();
+            assert_eq!(hub, "alpine:3.20".parse::<Image>().unwrap());
+
+            let k8s = Image::parse_with_default_registry("pause:3.9", Registry::K8s).unwrap();
+            assert_eq!(k8s, "registry.k8s.io/pause:3.9".parse::<Image>().unwrap());
+        }
+
+        #[test]
+        fn invalid() {
+            assert!(matches!(
+                Image::parse_with_default_registry("\n", Registry::DockerHub),
+                Err(FromStrError::Empty)
+            ));
+            assert!(matches!(
+                Image::parse_with_default_registry("My.Company/app", Registry::DockerHub),
+                Err(FromStrError::ParseRepository(_))
+            ));
+        }
+    }
+
+    mod round_trip {
+        use std::collections::HashSet;
+
+        use either::Either;
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            image::{
+                image_name::{
+                    self,
+                    ImageName,
+                },
+                registry::Registry,
+                repository::Repository,
+                FromStrError,
+            },
+            Image,
+            Tag,
+        };
+
+        /// References used throughout the test suite and a few more corners
+        /// like aliases, custom registries with ports and digests.
+        const CORPUS: &[&str] = &[
+            "alpine",
+            "alpine:3.20",
+            "alpine:latest",
+            "archlinux:latest",
+            "nginx:latest",
+            "prom/prometheus",
+            "prom/prometheus:latest",
+            "prom/prometheus:v2.53.2",
+            "docker.io/alpine",
+            "docker.io/library/alpine:3.20",
+            "index.docker.io/grafana/grafana:11.2.0",
+            "ghcr.io/aquasecurity/trivy:0.52.0",
+            "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+            "ghcr.io/sigstore/rekor-cli:v1.3.6",
+            "quay.io/openshift-community-operators/external-secrets-operator:v0.9.9",
+            "registry.access.redhat.com/ubi8:8.9",
+            "registry.access.redhat.com/ubi8:sbom",
+            "registry.k8s.io/pause:3.9",
+            "registry.k8s.io/pause@sha256:7031c1b2",
+            "mcr.microsoft.com/dotnet/runtime:8.0",
+            "gcr.io/distroless/static:nonroot",
+            "localhost:5000/app:dev",
+            "localhost/app",
+            "registry.example.com:8443/team/project/app@sha256:\
+             e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+        ];
+
+        fn assert_round_trip(input: &str) {
+            let image: Image = input.parse().unwrap();
+            let displayed = image.to_string();
+            let reparsed: Image = displayed.parse().unwrap();
+
+            assert_eq!(image, reparsed, "{input} displayed as {displayed}");
+            assert_eq!(displayed, reparsed.to_string());
+        }
+
+        #[test]
+        fn corpus() {
+            for input in CORPUS {
+                assert_round_trip(input);
+            }
+        }
+
+        #[test]
+        fn built_without_repository() {
+            let image = Image::new(
+                Registry::DockerHub,
+                ImageName::new("alpine", Either::Left(Tag::Specific("3".into()))),
+            );
+            let reparsed: Image = image.to_string().parse().unwrap();
+
+            assert_eq!(reparsed.repository, Some(Repository::library()));
+            assert_eq!(image, reparsed);
+            assert_eq!(
+                HashSet::from([image.clone()]),
+                HashSet::from([reparsed.clone()])
+            );
+
+            // Only Docker Hub has the implicit `library` repository.
+            let pause = Image::new(
+                Registry::K8s,
+                ImageName::new("pause", Either::Left(Tag::Specific("3.9".into()))),
+            );
+            assert_ne!(pause, pause.clone().with_repository("library").unwrap());
+        }
+
+        #[test]
+        fn aliases_are_equal() {
+            let expected: Image = "alpine".parse().unwrap();
+
+            for input in ["docker.io/alpine", "index.docker.io/library/alpine:latest"] {
+                assert_eq!(expected, input.parse::<Image>().unwrap());
+            }
+        }
+
+        #[test]
+        fn digest_display() {
+            let image: Image = "registry.k8s.io/pause@sha256:7031c1b2".parse().unwrap();
+
+            assert_eq!(image.to_string(), "registry.k8s.io/pause@sha256:7031c1b2");
+            assert_eq!(image.image_name.to_string(), "pause@sha256:7031c1b2");
+        }
+
+        #[test]
+        fn rejected() {
+            for (input, expected) in [
+                (
+                    "alpine:3:20",
+                    image_name::FromStrError::MultipleSeparators(':'),
+                ),
+                (
+                    "alpine@sha256:a@sha256:b",
+                    image_name::FromStrError::MultipleSeparators('@'),
+                ),
+                ("alpine:", image_name::FromStrError::EmptyTag),
+                ("alpine@", image_name::FromStrError::MissingDigest),
+                ("ghcr.io/:latest", image_name::FromStrError::MissingNameTag),
+                (
+                    "ghcr.io/a/b:tag@sha256:7031c1b2",
+                    image_name::FromStrError::TagAndDigest,
+                ),
+                (
+                    "nginx:1.27@sha256:\
+                     e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+                    image_name::FromStrError::TagAndDigest,
+                ),
+            ] {
+                let got = input.parse::<Image>().unwrap_err();
+
+                assert!(
+                    matches!(&got, FromStrError::ParseImageName(e) if e.to_string() == expected.to_string()),
+                    "{input}: {got}"
+                );
+            }
+        }
+
+        proptest::proptest! {
+            #[test]
+            fn generated(
+                registry in proptest::option::of("(docker\\.io|ghcr\\.io|quay\\.io|localhost:5000|[a-z]{1,8}\\.example\\.com)"),
+                path in proptest::collection::vec("[a-z0-9][a-z0-9._-]{0,8}", 1..=3),
+                identifier in "(:[a-zA-Z0-9_][a-zA-Z0-9._-]{0,16}|@sha256:[0-9a-f]{8,64})?",
+            ) {
+                let registry = registry.map(|registry| format!("{registry}/")).unwrap_or_default();
+                let input = format!("{registry}{}{identifier}", path.join("/"));
+
+                if let Ok(image) = input.parse::<Image>() {
+                    let displayed = image.to_string();
+                    let reparsed: Image = displayed.parse().unwrap();
+
+                    proptest::prop_assert_eq!(&image, &reparsed);
+                    proptest::prop_assert_eq!(displayed, reparsed.to_string());
+                }
+            }
+        }
+    }
+
+    mod urls {
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            image::Scheme,
+            Image,
+        };
+
+        #[test]
+        fn manifest_url_per_registry() {
+            let cases = [
+                (
+                    "alpine:3.20",
+                    "https://registry-1.docker.io/v2/library/alpine/manifests/3.20",
+                ),
+                (
+                    "docker.io/grafana/grafana:11.2.0",
+                    "https://registry-1.docker.io/v2/grafana/grafana/manifests/11.2.0",
+                ),
+                (
+                    "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+                    "https://ghcr.io/v2/sigstore/cosign/cosign/manifests/v2.4.0",
+                ),
+                (
+                    "gcr.io/distroless/static:nonroot",
+                    "https://gcr.io/v2/distroless/static/manifests/nonroot",
+                ),
+                (
+                    "registry.k8s.io/pause:3.9",
+                    "https://registry.k8s.io/v2/pause/manifests/3.9",
+                ),
+                (
+                    "quay.io/prometheus/node-exporter:latest",
+                    "https://quay.io/v2/prometheus/node-exporter/manifests/latest",
+                ),
+                (
+                    "registry.access.redhat.com/ubi8:8.9",
+                    "https://registry.access.redhat.com/v2/ubi8/manifests/8.9",
+                ),
+                (
+                    "mcr.microsoft.com/playwright/python:v1.45.0-jammy",
+                    "https://mcr.microsoft.com/v2/playwright/python/manifests/v1.45.0-jammy",
+                ),
+            ];
+
+            for (image, expected) in cases {
+                let image: Image = image.parse().unwrap();
+
+                assert_eq!(
+                    image.manifest_url(Scheme::Https).unwrap().as_str(),
+                    expected
+                );
+            }
+        }
+
+        #[test]
+        fn blob_url() {
+            let image: Image = "ghcr.io/sigstore/cosign/cosign:v2.4.0".parse().unwrap();
+            let digest = "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f"
+                .parse()
+                .unwrap();
+
+            assert_eq!(
+                image.blob_url(Scheme::Http, &digest).unwrap().as_str(),
+                "http://ghcr.io/v2/sigstore/cosign/cosign/blobs/\
+                 sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f"
+            );
+        }
+
+        #[test]
+        fn digest_reference() {
+            let image: Image = "registry.k8s.io/pause@sha256:7031c1b2".parse().unwrap();
+
+            assert_eq!(image.reference(), "sha256:7031c1b2");
+            assert_eq!(
+                image.manifest_url(Scheme::Https).unwrap().as_str(),
+                "https://registry.k8s.io/v2/pause/manifests/sha256:7031c1b2"
+            );
+        }
+
+        #[test]
+        fn percent_encoding() {
+            let image: Image = "registry.k8s.io/pause:3.9#1".parse().unwrap();
+
+            assert_eq!(image.repository_path(), "pause");
+            assert_eq!(
+                image.manifest_url(Scheme::Https).unwrap().as_str(),
+                "https://registry.k8s.io/v2/pause/manifests/3.9%231"
+            );
+        }
+    }
+
+    mod path_segments {
+        use either::Either;
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            image,
+            image::{
+                image_name,
+                repository,
+                FromStrError,
+                Scheme,
+                UrlError,
+            },
+            Image,
+            ImageName,
+            Registry,
+            Tag,
+        };
+
+        #[test]
+        fn tag_with_plus() {
+            let image: Image = "registry.k8s.io/pause:1.0.0+build.7".parse().unwrap();
+
+            assert_eq!(
+                image.manifest_url(Scheme::Https).unwrap().as_str(),
+                "https://registry.k8s.io/v2/pause/manifests/1.0.0+build.7"
+            );
+        }
+
+        #[test]
+        fn dot_segments_can_not_escape() {
+            assert!(matches!(
+                "ghcr.io/../../image:latest".parse::<Image>(),
+                Err(FromStrError::ParseRepository(
+                    repository::FromStrError::InvalidComponent(_)
+                ))
+            ));
+
+            // The image name is not part of the repository, it is rejected
+            // on its own when parsed and again when a URL is built.
+            assert!(matches!(
+                "ghcr.io/sigstore/..:latest".parse::<Image>(),
+                Err(FromStrError::ParseImageName(
+                    image_name::FromStrError::InvalidName(_)
+                ))
+            ));
+
+            let image = Image {
+                image_name: ImageName::new("..", Either::Left(Tag::Latest)),
+                ..image!("ghcr.io/sigstore/cosign:latest")
+            };
+
+            assert_eq!(
+                image.manifest_url(Scheme::Https),
+                Err(UrlError::DotSegment("..".to_string()))
+            );
+        }
+
+        #[test]
+        fn slash_in_component() {
+            let image: Image = "registry.k8s.io/pause:3.9".parse().unwrap();
+
+            assert_eq!(
+                image.with_repository("other/../pause"),
+                Err(repository::FromStrError::InvalidComponent("..".to_string()))
+            );
+        }
+
+        #[test]
+        fn whitespace_and_control_characters() {
+            for (tag, character) in [("3.9 ", ' '), ("3\t9", '\t'), ("3.9\u{7f}", '\u{7f}')] {
+                // The parser rejects these characters, so the image is built by
+                // hand.
+                let image = Image::new(
+                    Registry::K8s,
+                    ImageName::new("pause", Either::Left(Tag::Specific(tag.into()))),
+                );
+
+                assert_eq!(
+                    image.manifest_url(Scheme::Https),
+                    Err(UrlError::InvalidCharacter {
+                        segment: tag.to_string(),
+                        character,
+                    })
+                );
+            }
+        }
+
+        #[test]
+        fn official_image_without_repository() {
+            let image = Image::new(
+                Registry::DockerHub,
+                ImageName::new("alpine", Either::Left(Tag::Specific("3.20".into()))),
+            );
+
+            assert_eq!(image.repository, None);
+            assert_eq!(image.repository_path(), "library/alpine");
+            assert_eq!(
+                image.manifest_url(Scheme::Https).unwrap().as_str(),
+                "https://registry-1.docker.io/v2/library/alpine/manifests/3.20"
+            );
+            assert_eq!(image.to_string(), "index.docker.io/library/alpine:3.20");
+        }
+    }
+
+    mod serde {
+        use pretty_assertions::assert_eq;
+
+        use crate::{
+            Digest,
+            Image,
+        };
+
+        #[test]
+        fn round_trip() {
+            const INPUT: &str = "ghcr.io/sigstore/cosign/cosign:v2.4.0";
+
+            let image: Image = INPUT.parse().unwrap();
+
+            let serialized = serde_json::to_string(&image).unwrap();
+            assert_eq!(format!("\"{INPUT}\""), serialized);
+
+            let deserialized: Image = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(image, deserialized);
+        }
+
+        #[test]
+        fn digest_is_a_plain_string() {
+            const INPUT: &str =
+                "sha256:2247f14d217577b451727b3015f95e97d47941e96b99806f8589a34c43112ec3";
+
+            let digest: Digest = INPUT.parse().unwrap();
+
+            assert_eq!(
+                format!("\"{INPUT}\""),
+                serde_json::to_string(&digest).unwrap()
+            );
+        }
+    }
 }
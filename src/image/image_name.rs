@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use either::Either;
 
 pub mod digest;
@@ -6,18 +8,24 @@ pub mod tag;
 use digest::Digest;
 use tag::Tag;
 
+use super::repository::is_valid_component;
+
 #[derive(Debug)]
 pub enum FromStrError {
     MissingNameDigest,
     MissingNameTag,
+    InvalidName(String),
+    TagAndDigest,
     MissingDigest,
+    EmptyTag,
+    MultipleSeparators(char),
     ParseDigest(digest::FromStrError),
     ParseTag(tag::FromStrError),
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub struct ImageName {
-    pub name: String,
+    pub name: Arc<str>,
     pub identifier: Either<Tag, Digest>,
 }
 
@@ -26,7 +34,15 @@ impl std::fmt::Display for FromStrError {
         match self {
             Self::MissingNameDigest => f.write_str("missing name and digest"),
             Self::MissingNameTag => f.write_str("missing name and tag"),
+            Self::InvalidName(name) => write!(f, "invalid image name: {name:?}"),
+            Self::TagAndDigest => {
+                f.write_str("a tag and a digest together are not supported, use only the digest")
+            }
             Self::MissingDigest => f.write_str("missing digest"),
+            Self::EmptyTag => f.write_str("empty tag"),
+            Self::MultipleSeparators(separator) => {
+                write!(f, "more than one {separator} separator")
+            }
             Self::ParseDigest(e) => write!(f, "error parsing digest: {e}"),
             Self::ParseTag(e) => write!(f, "error parsing tag: {e}"),
         }
@@ -37,15 +53,20 @@ impl std::error::Error for FromStrError {}
 
 impl std::fmt::Display for ImageName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{image_name}:{identifier}",
-            image_name = self.name,
-            identifier = match &self.identifier {
-                Either::Left(tag) => tag.to_string(),
-                Either::Right(digest) => digest.to_string(),
-            }
-        )
+        match &self.identifier {
+            Either::Left(tag) => write!(f, "{}:{tag}", self.name),
+            Either::Right(digest) => write!(f, "{}@{digest}", self.name),
+        }
+    }
+}
+
+impl ImageName {
+    #[must_use]
+    pub fn new(name: impl Into<Arc<str>>, identifier: Either<Tag, Digest>) -> Self {
+        Self {
+            name: name.into(),
+            identifier,
+        }
     }
 }
 
@@ -53,34 +74,61 @@ impl std::str::FromStr for ImageName {
     type Err = FromStrError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let has_digest = s.contains('@');
+        // Anything after a second separator would be dropped and the name
+        // would not display as it was parsed.
+        if let Some((name, digest)) = s.split_once('@') {
+            if digest.contains('@') {
+                return Err(Self::Err::MultipleSeparators('@'));
+            }
+
+            if name.is_empty() {
+                return Err(Self::Err::MissingNameDigest);
+            }
 
-        if has_digest {
-            let parts: Vec<&str> = s.split('@').collect();
-            let name = (*parts.first().ok_or(Self::Err::MissingNameDigest)?).to_string();
+            // `name:tag@digest` would be requested by digest and displayed
+            // without the tag, so it is not accepted instead of silently
+            // dropping the tag.
+            if name.contains(':') {
+                return Err(Self::Err::TagAndDigest);
+            }
+
+            if !is_valid_component(name) {
+                return Err(Self::Err::InvalidName(name.to_string()));
+            }
 
-            let digest = parts
-                .get(1)
-                .ok_or(Self::Err::MissingDigest)?
-                .parse()
-                .map_err(Self::Err::ParseDigest)?;
+            if digest.is_empty() {
+                return Err(Self::Err::MissingDigest);
+            }
+
+            let digest = digest.parse().map_err(Self::Err::ParseDigest)?;
 
             Ok(Self {
-                name,
+                name: name.into(),
                 identifier: Either::Right(digest),
             })
         } else {
-            let parts: Vec<&str> = s.split(':').collect();
-            let name = (*parts.first().ok_or(Self::Err::MissingNameTag)?).to_string();
+            let (name, tag) = s.split_once(':').unwrap_or((s, "latest"));
+
+            if tag.contains(':') {
+                return Err(Self::Err::MultipleSeparators(':'));
+            }
+
+            if name.is_empty() {
+                return Err(Self::Err::MissingNameTag);
+            }
+
+            if !is_valid_component(name) {
+                return Err(Self::Err::InvalidName(name.to_string()));
+            }
+
+            if tag.is_empty() {
+                return Err(Self::Err::EmptyTag);
+            }
 
-            let tag = parts
-                .get(1)
-                .unwrap_or(&"latest")
-                .parse()
-                .map_err(Self::Err::ParseTag)?;
+            let tag = tag.parse().map_err(Self::Err::ParseTag)?;
 
             Ok(Self {
-                name,
+                name: name.into(),
                 identifier: Either::Left(tag),
             })
         }
@@ -1,8 +1,41 @@
+use std::sync::Arc;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest as _,
+    Sha256,
+    Sha512,
+};
+
 #[derive(Debug)]
 pub enum FromStrError {}
 
-#[derive(Debug, PartialEq, Clone, Eq, Hash)]
-pub struct Digest(String);
+/// The algorithms content can be verified with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+/// The algorithm of a digest is not one of [`Algorithm`], content addressed
+/// by it can not be verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedDigestAlgorithm(pub Digest);
+
+/// Hashes content incrementally with the algorithm of a digest.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone)]
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Digest(Arc<str>);
 
 impl std::fmt::Display for FromStrError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -12,6 +45,20 @@ impl std::fmt::Display for FromStrError {
 
 impl std::error::Error for FromStrError {}
 
+impl std::fmt::Display for UnsupportedDigestAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsupported digest algorithm of {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedDigestAlgorithm {}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.prefix())
+    }
+}
+
 impl std::fmt::Display for Digest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -22,6 +69,129 @@ impl std::str::FromStr for Digest {
     type Err = FromStrError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.to_string()))
+        Ok(Self(s.into()))
+    }
+}
+
+impl Digest {
+    /// Computes the sha256 digest of the given content.
+    #[must_use]
+    pub fn sha256(content: &[u8]) -> Self {
+        Self(format!("sha256:{}", hex::encode(Sha256::digest(content))).into())
+    }
+
+    /// Computes the sha512 digest of the given content.
+    #[must_use]
+    pub fn sha512(content: &[u8]) -> Self {
+        Self(format!("sha512:{}", hex::encode(Sha512::digest(content))).into())
+    }
+
+    /// Returns the algorithm the digest was computed with, the part before
+    /// the colon. The prefix is matched case insensitive.
+    ///
+    /// # Errors
+    /// Returns an error if the algorithm is not one of [`Algorithm`].
+    pub fn algorithm(&self) -> Result<Algorithm, UnsupportedDigestAlgorithm> {
+        let (algorithm, _) = self.0.split_once(':').unwrap_or_default();
+
+        [Algorithm::Sha256, Algorithm::Sha512]
+            .into_iter()
+            .find(|known| algorithm.trim().eq_ignore_ascii_case(known.prefix()))
+            .ok_or_else(|| UnsupportedDigestAlgorithm(self.clone()))
+    }
+
+    /// Returns the digest in its canonical lowercase form. Registries and
+    /// users sometimes differ in the casing of the algorithm prefix or the
+    /// hex encoded part, which both refer to the same content.
+    #[must_use]
+    pub fn normalized(&self) -> Self {
+        Self(self.0.trim().to_ascii_lowercase().into())
+    }
+
+    /// Returns true if both digests refer to the same content after
+    /// normalization.
+    #[must_use]
+    pub fn is_equivalent(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+impl Algorithm {
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    /// Computes the digest of `content` with this algorithm.
+    #[must_use]
+    pub fn digest(self, content: &[u8]) -> Digest {
+        match self {
+            Self::Sha256 => Digest::sha256(content),
+            Self::Sha512 => Digest::sha512(content),
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl Hasher {
+    pub(crate) fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Self::Sha512(Sha512::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, content: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(content),
+            Self::Sha512(hasher) => hasher.update(content),
+        }
+    }
+
+    /// Returns the digest of the content that was fed to the hasher.
+    pub(crate) fn finalize(self) -> Digest {
+        let (algorithm, hash) = match self {
+            Self::Sha256(hasher) => (Algorithm::Sha256, hex::encode(hasher.finalize())),
+            Self::Sha512(hasher) => (Algorithm::Sha512, hex::encode(hasher.finalize())),
+        };
+
+        Digest(format!("{algorithm}:{hash}").into())
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn algorithm() {
+        assert_eq!(Digest::sha256(b"").algorithm().unwrap(), Algorithm::Sha256);
+        assert_eq!(
+            "SHA512:AB".parse::<Digest>().unwrap().algorithm().unwrap(),
+            Algorithm::Sha512
+        );
+
+        let blake3: Digest = "blake3:af1349b9".parse().unwrap();
+        assert_eq!(
+            blake3.algorithm().unwrap_err(),
+            UnsupportedDigestAlgorithm(blake3)
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn hasher() {
+        for algorithm in [Algorithm::Sha256, Algorithm::Sha512] {
+            let mut hasher = Hasher::new(algorithm);
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+
+            assert_eq!(hasher.finalize(), algorithm.digest(b"hello world"));
+        }
     }
 }
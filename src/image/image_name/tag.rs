@@ -1,10 +1,12 @@
+use std::sync::Arc;
+
 #[derive(Debug)]
 pub enum FromStrError {}
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub enum Tag {
     Latest,
-    Specific(String),
+    Specific(Arc<str>),
 }
 
 impl std::fmt::Display for FromStrError {
@@ -30,7 +32,7 @@ impl std::str::FromStr for Tag {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "latest" => Ok(Self::Latest),
-            s => Ok(Self::Specific(s.to_string())),
+            s => Ok(Self::Specific(s.into())),
         }
     }
 }
@@ -1,3 +1,11 @@
+use std::sync::Arc;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use url::Url;
+
 #[derive(Debug)]
 pub enum FromStrError {
     UnkownRegistry(String),
@@ -12,6 +20,9 @@ pub enum Registry {
     Quay,
     RedHat,
     Microsoft,
+
+    /// Any other registry, identified by its host and optional port.
+    Custom(Arc<str>),
 }
 
 impl std::fmt::Display for FromStrError {
@@ -43,12 +54,40 @@ impl std::str::FromStr for Registry {
             "registry.access.redhat.com" => Ok(Registry::RedHat),
             "registry.k8s.io" => Ok(Registry::K8s),
 
+            // Same heuristic as the docker CLI: a component is only a
+            // registry if it looks like a host.
+            _ if s.contains('.') || s.contains(':') || s == "localhost" => {
+                Ok(Registry::Custom(s.into()))
+            }
+
             _ => Err(FromStrError::UnkownRegistry(s.to_string())),
         }
     }
 }
 
+impl Serialize for Registry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.registry_domain())
+    }
+}
+
+impl<'de> Deserialize<'de> for Registry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+
+        string.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl Registry {
+    /// Returns the canonical domain of the registry as used in image
+    /// references.
     #[must_use]
     pub fn registry_domain(&self) -> &str {
         match self {
@@ -59,14 +98,159 @@ impl Registry {
             Self::Microsoft => "mcr.microsoft.com",
             Self::Quay => "quay.io",
             Self::RedHat => "registry.access.redhat.com",
+            Self::Custom(host) => host,
         }
     }
 
+    /// Returns the host serving the distribution API of the registry. This
+    /// only differs from [`Registry::registry_domain`] for Docker Hub whose
+    /// API is served at `registry-1.docker.io` like the docker CLI uses.
+    #[must_use]
+    pub fn api_host(&self) -> &str {
+        match self {
+            Self::DockerHub => "registry-1.docker.io",
+            _ => self.registry_domain(),
+        }
+    }
+
+    /// Returns the base URL requests to the distribution API are relative
+    /// to, see [`Registry::api_host`]. Can be overridden per registry with
+    /// [`crate::ClientBuilder::registry_api_base`].
+    ///
+    /// # Errors
+    /// Returns an error if the host of a custom registry is not a valid host.
+    pub fn api_base(&self) -> Result<Url, url::ParseError> {
+        Url::parse(&format!("https://{}/", self.api_host()))
+    }
+
+    /// Returns the registry served at `host`. Hosts of registries that are
+    /// not known are returned as [`Registry::Custom`].
+    #[must_use]
+    pub fn try_from_host(host: &str) -> Self {
+        host.parse().unwrap_or_else(|_| Self::Custom(host.into()))
+    }
+
     #[must_use]
     pub fn needs_authentication(&self) -> bool {
         match self {
             Self::DockerHub | Self::Github | Self::Quay => true,
-            Self::RedHat | Self::K8s | Self::Google | Self::Microsoft => false,
+            Self::RedHat | Self::K8s | Self::Google | Self::Microsoft | Self::Custom(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    mod serde {
+        use pretty_assertions::assert_eq;
+        use serde::{
+            Deserialize,
+            Serialize,
+        };
+
+        use crate::Registry;
+
+        fn all() -> Vec<Registry> {
+            vec![
+                Registry::DockerHub,
+                Registry::Github,
+                Registry::Google,
+                Registry::K8s,
+                Registry::Quay,
+                Registry::RedHat,
+                Registry::Microsoft,
+                Registry::Custom("localhost:5000".into()),
+            ]
+        }
+
+        #[test]
+        fn round_trip() {
+            for registry in all() {
+                let json = serde_json::to_string(&registry).unwrap();
+                assert_eq!(json, format!("\"{}\"", registry.registry_domain()));
+
+                let got: Registry = serde_json::from_str(&json).unwrap();
+                assert_eq!(got, registry);
+            }
+        }
+
+        #[test]
+        fn aliases() {
+            let got: Registry = serde_json::from_str("\"docker.io\"").unwrap();
+
+            assert_eq!(got, Registry::DockerHub);
+        }
+
+        #[test]
+        fn not_a_host() {
+            assert!(serde_json::from_str::<Registry>("\"prometheus\"").is_err());
+        }
+
+        #[test]
+        fn yaml_config() {
+            #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+            struct Config {
+                registries: Vec<Registry>,
+            }
+
+            let config = Config {
+                registries: vec![
+                    Registry::Quay,
+                    Registry::Custom("registry.example.com".into()),
+                ],
+            };
+
+            let yaml = serde_yaml::to_string(&config).unwrap();
+            assert_eq!(yaml, "registries:\n- quay.io\n- registry.example.com\n");
+
+            let got: Config = serde_yaml::from_str(&yaml).unwrap();
+            assert_eq!(got, config);
+        }
+    }
+
+    mod api_base {
+        use pretty_assertions::assert_eq;
+
+        use crate::Registry;
+
+        #[test]
+        fn dockerhub() {
+            assert_eq!(
+                Registry::DockerHub.api_base().unwrap().as_str(),
+                "https://registry-1.docker.io/"
+            );
+            assert_eq!(Registry::DockerHub.registry_domain(), "index.docker.io");
+        }
+
+        #[test]
+        fn custom() {
+            assert_eq!(
+                Registry::Custom("localhost:5000".into())
+                    .api_base()
+                    .unwrap()
+                    .as_str(),
+                "https://localhost:5000/"
+            );
+        }
+    }
+
+    mod try_from_host {
+        use pretty_assertions::assert_eq;
+
+        use crate::Registry;
+
+        #[test]
+        fn known() {
+            assert_eq!(Registry::try_from_host("ghcr.io"), Registry::Github);
+        }
+
+        #[test]
+        fn custom() {
+            assert_eq!(
+                Registry::try_from_host("mirror"),
+                Registry::Custom("mirror".into())
+            );
         }
     }
 }
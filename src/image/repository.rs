@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FromStrError {
+    Empty,
+    EmptyComponent,
+    InvalidComponent(String),
+}
+
+/// The path of an image between the registry and the image name, for example
+/// `sigstore/cosign` of `ghcr.io/sigstore/cosign/cosign:v2.4.0`. Every
+/// component follows the grammar of the distribution specification:
+/// lowercase letters and digits, separated by a `.`, one or two `_` or any
+/// number of `-`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub struct Repository(Arc<str>);
+
+impl std::fmt::Display for FromStrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty repository"),
+            Self::EmptyComponent => f.write_str("empty repository path component"),
+            Self::InvalidComponent(s) => write!(f, "invalid repository path component: {s:?}"),
+        }
+    }
+}
+
+impl std::error::Error for FromStrError {}
+
+impl Repository {
+    /// Returns the components of the path, for example `sigstore` and
+    /// `cosign` for `sigstore/cosign`.
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/')
+    }
+
+    /// Returns the path without its last component or `None` if the path has
+    /// only one component.
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        self.0
+            .rsplit_once('/')
+            .map(|(parent, _)| Self(parent.into()))
+    }
+
+    /// Returns the last component of the path.
+    #[must_use]
+    pub fn last(&self) -> &str {
+        self.0.rsplit('/').next().unwrap_or(&self.0)
+    }
+
+    /// Appends `path`, one or more components separated by slashes.
+    ///
+    /// # Errors
+    /// Returns an error if a component of `path` is not valid.
+    pub fn join(&self, path: &str) -> Result<Self, FromStrError> {
+        let path: Self = path.parse()?;
+
+        Ok(Self(format!("{self}/{path}").into()))
+    }
+
+    /// The repository of official Docker Hub images.
+    pub(super) fn library() -> Self {
+        Self("library".into())
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Repository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::str::FromStr for Repository {
+    type Err = FromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(FromStrError::Empty);
+        }
+
+        for component in s.split('/') {
+            if component.is_empty() {
+                return Err(FromStrError::EmptyComponent);
+            }
+
+            if !is_valid_component(component) {
+                return Err(FromStrError::InvalidComponent(component.to_string()));
+            }
+        }
+
+        Ok(Self(s.into()))
+    }
+}
+
+impl TryFrom<&str> for Repository {
+    type Error = FromStrError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl TryFrom<String> for Repository {
+    type Error = FromStrError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Serialize for Repository {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Repository {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+
+        string.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*`, which image names follow as well.
+pub(super) fn is_valid_component(component: &str) -> bool {
+    let bytes = component.as_bytes();
+    let alphanumeric = |b: &u8| b.is_ascii_lowercase() || b.is_ascii_digit();
+
+    let mut index = 0;
+
+    loop {
+        let start = index;
+        while bytes.get(index).is_some_and(alphanumeric) {
+            index += 1;
+        }
+
+        if index == start {
+            return false;
+        }
+
+        let Some(separator) = bytes.get(index) else {
+            return true;
+        };
+
+        match separator {
+            b'.' => index += 1,
+            b'_' => {
+                index += if bytes.get(index + 1) == Some(&b'_') {
+                    2
+                } else {
+                    1
+                }
+            }
+            b'-' => {
+                while bytes.get(index) == Some(&b'-') {
+                    index += 1;
+                }
+            }
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn valid() {
+        for input in [
+            "library",
+            "sigstore/cosign",
+            "a/b/c",
+            "openshift-community-operators",
+            "my.org",
+            "a_b",
+            "a__b",
+            "a---b",
+            "0",
+        ] {
+            assert_eq!(input.parse::<Repository>().unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for (input, expected) in [
+            ("", FromStrError::Empty),
+            ("a//b", FromStrError::EmptyComponent),
+            ("a/", FromStrError::EmptyComponent),
+            ("..", FromStrError::InvalidComponent("..".to_string())),
+            ("a/./b", FromStrError::InvalidComponent(".".to_string())),
+            (
+                "Library",
+                FromStrError::InvalidComponent("Library".to_string()),
+            ),
+            ("a___b", FromStrError::InvalidComponent("a___b".to_string())),
+            ("a..b", FromStrError::InvalidComponent("a..b".to_string())),
+            ("-a", FromStrError::InvalidComponent("-a".to_string())),
+            ("a-", FromStrError::InvalidComponent("a-".to_string())),
+            ("a b", FromStrError::InvalidComponent("a b".to_string())),
+        ] {
+            assert_eq!(input.parse::<Repository>(), Err(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        let repository: Repository = "sigstore/cosign".parse().unwrap();
+
+        assert_eq!(
+            repository.components().collect::<Vec<_>>(),
+            ["sigstore", "cosign"]
+        );
+        assert_eq!(repository.last(), "cosign");
+        assert_eq!(repository.parent(), Some("sigstore".parse().unwrap()));
+        assert_eq!(repository.parent().unwrap().parent(), None);
+        assert_eq!(
+            repository.join("rekor/cli").unwrap().as_str(),
+            "sigstore/cosign/rekor/cli"
+        );
+        assert!(repository.join("../other").is_err());
+    }
+
+    #[test]
+    fn serde() {
+        let repository: Repository = serde_json::from_str("\"sigstore/cosign\"").unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&repository).unwrap(),
+            "\"sigstore/cosign\""
+        );
+        assert!(serde_json::from_str::<Repository>("\"../x\"").is_err());
+    }
+}
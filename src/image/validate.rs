@@ -0,0 +1,378 @@
+//! Validation of image references in constant contexts, used by
+//! [`crate::image!`]. Follows [`crate::Image`]'s `FromStr` implementation
+//! step by step, a reference that is valid here parses without errors.
+
+/// Parses an image reference at runtime after checking it at compile time.
+/// An invalid reference is a compile error naming the problem:
+///
+/// ```
+/// use docker_registry_client::image;
+///
+/// let image = image!("ghcr.io/sigstore/cosign/cosign:v2.4.0");
+/// assert_eq!(image.repository_path(), "sigstore/cosign/cosign");
+/// ```
+///
+/// ```compile_fail
+/// use docker_registry_client::image;
+///
+/// let image = image!("ghcr.io/sigstore/cosign:v2:4");
+/// ```
+///
+/// The argument has to be a `&str` constant. For a static use a
+/// `std::sync::LazyLock`, the reference is still checked at compile time.
+#[macro_export]
+macro_rules! image {
+    ($reference:expr) => {{
+        const REFERENCE: &str = $reference;
+        const _: () = if let ::core::result::Result::Err(error) =
+            $crate::image::validate::validate(REFERENCE)
+        {
+            ::core::panic!("{}", error);
+        };
+
+        match <$crate::Image as ::core::str::FromStr>::from_str(REFERENCE) {
+            ::core::result::Result::Ok(image) => image,
+            ::core::result::Result::Err(error) => {
+                ::core::unreachable!("{REFERENCE} was validated at compile time: {error}")
+            }
+        }
+    }};
+}
+
+/// Returns why `reference` is not a valid image reference.
+///
+/// # Errors
+/// Returns a description of the problem if the reference is not valid.
+#[doc(hidden)]
+pub const fn validate(reference: &str) -> Result<(), &'static str> {
+    let mut bytes = reference.as_bytes();
+
+    if let [rest @ .., b'\r', b'\n'] | [rest @ .., b'\n'] = bytes {
+        bytes = rest;
+    }
+
+    if bytes.is_empty() {
+        return Err("invalid image reference: empty image reference");
+    }
+
+    if has_invalid_character(bytes) {
+        return Err("invalid image reference: contains whitespace or a control character");
+    }
+
+    match count(bytes, b'/') {
+        0 => image_name(bytes),
+
+        1 => {
+            let (first, name) = split(bytes, b'/');
+
+            if !is_registry(first) {
+                if let Err(error) = repository_component(first) {
+                    return Err(error);
+                }
+            }
+
+            image_name(name)
+        }
+
+        2 | 3 => {
+            let (registry, rest) = split(bytes, b'/');
+
+            if !is_registry(registry) {
+                return Err("invalid image reference: unknown registry");
+            }
+
+            let (mut repository, mut name) = split(rest, b'/');
+
+            if count(name, b'/') == 1 {
+                let (namespace, rest) = (repository, name);
+                (repository, name) = split(rest, b'/');
+
+                if let Err(error) = repository_component(namespace) {
+                    return Err(error);
+                }
+            }
+
+            if let Err(error) = repository_component(repository) {
+                return Err(error);
+            }
+
+            image_name(name)
+        }
+
+        _ => Err("invalid image reference: more than four path components are not supported"),
+    }
+}
+
+const INVALID_NAME: &str = "invalid image reference: image names may only contain lowercase \
+                            letters and digits separated by `.`, `_`, `__` or dashes";
+
+/// Same as `ImageName::from_str`.
+const fn image_name(bytes: &[u8]) -> Result<(), &'static str> {
+    if let Some(index) = find(bytes, b'@') {
+        let (name, digest) = bytes.split_at(index);
+        let digest = digest.split_at(1).1;
+
+        return if find(digest, b'@').is_some() {
+            Err("invalid image reference: more than one @ separator")
+        } else if name.is_empty() {
+            Err("invalid image reference: missing name and digest")
+        } else if find(name, b':').is_some() {
+            Err("invalid image reference: a tag and a digest together are not supported")
+        } else if !is_component(name) {
+            Err(INVALID_NAME)
+        } else if digest.is_empty() {
+            Err("invalid image reference: missing digest")
+        } else {
+            Ok(())
+        };
+    }
+
+    let Some(index) = find(bytes, b':') else {
+        return if bytes.is_empty() {
+            Err("invalid image reference: missing name and tag")
+        } else if !is_component(bytes) {
+            Err(INVALID_NAME)
+        } else {
+            Ok(())
+        };
+    };
+
+    let (name, tag) = bytes.split_at(index);
+    let tag = tag.split_at(1).1;
+
+    if find(tag, b':').is_some() {
+        Err("invalid image reference: more than one : separator")
+    } else if name.is_empty() {
+        Err("invalid image reference: missing name and tag")
+    } else if !is_component(name) {
+        Err(INVALID_NAME)
+    } else if tag.is_empty() {
+        Err("invalid image reference: empty tag")
+    } else {
+        Ok(())
+    }
+}
+
+/// Same as `Registry::from_str`: a known registry or a component that looks
+/// like a host.
+const fn is_registry(bytes: &[u8]) -> bool {
+    find(bytes, b'.').is_some() || find(bytes, b':').is_some() || eq(bytes, b"localhost")
+}
+
+/// Same as the component grammar of `Repository::from_str`.
+const fn repository_component(bytes: &[u8]) -> Result<(), &'static str> {
+    if bytes.is_empty() {
+        return Err("invalid image reference: empty repository path component");
+    }
+
+    if is_component(bytes) {
+        Ok(())
+    } else {
+        Err(
+            "invalid image reference: repository path components may only contain lowercase \
+             letters and digits separated by `.`, `_`, `__` or dashes",
+        )
+    }
+}
+
+/// `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*`, the grammar of repository path
+/// components and image names.
+const fn is_component(bytes: &[u8]) -> bool {
+    let mut index = 0;
+
+    loop {
+        let start = index;
+        while index < bytes.len()
+            && (bytes[index].is_ascii_lowercase() || bytes[index].is_ascii_digit())
+        {
+            index += 1;
+        }
+
+        if index == start {
+            return false;
+        }
+
+        if index == bytes.len() {
+            return true;
+        }
+
+        match bytes[index] {
+            b'_' if index + 1 < bytes.len() && bytes[index + 1] == b'_' => index += 2,
+            b'.' | b'_' => index += 1,
+            b'-' => {
+                while index < bytes.len() && bytes[index] == b'-' {
+                    index += 1;
+                }
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Same as the check of `Image::from_str` for `char::is_whitespace` and
+/// `char::is_control`.
+const fn has_invalid_character(bytes: &[u8]) -> bool {
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let first = bytes[index] as u32;
+
+        let (len, mut code) = match first {
+            0x00..=0x7f => (1, first),
+            0xc0..=0xdf => (2, first & 0x1f),
+            0xe0..=0xef => (3, first & 0x0f),
+            _ => (4, first & 0x07),
+        };
+
+        let mut continuation = 1;
+        while continuation < len {
+            code = (code << 6) | (bytes[index + continuation] as u32 & 0x3f);
+            continuation += 1;
+        }
+
+        let Some(c) = char::from_u32(code) else {
+            return true;
+        };
+
+        if c.is_whitespace() || matches!(c, '\u{0}'..='\u{1f}' | '\u{7f}'..='\u{9f}') {
+            return true;
+        }
+
+        index += len;
+    }
+
+    false
+}
+
+const fn find(bytes: &[u8], needle: u8) -> Option<usize> {
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == needle {
+            return Some(index);
+        }
+
+        index += 1;
+    }
+
+    None
+}
+
+const fn count(bytes: &[u8], needle: u8) -> usize {
+    let mut count = 0;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == needle {
+            count += 1;
+        }
+
+        index += 1;
+    }
+
+    count
+}
+
+/// Splits at the first `separator`, which has to be present.
+const fn split(bytes: &[u8], separator: u8) -> (&[u8], &[u8]) {
+    let index = match find(bytes, separator) {
+        Some(index) => index,
+        None => bytes.len(),
+    };
+
+    let (head, tail) = bytes.split_at(index);
+
+    match tail {
+        [_, rest @ ..] => (head, rest),
+        [] => (head, tail),
+    }
+}
+
+const fn eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+
+    let mut index = 0;
+
+    while index < left.len() {
+        if left[index] != right[index] {
+            return false;
+        }
+
+        index += 1;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::Image;
+
+    /// The validator has to agree with the parser, otherwise the macro
+    /// either rejects valid references or panics at runtime.
+    fn assert_agrees(input: &str) {
+        assert_eq!(
+            validate(input).is_ok(),
+            input.parse::<Image>().is_ok(),
+            "{input:?}: {:?}",
+            validate(input)
+        );
+    }
+
+    #[test]
+    fn corpus() {
+        for input in [
+            "alpine",
+            "alpine:3.20",
+            "prom/prometheus:v2.53.2",
+            "docker.io/alpine",
+            "ghcr.io/sigstore/cosign/cosign:v2.4.0",
+            "registry.k8s.io/pause@sha256:7031c1b2",
+            "localhost:5000/app:dev",
+            "localhost/app",
+            "registry.example.com:8443/team/project/app@sha256:1234",
+            "nginx:latest\n",
+            "nginx:latest\r\n",
+            "",
+            "\n",
+            "nginx :latest",
+            "nginx\u{a0}:latest",
+            "nginx\u{85}:latest",
+            "alpine:3:20",
+            "alpine@sha256:a@sha256:b",
+            "alpine:",
+            "alpine@",
+            "@sha256:a",
+            ":latest",
+            "ghcr.io/:latest",
+            "Prom/prometheus",
+            "ghcr.io/../../image:latest",
+            "ghcr.io/a__b/c",
+            "ghcr.io/a___b/c",
+            "unknown/a/b",
+            "a/b/c/d/e",
+            "a//b",
+            "/a",
+            "ghcr.io/",
+            "ghcr.io/a-/b",
+            "ghcr.io/a/B-",
+            "ghcr.io/a/b-",
+            "foo/Bar",
+            "Alpine:3.20",
+            "alpine_@sha256:a",
+            "ghcr.io/a/b:tag@sha256:7031c1b2",
+        ] {
+            assert_agrees(input);
+        }
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn agrees_with_parser(input in "\\PC*|[a-zA-Z0-9./:@_ \t\r\n-]{0,48}") {
+            assert_agrees(&input);
+        }
+    }
+}
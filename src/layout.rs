@@ -0,0 +1,613 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use bytes::Bytes;
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    docker::content_store::FsContentStore,
+    manifest::{
+        self,
+        Descriptor,
+        Entry,
+        List,
+        Manifest,
+        Platform,
+    },
+    Digest,
+    ImageConfig,
+};
+
+/// The only version of the image layout specification.
+const LAYOUT_VERSION: &str = "1.0.0";
+
+/// The annotation that holds the tag of an `index.json` entry.
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Indexes nested deeper than this are not followed so a layout with a
+/// cycle can not hang.
+const MAX_DEPTH: usize = 8;
+
+/// A directory in the OCI image layout format, as written by
+/// `docker buildx build --output type=oci,tar=false`, `skopeo` or `oras`.
+///
+/// Every blob is checked against the size and digest of its descriptor when
+/// it is read. Reading does not need a [`crate::Client`].
+#[derive(Debug, Clone)]
+pub struct OciLayout {
+    dir: PathBuf,
+    index: List,
+}
+
+/// An image manifest of an [`OciLayout`], either listed in `index.json`
+/// directly or in a nested index.
+#[derive(Debug, Clone)]
+pub struct LayoutImage {
+    /// The `org.opencontainers.image.ref.name` annotation of the
+    /// `index.json` entry the image was found through, usually a tag.
+    pub ref_name: Option<String>,
+
+    /// The platform of the entry that points to the manifest.
+    pub platform: Option<Platform>,
+
+    pub descriptor: Descriptor,
+
+    pub manifest: manifest::Image,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, std::io::Error),
+    Write(PathBuf, std::io::Error),
+    Deserialize(PathBuf, serde_json::Error),
+    Serialize(serde_json::Error),
+    UnsupportedVersion(String),
+    UnsupportedDigest(Digest),
+    UnsupportedManifest(Digest),
+    TooDeeplyNested(Digest),
+    BlobMismatch {
+        expected_size: u64,
+        actual_size: u64,
+        expected_digest: Digest,
+        actual_digest: Digest,
+    },
+}
+
+/// The content of the `oci-layout` file.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Version {
+    image_layout_version: String,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            Self::Write(path, e) => write!(f, "failed to write {}: {e}", path.display()),
+            Self::Deserialize(path, e) => {
+                write!(f, "failed to deserialize {}: {e}", path.display())
+            }
+            Self::Serialize(e) => write!(f, "failed to serialize index.json: {e}"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported image layout version {version}")
+            }
+            Self::UnsupportedDigest(digest) => {
+                write!(f, "unsupported digest {digest}, only sha256 is supported")
+            }
+            Self::UnsupportedManifest(digest) => {
+                write!(
+                    f,
+                    "manifest {digest} is neither an image manifest nor an index"
+                )
+            }
+            Self::TooDeeplyNested(digest) => {
+                write!(
+                    f,
+                    "index {digest} is nested more than {MAX_DEPTH} levels deep"
+                )
+            }
+            Self::BlobMismatch {
+                expected_size,
+                actual_size,
+                expected_digest,
+                actual_digest,
+            } => write!(
+                f,
+                "blob has size {actual_size} and digest {actual_digest} but the descriptor \
+                 expects size {expected_size} and digest {expected_digest}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl OciLayout {
+    /// Reads `index.json` of the layout in `dir` after checking the version
+    /// in its `oci-layout` file.
+    ///
+    /// # Errors
+    /// Returns an error if `oci-layout` or `index.json` is missing or
+    /// malformed or the layout has a version other than 1.0.0.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+
+        let version: Version = read_json(&dir.join("oci-layout"))?;
+        if version.image_layout_version != LAYOUT_VERSION {
+            return Err(Error::UnsupportedVersion(version.image_layout_version));
+        }
+
+        let index = read_json(&dir.join("index.json"))?;
+
+        Ok(Self { dir, index })
+    }
+
+    /// Creates an empty layout in `dir`. The directory is created if it does
+    /// not exist, an existing `index.json` is replaced.
+    ///
+    /// # Errors
+    /// Returns an error if a file or directory can not be written.
+    pub fn create(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+
+        let blobs = dir.join("blobs").join("sha256");
+        std::fs::create_dir_all(&blobs).map_err(|e| Error::Write(blobs, e))?;
+
+        let version = Version {
+            image_layout_version: LAYOUT_VERSION.to_string(),
+        };
+        write(
+            &dir.join("oci-layout"),
+            &serde_json::to_vec(&version).map_err(Error::Serialize)?,
+        )?;
+
+        let layout = Self {
+            dir,
+            index: List::new(Vec::new()),
+        };
+        layout.write_index()?;
+
+        Ok(layout)
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The content of `index.json`.
+    #[must_use]
+    pub fn index(&self) -> &List {
+        &self.index
+    }
+
+    /// Reads a blob and checks it against `descriptor`.
+    ///
+    /// # Errors
+    /// Returns an error if the blob can not be read or its size or digest
+    /// differs from the descriptor.
+    pub fn blob(&self, descriptor: &Descriptor) -> Result<Bytes, Error> {
+        let path = blob_path(&self.dir, &descriptor.digest)?;
+        let content = std::fs::read(&path).map_err(|e| Error::Read(path, e))?;
+
+        let actual_size = content.len() as u64;
+        let actual_digest = Digest::sha256(&content);
+
+        if actual_size != descriptor.size || !actual_digest.is_equivalent(&descriptor.digest) {
+            return Err(Error::BlobMismatch {
+                expected_size: descriptor.size,
+                actual_size,
+                expected_digest: descriptor.digest.clone(),
+                actual_digest,
+            });
+        }
+
+        Ok(content.into())
+    }
+
+    /// Reads the manifest or index an entry of an index points to.
+    ///
+    /// # Errors
+    /// Returns an error if the blob can not be read, does not match the
+    /// entry or is not a manifest.
+    pub fn manifest(&self, entry: &Entry) -> Result<Manifest, Error> {
+        self.blob_json(entry)
+    }
+
+    /// Reads the config of an image manifest.
+    ///
+    /// # Errors
+    /// Returns an error if the blob can not be read, does not match the
+    /// descriptor in the manifest or is not an image config.
+    pub fn config(&self, manifest: &manifest::Image) -> Result<ImageConfig, Error> {
+        self.blob_json(&manifest.config)
+    }
+
+    /// Returns every image manifest of the layout, following nested indexes
+    /// like the ones buildx writes for multi-platform images. Attestation
+    /// manifests are skipped.
+    ///
+    /// # Errors
+    /// Returns an error if a manifest can not be read or indexes are nested
+    /// too deeply.
+    pub fn images(&self) -> Result<Vec<LayoutImage>, Error> {
+        let mut images = Vec::new();
+
+        for entry in &self.index.manifests {
+            let ref_name = entry.annotations.get(REF_NAME_ANNOTATION).cloned();
+            self.collect_images(entry, ref_name.as_deref(), 0, &mut images)?;
+        }
+
+        Ok(images)
+    }
+
+    fn collect_images(
+        &self,
+        entry: &Entry,
+        ref_name: Option<&str>,
+        depth: usize,
+        images: &mut Vec<LayoutImage>,
+    ) -> Result<(), Error> {
+        if entry.attestation_for().is_some() {
+            return Ok(());
+        }
+
+        let descriptor = entry.clone();
+
+        match self.manifest(entry)? {
+            Manifest::Image(manifest) => images.push(LayoutImage {
+                ref_name: ref_name.map(ToString::to_string),
+                platform: entry.platform.clone(),
+                descriptor,
+                manifest,
+            }),
+
+            Manifest::List(list) => {
+                if depth >= MAX_DEPTH {
+                    return Err(Error::TooDeeplyNested(descriptor.digest));
+                }
+
+                for nested in &list.manifests {
+                    self.collect_images(nested, ref_name, depth + 1, images)?;
+                }
+            }
+
+            Manifest::Single(_) => return Err(Error::UnsupportedManifest(descriptor.digest)),
+        }
+
+        Ok(())
+    }
+
+    /// Returns a [`FsContentStore`] on the blobs of the layout, for example
+    /// to fetch an image into the layout with [`crate::Client::fetch_into`]
+    /// before adding its manifest with [`OciLayout::add_manifest`].
+    #[must_use]
+    pub fn content_store(&self) -> FsContentStore {
+        FsContentStore::new(&self.dir)
+    }
+
+    /// Stores `content` under its sha256 digest and returns the digest.
+    /// Writing a blob that already exists does nothing.
+    ///
+    /// # Errors
+    /// Returns an error if the blob can not be written.
+    pub fn write_blob(&self, content: &[u8]) -> Result<Digest, Error> {
+        let digest = Digest::sha256(content);
+        let path = blob_path(&self.dir, &digest)?;
+
+        if !path.exists() {
+            write(&path, content)?;
+        }
+
+        Ok(digest)
+    }
+
+    /// Adds `entry` to `index.json`. An existing entry with the same
+    /// `org.opencontainers.image.ref.name` annotation is replaced, like
+    /// `skopeo` and `oras` do when they write a tag again.
+    ///
+    /// # Errors
+    /// Returns an error if `index.json` can not be written.
+    pub fn add_manifest(&mut self, entry: Entry) -> Result<(), Error> {
+        if let Some(ref_name) = entry.annotations.get(REF_NAME_ANNOTATION) {
+            self.index
+                .manifests
+                .retain(|existing| existing.annotations.get(REF_NAME_ANNOTATION) != Some(ref_name));
+        }
+
+        self.index.manifests.push(entry);
+        self.write_index()
+    }
+
+    fn blob_json<T: DeserializeOwned>(&self, descriptor: &Descriptor) -> Result<T, Error> {
+        let content = self.blob(descriptor)?;
+
+        serde_json::from_slice(&content).map_err(|e| {
+            Error::Deserialize(
+                blob_path(&self.dir, &descriptor.digest).unwrap_or_default(),
+                e,
+            )
+        })
+    }
+
+    fn write_index(&self) -> Result<(), Error> {
+        let content = serde_json::to_vec(&self.index).map_err(Error::Serialize)?;
+
+        write(&self.dir.join("index.json"), &content)
+    }
+}
+
+/// Returns the path of a blob in the layout, `blobs/<algorithm>/<hex>`. Only
+/// sha256 is supported and the hex part is checked so a digest can not
+/// point outside of the layout.
+pub(crate) fn blob_path(dir: &Path, digest: &Digest) -> Result<PathBuf, Error> {
+    let digest_string = digest.normalized().to_string();
+
+    match digest_string.split_once(':') {
+        Some(("sha256", hex))
+            if hex.len() == 64 && hex.bytes().all(|byte| byte.is_ascii_hexdigit()) =>
+        {
+            Ok(dir.join("blobs").join("sha256").join(hex))
+        }
+
+        _ => Err(Error::UnsupportedDigest(digest.clone())),
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let content = std::fs::read(path).map_err(|e| Error::Read(path.to_path_buf(), e))?;
+
+    serde_json::from_slice(&content).map_err(|e| Error::Deserialize(path.to_path_buf(), e))
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<(), Error> {
+    std::fs::write(path, content).map_err(|e| Error::Write(path.to_path_buf(), e))
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::manifest::{
+        Architecture,
+        OperatingSystem,
+    };
+
+    const BUILDX: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/layout/buildx");
+
+    fn entry(media_type: &str, content: &[u8], digest: &Digest) -> Entry {
+        Entry::new(media_type, content.len() as u64, digest.clone())
+    }
+
+    #[test]
+    fn buildx() {
+        let layout = OciLayout::open(BUILDX).unwrap();
+        let images = layout.images().unwrap();
+
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| (
+                    image.ref_name.as_deref(),
+                    image.platform.as_ref().map(ToString::to_string)
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (Some("latest"), Some("linux/amd64".to_string())),
+                (Some("latest"), Some("linux/arm64".to_string())),
+            ]
+        );
+
+        for image in &images {
+            let config = layout.config(&image.manifest).unwrap();
+            let platform = image.platform.as_ref().unwrap();
+
+            assert_eq!(config.architecture, platform.architecture);
+            assert_eq!(config.os, OperatingSystem::Linux);
+            assert_eq!(config.rootfs.diff_ids.len(), image.manifest.layers.len());
+
+            layout.blob(&image.manifest.layers[0]).unwrap();
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = OciLayout::open(BUILDX).unwrap();
+
+        let mut copy = OciLayout::create(dir.path()).unwrap();
+        assert!(copy.images().unwrap().is_empty());
+
+        for image in source.images().unwrap() {
+            for descriptor in std::iter::once(&image.manifest.config).chain(&image.manifest.layers)
+            {
+                let digest = copy.write_blob(&source.blob(descriptor).unwrap()).unwrap();
+                assert_eq!(digest, descriptor.digest);
+            }
+
+            let manifest = source.blob(&image.descriptor).unwrap();
+            let digest = copy.write_blob(&manifest).unwrap();
+
+            let mut entry = entry(&image.descriptor.media_type, &manifest, &digest);
+            entry.platform = image.platform.clone();
+            entry.annotations.insert(
+                REF_NAME_ANNOTATION.to_string(),
+                image.platform.unwrap().architecture.to_string(),
+            );
+
+            copy.add_manifest(entry).unwrap();
+        }
+
+        let reopened = OciLayout::open(dir.path()).unwrap();
+        let images = reopened.images().unwrap();
+
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| image.ref_name.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("amd64"), Some("arm64")]
+        );
+        assert_eq!(
+            reopened.config(&images[1].manifest).unwrap().architecture,
+            Architecture::Arm64
+        );
+    }
+
+    /// Serves the amd64 image of the buildx layout from a registry and
+    /// fetches it into the blobs of a new layout.
+    #[tokio::test]
+    async fn fetch_into_content_store() {
+        use http::{
+            Method,
+            StatusCode,
+        };
+
+        use crate::docker::transport::{
+            MockResponse,
+            MockTransport,
+        };
+
+        const BASE: &str = "https://registry.k8s.io/v2/app";
+
+        let source = OciLayout::open(BUILDX).unwrap();
+        let image = source.images().unwrap().remove(0);
+        let manifest = source.blob(&image.descriptor).unwrap();
+
+        let transport = std::iter::once(&image.manifest.config)
+            .chain(&image.manifest.layers)
+            .fold(
+                MockTransport::new().with_response(
+                    Method::GET,
+                    &format!("{BASE}/manifests/1.0"),
+                    MockResponse::new(StatusCode::OK).body(manifest.clone()),
+                ),
+                |transport, descriptor| {
+                    transport.with_response(
+                        Method::GET,
+                        &format!("{BASE}/blobs/{}", descriptor.digest),
+                        MockResponse::new(StatusCode::OK).body(source.blob(descriptor).unwrap()),
+                    )
+                },
+            );
+
+        let client = crate::Client::builder().transport(transport).build();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut layout = OciLayout::create(dir.path()).unwrap();
+
+        let report = client
+            .fetch_into(
+                &"registry.k8s.io/app:1.0".parse().unwrap(),
+                &layout.content_store(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(report.digest, image.descriptor.digest);
+        layout
+            .add_manifest(entry(
+                &image.descriptor.media_type,
+                &manifest,
+                &report.digest,
+            ))
+            .unwrap();
+
+        let reopened = OciLayout::open(dir.path()).unwrap();
+        let images = reopened.images().unwrap();
+
+        assert_eq!(images.len(), 1);
+        assert_eq!(
+            reopened.config(&images[0].manifest).unwrap().architecture,
+            Architecture::Amd64
+        );
+    }
+
+    #[test]
+    fn add_manifest_replaces_ref_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut layout = OciLayout::create(dir.path()).unwrap();
+
+        for content in [b"first".as_slice(), b"second".as_slice()] {
+            let digest = layout.write_blob(content).unwrap();
+
+            let mut entry = entry("application/octet-stream", content, &digest);
+            entry
+                .annotations
+                .insert(REF_NAME_ANNOTATION.to_string(), "latest".to_string());
+
+            layout.add_manifest(entry).unwrap();
+        }
+
+        let reopened = OciLayout::open(dir.path()).unwrap();
+        let [entry] = reopened.index().manifests.as_slice() else {
+            panic!("expected a single entry");
+        };
+
+        assert_eq!(entry.digest, Digest::sha256(b"second"));
+    }
+
+    #[test]
+    fn corrupted_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = OciLayout::create(dir.path()).unwrap();
+
+        let digest = layout.write_blob(b"content").unwrap();
+        std::fs::write(blob_path(dir.path(), &digest).unwrap(), b"tampered").unwrap();
+
+        let got = layout.blob(&Descriptor::new("application/octet-stream", 7, digest));
+
+        assert!(
+            matches!(got, Err(Error::BlobMismatch { actual_size: 8, .. })),
+            "{got:?}"
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let dir = tempfile::tempdir().unwrap();
+        OciLayout::create(dir.path()).unwrap();
+        std::fs::write(
+            dir.path().join("oci-layout"),
+            r#"{"imageLayoutVersion":"2.0.0"}"#,
+        )
+        .unwrap();
+
+        let got = OciLayout::open(dir.path());
+
+        assert!(
+            matches!(&got, Err(Error::UnsupportedVersion(version)) if version == "2.0.0"),
+            "{got:?}"
+        );
+    }
+
+    #[test]
+    fn missing_layout_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(matches!(
+            OciLayout::open(dir.path()),
+            Err(Error::Read(path, _)) if path.ends_with("oci-layout")
+        ));
+    }
+
+    #[test]
+    fn traversal_digest() {
+        let digest: Digest = "sha256:../../etc/passwd".parse().unwrap();
+
+        assert!(matches!(
+            blob_path(Path::new("/layout"), &digest),
+            Err(Error::UnsupportedDigest(_))
+        ));
+    }
+}
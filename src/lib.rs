@@ -9,15 +9,74 @@
 #![warn(clippy::unwrap_used)]
 #![warn(rust_2018_idioms, unused_lifetimes, missing_debug_implementations)]
 
+#[cfg(all(
+    feature = "client",
+    not(any(feature = "tls-rustls", feature = "tls-native"))
+))]
+compile_error!("enable the `tls-rustls` or `tls-native` feature to select a TLS backend");
+
+#[cfg(feature = "client")]
+pub mod archive;
+pub mod attestation;
+pub mod config;
+#[cfg(feature = "client")]
 pub mod docker;
 pub mod image;
+#[cfg(feature = "client")]
+pub mod layout;
 pub mod manifest;
 
+#[cfg(feature = "client")]
+pub use archive::DockerArchive;
+pub use attestation::Attestation;
+pub use config::ImageConfig;
+#[cfg(feature = "client")]
 pub use docker::{
+    api::RegistryApi,
+    bundle::{
+        Bundle,
+        BundleRequest,
+    },
+    expand::{
+        ExpandOptions,
+        ExpandedImage,
+    },
+    health::HealthReport,
+    missing_child::MissingChildPolicy,
+    negotiation::ManifestPreference,
+    options::GetManifestOptions,
+    ping::PingResult,
+    platform::ResolvedManifest,
+    rate_limit_status::RateLimitStatus,
+    stale::StaleTag,
+    stats::OperationStats,
+    tag_groups::TagGroups,
+    walk::{
+        RepoEntry,
+        WalkOptions,
+    },
+    warning::RegistryWarning,
     Client,
+    ClientBuilder,
     Error as ClientError,
+    FailedResponse,
+    LenientResponse,
+    RawResponse,
     Response,
+    UpdateStatus,
 };
+/// The `http` crate whose `HeaderMap`, `StatusCode` and `Method` the client
+/// uses in its API, re-exported so callers do not have to depend on a
+/// matching version of it.
+///
+/// # Migrating from the reqwest types
+/// Earlier versions used the re-exports of reqwest. The types are the same,
+/// replace `reqwest::header`, `reqwest::StatusCode` and `reqwest::Method`
+/// with their counterparts in this module. Errors of the HTTP client are
+/// wrapped in [`docker::transport::HttpError`] instead of exposing
+/// `reqwest::Error`.
+#[cfg(feature = "client")]
+pub use http;
 pub use image::{
     image_name::{
         digest::Digest,
@@ -25,6 +84,10 @@ pub use image::{
         ImageName,
     },
     registry::Registry,
+    repository::Repository,
     Image,
+    Scheme,
 };
+#[cfg(feature = "client")]
+pub use layout::OciLayout;
 pub use manifest::Manifest;
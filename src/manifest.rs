@@ -1,4 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
 
 use chrono::{
     DateTime,
@@ -14,6 +17,24 @@ use serde::{
 };
 use url::Url;
 
+use crate::{
+    config::{
+        object_set,
+        Healthcheck,
+    },
+    Digest,
+};
+
+mod canonical;
+mod lenient;
+mod schema1;
+mod size;
+
+pub use canonical::CanonicalJsonError;
+pub use lenient::ParseWarning;
+pub use schema1::SynthesizedConfig;
+pub use size::Size;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(untagged)]
 pub enum Manifest {
@@ -27,11 +48,17 @@ pub struct Image {
     #[serde(rename = "schemaVersion")]
     pub schema_version: SchemaVersion,
 
+    /// Optional in older versions of the OCI image specification. Missing
+    /// media types default to an OCI image manifest, or to the
+    /// `Content-Type` of the response for manifests fetched by the client.
     #[serde(rename = "mediaType")]
+    #[serde(default = "oci_manifest_media_type")]
     pub media_type: String,
 
     pub config: Config,
 
+    /// Empty for images built `FROM scratch` without adding files.
+    #[serde(default)]
     pub layers: Vec<Layer>,
 }
 
@@ -40,7 +67,10 @@ pub struct List {
     #[serde(rename = "schemaVersion")]
     schema_version: SchemaVersion,
 
+    /// Only recommended by the image layout specification, `index.json`
+    /// files written by some tools do not have one.
     #[serde(rename = "mediaType")]
+    #[serde(default = "oci_index_media_type")]
     media_type: String,
 
     pub manifests: Vec<Entry>,
@@ -56,8 +86,10 @@ pub struct Single {
     pub architecture: Architecture,
 
     #[serde(rename = "fsLayers")]
+    #[serde(default)]
     pub fs_layers: Vec<FsLayer>,
 
+    #[serde(default)]
     pub history: Vec<History>,
 }
 
@@ -67,16 +99,7 @@ pub enum SchemaVersion {
     V2,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Entry {
-    #[serde(rename = "mediaType")]
-    pub media_type: String,
-    pub size: u64,
-    pub digest: String,
-    pub platform: Platform,
-}
-
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Platform {
     pub architecture: Architecture,
     pub os: OperatingSystem,
@@ -97,15 +120,20 @@ pub struct Platform {
     features: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Architecture {
     #[serde(rename = "386")]
     I386,
 
+    #[serde(alias = "x86_64")]
     Amd64,
+
     Arm,
+
+    #[serde(alias = "aarch64")]
     Arm64,
+
     Loong64,
     Mips,
     Mips64,
@@ -120,7 +148,7 @@ pub enum Architecture {
     Unknown,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum OperatingSystem {
     Aix,
@@ -142,30 +170,57 @@ pub enum OperatingSystem {
     Unknown,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Config {
-    #[serde(rename = "mediaType")]
-    pub media_type: String,
-    pub size: u64,
-    pub digest: String,
+#[derive(Debug, PartialEq, Eq)]
+pub enum FromStrError {
+    MissingArchitecture,
+    TooManyComponents,
+    UnknownOperatingSystem(String),
+    UnknownArchitecture(String),
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Layer {
-    #[serde(rename = "mediaType")]
+/// A reference to a blob as the OCI image specification describes it: the
+/// config and layers of an image manifest, the entries of an index and the
+/// descriptors passed to [`crate::Client::get_blob_json`]. Besides the media
+/// type, size and digest every field is optional and only serialized if it
+/// is set.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
     pub media_type: String,
     pub size: u64,
-    pub digest: String,
+    pub digest: Digest,
 
+    /// Where else the blob can be downloaded from, set for foreign layers.
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub urls: Option<Vec<Url>>,
 
+    /// The platform of a manifest in an index. Optional in the image index
+    /// specification, artifact indexes like the ones pushed by ORAS have
+    /// entries without one.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+
+    /// The content of small blobs embedded base64 encoded.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     pub annotations: BTreeMap<String, String>,
 }
 
+/// The `config` descriptor of an image manifest.
+pub type Config = Descriptor;
+
+/// A descriptor in the `layers` of an image manifest.
+pub type Layer = Descriptor;
+
+/// A descriptor in the `manifests` of a manifest list or image index.
+pub type Entry = Descriptor;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct FsLayer {
     #[serde(rename = "blobSum")]
@@ -191,6 +246,26 @@ pub struct V1Compatibility {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub container_config: Option<ContainerConfig>,
+
+    /// The configuration of the image as of this step, the newest entry
+    /// holds the configuration of the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ContainerConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+
+    /// Set for steps that did not change the filesystem, their layer is an
+    /// empty tar archive.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub throwaway: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -244,8 +319,19 @@ pub struct ContainerConfig {
     pub image: Option<String>,
 
     #[serde(rename = "Volumes")]
+    #[serde(default, with = "object_set")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<BTreeSet<String>>,
+
+    #[serde(rename = "ExposedPorts")]
+    #[serde(default, with = "object_set")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub volumes: Option<BTreeMap<String, String>>,
+    pub exposed_ports: Option<BTreeSet<String>>,
+
+    #[serde(rename = "Healthcheck")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub healthcheck: Option<Healthcheck>,
 
     #[serde(rename = "WorkingDir")]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -299,6 +385,366 @@ impl<'de> Deserialize<'de> for SchemaVersion {
     }
 }
 
+impl Platform {
+    #[must_use]
+    pub fn new(os: OperatingSystem, architecture: Architecture) -> Self {
+        Self {
+            architecture,
+            os,
+            os_version: None,
+            os_features: None,
+            variant: None,
+            features: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_variant(mut self, variant: impl Into<String>) -> Self {
+        self.variant = Some(variant.into());
+        self
+    }
+
+    #[must_use]
+    pub fn variant(&self) -> Option<&str> {
+        self.variant.as_deref()
+    }
+
+    /// Sets the operating system version, for Windows images the build like
+    /// `10.0.20348` or `10.0.20348.2402`.
+    #[must_use]
+    pub fn with_os_version(mut self, os_version: impl Into<String>) -> Self {
+        self.os_version = Some(os_version.into());
+        self
+    }
+
+    #[must_use]
+    pub fn os_version(&self) -> Option<&str> {
+        self.os_version.as_deref()
+    }
+
+    /// Returns the platform the program is running on. Targets without an
+    /// equivalent in the image specification are reported as
+    /// [`Architecture::Unknown`] or [`OperatingSystem::Unknown`].
+    #[must_use]
+    pub fn current() -> Self {
+        let architecture = match std::env::consts::ARCH {
+            "x86" => Architecture::I386,
+            "x86_64" => Architecture::Amd64,
+            "arm" => Architecture::Arm,
+            "aarch64" => Architecture::Arm64,
+            "loongarch64" => Architecture::Loong64,
+            "mips" if cfg!(target_endian = "little") => Architecture::Mipsle,
+            "mips" => Architecture::Mips,
+            "mips64" if cfg!(target_endian = "little") => Architecture::Mips64le,
+            "mips64" => Architecture::Mips64,
+            "powerpc64" if cfg!(target_endian = "little") => Architecture::Ppc64le,
+            "powerpc64" => Architecture::Ppc64,
+            "riscv64" => Architecture::Riscv64,
+            "s390x" => Architecture::S390x,
+            "wasm32" => Architecture::Wasm,
+            _ => Architecture::Unknown,
+        };
+
+        let os = match std::env::consts::OS {
+            "aix" => OperatingSystem::Aix,
+            "android" => OperatingSystem::Android,
+            "macos" => OperatingSystem::Darwin,
+            "dragonfly" => OperatingSystem::Dragonfly,
+            "freebsd" => OperatingSystem::Freebsd,
+            "illumos" => OperatingSystem::Illumos,
+            "ios" => OperatingSystem::Ios,
+            "linux" => OperatingSystem::Linux,
+            "netbsd" => OperatingSystem::Netbsd,
+            "openbsd" => OperatingSystem::Openbsd,
+            "solaris" => OperatingSystem::Solaris,
+            "windows" => OperatingSystem::Windows,
+            _ => OperatingSystem::Unknown,
+        };
+
+        Self::new(os, architecture)
+    }
+
+    /// Returns true if an entry of a manifest list with the platform `other`
+    /// satisfies this platform. The variant is only compared if this platform
+    /// asks for one. Registries are inconsistent about variants so an absent
+    /// variant is treated as `v8` for arm64 and as `v7` for arm.
+    ///
+    /// The OS version is only compared if this platform asks for one, by its
+    /// first three components. Windows images list the revision as fourth
+    /// component, `10.0.20348` matches an entry for `10.0.20348.2402` and so
+    /// does `10.0.20348.1`.
+    #[must_use]
+    pub fn matches(&self, other: &Self) -> bool {
+        if self.os != other.os || self.architecture != other.architecture {
+            return false;
+        }
+
+        if let Some(os_version) = &self.os_version {
+            let wanted: Vec<&str> = os_version.split('.').take(3).collect();
+
+            let matches = other.os_version.as_deref().is_some_and(|other| {
+                other
+                    .split('.')
+                    .take(wanted.len())
+                    .eq(wanted.iter().copied())
+            });
+
+            if !matches {
+                return false;
+            }
+        }
+
+        if self.variant.is_none() {
+            return true;
+        }
+
+        self.normalized_variant() == other.normalized_variant()
+    }
+
+    fn normalized_variant(&self) -> Option<&str> {
+        match (self.architecture, self.variant.as_deref()) {
+            (Architecture::Arm64, None) => Some("v8"),
+            (Architecture::Arm, None) => Some("v7"),
+            (_, variant) => variant,
+        }
+    }
+}
+
+impl Descriptor {
+    /// A descriptor without any of the optional fields.
+    #[must_use]
+    pub fn new(media_type: impl Into<String>, size: u64, digest: Digest) -> Self {
+        Self {
+            media_type: media_type.into(),
+            size,
+            digest,
+            urls: None,
+            platform: None,
+            data: None,
+            annotations: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl Manifest {
+    /// Sets the media type of an image manifest or list that did not have
+    /// one in its JSON to `content_type`, the `Content-Type` the registry
+    /// served it with. Content types that do not fit the kind of manifest
+    /// are ignored.
+    pub(crate) fn default_media_type(&mut self, content_type: &str) {
+        let content_type = content_type.split(';').next().unwrap_or_default().trim();
+
+        match self {
+            Self::Image(image) if IMAGE_MEDIA_TYPES.contains(&content_type) => {
+                image.media_type = content_type.to_string();
+            }
+
+            Self::List(list) if LIST_MEDIA_TYPES.contains(&content_type) => {
+                list.media_type = content_type.to_string();
+            }
+
+            _ => {}
+        }
+    }
+}
+
+impl Image {
+    /// Returns the combined size of the layers, `0` for images without
+    /// layers. The config is not included. The sum saturates at
+    /// [`u64::MAX`], see [`Size`] to display it.
+    #[must_use]
+    pub fn total_size(&self) -> u64 {
+        Size::sum(self.layers.iter().map(|layer| layer.size)).0
+    }
+}
+
+impl Single {
+    /// Pairs the layers of a schema1 manifest with their history entries.
+    /// Both lists are ordered from the newest to the oldest layer, layers
+    /// without a history entry are paired with `None`.
+    #[must_use]
+    pub fn layer_history(&self) -> Vec<(&FsLayer, Option<&History>)> {
+        self.fs_layers
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| (layer, self.history.get(index)))
+            .collect()
+    }
+}
+
+impl ContainerConfig {
+    /// Returns the numbers of the exposed ports in ascending order.
+    #[must_use]
+    pub fn exposed_port_numbers(&self) -> Vec<u16> {
+        crate::config::exposed_port_numbers(self.exposed_ports.as_ref())
+    }
+}
+
+impl Entry {
+    /// Returns the digest of the image this entry attests to if the entry is
+    /// an attestation manifest as pushed by buildkit.
+    #[must_use]
+    pub fn attestation_for(&self) -> Option<&str> {
+        (self.annotations.get("vnd.docker.reference.type")?.as_str() == "attestation-manifest")
+            .then(|| self.annotations.get("vnd.docker.reference.digest"))
+            .flatten()
+            .map(String::as_str)
+    }
+}
+
+impl List {
+    /// Creates an OCI image index with the given entries.
+    #[must_use]
+    pub fn new(manifests: Vec<Entry>) -> Self {
+        Self {
+            schema_version: SchemaVersion::V2,
+            media_type: oci_index_media_type(),
+            manifests,
+        }
+    }
+
+    #[must_use]
+    pub fn media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// Returns the first entry whose platform satisfies `platform`. Entries
+    /// without a platform are skipped.
+    #[must_use]
+    pub fn find(&self, platform: &Platform) -> Option<&Entry> {
+        self.manifests.iter().find(|entry| {
+            entry
+                .platform
+                .as_ref()
+                .is_some_and(|other| platform.matches(other))
+        })
+    }
+}
+
+#[cfg(feature = "client")]
+const IMAGE_MEDIA_TYPES: [&str; 2] = [
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.oci.image.manifest.v1+json",
+];
+
+#[cfg(feature = "client")]
+const LIST_MEDIA_TYPES: [&str; 2] = [
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+    "application/vnd.oci.image.index.v1+json",
+];
+
+fn oci_manifest_media_type() -> String {
+    "application/vnd.oci.image.manifest.v1+json".to_string()
+}
+
+fn oci_index_media_type() -> String {
+    "application/vnd.oci.image.index.v1+json".to_string()
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for FromStrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingArchitecture => f.write_str("missing architecture"),
+            Self::TooManyComponents => f.write_str("too many components"),
+            Self::UnknownOperatingSystem(s) => write!(f, "unknown operating system: {s}"),
+            Self::UnknownArchitecture(s) => write!(f, "unknown architecture: {s}"),
+        }
+    }
+}
+
+impl std::error::Error for FromStrError {}
+
+impl std::str::FromStr for Platform {
+    type Err = FromStrError;
+
+    /// Parses platforms like `linux/amd64` or `linux/arm/v7`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = s.split('/');
+
+        let os = components.next().unwrap_or_default().parse()?;
+        let architecture = components
+            .next()
+            .ok_or(FromStrError::MissingArchitecture)?
+            .parse()?;
+
+        let platform = Self::new(os, architecture);
+
+        match (components.next(), components.next()) {
+            (None, _) => Ok(platform),
+            (Some(variant), None) => Ok(platform.with_variant(variant)),
+            (Some(_), Some(_)) => Err(FromStrError::TooManyComponents),
+        }
+    }
+}
+
+impl std::str::FromStr for Architecture {
+    type Err = FromStrError;
+
+    /// Parses the architecture names of the image specification. The names
+    /// `uname` uses for amd64 and arm64 are accepted as well.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let architecture = match s {
+            "386" => Self::I386,
+            "amd64" | "x86_64" => Self::Amd64,
+            "arm" => Self::Arm,
+            "arm64" | "aarch64" => Self::Arm64,
+            "loong64" => Self::Loong64,
+            "mips" => Self::Mips,
+            "mips64" => Self::Mips64,
+            "mips64le" => Self::Mips64le,
+            "mipsle" => Self::Mipsle,
+            "ppc64" => Self::Ppc64,
+            "ppc64le" => Self::Ppc64le,
+            "riscv64" => Self::Riscv64,
+            "s390x" => Self::S390x,
+            "wasm" => Self::Wasm,
+            _ => return Err(FromStrError::UnknownArchitecture(s.to_string())),
+        };
+
+        Ok(architecture)
+    }
+}
+
+impl std::str::FromStr for OperatingSystem {
+    type Err = FromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let os = match s {
+            "aix" => Self::Aix,
+            "android" => Self::Android,
+            "darwin" => Self::Darwin,
+            "dragonfly" => Self::Dragonfly,
+            "freebsd" => Self::Freebsd,
+            "illumos" => Self::Illumos,
+            "ios" => Self::Ios,
+            "js" => Self::Js,
+            "linux" => Self::Linux,
+            "netbsd" => Self::Netbsd,
+            "openbsd" => Self::Openbsd,
+            "plan9" => Self::Plan9,
+            "solaris" => Self::Solaris,
+            "wasip1" => Self::Wasip1,
+            "windows" => Self::Windows,
+            _ => return Err(FromStrError::UnknownOperatingSystem(s.to_string())),
+        };
+
+        Ok(os)
+    }
+}
+
 impl std::fmt::Display for Architecture {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let out = match self {
@@ -364,6 +810,16 @@ mod tests {
                 insta::assert_json_snapshot!(out);
             }
 
+            #[test]
+            fn artifacts() {
+                const INPUT: &str = include_str!("../resources/manifest/list/artifacts.json");
+
+                let out: List = serde_json::from_str(INPUT).unwrap();
+
+                assert!(out.manifests[0].platform.is_none());
+                insta::assert_json_snapshot!(out);
+            }
+
             #[test]
             fn trivy() {
                 const INPUT: &str = include_str!("../resources/manifest/list/trivy.json");
@@ -381,6 +837,15 @@ mod tests {
 
                 insta::assert_json_snapshot!(out);
             }
+
+            #[test]
+            fn nanoserver() {
+                const INPUT: &str = include_str!("../resources/manifest/list/nanoserver.json");
+
+                let out: List = serde_json::from_str(INPUT).unwrap();
+
+                insta::assert_json_snapshot!(out);
+            }
         }
     }
 
@@ -396,6 +861,62 @@ mod tests {
 
                 insta::assert_json_snapshot!(out);
             }
+
+            #[test]
+            fn scratch() {
+                const INPUT: &str = include_str!("../resources/manifest/image/scratch.json");
+
+                let out: Image = serde_json::from_str(INPUT).unwrap();
+
+                assert_eq!(out.total_size(), 0);
+                insta::assert_json_snapshot!(out);
+            }
+
+            #[test]
+            fn without_media_type() {
+                const INPUT: &str =
+                    include_str!("../resources/manifest/image/oci-without-media-type.json");
+
+                let out: Image = serde_json::from_str(INPUT).unwrap();
+
+                assert_eq!(out.total_size(), 3_623_807);
+                insta::assert_json_snapshot!(out);
+            }
+        }
+
+        #[cfg(feature = "client")]
+        mod default_media_type {
+            use crate::Manifest;
+
+            #[test]
+            fn from_content_type() {
+                const INPUT: &str =
+                    include_str!("../resources/manifest/image/oci-without-media-type.json");
+
+                let mut manifest: Manifest = serde_json::from_str(INPUT).unwrap();
+                manifest.default_media_type(
+                    "application/vnd.docker.distribution.manifest.v2+json; charset=utf-8",
+                );
+
+                let Manifest::Image(image) = &manifest else {
+                    panic!("expected an image manifest");
+                };
+                assert_eq!(
+                    image.media_type,
+                    "application/vnd.docker.distribution.manifest.v2+json"
+                );
+
+                // An index content type does not fit an image manifest.
+                manifest.default_media_type("application/vnd.oci.image.index.v1+json");
+
+                let Manifest::Image(image) = &manifest else {
+                    panic!("expected an image manifest");
+                };
+                assert_eq!(
+                    image.media_type,
+                    "application/vnd.docker.distribution.manifest.v2+json"
+                );
+            }
         }
     }
 
@@ -412,6 +933,192 @@ mod tests {
 
                 insta::assert_json_snapshot!(out);
             }
+
+            #[test]
+            fn empty_history() {
+                const INPUT: &str = include_str!("../resources/manifest/single/empty-history.json");
+
+                let out: Single = serde_json::from_str(INPUT).unwrap();
+
+                let pairs = out.layer_history();
+                assert_eq!(pairs.len(), 1);
+                assert!(pairs[0].1.is_none());
+                insta::assert_json_snapshot!(out);
+            }
+        }
+    }
+
+    mod platform {
+        mod matches {
+            use crate::manifest::Platform;
+
+            /// Requested platform, platform of the list entry and if the entry
+            /// satisfies the request. The entries are the combinations found
+            /// in the fixtures under `resources`.
+            const CASES: &[(&str, &str, bool)] = &[
+                ("linux/arm64", "linux/arm64", true),
+                ("linux/arm64", "linux/arm64/v8", true),
+                ("linux/arm64/v8", "linux/arm64", true),
+                ("linux/arm64/v8", "linux/arm64/v8", true),
+                ("linux/aarch64", "linux/arm64/v8", true),
+                ("linux/arm64/v9", "linux/arm64", false),
+                ("linux/arm64/v9", "linux/arm64/v8", false),
+                ("linux/arm", "linux/arm/v6", true),
+                ("linux/arm", "linux/arm/v7", true),
+                ("linux/arm/v7", "linux/arm/v7", true),
+                ("linux/arm/v7", "linux/arm", true),
+                ("linux/arm/v6", "linux/arm/v6", true),
+                ("linux/arm/v6", "linux/arm/v7", false),
+                ("linux/arm/v6", "linux/arm", false),
+                ("linux/arm/v7", "linux/arm/v6", false),
+                ("linux/amd64", "linux/amd64", true),
+                ("linux/x86_64", "linux/amd64", true),
+                ("linux/amd64", "linux/386", false),
+                ("linux/arm64", "linux/amd64", false),
+                ("linux/arm", "linux/arm64/v8", false),
+                ("linux/ppc64le", "linux/ppc64le", true),
+                ("linux/s390x", "linux/s390x", true),
+                ("linux/riscv64", "linux/riscv64", true),
+                ("linux/386", "linux/386", true),
+                ("windows/amd64", "linux/amd64", false),
+            ];
+
+            #[test]
+            fn table() {
+                for (requested, entry, expected) in CASES {
+                    let requested: Platform = requested.parse().unwrap();
+                    let entry: Platform = entry.parse().unwrap();
+
+                    assert_eq!(
+                        requested.matches(&entry),
+                        *expected,
+                        "{requested} requested, entry {entry}"
+                    );
+                }
+            }
+
+            #[test]
+            fn fixtures() {
+                const LISTS: [&str; 7] = [
+                    include_str!("../resources/manifest/list/artifacts.json"),
+                    include_str!("../resources/manifest/list/trivy.json"),
+                    include_str!("../resources/manifest/list/vaultwarden.json"),
+                    include_str!("../resources/registry/dockerhub/alpine.json"),
+                    include_str!("../resources/registry/github/cosign.json"),
+                    include_str!("../resources/registry/microsoft/playwright.json"),
+                    include_str!("../resources/registry/redhat/ubi8.json"),
+                ];
+
+                for input in LISTS {
+                    let list: crate::manifest::List = serde_json::from_str(input).unwrap();
+
+                    for requested in ["linux/arm64", "linux/arm64/v8", "linux/aarch64"] {
+                        let requested: Platform = requested.parse().unwrap();
+
+                        assert!(list.find(&requested).is_some(), "{requested}");
+                    }
+                }
+            }
+
+            #[test]
+            fn os_version() {
+                const INPUT: &str = include_str!("../resources/manifest/list/nanoserver.json");
+
+                let list: crate::manifest::List = serde_json::from_str(INPUT).unwrap();
+                let windows: Platform = "windows/amd64".parse().unwrap();
+
+                for (os_version, expected) in [
+                    ("10.0.17763", Some("10.0.17763.6054")),
+                    ("10.0.20348", Some("10.0.20348.2582")),
+                    ("10.0.20348.1", Some("10.0.20348.2582")),
+                    ("10.0", Some("10.0.17763.6054")),
+                    ("10.0.2034", None),
+                    ("10.0.22631", None),
+                ] {
+                    let requested = windows.clone().with_os_version(os_version);
+
+                    assert_eq!(
+                        list.find(&requested)
+                            .and_then(|entry| entry.platform.as_ref()?.os_version()),
+                        expected,
+                        "{os_version}"
+                    );
+                }
+
+                // Without an OS version the first Windows entry matches.
+                assert!(list.find(&windows).is_some());
+
+                let linux: Platform = "linux/amd64".parse().unwrap();
+                assert!(!linux.clone().with_os_version("10.0.20348").matches(&linux));
+            }
+        }
+
+        mod from_str {
+            use pretty_assertions::assert_eq;
+
+            use crate::manifest::{
+                Architecture,
+                FromStrError,
+                OperatingSystem,
+                Platform,
+            };
+
+            #[test]
+            fn aliases() {
+                assert_eq!(
+                    "linux/aarch64".parse::<Platform>().unwrap(),
+                    Platform::new(OperatingSystem::Linux, Architecture::Arm64)
+                );
+                assert_eq!(
+                    "linux/x86_64".parse::<Platform>().unwrap(),
+                    Platform::new(OperatingSystem::Linux, Architecture::Amd64)
+                );
+            }
+
+            #[test]
+            fn variant() {
+                assert_eq!(
+                    "linux/arm/v7".parse::<Platform>().unwrap(),
+                    Platform::new(OperatingSystem::Linux, Architecture::Arm).with_variant("v7")
+                );
+            }
+
+            #[test]
+            fn invalid() {
+                assert_eq!(
+                    "linux".parse::<Platform>(),
+                    Err(FromStrError::MissingArchitecture)
+                );
+                assert_eq!(
+                    "linux/arm/v7/extra".parse::<Platform>(),
+                    Err(FromStrError::TooManyComponents)
+                );
+                assert_eq!(
+                    "linux/sparc".parse::<Platform>(),
+                    Err(FromStrError::UnknownArchitecture("sparc".to_string()))
+                );
+            }
+        }
+    }
+
+    mod descriptor {
+        use pretty_assertions::assert_eq;
+
+        use crate::manifest::Descriptor;
+
+        #[test]
+        fn round_trip() {
+            let cases = [
+                r#"{"mediaType":"application/vnd.oci.image.config.v1+json","size":2,"digest":"sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a","data":"e30="}"#,
+                r#"{"mediaType":"application/vnd.docker.image.rootfs.foreign.diff.tar.gzip","size":1,"digest":"sha256:abc","urls":["https://example.com/layer"],"annotations":{"key":"value"}}"#,
+                r#"{"mediaType":"application/vnd.oci.image.manifest.v1+json","size":1,"digest":"sha256:abc","platform":{"architecture":"arm64","os":"linux","variant":"v8"}}"#,
+            ];
+
+            for input in cases {
+                let descriptor: Descriptor = serde_json::from_str(input).unwrap();
+
+                assert_eq!(serde_json::to_string(&descriptor).unwrap(), input);
+            }
         }
     }
 
@@ -429,6 +1136,22 @@ mod tests {
 
                 insta::assert_json_snapshot!(out);
             }
+
+            #[test]
+            fn nginx() {
+                const INPUT: &str =
+                    include_str!("../resources/manifest/v1_compatibility/nginx.json");
+
+                let out: Vec<V1Compatibility> = serde_json::from_str(INPUT).unwrap();
+
+                let config = out[0].container_config.as_ref().unwrap();
+                assert_eq!(config.exposed_port_numbers(), [80]);
+                assert_eq!(
+                    config.healthcheck.as_ref().unwrap().interval,
+                    Some(std::time::Duration::from_secs(30))
+                );
+                insta::assert_json_snapshot!(out);
+            }
         }
     }
 }
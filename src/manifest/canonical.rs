@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::Digest;
+
+use crate::manifest::{
+    Descriptor,
+    Image,
+    List,
+    Manifest,
+    Platform,
+};
+
+#[derive(Debug)]
+pub enum CanonicalJsonError {
+    /// Schema 1 manifests are signed over their exact bytes, serializing them
+    /// again would break the signature.
+    SchemaV1,
+    Serialize(serde_json::Error),
+}
+
+/// A JSON value whose object keys are written in the order they were added.
+/// `serde_json` sorts keys unless its `preserve_order` feature is enabled.
+enum Node {
+    Object(Vec<(&'static str, Node)>),
+    Array(Vec<Node>),
+    Value(serde_json::Value),
+}
+
+/// The order registries write the fields of a descriptor in.
+#[derive(Clone, Copy)]
+enum Order {
+    /// `docker/distribution` writes the size before the digest.
+    Docker,
+
+    /// The Go types of the image specification write the digest first.
+    Oci,
+}
+
+impl std::fmt::Display for CanonicalJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SchemaV1 => write!(f, "schema 1 manifests can not be serialized again"),
+            Self::Serialize(e) => write!(f, "failed to serialize manifest: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CanonicalJsonError {}
+
+impl Manifest {
+    /// Serializes the manifest the way registries and the docker and
+    /// containerd tooling do: compact, fields in the order of the Go types of
+    /// the specification, map keys sorted and `<`, `>` and `&` escaped. A
+    /// manifest that was written like that serializes to the same bytes and
+    /// so to the same digest.
+    ///
+    /// Fields the model does not know, like top level annotations or a
+    /// `subject`, are lost. To push a manifest that was not modified use the
+    /// body of [`crate::Client::get_manifest_raw`] instead.
+    ///
+    /// # Errors
+    /// Returns an error for schema 1 manifests and if a value can not be
+    /// serialized.
+    pub fn to_canonical_json(&self) -> Result<Vec<u8>, CanonicalJsonError> {
+        let node = match self {
+            Self::Image(image) => image.node()?,
+            Self::List(list) => list.node()?,
+            Self::Single(_) => return Err(CanonicalJsonError::SchemaV1),
+        };
+
+        let mut out = Vec::new();
+        node.write(&mut out)?;
+
+        Ok(escape_html(&out))
+    }
+}
+
+impl Image {
+    fn node(&self) -> Result<Node, CanonicalJsonError> {
+        let order = Order::of(&self.media_type);
+
+        Ok(Node::Object(vec![
+            ("schemaVersion", value(&self.schema_version)?),
+            ("mediaType", value(&self.media_type)?),
+            ("config", self.config.node(order)?),
+            (
+                "layers",
+                Node::Array(
+                    self.layers
+                        .iter()
+                        .map(|layer| layer.node(order))
+                        .collect::<Result<_, _>>()?,
+                ),
+            ),
+        ]))
+    }
+}
+
+impl List {
+    fn node(&self) -> Result<Node, CanonicalJsonError> {
+        let order = Order::of(&self.media_type);
+
+        Ok(Node::Object(vec![
+            ("schemaVersion", value(&self.schema_version)?),
+            ("mediaType", value(&self.media_type)?),
+            (
+                "manifests",
+                Node::Array(
+                    self.manifests
+                        .iter()
+                        .map(|entry| entry.node(order))
+                        .collect::<Result<_, _>>()?,
+                ),
+            ),
+        ]))
+    }
+}
+
+impl Descriptor {
+    fn node(&self, order: Order) -> Result<Node, CanonicalJsonError> {
+        let mut fields = descriptor(order, &self.media_type, self.size, &self.digest)?;
+
+        if let Some(urls) = &self.urls {
+            fields.push(("urls", value(urls)?));
+        }
+
+        annotations(&mut fields, &self.annotations)?;
+
+        if let Some(data) = &self.data {
+            fields.push(("data", value(data)?));
+        }
+
+        if let Some(platform) = &self.platform {
+            fields.push(("platform", platform.node()?));
+        }
+
+        Ok(Node::Object(fields))
+    }
+}
+
+impl Platform {
+    fn node(&self) -> Result<Node, CanonicalJsonError> {
+        let mut fields = vec![
+            ("architecture", value(self.architecture)?),
+            ("os", value(self.os)?),
+        ];
+
+        if let Some(os_version) = &self.os_version {
+            fields.push(("os.version", value(os_version)?));
+        }
+
+        if let Some(os_features) = &self.os_features {
+            fields.push(("os.features", value(os_features)?));
+        }
+
+        if let Some(variant) = &self.variant {
+            fields.push(("variant", value(variant)?));
+        }
+
+        if let Some(features) = &self.features {
+            fields.push(("features", value(features)?));
+        }
+
+        Ok(Node::Object(fields))
+    }
+}
+
+impl Order {
+    fn of(media_type: &str) -> Self {
+        if media_type.starts_with("application/vnd.docker.") {
+            Self::Docker
+        } else {
+            Self::Oci
+        }
+    }
+}
+
+impl Node {
+    fn write(&self, out: &mut Vec<u8>) -> Result<(), CanonicalJsonError> {
+        match self {
+            Self::Object(fields) => {
+                out.push(b'{');
+
+                for (index, (key, node)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        out.push(b',');
+                    }
+
+                    serde_json::to_writer(&mut *out, key).map_err(CanonicalJsonError::Serialize)?;
+                    out.push(b':');
+                    node.write(out)?;
+                }
+
+                out.push(b'}');
+            }
+
+            Self::Array(nodes) => {
+                out.push(b'[');
+
+                for (index, node) in nodes.iter().enumerate() {
+                    if index > 0 {
+                        out.push(b',');
+                    }
+
+                    node.write(out)?;
+                }
+
+                out.push(b']');
+            }
+
+            Self::Value(value) => {
+                serde_json::to_writer(&mut *out, value).map_err(CanonicalJsonError::Serialize)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn value(value: impl Serialize) -> Result<Node, CanonicalJsonError> {
+    serde_json::to_value(value)
+        .map(Node::Value)
+        .map_err(CanonicalJsonError::Serialize)
+}
+
+fn descriptor(
+    order: Order,
+    media_type: &str,
+    size: u64,
+    digest: &Digest,
+) -> Result<Vec<(&'static str, Node)>, CanonicalJsonError> {
+    let media_type = ("mediaType", value(media_type)?);
+    let size = ("size", value(size)?);
+    let digest = ("digest", value(digest)?);
+
+    Ok(match order {
+        Order::Docker => vec![media_type, size, digest],
+        Order::Oci => vec![media_type, digest, size],
+    })
+}
+
+fn annotations(
+    fields: &mut Vec<(&'static str, Node)>,
+    annotations: &BTreeMap<String, String>,
+) -> Result<(), CanonicalJsonError> {
+    if !annotations.is_empty() {
+        fields.push(("annotations", value(annotations)?));
+    }
+
+    Ok(())
+}
+
+/// Escapes the characters the JSON encoder of Go escapes by default. They
+/// can only appear inside of strings, so replacing them everywhere is safe.
+fn escape_html(json: &[u8]) -> Vec<u8> {
+    let json = String::from_utf8_lossy(json);
+    let mut out = String::with_capacity(json.len());
+
+    for c in json.chars() {
+        match c {
+            '<' => out.push_str("\\u003c"),
+            '>' => out.push_str("\\u003e"),
+            '&' => out.push_str("\\u0026"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c => out.push(c),
+        }
+    }
+
+    out.into_bytes()
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "unwrap use in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        manifest::{
+            CanonicalJsonError,
+            Manifest,
+        },
+        Digest,
+    };
+
+    /// Fixtures as registries serve them together with their digests.
+    const CANONICAL: [(&str, &str); 3] = [
+        (
+            include_str!("../../resources/manifest/canonical/docker-image.json"),
+            "sha256:da4de65fc44f764cc87a0fb86da5da67d953c21ce39ba4f2625d3bfb7d886f09",
+        ),
+        (
+            include_str!("../../resources/manifest/canonical/oci-image.json"),
+            "sha256:a5103ef303dd641e9d17ec48b40b60cd384b6449e59f204b4a4fb23573fa19a4",
+        ),
+        (
+            include_str!("../../resources/manifest/canonical/oci-index.json"),
+            "sha256:e74541d806512fb95fc3fc16e6b90cb4b081d70030ba7146cccd0a0aec746a99",
+        ),
+    ];
+
+    #[test]
+    fn canonical_fixtures_keep_their_digest() {
+        for (input, digest) in CANONICAL {
+            let manifest: Manifest = serde_json::from_str(input).unwrap();
+
+            let out = manifest.to_canonical_json().unwrap();
+
+            assert_eq!(String::from_utf8(out.clone()).unwrap(), input.trim_end());
+            assert_eq!(Digest::sha256(&out).to_string(), digest);
+        }
+    }
+
+    #[test]
+    fn pretty_fixtures_are_compacted() {
+        const INPUT: &str = include_str!("../../resources/manifest/image/example.json");
+
+        let manifest: Manifest = serde_json::from_str(INPUT).unwrap();
+        let out = manifest.to_canonical_json().unwrap();
+
+        // Serializing the canonical form again does not change it.
+        let again: Manifest = serde_json::from_slice(&out).unwrap();
+        assert_eq!(again.to_canonical_json().unwrap(), out);
+
+        insta::assert_snapshot!(String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn go_html_escaping() {
+        const INPUT: &str = r#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a","size":2},"layers":[{"mediaType":"application/vnd.oci.image.layer.v1.tar","digest":"sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a","size":2,"annotations":{"note":"\u003cb\u003e \u0026 co"}}]}"#;
+
+        let manifest: Manifest = serde_json::from_str(INPUT).unwrap();
+
+        assert_eq!(
+            String::from_utf8(manifest.to_canonical_json().unwrap()).unwrap(),
+            INPUT
+        );
+    }
+
+    #[test]
+    fn schema_v1_is_rejected() {
+        const INPUT: &str =
+            include_str!("../../resources/manifest/single/external-secrets-operator.json");
+
+        let manifest: Manifest = serde_json::from_str(INPUT).unwrap();
+
+        assert!(matches!(
+            manifest.to_canonical_json(),
+            Err(CanonicalJsonError::SchemaV1)
+        ));
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::BTreeMap;
+
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+
+use crate::manifest::{
+    Architecture,
+    Config,
+    Entry,
+    FsLayer,
+    History,
+    Image,
+    Layer,
+    List,
+    Manifest,
+    OperatingSystem,
+    Platform,
+    SchemaVersion,
+    Single,
+};
+
+/// A part of a manifest [`Manifest::parse_lenient`] had to skip or coerce.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseWarning {
+    /// JSON pointer to the offending value, for example
+    /// `/manifests/2/platform/architecture`.
+    pub path: String,
+
+    pub message: String,
+}
+
+/// Collects the warnings of a lenient parse.
+#[derive(Default)]
+struct Salvage {
+    warnings: Vec<ParseWarning>,
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl Manifest {
+    /// Parses a manifest like the typed deserialization does, but instead of
+    /// failing on a value that does not fit it salvages what it can and
+    /// reports everything it skipped or coerced.
+    ///
+    /// Unknown architectures and operating systems become `unknown`, list
+    /// entries without a platform get an unknown one and layers or entries
+    /// without a digest are skipped. `media_type`, usually the
+    /// `Content-Type` of the response, is used if the manifest has no
+    /// `mediaType`. Returns `None` if the body is not JSON or not recognizable
+    /// as a manifest at all.
+    #[must_use]
+    pub fn parse_lenient(
+        bytes: &[u8],
+        media_type: Option<&str>,
+    ) -> (Option<Self>, Vec<ParseWarning>) {
+        if let Ok(manifest) = serde_json::from_slice(bytes) {
+            return (Some(manifest), Vec::new());
+        }
+
+        let mut salvage = Salvage::default();
+
+        let manifest = match serde_json::from_slice::<Value>(bytes) {
+            Ok(value) => salvage.manifest(&value, media_type),
+
+            Err(e) => {
+                salvage.warn("", format!("not valid JSON: {e}"));
+                None
+            }
+        };
+
+        (manifest, salvage.warnings)
+    }
+}
+
+impl Salvage {
+    fn warn(&mut self, path: &str, message: impl Into<String>) {
+        self.warnings.push(ParseWarning {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Deserializes `value[key]`, warning if it is missing or does not fit.
+    fn field<T: DeserializeOwned>(&mut self, value: &Value, path: &str, key: &str) -> Option<T> {
+        let path = format!("{path}/{key}");
+
+        let Some(field) = value.get(key) else {
+            self.warn(&path, "missing");
+            return None;
+        };
+
+        match T::deserialize(field) {
+            Ok(field) => Some(field),
+
+            Err(e) => {
+                self.warn(&path, format!("invalid: {e}"));
+                None
+            }
+        }
+    }
+
+    fn manifest(&mut self, value: &Value, media_type: Option<&str>) -> Option<Manifest> {
+        if !value.is_object() {
+            self.warn("", "not a JSON object");
+            return None;
+        }
+
+        if value.get("manifests").is_some() {
+            return Some(Manifest::List(self.list(value, media_type)));
+        }
+
+        if value.get("fsLayers").is_some() {
+            return Some(Manifest::Single(self.single(value)));
+        }
+
+        if value.get("layers").is_some() || value.get("config").is_some() {
+            return self.image(value, media_type).map(Manifest::Image);
+        }
+
+        self.warn("", "has neither manifests, layers nor fsLayers");
+        None
+    }
+
+    fn schema_version(&mut self, value: &Value, fallback: SchemaVersion) -> SchemaVersion {
+        self.field(value, "", "schemaVersion").unwrap_or_else(|| {
+            self.warn("/schemaVersion", format!("used {}", fallback.number()));
+            fallback
+        })
+    }
+
+    fn media_type(&mut self, value: &Value, media_type: Option<&str>) -> String {
+        if let Some(found) = value.get("mediaType").and_then(Value::as_str) {
+            return found.to_string();
+        }
+
+        let fallback = media_type.unwrap_or_default().to_string();
+        self.warn(
+            "/mediaType",
+            format!("missing or not a string, used `{fallback}`"),
+        );
+
+        fallback
+    }
+
+    /// Returns the elements of the array `value[key]`, warning if it is not
+    /// an array.
+    fn array<'a>(&mut self, value: &'a Value, key: &str) -> &'a [Value] {
+        match value.get(key).map(Value::as_array) {
+            Some(Some(array)) => array,
+
+            Some(None) => {
+                self.warn(&format!("/{key}"), "not an array, skipped");
+                &[]
+            }
+
+            None => {
+                self.warn(&format!("/{key}"), "missing");
+                &[]
+            }
+        }
+    }
+
+    fn list(&mut self, value: &Value, media_type: Option<&str>) -> List {
+        let schema_version = self.schema_version(value, SchemaVersion::V2);
+        let media_type = self.media_type(value, media_type);
+
+        let manifests = self
+            .array(value, "manifests")
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| self.entry(entry, &format!("/manifests/{index}")))
+            .collect();
+
+        List {
+            schema_version,
+            media_type,
+            manifests,
+        }
+    }
+
+    fn entry(&mut self, value: &Value, path: &str) -> Option<Entry> {
+        if let Ok(entry) = Entry::deserialize(value) {
+            return Some(entry);
+        }
+
+        let Some(digest) = self.field(value, path, "digest") else {
+            self.warn(path, "entry without digest skipped");
+            return None;
+        };
+
+        let platform = value
+            .get("platform")
+            .map(|platform| self.platform(platform, &format!("{path}/platform")));
+
+        Some(Entry {
+            platform,
+            annotations: self.annotations(value, path),
+            ..Entry::new(
+                self.field::<String>(value, path, "mediaType")
+                    .unwrap_or_default(),
+                self.size(value, path),
+                digest,
+            )
+        })
+    }
+
+    fn platform(&mut self, value: &Value, path: &str) -> Platform {
+        if let Ok(platform) = Platform::deserialize(value) {
+            return platform;
+        }
+
+        let architecture = self.known(value, path, "architecture", Architecture::Unknown);
+        let os = self.known(value, path, "os", OperatingSystem::Unknown);
+
+        Platform {
+            variant: optional(value, "variant"),
+            os_version: optional(value, "os.version"),
+            os_features: optional(value, "os.features"),
+            features: optional(value, "features"),
+            ..Platform::new(os, architecture)
+        }
+    }
+
+    /// Parses `value[key]` with its `FromStr` implementation and falls back
+    /// to `unknown` for names the specification does not list.
+    fn known<T>(&mut self, value: &Value, path: &str, key: &str, unknown: T) -> T
+    where
+        T: std::str::FromStr + std::fmt::Display,
+    {
+        let path = format!("{path}/{key}");
+
+        let Some(name) = value.get(key).and_then(Value::as_str) else {
+            self.warn(&path, format!("missing or not a string, used {unknown}"));
+            return unknown;
+        };
+
+        name.parse().unwrap_or_else(|_| {
+            self.warn(&path, format!("unknown `{name}`, used {unknown}"));
+            unknown
+        })
+    }
+
+    /// Sizes are sometimes sent as strings, those are parsed. Everything
+    /// else that is not a number becomes 0.
+    fn size(&mut self, value: &Value, path: &str) -> u64 {
+        if let Some(Value::String(size)) = value.get("size") {
+            if let Ok(parsed) = size.parse() {
+                self.warn(
+                    &format!("{path}/size"),
+                    format!("string `{size}`, used {parsed}"),
+                );
+                return parsed;
+            }
+        }
+
+        self.field(value, path, "size").unwrap_or_default()
+    }
+
+    fn annotations(&mut self, value: &Value, path: &str) -> BTreeMap<String, String> {
+        if value.get("annotations").is_none() {
+            return BTreeMap::new();
+        }
+
+        self.field(value, path, "annotations").unwrap_or_default()
+    }
+
+    fn image(&mut self, value: &Value, media_type: Option<&str>) -> Option<Image> {
+        let schema_version = self.schema_version(value, SchemaVersion::V2);
+        let media_type = self.media_type(value, media_type);
+
+        let Some(config) = value.get("config") else {
+            self.warn("/config", "missing");
+            return None;
+        };
+
+        let config = match Config::deserialize(config) {
+            Ok(config) => config,
+
+            Err(_) => Config::new(
+                self.field::<String>(config, "/config", "mediaType")
+                    .unwrap_or_default(),
+                self.field(config, "/config", "size").unwrap_or_default(),
+                self.field(config, "/config", "digest")?,
+            ),
+        };
+
+        let layers = self
+            .array(value, "layers")
+            .iter()
+            .enumerate()
+            .filter_map(|(index, layer)| self.layer(layer, &format!("/layers/{index}")))
+            .collect();
+
+        Some(Image {
+            schema_version,
+            media_type,
+            config,
+            layers,
+        })
+    }
+
+    fn layer(&mut self, value: &Value, path: &str) -> Option<Layer> {
+        if let Ok(layer) = Layer::deserialize(value) {
+            return Some(layer);
+        }
+
+        let Some(digest) = self.field(value, path, "digest") else {
+            self.warn(path, "layer without digest skipped");
+            return None;
+        };
+
+        let urls = if value.get("urls").is_some() {
+            self.field(value, path, "urls")
+        } else {
+            None
+        };
+
+        Some(Layer {
+            urls,
+            annotations: self.annotations(value, path),
+            ..Layer::new(
+                self.field::<String>(value, path, "mediaType")
+                    .unwrap_or_default(),
+                self.size(value, path),
+                digest,
+            )
+        })
+    }
+
+    fn single(&mut self, value: &Value) -> Single {
+        let schema_version = self.schema_version(value, SchemaVersion::V1);
+
+        let fs_layers = self
+            .array(value, "fsLayers")
+            .iter()
+            .enumerate()
+            .filter_map(|(index, layer)| {
+                self.field::<String>(layer, &format!("/fsLayers/{index}"), "blobSum")
+                    .map(|blob_sum| FsLayer { blob_sum })
+            })
+            .collect();
+
+        let history = self
+            .array(value, "history")
+            .iter()
+            .enumerate()
+            .filter_map(|(index, history)| match History::deserialize(history) {
+                Ok(history) => Some(history),
+
+                Err(e) => {
+                    self.warn(&format!("/history/{index}"), format!("skipped: {e}"));
+                    None
+                }
+            })
+            .collect();
+
+        Single {
+            schema_version,
+            name: self.field(value, "", "name").unwrap_or_default(),
+            tag: self.field(value, "", "tag").unwrap_or_default(),
+            architecture: self.known(value, "", "architecture", Architecture::Unknown),
+            fs_layers,
+            history,
+        }
+    }
+}
+
+/// Returns `value[key]` if it is present and fits, optional fields are
+/// dropped silently.
+fn optional<T: DeserializeOwned>(value: &Value, key: &str) -> Option<T> {
+    value.get(key).and_then(|field| T::deserialize(field).ok())
+}
+
+impl SchemaVersion {
+    fn number(&self) -> u8 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "unwrap use in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::manifest::{
+        Architecture,
+        Manifest,
+        OperatingSystem,
+        ParseWarning,
+    };
+
+    fn warning(path: &str, message: &str) -> ParseWarning {
+        ParseWarning {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn valid_manifest_has_no_warnings() {
+        const INPUT: &str = include_str!("../../resources/manifest/list/trivy.json");
+
+        let (manifest, warnings) = Manifest::parse_lenient(INPUT.as_bytes(), None);
+
+        assert!(matches!(manifest, Some(Manifest::List(_))));
+        assert_eq!(warnings, Vec::new());
+    }
+
+    #[test]
+    fn weird_architecture() {
+        const INPUT: &str =
+            include_str!("../../resources/manifest/lenient/weird-architecture.json");
+
+        let (manifest, warnings) = Manifest::parse_lenient(INPUT.as_bytes(), None);
+
+        let Some(Manifest::List(list)) = manifest else {
+            panic!("expected a list");
+        };
+
+        let platforms: Vec<String> = list
+            .manifests
+            .iter()
+            .map(|entry| entry.platform.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            platforms,
+            vec!["linux/amd64", "linux/unknown", "unknown/riscv64/rva22u64"]
+        );
+        assert_eq!(
+            list.manifests[1].platform.as_ref().unwrap().architecture,
+            Architecture::Unknown
+        );
+        assert_eq!(
+            list.manifests[2].platform.as_ref().unwrap().os,
+            OperatingSystem::Unknown
+        );
+
+        assert_eq!(
+            warnings,
+            vec![
+                warning(
+                    "/manifests/1/platform/architecture",
+                    "unknown `sparc64`, used unknown"
+                ),
+                warning("/manifests/2/platform/os", "unknown `haiku`, used unknown"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_platform() {
+        const INPUT: &str = include_str!("../../resources/manifest/lenient/missing-platform.json");
+
+        let (manifest, warnings) = Manifest::parse_lenient(INPUT.as_bytes(), None);
+
+        let Some(Manifest::List(list)) = manifest else {
+            panic!("expected a list");
+        };
+
+        // The entry without a digest is skipped, the attestation is kept.
+        assert_eq!(list.manifests.len(), 2);
+        assert_eq!(
+            list.manifests[1].attestation_for(),
+            Some(list.manifests[0].digest.to_string().as_str())
+        );
+
+        assert_eq!(
+            warnings,
+            vec![
+                warning("/manifests/2/digest", "missing"),
+                warning("/manifests/2", "entry without digest skipped"),
+            ]
+        );
+    }
+
+    #[test]
+    fn odd_layers() {
+        const INPUT: &str = include_str!("../../resources/manifest/lenient/odd-layers.json");
+
+        let (manifest, warnings) = Manifest::parse_lenient(
+            INPUT.as_bytes(),
+            Some("application/vnd.oci.image.manifest.v1+json"),
+        );
+
+        let Some(Manifest::Image(image)) = manifest else {
+            panic!("expected an image");
+        };
+
+        insta::assert_json_snapshot!(image);
+
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| warning.path.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "/mediaType",
+                "/layers/1/size",
+                "/layers/2/digest",
+                "/layers/2",
+            ]
+        );
+    }
+
+    #[test]
+    fn not_a_manifest() {
+        let (manifest, warnings) = Manifest::parse_lenient(br#"{"errors":[]}"#, None);
+
+        assert!(manifest.is_none());
+        assert_eq!(
+            warnings,
+            vec![warning("", "has neither manifests, layers nor fsLayers")]
+        );
+
+        let (manifest, warnings) = Manifest::parse_lenient(b"<html>", None);
+
+        assert!(manifest.is_none());
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::Serialize;
+
+use crate::{
+    config,
+    manifest::{
+        Architecture,
+        ContainerConfig,
+        FsLayer,
+        History,
+        OperatingSystem,
+        Single,
+    },
+    Digest,
+};
+
+/// The gzipped empty tar archive schema1 manifests list for every step that
+/// did not change the filesystem.
+const EMPTY_LAYER: &str = "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4";
+
+/// The parts of an image configuration a schema1 manifest carries, see
+/// [`Single::synthesize_config`]. Unlike [`crate::ImageConfig`] there is no
+/// root filesystem, schema1 only knows the digests of the compressed layers
+/// and not the ones of their content.
+#[derive(Debug, Clone, Serialize)]
+pub struct SynthesizedConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    pub architecture: Architecture,
+    pub os: OperatingSystem,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ContainerConfig>,
+
+    /// Ordered from the oldest to the newest step like in an image
+    /// configuration.
+    pub history: Vec<config::History>,
+}
+
+impl Single {
+    /// Returns the digests of the layers ordered from the base layer to the
+    /// newest one, like the layers of an image manifest. Schema1 manifests
+    /// list the layers newest first and have an empty layer for every step
+    /// that did not change the filesystem, those are skipped.
+    #[must_use]
+    pub fn layers(&self) -> Vec<Digest> {
+        self.layer_history()
+            .into_iter()
+            .rev()
+            .filter(|(layer, history)| !is_empty(layer, *history))
+            .map(|(layer, _)| layer.blob_sum.parse().unwrap_or_else(|e| match e {}))
+            .collect()
+    }
+
+    /// Builds the configuration of the image from the `v1Compatibility`
+    /// entries of the history. The newest entry holds the configuration of
+    /// the image, every entry becomes a step of the history with the command
+    /// of its container configuration. Steps are marked as empty layers
+    /// where [`Single::layers`] skips their layer, so the steps with a layer
+    /// line up with it.
+    #[must_use]
+    pub fn synthesize_config(&self) -> SynthesizedConfig {
+        let newest = self
+            .history
+            .first()
+            .map(|history| &history.v1_compatibility);
+
+        let os = newest
+            .and_then(|newest| newest.os.as_deref())
+            .map_or(OperatingSystem::Linux, |os| {
+                os.parse().unwrap_or(OperatingSystem::Unknown)
+            });
+
+        let history = self
+            .history
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, history)| {
+                let step = &history.v1_compatibility;
+
+                let created_by = step
+                    .container_config
+                    .as_ref()
+                    .and_then(|config| config.cmd.as_ref())
+                    .filter(|cmd| !cmd.is_empty())
+                    .map(|cmd| cmd.join(" "));
+
+                let empty_layer = self
+                    .fs_layers
+                    .get(index)
+                    .map_or(step.throwaway, |layer| is_empty(layer, Some(history)));
+
+                config::History {
+                    created: Some(step.created),
+                    author: step.author.clone(),
+                    created_by,
+                    comment: step.comment.clone(),
+                    empty_layer,
+                }
+            })
+            .collect();
+
+        SynthesizedConfig {
+            created: newest.map(|newest| newest.created),
+            author: newest.and_then(|newest| newest.author.clone()),
+            architecture: self.architecture,
+            os,
+            config: newest.and_then(|newest| newest.config.clone()),
+            history,
+        }
+    }
+}
+
+fn is_empty(layer: &FsLayer, history: Option<&History>) -> bool {
+    layer.blob_sum == EMPTY_LAYER
+        || history.is_some_and(|history| history.v1_compatibility.throwaway)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used, reason = "using unwrap in tests is fine")]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::manifest::{
+        Architecture,
+        OperatingSystem,
+        Single,
+    };
+
+    const EXTERNAL_SECRETS: &str =
+        include_str!("../../resources/manifest/single/external-secrets-operator.json");
+
+    /// The layers of the fixture that are not empty, from the base layer to
+    /// the newest one like an image manifest of the image lists them.
+    const V2_LAYERS: [&str; 3] = [
+        "sha256:1708f2f83bad0dd2b0df9848e842309c31fc753b55cbc77f279bd822a0a54e66",
+        "sha256:75668c83b5eda65c309535738c3985105813e91a2d2276c4a0715823c13f0dff",
+        "sha256:e4e09d2a6660e9f5ff3ce061ccebacadd99df09824f71d214ba3c33828b651e3",
+    ];
+
+    #[test]
+    fn layers() {
+        let single: Single = serde_json::from_str(EXTERNAL_SECRETS).unwrap();
+
+        let got: Vec<String> = single.layers().iter().map(ToString::to_string).collect();
+
+        assert_eq!(got, V2_LAYERS);
+    }
+
+    #[test]
+    fn layers_without_history() {
+        const INPUT: &str = include_str!("../../resources/manifest/single/empty-history.json");
+
+        let single: Single = serde_json::from_str(INPUT).unwrap();
+
+        assert!(single.layers().is_empty());
+        assert!(single.synthesize_config().history.is_empty());
+    }
+
+    #[test]
+    fn synthesize_config() {
+        let single: Single = serde_json::from_str(EXTERNAL_SECRETS).unwrap();
+
+        let got = single.synthesize_config();
+
+        assert_eq!(got.architecture, Architecture::Amd64);
+        assert_eq!(got.os, OperatingSystem::Linux);
+        assert_eq!(got.history.len(), single.history.len());
+        assert_eq!(
+            got.history.iter().filter(|step| !step.empty_layer).count(),
+            V2_LAYERS.len()
+        );
+        assert_eq!(
+            got.history.last().unwrap().created,
+            got.created,
+            "the newest step is last"
+        );
+
+        insta::assert_json_snapshot!(got);
+    }
+}
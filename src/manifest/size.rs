@@ -0,0 +1,71 @@
+/// Binary units from bytes to exbibytes, the last one covers [`u64::MAX`].
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// A size in bytes that is displayed with a binary unit and two decimals,
+/// for example `1.50 GiB`. The decimals are truncated, not rounded, so a size
+/// is never displayed larger than it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Size(pub u64);
+
+impl Size {
+    /// Adds up `sizes`, saturating at [`u64::MAX`] instead of overflowing.
+    #[must_use]
+    pub fn sum(sizes: impl IntoIterator<Item = u64>) -> Self {
+        Self(sizes.into_iter().fold(0, u64::saturating_add))
+    }
+}
+
+impl From<u64> for Size {
+    fn from(bytes: u64) -> Self {
+        Self(bytes)
+    }
+}
+
+impl std::fmt::Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = u128::from(self.0);
+
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && bytes >= 1 << (10 * (unit + 1)) {
+            unit += 1;
+        }
+
+        if unit == 0 {
+            return write!(f, "{bytes} B");
+        }
+
+        let divisor = 1u128 << (10 * unit);
+        let whole = bytes / divisor;
+        let hundredths = bytes % divisor * 100 / divisor;
+
+        write!(f, "{whole}.{hundredths:02} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::Size;
+
+    #[test]
+    fn display() {
+        for (bytes, expected) in [
+            (0, "0 B"),
+            (1023, "1023 B"),
+            (1024, "1.00 KiB"),
+            (1536, "1.50 KiB"),
+            (3_623_807, "3.45 MiB"),
+            (5_368_709_120, "5.00 GiB"),
+            (u64::MAX, "15.99 EiB"),
+        ] {
+            assert_eq!(Size(bytes).to_string(), expected, "{bytes}");
+        }
+    }
+
+    #[test]
+    fn sum_saturates() {
+        assert_eq!(Size::sum([u64::MAX, 1]), Size(u64::MAX));
+        assert_eq!(Size::sum([3 << 30, 3 << 30]), Size(6 << 30));
+    }
+}
@@ -0,0 +1,6 @@
+#[test]
+fn image_macro() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/image/pass.rs");
+    cases.compile_fail("tests/ui/image/fail_*.rs");
+}
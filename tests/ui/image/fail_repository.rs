@@ -0,0 +1,5 @@
+use docker_registry_client::image;
+
+fn main() {
+    let _ = image!("ghcr.io/Sigstore/cosign:v2.4.0");
+}
@@ -0,0 +1,5 @@
+use docker_registry_client::image;
+
+fn main() {
+    let _ = image!("ghcr.io/sigstore/cosign:v2:4");
+}
@@ -0,0 +1,5 @@
+use docker_registry_client::image;
+
+fn main() {
+    let _ = image!("alpine :latest");
+}
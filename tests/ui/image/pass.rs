@@ -0,0 +1,19 @@
+use std::sync::LazyLock;
+
+use docker_registry_client::{
+    image,
+    Image,
+};
+
+const COSIGN: &str = "ghcr.io/sigstore/cosign/cosign:v2.4.0";
+
+static ALPINE: LazyLock<Image> = LazyLock::new(|| image!("alpine:3.20"));
+
+fn main() {
+    assert_eq!(ALPINE.to_string(), "index.docker.io/library/alpine:3.20");
+    assert_eq!(image!(COSIGN), COSIGN.parse::<Image>().unwrap());
+    assert_eq!(
+        image!("registry.k8s.io/pause@sha256:7031c1b2").reference(),
+        "sha256:7031c1b2"
+    );
+}